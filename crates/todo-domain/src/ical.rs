@@ -0,0 +1,227 @@
+use crate::models::Todo;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+/// `PRODID` of every calendar written.
+const PRODID: &str = "-//todo-api//CalDAV//EN";
+
+/// Longest line, in bytes, before it is folded (RFC 5545 section 3.1).
+const MAX_LINE: usize = 75;
+
+/// The properties of a VTODO that map onto a todo. Everything else in it is ignored.
+#[derive(Debug, Clone, Default)]
+pub struct VTodo {
+    pub uid: Option<String>,
+    pub summary: String,
+    pub description: Option<String>,
+    /// `STATUS:COMPLETED`, or a `COMPLETED` timestamp.
+    pub completed: bool,
+    pub due: Option<DateTime<Utc>>,
+    /// Every `CATEGORIES` value, in order.
+    pub categories: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IcalError {
+    #[error("the calendar has no VTODO")]
+    NoVTodo,
+
+    #[error("invalid {property}: {value:?}")]
+    InvalidValue { property: &'static str, value: String },
+}
+
+/// The todo as a VCALENDAR holding one VTODO with the given `UID`. Times are written in UTC.
+pub fn to_vcalendar(todo: &Todo, uid: &str) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        format!("PRODID:{PRODID}"),
+        "BEGIN:VTODO".to_owned(),
+        format!("UID:{}", escape(uid)),
+        format!("DTSTAMP:{}", timestamp(todo.updated_at)),
+        format!("CREATED:{}", timestamp(todo.created_at)),
+        format!("LAST-MODIFIED:{}", timestamp(todo.updated_at)),
+        format!("SUMMARY:{}", escape(&todo.title)),
+    ];
+    if let Some(description) = &todo.description {
+        lines.push(format!("DESCRIPTION:{}", escape(description)));
+    }
+    if let Some(due_at) = todo.due_at {
+        lines.push(format!("DUE:{}", timestamp(due_at)));
+    }
+    if !todo.tags.is_empty() {
+        let categories: Vec<String> = todo.tags.iter().map(|tag| escape(tag)).collect();
+        lines.push(format!("CATEGORIES:{}", categories.join(",")));
+    }
+    if todo.completed {
+        lines.push("STATUS:COMPLETED".to_owned());
+        lines.push("PERCENT-COMPLETE:100".to_owned());
+        // Todos don't keep when they were completed, so their last change stands in for it
+        lines.push(format!("COMPLETED:{}", timestamp(todo.updated_at)));
+    } else {
+        lines.push("STATUS:NEEDS-ACTION".to_owned());
+    }
+    lines.push("END:VTODO".to_owned());
+    lines.push("END:VCALENDAR".to_owned());
+
+    let mut calendar = String::new();
+    for line in lines {
+        fold(&line, &mut calendar);
+    }
+    calendar
+}
+
+/// Reads the first VTODO of an iCalendar object. Times with a `TZID` or without a zone are
+/// taken as UTC, and a `DUE` date without a time as midnight UTC.
+pub fn parse_vtodo(text: &str) -> Result<VTodo, IcalError> {
+    let mut vtodo = VTodo::default();
+    let mut found = false;
+    // Components nested in the VTODO, such as alarms, whose properties aren't the todo's
+    let mut nested = 0;
+
+    for line in unfold(text) {
+        let Some((name, params, value)) = split_property(&line) else {
+            continue;
+        };
+        if !found {
+            found = name == "BEGIN" && value.eq_ignore_ascii_case("VTODO");
+            continue;
+        }
+        match name.as_str() {
+            "BEGIN" => nested += 1,
+            "END" if nested > 0 => nested -= 1,
+            "END" => return Ok(vtodo),
+            _ if nested > 0 => {}
+            "UID" => vtodo.uid = Some(unescape(value)),
+            "SUMMARY" => vtodo.summary = unescape(value),
+            "DESCRIPTION" => vtodo.description = Some(unescape(value)).filter(|d| !d.is_empty()),
+            "STATUS" => vtodo.completed = value.eq_ignore_ascii_case("COMPLETED"),
+            "COMPLETED" => vtodo.completed = true,
+            "DUE" => vtodo.due = Some(parse_time(params, value)?),
+            "CATEGORIES" => vtodo.categories.extend(split_list(value)),
+            _ => {}
+        }
+    }
+    Err(IcalError::NoVTodo)
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn parse_time(params: &str, value: &str) -> Result<DateTime<Utc>, IcalError> {
+    let invalid = || IcalError::InvalidValue {
+        property: "DUE",
+        value: value.to_owned(),
+    };
+    let value = value.trim();
+    let date_only = params.split(';').any(|param| param.eq_ignore_ascii_case("VALUE=DATE"));
+    if date_only || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").map_err(|_| invalid())?;
+        return Ok(date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?.and_utc());
+    }
+    NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+        .map(|time| time.and_utc())
+        .map_err(|_| invalid())
+}
+
+/// Joins continuation lines (those starting with a space or tab) onto the line before.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    lines
+}
+
+/// Splits `NAME;PARAM=x:value` into the upper-cased name, the parameters and the value.
+/// Colons inside quoted parameter values don't end the parameters.
+fn split_property(line: &str) -> Option<(String, &str, &str)> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let (name, params) = head.split_once(';').unwrap_or((head, ""));
+    Some((name.trim().to_ascii_uppercase(), params, value))
+}
+
+/// Appends `line` to `out`, folded into lines of at most 75 bytes, each ending in CRLF.
+fn fold(line: &str, out: &mut String) {
+    let mut rest = line;
+    let mut limit = MAX_LINE;
+    while rest.len() > limit {
+        let mut end = limit;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        out.push_str(&rest[..end]);
+        out.push_str("\r\n ");
+        rest = &rest[end..];
+        // The leading space of a continuation line counts towards its length
+        limit = MAX_LINE - 1;
+    }
+    out.push_str(rest);
+    out.push_str("\r\n");
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// The values of a comma-separated list, split on commas that aren't escaped.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                items.push(unescape(&value[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(unescape(&value[start..]));
+    items.retain(|item| !item.trim().is_empty());
+    items
+}
@@ -0,0 +1,116 @@
+use crate::models::{Priority, Todo};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::cmp::Ordering;
+
+/// Days after which a todo's age counts in full.
+pub const AGE_HORIZON_DAYS: f64 = 30.0;
+
+/// How much each factor counts towards a todo's place in the priority inbox. Factors run
+/// from 0 to 1, so a weight is the most its factor can add to a score.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct InboxWeights {
+    pub due: f64,
+    pub priority: f64,
+    pub age: f64,
+    pub pinned: f64,
+}
+
+/// Pinned todos come first, then what's due soonest, with priority able to lift a todo over
+/// one due a day or two earlier and age only settling what's otherwise close.
+impl Default for InboxWeights {
+    fn default() -> Self {
+        Self {
+            due: 3.0,
+            priority: 2.0,
+            age: 1.0,
+            pinned: 10.0,
+        }
+    }
+}
+
+/// A todo's factors before weighting, each from 0 to 1.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ScoreFactors {
+    /// 1 when due now or overdue, halving as the due date moves a day away, then a third for
+    /// two days and so on; 0 without a due date.
+    pub due: f64,
+    /// 1 for high, 0.5 for medium, 0.25 for none and 0 for low.
+    pub priority: f64,
+    /// Rises evenly from 0 at creation to 1 after `AGE_HORIZON_DAYS`, so nothing waits forever.
+    pub age: f64,
+    pub pinned: f64,
+}
+
+impl ScoreFactors {
+    pub fn of(todo: &Todo, now: DateTime<Utc>) -> Self {
+        let due = todo.due_at.map_or(0.0, |due_at| {
+            let days = (due_at - now).num_seconds() as f64 / 86_400.0;
+            if days <= 0.0 {
+                1.0
+            } else {
+                1.0 / (1.0 + days)
+            }
+        });
+        let priority = match todo.priority {
+            Some(Priority::High) => 1.0,
+            Some(Priority::Medium) => 0.5,
+            None => 0.25,
+            Some(Priority::Low) => 0.0,
+        };
+        let age_days = (now - todo.created_at).num_seconds().max(0) as f64 / 86_400.0;
+        Self {
+            due,
+            priority,
+            age: (age_days / AGE_HORIZON_DAYS).min(1.0),
+            pinned: if todo.pinned { 1.0 } else { 0.0 },
+        }
+    }
+
+    pub fn score(&self, weights: &InboxWeights) -> f64 {
+        self.due * weights.due
+            + self.priority * weights.priority
+            + self.age * weights.age
+            + self.pinned * weights.pinned
+    }
+}
+
+/// A todo with where it stands in the inbox and why.
+#[derive(Debug, Clone, Serialize)]
+pub struct RankedTodo {
+    pub score: f64,
+    pub factors: ScoreFactors,
+    pub todo: Todo,
+}
+
+/// The open, unblocked todos in the order to work on them, highest score first, at most
+/// `limit` of them. Equal scores go to the earlier due date, then the older todo, so every
+/// client given the same todos shows the same order.
+pub fn rank(todos: Vec<Todo>, now: DateTime<Utc>, weights: &InboxWeights, limit: usize) -> Vec<RankedTodo> {
+    let mut ranked: Vec<RankedTodo> = todos
+        .into_iter()
+        .filter(|todo| !todo.completed && !todo.blocked)
+        .map(|todo| {
+            let factors = ScoreFactors::of(&todo, now);
+            RankedTodo {
+                score: factors.score(weights),
+                factors,
+                todo,
+            }
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| match (a.todo.due_at, b.todo.due_at) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            })
+            .then_with(|| a.todo.created_at.cmp(&b.todo.created_at))
+            .then_with(|| a.todo.id.cmp(&b.todo.id))
+    });
+    ranked.truncate(limit);
+    ranked
+}
@@ -0,0 +1,11 @@
+//! Todo models, natural-language parsing, iCalendar VTODOs, Markdown checklists, the priority
+//! inbox ranking, the clock and the repository trait, with no storage, HTTP or telemetry
+//! dependencies.
+
+pub mod clock;
+pub mod ical;
+pub mod inbox;
+pub mod markdown;
+pub mod models;
+pub mod quick_add;
+pub mod repository;
@@ -0,0 +1,405 @@
+use crate::models::{normalize_tags, Todo, UnparsedLine};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Checklists with more items than this are refused rather than imported in part.
+pub const MAX_CHECKLIST_ITEMS: usize = 10_000;
+
+/// Heading of the todos with no tags when grouping by tag.
+pub const UNTAGGED: &str = "Untagged";
+
+/// Heading of the todos outside any project when grouping by project.
+pub const NO_PROJECT: &str = "No project";
+
+/// A titled group of todos in a checklist.
+#[derive(Debug)]
+pub struct Section<'a> {
+    pub heading: String,
+    pub todos: Vec<&'a Todo>,
+}
+
+/// Groups todos under the name `name_of` gives their project, by name, with those outside a
+/// project (or whose project has no name) last under [`NO_PROJECT`].
+pub fn by_project<'a>(todos: &'a [Todo], name_of: impl Fn(&Todo) -> Option<String>) -> Vec<Section<'a>> {
+    let mut named: BTreeMap<String, Vec<&Todo>> = BTreeMap::new();
+    let mut loose = Vec::new();
+    for todo in todos {
+        match name_of(todo) {
+            Some(name) => named.entry(name).or_default().push(todo),
+            None => loose.push(todo),
+        }
+    }
+    sections(named, NO_PROJECT, loose)
+}
+
+/// Groups todos under each of their tags, by tag, so a todo with several tags appears under
+/// each one. Those without tags come last under [`UNTAGGED`].
+pub fn by_tag(todos: &[Todo]) -> Vec<Section<'_>> {
+    let mut tagged: BTreeMap<String, Vec<&Todo>> = BTreeMap::new();
+    let mut untagged = Vec::new();
+    for todo in todos {
+        if todo.tags.is_empty() {
+            untagged.push(todo);
+        }
+        for tag in &todo.tags {
+            tagged.entry(tag.clone()).or_default().push(todo);
+        }
+    }
+    sections(tagged, UNTAGGED, untagged)
+}
+
+fn sections<'a>(groups: BTreeMap<String, Vec<&'a Todo>>, rest: &str, todos: Vec<&'a Todo>) -> Vec<Section<'a>> {
+    let mut sections: Vec<Section> = groups
+        .into_iter()
+        .map(|(heading, todos)| Section { heading, todos })
+        .collect();
+    if !todos.is_empty() {
+        sections.push(Section { heading: rest.to_owned(), todos });
+    }
+    sections
+}
+
+/// A GitHub-flavored Markdown task list: an `##` heading per section, then a `- [ ]` item per
+/// todo, checked when it's completed.
+pub fn checklist(sections: &[Section]) -> String {
+    let mut out = String::new();
+    for section in sections {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str("## ");
+        out.push_str(&escape(&section.heading));
+        out.push_str("\n\n");
+        for todo in &section.todos {
+            out.push_str(&item(todo));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// `- [ ] title (due YYYY-MM-DD) #tag`, with the due part only when there is one and a `#tag`
+/// token per tag, so [`parse_checklist`] reads the todo back as it was.
+pub fn item(todo: &Todo) -> String {
+    let mark = if todo.completed { 'x' } else { ' ' };
+    let mut line = format!("- [{mark}] {}", escape(&todo.title));
+    if let Some(due_at) = todo.due_at {
+        line.push_str(&format!(" (due {})", due_at.format("%Y-%m-%d")));
+    }
+    for tag in &todo.tags {
+        line.push_str(" #");
+        line.push_str(&escape_tag(tag));
+    }
+    line
+}
+
+/// Backslash-escapes the characters Markdown would otherwise format, and folds line breaks
+/// into spaces so a title stays on its item's line.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\r' | '\n' => out.push(' '),
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '~' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `escape`, with whitespace escaped too so a tag stays one token.
+fn escape_tag(tag: &str) -> String {
+    let mut out = String::with_capacity(tag.len());
+    for c in escape(tag).chars() {
+        if c.is_whitespace() {
+            out.push_str("\\ ");
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Too many checklist items (limit is {MAX_CHECKLIST_ITEMS})")]
+pub struct TooManyItems;
+
+/// The items of a checklist as todos, plus the lines that weren't items.
+#[derive(Debug, Default)]
+pub struct ParsedChecklist {
+    pub todos: Vec<Todo>,
+    pub unparsed: Vec<UnparsedLine>,
+}
+
+/// Reads a task list such as [`checklist`] writes, stamping the todos as created at `now`.
+///
+/// Each `- [ ]` or `- [x]` item (`*` and `+` bullets too, indented or not) becomes a todo. In
+/// its text, a `due:` token with an RFC 3339 time or a `YYYY-MM-DD` date sets the due date, as
+/// does a trailing `(due YYYY-MM-DD)`, and each `#tag` token adds a tag; what's left is the
+/// title. Headings and blank lines are skipped, and every other line is reported as unparsed.
+pub fn parse_checklist(text: &str, now: DateTime<Utc>) -> Result<ParsedChecklist, TooManyItems> {
+    let mut parsed = ParsedChecklist::default();
+    for (index, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || is_heading(trimmed) {
+            continue;
+        }
+        let line = index as u64 + 1;
+        match parse_item(trimmed, now) {
+            Ok(todo) => {
+                if parsed.todos.len() == MAX_CHECKLIST_ITEMS {
+                    return Err(TooManyItems);
+                }
+                parsed.todos.push(todo);
+            }
+            Err(error) => parsed.unparsed.push(UnparsedLine {
+                line,
+                text: trimmed.to_owned(),
+                error,
+            }),
+        }
+    }
+    Ok(parsed)
+}
+
+/// `#` to `######`, then a space or nothing.
+fn is_heading(line: &str) -> bool {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    (1..=6).contains(&level) && line[level..].chars().next().is_none_or(char::is_whitespace)
+}
+
+fn parse_item(line: &str, now: DateTime<Utc>) -> Result<Todo, String> {
+    let rest = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|bullet| line.strip_prefix(bullet))
+        .ok_or_else(|| "not a checklist item".to_owned())?
+        .trim_start();
+    let (completed, rest) = if let Some(rest) = rest.strip_prefix("[ ]") {
+        (false, rest)
+    } else if let Some(rest) = rest.strip_prefix("[x]").or_else(|| rest.strip_prefix("[X]")) {
+        (true, rest)
+    } else {
+        return Err("list item without a [ ] or [x] box".to_owned());
+    };
+    if rest.chars().next().is_some_and(|c| !c.is_whitespace()) {
+        return Err("list item without a [ ] or [x] box".to_owned());
+    }
+
+    let mut due_at = None;
+    let mut tags = Vec::new();
+    let mut words = Vec::new();
+    for word in tokens(rest) {
+        if let Some(value) = word.strip_prefix("due:").filter(|value| !value.is_empty()) {
+            due_at = Some(parse_due(value)?);
+        } else if let Some(tag) = word.strip_prefix('#').filter(|tag| !tag.is_empty()) {
+            tags.push(unescape(tag));
+        } else {
+            words.push(word);
+        }
+    }
+    let mut title = words.join(" ");
+    // The suffix `item` writes
+    if let Some((head, date)) = title.strip_suffix(')').and_then(|rest| rest.rsplit_once(" (due ")) {
+        if let Ok(date) = parse_due(date) {
+            due_at.get_or_insert(date);
+            title = head.to_owned();
+        }
+    }
+    let title = unescape(&title);
+    if title.is_empty() {
+        return Err("checklist item without a title".to_owned());
+    }
+
+    Ok(Todo {
+        id: Uuid::new_v4(),
+        title,
+        description: None,
+        completed,
+        due_at,
+        tags: normalize_tags(tags),
+        estimate_minutes: None,
+        expires_at: None,
+        project_id: None,
+        priority: None,
+        pinned: false,
+        custom_fields: BTreeMap::new(),
+        created_at: now,
+        updated_at: now,
+        blocked: false,
+        version: 0,
+        change_seq: 0,
+    })
+}
+
+/// Splits on whitespace that isn't backslash-escaped, as in a tag written `#long\ tag`.
+fn tokens(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() && !escaped {
+            if let Some(start) = start.take() {
+                words.push(&text[start..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    if let Some(start) = start {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+/// RFC 3339, or a plain `YYYY-MM-DD` meaning midnight UTC.
+fn parse_due(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).expect("midnight is a valid time").and_utc())
+        .map_err(|_| format!("due date {value:?} is not RFC 3339 or YYYY-MM-DD"))
+}
+
+/// Undoes `escape` and `escape_tag`: a backslash before punctuation or a space keeps just
+/// what follows it.
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && (next.is_ascii_punctuation() || *next == ' ') => {
+                out.push(*next);
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap()
+    }
+
+    fn todo(title: &str, tags: &[&str]) -> Todo {
+        let mut todo = parse_item(&format!("- [ ] {}", escape(title)), now()).unwrap();
+        todo.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        todo
+    }
+
+    fn titles(section: &Section) -> Vec<String> {
+        section.todos.iter().map(|todo| todo.title.clone()).collect()
+    }
+
+    #[test]
+    fn items_show_the_box_due_date_and_tags() {
+        let mut done = todo("Ship *it*", &["work", "q4 goals"]);
+        done.completed = true;
+        done.due_at = Some(Utc.with_ymd_and_hms(2026, 11, 1, 17, 0, 0).unwrap());
+        assert_eq!(item(&done), r"- [x] Ship \*it\* (due 2026-11-01) #work #q4\ goals");
+        assert_eq!(item(&todo("Buy milk", &[])), "- [ ] Buy milk");
+    }
+
+    #[test]
+    fn escaping_covers_markdown_and_line_breaks() {
+        assert_eq!(escape("a_b [c] #d\r\ne"), r"a\_b \[c\] \#d  e");
+        assert_eq!(unescape(r"a\_b \[c\] \#d"), "a_b [c] #d");
+        // Letters after a backslash aren't escapes
+        assert_eq!(unescape(r"C:\dir"), r"C:\dir");
+    }
+
+    #[test]
+    fn tags_group_each_todo_under_every_one_and_untagged_last() {
+        let todos = [todo("Both", &["b", "a"]), todo("Loose", &[]), todo("Only b", &["b"])];
+        let sections = by_tag(&todos);
+        let headings: Vec<_> = sections.iter().map(|section| section.heading.as_str()).collect();
+        assert_eq!(headings, ["a", "b", UNTAGGED]);
+        assert_eq!(titles(&sections[1]), ["Both", "Only b"]);
+        assert_eq!(titles(&sections[2]), ["Loose"]);
+    }
+
+    #[test]
+    fn projects_group_by_name_with_loose_todos_last() {
+        let todos = [todo("In Zeta", &[]), todo("Loose", &[]), todo("In Alpha", &[])];
+        let sections = by_project(&todos, |todo| match todo.title.as_str() {
+            "In Zeta" => Some("Zeta".to_owned()),
+            "In Alpha" => Some("Alpha".to_owned()),
+            _ => None,
+        });
+        let headings: Vec<_> = sections.iter().map(|section| section.heading.as_str()).collect();
+        assert_eq!(headings, ["Alpha", "Zeta", NO_PROJECT]);
+        // Nothing to group, nothing written
+        assert_eq!(checklist(&by_project(&[], |_| None)), "");
+    }
+
+    #[test]
+    fn checklists_read_items_tokens_and_skip_headings() {
+        let text = "# Sprint\n\n  * [X] Deploy due:2026-11-02T09:30:00+01:00 #ops\n+ [ ] Write docs (due 2026-11-03)\n";
+        let parsed = parse_checklist(text, now()).unwrap();
+        assert!(parsed.unparsed.is_empty());
+        let [deploy, docs] = &parsed.todos[..] else { panic!("{:?}", parsed.todos) };
+        assert_eq!((deploy.title.as_str(), deploy.completed), ("Deploy", true));
+        assert_eq!(deploy.tags, ["ops"]);
+        assert_eq!(deploy.due_at, Some(Utc.with_ymd_and_hms(2026, 11, 2, 8, 30, 0).unwrap()));
+        assert_eq!(docs.title, "Write docs");
+        assert_eq!(docs.due_at, Some(Utc.with_ymd_and_hms(2026, 11, 3, 0, 0, 0).unwrap()));
+        assert_eq!((docs.created_at, docs.updated_at), (now(), now()));
+    }
+
+    #[test]
+    fn lines_that_are_not_items_are_reported_with_why() {
+        let text = "Notes\n- plain bullet\n- [x]nope\n- [ ] #only-a-tag\n- [ ] Pay due:someday\n#hashtag\n";
+        let parsed = parse_checklist(text, now()).unwrap();
+        assert!(parsed.todos.is_empty());
+        let reasons: Vec<_> = parsed.unparsed.iter().map(|line| (line.line, line.error.as_str())).collect();
+        assert_eq!(
+            reasons,
+            [
+                (1, "not a checklist item"),
+                (2, "list item without a [ ] or [x] box"),
+                (3, "list item without a [ ] or [x] box"),
+                (4, "checklist item without a title"),
+                (5, "due date \"someday\" is not RFC 3339 or YYYY-MM-DD"),
+                (6, "not a checklist item"),
+            ]
+        );
+    }
+
+    #[test]
+    fn checklists_past_the_limit_are_refused() {
+        let text = "- [ ] item\n".repeat(MAX_CHECKLIST_ITEMS);
+        assert_eq!(parse_checklist(&text, now()).unwrap().todos.len(), MAX_CHECKLIST_ITEMS);
+        assert!(parse_checklist(&format!("{text}- [ ] one more\n"), now()).is_err());
+    }
+
+    #[test]
+    fn an_export_reads_back_as_it_was_written() {
+        let mut release = todo("Release v2.0 [beta] #1 \\o/", &["work", "launch week"]);
+        release.due_at = Some(Utc.with_ymd_and_hms(2026, 11, 1, 0, 0, 0).unwrap());
+        let mut milk = todo("Buy milk_", &[]);
+        milk.completed = true;
+        let todos = [release, milk];
+
+        let exported = checklist(&by_project(&todos, |_| None));
+        let imported = parse_checklist(&exported, now()).unwrap();
+        assert!(imported.unparsed.is_empty(), "{:?}", imported.unparsed);
+        assert_eq!(imported.todos.len(), todos.len());
+        for (before, after) in todos.iter().zip(&imported.todos) {
+            assert_eq!(after.title, before.title);
+            assert_eq!(after.tags, before.tags);
+            assert_eq!(after.completed, before.completed);
+            assert_eq!(after.due_at, before.due_at);
+        }
+    }
+}
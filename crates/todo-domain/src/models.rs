@@ -0,0 +1,1302 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// A todo as the rest of the code sees it. Its wire format is `TodoDto` and its columns
+/// are the storage crate's `TodoRow`, each mapped explicitly, so a field can be added or
+/// renamed here without changing either.
+#[derive(Debug, Clone)]
+pub struct Todo {
+    pub id: Uuid,
+    pub title: String,
+    pub description: Option<String>,
+    pub completed: bool,
+    pub due_at: Option<DateTime<Utc>>,
+    pub tags: Vec<String>,
+    pub estimate_minutes: Option<u32>,
+    /// Expired todos drop out of lists and are deleted by the expiry sweep.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The shared project this todo belongs to; only its members can see it.
+    pub project_id: Option<Uuid>,
+    pub priority: Option<Priority>,
+    /// Pinned todos head `GET /todos/next` whatever else is due.
+    pub pinned: bool,
+    /// Values of the fields defined through `/admin/custom-fields`, by field name.
+    pub custom_fields: BTreeMap<String, Value>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// True while any todo blocking this one is still open. Computed on read.
+    pub blocked: bool,
+    /// Counts writes to this todo; offline clients send it back as `base_version`.
+    pub version: u64,
+    /// Where this todo's latest change falls in the server's change sequence, the same
+    /// counter `GET /sync` tokens come from. It is assigned by the database rather than read
+    /// from a clock, so unlike `updated_at` it only ever goes up, whatever the clocks of the
+    /// clients and replicas say.
+    pub change_seq: u64,
+}
+
+/// A todo as responses, events, webhooks and exports write it. Every field clients see is
+/// listed here, so removing or renaming one is a visible change to this struct rather than
+/// a side effect of changing `Todo`.
+#[derive(Debug, Serialize)]
+pub struct TodoDto<'a> {
+    pub id: Uuid,
+    pub title: &'a str,
+    pub description: Option<&'a str>,
+    pub completed: bool,
+    pub due_at: Option<DateTime<Utc>>,
+    pub tags: &'a [String],
+    pub estimate_minutes: Option<u32>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub project_id: Option<Uuid>,
+    pub priority: Option<Priority>,
+    pub pinned: bool,
+    pub custom_fields: &'a BTreeMap<String, Value>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub blocked: bool,
+    pub version: u64,
+    pub change_seq: u64,
+}
+
+impl<'a> From<&'a Todo> for TodoDto<'a> {
+    fn from(todo: &'a Todo) -> Self {
+        Self {
+            id: todo.id,
+            title: &todo.title,
+            description: todo.description.as_deref(),
+            completed: todo.completed,
+            due_at: todo.due_at,
+            tags: &todo.tags,
+            estimate_minutes: todo.estimate_minutes,
+            expires_at: todo.expires_at,
+            project_id: todo.project_id,
+            priority: todo.priority,
+            pinned: todo.pinned,
+            custom_fields: &todo.custom_fields,
+            created_at: todo.created_at,
+            updated_at: todo.updated_at,
+            blocked: todo.blocked,
+            version: todo.version,
+            change_seq: todo.change_seq,
+        }
+    }
+}
+
+/// Todos are written as their `TodoDto`, wherever they appear in a response.
+impl Serialize for Todo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TodoDto::from(self).serialize(serializer)
+    }
+}
+
+/// How much a todo matters, as set on create and update or read by `POST /todos/parse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    High,
+    Medium,
+    Low,
+}
+
+impl Priority {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::High => "high",
+            Self::Medium => "medium",
+            Self::Low => "low",
+        }
+    }
+}
+
+impl std::str::FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "high" => Ok(Self::High),
+            "medium" => Ok(Self::Medium),
+            "low" => Ok(Self::Low),
+            other => Err(format!("unknown priority: {other}")),
+        }
+    }
+}
+
+/// Trims tags, drops empty ones and removes duplicates, keeping the first occurrence.
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|t| t == tag) {
+            normalized.push(tag.to_owned());
+        }
+    }
+    normalized
+}
+
+/// Drops custom fields given as `null`, which on a new todo just means "not set".
+pub fn normalize_custom_fields(values: BTreeMap<String, Value>) -> BTreeMap<String, Value> {
+    values.into_iter().filter(|(_, value)| !value.is_null()).collect()
+}
+
+/// The fields a long list view needs, without descriptions or timestamps.
+#[derive(Debug, Clone, Serialize)]
+pub struct CompactTodo {
+    pub id: Uuid,
+    pub title: String,
+    pub completed: bool,
+    pub due_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CreateTodoRequest {
+    #[schemars(length(min = 1))]
+    pub title: String,
+    pub description: Option<String>,
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub estimate_minutes: Option<u32>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub custom_fields: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UpdateTodoRequest {
+    #[schemars(length(min = 1))]
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub completed: Option<bool>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub tags: Option<Vec<String>>,
+    pub estimate_minutes: Option<u32>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub priority: Option<Priority>,
+    pub pinned: Option<bool>,
+    /// Custom fields to set; the others keep their values, and `null` clears one.
+    pub custom_fields: Option<BTreeMap<String, Value>>,
+}
+
+impl UpdateTodoRequest {
+    /// Overwrites the fields that were given and stamps `updated_at` with `now`.
+    pub fn apply(self, todo: &mut Todo, now: DateTime<Utc>) {
+        if let Some(title) = self.title {
+            todo.title = title;
+        }
+        if let Some(description) = self.description {
+            todo.description = Some(description);
+        }
+        if let Some(completed) = self.completed {
+            todo.completed = completed;
+        }
+        if let Some(due_at) = self.due_at {
+            todo.due_at = Some(due_at);
+        }
+        if let Some(tags) = self.tags {
+            todo.tags = normalize_tags(tags);
+        }
+        if let Some(estimate_minutes) = self.estimate_minutes {
+            todo.estimate_minutes = Some(estimate_minutes);
+        }
+        if let Some(expires_at) = self.expires_at {
+            todo.expires_at = Some(expires_at);
+        }
+        if let Some(priority) = self.priority {
+            todo.priority = Some(priority);
+        }
+        if let Some(pinned) = self.pinned {
+            todo.pinned = pinned;
+        }
+        for (name, value) in self.custom_fields.unwrap_or_default() {
+            if value.is_null() {
+                todo.custom_fields.remove(&name);
+            } else {
+                todo.custom_fields.insert(name, value);
+            }
+        }
+        todo.updated_at = now;
+    }
+}
+
+/// Push the due date forward by `minutes`, or to `until`. Exactly one must be given.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SnoozeRequest {
+    pub minutes: Option<i64>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Free text for `POST /todos/parse`. Times in the text are read in `utc_offset_minutes`
+/// (UTC when absent).
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ParseTodoRequest {
+    pub text: String,
+    pub utc_offset_minutes: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AddDependencyRequest {
+    pub blocker_id: Uuid,
+}
+
+/// Open todos and their estimated effort for one tag.
+#[derive(Debug, Clone, Serialize)]
+pub struct TagRollup {
+    pub tag: String,
+    pub open_count: u64,
+    pub open_estimate_minutes: u64,
+}
+
+/// Counts and effort rollups across all todos.
+#[derive(Debug, Clone, Serialize)]
+pub struct TodoStats {
+    pub open_count: u64,
+    pub completed_count: u64,
+    /// Sum of `estimate_minutes` over open todos; unestimated ones count as zero.
+    pub open_estimate_minutes: u64,
+    pub unestimated_open_count: u64,
+    pub by_tag: Vec<TagRollup>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VelocityPeriod {
+    Day,
+    /// Weeks start on Monday (UTC).
+    Week,
+}
+
+/// `GET /stats/velocity?period=day|week&periods=N`.
+#[derive(Debug, Deserialize)]
+pub struct VelocityQuery {
+    pub period: Option<VelocityPeriod>,
+    pub periods: Option<u32>,
+}
+
+/// `GET /todos/next?limit=N`.
+#[derive(Debug, Deserialize)]
+pub struct NextTodosQuery {
+    pub limit: Option<usize>,
+}
+
+/// What `GET /todos/export` writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// A GitHub-flavored Markdown task list.
+    #[default]
+    Markdown,
+}
+
+/// What an export's sections are headed by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportGroup {
+    #[default]
+    Project,
+    /// A todo with several tags is listed under each.
+    Tag,
+}
+
+/// `GET /todos/export?format=markdown&group=project|tag&download=true`.
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<ExportFormat>,
+    pub group: Option<ExportGroup>,
+    /// Sends the export as a file to save rather than to show.
+    #[serde(default)]
+    pub download: bool,
+}
+
+/// Completions per period, oldest first and ending with the current one. Periods with no
+/// completions are included with a count of zero.
+#[derive(Debug, Clone, Serialize)]
+pub struct Velocity {
+    pub period: VelocityPeriod,
+    pub points: Vec<VelocityPoint>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VelocityPoint {
+    pub start: NaiveDate,
+    pub completed: u64,
+}
+
+/// Open todos grouped by how long ago they were created.
+#[derive(Debug, Clone, Serialize)]
+pub struct TodoAging {
+    pub open_count: u64,
+    pub buckets: Vec<AgingBucket>,
+}
+
+/// Todos at least `min_days` and less than `max_days` old; the last bucket has no upper bound.
+#[derive(Debug, Clone, Serialize)]
+pub struct AgingBucket {
+    pub min_days: u32,
+    pub max_days: Option<u32>,
+    pub count: u64,
+}
+
+/// `GET /stats/completion-time?days=N`.
+#[derive(Debug, Deserialize)]
+pub struct CompletionTimeQuery {
+    pub days: Option<u32>,
+}
+
+/// Seconds from creation to completion for todos completed in the last `window_days`.
+/// Percentiles use the nearest-rank method and are absent when nothing was completed.
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionTime {
+    pub window_days: u32,
+    pub count: u64,
+    pub mean_seconds: Option<f64>,
+    pub p50_seconds: Option<f64>,
+    pub p75_seconds: Option<f64>,
+    pub p90_seconds: Option<f64>,
+    pub p95_seconds: Option<f64>,
+    pub max_seconds: Option<f64>,
+}
+
+/// `GET /admin/slow-queries?limit=N`.
+#[derive(Debug, Deserialize)]
+pub struct SlowQueryQuery {
+    pub limit: Option<usize>,
+}
+
+/// `GET /admin/usage?hours=N&limit=N`.
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    pub hours: Option<u32>,
+    pub limit: Option<usize>,
+}
+
+/// Requests one client made to one endpoint, as kept per hour in the usage rollup.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UsageKey {
+    /// `token:<id>`, `user:<id>`, `key:<hash>` or `ip:<address>`.
+    pub client: String,
+    pub user_id: Option<Uuid>,
+    pub method: String,
+    /// The matched route template, e.g. `/todos/:id`.
+    pub route: String,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsageCounts {
+    pub requests: u64,
+    /// `4xx` responses.
+    pub client_errors: u64,
+    /// `5xx` responses.
+    pub server_errors: u64,
+}
+
+/// `GET /sync?since=<token>&limit=N`. Without `since`, every todo comes back as created.
+#[derive(Debug, Deserialize)]
+pub struct SyncQuery {
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// A todo deleted after the client's sync token.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeletedTodo {
+    pub id: Uuid,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// What changed after a sync token, oldest change first. A todo appears once, as it is now.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncChanges {
+    pub created: Vec<Todo>,
+    pub updated: Vec<Todo>,
+    pub deleted: Vec<DeletedTodo>,
+    /// Opaque; send it as `since` on the next sync.
+    pub token: String,
+    /// Changes after `token` are waiting; sync again straight away to get them.
+    pub has_more: bool,
+}
+
+/// `POST /sync/push`: edits a client made while offline, applied in order.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SyncPush {
+    pub mutations: Vec<SyncMutation>,
+}
+
+/// One offline edit. Updates and deletes carry the `version` of the todo the client last
+/// saw, so anything written on the server since then is caught as a conflict.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SyncMutation {
+    /// The client picks the id, so it can refer to the todo before it reaches the server.
+    Create { id: Uuid, todo: CreateTodoRequest },
+    Update { id: Uuid, base_version: u64, changes: UpdateTodoRequest },
+    Delete { id: Uuid, base_version: u64 },
+}
+
+impl SyncMutation {
+    pub fn id(&self) -> Uuid {
+        match self {
+            Self::Create { id, .. } | Self::Update { id, .. } | Self::Delete { id, .. } => *id,
+        }
+    }
+}
+
+/// Why a pushed edit wasn't applied.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictReason {
+    /// The todo was written on the server after `base_version`.
+    VersionMismatch,
+    /// The todo was deleted on the server.
+    Deleted,
+    /// A todo with the created id already exists.
+    Exists,
+    /// The todo belongs to a project the caller can't change; it isn't sent back.
+    Forbidden,
+}
+
+/// The outcome of one pushed edit, in the order they were sent.
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SyncMutationResult {
+    /// `todo` is the todo as now stored; deletes have none.
+    Applied { id: Uuid, todo: Option<Todo> },
+    /// Nothing was written. `client` is the edit as sent and `server` the todo as it is now,
+    /// if it still exists, for the client to merge and push again.
+    Conflict {
+        id: Uuid,
+        reason: ConflictReason,
+        client: SyncMutation,
+        server: Option<Todo>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncPushResponse {
+    pub results: Vec<SyncMutationResult>,
+    pub applied: usize,
+    pub conflicts: usize,
+}
+
+/// One entry in a todo's history, e.g. a snooze.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub event: String,
+    pub detail: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BatchCreateRequest {
+    pub todos: Vec<CreateTodoRequest>,
+}
+
+/// `?dry_run=true` on `POST /todos` and `PUT /todos/{id}`.
+#[derive(Debug, Default, Deserialize)]
+pub struct DryRunQuery {
+    /// Validate and answer with the would-be result, but store nothing.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// `?expand=` on `GET /todos/{id}`: related resources to embed, e.g. `comments,project`.
+#[derive(Debug, Default, Deserialize)]
+pub struct ExpandQuery {
+    pub expand: Option<String>,
+}
+
+/// `POST /todos/batch?atomic=false&dry_run=true`.
+#[derive(Debug, Deserialize)]
+pub struct BatchCreateQuery {
+    /// Create nothing unless every item is valid (the default); `false` creates the valid
+    /// items and reports the rest.
+    #[serde(default = "default_atomic")]
+    pub atomic: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+fn default_atomic() -> bool {
+    true
+}
+
+/// A `GET /todos?page=&per_page=` page, with where it falls in the whole list. Its todos
+/// are `ExpandedTodo`s when the request has `?expand=`.
+#[derive(Debug, Serialize)]
+pub struct TodoListPage<T = Todo> {
+    pub todos: Vec<T>,
+    /// From 1.
+    pub page: usize,
+    pub per_page: usize,
+    /// Todos in the whole list, not just this page.
+    pub total: u64,
+    pub total_pages: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchItemError {
+    /// Position of the item in `todos`, from 0.
+    pub index: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchCreateResponse {
+    pub atomic: bool,
+    /// Nothing was stored; `created` shows what would have been.
+    pub dry_run: bool,
+    pub created: Vec<Todo>,
+    pub total: usize,
+    pub errors: Vec<BatchItemError>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct BatchDeleteRequest {
+    pub ids: Vec<Uuid>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize)]
+pub struct BatchDeleteResponse {
+    pub deleted: usize,
+    pub not_found: Vec<Uuid>,
+}
+
+/// How `POST /todos/import` treats rows that fail validation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Import nothing unless every row is valid.
+    Transactional,
+    /// Import the valid rows and report the rest.
+    #[default]
+    BestEffort,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub mode: ImportMode,
+    /// Validate the rows and count what would be created, but store nothing.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportRowError {
+    /// Line in the uploaded file, counting the header as line 1.
+    pub line: u64,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    pub mode: ImportMode,
+    pub dry_run: bool,
+    pub total_rows: usize,
+    pub created: usize,
+    pub errors: Vec<ImportRowError>,
+}
+
+/// A line of an imported checklist that didn't become a todo.
+#[derive(Debug, Serialize)]
+pub struct UnparsedLine {
+    /// Counting from 1.
+    pub line: u64,
+    pub text: String,
+    pub error: String,
+}
+
+/// `POST /import/markdown?dry_run=true`.
+#[derive(Debug, Deserialize)]
+pub struct MarkdownImportQuery {
+    /// Parse the checklist and list the todos it would create, but store nothing.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarkdownImportResponse {
+    pub dry_run: bool,
+    pub created: Vec<Todo>,
+    pub unparsed: Vec<UnparsedLine>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteCompletedResponse {
+    pub deleted_count: usize,
+    pub deleted_ids: Vec<Uuid>,
+}
+
+/// Body of `POST /todos/tags`: the todos to change, as `ids` or a `filter` but not both, and
+/// the tags to add to and remove from each.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BulkTagRequest {
+    #[serde(default)]
+    pub ids: Option<Vec<Uuid>>,
+    #[serde(default)]
+    pub filter: Option<BulkTagFilter>,
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+/// Which todos `POST /todos/tags` changes; each condition given must hold, and `{}` matches
+/// every todo.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct BulkTagFilter {
+    #[serde(default)]
+    pub completed: Option<bool>,
+    /// Todos carrying this tag.
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub project_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkTagResponse {
+    pub updated_count: usize,
+    /// Todos already tagged as asked are left alone, and not listed.
+    pub updated_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupResponse {
+    pub snapshot: String,
+    pub path: String,
+    pub checksum: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RestoreRequest {
+    pub snapshot: String,
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RestoreResponse {
+    pub snapshot: String,
+    pub restored_count: u64,
+}
+
+/// What a credential may do. Each route group requires one scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+pub enum Scope {
+    #[serde(rename = "todos:read")]
+    TodosRead,
+    #[serde(rename = "todos:write")]
+    TodosWrite,
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::TodosRead => "todos:read",
+            Self::TodosWrite => "todos:write",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl std::str::FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "todos:read" => Ok(Self::TodosRead),
+            "todos:write" => Ok(Self::TodosWrite),
+            "admin" => Ok(Self::Admin),
+            other => Err(format!("unknown scope {other:?}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct User {
+    pub id: Uuid,
+    pub email: String,
+    /// What others write after `@` to mention this user; unset until they choose one.
+    pub username: Option<String>,
+    pub mention_channel: MentionChannel,
+    pub created_at: DateTime<Utc>,
+    /// Holds the `admin` scope. Only an operator grants it, with `todo grant-admin`.
+    pub admin: bool,
+}
+
+/// How a user hears about being mentioned. Every mention also goes to their activity feed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MentionChannel {
+    #[default]
+    Email,
+    /// Only the activity feed.
+    Feed,
+}
+
+impl MentionChannel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Email => "email",
+            Self::Feed => "feed",
+        }
+    }
+}
+
+impl std::str::FromStr for MentionChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "email" => Ok(Self::Email),
+            "feed" => Ok(Self::Feed),
+            other => Err(format!("unknown mention channel {other:?}")),
+        }
+    }
+}
+
+/// `PATCH /users/me`. Omitted fields are left alone; an empty `username` removes it.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UpdateProfileRequest {
+    pub username: Option<String>,
+    pub mention_channel: Option<MentionChannel>,
+}
+
+/// Body of both `/auth/register` and `/auth/login`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Credentials {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: u64,
+    pub refresh_token: String,
+}
+
+/// Body of `/auth/refresh` and `/auth/logout`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+/// A personal access token, without its secret.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Never expires when omitted.
+    pub expires_in_days: Option<u32>,
+}
+
+/// One row of the authentication audit log.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub event: String,
+    pub user_id: Option<Uuid>,
+    pub email: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Filters for `GET /admin/audit`. Entries come newest first; pass the last `id` seen as
+/// `before` for the next page.
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub event: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub since: Option<DateTime<Utc>>,
+    pub before: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+/// A runtime switch stored in the database.
+#[derive(Debug, Clone, Serialize)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+    pub description: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Body of `PUT /admin/flags/:name`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetFeatureFlagRequest {
+    pub enabled: bool,
+    pub description: Option<String>,
+}
+
+/// A notification event webhooks can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum WebhookEvent {
+    #[serde(rename = "todo.created")]
+    Created,
+    #[serde(rename = "todo.completed")]
+    Completed,
+    #[serde(rename = "todo.batch_created")]
+    BatchCreated,
+    #[serde(rename = "todo.digest")]
+    Digest,
+}
+
+impl WebhookEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Created => "todo.created",
+            Self::Completed => "todo.completed",
+            Self::BatchCreated => "todo.batch_created",
+            Self::Digest => "todo.digest",
+        }
+    }
+}
+
+/// How a webhook payload is shaped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// The event with the todo's id and title, as sent to the `WEBHOOK_*_URL` targets.
+    #[default]
+    Delta,
+    /// `delta` plus the whole todo, as of delivery.
+    Full,
+    /// `full`, wrapped in a CloudEvents 1.0 envelope.
+    CloudEvents,
+}
+
+impl WebhookFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Delta => "delta",
+            Self::Full => "full",
+            Self::CloudEvents => "cloudevents",
+        }
+    }
+}
+
+impl std::str::FromStr for WebhookFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "delta" => Ok(Self::Delta),
+            "full" => Ok(Self::Full),
+            "cloudevents" => Ok(Self::CloudEvents),
+            other => Err(format!("unknown webhook format {other:?}")),
+        }
+    }
+}
+
+/// A receiver registered through `/admin/webhooks`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub format: WebhookFormat,
+    /// Which todo fields `full` and `cloudevents` payloads carry. A version's fields never
+    /// change; new ones only appear in a new version.
+    pub api_version: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Body of `POST /admin/webhooks`. Defaults to the `delta` format and the latest API version.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub format: Option<WebhookFormat>,
+    pub api_version: Option<u32>,
+}
+
+/// What values a custom field takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomFieldType {
+    Text,
+    Number,
+    Boolean,
+    /// A calendar date, `YYYY-MM-DD`.
+    Date,
+}
+
+impl CustomFieldType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Number => "number",
+            Self::Boolean => "boolean",
+            Self::Date => "date",
+        }
+    }
+}
+
+impl std::str::FromStr for CustomFieldType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "number" => Ok(Self::Number),
+            "boolean" => Ok(Self::Boolean),
+            "date" => Ok(Self::Date),
+            other => Err(format!("unknown custom field type {other:?}")),
+        }
+    }
+}
+
+/// A field defined through `/admin/custom-fields`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CustomFieldDef {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: CustomFieldType,
+    /// New todos must have a value for it, and updates can't clear it.
+    pub required: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Body of `POST /admin/custom-fields`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateCustomFieldRequest {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: CustomFieldType,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// A tag as listed by `/tags`: every tag some todo carries, and every tag given a color or
+/// description, whether or not a todo carries it yet.
+#[derive(Debug, Clone, Serialize)]
+pub struct Tag {
+    pub name: String,
+    /// `#rrggbb`, lowercase.
+    pub color: Option<String>,
+    pub description: Option<String>,
+    /// Todos carrying the tag, completed ones included.
+    pub usage_count: u64,
+    pub open_count: u64,
+}
+
+/// Body of `PUT /tags/:name`, which replaces both fields.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UpdateTagRequest {
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Body of `POST /tags/:name/rename`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RenameTagRequest {
+    pub name: String,
+}
+
+/// Body of `POST /tags/:name/merge`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MergeTagRequest {
+    pub into: String,
+}
+
+/// What a rename or merge left behind.
+#[derive(Debug, Clone, Serialize)]
+pub struct TagChange {
+    pub tag: Tag,
+    /// Todos whose tags were rewritten.
+    pub todos_updated: u64,
+}
+
+/// Body of `DELETE /users/me`; the password confirms the request.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DeleteAccountRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErasureStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+}
+
+impl ErasureStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Running => "running",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for ErasureStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "running" => Ok(Self::Running),
+            "completed" => Ok(Self::Completed),
+            "failed" => Ok(Self::Failed),
+            other => Err(format!("unknown erasure status {other:?}")),
+        }
+    }
+}
+
+/// An account erasure request and how far it has got.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErasureJob {
+    pub id: Uuid,
+    pub status: ErasureStatus,
+    pub steps_completed: u32,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A login session: the chain of refresh tokens one login started.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub last_refreshed_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Everything stored about an account, as returned by `GET /users/me/export`.
+#[derive(Debug, Serialize)]
+pub struct UserExport {
+    pub generated_at: DateTime<Utc>,
+    pub user: User,
+    pub api_tokens: Vec<ApiToken>,
+    pub sessions: Vec<SessionInfo>,
+    pub activity: Vec<AuditEntry>,
+    /// The todos the account can see: those outside projects and in its projects.
+    pub todos: Vec<Todo>,
+    pub comments: Vec<Comment>,
+    /// The account's activity feed, newest first.
+    pub mentions: Vec<ActivityItem>,
+    /// Metadata of the files the account uploaded; the files themselves aren't included.
+    pub attachments: Vec<Attachment>,
+}
+
+/// Where to fetch an export that is too large to return inline.
+#[derive(Debug, Serialize)]
+pub struct ExportJob {
+    pub id: Uuid,
+    /// Always `pending`; a finished export is served as the file itself.
+    pub status: &'static str,
+    pub download_url: String,
+}
+
+/// The only response that includes the token itself.
+#[derive(Debug, Serialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    pub token: String,
+}
+
+/// What a project member may do. Each role can do everything the ones after it can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectRole {
+    /// Manages members, as well as editing.
+    Owner,
+    /// Creates, changes and deletes the project's todos.
+    Editor,
+    Viewer,
+}
+
+impl ProjectRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::Editor => "editor",
+            Self::Viewer => "viewer",
+        }
+    }
+
+    pub fn can_edit(self) -> bool {
+        self <= Self::Editor
+    }
+}
+
+impl std::str::FromStr for ProjectRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "owner" => Ok(Self::Owner),
+            "editor" => Ok(Self::Editor),
+            "viewer" => Ok(Self::Viewer),
+            other => Err(format!("unknown project role {other:?}")),
+        }
+    }
+}
+
+/// A shared list, with the caller's role in it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Project {
+    pub id: Uuid,
+    pub name: String,
+    pub role: ProjectRole,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectMember {
+    pub user_id: Uuid,
+    pub email: String,
+    pub role: ProjectRole,
+    pub added_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateProjectRequest {
+    pub name: String,
+}
+
+/// `POST /projects/:id/members`: adds the account with this email, or changes its role.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AddMemberRequest {
+    pub email: String,
+    pub role: ProjectRole,
+}
+
+/// A project as `?expand=project` embeds it in its todos.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectRef {
+    pub id: Uuid,
+    pub name: String,
+}
+
+/// A todo with the related resources `?expand=` asked for embedded in it. Those not asked
+/// for are left out rather than written empty; an expanded `project` is `null` for a todo
+/// that isn't in one.
+#[derive(Debug, Serialize)]
+pub struct ExpandedTodo {
+    #[serde(flatten)]
+    pub todo: Todo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<Vec<Comment>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<Option<ProjectRef>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Comment {
+    pub id: Uuid,
+    pub todo_id: Uuid,
+    pub author_id: Option<Uuid>,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A file attached to a todo. Its bytes are in the blob store, under `blob_key`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub todo_id: Uuid,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: u64,
+    #[serde(skip)]
+    pub blob_key: String,
+    pub created_at: DateTime<Utc>,
+    /// Who uploaded it, if they were signed in; `None` once their account is erased.
+    pub uploaded_by: Option<Uuid>,
+}
+
+/// `POST /todos/{id}/attachments?filename=...`; the body is the file itself.
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    pub filename: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateCommentRequest {
+    pub body: String,
+}
+
+/// `GET /users/me/activity?before=<id>&limit=N`, for paging back through the feed.
+#[derive(Debug, Deserialize)]
+pub struct ActivityQuery {
+    pub before: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityKind {
+    Mention,
+}
+
+impl ActivityKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mention => "mention",
+        }
+    }
+}
+
+impl std::str::FromStr for ActivityKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mention" => Ok(Self::Mention),
+            other => Err(format!("unknown activity kind {other:?}")),
+        }
+    }
+}
+
+/// One entry in a user's activity feed, newest first in `GET /users/me/activity`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityItem {
+    pub id: i64,
+    pub kind: ActivityKind,
+    pub todo_id: Uuid,
+    /// The comment the mention was in; `None` for a todo's description.
+    pub comment_id: Option<Uuid>,
+    /// Who wrote the mention, if they were signed in.
+    pub actor_id: Option<Uuid>,
+    /// The text around the mention.
+    pub excerpt: String,
+    pub created_at: DateTime<Utc>,
+}
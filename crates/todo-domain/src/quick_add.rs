@@ -0,0 +1,548 @@
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, SubsecRound,
+    TimeZone, Utc, Weekday,
+};
+use serde::Serialize;
+
+pub use crate::models::Priority;
+
+/// Time of day used when the text names a day but no time.
+const DEFAULT_TIME: NaiveTime = match NaiveTime::from_hms_opt(9, 0, 0) {
+    Some(time) => time,
+    None => unreachable!(),
+};
+const TONIGHT: NaiveTime = match NaiveTime::from_hms_opt(20, 0, 0) {
+    Some(time) => time,
+    None => unreachable!(),
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Frequency {
+    Daily,
+    /// Monday to Friday.
+    Weekdays,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// How often a todo repeats, e.g. every 2 weeks on Monday.
+#[derive(Debug, Clone, Serialize)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    /// Every `interval` days, weeks, months or years.
+    pub interval: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weekday: Option<Weekday>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day_of_month: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub month: Option<u32>,
+}
+
+/// What `POST /todos/parse` made of the text. `title`, `due_at`, `tags` and `priority` can be
+/// sent to `POST /todos` as they are.
+#[derive(Debug, Serialize)]
+pub struct ParsedTodo {
+    pub title: String,
+    pub due_at: Option<DateTime<Utc>>,
+    pub tags: Vec<String>,
+    pub priority: Option<Priority>,
+    pub recurrence: Option<Recurrence>,
+    /// The phrases that were understood, in order. Every other word is part of the title.
+    pub recognized: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("No title left after reading dates, tags and priority")]
+    NoTitle,
+
+    #[error("The time named doesn't exist in that time zone")]
+    InvalidTime,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Day {
+    Today,
+    Tomorrow,
+    /// The next such weekday, today included unless `skip_today`.
+    Weekday { weekday: Weekday, skip_today: bool },
+    Date(NaiveDate),
+}
+
+#[derive(Debug)]
+enum Token {
+    Tag(String),
+    Priority(Priority),
+    Time(NaiveTime),
+    Day(Day),
+    In(Duration),
+    Every(Recurrence),
+}
+
+/// Reads a todo out of text like `pay rent every 1st at 9am #finance !high`. Times are
+/// local to `now`'s offset; the result is in UTC.
+pub fn parse(text: &str, now: DateTime<FixedOffset>) -> Result<ParsedTodo, ParseError> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let keys: Vec<String> = words
+        .iter()
+        .map(|word| word.trim_end_matches([',', ';', '.']).to_lowercase())
+        .collect();
+
+    let mut title = Vec::new();
+    let mut tokens = Vec::new();
+    let mut recognized = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        match match_at(&keys[i..], words[i]) {
+            Some((consumed, matched)) => {
+                recognized.push(words[i..i + consumed].join(" "));
+                tokens.extend(matched);
+                i += consumed;
+            }
+            None => {
+                title.push(words[i]);
+                i += 1;
+            }
+        }
+    }
+
+    let title = title.join(" ").trim_matches([' ', ',', ';', '-']).to_string();
+    if title.is_empty() {
+        return Err(ParseError::NoTitle);
+    }
+
+    let mut parsed = ParsedTodo {
+        title,
+        due_at: None,
+        tags: Vec::new(),
+        priority: None,
+        recurrence: None,
+        recognized,
+    };
+    let (mut time, mut day, mut offset) = (None, None, None);
+    for token in tokens {
+        match token {
+            Token::Tag(tag) => parsed.tags.push(tag),
+            Token::Priority(priority) => parsed.priority = Some(priority),
+            Token::Time(t) => time = Some(t),
+            Token::Day(d) => day = Some(d),
+            Token::In(duration) => offset = Some(duration),
+            Token::Every(recurrence) => parsed.recurrence = Some(recurrence),
+        }
+    }
+
+    let today = now.date_naive();
+    let local_due = if let Some(offset) = offset {
+        Some(now.naive_local().trunc_subsecs(0) + offset)
+    } else if let Some(day) = day {
+        Some(resolve_day(day, today).and_time(time.unwrap_or(DEFAULT_TIME)))
+    } else if let Some(recurrence) = &mut parsed.recurrence {
+        fill_anchor(recurrence, today);
+        Some(first_occurrence(recurrence, now.naive_local(), time.unwrap_or(DEFAULT_TIME)))
+    } else {
+        // A bare time means the next time the clock shows it
+        time.map(|time| {
+            let due = today.and_time(time);
+            if due > now.naive_local() {
+                due
+            } else {
+                due + Duration::days(1)
+            }
+        })
+    };
+    if let Some(local_due) = local_due {
+        let due = now
+            .timezone()
+            .from_local_datetime(&local_due)
+            .single()
+            .ok_or(ParseError::InvalidTime)?;
+        parsed.due_at = Some(due.with_timezone(&Utc));
+    }
+    Ok(parsed)
+}
+
+/// Recognizes a phrase at the start of `keys` (lowercased words), returning how many words
+/// it took. `word` is the first word as typed, for tags.
+fn match_at(keys: &[String], word: &str) -> Option<(usize, Vec<Token>)> {
+    let key = keys[0].as_str();
+    let next = keys.get(1).map(String::as_str);
+
+    if let Some(tag) = word.strip_prefix('#') {
+        let tag = tag.trim_end_matches([',', ';', '.']);
+        return (!tag.is_empty()).then(|| (1, vec![Token::Tag(tag.to_string())]));
+    }
+    if key.starts_with('!') {
+        return priority(key).map(|p| (1, vec![Token::Priority(p)]));
+    }
+
+    match key {
+        "every" => return every(&keys[1..]).map(|(n, r)| (n + 1, vec![Token::Every(r)])),
+        "daily" => return Some((1, vec![Token::Every(repeat(Frequency::Daily, 1))])),
+        "weekly" => return Some((1, vec![Token::Every(repeat(Frequency::Weekly, 1))])),
+        "monthly" => return Some((1, vec![Token::Every(repeat(Frequency::Monthly, 1))])),
+        "yearly" | "annually" => return Some((1, vec![Token::Every(repeat(Frequency::Yearly, 1))])),
+        "at" => return next.and_then(time_of_day).map(|t| (2, vec![Token::Time(t)])),
+        "in" => {
+            let amount = next.and_then(count)?;
+            let unit = keys.get(2)?;
+            return span(amount, unit).map(|d| (3, vec![Token::In(d)]));
+        }
+        "tonight" => return Some((1, vec![Token::Day(Day::Today), Token::Time(TONIGHT)])),
+        "on" | "by" | "due" => {
+            return day_at(&keys[1..]).map(|(n, d)| (n + 1, vec![Token::Day(d)]));
+        }
+        _ => {}
+    }
+    if let Some((n, d)) = day_at(keys) {
+        return Some((n, vec![Token::Day(d)]));
+    }
+    // Only times that can't be mistaken for numbers, like `9am` or `17:30`
+    let unambiguous = key.contains(':') || key.ends_with("am") || key.ends_with("pm")
+        || key == "noon" || key == "midnight";
+    time_of_day(key)
+        .filter(|_| unambiguous)
+        .map(|t| (1, vec![Token::Time(t)]))
+}
+
+fn priority(key: &str) -> Option<Priority> {
+    match key {
+        "!high" | "!h" | "!1" | "!!!" => Some(Priority::High),
+        "!medium" | "!med" | "!m" | "!2" | "!!" => Some(Priority::Medium),
+        "!low" | "!l" | "!3" => Some(Priority::Low),
+        _ => None,
+    }
+}
+
+fn repeat(frequency: Frequency, interval: u32) -> Recurrence {
+    Recurrence {
+        frequency,
+        interval,
+        weekday: None,
+        day_of_month: None,
+        month: None,
+    }
+}
+
+/// The words after `every`: `day`, `weekday`, `monday`, `1st`, `2 weeks`, `other week`...
+fn every(keys: &[String]) -> Option<(usize, Recurrence)> {
+    let first = keys.first()?.as_str();
+    if let Some(weekday) = weekday(first) {
+        return Some((1, Recurrence { weekday: Some(weekday), ..repeat(Frequency::Weekly, 1) }));
+    }
+    if let Some(day) = ordinal(first) {
+        return Some((1, Recurrence { day_of_month: Some(day), ..repeat(Frequency::Monthly, 1) }));
+    }
+    if first == "weekday" {
+        return Some((1, repeat(Frequency::Weekdays, 1)));
+    }
+    if let Some(frequency) = frequency(first) {
+        return Some((1, repeat(frequency, 1)));
+    }
+
+    let interval = if first == "other" { 2 } else { count(first)? };
+    let frequency = frequency(keys.get(1)?)?;
+    (interval > 0).then(|| (2, repeat(frequency, interval)))
+}
+
+fn frequency(unit: &str) -> Option<Frequency> {
+    match unit {
+        "day" | "days" => Some(Frequency::Daily),
+        "week" | "weeks" => Some(Frequency::Weekly),
+        "month" | "months" => Some(Frequency::Monthly),
+        "year" | "years" => Some(Frequency::Yearly),
+        _ => None,
+    }
+}
+
+/// `today`, `tomorrow`, `friday`, `next friday`, `this friday` or `2026-11-01`.
+fn day_at(keys: &[String]) -> Option<(usize, Day)> {
+    let first = keys.first()?.as_str();
+    let day = match first {
+        "today" => Day::Today,
+        "tomorrow" | "tmr" | "tmrw" => Day::Tomorrow,
+        "next" | "this" => {
+            let weekday = weekday(keys.get(1)?)?;
+            return Some((2, Day::Weekday { weekday, skip_today: first == "next" }));
+        }
+        _ => match weekday(first) {
+            Some(weekday) => Day::Weekday { weekday, skip_today: false },
+            None => Day::Date(NaiveDate::parse_from_str(first, "%Y-%m-%d").ok()?),
+        },
+    };
+    Some((1, day))
+}
+
+fn weekday(key: &str) -> Option<Weekday> {
+    // chrono also accepts three-letter names, which would swallow words like "sun" and "sat"
+    (key.len() > 3 || key == "mon" || key == "tue" || key == "wed" || key == "thu" || key == "fri")
+        .then(|| key.parse().ok())
+        .flatten()
+}
+
+/// `1st` to `31st`.
+fn ordinal(key: &str) -> Option<u32> {
+    let digits = key
+        .strip_suffix("st")
+        .or_else(|| key.strip_suffix("nd"))
+        .or_else(|| key.strip_suffix("rd"))
+        .or_else(|| key.strip_suffix("th"))?;
+    digits.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+/// A small number, written as digits or `a`/`an`.
+fn count(key: &str) -> Option<u32> {
+    match key {
+        "a" | "an" | "one" => Some(1),
+        "two" => Some(2),
+        "three" => Some(3),
+        _ => key.parse().ok().filter(|n| *n <= 1000),
+    }
+}
+
+fn span(amount: u32, unit: &str) -> Option<Duration> {
+    let amount = i64::from(amount);
+    match unit {
+        "minute" | "minutes" | "min" | "mins" => Some(Duration::minutes(amount)),
+        "hour" | "hours" | "hr" | "hrs" => Some(Duration::hours(amount)),
+        "day" | "days" => Some(Duration::days(amount)),
+        "week" | "weeks" => Some(Duration::weeks(amount)),
+        _ => None,
+    }
+}
+
+/// `9am`, `9:30pm`, `17:00`, `noon` or `midnight`; a bare hour is read on a 24-hour clock.
+fn time_of_day(key: &str) -> Option<NaiveTime> {
+    match key {
+        "noon" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {}
+    }
+    let (clock, meridiem) = match key.strip_suffix("am") {
+        Some(clock) => (clock, Some(false)),
+        None => match key.strip_suffix("pm") {
+            Some(clock) => (clock, Some(true)),
+            None => (key, None),
+        },
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) if minute.len() == 2 => {
+            (hour.parse::<u32>().ok()?, minute.parse().ok()?)
+        }
+        Some(_) => return None,
+        None => (clock.parse::<u32>().ok()?, 0),
+    };
+    let hour = match meridiem {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+fn resolve_day(day: Day, today: NaiveDate) -> NaiveDate {
+    match day {
+        Day::Today => today,
+        Day::Tomorrow => today + Duration::days(1),
+        Day::Weekday { weekday, skip_today } => {
+            let target = weekday.num_days_from_monday();
+            let ahead = (7 + target - today.weekday().num_days_from_monday()) % 7;
+            let ahead = if ahead == 0 && skip_today { 7 } else { ahead };
+            today + Duration::days(i64::from(ahead))
+        }
+        Day::Date(date) => date,
+    }
+}
+
+/// Pins a recurrence without a day (`every week`, `monthly`) to today's weekday or date.
+fn fill_anchor(recurrence: &mut Recurrence, today: NaiveDate) {
+    match recurrence.frequency {
+        Frequency::Weekly if recurrence.weekday.is_none() => {
+            recurrence.weekday = Some(today.weekday());
+        }
+        Frequency::Monthly if recurrence.day_of_month.is_none() => {
+            recurrence.day_of_month = Some(today.day());
+        }
+        Frequency::Yearly => {
+            recurrence.day_of_month = Some(today.day());
+            recurrence.month = Some(today.month());
+        }
+        _ => {}
+    }
+}
+
+/// The first time on or after `now` that the recurrence falls on.
+fn first_occurrence(recurrence: &Recurrence, now: NaiveDateTime, time: NaiveTime) -> NaiveDateTime {
+    let mut date = now.date();
+    // A year and a month covers every pattern, including the 29th of February
+    for _ in 0..400 {
+        if falls_on(recurrence, date) && date.and_time(time) > now {
+            return date.and_time(time);
+        }
+        date += Duration::days(1);
+    }
+    now.date().and_time(time)
+}
+
+fn falls_on(recurrence: &Recurrence, date: NaiveDate) -> bool {
+    match recurrence.frequency {
+        Frequency::Daily => true,
+        Frequency::Weekdays => !matches!(date.weekday(), Weekday::Sat | Weekday::Sun),
+        Frequency::Weekly => recurrence.weekday == Some(date.weekday()),
+        // The 31st falls on the last day of shorter months
+        Frequency::Monthly => recurrence
+            .day_of_month
+            .is_some_and(|day| date.day() == day.min(days_in_month(date))),
+        Frequency::Yearly => {
+            recurrence.month == Some(date.month()) && recurrence.day_of_month == Some(date.day())
+        }
+    }
+}
+
+fn days_in_month(date: NaiveDate) -> u32 {
+    let (year, month) = if date.month() == 12 {
+        (date.year() + 1, 1)
+    } else {
+        (date.year(), date.month() + 1)
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|first| first.pred_opt())
+        .map_or(31, |last| last.day())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Friday 16 October 2026, 14:30 at UTC+2.
+    fn now() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2026-10-16T14:30:45.5+02:00").unwrap()
+    }
+
+    fn parse_now(text: &str) -> ParsedTodo {
+        parse(text, now()).unwrap()
+    }
+
+    /// `due_at` back in the caller's offset, to compare with local times.
+    fn local_due(parsed: &ParsedTodo) -> Option<String> {
+        parsed.due_at.map(|due| due.with_timezone(now().offset()).to_rfc3339())
+    }
+
+    #[test]
+    fn text_without_tokens_is_all_title() {
+        let parsed = parse_now("  buy milk  ");
+        assert_eq!(parsed.title, "buy milk");
+        assert_eq!(parsed.due_at, None);
+        assert!(parsed.tags.is_empty());
+        assert_eq!(parsed.priority, None);
+        assert!(parsed.recurrence.is_none());
+        assert!(parsed.recognized.is_empty());
+    }
+
+    #[test]
+    fn text_of_only_tokens_has_no_title() {
+        assert!(matches!(parse("#home !high tomorrow", now()), Err(ParseError::NoTitle)));
+    }
+
+    #[test]
+    fn tags_keep_their_case_and_drop_trailing_punctuation() {
+        let parsed = parse_now("file taxes #Finance, #home");
+        assert_eq!(parsed.title, "file taxes");
+        assert_eq!(parsed.tags, ["Finance", "home"]);
+        assert_eq!(parsed.recognized, ["#Finance,", "#home"]);
+        // A lone `#` is part of the title
+        assert_eq!(parse_now("press # twice").title, "press # twice");
+    }
+
+    #[test]
+    fn priorities_have_several_spellings() {
+        for (text, priority) in [("!high", Priority::High), ("!!", Priority::Medium), ("!3", Priority::Low)] {
+            assert_eq!(parse_now(&format!("call mum {text}")).priority, Some(priority), "{text}");
+        }
+        assert_eq!(parse_now("wow !urgent").priority, None);
+    }
+
+    #[test]
+    fn days_default_to_nine_in_the_morning() {
+        assert_eq!(local_due(&parse_now("dentist tomorrow")).unwrap(), "2026-10-17T09:00:00+02:00");
+        assert_eq!(local_due(&parse_now("dentist on 2026-11-01")).unwrap(), "2026-11-01T09:00:00+02:00");
+        assert_eq!(local_due(&parse_now("dentist tomorrow at 5pm")).unwrap(), "2026-10-17T17:00:00+02:00");
+        assert_eq!(local_due(&parse_now("party tonight")).unwrap(), "2026-10-16T20:00:00+02:00");
+    }
+
+    #[test]
+    fn due_dates_are_returned_in_utc() {
+        let parsed = parse_now("dentist tomorrow at 17:30");
+        assert_eq!(parsed.due_at.unwrap().to_rfc3339(), "2026-10-17T15:30:00+00:00");
+    }
+
+    #[test]
+    fn weekdays_count_today_unless_next() {
+        // `now` is a Friday
+        assert_eq!(local_due(&parse_now("review friday")).unwrap(), "2026-10-16T09:00:00+02:00");
+        assert_eq!(local_due(&parse_now("review this friday")).unwrap(), "2026-10-16T09:00:00+02:00");
+        assert_eq!(local_due(&parse_now("review next friday")).unwrap(), "2026-10-23T09:00:00+02:00");
+        assert_eq!(local_due(&parse_now("review by mon")).unwrap(), "2026-10-19T09:00:00+02:00");
+    }
+
+    #[test]
+    fn relative_times_count_from_now_to_the_second() {
+        assert_eq!(local_due(&parse_now("stretch in 2 hours")).unwrap(), "2026-10-16T16:30:45+02:00");
+        assert_eq!(local_due(&parse_now("stretch in a week")).unwrap(), "2026-10-23T14:30:45+02:00");
+        // Without a unit it's not a span
+        assert_eq!(parse_now("stretch in 2").title, "stretch in 2");
+    }
+
+    #[test]
+    fn a_bare_time_is_the_next_time_the_clock_shows_it() {
+        assert_eq!(local_due(&parse_now("standup at 9am")).unwrap(), "2026-10-17T09:00:00+02:00");
+        assert_eq!(local_due(&parse_now("standup 17:00")).unwrap(), "2026-10-16T17:00:00+02:00");
+        assert_eq!(local_due(&parse_now("standup at midnight")).unwrap(), "2026-10-17T00:00:00+02:00");
+        assert_eq!(local_due(&parse_now("standup at 12am")).unwrap(), "2026-10-17T00:00:00+02:00");
+    }
+
+    #[test]
+    fn ambiguous_words_stay_in_the_title() {
+        // Bare numbers, impossible times and three-letter words chrono reads as weekdays
+        for text in ["call 3 friends", "lunch at 13pm", "read sun tzu", "sat exam", "on call", "next steps"] {
+            let parsed = parse_now(text);
+            assert_eq!(parsed.title, text);
+            assert_eq!(parsed.due_at, None, "{text}");
+        }
+    }
+
+    #[test]
+    fn recurrences_start_on_their_next_occurrence() {
+        let parsed = parse_now("water plants every 2 weeks");
+        let recurrence = parsed.recurrence.as_ref().unwrap();
+        assert_eq!((recurrence.frequency, recurrence.interval), (Frequency::Weekly, 2));
+        // Anchored to today, a Friday whose 9am has passed
+        assert_eq!(recurrence.weekday, Some(Weekday::Fri));
+        assert_eq!(local_due(&parsed).unwrap(), "2026-10-23T09:00:00+02:00");
+
+        let parsed = parse_now("pay rent every 1st at 8am");
+        assert_eq!(parsed.recurrence.as_ref().unwrap().day_of_month, Some(1));
+        assert_eq!(local_due(&parsed).unwrap(), "2026-11-01T08:00:00+02:00");
+
+        let parsed = parse_now("timesheet every weekday");
+        assert_eq!(parsed.recurrence.as_ref().unwrap().frequency, Frequency::Weekdays);
+        assert_eq!(local_due(&parsed).unwrap(), "2026-10-19T09:00:00+02:00");
+    }
+
+    #[test]
+    fn the_31st_falls_on_the_last_day_of_short_months() {
+        let february = DateTime::parse_from_rfc3339("2026-02-10T12:00:00+00:00").unwrap();
+        let parsed = parse("invoice every 31st", february).unwrap();
+        assert_eq!(parsed.due_at.unwrap().to_rfc3339(), "2026-02-28T09:00:00+00:00");
+    }
+
+    #[test]
+    fn an_explicit_day_wins_over_the_recurrence_anchor() {
+        let parsed = parse_now("gym every monday tomorrow");
+        assert_eq!(parsed.recurrence.as_ref().unwrap().weekday, Some(Weekday::Mon));
+        assert_eq!(local_due(&parsed).unwrap(), "2026-10-17T09:00:00+02:00");
+        assert_eq!(parsed.recognized, ["every monday", "tomorrow"]);
+    }
+}
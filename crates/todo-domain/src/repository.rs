@@ -0,0 +1,167 @@
+use crate::models::{CompactTodo, HistoryEntry, Todo, TodoStats};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RepositoryError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Todo not found: {0}")]
+    NotFound(Uuid),
+
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+
+    #[error("Already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("{blocker} already depends on {blocked}, directly or through other todos")]
+    DependencyCycle { blocker: Uuid, blocked: Uuid },
+
+    /// The request the call was made for ran out of time, so the call was abandoned.
+    #[error("Deadline exceeded")]
+    DeadlineExceeded,
+}
+
+impl RepositoryError {
+    /// Whether the same call may well succeed if tried again shortly: a lost connection,
+    /// an exhausted pool, or a database locked by another writer.
+    pub fn is_transient(&self) -> bool {
+        self.is_unattempted() || matches!(self, Self::Database(sqlx::Error::Io(_) | sqlx::Error::WorkerCrashed))
+    }
+
+    /// Whether the call failed before it could change anything: no connection could be had, or
+    /// the database was locked by another writer. A lost connection, by contrast, may have
+    /// taken the reply to a write that went through, so only these are safe to retry for
+    /// writes that aren't idempotent.
+    pub fn is_unattempted(&self) -> bool {
+        match self {
+            Self::Database(sqlx::Error::PoolTimedOut) => true,
+            // Extended result codes keep the primary one (SQLITE_BUSY, SQLITE_LOCKED) in the low byte
+            Self::Database(sqlx::Error::Database(e)) => e
+                .code()
+                .and_then(|code| code.parse::<i32>().ok())
+                .is_some_and(|code| matches!(code & 0xff, 5 | 6)),
+            _ => false,
+        }
+    }
+}
+
+#[async_trait]
+pub trait TodoRepository: Send + Sync {
+    async fn create(&self, todo: Todo) -> Result<Todo, RepositoryError>;
+    async fn get(&self, id: Uuid) -> Result<Todo, RepositoryError>;
+    /// The todos `filter` selects, newest first, and how many it selects in all. With
+    /// `window`, only that slice of them is read, with LIMIT and OFFSET in the query.
+    async fn list(&self, filter: &ListFilter<'_>, window: Option<ListWindow>) -> Result<TodoList, RepositoryError>;
+    /// Up to `limit` todos in `list` order, starting after `after`, read with a keyset
+    /// predicate in one query that also tells whether more follow. With `projects`, todos in
+    /// any project not among them are left out.
+    async fn list_page(
+        &self,
+        after: Option<TodoCursor>,
+        limit: usize,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<TodoPage, RepositoryError>;
+    /// Like `list`, but only the columns in `CompactTodo`. With `projects`, todos in any
+    /// project not among them are left out.
+    async fn list_compact(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<CompactTodo>, RepositoryError>;
+    /// Count and latest modification time of all todos, without loading them. With
+    /// `projects`, todos in any project not among them aren't counted.
+    async fn summary(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoListSummary, RepositoryError>;
+    /// Counts and estimate rollups, aggregated in the database. With `projects`, todos in
+    /// any project not among them aren't counted.
+    async fn stats(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoStats, RepositoryError>;
+    async fn update(&self, todo: Todo) -> Result<Todo, RepositoryError>;
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError>;
+    /// Moves the due date to `until` and records the snooze in the todo's history, atomically.
+    async fn snooze(&self, id: Uuid, until: DateTime<Utc>) -> Result<Todo, RepositoryError>;
+    /// History of a todo, oldest first.
+    async fn history(&self, id: Uuid) -> Result<Vec<HistoryEntry>, RepositoryError>;
+    /// Records that `blocker` blocks `blocked` and returns the blocked todo. Fails with
+    /// `DependencyCycle` if `blocker` already (transitively) depends on `blocked`.
+    async fn add_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<Todo, RepositoryError>;
+    /// Fails with `NotFound(blocker)` if there was no such dependency.
+    async fn remove_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<(), RepositoryError>;
+    /// Creates all todos in one transaction: either every one is stored or none is.
+    async fn create_batch(&self, todos: Vec<Todo>) -> Result<Vec<Todo>, RepositoryError>;
+    /// Inserts all todos in one transaction: either every one is stored or none is.
+    async fn import(&self, todos: Vec<Todo>) -> Result<usize, RepositoryError>;
+    /// Deletes every completed todo, returning their ids. With `projects`, completed todos in
+    /// any project not among them are kept.
+    async fn delete_completed(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<Uuid>, RepositoryError>;
+    /// The version the database reports, which also shows it's answering.
+    async fn database_version(&self) -> Result<String, RepositoryError>;
+}
+
+/// What `HEAD /todos` reports: enough to tell whether the list changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TodoListSummary {
+    pub count: usize,
+    pub last_modified: Option<DateTime<Utc>>,
+    /// The highest `change_seq` in the list, which moves on every write even when
+    /// `last_modified` doesn't because a clock was behind.
+    pub last_change_seq: u64,
+}
+
+/// Where a keyset page of `list` starts: just after the todo with this `created_at` and `id`,
+/// which together order the list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TodoCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl TodoCursor {
+    pub fn after(todo: &Todo) -> Self {
+        Self {
+            created_at: todo.created_at,
+            id: todo.id,
+        }
+    }
+}
+
+/// One page of `list_page`.
+#[derive(Debug, Clone)]
+pub struct TodoPage {
+    pub todos: Vec<Todo>,
+    /// Whether any todo follows the last one, found by reading one row more than asked for.
+    pub has_more: bool,
+}
+
+/// Which todos `list` returns; the default is every one.
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter<'a> {
+    /// Todos in any project not among these are left out.
+    pub projects: Option<&'a HashSet<Uuid>>,
+    /// Only todos in this project.
+    pub project: Option<Uuid>,
+    /// Custom field values todos must all have. Numbers compare by value, so `2` matches `2.0`.
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// The slice of `list` to read: `limit` todos after the first `offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListWindow {
+    pub offset: usize,
+    pub limit: usize,
+}
+
+/// What `list` returns.
+#[derive(Debug, Clone)]
+pub struct TodoList {
+    pub todos: Vec<Todo>,
+    /// Of every todo the filter selects, not just those in the window.
+    pub summary: TodoListSummary,
+}
+
+impl TodoPage {
+    /// Where the next page starts, or `None` on the last page.
+    pub fn next(&self) -> Option<TodoCursor> {
+        self.todos.last().filter(|_| self.has_more).map(TodoCursor::after)
+    }
+}
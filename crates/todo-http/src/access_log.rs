@@ -0,0 +1,199 @@
+use axum::{
+    body::{Body, HttpBody},
+    extract::{ConnectInfo, MatchedPath, State},
+    http::{header, Request},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use opentelemetry::trace::TraceContextExt;
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    net::SocketAddr,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Line format for the access log (`ACCESS_LOG=common|json|otel-event`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// NCSA common log format, followed by `key=value` extras.
+    Common,
+    /// One JSON object per line.
+    Json,
+    /// A `tracing` event on the request span, exported with the trace instead of written out.
+    OtelEvent,
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "common" | "clf" => Ok(Self::Common),
+            "json" => Ok(Self::Json),
+            "otel-event" | "otel" => Ok(Self::OtelEvent),
+            other => Err(format!("unknown access log format: {other}")),
+        }
+    }
+}
+
+/// Everything recorded about one request.
+struct AccessRecord {
+    client_ip: Option<String>,
+    method: String,
+    path: String,
+    version: String,
+    route: Option<String>,
+    status: u16,
+    bytes: Option<u64>,
+    duration: Duration,
+    request_id: Option<String>,
+    trace_id: Option<String>,
+}
+
+impl AccessRecord {
+    fn common(&self) -> String {
+        let opt = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".to_string());
+        format!(
+            "{} - - [{}] \"{} {} {}\" {} {} rt={:.6} route={} request_id={} trace_id={}",
+            opt(&self.client_ip),
+            Utc::now().format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            self.path,
+            self.version,
+            self.status,
+            self.bytes.map_or_else(|| "-".to_string(), |b| b.to_string()),
+            self.duration.as_secs_f64(),
+            opt(&self.route),
+            opt(&self.request_id),
+            opt(&self.trace_id),
+        )
+    }
+
+    fn json(&self) -> String {
+        serde_json::json!({
+            "timestamp": Utc::now().to_rfc3339(),
+            "client_ip": self.client_ip,
+            "method": self.method,
+            "path": self.path,
+            "protocol": self.version,
+            "route": self.route,
+            "status": self.status,
+            "bytes": self.bytes,
+            "duration_ms": self.duration.as_secs_f64() * 1000.0,
+            "request_id": self.request_id,
+            "trace_id": self.trace_id,
+        })
+        .to_string()
+    }
+
+    fn emit_event(&self) {
+        tracing::event!(
+            target: "access_log",
+            tracing::Level::INFO,
+            http.request.method = %self.method,
+            http.route = self.route.as_deref(),
+            url.path = %self.path,
+            http.response.status_code = i64::from(self.status),
+            http.response.body.size = self.bytes.and_then(|b| i64::try_from(b).ok()),
+            http.server.request.duration = self.duration.as_secs_f64(),
+            client.address = self.client_ip.as_deref(),
+            http.request.id = self.request_id.as_deref(),
+            "access"
+        );
+    }
+}
+
+/// Writes one line per request, separately from the application log stream.
+pub struct AccessLog {
+    format: AccessLogFormat,
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    /// Appends to `path` when given, otherwise writes to stdout.
+    pub fn new(format: AccessLogFormat, path: Option<&Path>) -> io::Result<Self> {
+        let sink: Box<dyn Write + Send> = match path {
+            Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+            None => Box::new(io::stdout()),
+        };
+        Ok(Self {
+            format,
+            sink: Mutex::new(sink),
+        })
+    }
+
+    fn write(&self, record: &AccessRecord) {
+        let line = match self.format {
+            AccessLogFormat::Common => record.common(),
+            AccessLogFormat::Json => record.json(),
+            AccessLogFormat::OtelEvent => return record.emit_event(),
+        };
+
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(sink, "{line}") {
+            tracing::warn!(error = %e, "Failed to write access log line");
+        }
+    }
+}
+
+/// The OpenTelemetry trace id of the current span, if it is being traced.
+pub fn current_trace_id() -> Option<String> {
+    let context = Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
+
+/// Must sit inside the `TraceLayer` (to see the trace id) and the request id layer.
+pub async fn log_access(State(log): State<Arc<AccessLog>>, req: Request<Body>, next: Next) -> Response {
+    let client_ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned());
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+    let method = req.method().to_string();
+    let path = req.uri().path().to_owned();
+    let version = format!("{:?}", req.version());
+    let started = Instant::now();
+
+    let response = next.run(req).await;
+
+    let bytes = response.body().size_hint().exact().or_else(|| {
+        response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+    });
+
+    log.write(&AccessRecord {
+        client_ip,
+        method,
+        path,
+        version,
+        route,
+        status: response.status().as_u16(),
+        bytes,
+        duration: started.elapsed(),
+        request_id,
+        trace_id: current_trace_id(),
+    });
+    response
+}
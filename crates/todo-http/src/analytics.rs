@@ -0,0 +1,130 @@
+use crate::models::{CompletionTime, TodoAging, Velocity, VelocityPeriod};
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::debug;
+use uuid::Uuid;
+
+/// Projects the caller may see, sorted so equal sets share a cache entry; `None` when nobody
+/// is signed in.
+type Visible = Option<Vec<Uuid>>;
+
+fn visible_key(visible: Option<&HashSet<Uuid>>) -> Visible {
+    visible.map(|visible| {
+        let mut visible: Vec<Uuid> = visible.iter().copied().collect();
+        visible.sort();
+        visible
+    })
+}
+
+/// Results computed within the TTL, by request parameters.
+struct Cache<K, V> {
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> Cache<K, V> {
+    fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, key: &K, ttl: Duration) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        let (computed_at, value) = entries.get(key)?;
+        (computed_at.elapsed() < ttl).then(|| value.clone())
+    }
+
+    fn put(&self, key: K, value: V, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap();
+        // Keeps parameter combinations nobody asks for again from piling up
+        entries.retain(|_, (computed_at, _)| computed_at.elapsed() < ttl);
+        entries.insert(key, (Instant::now(), value));
+    }
+}
+
+/// Productivity statistics for dashboards, computed by SQL aggregates. Each result is kept
+/// for `ttl`, so any number of dashboards polling the same figures cost one query per TTL.
+/// Results are kept per set of visible projects, so a caller only sees figures for theirs.
+pub struct Analytics {
+    repository: Arc<SqliteTodoRepository>,
+    ttl: Option<Duration>,
+    velocity: Cache<(Visible, VelocityPeriod, u32), Velocity>,
+    aging: Cache<Visible, TodoAging>,
+    completion_time: Cache<(Visible, u32), CompletionTime>,
+}
+
+impl Analytics {
+    /// `ttl` of `None` computes every request afresh.
+    pub fn new(repository: Arc<SqliteTodoRepository>, ttl: Option<Duration>) -> Self {
+        Self {
+            repository,
+            ttl,
+            velocity: Cache::new(),
+            aging: Cache::new(),
+            completion_time: Cache::new(),
+        }
+    }
+
+    /// How long clients may cache a result, matching how long it is cached here.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    pub async fn velocity(
+        &self,
+        visible: Option<&HashSet<Uuid>>,
+        period: VelocityPeriod,
+        periods: u32,
+    ) -> Result<Velocity, RepositoryError> {
+        let key = (visible_key(visible), period, periods);
+        if let Some(velocity) = self.cached(&self.velocity, &key) {
+            return Ok(velocity);
+        }
+        let velocity = self.repository.velocity(period, periods, visible).await?;
+        self.store(&self.velocity, key, &velocity);
+        Ok(velocity)
+    }
+
+    pub async fn aging(&self, visible: Option<&HashSet<Uuid>>) -> Result<TodoAging, RepositoryError> {
+        let key = visible_key(visible);
+        if let Some(aging) = self.cached(&self.aging, &key) {
+            return Ok(aging);
+        }
+        let aging = self.repository.aging(visible).await?;
+        self.store(&self.aging, key, &aging);
+        Ok(aging)
+    }
+
+    pub async fn completion_time(
+        &self,
+        visible: Option<&HashSet<Uuid>>,
+        days: u32,
+    ) -> Result<CompletionTime, RepositoryError> {
+        let key = (visible_key(visible), days);
+        if let Some(completion_time) = self.cached(&self.completion_time, &key) {
+            return Ok(completion_time);
+        }
+        let completion_time = self.repository.completion_time(days, visible).await?;
+        self.store(&self.completion_time, key, &completion_time);
+        Ok(completion_time)
+    }
+
+    fn cached<K: Eq + Hash, V: Clone>(&self, cache: &Cache<K, V>, key: &K) -> Option<V> {
+        let value = cache.get(key, self.ttl?);
+        if value.is_some() {
+            debug!("Serving cached statistics");
+        }
+        value
+    }
+
+    fn store<K: Eq + Hash, V: Clone>(&self, cache: &Cache<K, V>, key: K, value: &V) {
+        if let Some(ttl) = self.ttl {
+            cache.put(key, value.clone(), ttl);
+        }
+    }
+}
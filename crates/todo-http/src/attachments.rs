@@ -0,0 +1,213 @@
+use crate::blob_store::{BlobError, BlobStore};
+use crate::config::AttachmentConfig;
+use crate::models::{Attachment, Todo, User};
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use crate::upload_scan::{ScanError, Upload, UploadScanner};
+use chrono::{DateTime, Utc};
+use std::{sync::Arc, time::Duration};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const MAX_FILENAME_LEN: usize = 255;
+
+/// Stored when the upload doesn't say what it is, or says something unusable.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Attachments of deleted todos removed per sweep, so one sweep can't run for long.
+const ORPHAN_BATCH: u32 = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AttachmentError {
+    #[error("Filenames are 1 to {MAX_FILENAME_LEN} characters")]
+    InvalidFilename,
+
+    #[error("Attachments can't be empty")]
+    Empty,
+
+    #[error("Attachments are at most {0} bytes")]
+    TooLarge(usize),
+
+    #[error(transparent)]
+    Scan(#[from] ScanError),
+
+    #[error(transparent)]
+    Blob(#[from] BlobError),
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+/// How a client gets an attachment's bytes.
+pub enum Download {
+    /// From the blob store directly, through a short-lived link.
+    Redirect(String),
+    Bytes(Vec<u8>),
+}
+
+/// Files attached to todos. Metadata is in the repository, the bytes in a `BlobStore`, so
+/// they can live in an object store rather than on the application host.
+pub struct AttachmentService {
+    repository: Arc<SqliteTodoRepository>,
+    store: Arc<dyn BlobStore>,
+    scanner: Arc<dyn UploadScanner>,
+    max_bytes: usize,
+    url_ttl: Duration,
+}
+
+impl AttachmentService {
+    pub fn new(
+        repository: Arc<SqliteTodoRepository>,
+        store: Arc<dyn BlobStore>,
+        scanner: Arc<dyn UploadScanner>,
+        config: &AttachmentConfig,
+    ) -> Self {
+        Self {
+            repository,
+            store,
+            scanner,
+            max_bytes: config.max_bytes,
+            url_ttl: config.url_ttl,
+        }
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Scans the upload by `uploader`, then stores the bytes and the metadata. Only the last
+    /// path segment of `filename` is kept.
+    pub async fn upload(
+        &self,
+        todo: &Todo,
+        filename: &str,
+        content_type: Option<&str>,
+        data: Vec<u8>,
+        uploader: Option<&User>,
+    ) -> Result<Attachment, AttachmentError> {
+        let filename = filename.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+        if !(1..=MAX_FILENAME_LEN).contains(&filename.chars().count()) || filename.chars().any(char::is_control) {
+            return Err(AttachmentError::InvalidFilename);
+        }
+        if data.is_empty() {
+            return Err(AttachmentError::Empty);
+        }
+        if data.len() > self.max_bytes {
+            return Err(AttachmentError::TooLarge(self.max_bytes));
+        }
+
+        let id = Uuid::new_v4();
+        let attachment = Attachment {
+            id,
+            todo_id: todo.id,
+            filename: filename.to_owned(),
+            content_type: content_type
+                .map(str::trim)
+                .filter(|t| valid_content_type(t))
+                .unwrap_or(DEFAULT_CONTENT_TYPE)
+                .to_owned(),
+            size_bytes: data.len() as u64,
+            blob_key: format!("todos/{}/{id}", todo.id),
+            created_at: self.repository.clock().now(),
+            uploaded_by: uploader.map(|u| u.id),
+        };
+        let upload = Upload {
+            filename: &attachment.filename,
+            content_type: &attachment.content_type,
+            data: &data,
+        };
+        self.scanner.scan(&upload).await?;
+        self.store.put(&attachment.blob_key, &attachment.content_type, data).await?;
+        if let Err(e) = self.repository.add_attachment(&attachment).await {
+            if let Err(e) = self.store.delete(&attachment.blob_key).await {
+                warn!(error = %e, blob.key = %attachment.blob_key, "Failed to delete blob of failed upload");
+            }
+            return Err(e.into());
+        }
+        info!(
+            todo.id = %todo.id,
+            attachment.id = %id,
+            size_bytes = attachment.size_bytes,
+            blob.store = self.store.kind(),
+            "Attachment stored"
+        );
+        Ok(attachment)
+    }
+
+    pub async fn list(&self, todo_id: Uuid) -> Result<Vec<Attachment>, RepositoryError> {
+        self.repository.attachments(todo_id).await
+    }
+
+    pub async fn get(&self, todo_id: Uuid, id: Uuid) -> Result<Option<Attachment>, RepositoryError> {
+        self.repository.get_attachment(todo_id, id).await
+    }
+
+    /// A link to the blob store when it hands them out, the bytes otherwise.
+    pub async fn download(&self, attachment: &Attachment) -> Result<Download, BlobError> {
+        match self.store.presign(&attachment.blob_key, self.url_ttl)? {
+            Some(url) => Ok(Download::Redirect(url)),
+            None => Ok(Download::Bytes(self.store.get(&attachment.blob_key).await?)),
+        }
+    }
+
+    /// Deletes the metadata, then the bytes. Returns false if there was no such attachment.
+    pub async fn remove(&self, todo_id: Uuid, id: Uuid) -> Result<bool, AttachmentError> {
+        let Some(attachment) = self.repository.get_attachment(todo_id, id).await? else {
+            return Ok(false);
+        };
+        if !self.repository.delete_attachment(id).await? {
+            return Ok(false);
+        }
+        if let Err(e) = self.store.delete(&attachment.blob_key).await {
+            // Unreachable through the API now; only the store's space is lost
+            warn!(error = %e, blob.key = %attachment.blob_key, "Failed to delete attachment blob");
+        }
+        info!(todo.id = %todo_id, attachment.id = %id, "Attachment deleted");
+        Ok(true)
+    }
+
+    /// Deletes attachments whose todo is gone, bytes first, or with `deleted_before` only those
+    /// whose todo was deleted by then. Returns how many were removed.
+    pub async fn remove_orphans(&self, deleted_before: Option<DateTime<Utc>>) -> Result<u64, AttachmentError> {
+        let orphans = self.repository.orphaned_attachments(deleted_before, ORPHAN_BATCH).await?;
+        let mut removed = 0;
+        for attachment in orphans {
+            self.store.delete(&attachment.blob_key).await?;
+            if self.repository.delete_attachment(attachment.id).await? {
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            info!(removed, "Removed attachments of deleted todos");
+        }
+        Ok(removed)
+    }
+}
+
+/// `type/subtype`, optionally with parameters, in printable ASCII.
+fn valid_content_type(value: &str) -> bool {
+    let essence = value.split(';').next().unwrap_or_default().trim();
+    let Some((kind, subtype)) = essence.split_once('/') else {
+        return false;
+    };
+    value.len() <= 255
+        && !kind.is_empty()
+        && !subtype.is_empty()
+        && value.chars().all(|c| c.is_ascii_graphic() || c == ' ')
+}
+
+/// `Content-Disposition` that makes browsers save the file under its name: an ASCII
+/// fallback, plus the exact name percent-encoded as RFC 6266 allows.
+pub fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+    let mut encoded = String::with_capacity(filename.len());
+    for byte in filename.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
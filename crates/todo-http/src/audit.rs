@@ -0,0 +1,138 @@
+use crate::models::{AuditEntry, AuditQuery};
+use crate::repository::{NewAuditEntry, RepositoryError, SqliteTodoRepository};
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts, Extensions, HeaderMap},
+};
+use serde_json::Value;
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+use tracing::warn;
+use uuid::Uuid;
+
+const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 1000;
+/// User agents longer than this are cut short before they are stored.
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// Where a request came from, as recorded in the audit log.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    /// From the connection and headers of a request, for callers holding it whole.
+    pub fn of(extensions: &Extensions, headers: &HeaderMap) -> Self {
+        Self {
+            ip: extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string()),
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(|ua| ua.chars().take(MAX_USER_AGENT_LEN).collect()),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::of(&parts.extensions, &parts.headers))
+    }
+}
+
+/// Everything that ends up in `auth_audit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEvent {
+    Registered,
+    LoginSucceeded,
+    LoginFailed,
+    LoginThrottled,
+    /// A refresh token was replayed and its session revoked.
+    RefreshReused,
+    LoggedOut,
+    TokenCreated,
+    TokenRevoked,
+    DataExported,
+    ErasureRequested,
+    /// Carries no user id or email, only the erasure job's id.
+    AccountErased,
+    BackupCreated,
+    BackupRestored,
+    FlagChanged,
+    WebhookChanged,
+    CustomFieldChanged,
+    /// `DELETE /todos/completed`, with the ids it deleted.
+    CompletedDeleted,
+    /// A request an admin made as another user through `X-Impersonate-User`.
+    Impersonated,
+}
+
+impl AuditEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Registered => "user.registered",
+            Self::LoginSucceeded => "login.succeeded",
+            Self::LoginFailed => "login.failed",
+            Self::LoginThrottled => "login.throttled",
+            Self::RefreshReused => "session.refresh_reused",
+            Self::LoggedOut => "session.logged_out",
+            Self::TokenCreated => "token.created",
+            Self::TokenRevoked => "token.revoked",
+            Self::DataExported => "user.exported",
+            Self::ErasureRequested => "user.erasure_requested",
+            Self::AccountErased => "user.erased",
+            Self::BackupCreated => "admin.backup",
+            Self::BackupRestored => "admin.restore",
+            Self::FlagChanged => "admin.flag_changed",
+            Self::WebhookChanged => "admin.webhook_changed",
+            Self::CustomFieldChanged => "admin.custom_field_changed",
+            Self::CompletedDeleted => "todos.completed_deleted",
+            Self::Impersonated => "admin.impersonated",
+        }
+    }
+}
+
+/// Appends authentication and admin events to the `auth_audit` table. A failed write is
+/// logged and otherwise ignored, so a problem with the log never locks anyone out.
+pub struct AuditLog {
+    repository: Arc<SqliteTodoRepository>,
+}
+
+impl AuditLog {
+    pub fn new(repository: Arc<SqliteTodoRepository>) -> Self {
+        Self { repository }
+    }
+
+    pub async fn record(
+        &self,
+        client: &ClientInfo,
+        event: AuditEvent,
+        user_id: Option<Uuid>,
+        email: Option<&str>,
+        detail: Value,
+    ) {
+        let entry = NewAuditEntry {
+            event: event.as_str(),
+            user_id,
+            email,
+            ip: client.ip.as_deref(),
+            user_agent: client.user_agent.as_deref(),
+            detail,
+        };
+        if let Err(e) = self.repository.insert_audit(entry).await {
+            warn!(error = %e, audit.event = event.as_str(), "Failed to write audit entry");
+        }
+    }
+
+    /// A page of entries, newest first, at most `MAX_PAGE_SIZE` long.
+    pub async fn list(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, RepositoryError> {
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        self.repository.list_audit(query, limit).await
+    }
+}
@@ -0,0 +1,871 @@
+use crate::audit::{AuditEvent, AuditLog, ClientInfo};
+use crate::auth_cache::AuthCache;
+use crate::config::AuthConfig;
+use crate::mentions;
+use crate::models::{ApiToken, CreateApiTokenRequest, CreatedApiToken, Scope, UpdateProfileRequest, User};
+use crate::repository::{RefreshOutcome, RepositoryError, SqliteTodoRepository};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use chrono::{DateTime, Days, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+/// Failed logins allowed per email within `LOGIN_WINDOW` before further attempts are refused.
+const MAX_FAILED_LOGINS: u32 = 5;
+const LOGIN_WINDOW: Duration = Duration::from_secs(15 * 60);
+const MIN_PASSWORD_LEN: usize = 8;
+const MAX_PASSWORD_LEN: usize = 128;
+/// Tells personal access tokens apart from session JWTs at a glance.
+const API_TOKEN_PREFIX: &str = "tdo_";
+/// Names the user an admin acts as, for troubleshooting what that user sees.
+pub const IMPERSONATE_HEADER: &str = "x-impersonate-user";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Repository error: {0}")]
+    Repository(#[from] RepositoryError),
+
+    #[error("Invalid email address")]
+    InvalidEmail,
+
+    #[error("Password must be between {MIN_PASSWORD_LEN} and {MAX_PASSWORD_LEN} characters")]
+    InvalidPassword,
+
+    #[error("Email is already registered")]
+    EmailTaken,
+
+    #[error(
+        "Usernames are {} to {} letters, digits, '_' or '-'",
+        mentions::MIN_USERNAME_LEN,
+        mentions::MAX_USERNAME_LEN
+    )]
+    InvalidUsername,
+
+    #[error("Username is already taken")]
+    UsernameTaken,
+
+    #[error("Invalid email or password")]
+    InvalidCredentials,
+
+    #[error("Too many failed logins, retry in {} seconds", retry_after.as_secs())]
+    TooManyAttempts { retry_after: Duration },
+
+    #[error("Invalid or expired token")]
+    InvalidToken,
+
+    #[error("Invalid or expired refresh token")]
+    InvalidRefreshToken,
+
+    #[error("Credential lacks the {} scope", .0.as_str())]
+    MissingScope(Scope),
+
+    #[error("Personal access tokens can't do this; use a session token")]
+    SessionRequired,
+
+    #[error("Invalid token request: {0}")]
+    InvalidTokenRequest(String),
+
+    #[error("Token not found")]
+    TokenNotFound,
+
+    #[error("User to impersonate not found")]
+    ImpersonatedUserNotFound,
+
+    #[error("Password hashing failed: {0}")]
+    Hash(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: Uuid,
+    /// Session the token was issued for; logging out revokes it.
+    sid: Uuid,
+    iat: i64,
+    exp: i64,
+}
+
+/// Who made a request and what they may do, as resolved from its bearer credential.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub user: User,
+    pub scopes: Vec<Scope>,
+    /// False for personal access tokens.
+    pub session: bool,
+    /// The personal access token used, if any.
+    pub api_token_id: Option<Uuid>,
+    /// The admin acting as `user` through `X-Impersonate-User`, if any.
+    pub impersonator: Option<User>,
+}
+
+impl Principal {
+    pub fn require(&self, scope: Scope) -> Result<(), AuthError> {
+        if self.scopes.contains(&scope) {
+            Ok(())
+        } else {
+            Err(AuthError::MissingScope(scope))
+        }
+    }
+}
+
+/// A short-lived access token and the refresh token that renews it.
+pub struct Session {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+struct FailedLogins {
+    count: u32,
+    since: Instant,
+}
+
+/// Registers users with argon2 password hashes and issues HS256 access tokens for them.
+pub struct AuthService {
+    repository: Arc<SqliteTodoRepository>,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    token_ttl: Duration,
+    refresh_token_ttl: Duration,
+    required: bool,
+    audit: AuditLog,
+    failed_logins: Mutex<HashMap<String, FailedLogins>>,
+    cache: AuthCache,
+    /// Verified against when the email is unknown, so a login takes as long either way.
+    dummy_hash: String,
+}
+
+impl AuthService {
+    pub fn new(repository: Arc<SqliteTodoRepository>, config: &AuthConfig) -> Self {
+        let secret = config.jwt_secret.expose().as_bytes();
+        let clock = repository.clock().clone();
+        Self {
+            audit: AuditLog::new(repository.clone()),
+            repository,
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            token_ttl: config.token_ttl,
+            refresh_token_ttl: config.refresh_token_ttl,
+            required: config.required,
+            failed_logins: Mutex::new(HashMap::new()),
+            cache: AuthCache::new(config.cache_ttl, config.cache_max_entries, clock),
+            dummy_hash: hash_password("not a real password").expect("hashing a constant succeeds"),
+        }
+    }
+
+    /// The time tokens are issued and checked at: the repository's clock, so expiry agrees
+    /// with the expiry checks it makes itself.
+    fn now(&self) -> DateTime<Utc> {
+        self.repository.clock().now()
+    }
+
+    pub fn token_ttl(&self) -> Duration {
+        self.token_ttl
+    }
+
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// Whether todo and admin routes need a credential (`AUTH_REQUIRED`).
+    pub fn required(&self) -> bool {
+        self.required
+    }
+
+    /// Everything an account may do: the todo scopes, plus `admin` for accounts an operator
+    /// granted it to.
+    fn user_scopes(&self, user: &User) -> Vec<Scope> {
+        let mut scopes = vec![Scope::TodosRead, Scope::TodosWrite];
+        if user.admin {
+            scopes.push(Scope::Admin);
+        }
+        scopes
+    }
+
+    /// Resolves a bearer credential, either a session access token or a personal access
+    /// token, from the cache when it was checked recently. A personal token never has scopes
+    /// its owner has since lost.
+    pub async fn principal(&self, bearer: &str) -> Result<Principal, AuthError> {
+        let key = hash_token(bearer);
+        let api_token = bearer.starts_with(API_TOKEN_PREFIX);
+        let credential = if api_token { "api_token" } else { "session" };
+        if let Some(principal) = self.cache.get(&key, credential).await {
+            return Ok(principal);
+        }
+
+        let generation = self.cache.generation();
+        let (principal, expires_at) = if api_token {
+            self.token_principal(&key).await?
+        } else {
+            let (user, claims) = self.verify_access_token(bearer).await?;
+            let principal = Principal {
+                scopes: self.user_scopes(&user),
+                user,
+                session: true,
+                api_token_id: None,
+                impersonator: None,
+            };
+            (principal, DateTime::from_timestamp(claims.exp, 0))
+        };
+        self.cache.insert(key, principal.clone(), expires_at, generation).await;
+        Ok(principal)
+    }
+
+    /// The principal of a bearer credential resolved recently, without checking it again.
+    pub async fn cached_principal(&self, bearer: &str) -> Option<Principal> {
+        self.cache.peek(&hash_token(bearer)).await
+    }
+
+    /// The principal of the personal access token hashed to `token_hash`, and when it expires.
+    async fn token_principal(&self, token_hash: &str) -> Result<(Principal, Option<DateTime<Utc>>), AuthError> {
+        let Some((user_id, token)) = self.repository.find_api_token(token_hash).await? else {
+            return Err(AuthError::InvalidToken);
+        };
+        let user = match self.repository.get_user(user_id).await {
+            Ok(user) => user,
+            Err(RepositoryError::NotFound(_)) => return Err(AuthError::InvalidToken),
+            Err(e) => return Err(e.into()),
+        };
+        let allowed = self.user_scopes(&user);
+        let principal = Principal {
+            scopes: token.scopes.into_iter().filter(|s| allowed.contains(s)).collect(),
+            user,
+            session: false,
+            api_token_id: Some(token.id),
+            impersonator: None,
+        };
+        Ok((principal, token.expires_at))
+    }
+
+    /// `admin` acting as the user `user_id`, with those of the user's scopes the admin has
+    /// too. Only admins signed in with a session may impersonate anyone.
+    pub async fn impersonate(&self, admin: Principal, user_id: Uuid) -> Result<Principal, AuthError> {
+        admin.require(Scope::Admin)?;
+        if !admin.session {
+            return Err(AuthError::SessionRequired);
+        }
+        let user = match self.repository.get_user(user_id).await {
+            Ok(user) => user,
+            Err(RepositoryError::NotFound(_)) => return Err(AuthError::ImpersonatedUserNotFound),
+            Err(e) => return Err(e.into()),
+        };
+        let scopes = self
+            .user_scopes(&user)
+            .into_iter()
+            .filter(|s| admin.scopes.contains(s))
+            .collect();
+        Ok(Principal {
+            user,
+            scopes,
+            session: true,
+            api_token_id: None,
+            impersonator: Some(admin.user),
+        })
+    }
+
+    /// Stops trusting cached credentials of the user, for when their account is going away.
+    pub fn forget_user(&self, user_id: Uuid) {
+        self.cache.forget_user(user_id);
+    }
+
+    /// Issues a personal access token with a subset of the caller's scopes. Only session
+    /// credentials may do this, so a leaked token can't mint more tokens.
+    #[instrument(skip_all, fields(user.id = %principal.user.id, api_token.id))]
+    pub async fn create_api_token(
+        &self,
+        principal: &Principal,
+        request: CreateApiTokenRequest,
+        client: &ClientInfo,
+    ) -> Result<CreatedApiToken, AuthError> {
+        if !principal.session {
+            return Err(AuthError::SessionRequired);
+        }
+        let name = request.name.trim();
+        if name.is_empty() || name.chars().count() > 100 {
+            return Err(AuthError::InvalidTokenRequest("name must be 1 to 100 characters".into()));
+        }
+        if request.scopes.is_empty() {
+            return Err(AuthError::InvalidTokenRequest("at least one scope is required".into()));
+        }
+        for scope in &request.scopes {
+            principal.require(*scope)?;
+        }
+
+        let mut scopes = request.scopes;
+        scopes.sort();
+        scopes.dedup();
+        let created_at = self.now();
+        let api_token = ApiToken {
+            id: Uuid::new_v4(),
+            name: name.to_owned(),
+            scopes,
+            created_at,
+            expires_at: request
+                .expires_in_days
+                .map(|days| created_at + Days::new(u64::from(days.max(1)))),
+            revoked_at: None,
+        };
+        let token = format!("{API_TOKEN_PREFIX}{}", new_refresh_token());
+        self.repository
+            .create_api_token(principal.user.id, &api_token, &hash_token(&token))
+            .await?;
+        tracing::Span::current().record("api_token.id", tracing::field::display(api_token.id));
+        info!("Personal access token created");
+        let detail = json!({
+            "token_id": api_token.id,
+            "name": api_token.name,
+            "scopes": api_token.scopes,
+        });
+        self.audit
+            .record(client, AuditEvent::TokenCreated, Some(principal.user.id), Some(&principal.user.email), detail)
+            .await;
+        Ok(CreatedApiToken { api_token, token })
+    }
+
+    pub async fn list_api_tokens(&self, principal: &Principal) -> Result<Vec<ApiToken>, AuthError> {
+        if !principal.session {
+            return Err(AuthError::SessionRequired);
+        }
+        Ok(self.repository.list_api_tokens(principal.user.id).await?)
+    }
+
+    #[instrument(skip_all, fields(user.id = %principal.user.id, api_token.id = %id))]
+    pub async fn revoke_api_token(&self, principal: &Principal, id: Uuid, client: &ClientInfo) -> Result<(), AuthError> {
+        if !principal.session {
+            return Err(AuthError::SessionRequired);
+        }
+        match self.repository.revoke_api_token(principal.user.id, id).await {
+            Ok(()) => {
+                self.cache.forget_api_token(id);
+                info!("Personal access token revoked");
+                let user = &principal.user;
+                self.audit
+                    .record(client, AuditEvent::TokenRevoked, Some(user.id), Some(&user.email), json!({"token_id": id}))
+                    .await;
+                Ok(())
+            }
+            Err(RepositoryError::NotFound(_)) => Err(AuthError::TokenNotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[instrument(skip_all, fields(user.id))]
+    pub async fn register(&self, email: &str, password: &str, client: &ClientInfo) -> Result<User, AuthError> {
+        let email = email.trim();
+        let plausible = email
+            .split_once('@')
+            .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'));
+        if !plausible || email.len() > 254 {
+            return Err(AuthError::InvalidEmail);
+        }
+        if !(MIN_PASSWORD_LEN..=MAX_PASSWORD_LEN).contains(&password.chars().count()) {
+            return Err(AuthError::InvalidPassword);
+        }
+
+        let password = password.to_owned();
+        let password_hash = tokio::task::spawn_blocking(move || hash_password(&password))
+            .await
+            .map_err(|e| AuthError::Hash(e.to_string()))??;
+
+        let user = User {
+            id: Uuid::new_v4(),
+            email: email.to_owned(),
+            username: None,
+            mention_channel: Default::default(),
+            created_at: self.now(),
+            admin: false,
+        };
+        tracing::Span::current().record("user.id", tracing::field::display(user.id));
+        match self.repository.create_user(&user, &password_hash).await {
+            Ok(()) => {
+                info!("User registered");
+                self.audit
+                    .record(client, AuditEvent::Registered, Some(user.id), Some(&user.email), json!({}))
+                    .await;
+                Ok(user)
+            }
+            Err(RepositoryError::AlreadyExists(_)) => Err(AuthError::EmailTaken),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Changes the user's username or mention channel and returns the updated account.
+    pub async fn update_profile(&self, user: &User, changes: UpdateProfileRequest) -> Result<User, AuthError> {
+        let mut user = self.repository.get_user(user.id).await?;
+        if let Some(username) = changes.username {
+            let username = username.trim();
+            if username.is_empty() {
+                user.username = None;
+            } else if mentions::valid_username(username) {
+                user.username = Some(username.to_owned());
+            } else {
+                return Err(AuthError::InvalidUsername);
+            }
+        }
+        if let Some(channel) = changes.mention_channel {
+            user.mention_channel = channel;
+        }
+        match self.repository.update_profile(&user).await {
+            Ok(()) => {
+                self.cache.forget_user(user.id);
+                info!(user.id = %user.id, "Profile updated");
+                Ok(user)
+            }
+            Err(RepositoryError::AlreadyExists(_)) => Err(AuthError::UsernameTaken),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Checks the user's current password, for actions that need it confirmed.
+    pub async fn confirm_password(&self, user: &User, password: &str) -> Result<(), AuthError> {
+        let password_hash = match self.repository.find_user_by_email(&user.email).await? {
+            Some((found, hash)) if found.id == user.id => hash,
+            _ => return Err(AuthError::InvalidCredentials),
+        };
+        let password = password.to_owned();
+        let verified = tokio::task::spawn_blocking(move || verify_password(&password, &password_hash))
+            .await
+            .map_err(|e| AuthError::Hash(e.to_string()))?;
+        if verified {
+            Ok(())
+        } else {
+            Err(AuthError::InvalidCredentials)
+        }
+    }
+
+    /// Starts a session. After `MAX_FAILED_LOGINS` failures for an email, further
+    /// attempts are refused until `LOGIN_WINDOW` has passed since the first one.
+    #[instrument(skip_all, fields(user.id))]
+    pub async fn login(&self, email: &str, password: &str, client: &ClientInfo) -> Result<Session, AuthError> {
+        let email = email.trim();
+        let throttle_key = email.to_lowercase();
+        if let Err(e) = self.check_throttle(&throttle_key) {
+            self.audit
+                .record(client, AuditEvent::LoginThrottled, None, Some(email), json!({}))
+                .await;
+            return Err(e);
+        }
+
+        let found = self.repository.find_user_by_email(email).await?;
+        let (user, password_hash) = match found {
+            Some((user, hash)) => (Some(user), hash),
+            None => (None, self.dummy_hash.clone()),
+        };
+        let password = password.to_owned();
+        let verified = tokio::task::spawn_blocking(move || verify_password(&password, &password_hash))
+            .await
+            .map_err(|e| AuthError::Hash(e.to_string()))?;
+
+        let user = match user {
+            Some(user) if verified => user,
+            user => {
+                warn!("Failed login");
+                self.record_failure(throttle_key);
+                let reason = if user.is_some() { "wrong_password" } else { "unknown_email" };
+                self.audit
+                    .record(
+                        client,
+                        AuditEvent::LoginFailed,
+                        user.map(|u| u.id),
+                        Some(email),
+                        json!({"reason": reason}),
+                    )
+                    .await;
+                return Err(AuthError::InvalidCredentials);
+            }
+        };
+        self.failed_logins.lock().unwrap().remove(&throttle_key);
+        tracing::Span::current().record("user.id", tracing::field::display(user.id));
+
+        let session_id = Uuid::new_v4();
+        let refresh_token = new_refresh_token();
+        let expires_at = self.now() + self.refresh_token_ttl;
+        self.repository
+            .insert_refresh_token(&hash_token(&refresh_token), user.id, session_id, expires_at)
+            .await?;
+        info!(session.id = %session_id, "User logged in");
+        self.audit
+            .record(
+                client,
+                AuditEvent::LoginSucceeded,
+                Some(user.id),
+                Some(&user.email),
+                json!({"session_id": session_id}),
+            )
+            .await;
+        Ok(Session {
+            access_token: self.access_token(user.id, session_id)?,
+            refresh_token,
+        })
+    }
+
+    /// Trades a refresh token for a new access token and a new refresh token. Each refresh
+    /// token works once; replaying one revokes the session it belongs to.
+    #[instrument(skip_all, fields(user.id, session.id))]
+    pub async fn refresh(&self, refresh_token: &str, client: &ClientInfo) -> Result<Session, AuthError> {
+        let new_refresh_token = new_refresh_token();
+        let outcome = self
+            .repository
+            .rotate_refresh_token(
+                &hash_token(refresh_token),
+                &hash_token(&new_refresh_token),
+                self.now() + self.refresh_token_ttl,
+            )
+            .await?;
+
+        match outcome {
+            RefreshOutcome::Rotated { user_id, family_id } => {
+                let span = tracing::Span::current();
+                span.record("user.id", tracing::field::display(user_id));
+                span.record("session.id", tracing::field::display(family_id));
+                info!("Session refreshed");
+                Ok(Session {
+                    access_token: self.access_token(user_id, family_id)?,
+                    refresh_token: new_refresh_token,
+                })
+            }
+            RefreshOutcome::Reused { user_id } => {
+                self.cache.forget_user(user_id);
+                warn!(outcome = ?outcome, "Refresh rejected");
+                self.audit
+                    .record(client, AuditEvent::RefreshReused, None, None, json!({}))
+                    .await;
+                Err(AuthError::InvalidRefreshToken)
+            }
+            RefreshOutcome::Expired | RefreshOutcome::Unknown => {
+                warn!(outcome = ?outcome, "Refresh rejected");
+                Err(AuthError::InvalidRefreshToken)
+            }
+        }
+    }
+
+    /// Revokes the session the refresh token belongs to, including its access tokens.
+    #[instrument(skip_all)]
+    pub async fn logout(&self, refresh_token: &str, client: &ClientInfo) -> Result<(), AuthError> {
+        let Some(user_id) = self.repository.revoke_session(&hash_token(refresh_token)).await? else {
+            return Err(AuthError::InvalidRefreshToken);
+        };
+        // Cached entries aren't keyed by session, so the user's others are checked again too
+        self.cache.forget_user(user_id);
+        info!("Session revoked");
+        self.audit
+            .record(client, AuditEvent::LoggedOut, Some(user_id), None, json!({}))
+            .await;
+        Ok(())
+    }
+
+    fn access_token(&self, user_id: Uuid, session_id: Uuid) -> Result<String, AuthError> {
+        let now = self.now().timestamp();
+        let claims = Claims {
+            sub: user_id,
+            sid: session_id,
+            iat: now,
+            exp: now + self.token_ttl.as_secs() as i64,
+        };
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &self.encoding_key)
+            .map_err(|e| AuthError::Hash(e.to_string()))
+    }
+
+    /// The user an access token was issued to, if it is valid and unexpired.
+    pub async fn authenticate(&self, token: &str) -> Result<User, AuthError> {
+        self.verify_access_token(token).await.map(|(user, _)| user)
+    }
+
+    #[instrument(skip_all, fields(user.id))]
+    async fn verify_access_token(&self, token: &str) -> Result<(User, Claims), AuthError> {
+        // Expiry is checked against the injected clock below, not the system's
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.validate_exp = false;
+        let claims = jsonwebtoken::decode::<Claims>(token, &self.decoding_key, &validation)
+            .map_err(|_| AuthError::InvalidToken)?
+            .claims;
+        if claims.exp <= self.now().timestamp() {
+            return Err(AuthError::InvalidToken);
+        }
+        tracing::Span::current().record("user.id", tracing::field::display(claims.sub));
+        if !self.repository.session_active(claims.sid).await? {
+            return Err(AuthError::InvalidToken);
+        }
+        match self.repository.get_user(claims.sub).await {
+            Ok(user) => Ok((user, claims)),
+            // The account is gone, so its tokens are no good either
+            Err(RepositoryError::NotFound(_)) => Err(AuthError::InvalidToken),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn check_throttle(&self, key: &str) -> Result<(), AuthError> {
+        let failed_logins = self.failed_logins.lock().unwrap();
+        match failed_logins.get(key) {
+            Some(failed) if failed.count >= MAX_FAILED_LOGINS => {
+                let elapsed = failed.since.elapsed();
+                if elapsed < LOGIN_WINDOW {
+                    warn!("Login throttled");
+                    return Err(AuthError::TooManyAttempts {
+                        retry_after: LOGIN_WINDOW - elapsed,
+                    });
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn record_failure(&self, key: String) {
+        let mut failed_logins = self.failed_logins.lock().unwrap();
+        failed_logins.retain(|_, failed| failed.since.elapsed() < LOGIN_WINDOW);
+        let failed = failed_logins.entry(key).or_insert(FailedLogins {
+            count: 0,
+            since: Instant::now(),
+        });
+        failed.count += 1;
+    }
+}
+
+/// 256 random bits, URL-safe so clients can put it anywhere.
+fn new_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Refresh tokens are high-entropy, so a plain hash is enough to keep a database leak from
+/// handing out live tokens.
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn hash_password(password: &str) -> Result<String, AuthError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AuthError::Hash(e.to_string()))
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+        .unwrap_or(false)
+}
+
+/// The credential from `Authorization: Bearer ...`.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// The password of `Authorization: Basic ...`, which CalDAV clients send an access token
+/// in since most can't send a bearer token. The user name is ignored.
+fn basic_password(headers: &HeaderMap) -> Option<String> {
+    let encoded = headers.get(header::AUTHORIZATION)?.to_str().ok()?.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (_, password) = decoded.split_once(':')?;
+    Some(password.to_owned())
+}
+
+fn is_dav(path: &str) -> bool {
+    path == "/dav" || path.starts_with("/dav/")
+}
+
+/// The scope a route group needs: reading or changing todos (through the API, `/ui`,
+/// `/mcp`, `/projects` or `/dav`), or administration. Health, metrics and `/auth` itself are
+/// open, as is `/integrations`, whose deliveries are signed instead.
+fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    if path.starts_with("/admin/") {
+        Some(Scope::Admin)
+    } else if path == "/mcp" {
+        // Writing tools check for `todos:write` themselves
+        Some(Scope::TodosRead)
+    } else if ["/todos", "/import", "/ui", "/stats", "/sync", "/projects", "/dav", "/tags"]
+        .iter()
+        .any(|root| path == *root || path.strip_prefix(root).is_some_and(|rest| rest.starts_with('/')))
+    {
+        // WebDAV reads with `PROPFIND` and `REPORT` as well
+        if matches!(method.as_str(), "GET" | "HEAD" | "OPTIONS" | "PROPFIND" | "REPORT") {
+            Some(Scope::TodosRead)
+        } else {
+            Some(Scope::TodosWrite)
+        }
+    } else {
+        None
+    }
+}
+
+/// Rejects requests without a credential (`401`) or without the route's scope (`403`).
+/// The resolved `Principal` is left in the request extensions for handlers. With
+/// `X-Impersonate-User`, an admin's request runs as that user instead and is audited, whether
+/// it's refused or not; routes the header can't apply to answer `400`.
+pub async fn require_scope(State(auth): State<Arc<AuthService>>, req: Request, next: Next) -> Response {
+    let impersonating = req.headers().contains_key(IMPERSONATE_HEADER);
+    let Some(scope) = required_scope(req.method(), req.uri().path()) else {
+        if impersonating {
+            return (StatusCode::BAD_REQUEST, "X-Impersonate-User doesn't apply to this route").into_response();
+        }
+        return next.run(req).await;
+    };
+    if impersonating && !impersonation_applies(req.uri().path()) {
+        return (StatusCode::BAD_REQUEST, "X-Impersonate-User doesn't apply to this route").into_response();
+    }
+
+    let dav = is_dav(req.uri().path());
+    let credential = match bearer_token(req.headers()) {
+        Some(bearer) => Some(bearer.to_owned()),
+        None if dav => basic_password(req.headers()),
+        None => None,
+    };
+    let principal = match credential {
+        Some(credential) => auth.principal(&credential).await,
+        None => Err(AuthError::InvalidToken),
+    };
+    let principal = match principal {
+        Ok(principal) => principal,
+        Err(AuthError::InvalidToken) => {
+            let challenge = if dav { "Basic realm=\"todo-api\"" } else { "Bearer" };
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, challenge)],
+                "Missing or invalid credentials",
+            )
+                .into_response();
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to resolve credentials");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Authentication failed").into_response();
+        }
+    };
+    let Some(value) = req.headers().get(IMPERSONATE_HEADER) else {
+        if let Err(e) = principal.require(scope) {
+            warn!(user.id = %principal.user.id, scope = scope.as_str(), "Missing scope");
+            return (StatusCode::FORBIDDEN, e.to_string()).into_response();
+        }
+        return run_as(principal, req, next).await;
+    };
+
+    let attempt = Impersonation {
+        client: ClientInfo::of(req.extensions(), req.headers()),
+        admin: principal.user.clone(),
+        user_id: value.to_str().ok().and_then(|v| Uuid::parse_str(v.trim()).ok()),
+        method: req.method().clone(),
+        path: req.uri().path().to_owned(),
+    };
+    let Some(user_id) = attempt.user_id else {
+        let response = (StatusCode::BAD_REQUEST, "Invalid X-Impersonate-User header").into_response();
+        return attempt.audit(&auth, None, response).await;
+    };
+    let principal = match auth.impersonate(principal, user_id).await {
+        Ok(principal) => principal,
+        Err(e @ (AuthError::MissingScope(_) | AuthError::SessionRequired)) => {
+            warn!(impersonated.id = %user_id, "Impersonation refused");
+            let response = (StatusCode::FORBIDDEN, e.to_string()).into_response();
+            return attempt.audit(&auth, None, response).await;
+        }
+        Err(e @ AuthError::ImpersonatedUserNotFound) => {
+            let response = (StatusCode::NOT_FOUND, e.to_string()).into_response();
+            return attempt.audit(&auth, None, response).await;
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to resolve impersonated user");
+            let response = (StatusCode::INTERNAL_SERVER_ERROR, "Authentication failed").into_response();
+            return attempt.audit(&auth, None, response).await;
+        }
+    };
+
+    let response = if let Err(e) = principal.require(scope) {
+        warn!(user.id = %principal.user.id, scope = scope.as_str(), "Missing scope");
+        (StatusCode::FORBIDDEN, e.to_string()).into_response()
+    } else if is_destructive_admin(&attempt.method, &attempt.path) {
+        warn!(
+            user.id = %attempt.admin.id,
+            impersonated.id = %principal.user.id,
+            "Admin change refused while impersonating"
+        );
+        (StatusCode::FORBIDDEN, "Admin changes can't be made while impersonating").into_response()
+    } else {
+        info!(user.id = %attempt.admin.id, impersonated.id = %principal.user.id, "Impersonated request");
+        run_as(principal.clone(), req, next).await
+    };
+    attempt.audit(&auth, Some(&principal.user), response).await
+}
+
+/// An admin's request to act as another user, for the audit log.
+struct Impersonation {
+    client: ClientInfo,
+    admin: User,
+    /// `None` when the header isn't a user id.
+    user_id: Option<Uuid>,
+    method: Method,
+    path: String,
+}
+
+impl Impersonation {
+    /// Records the attempt and how it was answered under the impersonated user, then returns
+    /// the response. `user` is `None` when the user couldn't be impersonated.
+    async fn audit(&self, auth: &AuthService, user: Option<&User>, response: Response) -> Response {
+        auth.audit
+            .record(
+                &self.client,
+                AuditEvent::Impersonated,
+                user.map(|u| u.id).or(self.user_id),
+                user.map(|u| u.email.as_str()),
+                json!({
+                    "impersonator_id": self.admin.id,
+                    "impersonator_email": self.admin.email,
+                    "method": self.method.as_str(),
+                    "path": self.path,
+                    "status": response.status().as_u16(),
+                }),
+            )
+            .await;
+        response
+    }
+}
+
+/// Whether a scoped route's handler acts as the principal `require_scope` resolves. Project
+/// routes resolve the bearer token themselves, so they'd run as the admin rather than the
+/// impersonated user.
+fn impersonation_applies(path: &str) -> bool {
+    path != "/projects" && !path.starts_with("/projects/")
+}
+
+/// Runs the rest of the stack on behalf of `principal`.
+async fn run_as(principal: Principal, mut req: Request, next: Next) -> Response {
+    req.extensions_mut().insert(principal.clone());
+    let mut response = next.run(req).await;
+    // For outer layers, such as usage tracking, to tell who made the request
+    response.extensions_mut().insert(principal);
+    response
+}
+
+/// Admin routes that change something, such as restoring a backup or flipping a flag. These
+/// are refused outright while impersonating, whatever the impersonated user's scopes. Nothing
+/// outside `/admin` needs refusing: account, token and session routes (`/auth`, `/users`)
+/// don't take `X-Impersonate-User` at all, so the admin can't mint a token or delete an account
+/// as the user, and every other route only does what the user could do themselves.
+fn is_destructive_admin(method: &Method, path: &str) -> bool {
+    path.starts_with("/admin/") && !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
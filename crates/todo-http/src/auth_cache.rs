@@ -0,0 +1,129 @@
+use crate::auth::Principal;
+use chrono::{DateTime, Utc};
+use moka::future::Cache;
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use todo_domain::clock::Clock;
+use uuid::Uuid;
+
+#[derive(Clone)]
+struct Entry {
+    principal: Principal,
+    /// When the credential itself stops working, which can come before the cache TTL.
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// Principals resolved from bearer credentials, so a client's requests don't each check its
+/// session or token against the database. Keyed by the credential's SHA-256, never the
+/// credential itself. Revoking a session or token, changing a profile or erasing an account
+/// drops the user's entries here straight away; revocations made through another replica
+/// take effect once the TTL runs out.
+pub(crate) struct AuthCache {
+    /// `None` when caching is off.
+    entries: Option<Cache<String, Entry>>,
+    /// Bumped whenever entries are dropped, so a lookup that raced with a revocation isn't kept.
+    generation: AtomicU64,
+    /// What credential expiry is checked against, the same clock the repository issues by.
+    clock: Arc<dyn Clock>,
+    hits: Counter<u64>,
+    misses: Counter<u64>,
+}
+
+impl AuthCache {
+    /// Keeps up to `max_entries` principals for `ttl`; a `ttl` of `None` disables the cache.
+    pub(crate) fn new(ttl: Option<Duration>, max_entries: u64, clock: Arc<dyn Clock>) -> Self {
+        let entries = ttl.map(|ttl| {
+            Cache::builder()
+                .max_capacity(max_entries)
+                .time_to_live(ttl)
+                .support_invalidation_closures()
+                .build()
+        });
+        let meter = global::meter("todo-api");
+        Self {
+            entries,
+            generation: AtomicU64::new(0),
+            clock,
+            hits: meter
+                .u64_counter("auth.cache.hits")
+                .with_description("Requests authenticated from the credential cache")
+                .init(),
+            misses: meter
+                .u64_counter("auth.cache.misses")
+                .with_description("Requests whose credential had to be checked against the database")
+                .init(),
+        }
+    }
+
+    /// The cached principal for the credential hashed to `key`, unless it has expired since.
+    pub(crate) async fn get(&self, key: &str, credential: &'static str) -> Option<Principal> {
+        self.entries.as_ref()?;
+        let attributes = [KeyValue::new("credential", credential)];
+        match self.peek(key).await {
+            Some(principal) => {
+                self.hits.add(1, &attributes);
+                Some(principal)
+            }
+            None => {
+                self.misses.add(1, &attributes);
+                None
+            }
+        }
+    }
+
+    /// Like `get`, without counting a hit or miss, for callers that only look.
+    pub(crate) async fn peek(&self, key: &str) -> Option<Principal> {
+        self.entries
+            .as_ref()?
+            .get(key)
+            .await
+            .filter(|entry| entry.expires_at.is_none_or(|expires_at| expires_at > self.clock.now()))
+            .map(|entry| entry.principal)
+    }
+
+    /// To read before checking a credential against the database, and hand to `insert`.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Keeps a principal checked since `generation`, unless entries were dropped meanwhile.
+    pub(crate) async fn insert(
+        &self,
+        key: String,
+        principal: Principal,
+        expires_at: Option<DateTime<Utc>>,
+        generation: u64,
+    ) {
+        let Some(entries) = &self.entries else {
+            return;
+        };
+        entries.insert(key.clone(), Entry { principal, expires_at }).await;
+        if self.generation() != generation {
+            entries.invalidate(&key).await;
+        }
+    }
+
+    /// Drops every credential of the user, sessions and personal tokens alike.
+    pub(crate) fn forget_user(&self, user_id: Uuid) {
+        self.forget(move |entry| entry.principal.user.id == user_id);
+    }
+
+    pub(crate) fn forget_api_token(&self, token_id: Uuid) {
+        self.forget(move |entry| entry.principal.api_token_id == Some(token_id));
+    }
+
+    fn forget(&self, predicate: impl Fn(&Entry) -> bool + Send + Sync + 'static) {
+        let Some(entries) = &self.entries else {
+            return;
+        };
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        // Only fails when closures aren't supported, and they're turned on in `new`
+        let _ = entries.invalidate_entries_if(move |_, entry| predicate(entry));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::config::BodyLogConfig;
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::State,
+    http::{header, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+use std::sync::Arc;
+use todo_storage::redact;
+
+/// Field names whose values never appear in recorded bodies.
+const CREDENTIAL_FIELDS: &[&str] = &[
+    "password",
+    "current_password",
+    "new_password",
+    "token",
+    "access_token",
+    "refresh_token",
+    "secret",
+    "client_secret",
+    "api_key",
+    "authorization",
+];
+
+/// Fields of todos rendered through `PII_REDACTION`, as they are in spans.
+const PII_FIELDS: &[&str] = &["title", "description", "text"];
+
+/// Bodies larger than this, or of unknown length, pass through unrecorded rather than
+/// being held in memory for a debug event.
+const MAX_BUFFERED: u64 = 1024 * 1024;
+
+const MASK: &str = "[redacted]";
+
+/// Records the bodies of a sample of requests, and of their responses, as events on the
+/// request span, so malformed client payloads can be seen in the trace that rejected them.
+pub struct BodyLog {
+    sample_rate: f64,
+    max_bytes: usize,
+    redact_fields: Vec<String>,
+}
+
+impl BodyLog {
+    pub fn new(config: &BodyLogConfig) -> Self {
+        let mut redact_fields = config.redact_fields.clone();
+        redact_fields.extend(CREDENTIAL_FIELDS.iter().map(|field| field.to_string()));
+        Self {
+            sample_rate: config.sample_rate,
+            max_bytes: config.max_bytes,
+            redact_fields,
+        }
+    }
+
+    fn sampled(&self) -> bool {
+        self.sample_rate >= 1.0 || rand::random::<f64>() < self.sample_rate
+    }
+
+    fn is_secret(&self, field: &str) -> bool {
+        let field = field.to_ascii_lowercase();
+        self.redact_fields.contains(&field)
+    }
+
+    /// The body as recorded: masked, cut to `max_bytes`, or only described if it isn't text.
+    fn render(&self, headers: &HeaderMap, bytes: &[u8]) -> String {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let text = String::from_utf8_lossy(bytes);
+        let rendered = if content_type == "application/json" || content_type.ends_with("+json") {
+            match serde_json::from_slice::<Value>(bytes) {
+                Ok(mut value) => {
+                    self.mask_json(&mut value);
+                    value.to_string()
+                }
+                // Malformed, which is what this is for; mask what can still be found
+                Err(_) => self.mask_text(&text),
+            }
+        } else if content_type == "application/x-www-form-urlencoded" {
+            self.mask_form(&text)
+        } else if content_type.ends_with("xml") {
+            self.mask_xml(&self.mask_text(&text))
+        } else if content_type.starts_with("text/") || content_type.is_empty() {
+            self.mask_text(&text)
+        } else {
+            return format!("<{} bytes of {content_type}>", bytes.len());
+        };
+        truncate(rendered, self.max_bytes, bytes.len())
+    }
+
+    fn mask_json(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self.is_secret(key) {
+                        *value = Value::String(MASK.into());
+                    } else if let (true, Value::String(text)) = (PII_FIELDS.contains(&key.as_str()), &*value) {
+                        *value = Value::String(redact::redacted(text).to_string());
+                    } else {
+                        self.mask_json(value);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.mask_json(item)),
+            _ => {}
+        }
+    }
+
+    fn mask_form(&self, text: &str) -> String {
+        text.split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if self.is_secret(key) => format!("{key}={MASK}"),
+                Some((key, value)) if PII_FIELDS.contains(&key) => format!("{key}={}", redact::redacted(value)),
+                _ => pair.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    /// Masks the string following each `"<secret field>":` in text that isn't valid JSON, and
+    /// redacts the one following each PII field.
+    fn mask_text(&self, text: &str) -> String {
+        let mut masked = String::with_capacity(text.len());
+        let mut rest = text;
+        // Each pair of quotes is taken as a key, or a string value that is skipped the same way
+        while let Some(start) = rest.find('"') {
+            let after_quote = &rest[start + 1..];
+            let Some(end) = after_quote.find('"') else {
+                break;
+            };
+            let key = &after_quote[..end];
+            masked.push_str(&rest[..start + end + 2]);
+            rest = &after_quote[end + 1..];
+
+            let value = rest
+                .trim_start()
+                .strip_prefix(':')
+                .and_then(|v| v.trim_start().strip_prefix('"'));
+            let Some(value) = value else {
+                continue;
+            };
+            if self.is_secret(key) {
+                masked.push_str(&rest[..rest.len() - value.len()]);
+                masked.push_str(MASK);
+                masked.push('"');
+                rest = after_string(value);
+            } else if PII_FIELDS.contains(&key) {
+                let after = after_string(value);
+                // Without a closing quote, the value runs to the end
+                let text = value[..value.len() - after.len()].strip_suffix('"').unwrap_or(value);
+                masked.push_str(&rest[..rest.len() - value.len()]);
+                masked.push_str(&redact::redacted(text).to_string());
+                masked.push('"');
+                rest = after;
+            }
+        }
+        masked.push_str(rest);
+        masked
+    }
+
+    /// Masks the text of `<secret>` elements and redacts that of PII ones, as in XML bodies.
+    fn mask_xml(&self, text: &str) -> String {
+        let mut masked = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('<') {
+            let after_open = &rest[start + 1..];
+            let Some(end) = after_open.find('>') else {
+                break;
+            };
+            let name = &after_open[..end];
+            masked.push_str(&rest[..start + end + 2]);
+            rest = &after_open[end + 1..];
+
+            let secret = self.is_secret(name);
+            if !secret && !PII_FIELDS.contains(&name) {
+                continue;
+            }
+            let content_end = rest.find(&format!("</{name}>")).unwrap_or(rest.len());
+            let content = &rest[..content_end];
+            if secret {
+                masked.push_str(MASK);
+            } else {
+                masked.push_str(&redact::redacted(content).to_string());
+            }
+            rest = &rest[content_end..];
+        }
+        masked.push_str(rest);
+        masked
+    }
+}
+
+/// What follows the closing quote of the string `text` is inside of.
+fn after_string(text: &str) -> &str {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return &text[i + 1..],
+            _ => escaped = false,
+        }
+    }
+    ""
+}
+
+fn truncate(mut text: String, max_bytes: usize, total: usize) -> String {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    text.push_str(&format!("... ({total} bytes)"));
+    text
+}
+
+/// Reads a body small enough to keep in memory, or gives it back untouched.
+async fn buffer(body: Body) -> Result<Bytes, Body> {
+    match body.size_hint().exact() {
+        Some(size) if size <= MAX_BUFFERED => Ok(axum::body::to_bytes(body, MAX_BUFFERED as usize)
+            .await
+            .unwrap_or_default()),
+        _ => Err(body),
+    }
+}
+
+/// Records the request body before the handler sees it and the response body after. Must
+/// sit inside the `TraceLayer`, so the events land on the request span.
+pub async fn log_bodies(State(log): State<Arc<BodyLog>>, req: Request<Body>, next: Next) -> Response {
+    if !log.sampled() {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+    let body = match buffer(body).await {
+        Ok(bytes) => {
+            if !bytes.is_empty() {
+                let recorded = log.render(&parts.headers, &bytes);
+                tracing::event!(
+                    target: "body_log",
+                    tracing::Level::INFO,
+                    http.request.body = %recorded,
+                    "request body"
+                );
+            }
+            Body::from(bytes)
+        }
+        Err(body) => {
+            tracing::info!(target: "body_log", "request body not recorded: streamed or too large");
+            body
+        }
+    };
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let (parts, body) = response.into_parts();
+    let body = match buffer(body).await {
+        Ok(bytes) => {
+            if !bytes.is_empty() {
+                let recorded = log.render(&parts.headers, &bytes);
+                tracing::event!(
+                    target: "body_log",
+                    tracing::Level::INFO,
+                    http.response.body = %recorded,
+                    http.response.status_code = parts.status.as_u16(),
+                    "response body"
+                );
+            }
+            Body::from(bytes)
+        }
+        Err(body) => body,
+    };
+    Response::from_parts(parts, body)
+}
@@ -0,0 +1,375 @@
+use crate::models::{
+    AddDependencyRequest, AddMemberRequest, BatchCreateRequest, BulkTagRequest, CreateApiTokenRequest,
+    CreateCommentRequest, CreateCustomFieldRequest, CreateProjectRequest, CreateTodoRequest, CreateWebhookRequest,
+    Credentials, DeleteAccountRequest, MergeTagRequest, ParseTodoRequest, RefreshRequest, RenameTagRequest,
+    RestoreRequest, SetFeatureFlagRequest, SnoozeRequest, SyncPush, UpdateProfileRequest, UpdateTagRequest,
+    UpdateTodoRequest,
+};
+use crate::negotiate::ApiMode;
+use axum::{
+    body::{Body, Bytes},
+    extract::{MatchedPath, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::DateTime;
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use schemars::{generate::SchemaSettings, JsonSchema, SchemaGenerator};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Bodies larger than this are left for the handler to refuse, as axum's default limit does.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+/// Problems listed in one response; a body wrong in more places than this is wrong enough.
+const MAX_ERRORS: usize = 20;
+
+/// Registers a body type's schema under `$defs`, returning the name it's under.
+type Definition = fn(&mut SchemaGenerator) -> String;
+
+fn definition<T: JsonSchema>(generator: &mut SchemaGenerator) -> String {
+    generator.subschema_for::<T>();
+    T::schema_name().into_owned()
+}
+
+/// Every route that reads a JSON body, by method and route template, with the body's type.
+/// Webhook receivers (`/integrations/...`) and `/mcp` are left out: their bodies are the
+/// sender's format or JSON-RPC, which answers errors its own way.
+const ROUTES: &[(Method, &str, Definition)] = &[
+    (Method::POST, "/todos", definition::<CreateTodoRequest>),
+    (Method::PUT, "/todos/:id", definition::<UpdateTodoRequest>),
+    (Method::POST, "/todos/batch", definition::<BatchCreateRequest>),
+    (Method::POST, "/todos/parse", definition::<ParseTodoRequest>),
+    (Method::POST, "/todos/:id/snooze", definition::<SnoozeRequest>),
+    (Method::POST, "/todos/:id/comments", definition::<CreateCommentRequest>),
+    (Method::POST, "/todos/:id/blockers", definition::<AddDependencyRequest>),
+    (Method::POST, "/todos/tags", definition::<BulkTagRequest>),
+    (Method::POST, "/sync/push", definition::<SyncPush>),
+    (Method::PUT, "/tags/:name", definition::<UpdateTagRequest>),
+    (Method::POST, "/tags/:name/rename", definition::<RenameTagRequest>),
+    (Method::POST, "/tags/:name/merge", definition::<MergeTagRequest>),
+    (Method::POST, "/projects", definition::<CreateProjectRequest>),
+    (Method::POST, "/projects/:id/members", definition::<AddMemberRequest>),
+    (Method::POST, "/projects/:id/todos", definition::<CreateTodoRequest>),
+    (Method::POST, "/auth/register", definition::<Credentials>),
+    (Method::POST, "/auth/login", definition::<Credentials>),
+    (Method::POST, "/auth/refresh", definition::<RefreshRequest>),
+    (Method::POST, "/auth/logout", definition::<RefreshRequest>),
+    (Method::POST, "/auth/tokens", definition::<CreateApiTokenRequest>),
+    (Method::PATCH, "/users/me", definition::<UpdateProfileRequest>),
+    (Method::DELETE, "/users/me", definition::<DeleteAccountRequest>),
+    (Method::POST, "/admin/restore", definition::<RestoreRequest>),
+    (Method::PUT, "/admin/flags/:name", definition::<SetFeatureFlagRequest>),
+    (Method::POST, "/admin/webhooks", definition::<CreateWebhookRequest>),
+    (Method::POST, "/admin/custom-fields", definition::<CreateCustomFieldRequest>),
+];
+
+/// One way a body breaks its schema, at the JSON Pointer of the offending value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    pub pointer: String,
+    pub detail: String,
+}
+
+/// Checks JSON request bodies against `schema` before the handler deserializes them. Under
+/// `ApiMode::Strict` fields the schema doesn't know are refused too, so a typo such as
+/// `compelted` isn't silently dropped.
+pub struct BodyValidator {
+    mode: ApiMode,
+    schema: Value,
+    /// The `$defs` name of each route's body, in the order of `ROUTES`.
+    names: Vec<String>,
+    rejected: Counter<u64>,
+}
+
+impl BodyValidator {
+    pub fn new(mode: ApiMode) -> Self {
+        let (schema, names) = schemas();
+        Self {
+            mode,
+            schema,
+            names,
+            rejected: global::meter("todo-api")
+                .u64_counter("request_validation.rejected")
+                .with_description("Request bodies refused for not matching their schema")
+                .init(),
+        }
+    }
+
+    /// What's wrong with `body` as the definition `name`, at most `MAX_ERRORS` of it.
+    pub fn validate(&self, name: &str, body: &Value) -> Vec<SchemaError> {
+        let mut errors = Vec::new();
+        let definition = &self.schema["$defs"][name];
+        self.check(definition, body, &mut String::new(), &mut errors);
+        errors
+    }
+
+    fn check(&self, schema: &Value, value: &Value, pointer: &mut String, errors: &mut Vec<SchemaError>) {
+        if errors.len() >= MAX_ERRORS {
+            return;
+        }
+        let fail = |errors: &mut Vec<SchemaError>, detail: String| {
+            errors.push(SchemaError {
+                pointer: pointer.clone(),
+                detail,
+            })
+        };
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            let definition = &self.schema["$defs"][name];
+            return self.check(definition, value, pointer, errors);
+        }
+        // Options and tagged enums; serde's tags keep the alternatives apart
+        for keyword in ["anyOf", "oneOf"] {
+            if let Some(alternatives) = schema[keyword].as_array() {
+                return self.check_alternatives(alternatives, value, pointer, errors);
+            }
+        }
+        if let Some(types) = types(schema) {
+            if !types.iter().any(|t| has_type(value, t)) {
+                return fail(errors, format!("expected {}, got {}", types.join(" or "), type_name(value)));
+            }
+        }
+        if let Some(allowed) = schema["enum"].as_array() {
+            if !allowed.contains(value) {
+                let names: Vec<_> = allowed.iter().map(Value::to_string).collect();
+                return fail(errors, format!("expected one of {}", names.join(", ")));
+            }
+        }
+        if let Some(expected) = schema.get("const").filter(|&expected| expected != value) {
+            return fail(errors, format!("expected {expected}"));
+        }
+        match value {
+            Value::String(s) if schema["format"] == "date-time" && DateTime::parse_from_rfc3339(s).is_err() => {
+                fail(errors, "expected an RFC 3339 date-time".to_string());
+            }
+            Value::String(s) if schema["format"] == "uuid" && Uuid::parse_str(s).is_err() => {
+                fail(errors, "expected a UUID".to_string());
+            }
+            Value::String(s) if schema["minLength"].as_u64().is_some_and(|min| (s.chars().count() as u64) < min) => {
+                match schema["minLength"].as_u64() {
+                    Some(1) => fail(errors, "must not be empty".to_string()),
+                    min => fail(errors, format!("must be at least {} characters", min.unwrap_or_default())),
+                }
+            }
+            Value::Number(n) => {
+                let n = n.as_f64().unwrap_or_default();
+                let (min, max) = integer_range(&schema["format"]).unwrap_or((f64::NEG_INFINITY, f64::INFINITY));
+                let min = schema["minimum"].as_f64().map_or(min, |minimum| minimum.max(min));
+                let max = schema["maximum"].as_f64().map_or(max, |maximum| maximum.min(max));
+                if n < min {
+                    fail(errors, format!("must be at least {min}"));
+                } else if n > max {
+                    fail(errors, format!("must be at most {max}"));
+                }
+            }
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    let len = pointer.len();
+                    pointer.push_str(&format!("/{i}"));
+                    self.check(&schema["items"], item, pointer, errors);
+                    pointer.truncate(len);
+                }
+            }
+            Value::Object(fields) => self.check_object(schema, fields, pointer, errors),
+            _ => {}
+        }
+    }
+
+    /// Passes when any alternative does; otherwise reports the problems of the one `value`
+    /// comes closest to, which for a tagged enum is the variant its tag names.
+    fn check_alternatives(
+        &self,
+        alternatives: &[Value],
+        value: &Value,
+        pointer: &mut String,
+        errors: &mut Vec<SchemaError>,
+    ) {
+        let mut closest: Option<Vec<SchemaError>> = None;
+        for alternative in alternatives {
+            let mut attempt = Vec::new();
+            self.check(alternative, value, pointer, &mut attempt);
+            if attempt.is_empty() {
+                return;
+            }
+            if closest.as_ref().is_none_or(|closest| attempt.len() < closest.len()) {
+                closest = Some(attempt);
+            }
+        }
+        let room = MAX_ERRORS.saturating_sub(errors.len());
+        errors.extend(closest.into_iter().flatten().take(room));
+    }
+
+    fn check_object(
+        &self,
+        schema: &Value,
+        fields: &Map<String, Value>,
+        pointer: &mut String,
+        errors: &mut Vec<SchemaError>,
+    ) {
+        let properties = schema["properties"].as_object();
+        for required in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+            if !fields.contains_key(required) {
+                errors.push(SchemaError {
+                    pointer: format!("{pointer}/{}", escape(required)),
+                    detail: "is required".to_string(),
+                });
+            }
+        }
+        for (name, value) in fields {
+            let len = pointer.len();
+            pointer.push_str(&format!("/{}", escape(name)));
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(property) => self.check(property, value, pointer, errors),
+                None => match &schema["additionalProperties"] {
+                    // Maps, such as custom field values
+                    Value::Object(_) | Value::Bool(true) => {
+                        self.check(&schema["additionalProperties"], value, pointer, errors)
+                    }
+                    _ if self.mode == ApiMode::Strict && properties.is_some() => errors.push(SchemaError {
+                        pointer: pointer.clone(),
+                        detail: "unknown field".to_string(),
+                    }),
+                    _ => {}
+                },
+            }
+            pointer.truncate(len);
+        }
+    }
+}
+
+/// The values an integer `format` such as `uint32` can hold, for the sizes a request field
+/// might overflow.
+fn integer_range(format: &Value) -> Option<(f64, f64)> {
+    let range = match format.as_str()? {
+        "int8" => (i8::MIN.into(), i8::MAX.into()),
+        "uint8" => (u8::MIN.into(), u8::MAX.into()),
+        "int16" => (i16::MIN.into(), i16::MAX.into()),
+        "uint16" => (u16::MIN.into(), u16::MAX.into()),
+        "int32" => (i32::MIN.into(), i32::MAX.into()),
+        "uint32" => (u32::MIN.into(), u32::MAX.into()),
+        _ => return None,
+    };
+    Some(range)
+}
+
+/// The `type` keyword as a list; `None` when any type goes.
+fn types(schema: &Value) -> Option<Vec<&str>> {
+    match &schema["type"] {
+        Value::String(t) => Some(vec![t.as_str()]),
+        Value::Array(types) => Some(types.iter().filter_map(Value::as_str).collect()),
+        _ => None,
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// A field name as a JSON Pointer token (RFC 6901).
+fn escape(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+/// Refuses a JSON body that doesn't match its route's schema with `422` and a problem
+/// document listing each problem's JSON Pointer. Other formats, routes without a schema, and
+/// bodies that aren't JSON at all go through to the handler, which answers as it always has.
+pub async fn validate_bodies(State(validator): State<Arc<BodyValidator>>, req: Request, next: Next) -> Response {
+    let route = req.extensions().get::<MatchedPath>().map(|path| path.as_str().to_owned());
+    let Some(name) = ROUTES
+        .iter()
+        .position(|(method, path, _)| req.method() == method && route.as_deref() == Some(*path))
+        .map(|i| validator.names[i].clone())
+    else {
+        return next.run(req).await;
+    };
+    let json = match req.headers().get(header::CONTENT_TYPE) {
+        None => true,
+        Some(value) => value
+            .to_str()
+            .is_ok_and(|v| v.split(';').next().is_some_and(|t| t.trim().eq_ignore_ascii_case("application/json"))),
+    };
+    if !json {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+    let bytes: Bytes = match axum::body::to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => return (StatusCode::PAYLOAD_TOO_LARGE, "Request body too large").into_response(),
+    };
+    if let Ok(value) = serde_json::from_slice::<Value>(&bytes) {
+        let errors = validator.validate(&name, &value);
+        if !errors.is_empty() {
+            let route = route.unwrap_or_default();
+            warn!(http.route = %route, errors = errors.len(), "Request body doesn't match its schema");
+            validator.rejected.add(
+                1,
+                &[
+                    KeyValue::new("http.route", route),
+                    KeyValue::new("http.request.method", parts.method.to_string()),
+                ],
+            );
+            return invalid_body(&errors);
+        }
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes))).await
+}
+
+fn invalid_body(errors: &[SchemaError]) -> Response {
+    let first = &errors[0];
+    let body = json!({
+        "type": "about:blank",
+        "title": "Invalid request body",
+        "status": StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+        "detail": format!("{}: {}", first.pointer, first.detail),
+        "errors": errors
+            .iter()
+            .map(|e| json!({ "pointer": e.pointer, "detail": e.detail }))
+            .collect::<Vec<_>>(),
+    });
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        [(header::CONTENT_TYPE, "application/problem+json")],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+/// JSON Schemas (draft 2020-12) for the request bodies in `ROUTES`, derived from the request
+/// types in `todo_domain::models` so they can't drift from what serde accepts. Served at
+/// `/schemas/requests`, for clients to validate against and for OpenAPI 3.1 documents to
+/// reference.
+pub fn schema() -> Value {
+    schemas().0
+}
+
+/// `schema`, and the `$defs` name of each route's body.
+fn schemas() -> (Value, Vec<String>) {
+    let mut generator = SchemaSettings::draft2020_12().for_deserialize().into_generator();
+    let names = ROUTES.iter().map(|(_, _, definition)| definition(&mut generator)).collect();
+    let schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": "/schemas/requests",
+        "title": "Request bodies",
+        "$defs": generator.take_definitions(true),
+    });
+    (schema, names)
+}
@@ -0,0 +1,59 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+pub const SPEC_VERSION: &str = "1.0";
+
+/// `datacontenttype` of every event; `data` is always JSON.
+const CONTENT_TYPE: &str = "application/json";
+
+/// An event in the CloudEvents 1.0 JSON format, as sent to webhook subscriptions and on
+/// `GET /todos/events?envelope=cloudevents`, so serverless event routers can take it as is.
+#[derive(Debug, Clone, Serialize)]
+pub struct CloudEvent {
+    pub specversion: &'static str,
+    /// Unique per event from this `source`; receivers can drop repeats by it.
+    pub id: Uuid,
+    pub source: &'static str,
+    /// The event name, e.g. `todo.created`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The todo the event is about, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    pub time: DateTime<Utc>,
+    pub datacontenttype: &'static str,
+    pub data: Value,
+}
+
+impl CloudEvent {
+    pub fn new(source: &'static str, kind: impl Into<String>, data: Value) -> Self {
+        Self::with_id(Uuid::new_v4(), Utc::now(), source, kind, data)
+    }
+
+    /// An event whose id and time were fixed when it happened, so every copy of it agrees.
+    pub fn with_id(
+        id: Uuid,
+        time: DateTime<Utc>,
+        source: &'static str,
+        kind: impl Into<String>,
+        data: Value,
+    ) -> Self {
+        Self {
+            specversion: SPEC_VERSION,
+            id,
+            source,
+            kind: kind.into(),
+            subject: None,
+            time,
+            datacontenttype: CONTENT_TYPE,
+            data,
+        }
+    }
+
+    pub fn subject(mut self, subject: impl ToString) -> Self {
+        self.subject = Some(subject.to_string());
+        self
+    }
+}
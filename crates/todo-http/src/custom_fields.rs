@@ -0,0 +1,185 @@
+use crate::models::{CreateCustomFieldRequest, CustomFieldDef, CustomFieldType};
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use chrono::NaiveDate;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use tracing::info;
+
+const MAX_NAME_LEN: usize = 64;
+
+/// Most fields that can be defined, so every todo read stays cheap.
+const MAX_FIELDS: usize = 50;
+
+const MAX_TEXT_LEN: usize = 1000;
+
+/// Query parameters of `GET /todos` that filter by a custom field: `field.<name>=<value>`.
+pub const FILTER_PREFIX: &str = "field.";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CustomFieldError {
+    #[error(
+        "Custom field names are 1 to {MAX_NAME_LEN} lowercase letters, digits and underscores, \
+         starting with a letter"
+    )]
+    InvalidName,
+
+    #[error("At most {MAX_FIELDS} custom fields can be defined")]
+    TooMany,
+
+    #[error("Custom field {0} already exists")]
+    AlreadyExists(String),
+
+    #[error("Unknown custom field: {0}")]
+    Unknown(String),
+
+    #[error("Custom field {name} must be {expected}")]
+    WrongType { name: String, expected: &'static str },
+
+    #[error("Custom field {0} is required")]
+    Missing(String),
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+fn valid_name(name: &str) -> bool {
+    (1..=MAX_NAME_LEN).contains(&name.len())
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// What a value of `field_type` has to be, for error messages.
+fn expected(field_type: CustomFieldType) -> &'static str {
+    match field_type {
+        CustomFieldType::Text => "text of at most 1000 characters",
+        CustomFieldType::Number => "a number",
+        CustomFieldType::Boolean => "true or false",
+        CustomFieldType::Date => "a date as YYYY-MM-DD",
+    }
+}
+
+fn has_type(value: &Value, field_type: CustomFieldType) -> bool {
+    match (field_type, value) {
+        (CustomFieldType::Text, Value::String(s)) => s.chars().count() <= MAX_TEXT_LEN,
+        (CustomFieldType::Number, Value::Number(_)) => true,
+        (CustomFieldType::Boolean, Value::Bool(_)) => true,
+        (CustomFieldType::Date, Value::String(s)) => NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok(),
+        _ => false,
+    }
+}
+
+/// Fields teams define for themselves, such as a customer or a sprint, stored alongside
+/// each todo's own columns. Values are checked against the definitions on every API write.
+pub struct CustomFields {
+    repository: Arc<SqliteTodoRepository>,
+}
+
+impl CustomFields {
+    pub fn new(repository: Arc<SqliteTodoRepository>) -> Self {
+        Self { repository }
+    }
+
+    /// Defines a new field. Todos created before it keep having no value for it, even if
+    /// it's required.
+    pub async fn define(&self, request: CreateCustomFieldRequest) -> Result<CustomFieldDef, CustomFieldError> {
+        let name = request.name.trim();
+        if !valid_name(name) {
+            return Err(CustomFieldError::InvalidName);
+        }
+        if self.repository.list_custom_fields().await?.len() >= MAX_FIELDS {
+            return Err(CustomFieldError::TooMany);
+        }
+        let field = CustomFieldDef {
+            name: name.to_owned(),
+            field_type: request.field_type,
+            required: request.required,
+            created_at: self.repository.clock().now(),
+        };
+        match self.repository.create_custom_field(&field).await {
+            Ok(()) => {}
+            Err(RepositoryError::AlreadyExists(name)) => return Err(CustomFieldError::AlreadyExists(name)),
+            Err(e) => return Err(e.into()),
+        }
+        info!(field.name = %field.name, field.type = field.field_type.as_str(), "Custom field defined");
+        Ok(field)
+    }
+
+    pub async fn list(&self) -> Result<Vec<CustomFieldDef>, RepositoryError> {
+        self.repository.list_custom_fields().await
+    }
+
+    /// Deletes the field and every todo's value for it. Returns false if there was no such field.
+    pub async fn remove(&self, name: &str) -> Result<bool, RepositoryError> {
+        self.repository.delete_custom_field(name).await
+    }
+
+    /// Checks values a write sets against the definitions. `null` clears a field, which
+    /// required ones refuse. With `creating`, every required field must also have a value.
+    pub async fn validate(&self, values: &BTreeMap<String, Value>, creating: bool) -> Result<(), CustomFieldError> {
+        let defs = self.repository.list_custom_fields().await?;
+        if values.is_empty() && !(creating && defs.iter().any(|d| d.required)) {
+            return Ok(());
+        }
+        for (name, value) in values {
+            let def = defs
+                .iter()
+                .find(|d| &d.name == name)
+                .ok_or_else(|| CustomFieldError::Unknown(name.clone()))?;
+            if value.is_null() {
+                if def.required {
+                    return Err(CustomFieldError::Missing(name.clone()));
+                }
+                continue;
+            }
+            if !has_type(value, def.field_type) {
+                return Err(CustomFieldError::WrongType {
+                    name: name.clone(),
+                    expected: expected(def.field_type),
+                });
+            }
+        }
+        if creating {
+            if let Some(def) = defs.iter().find(|d| d.required && values.get(&d.name).is_none_or(Value::is_null)) {
+                return Err(CustomFieldError::Missing(def.name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// The `field.<name>=<value>` conditions among `GET /todos` query parameters, each value
+    /// read as its field's type. Other parameters are ignored.
+    pub async fn filter(&self, query: &HashMap<String, String>) -> Result<BTreeMap<String, Value>, CustomFieldError> {
+        let mut conditions = BTreeMap::new();
+        let wanted: Vec<(&str, &str)> = query
+            .iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix(FILTER_PREFIX)?, value.as_str())))
+            .collect();
+        if wanted.is_empty() {
+            return Ok(conditions);
+        }
+
+        let defs = self.repository.list_custom_fields().await?;
+        for (name, raw) in wanted {
+            let def = defs
+                .iter()
+                .find(|d| d.name == name)
+                .ok_or_else(|| CustomFieldError::Unknown(name.to_owned()))?;
+            let value = match def.field_type {
+                CustomFieldType::Text => Some(Value::String(raw.to_owned())),
+                CustomFieldType::Number => raw.parse::<f64>().ok().map(Value::from),
+                CustomFieldType::Boolean => raw.parse::<bool>().ok().map(Value::Bool),
+                CustomFieldType::Date => Some(Value::String(raw.to_owned())),
+            }
+            .filter(|value| has_type(value, def.field_type))
+            .ok_or_else(|| CustomFieldError::WrongType {
+                name: name.to_owned(),
+                expected: expected(def.field_type),
+            })?;
+            conditions.insert(name.to_owned(), value);
+        }
+        Ok(conditions)
+    }
+}
@@ -0,0 +1,612 @@
+use crate::auth::Principal;
+use crate::models::{normalize_tags, Todo, User};
+use crate::notification_worker::NotificationJob;
+use crate::projects::TodoChange;
+use crate::repository::{ListFilter, RepositoryError, SqliteTodoRepository, TodoRepository};
+use crate::{check_todo_access, validation_error, visible_projects, AppState};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderName, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::any,
+    Extension, Router,
+};
+use quick_xml::{escape::escape, events::Event, Reader};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    sync::Arc,
+};
+use todo_domain::ical::{self, VTodo};
+use tracing::{error, info, instrument, warn};
+use uuid::Uuid;
+
+/// `source` of client-chosen item names in the external link table.
+const SOURCE: &str = "caldav";
+
+/// The principal, which is also the calendar home holding the one collection.
+const HOME: &str = "/dav/";
+const COLLECTION: &str = "/dav/todos/";
+
+const CALENDAR_TYPE: &str = "text/calendar; charset=utf-8";
+const XML_TYPE: &str = "application/xml; charset=utf-8";
+const ALLOW: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, REPORT";
+
+/// The names CalDAV clients gave the items they created. A todo is served as `<id>.ics`
+/// unless a client PUT it under another name, which is then kept in the shared database's
+/// external links so the client finds it where it left it.
+pub struct CalDavNames {
+    links: Arc<SqliteTodoRepository>,
+}
+
+impl CalDavNames {
+    pub fn new(links: Arc<SqliteTodoRepository>) -> Self {
+        Self { links }
+    }
+
+    /// Client-given names, by todo id.
+    async fn all(&self) -> Result<HashMap<Uuid, String>, RepositoryError> {
+        let links = self.links.external_links(SOURCE).await?;
+        Ok(links.into_iter().map(|link| (link.todo_id, link.external_id)).collect())
+    }
+
+    /// The todo an item name stands for: a client-given name, or else a todo id.
+    async fn resolve(&self, todos: &dyn TodoRepository, name: &str) -> Result<Option<Todo>, RepositoryError> {
+        let id = match self.links.external_link(SOURCE, name).await? {
+            Some(link) => link.todo_id,
+            None => match Uuid::parse_str(name) {
+                Ok(id) => id,
+                Err(_) => return Ok(None),
+            },
+        };
+        match todos.get(id).await {
+            Ok(todo) => Ok(Some(todo)),
+            Err(RepositoryError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn name(&self, name: &str, todo: Uuid) -> Result<(), RepositoryError> {
+        self.links.link_external(SOURCE, name, todo, self.links.clock().now()).await
+    }
+}
+
+/// A minimal CalDAV server with one calendar, `/dav/todos/`, holding every todo the caller
+/// can see as a VTODO, so clients such as Apple Reminders and Tasks.org sync without an
+/// adapter. Clients find it from `/.well-known/caldav`, read it with `PROPFIND` and
+/// `REPORT`, and write items with `PUT` and `DELETE`.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/.well-known/caldav", any(well_known))
+        .route("/dav", any(home))
+        .route("/dav/", any(home))
+        .route("/dav/todos", any(collection))
+        .route("/dav/todos/", any(collection))
+        .route("/dav/todos/:name", any(item))
+}
+
+async fn well_known() -> Response {
+    (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, HOME)]).into_response()
+}
+
+#[instrument(skip_all, fields(http.request.method = %method))]
+async fn home(
+    State(state): State<AppState>,
+    method: Method,
+    headers: HeaderMap,
+    principal: Option<Extension<Principal>>,
+) -> Result<Response, Response> {
+    match method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "PROPFIND" => {
+            let mut responses = vec![found(HOME, &home_props())];
+            if !shallow(&headers) {
+                responses.push(found(COLLECTION, &collection_props(&state, principal.as_deref()).await?));
+            }
+            Ok(multistatus(responses))
+        }
+        _ => Ok(method_not_allowed()),
+    }
+}
+
+#[instrument(skip_all, fields(http.request.method = %method))]
+async fn collection(
+    State(state): State<AppState>,
+    method: Method,
+    headers: HeaderMap,
+    principal: Option<Extension<Principal>>,
+    body: Bytes,
+) -> Result<Response, Response> {
+    match method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "PROPFIND" => {
+            let mut responses = vec![found(COLLECTION, &collection_props(&state, principal.as_deref()).await?)];
+            if !shallow(&headers) {
+                let names = state.caldav.all().await.map_err(|e| internal_error(e, "Failed to retrieve todos"))?;
+                for todo in visible_todos(&state, principal.as_deref()).await? {
+                    let name = name_of(&names, &todo);
+                    responses.push(found(&href(&name), &item_props(&todo, &name, false)));
+                }
+            }
+            Ok(multistatus(responses))
+        }
+        "REPORT" => report(&state, principal.as_deref(), &body).await,
+        _ => Ok(method_not_allowed()),
+    }
+}
+
+#[instrument(skip_all, fields(http.request.method = %method, dav.item = %name))]
+async fn item(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    method: Method,
+    headers: HeaderMap,
+    principal: Option<Extension<Principal>>,
+    body: Bytes,
+) -> Result<Response, Response> {
+    let name = name.strip_suffix(".ics").unwrap_or(&name);
+    let principal = principal.as_deref();
+    match method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "GET" | "HEAD" => {
+            let todo = find(&state, principal, name, false).await?.ok_or_else(not_found)?;
+            let headers = [(header::CONTENT_TYPE, CALENDAR_TYPE.to_owned()), (header::ETAG, etag(&todo))];
+            Ok((headers, ical::to_vcalendar(&todo, name)).into_response())
+        }
+        "PROPFIND" => {
+            let todo = find(&state, principal, name, false).await?.ok_or_else(not_found)?;
+            Ok(multistatus(vec![found(&href(name), &item_props(&todo, name, false))]))
+        }
+        "PUT" => put_item(&state, principal, name, &headers, &body).await,
+        "DELETE" => delete_item(&state, principal, name, &headers).await,
+        _ => Ok(method_not_allowed()),
+    }
+}
+
+/// Creates the item, or replaces the title, description, status, due date and tags of the
+/// todo it names. Other properties of the VTODO are dropped.
+async fn put_item(
+    state: &AppState,
+    principal: Option<&Principal>,
+    name: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Response, Response> {
+    let text = std::str::from_utf8(body)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Calendar data must be UTF-8").into_response())?;
+    let vtodo = ical::parse_vtodo(text).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+    if vtodo.summary.trim().is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "SUMMARY must not be empty").into_response());
+    }
+    state
+        .validator
+        .check_text(Some(&vtodo.summary), vtodo.description.as_deref(), "caldav")
+        .map_err(validation_error)?;
+
+    let existing = find(state, principal, name, true).await?;
+    check_preconditions(headers, existing.as_ref()).map_err(IntoResponse::into_response)?;
+    let actor = principal.map(|p| &p.user);
+    let Some(todo) = existing else {
+        return create_item(state, actor, name, vtodo).await;
+    };
+
+    let was_completed = todo.completed;
+    let previous_description = todo.description.clone();
+    let todo = Todo {
+        title: vtodo.summary.trim().to_owned(),
+        description: vtodo.description,
+        completed: vtodo.completed,
+        due_at: vtodo.due,
+        tags: normalize_tags(vtodo.categories),
+        updated_at: state.clock.now(),
+        ..todo
+    };
+    let updated = state
+        .repository
+        .update(todo)
+        .await
+        .map_err(|e| internal_error(e, "Failed to update todo"))?;
+
+    let just_completed = !was_completed && updated.completed;
+    if just_completed {
+        let job = NotificationJob::Completed {
+            todo_id: updated.id,
+            title: updated.title.clone(),
+        };
+        if let Err(e) = state.notifications.enqueue(job).await {
+            warn!(error = %e, "Failed to queue completion notification");
+        }
+    }
+    let change = if just_completed { TodoChange::Completed } else { TodoChange::Updated };
+    state.projects.todo_changed(&updated, change, actor).await;
+    state
+        .mentions
+        .description_saved(&updated, previous_description.as_deref(), actor)
+        .await;
+
+    info!(todo.id = %updated.id, "Todo updated over CalDAV");
+    Ok((StatusCode::NO_CONTENT, [(header::ETAG, etag(&updated))]).into_response())
+}
+
+async fn create_item(
+    state: &AppState,
+    actor: Option<&User>,
+    name: &str,
+    vtodo: VTodo,
+) -> Result<Response, Response> {
+    state.validator.check_custom_fields(&BTreeMap::new(), true).await.map_err(validation_error)?;
+    let now = state.clock.now();
+    let todo = Todo {
+        id: Uuid::new_v4(),
+        title: vtodo.summary.trim().to_owned(),
+        description: vtodo.description,
+        completed: vtodo.completed,
+        due_at: vtodo.due,
+        tags: normalize_tags(vtodo.categories),
+        estimate_minutes: None,
+        expires_at: None,
+        project_id: None,
+        priority: None,
+        pinned: false,
+        custom_fields: BTreeMap::new(),
+        created_at: now,
+        updated_at: now,
+        blocked: false,
+        version: 0,
+        change_seq: 0,
+    };
+    let created = state
+        .repository
+        .create(todo)
+        .await
+        .map_err(|e| internal_error(e, "Failed to create todo"))?;
+    // Without its name the client would see the item vanish and a copy appear under the id
+    if name != created.id.to_string() {
+        state
+            .caldav
+            .name(name, created.id)
+            .await
+            .map_err(|e| internal_error(e, "Failed to record the item's name"))?;
+    }
+
+    let job = NotificationJob::Created {
+        todo_id: created.id,
+        title: created.title.clone(),
+    };
+    if let Err(e) = state.notifications.enqueue(job).await {
+        warn!(error = %e, "Failed to queue notification, continuing anyway");
+    }
+    state.mentions.description_saved(&created, None, actor).await;
+
+    info!(todo.id = %created.id, "Todo created over CalDAV");
+    Ok((StatusCode::CREATED, [(header::ETAG, etag(&created))]).into_response())
+}
+
+async fn delete_item(
+    state: &AppState,
+    principal: Option<&Principal>,
+    name: &str,
+    headers: &HeaderMap,
+) -> Result<Response, Response> {
+    let todo = find(state, principal, name, true).await?.ok_or_else(not_found)?;
+    check_preconditions(headers, Some(&todo)).map_err(IntoResponse::into_response)?;
+    match state.repository.delete(todo.id).await {
+        Ok(()) => {}
+        Err(RepositoryError::NotFound(_)) => return Err(not_found()),
+        Err(e) => return Err(internal_error(e, "Failed to delete todo")),
+    }
+    if todo.project_id.is_some() {
+        state
+            .projects
+            .todo_changed(&todo, TodoChange::Deleted, principal.map(|p| &p.user))
+            .await;
+    }
+    info!(todo.id = %todo.id, "Todo deleted over CalDAV");
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Answers a `calendar-query` with every item, unless it only asks for other components
+/// such as events, and a `calendar-multiget` with the items it names. Filters on times
+/// and properties aren't applied; clients filter what they get back themselves.
+async fn report(state: &AppState, principal: Option<&Principal>, body: &[u8]) -> Result<Response, Response> {
+    let report = parse_report(body).map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid REPORT: {e}")).into_response())?;
+    let names = state.caldav.all().await.map_err(|e| internal_error(e, "Failed to retrieve todos"))?;
+    let todos = visible_todos(state, principal).await?;
+
+    let responses = match report.name.as_str() {
+        "calendar-query" => {
+            let components: Vec<&String> =
+                report.components.iter().filter(|c| !c.eq_ignore_ascii_case("VCALENDAR")).collect();
+            if !components.is_empty() && !components.iter().any(|c| c.eq_ignore_ascii_case("VTODO")) {
+                Vec::new()
+            } else {
+                todos
+                    .iter()
+                    .map(|todo| {
+                        let name = name_of(&names, todo);
+                        found(&href(&name), &item_props(todo, &name, report.data))
+                    })
+                    .collect()
+            }
+        }
+        "calendar-multiget" => {
+            let by_name: HashMap<String, &Todo> = todos.iter().map(|todo| (name_of(&names, todo), todo)).collect();
+            report
+                .hrefs
+                .iter()
+                .map(|requested| {
+                    let segment = requested.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+                    let name = decode(segment);
+                    let name = name.strip_suffix(".ics").unwrap_or(&name);
+                    match by_name.get(name) {
+                        Some(todo) => found(requested, &item_props(todo, name, report.data)),
+                        None => missing(requested),
+                    }
+                })
+                .collect()
+        }
+        _ => return Err((StatusCode::FORBIDDEN, "Only calendar-query and calendar-multiget are supported").into_response()),
+    };
+    Ok(multistatus(responses))
+}
+
+/// The todo named `name` if there is one and the caller may read it (or change it, with
+/// `edit`).
+async fn find(
+    state: &AppState,
+    principal: Option<&Principal>,
+    name: &str,
+    edit: bool,
+) -> Result<Option<Todo>, Response> {
+    let todo = state
+        .caldav
+        .resolve(state.repository.as_ref(), name)
+        .await
+        .map_err(|e| internal_error(e, "Failed to load todo"))?;
+    if let Some(todo) = &todo {
+        check_todo_access(state, principal, todo, edit)
+            .await
+            .map_err(IntoResponse::into_response)?;
+    }
+    Ok(todo)
+}
+
+/// Every todo the caller may see, as `GET /todos` lists them.
+async fn visible_todos(state: &AppState, principal: Option<&Principal>) -> Result<Vec<Todo>, Response> {
+    let visible = match principal {
+        Some(principal) => Some(
+            state
+                .projects
+                .visible(&principal.user)
+                .await
+                .map_err(|e| internal_error(e, "Failed to retrieve todos"))?,
+        ),
+        None => None,
+    };
+    let filter = ListFilter {
+        projects: visible.as_ref(),
+        ..ListFilter::default()
+    };
+    state
+        .repository
+        .list(&filter, None)
+        .await
+        .map(|list| list.todos)
+        .map_err(|e| internal_error(e, "Failed to retrieve todos"))
+}
+
+/// Refuses a write whose `If-Match` doesn't name the item's current ETag, or whose
+/// `If-None-Match: *` finds the item already there, so clients don't overwrite changes
+/// they haven't seen.
+fn check_preconditions(headers: &HeaderMap, existing: Option<&Todo>) -> Result<(), (StatusCode, &'static str)> {
+    let value = |name: HeaderName| headers.get(name).and_then(|value| value.to_str().ok()).map(str::trim);
+    let matches = match (value(header::IF_MATCH), existing) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(tags), Some(todo)) => tags == "*" || tags.split(',').any(|tag| tag.trim() == etag(todo)),
+    };
+    let clashes = existing.is_some() && value(header::IF_NONE_MATCH) == Some("*");
+    if matches && !clashes {
+        Ok(())
+    } else {
+        Err((StatusCode::PRECONDITION_FAILED, "The item changed since it was read"))
+    }
+}
+
+/// The item's ETag, which changes on every write to its todo.
+fn etag(todo: &Todo) -> String {
+    format!("\"{}\"", todo.change_seq)
+}
+
+fn name_of(names: &HashMap<Uuid, String>, todo: &Todo) -> String {
+    names.get(&todo.id).cloned().unwrap_or_else(|| todo.id.to_string())
+}
+
+fn href(name: &str) -> String {
+    format!("{COLLECTION}{}.ics", encode(name))
+}
+
+/// Whether a `PROPFIND` has `Depth: 0`, asking about the resource itself and not its
+/// children. A missing header means infinity.
+fn shallow(headers: &HeaderMap) -> bool {
+    headers.get("depth").is_some_and(|depth| depth.as_bytes() == b"0")
+}
+
+fn home_props() -> String {
+    format!(
+        "<d:resourcetype><d:collection/><d:principal/></d:resourcetype>\
+         <d:displayname>todo-api</d:displayname>\
+         <d:current-user-principal><d:href>{HOME}</d:href></d:current-user-principal>\
+         <d:principal-URL><d:href>{HOME}</d:href></d:principal-URL>\
+         <c:calendar-home-set><d:href>{HOME}</d:href></c:calendar-home-set>"
+    )
+}
+
+/// The collection's properties. Its ctag, which clients poll to tell whether anything
+/// changed, combines the todo count with the latest `change_seq`, so deletes move it too.
+/// Both cover only the todos the caller may see.
+async fn collection_props(state: &AppState, principal: Option<&Principal>) -> Result<String, Response> {
+    let visible = visible_projects(state, principal).await?;
+    let summary = state
+        .repository
+        .summary(visible.as_ref())
+        .await
+        .map_err(|e| internal_error(e, "Failed to summarize todos"))?;
+    let ctag = format!("{}-{}", summary.count, summary.last_change_seq);
+    Ok(format!(
+        "<d:resourcetype><d:collection/><c:calendar/></d:resourcetype>\
+         <d:displayname>Todos</d:displayname>\
+         <c:supported-calendar-component-set><c:comp name=\"VTODO\"/></c:supported-calendar-component-set>\
+         <d:supported-report-set>\
+         <d:supported-report><d:report><c:calendar-query/></d:report></d:supported-report>\
+         <d:supported-report><d:report><c:calendar-multiget/></d:report></d:supported-report>\
+         </d:supported-report-set>\
+         <d:current-user-privilege-set>\
+         <d:privilege><d:read/></d:privilege><d:privilege><d:write/></d:privilege>\
+         <d:privilege><d:bind/></d:privilege><d:privilege><d:unbind/></d:privilege>\
+         </d:current-user-privilege-set>\
+         <d:current-user-principal><d:href>{HOME}</d:href></d:current-user-principal>\
+         <cs:getctag>{ctag}</cs:getctag><d:getetag>\"{ctag}\"</d:getetag>"
+    ))
+}
+
+/// An item's properties, with its calendar data when `data` is set.
+fn item_props(todo: &Todo, name: &str, data: bool) -> String {
+    let mut props = format!(
+        "<d:resourcetype/><d:getcontenttype>{CALENDAR_TYPE}; component=VTODO</d:getcontenttype>\
+         <d:getetag>{}</d:getetag>",
+        escape(&etag(todo))
+    );
+    if data {
+        props.push_str("<c:calendar-data>");
+        props.push_str(&escape(&ical::to_vcalendar(todo, name)));
+        props.push_str("</c:calendar-data>");
+    }
+    props
+}
+
+/// Answers with every response in one `207 Multi-Status`. Each lists the properties this
+/// server has for the resource, whichever the client asked for.
+fn multistatus(responses: Vec<String>) -> Response {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <d:multistatus xmlns:d=\"DAV:\" xmlns:c=\"urn:ietf:params:xml:ns:caldav\" \
+         xmlns:cs=\"http://calendarserver.org/ns/\">{}</d:multistatus>",
+        responses.concat()
+    );
+    (StatusCode::MULTI_STATUS, [(header::CONTENT_TYPE, XML_TYPE)], body).into_response()
+}
+
+fn found(href: &str, props: &str) -> String {
+    format!(
+        "<d:response><d:href>{}</d:href><d:propstat><d:prop>{props}</d:prop>\
+         <d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+        escape(href)
+    )
+}
+
+fn missing(href: &str) -> String {
+    format!(
+        "<d:response><d:href>{}</d:href><d:status>HTTP/1.1 404 Not Found</d:status></d:response>",
+        escape(href)
+    )
+}
+
+fn options() -> Response {
+    let headers = [(HeaderName::from_static("dav"), "1, 3, calendar-access"), (header::ALLOW, ALLOW)];
+    (StatusCode::OK, headers).into_response()
+}
+
+fn method_not_allowed() -> Response {
+    (StatusCode::METHOD_NOT_ALLOWED, [(header::ALLOW, ALLOW)]).into_response()
+}
+
+fn not_found() -> Response {
+    (StatusCode::NOT_FOUND, "Todo not found").into_response()
+}
+
+fn internal_error(e: impl Display, message: &'static str) -> Response {
+    error!(error = %e, "{message}");
+    (StatusCode::INTERNAL_SERVER_ERROR, message).into_response()
+}
+
+/// The parts of a `REPORT` body used here.
+#[derive(Debug, Default)]
+struct ReportRequest {
+    /// Local name of the root element, such as `calendar-multiget`.
+    name: String,
+    /// The items a multiget names.
+    hrefs: Vec<String>,
+    /// Components named by a query's `comp-filter`s, such as `VCALENDAR` and `VTODO`.
+    components: Vec<String>,
+    /// Whether `calendar-data` was asked for, rather than only ETags.
+    data: bool,
+}
+
+/// Reads a `REPORT` body by local names, whatever prefixes the client bound the DAV and
+/// CalDAV namespaces to.
+fn parse_report(body: &[u8]) -> Result<ReportRequest, quick_xml::Error> {
+    let mut reader = Reader::from_reader(body);
+    let mut buf = Vec::new();
+    let mut report = ReportRequest::default();
+    let mut in_href = false;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) if e.local_name().as_ref() == b"href" => in_href = true,
+            Event::Start(e) | Event::Empty(e) => {
+                match e.local_name().as_ref() {
+                    b"calendar-data" => report.data = true,
+                    b"comp-filter" => {
+                        if let Some(name) = e.try_get_attribute("name")? {
+                            report.components.push(name.unescape_value()?.into_owned());
+                        }
+                    }
+                    _ => {}
+                }
+                if report.name.is_empty() {
+                    report.name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                }
+            }
+            Event::Text(text) if in_href => report.hrefs.push(text.unescape()?.trim().to_owned()),
+            Event::End(e) if e.local_name().as_ref() == b"href" => in_href = false,
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(report)
+}
+
+/// Percent-encodes everything in an item name but unreserved characters.
+fn encode(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+fn decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.filter(|_| bytes[i] == b'%').and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,256 @@
+use crate::models::{CompactTodo, HistoryEntry, Todo, TodoStats};
+use crate::repository::{
+    ListFilter, ListWindow, RepositoryError, TodoCursor, TodoList, TodoListSummary, TodoPage, TodoRepository,
+};
+use async_trait::async_trait;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use serde_json::json;
+use std::{collections::HashSet, future::Future, sync::Arc, time::Duration};
+use tokio::time::Instant;
+use tracing::{warn, Span};
+use uuid::Uuid;
+
+/// How long the client is willing to wait, in seconds (`2.5`) or milliseconds (`2500ms`).
+pub const TIMEOUT_HEADER: &str = "x-request-timeout";
+
+tokio::task_local! {
+    /// When the current request must be answered by, set by `enforce_deadline` around the
+    /// handler.
+    static DEADLINE: Instant;
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("the request's deadline passed")]
+pub struct DeadlineExceeded;
+
+/// The current request's deadline, or `None` outside a request or for one without a deadline.
+pub fn current() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Runs `future` until the current request's deadline, if it has one. Running out of time
+/// drops the future, which cancels whatever it was waiting on.
+pub async fn within<F: Future>(future: F) -> Result<F::Output, DeadlineExceeded> {
+    match current() {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.map_err(|_| DeadlineExceeded),
+        None => Ok(future.await),
+    }
+}
+
+/// Gives each request a deadline: the `X-Request-Timeout` it asks for, or the configured
+/// default, whichever is sooner. Requests that run past it are answered `504`.
+pub struct Deadlines {
+    /// `None` leaves requests without the header unbounded.
+    default: Option<Duration>,
+    expired: Counter<u64>,
+}
+
+impl Deadlines {
+    pub fn new(default: Option<Duration>) -> Self {
+        Self {
+            default,
+            expired: global::meter("todo-api")
+                .u64_counter("requests.deadline_exceeded")
+                .with_description("Requests answered 504 because they ran past their deadline")
+                .init(),
+        }
+    }
+}
+
+/// Runs the rest of the stack under the request's deadline. When it passes, the handler's
+/// future is dropped, and with it any repository query or notification it was waiting on,
+/// so nothing keeps working for a client that gave up. A handler that answers `5xx` after
+/// the deadline passed (because a repository call ran out of time) is answered `504` too.
+/// A streamed body, such as `/todos/events`, isn't limited once its headers are sent.
+pub async fn enforce_deadline(State(deadlines): State<Arc<Deadlines>>, req: Request, next: Next) -> Response {
+    let requested = match req.headers().get(TIMEOUT_HEADER).map(|value| parse_timeout(value.as_bytes())) {
+        Some(Ok(timeout)) => Some(timeout),
+        Some(Err(reason)) => return problem(StatusCode::BAD_REQUEST, "Invalid X-Request-Timeout", reason),
+        None => None,
+    };
+    let timeout = match (requested, deadlines.default) {
+        (Some(requested), Some(default)) => requested.min(default),
+        (requested, default) => match requested.or(default) {
+            Some(timeout) => timeout,
+            None => return next.run(req).await,
+        },
+    };
+    Span::current().record("http.request.timeout_ms", timeout.as_millis() as u64);
+
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| req.uri().path().to_owned(), |path| path.as_str().to_owned());
+    let method = req.method().to_string();
+    let deadline = Instant::now() + timeout;
+    match tokio::time::timeout_at(deadline, DEADLINE.scope(deadline, next.run(req))).await {
+        Ok(response) if !(response.status().is_server_error() && Instant::now() >= deadline) => {
+            return response;
+        }
+        _ => {}
+    }
+
+    warn!(
+        http.request.method = %method,
+        http.route = %route,
+        timeout_ms = timeout.as_millis() as u64,
+        "Request deadline exceeded"
+    );
+    deadlines.expired.add(
+        1,
+        &[
+            KeyValue::new("http.route", route),
+            KeyValue::new("http.request.method", method),
+        ],
+    );
+    let detail = format!("The request wasn't answered within {} ms", timeout.as_millis());
+    problem(StatusCode::GATEWAY_TIMEOUT, "Deadline exceeded", detail)
+}
+
+/// An RFC 9457 problem details body.
+fn problem(status: StatusCode, title: &str, detail: impl Into<String>) -> Response {
+    let body = json!({
+        "type": "about:blank",
+        "title": title,
+        "status": status.as_u16(),
+        "detail": detail.into(),
+    });
+    (status, [(header::CONTENT_TYPE, "application/problem+json")], body.to_string()).into_response()
+}
+
+/// Reads `2.5` as seconds and `2500ms` as milliseconds. Zero, negative and absurdly large
+/// timeouts are refused.
+fn parse_timeout(value: &[u8]) -> Result<Duration, &'static str> {
+    let value = std::str::from_utf8(value).map_err(|_| "not text")?.trim();
+    let seconds = match value.strip_suffix("ms") {
+        Some(millis) => millis.trim().parse::<f64>().map(|millis| millis / 1000.0),
+        None => value.strip_suffix('s').unwrap_or(value).trim().parse::<f64>(),
+    }
+    .map_err(|_| "expected seconds, such as 2.5, or milliseconds, such as 2500ms")?;
+    if seconds.is_nan() || seconds <= 0.0 {
+        return Err("must be more than zero");
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| "too large")
+}
+
+/// Decorates a repository so each call gives up when the current request's deadline passes,
+/// answering `RepositoryError::DeadlineExceeded`. Calls made outside a request, such as by
+/// background jobs, run as long as they take.
+pub struct DeadlineRepository {
+    inner: Arc<dyn TodoRepository>,
+}
+
+impl DeadlineRepository {
+    pub fn new(inner: Arc<dyn TodoRepository>) -> Self {
+        Self { inner }
+    }
+
+    async fn call<T>(&self, future: impl Future<Output = Result<T, RepositoryError>>) -> Result<T, RepositoryError> {
+        within(future).await.unwrap_or(Err(RepositoryError::DeadlineExceeded))
+    }
+}
+
+#[async_trait]
+impl TodoRepository for DeadlineRepository {
+    async fn create(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        self.call(self.inner.create(todo)).await
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Todo, RepositoryError> {
+        self.call(self.inner.get(id)).await
+    }
+
+    async fn list(&self, filter: &ListFilter<'_>, window: Option<ListWindow>) -> Result<TodoList, RepositoryError> {
+        self.call(self.inner.list(filter, window)).await
+    }
+
+    async fn list_page(
+        &self,
+        after: Option<TodoCursor>,
+        limit: usize,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<TodoPage, RepositoryError> {
+        self.call(self.inner.list_page(after, limit, projects)).await
+    }
+
+    async fn list_compact(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<CompactTodo>, RepositoryError> {
+        self.call(self.inner.list_compact(projects)).await
+    }
+
+    async fn summary(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoListSummary, RepositoryError> {
+        self.call(self.inner.summary(projects)).await
+    }
+
+    async fn stats(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoStats, RepositoryError> {
+        self.call(self.inner.stats(projects)).await
+    }
+
+    async fn update(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        self.call(self.inner.update(todo)).await
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError> {
+        self.call(self.inner.delete(id)).await
+    }
+
+    async fn snooze(&self, id: Uuid, until: DateTime<Utc>) -> Result<Todo, RepositoryError> {
+        self.call(self.inner.snooze(id, until)).await
+    }
+
+    async fn history(&self, id: Uuid) -> Result<Vec<HistoryEntry>, RepositoryError> {
+        self.call(self.inner.history(id)).await
+    }
+
+    async fn add_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<Todo, RepositoryError> {
+        self.call(self.inner.add_dependency(blocker, blocked)).await
+    }
+
+    async fn remove_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<(), RepositoryError> {
+        self.call(self.inner.remove_dependency(blocker, blocked)).await
+    }
+
+    async fn create_batch(&self, todos: Vec<Todo>) -> Result<Vec<Todo>, RepositoryError> {
+        self.call(self.inner.create_batch(todos)).await
+    }
+
+    async fn import(&self, todos: Vec<Todo>) -> Result<usize, RepositoryError> {
+        self.call(self.inner.import(todos)).await
+    }
+
+    async fn delete_completed(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<Uuid>, RepositoryError> {
+        self.call(self.inner.delete_completed(projects)).await
+    }
+
+    async fn database_version(&self) -> Result<String, RepositoryError> {
+        self.call(self.inner.database_version()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_seconds_and_milliseconds() {
+        assert_eq!(parse_timeout(b"2.5"), Ok(Duration::from_millis(2500)));
+        assert_eq!(parse_timeout(b"2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_timeout(b"2500ms"), Ok(Duration::from_millis(2500)));
+        assert_eq!(parse_timeout(b" 250 ms "), Ok(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn refuses_timeouts_that_cant_be_waited_for() {
+        for value in ["0", "0ms", "-1", "NaN", "nanms", "1e400", "1e300", "inf", "", "soon", "2.5m"] {
+            assert!(parse_timeout(value.as_bytes()).is_err(), "{value}");
+        }
+        assert_eq!(parse_timeout(b"1e300"), Err("too large"));
+        assert!(parse_timeout(&[0xff]).is_err());
+    }
+}
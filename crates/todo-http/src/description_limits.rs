@@ -0,0 +1,71 @@
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use tracing::warn;
+
+/// A description over the hard limit, with what to do instead as the message clients see.
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error(
+    "Description is {size} bytes, over the {limit}-byte limit. Descriptions are for notes about \
+     the todo; upload documents, logs and other large content as attachments instead \
+     (POST /todos/{{id}}/attachments)."
+)]
+pub struct DescriptionTooLarge {
+    pub size: usize,
+    pub limit: usize,
+}
+
+/// Size limits on todo descriptions, so the field isn't used as blob storage. Past the warning
+/// threshold a write still goes through but is logged and counted, which shows operators the
+/// clients heading for the limit; past the hard limit it's refused.
+#[derive(Debug)]
+pub struct DescriptionLimits {
+    warn_bytes: Option<usize>,
+    max_bytes: Option<usize>,
+    near_limit: Counter<u64>,
+    rejected: Counter<u64>,
+}
+
+impl Default for DescriptionLimits {
+    /// No limits, for servers built without a `Config`.
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+impl DescriptionLimits {
+    /// `None` turns a threshold off.
+    pub fn new(warn_bytes: Option<usize>, max_bytes: Option<usize>) -> Self {
+        let meter = global::meter("todo-api");
+        Self {
+            warn_bytes,
+            max_bytes,
+            near_limit: meter
+                .u64_counter("todo.description.near_limit")
+                .with_description("Descriptions written over DESCRIPTION_WARN_BYTES but within the limit")
+                .init(),
+            rejected: meter
+                .u64_counter("todo.description.rejected")
+                .with_description("Writes refused for a description over DESCRIPTION_MAX_BYTES")
+                .init(),
+        }
+    }
+
+    /// Refuses a description over the hard limit and counts one over the warning threshold.
+    /// `source` says where the write came from (`api`, `batch`, `import`, `sync`, `caldav` or
+    /// `mcp`), to tell the clients apart on the metrics.
+    pub fn check(&self, description: Option<&str>, source: &'static str) -> Result<(), DescriptionTooLarge> {
+        let Some(size) = description.map(str::len) else {
+            return Ok(());
+        };
+        let attributes = [KeyValue::new("source", source)];
+        if let Some(limit) = self.max_bytes.filter(|&limit| size > limit) {
+            self.rejected.add(1, &attributes);
+            warn!(description.bytes = size, limit, source, "Description over the limit refused");
+            return Err(DescriptionTooLarge { size, limit });
+        }
+        if let Some(threshold) = self.warn_bytes.filter(|&threshold| size > threshold) {
+            self.near_limit.add(1, &attributes);
+            warn!(description.bytes = size, threshold, source, "Description close to the limit");
+        }
+        Ok(())
+    }
+}
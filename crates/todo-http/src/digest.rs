@@ -0,0 +1,180 @@
+use crate::config::DigestConfig;
+use crate::leases::JobLeases;
+use crate::notification_worker::{NotificationJob, NotificationQueue};
+use crate::repository::SqliteTodoRepository;
+use crate::Todo;
+use chrono::{DateTime, Days, FixedOffset, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use tracing::{error, info, Instrument};
+use uuid::Uuid;
+
+/// Items listed per section; the counts still cover everything.
+const MAX_ITEMS_PER_SECTION: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestItem {
+    pub todo_id: Uuid,
+    pub title: String,
+    pub due_at: DateTime<Utc>,
+}
+
+/// Open, due-today and overdue todos as of the digest's local date.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Digest {
+    pub date: NaiveDate,
+    pub open_count: usize,
+    pub due_today_count: usize,
+    pub overdue_count: usize,
+    pub due_today: Vec<DigestItem>,
+    pub overdue: Vec<DigestItem>,
+}
+
+impl Digest {
+    /// Sorts open todos into overdue (due before `now`) and due later on the local day.
+    pub fn compile(open: Vec<Todo>, now: DateTime<Utc>, utc_offset: FixedOffset) -> Self {
+        let date = now.with_timezone(&utc_offset).date_naive();
+        let end_of_day = local_midnight(date + Days::new(1), utc_offset);
+
+        let mut digest = Self {
+            date,
+            open_count: open.len(),
+            due_today_count: 0,
+            overdue_count: 0,
+            due_today: Vec::new(),
+            overdue: Vec::new(),
+        };
+        for todo in open {
+            let Some(due_at) = todo.due_at else {
+                continue;
+            };
+            let (count, items) = if due_at < now {
+                (&mut digest.overdue_count, &mut digest.overdue)
+            } else if due_at < end_of_day {
+                (&mut digest.due_today_count, &mut digest.due_today)
+            } else {
+                continue;
+            };
+            *count += 1;
+            if items.len() < MAX_ITEMS_PER_SECTION {
+                items.push(DigestItem {
+                    todo_id: todo.id,
+                    title: todo.title,
+                    due_at,
+                });
+            }
+        }
+        digest
+    }
+
+    /// One line, e.g. `5 open, 2 due today, 1 overdue`.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} open, {} due today, {} overdue",
+            self.open_count, self.due_today_count, self.overdue_count
+        )
+    }
+
+    /// Plain-text body shared by the chat and email channels.
+    pub fn text(&self) -> String {
+        let mut text = format!("Todo digest for {}: {}\n", self.date, self.summary());
+        for (heading, count, items) in [
+            ("Overdue", self.overdue_count, &self.overdue),
+            ("Due today", self.due_today_count, &self.due_today),
+        ] {
+            if count == 0 {
+                continue;
+            }
+            let _ = write!(text, "\n{heading}:\n");
+            for item in items {
+                let _ = writeln!(text, "- {} (due {})", item.title, item.due_at.format("%Y-%m-%d %H:%M UTC"));
+            }
+            if count > items.len() {
+                let _ = writeln!(text, "- ...and {} more", count - items.len());
+            }
+        }
+        text
+    }
+}
+
+fn local_midnight(date: NaiveDate, utc_offset: FixedOffset) -> DateTime<Utc> {
+    utc_offset
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0).expect("midnight is a valid time"))
+        .single()
+        .expect("fixed offsets have no ambiguous local times")
+        .with_timezone(&Utc)
+}
+
+/// The first moment after `now` at which the configured local time comes around.
+fn next_run(now: DateTime<Utc>, config: &DigestConfig) -> DateTime<Utc> {
+    let local = now.with_timezone(&config.utc_offset);
+    let today = local.date_naive().and_time(config.time);
+    let candidate = config
+        .utc_offset
+        .from_local_datetime(&today)
+        .single()
+        .expect("fixed offsets have no ambiguous local times")
+        .with_timezone(&Utc);
+    if candidate > now {
+        candidate
+    } else {
+        candidate + Days::new(1)
+    }
+}
+
+async fn send_digest(repository: &SqliteTodoRepository, notifications: &NotificationQueue, config: &DigestConfig) {
+    let open = match repository.list_open().await {
+        Ok(open) => open,
+        Err(e) => {
+            error!(error = %e, "Failed to load todos for the daily digest");
+            return;
+        }
+    };
+    if open.is_empty() {
+        info!("No open todos, skipping daily digest");
+        return;
+    }
+
+    let digest = Digest::compile(open, repository.clock().now(), config.utc_offset);
+    info!(
+        digest.date = %digest.date,
+        digest.open = digest.open_count,
+        digest.due_today = digest.due_today_count,
+        digest.overdue = digest.overdue_count,
+        "Queueing daily digest"
+    );
+    if let Err(e) = notifications.enqueue(NotificationJob::Digest(digest)).await {
+        error!(error = %e, "Failed to queue daily digest");
+    }
+}
+
+/// Spawns the job that sends one digest of open todos per day at the configured local
+/// time, in place of a notification for every change. Abort the returned handle before
+/// draining the notification queue, since the job holds a queue handle.
+///
+/// Every replica wakes at the digest time, and the first to take the `digest` lease sends
+/// it; the lease lasts an hour, well past the others' attempts that day.
+pub fn spawn_digest_job(
+    repository: Arc<SqliteTodoRepository>,
+    notifications: NotificationQueue,
+    leases: Arc<JobLeases>,
+    config: DigestConfig,
+) -> JoinHandle<()> {
+    info!(time = %config.time, utc_offset = %config.utc_offset, "Starting daily digest job");
+
+    tokio::spawn(async move {
+        loop {
+            let now = repository.clock().now();
+            let next = next_run(now, &config);
+            tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+            if !leases.acquire("digest", Duration::from_secs(3600)).await {
+                continue;
+            }
+
+            send_digest(&repository, &notifications, &config)
+                .instrument(tracing::info_span!("daily_digest"))
+                .await;
+        }
+    })
+}
@@ -0,0 +1,579 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::str::FromStr;
+
+/// Asks for `camel` or `snake` field names in this response, overriding `JSON_FIELD_CASE`.
+pub const FIELD_CASE_HEADER: &str = "x-field-case";
+
+/// How the fields of JSON and MessagePack responses are named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldCase {
+    /// `due_at`, as the domain model names them.
+    #[default]
+    Snake,
+    /// `dueAt`, for clients whose platforms expect it.
+    Camel,
+}
+
+impl FromStr for FieldCase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "snake" | "snake_case" => Ok(Self::Snake),
+            "camel" | "camelcase" => Ok(Self::Camel),
+            other => Err(format!("unknown field case: {other}")),
+        }
+    }
+}
+
+tokio::task_local! {
+    /// The current request's field case, set by `negotiate_field_case` around the handler.
+    static FIELD_CASE: FieldCase;
+}
+
+/// The current request's field case; snake case outside a request.
+pub fn current() -> FieldCase {
+    FIELD_CASE.try_with(|case| *case).unwrap_or_default()
+}
+
+/// Runs the rest of the stack with the field case the request's `X-Field-Case` asks for, or
+/// `default` without one. An unknown case is refused with `400`, and every response varies
+/// on the header so caches keep the two shapes apart.
+pub async fn negotiate_field_case(State(default): State<FieldCase>, req: Request, next: Next) -> Response {
+    let case = match req.headers().get(FIELD_CASE_HEADER) {
+        Some(value) => match value.to_str().map_err(|e| e.to_string()).and_then(str::parse) {
+            Ok(case) => case,
+            Err(e) => return (StatusCode::BAD_REQUEST, format!("Invalid X-Field-Case: {e}")).into_response(),
+        },
+        None => default,
+    };
+    let mut response = FIELD_CASE.scope(case, next.run(req)).await;
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static(FIELD_CASE_HEADER));
+    response
+}
+
+/// A response body with a camelCase shape, from its DTO in `v1`.
+pub trait CamelCase: Serialize {
+    fn to_camel_case(&self) -> serde_json::Result<Value>;
+}
+
+/// Version 1 of the camelCase shapes, borrowing from the domain model. Clients rely on
+/// these names, so a shape that has to change gets a new version alongside this one.
+pub mod v1 {
+    use super::CamelCase;
+    use crate::models;
+    use crate::quick_add;
+    use todo_domain::inbox::{self, ScoreFactors};
+    use chrono::{DateTime, Utc};
+    use serde::{ser::{Error as _, SerializeMap}, Serialize, Serializer};
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    /// A todo's `TodoDto` with its field names in camelCase, so the fields themselves are
+    /// listed once. Custom field names are the caller's own and aren't renamed.
+    pub struct Todo<'a>(pub models::TodoDto<'a>);
+
+    impl<'a> From<&'a models::Todo> for Todo<'a> {
+        fn from(todo: &'a models::Todo) -> Self {
+            Self(models::TodoDto::from(todo))
+        }
+    }
+
+    impl Serialize for Todo<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let Value::Object(fields) = serde_json::to_value(&self.0).map_err(S::Error::custom)? else {
+                return Err(S::Error::custom("a todo serializes as an object"));
+            };
+            let mut map = serializer.serialize_map(Some(fields.len()))?;
+            for (name, value) in &fields {
+                map.serialize_entry(&camel_case(name), value)?;
+            }
+            map.end()
+        }
+    }
+
+    /// `change_seq` to `changeSeq`, as `#[serde(rename_all = "camelCase")]` would name it.
+    fn camel_case(name: &str) -> String {
+        let mut words = name.split('_');
+        let mut camel = words.next().unwrap_or_default().to_string();
+        for word in words {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                camel.extend(first.to_uppercase());
+                camel.push_str(chars.as_str());
+            }
+        }
+        camel
+    }
+
+    impl CamelCase for models::Todo {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(Todo::from(self))
+        }
+    }
+
+    /// `factors` keeps its names, which are single words.
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RankedTodo<'a> {
+        pub score: f64,
+        pub factors: &'a ScoreFactors,
+        pub todo: Todo<'a>,
+    }
+
+    impl CamelCase for inbox::RankedTodo {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(RankedTodo {
+                score: self.score,
+                factors: &self.factors,
+                todo: Todo::from(&self.todo),
+            })
+        }
+    }
+
+    /// The project keeps its names, which are single words.
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct ExpandedTodo<'a> {
+        #[serde(flatten)]
+        pub todo: Todo<'a>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub comments: Option<Vec<Comment<'a>>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub project: Option<Option<&'a models::ProjectRef>>,
+    }
+
+    impl CamelCase for models::ExpandedTodo {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(ExpandedTodo {
+                todo: Todo::from(&self.todo),
+                comments: self.comments.as_ref().map(|comments| comments.iter().map(Comment::from).collect()),
+                project: self.project.as_ref().map(Option::as_ref),
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct CompactTodo<'a> {
+        pub id: Uuid,
+        pub title: &'a str,
+        pub completed: bool,
+        pub due_at: Option<DateTime<Utc>>,
+    }
+
+    impl CamelCase for models::CompactTodo {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(CompactTodo {
+                id: self.id,
+                title: &self.title,
+                completed: self.completed,
+                due_at: self.due_at,
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct HistoryEntry<'a> {
+        pub event: &'a str,
+        pub detail: &'a Value,
+        pub created_at: DateTime<Utc>,
+    }
+
+    impl CamelCase for models::HistoryEntry {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(HistoryEntry {
+                event: &self.event,
+                detail: &self.detail,
+                created_at: self.created_at,
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct TagRollup<'a> {
+        pub tag: &'a str,
+        pub open_count: u64,
+        pub open_estimate_minutes: u64,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct TodoStats<'a> {
+        pub open_count: u64,
+        pub completed_count: u64,
+        pub open_estimate_minutes: u64,
+        pub unestimated_open_count: u64,
+        pub by_tag: Vec<TagRollup<'a>>,
+    }
+
+    impl CamelCase for models::TodoStats {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(TodoStats {
+                open_count: self.open_count,
+                completed_count: self.completed_count,
+                open_estimate_minutes: self.open_estimate_minutes,
+                unestimated_open_count: self.unestimated_open_count,
+                by_tag: self
+                    .by_tag
+                    .iter()
+                    .map(|rollup| TagRollup {
+                        tag: &rollup.tag,
+                        open_count: rollup.open_count,
+                        open_estimate_minutes: rollup.open_estimate_minutes,
+                    })
+                    .collect(),
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct BatchCreateResponse<'a> {
+        pub atomic: bool,
+        pub dry_run: bool,
+        pub created: Vec<Todo<'a>>,
+        pub total: usize,
+        /// `{index, error}`, which camelCase leaves as they are.
+        pub errors: &'a [models::BatchItemError],
+    }
+
+    impl CamelCase for models::BatchCreateResponse {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(BatchCreateResponse {
+                atomic: self.atomic,
+                dry_run: self.dry_run,
+                created: self.created.iter().map(Todo::from).collect(),
+                total: self.total,
+                errors: &self.errors,
+            })
+        }
+    }
+
+    /// `todos` are plain or expanded, each already in its camelCase shape.
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct TodoListPage {
+        pub todos: Vec<Value>,
+        pub page: usize,
+        pub per_page: usize,
+        pub total: u64,
+        pub total_pages: u64,
+    }
+
+    impl<T: CamelCase> CamelCase for models::TodoListPage<T> {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(TodoListPage {
+                todos: self.todos.iter().map(CamelCase::to_camel_case).collect::<Result<_, _>>()?,
+                page: self.page,
+                per_page: self.per_page,
+                total: self.total,
+                total_pages: self.total_pages,
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct DeleteCompletedResponse<'a> {
+        pub deleted_count: usize,
+        pub deleted_ids: &'a [Uuid],
+    }
+
+    impl CamelCase for models::DeleteCompletedResponse {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(DeleteCompletedResponse {
+                deleted_count: self.deleted_count,
+                deleted_ids: &self.deleted_ids,
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct BulkTagResponse<'a> {
+        pub updated_count: usize,
+        pub updated_ids: &'a [Uuid],
+    }
+
+    impl CamelCase for models::BulkTagResponse {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(BulkTagResponse {
+                updated_count: self.updated_count,
+                updated_ids: &self.updated_ids,
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct DeletedTodo {
+        pub id: Uuid,
+        pub deleted_at: DateTime<Utc>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct SyncChanges<'a> {
+        pub created: Vec<Todo<'a>>,
+        pub updated: Vec<Todo<'a>>,
+        pub deleted: Vec<DeletedTodo>,
+        pub token: &'a str,
+        pub has_more: bool,
+    }
+
+    impl CamelCase for models::SyncChanges {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(SyncChanges {
+                created: self.created.iter().map(Todo::from).collect(),
+                updated: self.updated.iter().map(Todo::from).collect(),
+                deleted: self
+                    .deleted
+                    .iter()
+                    .map(|deleted| DeletedTodo {
+                        id: deleted.id,
+                        deleted_at: deleted.deleted_at,
+                    })
+                    .collect(),
+                token: &self.token,
+                has_more: self.has_more,
+            })
+        }
+    }
+
+    /// `client` is the edit as it was sent, in the request's own snake_case.
+    #[derive(Serialize)]
+    #[serde(tag = "status", rename_all = "snake_case")]
+    pub enum SyncMutationResult<'a> {
+        Applied {
+            id: Uuid,
+            todo: Option<Todo<'a>>,
+        },
+        Conflict {
+            id: Uuid,
+            reason: models::ConflictReason,
+            client: &'a models::SyncMutation,
+            server: Option<Todo<'a>>,
+        },
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct SyncPushResponse<'a> {
+        pub results: Vec<SyncMutationResult<'a>>,
+        pub applied: usize,
+        pub conflicts: usize,
+    }
+
+    impl CamelCase for models::SyncPushResponse {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(SyncPushResponse {
+                results: self
+                    .results
+                    .iter()
+                    .map(|result| match result {
+                        models::SyncMutationResult::Applied { id, todo } => SyncMutationResult::Applied {
+                            id: *id,
+                            todo: todo.as_ref().map(Todo::from),
+                        },
+                        models::SyncMutationResult::Conflict { id, reason, client, server } => {
+                            SyncMutationResult::Conflict {
+                                id: *id,
+                                reason: *reason,
+                                client,
+                                server: server.as_ref().map(Todo::from),
+                            }
+                        }
+                    })
+                    .collect(),
+                applied: self.applied,
+                conflicts: self.conflicts,
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Comment<'a> {
+        pub id: Uuid,
+        pub todo_id: Uuid,
+        pub author_id: Option<Uuid>,
+        pub body: &'a str,
+        pub created_at: DateTime<Utc>,
+    }
+
+    impl<'a> From<&'a models::Comment> for Comment<'a> {
+        fn from(comment: &'a models::Comment) -> Self {
+            Self {
+                id: comment.id,
+                todo_id: comment.todo_id,
+                author_id: comment.author_id,
+                body: &comment.body,
+                created_at: comment.created_at,
+            }
+        }
+    }
+
+    impl CamelCase for models::Comment {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(Comment::from(self))
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Attachment<'a> {
+        pub id: Uuid,
+        pub todo_id: Uuid,
+        pub filename: &'a str,
+        pub content_type: &'a str,
+        pub size_bytes: u64,
+        pub created_at: DateTime<Utc>,
+        pub uploaded_by: Option<Uuid>,
+    }
+
+    impl CamelCase for models::Attachment {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(Attachment {
+                id: self.id,
+                todo_id: self.todo_id,
+                filename: &self.filename,
+                content_type: &self.content_type,
+                size_bytes: self.size_bytes,
+                created_at: self.created_at,
+                uploaded_by: self.uploaded_by,
+            })
+        }
+    }
+
+    /// `points` keep their names, which are single words.
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Velocity<'a> {
+        pub period: models::VelocityPeriod,
+        pub points: &'a [models::VelocityPoint],
+    }
+
+    impl CamelCase for models::Velocity {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(Velocity {
+                period: self.period,
+                points: &self.points,
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct AgingBucket {
+        pub min_days: u32,
+        pub max_days: Option<u32>,
+        pub count: u64,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct TodoAging {
+        pub open_count: u64,
+        pub buckets: Vec<AgingBucket>,
+    }
+
+    impl CamelCase for models::TodoAging {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(TodoAging {
+                open_count: self.open_count,
+                buckets: self
+                    .buckets
+                    .iter()
+                    .map(|bucket| AgingBucket {
+                        min_days: bucket.min_days,
+                        max_days: bucket.max_days,
+                        count: bucket.count,
+                    })
+                    .collect(),
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct CompletionTime {
+        pub window_days: u32,
+        pub count: u64,
+        pub mean_seconds: Option<f64>,
+        pub p50_seconds: Option<f64>,
+        pub p75_seconds: Option<f64>,
+        pub p90_seconds: Option<f64>,
+        pub p95_seconds: Option<f64>,
+        pub max_seconds: Option<f64>,
+    }
+
+    impl CamelCase for models::CompletionTime {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(CompletionTime {
+                window_days: self.window_days,
+                count: self.count,
+                mean_seconds: self.mean_seconds,
+                p50_seconds: self.p50_seconds,
+                p75_seconds: self.p75_seconds,
+                p90_seconds: self.p90_seconds,
+                p95_seconds: self.p95_seconds,
+                max_seconds: self.max_seconds,
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Recurrence {
+        pub frequency: quick_add::Frequency,
+        pub interval: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub weekday: Option<chrono::Weekday>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub day_of_month: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub month: Option<u32>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct ParsedTodo<'a> {
+        pub title: &'a str,
+        pub due_at: Option<DateTime<Utc>>,
+        pub tags: &'a [String],
+        pub priority: Option<models::Priority>,
+        pub recurrence: Option<Recurrence>,
+        pub recognized: &'a [String],
+    }
+
+    impl CamelCase for quick_add::ParsedTodo {
+        fn to_camel_case(&self) -> serde_json::Result<Value> {
+            serde_json::to_value(ParsedTodo {
+                title: &self.title,
+                due_at: self.due_at,
+                tags: &self.tags,
+                priority: self.priority,
+                recurrence: self.recurrence.as_ref().map(|recurrence| Recurrence {
+                    frequency: recurrence.frequency,
+                    interval: recurrence.interval,
+                    weekday: recurrence.weekday,
+                    day_of_month: recurrence.day_of_month,
+                    month: recurrence.month,
+                }),
+                recognized: &self.recognized,
+            })
+        }
+    }
+}
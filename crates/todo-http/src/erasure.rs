@@ -0,0 +1,157 @@
+use crate::audit::{AuditEvent, AuditLog, ClientInfo};
+use crate::models::{ErasureJob, ErasureStatus, User};
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use crate::user_export::UserExporter;
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{error, info, instrument, Instrument};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    DeleteAccount,
+    AnonymizeAuditLog,
+    DeleteExports,
+}
+
+impl Step {
+    fn name(self) -> &'static str {
+        match self {
+            Self::DeleteAccount => "delete_account",
+            Self::AnonymizeAuditLog => "anonymize_audit_log",
+            Self::DeleteExports => "delete_exports",
+        }
+    }
+}
+
+/// What an erasure does, in order. The account goes first so it stops working at once;
+/// every step can safely run again if the server stops part way through.
+const STEPS: [Step; 3] = [Step::DeleteAccount, Step::AnonymizeAuditLog, Step::DeleteExports];
+
+/// An erasure job as reported by `GET /users/erasures/:id`.
+#[derive(Debug, Serialize)]
+pub struct ErasureProgress {
+    #[serde(flatten)]
+    pub job: ErasureJob,
+    pub total_steps: u32,
+    /// The step running now or, for a failed job, the one that failed.
+    pub current_step: Option<&'static str>,
+}
+
+impl From<ErasureJob> for ErasureProgress {
+    fn from(job: ErasureJob) -> Self {
+        let current_step = match job.status {
+            ErasureStatus::Completed => None,
+            _ => STEPS.get(job.steps_completed as usize).map(|step| step.name()),
+        };
+        Self {
+            job,
+            total_steps: STEPS.len() as u32,
+            current_step,
+        }
+    }
+}
+
+/// Carries out `DELETE /users/me` requests in the background. Jobs are stored, so ones
+/// interrupted by a restart (or that failed) are picked up again by `resume`.
+pub struct ErasureService {
+    repository: Arc<SqliteTodoRepository>,
+    exporter: Arc<UserExporter>,
+    audit: AuditLog,
+}
+
+impl ErasureService {
+    pub fn new(repository: Arc<SqliteTodoRepository>, exporter: Arc<UserExporter>) -> Self {
+        Self {
+            audit: AuditLog::new(repository.clone()),
+            repository,
+            exporter,
+        }
+    }
+
+    #[instrument(skip_all, fields(user.id = %user.id, erasure.id))]
+    pub async fn start(self: &Arc<Self>, user: &User) -> Result<ErasureProgress, RepositoryError> {
+        let id = Uuid::new_v4();
+        tracing::Span::current().record("erasure.id", tracing::field::display(id));
+        let job = self.repository.create_erasure_job(id, user).await?;
+        info!("Account erasure requested");
+        self.spawn(job.clone(), user.id, user.email.clone());
+        Ok(job.into())
+    }
+
+    pub async fn progress(&self, id: Uuid) -> Result<Option<ErasureProgress>, RepositoryError> {
+        Ok(self.repository.erasure_job(id).await?.map(ErasureProgress::from))
+    }
+
+    /// Restarts every job that hasn't completed.
+    pub async fn resume(self: &Arc<Self>) -> Result<(), RepositoryError> {
+        for (job, user_id, email) in self.repository.unfinished_erasure_jobs().await? {
+            info!(erasure.id = %job.id, steps_completed = job.steps_completed, "Resuming account erasure");
+            self.spawn(job, user_id, email);
+        }
+        Ok(())
+    }
+
+    fn spawn(self: &Arc<Self>, job: ErasureJob, user_id: Uuid, email: String) {
+        let service = self.clone();
+        let span = tracing::info_span!("account_erasure", erasure.id = %job.id, user.id = %user_id);
+        tokio::spawn(async move { service.run(job, user_id, &email).await }.instrument(span));
+    }
+
+    async fn run(&self, job: ErasureJob, user_id: Uuid, email: &str) {
+        let mut steps_completed = job.steps_completed;
+        while let Some(&step) = STEPS.get(steps_completed as usize) {
+            let result = self
+                .repository
+                .update_erasure_job(job.id, ErasureStatus::Running, steps_completed, None)
+                .await
+                .map_err(|e| e.to_string());
+            let result = match result {
+                Ok(()) => self.run_step(step, user_id, email).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!(error = %e, step = step.name(), "Account erasure failed");
+                let _ = self
+                    .repository
+                    .update_erasure_job(job.id, ErasureStatus::Failed, steps_completed, Some(&e))
+                    .await;
+                return;
+            }
+            steps_completed += 1;
+        }
+
+        if let Err(e) = self
+            .repository
+            .update_erasure_job(job.id, ErasureStatus::Completed, steps_completed, None)
+            .await
+        {
+            error!(error = %e, "Failed to mark account erasure completed");
+            return;
+        }
+        self.audit
+            .record(
+                &ClientInfo::default(),
+                AuditEvent::AccountErased,
+                None,
+                None,
+                json!({"erasure_id": job.id}),
+            )
+            .await;
+        info!("Account erased");
+    }
+
+    async fn run_step(&self, step: Step, user_id: Uuid, email: &str) -> Result<(), String> {
+        match step {
+            Step::DeleteAccount => self.repository.delete_user(user_id).await.map_err(|e| e.to_string()),
+            Step::AnonymizeAuditLog => self
+                .repository
+                .anonymize_audit(user_id, email)
+                .await
+                .map(|count| info!(count, "Audit entries anonymized"))
+                .map_err(|e| e.to_string()),
+            Step::DeleteExports => self.exporter.remove_exports(user_id).await.map_err(|e| e.to_string()),
+        }
+    }
+}
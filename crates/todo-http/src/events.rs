@@ -0,0 +1,337 @@
+use crate::cloud_events::CloudEvent;
+use crate::tenancy;
+use crate::models::{CompactTodo, HistoryEntry, Todo, TodoStats};
+use crate::repository::{
+    ListFilter, ListWindow, RepositoryError, TodoCursor, TodoList, TodoListSummary, TodoPage, TodoRepository,
+};
+use async_trait::async_trait;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{collections::HashSet, convert::Infallible, sync::Arc};
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Events a subscriber may fall behind by before it is told to reload instead.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// `source` of the CloudEvents sent on the stream.
+const EVENT_SOURCE: &str = "/todos/events";
+
+/// A change to the todo list, as sent on `GET /todos/events`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TodoEvent {
+    Created { todo: Todo },
+    Updated { todo: Todo },
+    Deleted { id: Uuid },
+    /// Several todos changed at once (or the subscriber missed events); reload the list.
+    Resync,
+}
+
+impl TodoEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Created { .. } => "created",
+            Self::Updated { .. } => "updated",
+            Self::Deleted { .. } => "deleted",
+            Self::Resync => "resync",
+        }
+    }
+
+    /// Whether a subscriber who may see the todos of `visible` projects is sent the event.
+    fn visible_to(&self, visible: Option<&HashSet<Uuid>>) -> bool {
+        match (self, visible) {
+            (Self::Created { todo } | Self::Updated { todo }, Some(visible)) => {
+                todo.project_id.is_none_or(|id| visible.contains(&id))
+            }
+            _ => true,
+        }
+    }
+
+    /// The event as a CloudEvent of type `todo.<name>`, with the todo as its subject.
+    pub fn to_cloud_event(&self, id: Uuid, time: DateTime<Utc>) -> CloudEvent {
+        let kind = format!("todo.{}", self.name());
+        match self {
+            Self::Created { todo } | Self::Updated { todo } => {
+                CloudEvent::with_id(id, time, EVENT_SOURCE, kind, json!({ "todo": todo })).subject(todo.id)
+            }
+            Self::Deleted { id: todo_id } => {
+                CloudEvent::with_id(id, time, EVENT_SOURCE, kind, json!({ "id": todo_id })).subject(todo_id)
+            }
+            Self::Resync => CloudEvent::with_id(id, time, EVENT_SOURCE, kind, json!({})),
+        }
+    }
+}
+
+/// How `GET /todos/events` wraps each event's data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventEnvelope {
+    /// The `TodoEvent` itself.
+    #[default]
+    Plain,
+    /// A CloudEvent carrying the event, whose id is also the SSE event id.
+    CloudEvents,
+}
+
+/// `GET /todos/events?envelope=cloudevents`.
+#[derive(Debug, Deserialize)]
+pub struct EventStreamQuery {
+    #[serde(default)]
+    pub envelope: EventEnvelope,
+}
+
+/// An event with the id and time it was given when published, so every subscriber sees the
+/// same CloudEvent for it.
+#[derive(Debug, Clone)]
+struct Published {
+    id: Uuid,
+    time: DateTime<Utc>,
+    event: TodoEvent,
+    /// The tenant whose database was written, `None` for the shared one.
+    tenant: Option<String>,
+}
+
+impl Published {
+    fn new(event: TodoEvent, tenant: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            time: Utc::now(),
+            event,
+            tenant,
+        }
+    }
+
+    fn to_sse(&self, envelope: EventEnvelope) -> Event {
+        let sse = Event::default().event(self.event.name());
+        let sse = match envelope {
+            EventEnvelope::Plain => sse.json_data(&self.event),
+            EventEnvelope::CloudEvents => sse
+                .id(self.id.to_string())
+                .json_data(self.event.to_cloud_event(self.id, self.time)),
+        };
+        sse.unwrap_or_else(|_| Event::default().event("resync"))
+    }
+}
+
+/// Fans todo changes out to every open event stream. Nothing is stored: a client that
+/// connects late starts from `GET /todos`.
+#[derive(Clone)]
+pub struct TodoEvents {
+    sender: broadcast::Sender<Published>,
+    shutdown: CancellationToken,
+}
+
+impl Default for TodoEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TodoEvents {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    pub fn publish(&self, event: TodoEvent) {
+        // An error only means nobody is listening
+        let _ = self.sender.send(Published::new(event, tenancy::current_tenant()));
+    }
+
+    /// Hears about every write from now on, tenants' included.
+    pub fn listen(&self) -> ChangeListener {
+        ChangeListener {
+            receiver: self.sender.subscribe(),
+        }
+    }
+
+    /// Ends every open stream, so graceful shutdown isn't held up by idle subscribers.
+    pub fn close(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// The stream for one subscriber. With `visible`, changes to todos of any project not
+    /// among them are left out; deletions, which carry only the id, still go out.
+    pub fn sse(
+        &self,
+        envelope: EventEnvelope,
+        visible: Option<HashSet<Uuid>>,
+    ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        info!(subscribers = self.sender.receiver_count() + 1, ?envelope, "Todo event stream opened");
+        let events = stream::unfold((self.sender.subscribe(), visible), move |(mut receiver, visible)| async move {
+            loop {
+                let published = match receiver.recv().await {
+                    Ok(published) => published,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!(missed, "Todo event subscriber fell behind");
+                        Published::new(TodoEvent::Resync, None)
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                // Subscribers aren't per tenant, so a tenant's changes must not reach them
+                if published.tenant.is_none() && published.event.visible_to(visible.as_ref()) {
+                    return Some((Ok(published.to_sse(envelope)), (receiver, visible)));
+                }
+            }
+        });
+        let shutdown = self.shutdown.clone().cancelled_owned();
+        Sse::new(events.take_until(shutdown)).keep_alive(KeepAlive::default())
+    }
+}
+
+/// A write, as heard by a `ChangeListener`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// Todos in the shared database (`None`) or a tenant's changed.
+    Database(Option<String>),
+    /// Anything may have changed: a restore, or events the listener fell behind on.
+    Unknown,
+}
+
+/// Reports writes as they are published, for caches that must forget what they changed.
+pub struct ChangeListener {
+    receiver: broadcast::Receiver<Published>,
+}
+
+impl ChangeListener {
+    /// The next change already published, without waiting; `None` once caught up.
+    pub fn try_next(&mut self) -> Option<Change> {
+        match self.receiver.try_recv() {
+            Ok(Published { event: TodoEvent::Resync, .. }) => Some(Change::Unknown),
+            Ok(published) => Some(Change::Database(published.tenant)),
+            Err(TryRecvError::Lagged(_)) => Some(Change::Unknown),
+            Err(TryRecvError::Empty | TryRecvError::Closed) => None,
+        }
+    }
+}
+
+/// Publishes a `TodoEvent` for every successful write through the wrapped repository.
+pub struct PublishingRepository {
+    inner: Arc<dyn TodoRepository>,
+    events: TodoEvents,
+}
+
+impl PublishingRepository {
+    pub fn new(inner: Arc<dyn TodoRepository>, events: TodoEvents) -> Self {
+        Self { inner, events }
+    }
+
+    fn publish<T>(&self, result: &Result<T, RepositoryError>, event: impl FnOnce(&T) -> TodoEvent) {
+        if let Ok(value) = result {
+            self.events.publish(event(value));
+        }
+    }
+}
+
+#[async_trait]
+impl TodoRepository for PublishingRepository {
+    async fn create(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        let result = self.inner.create(todo).await;
+        self.publish(&result, |todo| TodoEvent::Created { todo: todo.clone() });
+        result
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Todo, RepositoryError> {
+        self.inner.get(id).await
+    }
+
+    async fn list(&self, filter: &ListFilter<'_>, window: Option<ListWindow>) -> Result<TodoList, RepositoryError> {
+        self.inner.list(filter, window).await
+    }
+
+    async fn list_page(
+        &self,
+        after: Option<TodoCursor>,
+        limit: usize,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<TodoPage, RepositoryError> {
+        self.inner.list_page(after, limit, projects).await
+    }
+
+    async fn list_compact(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<CompactTodo>, RepositoryError> {
+        self.inner.list_compact(projects).await
+    }
+
+    async fn summary(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoListSummary, RepositoryError> {
+        self.inner.summary(projects).await
+    }
+
+    async fn stats(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoStats, RepositoryError> {
+        self.inner.stats(projects).await
+    }
+
+    async fn update(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        let result = self.inner.update(todo).await;
+        self.publish(&result, |todo| TodoEvent::Updated { todo: todo.clone() });
+        result
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError> {
+        let result = self.inner.delete(id).await;
+        self.publish(&result, |_| TodoEvent::Deleted { id });
+        result
+    }
+
+    async fn snooze(&self, id: Uuid, until: DateTime<Utc>) -> Result<Todo, RepositoryError> {
+        let result = self.inner.snooze(id, until).await;
+        self.publish(&result, |todo| TodoEvent::Updated { todo: todo.clone() });
+        result
+    }
+
+    async fn history(&self, id: Uuid) -> Result<Vec<HistoryEntry>, RepositoryError> {
+        self.inner.history(id).await
+    }
+
+    async fn add_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<Todo, RepositoryError> {
+        let result = self.inner.add_dependency(blocker, blocked).await;
+        self.publish(&result, |todo| TodoEvent::Updated { todo: todo.clone() });
+        result
+    }
+
+    async fn remove_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<(), RepositoryError> {
+        let result = self.inner.remove_dependency(blocker, blocked).await;
+        self.publish(&result, |_| TodoEvent::Resync);
+        result
+    }
+
+    async fn create_batch(&self, todos: Vec<Todo>) -> Result<Vec<Todo>, RepositoryError> {
+        let result = self.inner.create_batch(todos).await;
+        if let Ok(created) = &result {
+            for todo in created {
+                self.events.publish(TodoEvent::Created { todo: todo.clone() });
+            }
+        }
+        result
+    }
+
+    async fn import(&self, todos: Vec<Todo>) -> Result<usize, RepositoryError> {
+        let result = self.inner.import(todos).await;
+        self.publish(&result, |_| TodoEvent::Resync);
+        result
+    }
+
+    async fn delete_completed(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<Uuid>, RepositoryError> {
+        let result = self.inner.delete_completed(projects).await;
+        if let Ok(deleted) = &result {
+            for &id in deleted {
+                self.events.publish(TodoEvent::Deleted { id });
+            }
+        }
+        result
+    }
+
+    async fn database_version(&self) -> Result<String, RepositoryError> {
+        self.inner.database_version().await
+    }
+}
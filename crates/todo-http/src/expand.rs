@@ -0,0 +1,69 @@
+use crate::mentions::MentionService;
+use crate::models::{Comment, ExpandedTodo, ProjectRef, Todo};
+use crate::projects::ProjectService;
+use crate::repository::RepositoryError;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+/// The related resources `?expand=` embeds in each todo of a `GET /todos` or
+/// `GET /todos/:id` response, e.g. `?expand=comments,project`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Expand {
+    pub comments: bool,
+    pub project: bool,
+}
+
+impl FromStr for Expand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut expand = Self::default();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name {
+                "comments" => expand.comments = true,
+                "project" => expand.project = true,
+                other => return Err(format!("Can't expand {other}; expected comments or project")),
+            }
+        }
+        Ok(expand)
+    }
+}
+
+impl Expand {
+    /// Embeds what was asked for in `todos`. Each kind of resource is read for all of them
+    /// at once, so a page costs the same two queries however many todos it holds.
+    pub async fn apply(
+        self,
+        todos: Vec<Todo>,
+        mentions: &MentionService,
+        projects: &ProjectService,
+    ) -> Result<Vec<ExpandedTodo>, RepositoryError> {
+        let mut comments: HashMap<_, Vec<Comment>> = HashMap::new();
+        if self.comments {
+            let ids: HashSet<_> = todos.iter().map(|todo| todo.id).collect();
+            for comment in mentions.comments_for(&ids).await? {
+                comments.entry(comment.todo_id).or_default().push(comment);
+            }
+        }
+        let names = if self.project {
+            let ids: HashSet<_> = todos.iter().filter_map(|todo| todo.project_id).collect();
+            projects.names(&ids).await?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(todos
+            .into_iter()
+            .map(|todo| ExpandedTodo {
+                comments: self.comments.then(|| comments.remove(&todo.id).unwrap_or_default()),
+                project: self.project.then(|| {
+                    let id = todo.project_id?;
+                    names.get(&id).map(|name| ProjectRef { id, name: name.clone() })
+                }),
+                todo,
+            })
+            .collect())
+    }
+}
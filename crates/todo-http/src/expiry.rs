@@ -0,0 +1,49 @@
+use crate::attachments::AttachmentService;
+use crate::leases::JobLeases;
+use crate::repository::SqliteTodoRepository;
+use opentelemetry::global;
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use tracing::{error, info, Instrument};
+
+/// Spawns the background job that deletes todos once their `expires_at` has passed.
+/// Lists already hide expired todos, so the sweep only has to catch up eventually. The
+/// attachments of todos deleted in any way are removed on the same schedule, unless
+/// `attachments` is `None` because a retention rule keeps them longer. Only the replica
+/// holding the `expiry` lease sweeps.
+pub fn spawn_expiry_job(
+    repository: Arc<SqliteTodoRepository>,
+    attachments: Option<Arc<AttachmentService>>,
+    leases: Arc<JobLeases>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    let expired_todos = global::meter("todo-api")
+        .u64_counter("todos.expired")
+        .with_description("Todos deleted by the expiry sweep")
+        .init();
+
+    info!(interval_secs = interval.as_secs(), "Starting todo expiry job");
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+            if !leases.acquire("expiry", interval * 2).await {
+                continue;
+            }
+
+            let span = tracing::debug_span!("expiry_sweep");
+            match repository.delete_expired(repository.clock().now()).instrument(span.clone()).await {
+                Ok(deleted) => expired_todos.add(deleted, &[]),
+                Err(e) => error!(error = %e, "Expiry sweep failed"),
+            }
+            let Some(attachments) = &attachments else {
+                continue;
+            };
+            if let Err(e) = attachments.remove_orphans(None).instrument(span).await {
+                error!(error = %e, "Failed to remove attachments of deleted todos");
+            }
+        }
+    })
+}
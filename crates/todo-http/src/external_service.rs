@@ -0,0 +1,405 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use opentelemetry::propagation::{Injector, TextMapPropagator};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    StatusCode,
+};
+use tracing::{debug, info, instrument, warn, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+use std::{sync::Arc, time::Duration};
+use crate::config::WebhookConfig;
+use crate::digest::Digest;
+use crate::latency::LatencyProfile;
+use crate::models::{WebhookEvent, WebhookFormat};
+use crate::notification_worker::MemberNotice;
+use crate::redact;
+use crate::repository::{RepositoryError, SqliteTodoRepository, TodoRepository};
+use crate::span_errors::{self, SpanError};
+use crate::webhooks;
+use futures::future::join_all;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServiceError {
+    #[error("Notification service error: {0}")]
+    NotificationFailed(String),
+
+    #[error("External API timeout")]
+    Timeout,
+
+    #[error("Rate limited")]
+    RateLimited,
+}
+
+impl SpanError for ServiceError {}
+
+#[async_trait]
+pub trait NotificationService: Send + Sync {
+    async fn send_created_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError>;
+    async fn send_completed_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError>;
+    async fn send_batch_summary(&self, count: usize) -> Result<(), ServiceError>;
+    async fn send_digest(&self, digest: &Digest) -> Result<(), ServiceError>;
+
+    /// Channels that only post to a shared team space have no one to address it to, and
+    /// skip it.
+    async fn send_member_notice(&self, _notice: &MemberNotice) -> Result<(), ServiceError> {
+        Ok(())
+    }
+}
+
+/// Simulated webhook and email calls that fail now and then, delayed by the latency profile.
+pub struct MockNotificationService {
+    latency: LatencyProfile,
+}
+
+impl MockNotificationService {
+    pub fn new(latency: LatencyProfile) -> Self {
+        Self { latency }
+    }
+
+    #[instrument(skip(self), fields(service = "external_api", latency_ms))]
+    async fn simulate_api_call(&self, endpoint: &str) -> Result<(), ServiceError> {
+        span_errors::capture(async {
+            let delay = self.latency.api_delay().unwrap_or_default();
+            Span::current().record("latency_ms", delay.as_millis());
+
+            info!(endpoint, "Calling external API");
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+
+            // Generate random values before async operations
+            let fail_chance = {
+                let mut rng = rand::thread_rng();
+                rng.gen::<f32>()
+            };
+
+            // Simulate occasional failures (10% failure rate)
+            if fail_chance < 0.1 {
+                warn!(endpoint, "External API call failed");
+                return Err(ServiceError::NotificationFailed("Random failure".to_string()));
+            }
+
+            // Simulate occasional rate limiting (5% rate)
+            if fail_chance < 0.15 {
+                warn!(endpoint, "Rate limited by external API");
+                return Err(ServiceError::RateLimited);
+            }
+
+            info!(endpoint, latency_ms = delay.as_millis(), "External API call successful");
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl NotificationService for MockNotificationService {
+    #[instrument(skip(self, title), fields(notification.type = "todo_created", todo.id = %todo_id, todo.title = %redact::redacted(title)))]
+    async fn send_created_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        span_errors::capture(async {
+            info!(%todo_id, "Sending todo created notification");
+
+            // Simulate webhook call
+            self.simulate_api_call("/webhook/todo-created")
+                .instrument(tracing::info_span!("webhook_call", url = "https://api.slack.com/webhook"))
+                .await?;
+
+            // Simulate email service call
+            self.simulate_api_call("/email/send")
+                .instrument(tracing::info_span!("email_service", recipient = "team@example.com"))
+                .await?;
+
+            info!("Notifications sent successfully");
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self, title), fields(notification.type = "todo_completed", todo.id = %todo_id, todo.title = %redact::redacted(title)))]
+    async fn send_completed_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        span_errors::capture(async {
+            info!(%todo_id, "Sending todo completed notification");
+
+            // Simulate analytics event
+            self.simulate_api_call("/analytics/track")
+                .instrument(tracing::info_span!("analytics_event", event = "todo.completed"))
+                .await?;
+
+            info!("Completion notification sent");
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(notification.type = "batch_summary", batch.count = count))]
+    async fn send_batch_summary(&self, count: usize) -> Result<(), ServiceError> {
+        span_errors::capture(async {
+            info!(count, "Sending batch summary notification");
+
+            // Simulate aggregation service call
+            self.simulate_api_call("/aggregate/batch-summary")
+                .instrument(tracing::info_span!("aggregation_service"))
+                .await?;
+
+            info!("Batch summary sent");
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self, digest), fields(notification.type = "daily_digest", digest.date = %digest.date))]
+    async fn send_digest(&self, digest: &Digest) -> Result<(), ServiceError> {
+        span_errors::capture(async {
+            info!(summary = %digest.summary(), "Sending daily digest");
+
+            self.simulate_api_call("/email/send")
+                .instrument(tracing::info_span!("email_service", recipient = "team@example.com"))
+                .await?;
+
+            info!("Daily digest sent");
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self, notice), fields(notification.type = "member_notice", recipients = notice.recipients.len()))]
+    async fn send_member_notice(&self, notice: &MemberNotice) -> Result<(), ServiceError> {
+        span_errors::capture(async {
+            info!("Sending member notice");
+
+            self.simulate_api_call("/email/send")
+                .instrument(tracing::info_span!("email_service", recipients = notice.recipients.len()))
+                .await?;
+
+            info!("Member notice sent");
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Shared by every HTTP-based channel; one client per service so connections to each
+/// host are pooled and reused.
+pub(crate) fn webhook_client(timeout: Duration) -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .connect_timeout(timeout)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(8)
+        .user_agent(concat!("todo-api/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// POSTs `payload` to `url`, mapping `429` to `RateLimited` and other failures to
+/// `NotificationFailed` or `Timeout`. Webhook URLs often embed credentials, so only the
+/// host is recorded on the span.
+#[instrument(
+    name = "webhook_post",
+    skip(client, url, payload),
+    fields(otel.kind = "client", http.request.method = "POST", server.address, http.response.status_code)
+)]
+pub(crate) async fn post_json(
+    client: &reqwest::Client,
+    event: &str,
+    url: &str,
+    payload: &serde_json::Value,
+) -> Result<(), ServiceError> {
+    span_errors::capture(async {
+        if let Some(host) = reqwest::Url::parse(url).ok().as_ref().and_then(|u| u.host_str()) {
+            Span::current().record("server.address", host);
+        }
+
+        // Propagate the trace so the receiver can continue it
+        let mut headers = HeaderMap::new();
+        TraceContextPropagator::new()
+            .inject_context(&Span::current().context(), &mut HeaderInjector(&mut headers));
+
+        let response = client
+            .post(url)
+            .headers(headers)
+            .json(payload)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    ServiceError::Timeout
+                } else {
+                    ServiceError::NotificationFailed(e.without_url().to_string())
+                }
+            })?;
+
+        let status = response.status();
+        Span::current().record("http.response.status_code", i64::from(status.as_u16()));
+        match status {
+            s if s.is_success() => {
+                info!(event, "Webhook delivered");
+                Ok(())
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                warn!(event, "Rate limited by webhook");
+                Err(ServiceError::RateLimited)
+            }
+            s => {
+                warn!(event, status = s.as_u16(), "Webhook rejected notification");
+                Err(ServiceError::NotificationFailed(format!("webhook returned {s}")))
+            }
+        }
+    })
+    .await
+}
+
+/// Sends each notification as a JSON `POST` to the webhook configured for its event type,
+/// and to every subscription to that event in the shape the subscription asked for.
+pub struct HttpNotificationService {
+    client: reqwest::Client,
+    config: WebhookConfig,
+    repository: Arc<SqliteTodoRepository>,
+}
+
+impl HttpNotificationService {
+    pub fn new(config: WebhookConfig, repository: Arc<SqliteTodoRepository>) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            client: webhook_client(config.timeout)?,
+            config,
+            repository,
+        })
+    }
+
+    fn configured_url(&self, event: WebhookEvent) -> Option<&str> {
+        match event {
+            WebhookEvent::Created => self.config.created_url.as_deref(),
+            WebhookEvent::Completed => self.config.completed_url.as_deref(),
+            WebhookEvent::BatchCreated => self.config.batch_url.as_deref(),
+            WebhookEvent::Digest => self.config.digest_url.as_deref(),
+        }
+    }
+
+    /// Posts to every target for `event` at once. Like the channel fan-out, one failing
+    /// target doesn't stop the others, but fails the notification so all are retried.
+    async fn post(
+        &self,
+        event: WebhookEvent,
+        todo_id: Option<Uuid>,
+        payload: serde_json::Value,
+    ) -> Result<(), ServiceError> {
+        let serde_json::Value::Object(delta) = payload else {
+            unreachable!("webhook payloads are JSON objects");
+        };
+        let subscriptions = self
+            .repository
+            .list_webhooks()
+            .await
+            .map_err(|e| ServiceError::NotificationFailed(format!("loading webhook subscriptions: {e}")))?;
+        let mut targets: Vec<(&str, WebhookFormat, u32)> = self
+            .configured_url(event)
+            .map(|url| (url, WebhookFormat::Delta, webhooks::LATEST_API_VERSION))
+            .into_iter()
+            .collect();
+        targets.extend(
+            subscriptions
+                .iter()
+                .filter(|s| s.events.contains(&event))
+                .map(|s| (s.url.as_str(), s.format, s.api_version)),
+        );
+        if targets.is_empty() {
+            debug!(event = event.as_str(), "No webhook configured for event, skipping");
+            return Ok(());
+        }
+
+        // Loaded once, and only when some target wants more than the delta
+        let todo = match todo_id {
+            Some(id) if targets.iter().any(|(_, format, _)| *format != WebhookFormat::Delta) => {
+                match self.repository.get(id).await {
+                    Ok(todo) => Some(todo),
+                    Err(RepositoryError::NotFound(_)) => None,
+                    Err(e) => return Err(ServiceError::NotificationFailed(format!("loading todo: {e}"))),
+                }
+            }
+            _ => None,
+        };
+
+        let total = targets.len();
+        let results = join_all(targets.into_iter().map(|(url, format, api_version)| {
+            let payload = webhooks::render(format, api_version, event, &delta, todo.as_ref());
+            async move { post_json(&self.client, event.as_str(), url, &payload).await }
+        }))
+        .await;
+        let failures: Vec<ServiceError> = results.into_iter().filter_map(Result::err).collect();
+        match failures.len() {
+            0 => Ok(()),
+            _ if failures.iter().all(|e| matches!(e, ServiceError::RateLimited)) => Err(ServiceError::RateLimited),
+            1 if total == 1 => Err(failures.into_iter().next().unwrap()),
+            failed => {
+                let summary = failures.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ");
+                Err(ServiceError::NotificationFailed(format!(
+                    "{failed} of {total} webhooks failed ({summary})"
+                )))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationService for HttpNotificationService {
+    #[instrument(skip(self, title), fields(notification.type = "todo_created", todo.id = %todo_id))]
+    async fn send_created_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        let payload = serde_json::json!({
+            "event": "todo.created",
+            "todo_id": todo_id,
+            "title": title,
+            "timestamp": Utc::now(),
+        });
+        self.post(WebhookEvent::Created, Some(todo_id), payload).await
+    }
+
+    #[instrument(skip(self, title), fields(notification.type = "todo_completed", todo.id = %todo_id))]
+    async fn send_completed_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        let payload = serde_json::json!({
+            "event": "todo.completed",
+            "todo_id": todo_id,
+            "title": title,
+            "timestamp": Utc::now(),
+        });
+        self.post(WebhookEvent::Completed, Some(todo_id), payload).await
+    }
+
+    #[instrument(skip(self), fields(notification.type = "batch_summary", batch.count = count))]
+    async fn send_batch_summary(&self, count: usize) -> Result<(), ServiceError> {
+        let payload = serde_json::json!({
+            "event": "todo.batch_created",
+            "count": count,
+            "timestamp": Utc::now(),
+        });
+        self.post(WebhookEvent::BatchCreated, None, payload).await
+    }
+
+    #[instrument(skip(self, digest), fields(notification.type = "daily_digest", digest.date = %digest.date))]
+    async fn send_digest(&self, digest: &Digest) -> Result<(), ServiceError> {
+        let payload = serde_json::json!({
+            "event": "todo.digest",
+            "date": digest.date,
+            "open_count": digest.open_count,
+            "due_today_count": digest.due_today_count,
+            "overdue_count": digest.overdue_count,
+            "due_today": digest.due_today,
+            "overdue": digest.overdue,
+            "timestamp": Utc::now(),
+        });
+        self.post(WebhookEvent::Digest, None, payload).await
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(&value)) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+use rand::Rng;
\ No newline at end of file
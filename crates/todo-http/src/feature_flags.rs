@@ -0,0 +1,102 @@
+use crate::models::FeatureFlag;
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use tracing::{error, info, Instrument};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FlagError {
+    #[error("Repository error: {0}")]
+    Repository(#[from] RepositoryError),
+
+    #[error("Flag names are 1 to 64 lowercase letters, digits, '.', '_' or '-'")]
+    InvalidName,
+}
+
+/// Feature flags stored in the database and read from an in-memory copy, so checking one
+/// is cheap enough for any code path. Changes made here apply at once; changes made
+/// through other replicas arrive with the next `spawn_refresh_job` tick.
+pub struct FeatureFlags {
+    repository: Arc<SqliteTodoRepository>,
+    cache: RwLock<HashMap<String, bool>>,
+}
+
+impl FeatureFlags {
+    pub async fn load(repository: Arc<SqliteTodoRepository>) -> Result<Self, RepositoryError> {
+        let flags = Self {
+            repository,
+            cache: RwLock::new(HashMap::new()),
+        };
+        flags.refresh().await?;
+        Ok(flags)
+    }
+
+    /// The flag's stored value, or `default` if it has never been set.
+    pub fn is_enabled(&self, name: &str, default: bool) -> bool {
+        self.cache.read().unwrap().get(name).copied().unwrap_or(default)
+    }
+
+    pub async fn list(&self) -> Result<Vec<FeatureFlag>, RepositoryError> {
+        self.repository.list_flags().await
+    }
+
+    pub async fn set(
+        &self,
+        name: &str,
+        enabled: bool,
+        description: Option<&str>,
+    ) -> Result<FeatureFlag, FlagError> {
+        let valid = (1..=64).contains(&name.len())
+            && name.chars().all(|c| {
+                c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-')
+            });
+        if !valid {
+            return Err(FlagError::InvalidName);
+        }
+        let flag = self.repository.set_flag(name, enabled, description).await?;
+        self.cache.write().unwrap().insert(flag.name.clone(), enabled);
+        info!(flag = name, enabled, "Feature flag set");
+        Ok(flag)
+    }
+
+    /// Returns false if the flag wasn't set. Removing a flag puts it back to its default.
+    pub async fn remove(&self, name: &str) -> Result<bool, RepositoryError> {
+        let removed = self.repository.delete_flag(name).await?;
+        self.cache.write().unwrap().remove(name);
+        if removed {
+            info!(flag = name, "Feature flag removed");
+        }
+        Ok(removed)
+    }
+
+    async fn refresh(&self) -> Result<(), RepositoryError> {
+        let flags = self.repository.list_flags().await?;
+        let flags = flags.into_iter().map(|flag| (flag.name, flag.enabled)).collect();
+        *self.cache.write().unwrap() = flags;
+        Ok(())
+    }
+}
+
+/// Reloads the flags periodically, picking up changes made through other replicas.
+pub fn spawn_refresh_job(flags: Arc<FeatureFlags>, interval: Duration) -> JoinHandle<()> {
+    info!(interval_secs = interval.as_secs(), "Starting feature flag refresh job");
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The flags were just loaded
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            let span = tracing::debug_span!("feature_flag_refresh");
+            if let Err(e) = flags.refresh().instrument(span).await {
+                error!(error = %e, "Feature flag refresh failed");
+            }
+        }
+    })
+}
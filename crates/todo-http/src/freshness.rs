@@ -0,0 +1,53 @@
+use crate::models::Todo;
+use crate::repository::TodoListSummary;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use chrono::{DateTime, Utc};
+
+pub const X_TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+/// HTTP-date, as used by `Last-Modified`. Sub-second precision is lost, which is why
+/// clients should prefer the `ETag`.
+fn http_date(timestamp: DateTime<Utc>) -> HeaderValue {
+    HeaderValue::from_str(&timestamp.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .expect("formatted dates are valid header values")
+}
+
+/// Weak because the same todo has a different byte representation per content type.
+fn weak_etag(tag: String) -> HeaderValue {
+    HeaderValue::from_str(&format!("W/\"{tag}\"")).expect("hex digits are valid header values")
+}
+
+/// `ETag` and `Last-Modified` for a single todo; both change whenever it is updated. The
+/// tag comes from `change_seq` rather than `updated_at`, so a write stamped by a server
+/// whose clock is behind still changes it. It also covers `blocked`, which flips when a
+/// blocker is completed.
+pub fn todo_headers(todo: &Todo) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let seq = todo.change_seq;
+    let tag = if todo.blocked { format!("{seq:x}-b") } else { format!("{seq:x}") };
+    headers.insert(header::ETAG, weak_etag(tag));
+    headers.insert(header::LAST_MODIFIED, http_date(todo.updated_at));
+    headers
+}
+
+/// `ETag`, `Last-Modified` and `X-Total-Count` for the whole list. Creating, updating or
+/// deleting a todo changes the count or the highest change sequence, and so the tag.
+pub fn list_headers(summary: &TodoListSummary) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let tag = format!("{:x}-{:x}", summary.count, summary.last_change_seq);
+    headers.insert(header::ETAG, weak_etag(tag));
+    if let Some(last_modified) = summary.last_modified {
+        headers.insert(header::LAST_MODIFIED, http_date(last_modified));
+    }
+    headers.insert(X_TOTAL_COUNT, HeaderValue::from(summary.count));
+    headers
+}
+
+/// The summary `list_headers` needs, for a list that is already loaded.
+pub fn summarize(todos: &[Todo]) -> TodoListSummary {
+    TodoListSummary {
+        count: todos.len(),
+        last_modified: todos.iter().map(|t| t.updated_at).max(),
+        last_change_seq: todos.iter().map(|t| t.change_seq).max().unwrap_or(0),
+    }
+}
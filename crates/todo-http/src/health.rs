@@ -0,0 +1,163 @@
+use crate::notification_worker::NotificationQueue;
+use crate::repository::TodoRepository;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::Instant;
+use tracing::warn;
+
+/// The server's version, as `/health` reports it.
+pub const VERSION: &str = "0.2.0";
+
+/// Ordered from best to worst, so the report's status is the worst of its components'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Healthy,
+    /// Working, but not as it should; requests are still answered.
+    Degraded,
+    /// Requests that need the component will fail.
+    Unhealthy,
+}
+
+/// One part of the service, as checked for this report.
+#[derive(Debug, Serialize)]
+pub struct ComponentHealth {
+    pub name: &'static str,
+    pub status: HealthStatus,
+    /// How long the check took, or `null` when it didn't need a round trip.
+    pub latency_ms: Option<f64>,
+    /// The version the component reports, or `null` when it doesn't report one.
+    pub version: Option<String>,
+    /// Why the component isn't healthy; `null` when it is.
+    pub detail: Option<String>,
+}
+
+/// What `GET /health` answers, in the shape `schema` describes. Fields are only ever added.
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    pub version: &'static str,
+    /// `connected` or `disconnected`, as the first version of this response put it.
+    pub database: &'static str,
+    pub checked_at: DateTime<Utc>,
+    pub components: Vec<ComponentHealth>,
+}
+
+/// Checks each component in turn.
+pub async fn check(repository: &dyn TodoRepository, notifications: &NotificationQueue) -> HealthResponse {
+    let components = vec![api(), database(repository).await, notification_queue(notifications)];
+    let status = components
+        .iter()
+        .map(|component| component.status)
+        .max()
+        .unwrap_or(HealthStatus::Healthy);
+    let database = match components.iter().find(|component| component.name == "database") {
+        Some(component) if component.status == HealthStatus::Unhealthy => "disconnected",
+        _ => "connected",
+    };
+    HealthResponse {
+        status,
+        version: VERSION,
+        database,
+        checked_at: Utc::now(),
+        components,
+    }
+}
+
+/// The server itself, which is healthy if it's answering at all.
+fn api() -> ComponentHealth {
+    ComponentHealth {
+        name: "api",
+        status: HealthStatus::Healthy,
+        latency_ms: None,
+        version: Some(VERSION.to_string()),
+        detail: None,
+    }
+}
+
+async fn database(repository: &dyn TodoRepository) -> ComponentHealth {
+    let started = Instant::now();
+    let result = repository.database_version().await;
+    let latency_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+    match result {
+        Ok(version) => ComponentHealth {
+            name: "database",
+            status: HealthStatus::Healthy,
+            latency_ms,
+            version: Some(version),
+            detail: None,
+        },
+        Err(e) => {
+            // The error stays in the log; `/health` is open to anyone
+            warn!(error = %e, "Health check couldn't reach the database");
+            ComponentHealth {
+                name: "database",
+                status: HealthStatus::Unhealthy,
+                latency_ms,
+                version: None,
+                detail: Some("The database isn't answering".to_string()),
+            }
+        }
+    }
+}
+
+/// A full queue still takes notifications, but leaves them to the dispatcher, so they go out
+/// late.
+fn notification_queue(notifications: &NotificationQueue) -> ComponentHealth {
+    let (waiting, capacity) = notifications.depth();
+    let full = waiting >= capacity;
+    ComponentHealth {
+        name: "notification_queue",
+        status: if full { HealthStatus::Degraded } else { HealthStatus::Healthy },
+        latency_ms: None,
+        version: None,
+        detail: full.then(|| format!("All {capacity} places are taken; notifications are delayed")),
+    }
+}
+
+/// A JSON Schema (draft 2020-12) for `HealthResponse`, served at `/health/schema` for uptime
+/// monitors to validate against and for OpenAPI 3.1 documents to reference.
+pub fn schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": "/health/schema",
+        "title": "HealthResponse",
+        "type": "object",
+        "required": ["status", "version", "database", "checked_at", "components"],
+        "properties": {
+            "status": {
+                "$ref": "#/$defs/HealthStatus",
+                "description": "The worst status among the components"
+            },
+            "version": { "type": "string", "description": "The server's version" },
+            "database": { "enum": ["connected", "disconnected"] },
+            "checked_at": { "type": "string", "format": "date-time" },
+            "components": {
+                "type": "array",
+                "items": { "$ref": "#/$defs/ComponentHealth" }
+            }
+        },
+        "$defs": {
+            "HealthStatus": {
+                "enum": ["healthy", "degraded", "unhealthy"],
+                "description": "degraded still answers requests; unhealthy fails those that need the component"
+            },
+            "ComponentHealth": {
+                "type": "object",
+                "required": ["name", "status", "latency_ms", "version", "detail"],
+                "properties": {
+                    "name": { "type": "string", "examples": ["api", "database", "notification_queue"] },
+                    "status": { "$ref": "#/$defs/HealthStatus" },
+                    "latency_ms": {
+                        "type": ["number", "null"],
+                        "minimum": 0,
+                        "description": "How long the check took, or null when it didn't need a round trip"
+                    },
+                    "version": { "type": ["string", "null"] },
+                    "detail": { "type": ["string", "null"] }
+                }
+            }
+        }
+    })
+}
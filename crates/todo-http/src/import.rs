@@ -0,0 +1,154 @@
+use crate::models::{normalize_tags, ImportRowError, Todo};
+use crate::validation::TodoValidator;
+use axum::body::Body;
+use chrono::{DateTime, NaiveDate, Utc};
+use csv_async::{AsyncReaderBuilder, ErrorKind, Trim};
+use futures::{StreamExt, TryStreamExt};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tokio_util::io::StreamReader;
+use tracing::{info, instrument, Span};
+use uuid::Uuid;
+
+/// Uploads larger than this are rejected instead of being held in memory.
+pub const MAX_IMPORT_ROWS: usize = 10_000;
+
+/// One CSV record. Columns are matched by header name, and everything but `title` may be
+/// missing or empty.
+#[derive(Debug, Deserialize)]
+struct ImportRow {
+    title: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default, alias = "due_at", alias = "due")]
+    due_date: Option<String>,
+    #[serde(default)]
+    tags: Option<String>,
+    #[serde(default, alias = "estimate")]
+    estimate_minutes: Option<String>,
+    #[serde(default)]
+    completed: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("Failed to read upload: {0}")]
+    Read(String),
+
+    #[error("Too many rows (limit is {MAX_IMPORT_ROWS})")]
+    TooManyRows,
+}
+
+/// Valid rows turned into todos, plus what was wrong with the others.
+#[derive(Debug, Default)]
+pub struct ParsedImport {
+    pub total_rows: usize,
+    pub todos: Vec<Todo>,
+    pub errors: Vec<ImportRowError>,
+}
+
+impl ImportRow {
+    fn into_todo(self, now: DateTime<Utc>, validator: &TodoValidator) -> Result<Todo, String> {
+        let title = self
+            .title
+            .filter(|t| !t.is_empty())
+            .ok_or_else(|| "title is required".to_string())?;
+        let due_at = self.due_date.as_deref().filter(|d| !d.is_empty()).map(parse_due_date).transpose()?;
+        let completed = match self.completed.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            None | Some("") | Some("false") | Some("no") | Some("0") => false,
+            Some("true") | Some("yes") | Some("1") => true,
+            Some(other) => return Err(format!("completed must be true or false, got {other:?}")),
+        };
+        let estimate_minutes = self
+            .estimate_minutes
+            .as_deref()
+            .filter(|e| !e.is_empty())
+            .map(|e| {
+                e.parse::<u32>()
+                    .map_err(|_| format!("estimate_minutes must be a whole number, got {e:?}"))
+            })
+            .transpose()?;
+        let description = self.description.filter(|d| !d.is_empty());
+        validator
+            .check_text(Some(&title), description.as_deref(), "import")
+            .map_err(|e| e.to_string())?;
+        let tags = self
+            .tags
+            .map(|tags| tags.split(';').map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        Ok(Todo {
+            id: Uuid::new_v4(),
+            title,
+            description,
+            completed,
+            due_at,
+            tags: normalize_tags(tags),
+            estimate_minutes,
+            expires_at: None,
+            project_id: None,
+            priority: None,
+            pinned: false,
+            custom_fields: BTreeMap::new(),
+            created_at: now,
+            updated_at: now,
+            blocked: false,
+            version: 0,
+            change_seq: 0,
+        })
+    }
+}
+
+/// Accepts RFC 3339 timestamps, or a plain `YYYY-MM-DD` meaning midnight UTC.
+fn parse_due_date(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).expect("midnight is a valid time").and_utc())
+        .map_err(|_| format!("due date {value:?} is not RFC 3339 or YYYY-MM-DD"))
+}
+
+/// Parses the upload record by record as it arrives, stamping the todos as created at `now`.
+/// Rows that fail to decode or `TodoValidator::check_text`, descriptions over the limit included, are collected
+/// with their line number; only I/O failures abort the parse.
+#[instrument(skip(body, validator), fields(import.rows, import.invalid_rows))]
+pub async fn parse_csv(
+    body: Body,
+    now: DateTime<Utc>,
+    validator: &TodoValidator,
+) -> Result<ParsedImport, ImportError> {
+    let stream = body.into_data_stream().map_err(std::io::Error::other);
+    let mut reader = AsyncReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(true)
+        .create_deserializer(StreamReader::new(stream));
+
+    let mut parsed = ParsedImport::default();
+    let mut records = reader.deserialize_with_pos::<ImportRow>();
+    while let Some((record, position)) = records.next().await {
+        parsed.total_rows += 1;
+        if parsed.total_rows > MAX_IMPORT_ROWS {
+            return Err(ImportError::TooManyRows);
+        }
+
+        let line = position.line();
+        let row = match record {
+            Ok(row) => row,
+            Err(e) if matches!(e.kind(), ErrorKind::Io(_)) => return Err(ImportError::Read(e.to_string())),
+            Err(e) => {
+                parsed.errors.push(ImportRowError { line, error: e.to_string() });
+                continue;
+            }
+        };
+        match row.into_todo(now, validator) {
+            Ok(todo) => parsed.todos.push(todo),
+            Err(error) => parsed.errors.push(ImportRowError { line, error }),
+        }
+    }
+
+    Span::current().record("import.rows", parsed.total_rows);
+    Span::current().record("import.invalid_rows", parsed.errors.len());
+    info!(rows = parsed.total_rows, invalid = parsed.errors.len(), "Parsed CSV import");
+    Ok(parsed)
+}
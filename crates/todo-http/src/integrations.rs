@@ -0,0 +1,408 @@
+use crate::config::InboundSource;
+use crate::models::{normalize_tags, Todo};
+use crate::notification_worker::{NotificationJob, NotificationQueue};
+use crate::repository::{RepositoryError, SqliteTodoRepository, TodoRepository};
+use crate::validation::{TodoValidator, ValidationError};
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use tracing::{info, warn, Span};
+use uuid::Uuid;
+
+/// How a source signs its deliveries and which of them become todos, as given for each
+/// source in `INBOUND_SOURCES_FILE`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceRules {
+    /// The header holding the hex HMAC-SHA256 of the request body.
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String,
+    /// Text in front of the hex digest, such as GitHub's `sha256=`.
+    #[serde(default)]
+    pub signature_prefix: String,
+    /// The header naming the kind of event, which rules can match with `event`.
+    #[serde(default)]
+    pub event_header: Option<String>,
+    /// Tried in order; the first that matches a delivery decides what happens to it.
+    pub rules: Vec<MappingRule>,
+}
+
+fn default_signature_header() -> String {
+    "x-signature".to_owned()
+}
+
+impl SourceRules {
+    /// GitHub issue webhooks: an opened issue becomes a todo, and closing or reopening it
+    /// completes or reopens that todo.
+    pub fn github() -> Self {
+        let issue = |action: &str, rule_action: RuleAction| MappingRule {
+            event: Some("issues".to_owned()),
+            when: BTreeMap::from([("/action".to_owned(), action.to_owned())]),
+            action: rule_action,
+            external_id: "{/repository/full_name}#{/issue/number}".to_owned(),
+            title: "{/issue/title}".to_owned(),
+            description: Some("{/issue/html_url}".to_owned()),
+            tags: vec!["github".to_owned(), "{/repository/name}".to_owned()],
+        };
+        Self {
+            signature_header: "x-hub-signature-256".to_owned(),
+            signature_prefix: "sha256=".to_owned(),
+            event_header: Some("x-github-event".to_owned()),
+            rules: vec![
+                issue("opened", RuleAction::Create),
+                issue("closed", RuleAction::Complete),
+                issue("reopened", RuleAction::Reopen),
+            ],
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, rule) in self.rules.iter().enumerate() {
+            if rule.external_id.trim().is_empty() {
+                return Err(format!("rule {i} has no external_id"));
+            }
+            if rule.action == RuleAction::Create && rule.title.trim().is_empty() {
+                return Err(format!("rule {i} creates todos but has no title"));
+            }
+            if let Some(pointer) = rule.when.keys().find(|pointer| !pointer.starts_with('/')) {
+                return Err(format!("rule {i} matches on {pointer:?}, which isn't a JSON pointer"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One way of turning a delivery into a change to a todo. Text fields are templates in which
+/// `{/json/pointer}` is replaced by that value of the payload (empty when it's missing).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MappingRule {
+    /// Matches only deliveries whose event header holds this.
+    #[serde(default)]
+    pub event: Option<String>,
+    /// Payload values the delivery must have, by JSON pointer.
+    #[serde(default)]
+    pub when: BTreeMap<String, String>,
+    #[serde(default)]
+    pub action: RuleAction,
+    /// Identifies the item in the source, so later deliveries about it find its todo.
+    pub external_id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    /// Creates a todo, unless the item already has one.
+    #[default]
+    Create,
+    /// Completes the item's todo.
+    Complete,
+    /// Marks the item's todo as open again.
+    Reopen,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IntegrationError {
+    #[error("No such integration source")]
+    UnknownSource,
+
+    #[error("Missing or invalid signature")]
+    InvalidSignature,
+
+    #[error("Payload is not valid JSON: {0}")]
+    InvalidPayload(String),
+
+    #[error("Rule {0} produced an empty title")]
+    EmptyTitle(usize),
+
+    #[error("Rule {0} can't create a todo: {1}")]
+    Invalid(usize, ValidationError),
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+/// What a delivery did, as reported back to the sender.
+#[derive(Debug, Serialize)]
+pub struct InboundOutcome {
+    /// `created`, `duplicate`, `completed`, `reopened`, `unchanged` or `ignored`.
+    pub outcome: &'static str,
+    /// The index of the rule that matched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub todo: Option<Todo>,
+}
+
+impl InboundOutcome {
+    fn ignored(rule: Option<usize>, external_id: Option<String>) -> Self {
+        Self {
+            outcome: "ignored",
+            rule,
+            external_id,
+            todo: None,
+        }
+    }
+}
+
+/// Turns signed webhook deliveries from other systems into todos. Which todo stands for which
+/// external item is kept in the shared database, so a redelivered event doesn't create a
+/// second todo and a later one (an issue being closed) finds the todo to complete.
+pub struct InboundIntegrations {
+    sources: HashMap<String, InboundSource>,
+    links: Arc<SqliteTodoRepository>,
+    validator: Arc<TodoValidator>,
+    notifications: NotificationQueue,
+    deliveries: Counter<u64>,
+}
+
+impl InboundIntegrations {
+    pub fn new(
+        sources: &[InboundSource],
+        links: Arc<SqliteTodoRepository>,
+        validator: Arc<TodoValidator>,
+        notifications: NotificationQueue,
+    ) -> Self {
+        Self {
+            sources: sources
+                .iter()
+                .map(|source| (source.name.clone(), source.clone()))
+                .collect(),
+            links,
+            validator,
+            notifications,
+            deliveries: global::meter("todo-api")
+                .u64_counter("integrations.deliveries")
+                .with_description("Inbound webhook deliveries, by source and outcome")
+                .init(),
+        }
+    }
+
+    /// Checks a delivery's signature and applies the first rule matching it, writing todos
+    /// through `todos`.
+    pub async fn receive(
+        &self,
+        todos: &dyn TodoRepository,
+        source: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<InboundOutcome, IntegrationError> {
+        let Some(inbound) = self.sources.get(source) else {
+            return Err(IntegrationError::UnknownSource);
+        };
+        let result = self.apply(todos, inbound, headers, body).await;
+        let outcome = match &result {
+            Ok(outcome) => outcome.outcome,
+            Err(IntegrationError::InvalidSignature) => "rejected",
+            Err(IntegrationError::Repository(_)) => "error",
+            Err(_) => "invalid",
+        };
+        self.deliveries.add(
+            1,
+            &[KeyValue::new("source", inbound.name.clone()), KeyValue::new("outcome", outcome)],
+        );
+        result
+    }
+
+    async fn apply(
+        &self,
+        todos: &dyn TodoRepository,
+        inbound: &InboundSource,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<InboundOutcome, IntegrationError> {
+        if !signature_valid(inbound, headers, body) {
+            warn!(source = %inbound.name, "Inbound delivery with a missing or invalid signature");
+            return Err(IntegrationError::InvalidSignature);
+        }
+        let payload: Value =
+            serde_json::from_slice(body).map_err(|e| IntegrationError::InvalidPayload(e.to_string()))?;
+
+        let rules = &inbound.rules;
+        let event = rules
+            .event_header
+            .as_ref()
+            .and_then(|name| headers.get(name))
+            .and_then(|value| value.to_str().ok());
+        let matched = rules.rules.iter().enumerate().find(|(_, rule)| {
+            rule.event.as_deref().is_none_or(|expected| event == Some(expected))
+                && rule
+                    .when
+                    .iter()
+                    .all(|(pointer, expected)| text(payload.pointer(pointer)) == *expected)
+        });
+        let Some((index, rule)) = matched else {
+            info!(source = %inbound.name, event, "No rule matches the delivery, ignoring it");
+            return Ok(InboundOutcome::ignored(None, None));
+        };
+
+        let external_id = render(&rule.external_id, &payload);
+        Span::current().record("external_id", external_id.as_str());
+        let linked = match self.links.external_link(&inbound.name, &external_id).await? {
+            Some(link) => match todos.get(link.todo_id).await {
+                Ok(todo) => Some(todo),
+                Err(RepositoryError::NotFound(_)) => None,
+                Err(e) => return Err(e.into()),
+            },
+            None => None,
+        };
+
+        let (outcome, todo) = match (rule.action, linked) {
+            (RuleAction::Create, Some(todo)) => ("duplicate", todo),
+            (RuleAction::Create, None) => {
+                let todo = self.create(todos, inbound, index, rule, &payload, &external_id).await?;
+                ("created", todo)
+            }
+            (RuleAction::Complete | RuleAction::Reopen, None) => {
+                info!(source = %inbound.name, external_id, "No todo for the item, ignoring the delivery");
+                return Ok(InboundOutcome::ignored(Some(index), Some(external_id)));
+            }
+            (action, Some(todo)) => {
+                let completed = action == RuleAction::Complete;
+                if todo.completed == completed {
+                    ("unchanged", todo)
+                } else {
+                    let todo = todos.update(Todo { completed, ..todo }).await?;
+                    if completed {
+                        self.notify(NotificationJob::Completed {
+                            todo_id: todo.id,
+                            title: todo.title.clone(),
+                        })
+                        .await;
+                    }
+                    (if completed { "completed" } else { "reopened" }, todo)
+                }
+            }
+        };
+        info!(source = %inbound.name, external_id, todo.id = %todo.id, outcome, "Inbound delivery applied");
+        Ok(InboundOutcome {
+            outcome,
+            rule: Some(index),
+            external_id: Some(external_id),
+            todo: Some(todo),
+        })
+    }
+
+    async fn create(
+        &self,
+        todos: &dyn TodoRepository,
+        inbound: &InboundSource,
+        index: usize,
+        rule: &MappingRule,
+        payload: &Value,
+        external_id: &str,
+    ) -> Result<Todo, IntegrationError> {
+        let title = render(&rule.title, payload).trim().to_owned();
+        if title.is_empty() {
+            return Err(IntegrationError::EmptyTitle(index));
+        }
+        let description = rule
+            .description
+            .as_ref()
+            .map(|template| render(template, payload))
+            .filter(|description| !description.trim().is_empty());
+        // Rules set no custom fields, so a required one leaves nothing to create
+        let checked = match self.validator.check_text(Some(&title), description.as_deref(), "integration") {
+            Ok(()) => self.validator.check_custom_fields(&BTreeMap::new(), true).await,
+            Err(e) => Err(e),
+        };
+        match checked {
+            Ok(()) => {}
+            Err(ValidationError::Repository(e)) => return Err(e.into()),
+            Err(e) => return Err(IntegrationError::Invalid(index, e)),
+        }
+        let now = self.links.clock().now();
+        let todo = todos
+            .create(Todo {
+                id: Uuid::new_v4(),
+                title,
+                description,
+                completed: false,
+                due_at: None,
+                tags: normalize_tags(rule.tags.iter().map(|tag| render(tag, payload)).collect()),
+                estimate_minutes: None,
+                expires_at: None,
+                project_id: None,
+                priority: None,
+                pinned: false,
+                custom_fields: BTreeMap::new(),
+                created_at: now,
+                updated_at: now,
+                blocked: false,
+                version: 0,
+                change_seq: 0,
+            })
+            .await?;
+        self.links.link_external(&inbound.name, external_id, todo.id, now).await?;
+        self.notify(NotificationJob::Created {
+            todo_id: todo.id,
+            title: todo.title.clone(),
+        })
+        .await;
+        Ok(todo)
+    }
+
+    async fn notify(&self, job: NotificationJob) {
+        if let Err(e) = self.notifications.enqueue(job).await {
+            warn!(error = %e, "Failed to queue notification, continuing anyway");
+        }
+    }
+}
+
+/// Whether the delivery carries the HMAC-SHA256 of its body under the source's secret.
+fn signature_valid(inbound: &InboundSource, headers: &HeaderMap, body: &[u8]) -> bool {
+    let rules = &inbound.rules;
+    let signature = headers
+        .get(&rules.signature_header)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().strip_prefix(rules.signature_prefix.as_str()))
+        .and_then(|digest| hex::decode(digest).ok());
+    let Some(signature) = signature else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(inbound.secret.expose().as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    // Compares in constant time
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Replaces each `{/json/pointer}` in `template` with that value of the payload.
+fn render(template: &str, payload: &Value) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{/") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&text(payload.pointer(&rest[start + 1..start + len])));
+        rest = &rest[start + len + 1..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// A payload value as text: strings without their quotes, missing values and `null` empty.
+fn text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(value) => value.to_string(),
+    }
+}
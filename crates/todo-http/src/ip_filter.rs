@@ -0,0 +1,262 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use opentelemetry::{global, metrics::Counter};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use tracing::{error, info, warn};
+
+#[derive(Debug, thiserror::Error)]
+pub enum IpFilterError {
+    #[error("Failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Line {line}: {message}")]
+    Parse { line: usize, message: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Allow,
+    Deny,
+}
+
+/// One `allow|deny CIDR [PATH_PREFIX]` line.
+#[derive(Debug)]
+struct Rule {
+    action: Action,
+    net: IpNet,
+    prefix: String,
+}
+
+impl Rule {
+    fn applies_to(&self, path: &str) -> bool {
+        self.prefix == "/"
+            || path
+                .strip_prefix(self.prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+/// Client IP rules read from a file, replaced wholesale when the file is reloaded.
+///
+/// A matching `deny` always wins. Otherwise, if any `allow` rules cover the path, the
+/// client must match one of those with the longest prefix, so `allow 10.0.0.0/8` plus
+/// `allow 127.0.0.1 /admin` keeps `/admin` to localhost and the rest to the LAN.
+pub struct IpFilter {
+    path: PathBuf,
+    rules: RwLock<Arc<Vec<Rule>>>,
+    rejections: Counter<u64>,
+}
+
+impl IpFilter {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, IpFilterError> {
+        let path = path.into();
+        let rules = read_rules(&path)?;
+        info!(path = %path.display(), rules = rules.len(), "IP filter loaded");
+        Ok(Self {
+            path,
+            rules: RwLock::new(Arc::new(rules)),
+            rejections: global::meter("todo-api")
+                .u64_counter("ip_filter.rejections")
+                .with_description("Requests refused by the IP allow/deny rules")
+                .init(),
+        })
+    }
+
+    /// Re-reads the rules file. On error the rules in force are kept.
+    pub fn reload(&self) -> Result<usize, IpFilterError> {
+        let rules = read_rules(&self.path)?;
+        let count = rules.len();
+        *self.rules.write().unwrap() = Arc::new(rules);
+        Ok(count)
+    }
+
+    fn permits(&self, ip: Option<IpAddr>, path: &str) -> bool {
+        let rules = self.rules.read().unwrap().clone();
+        let mut applicable = rules.iter().filter(|rule| rule.applies_to(path)).peekable();
+        if applicable.peek().is_none() {
+            return true;
+        }
+        // Without a peer address there is nothing to match, so rules that apply fail closed
+        let Some(ip) = ip.map(|ip| ip.to_canonical()) else {
+            return false;
+        };
+
+        let applicable: Vec<&Rule> = applicable.collect();
+        if applicable.iter().any(|r| r.action == Action::Deny && r.net.contains(&ip)) {
+            return false;
+        }
+        let allows = applicable.iter().filter(|r| r.action == Action::Allow);
+        let Some(scope) = allows.clone().map(|r| r.prefix.len()).max() else {
+            return true;
+        };
+        allows
+            .filter(|r| r.prefix.len() == scope)
+            .any(|r| r.net.contains(&ip))
+    }
+}
+
+fn read_rules(path: &Path) -> Result<Vec<Rule>, IpFilterError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| IpFilterError::Read {
+        path: path.display().to_string(),
+        source,
+    })?;
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            (!line.is_empty()).then(|| {
+                parse_rule(line).map_err(|message| IpFilterError::Parse { line: i + 1, message })
+            })
+        })
+        .collect()
+}
+
+fn parse_rule(line: &str) -> Result<Rule, String> {
+    let mut fields = line.split_whitespace();
+    let action = match fields.next().unwrap_or_default() {
+        "allow" => Action::Allow,
+        "deny" => Action::Deny,
+        other => return Err(format!("expected allow or deny, got {other:?}")),
+    };
+    let net = match fields.next() {
+        Some(cidr) => cidr
+            .parse::<IpNet>()
+            .or_else(|_| cidr.parse::<IpAddr>().map(IpNet::from))
+            .map_err(|_| format!("{cidr:?} is not an IP address or CIDR block"))?,
+        None => return Err("missing CIDR block".to_string()),
+    };
+    let prefix = match fields.next() {
+        Some(prefix) if prefix.starts_with('/') => {
+            let trimmed = prefix.trim_end_matches('/');
+            if trimmed.is_empty() { "/" } else { trimmed }.to_string()
+        }
+        Some(prefix) => return Err(format!("path prefix {prefix:?} must start with /")),
+        None => "/".to_string(),
+    };
+    if let Some(extra) = fields.next() {
+        return Err(format!("unexpected {extra:?}"));
+    }
+    Ok(Rule { action, net, prefix })
+}
+
+/// Reloads the rules whenever the process gets `SIGHUP`.
+#[cfg(unix)]
+pub fn spawn_reload_on_sighup(filter: Arc<IpFilter>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .expect("Failed to install SIGHUP handler");
+        while hangups.recv().await.is_some() {
+            match filter.reload() {
+                Ok(rules) => info!(rules, "IP filter reloaded"),
+                Err(e) => error!(error = %e, "Failed to reload IP filter, keeping previous rules"),
+            }
+        }
+    })
+}
+
+/// Refuses clients the rules don't permit with `403`, before any other check runs.
+pub async fn enforce_ip_filter(State(filter): State<Arc<IpFilter>>, req: Request, next: Next) -> Response {
+    let ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if filter.permits(ip, req.uri().path()) {
+        return next.run(req).await;
+    }
+
+    let address = ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+    warn!(client.address = %address, "Client refused by IP filter");
+    filter.rejections.add(1, &[]);
+    (StatusCode::FORBIDDEN, "Forbidden").into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_rules(rules: &[&str]) -> IpFilter {
+        let rules = rules.iter().map(|line| parse_rule(line).unwrap()).collect();
+        IpFilter {
+            path: PathBuf::new(),
+            rules: RwLock::new(Arc::new(rules)),
+            rejections: global::meter("todo-api").u64_counter("ip_filter.rejections").init(),
+        }
+    }
+
+    fn permits(filter: &IpFilter, ip: &str, path: &str) -> bool {
+        filter.permits(Some(ip.parse().unwrap()), path)
+    }
+
+    #[test]
+    fn matches_ipv4_and_ipv6_blocks() {
+        let filter = with_rules(&["allow 10.0.0.0/8", "allow 2001:db8::/32"]);
+        assert!(permits(&filter, "10.1.2.3", "/todos"));
+        assert!(!permits(&filter, "11.0.0.1", "/todos"));
+        assert!(permits(&filter, "2001:db8::1", "/todos"));
+        assert!(!permits(&filter, "2001:db9::1", "/todos"));
+        // An IPv4 client reached over an IPv6 socket is matched as IPv4
+        assert!(permits(&filter, "::ffff:10.1.2.3", "/todos"));
+    }
+
+    #[test]
+    fn handles_whole_and_single_address_blocks() {
+        let everyone = with_rules(&["allow 0.0.0.0/0"]);
+        assert!(permits(&everyone, "203.0.113.9", "/todos"));
+        assert!(!permits(&everyone, "2001:db8::1", "/todos"));
+
+        let one = with_rules(&["allow 192.0.2.7/32"]);
+        assert!(permits(&one, "192.0.2.7", "/todos"));
+        assert!(!permits(&one, "192.0.2.8", "/todos"));
+        // A bare address is a single-address block
+        let bare = with_rules(&["allow 192.0.2.7"]);
+        assert!(permits(&bare, "192.0.2.7", "/todos"));
+        assert!(!permits(&bare, "192.0.2.8", "/todos"));
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let filter = with_rules(&["allow 10.0.0.0/8", "deny 10.0.0.5/32", "allow 10.0.0.5 /admin"]);
+        assert!(permits(&filter, "10.0.0.4", "/todos"));
+        assert!(!permits(&filter, "10.0.0.5", "/todos"));
+        assert!(!permits(&filter, "10.0.0.5", "/admin"));
+    }
+
+    #[test]
+    fn longest_allowed_prefix_decides() {
+        let filter = with_rules(&["allow 10.0.0.0/8", "allow 127.0.0.1 /admin"]);
+        assert!(permits(&filter, "127.0.0.1", "/admin/backup"));
+        assert!(!permits(&filter, "10.0.0.1", "/admin"));
+        assert!(permits(&filter, "10.0.0.1", "/administrators"));
+        assert!(!filter.permits(None, "/todos"));
+        assert!(with_rules(&[]).permits(None, "/todos"));
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        for line in [
+            "allow",
+            "allow 10.0.0.0/33",
+            "allow 10.0.0/8",
+            "allow ::1/129",
+            "allow example.com",
+            "permit 10.0.0.0/8",
+            "allow 10.0.0.0/8 admin",
+            "allow 10.0.0.0/8 /admin extra",
+        ] {
+            assert!(parse_rule(line).is_err(), "{line}");
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use crate::config::JiraConfig;
+use crate::external_service::webhook_client;
+use crate::leases::JobLeases;
+use crate::models::Todo;
+use crate::notification_worker::{NotificationJob, NotificationQueue};
+use crate::repository::{RepositoryError, SqliteTodoRepository, TodoRepository};
+use crate::span_errors::{self, SpanError};
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use tracing::{error, info, instrument, warn, Instrument, Span};
+
+/// `source` of Jira issues in the external link table.
+const SOURCE: &str = "jira";
+
+/// Jira's status category for finished issues; the others are `new` and `indeterminate`.
+const DONE: &str = "done";
+
+#[derive(Debug, thiserror::Error)]
+pub enum JiraError {
+    #[error("Jira request failed: {0}")]
+    Request(String),
+
+    #[error("Jira answered {status}: {message}")]
+    Rejected { status: u16, message: String },
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+impl SpanError for JiraError {}
+
+/// The issue a todo was pushed to.
+#[derive(Debug, Serialize)]
+pub struct JiraIssue {
+    pub key: String,
+    /// The issue in Jira's web UI.
+    pub url: String,
+    /// Whether this push created the issue rather than updating it.
+    pub created: bool,
+}
+
+/// Pushes todos to a Jira project as issues, and completes or reopens them as their issues
+/// move in and out of Jira's `done` status category. Which issue belongs to which todo is
+/// kept in the shared database's external links.
+pub struct JiraSync {
+    client: reqwest::Client,
+    config: JiraConfig,
+    links: Arc<SqliteTodoRepository>,
+    notifications: NotificationQueue,
+    synced: Counter<u64>,
+}
+
+impl JiraSync {
+    pub fn new(
+        config: &JiraConfig,
+        links: Arc<SqliteTodoRepository>,
+        notifications: NotificationQueue,
+    ) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            client: webhook_client(config.timeout)?,
+            config: config.clone(),
+            links,
+            notifications,
+            synced: global::meter("todo-api")
+                .u64_counter("jira.synced")
+                .with_description("Todos pushed to Jira and issue status changes applied to todos")
+                .init(),
+        })
+    }
+
+    /// Creates the todo's issue, or brings its summary, description, labels and due date up
+    /// to date if it already has one.
+    #[instrument(skip(self, todo), fields(todo.id = %todo.id, jira.issue))]
+    pub async fn push(&self, todo: &Todo) -> Result<JiraIssue, JiraError> {
+        let mut fields = json!({
+            "summary": todo.title,
+            "description": todo.description.clone().unwrap_or_default(),
+            // Jira labels can't hold spaces
+            "labels": todo
+                .tags
+                .iter()
+                .map(|tag| tag.split_whitespace().collect::<Vec<_>>().join("-"))
+                .collect::<Vec<_>>(),
+            "duedate": todo.due_at.map(|due_at| due_at.format("%Y-%m-%d").to_string()),
+        });
+
+        let (key, created) = match self.links.external_link_for_todo(SOURCE, todo.id).await? {
+            Some(link) => {
+                let path = format!("/rest/api/2/issue/{}", link.external_id);
+                self.send(Method::PUT, &path, Some(json!({ "fields": fields }))).await?;
+                (link.external_id, false)
+            }
+            None => {
+                fields["project"] = json!({ "key": self.config.project_key });
+                fields["issuetype"] = json!({ "name": self.config.issue_type });
+                let response = self
+                    .send(Method::POST, "/rest/api/2/issue", Some(json!({ "fields": fields })))
+                    .await?;
+                let Some(key) = response["key"].as_str() else {
+                    return Err(JiraError::Request("created issue has no key".to_owned()));
+                };
+                self.links.link_external(SOURCE, key, todo.id, self.links.clock().now()).await?;
+                (key.to_owned(), true)
+            }
+        };
+
+        Span::current().record("jira.issue", key.as_str());
+        let outcome = if created { "created" } else { "updated" };
+        self.synced.add(1, &[KeyValue::new("outcome", outcome)]);
+        info!(issue = %key, outcome, "Todo pushed to Jira");
+        Ok(JiraIssue {
+            url: format!("{}/browse/{key}", self.config.base_url),
+            key,
+            created,
+        })
+    }
+
+    /// Reads the status of every linked issue and completes or reopens todos whose issue
+    /// changed status category since the last poll. An issue's first poll only records its
+    /// status, so pushing a completed todo doesn't reopen it. Returns the todos changed.
+    #[instrument(skip_all, fields(count, changed))]
+    pub async fn poll(&self, todos: &dyn TodoRepository) -> Result<usize, JiraError> {
+        let links = self.links.external_links(SOURCE).await?;
+        Span::current().record("count", links.len());
+
+        let mut changed = 0;
+        for link in links {
+            let todo = match todos.get(link.todo_id).await {
+                Ok(todo) => todo,
+                Err(RepositoryError::NotFound(_)) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let path = format!("/rest/api/2/issue/{}?fields=status", link.external_id);
+            let issue = match self.send(Method::GET, &path, None).await {
+                Ok(issue) => issue,
+                Err(JiraError::Rejected { status: 404, .. }) => continue,
+                Err(e) => {
+                    warn!(issue = %link.external_id, error = %e, "Failed to read Jira issue");
+                    continue;
+                }
+            };
+            let Some(category) = issue.pointer("/fields/status/statusCategory/key").and_then(Value::as_str) else {
+                continue;
+            };
+            if link.remote_state.as_deref() == Some(category) {
+                continue;
+            }
+            self.links.set_external_state(SOURCE, &link.external_id, category).await?;
+            if link.remote_state.is_none() {
+                continue;
+            }
+
+            let completed = category == DONE;
+            if todo.completed == completed {
+                continue;
+            }
+            let todo = todos.update(Todo { completed, ..todo }).await?;
+            changed += 1;
+            let outcome = if completed { "completed" } else { "reopened" };
+            self.synced.add(1, &[KeyValue::new("outcome", outcome)]);
+            info!(issue = %link.external_id, todo.id = %todo.id, outcome, "Jira status applied to todo");
+            if completed {
+                let job = NotificationJob::Completed {
+                    todo_id: todo.id,
+                    title: todo.title.clone(),
+                };
+                if let Err(e) = self.notifications.enqueue(job).await {
+                    warn!(error = %e, "Failed to queue completion notification");
+                }
+            }
+        }
+        Span::current().record("changed", changed);
+        Ok(changed)
+    }
+
+    /// One call to the Jira REST API, returning its JSON body (`null` when there is none).
+    #[instrument(
+        name = "jira_request",
+        skip(self, body),
+        fields(otel.kind = "client", http.request.method = %method, http.response.status_code)
+    )]
+    async fn send(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, JiraError> {
+        span_errors::capture(async {
+            let url = format!("{}{path}", self.config.base_url);
+            let token = self.config.api_token.expose();
+            let mut request = self.client.request(method, url);
+            request = match &self.config.email {
+                Some(email) => request.basic_auth(email, Some(token)),
+                None => request.bearer_auth(token),
+            };
+            if let Some(body) = body {
+                request = request.json(&body);
+            }
+
+            let response = request
+                .send()
+                .await
+                .map_err(|e| JiraError::Request(e.without_url().to_string()))?;
+            let status = response.status();
+            Span::current().record("http.response.status_code", i64::from(status.as_u16()));
+            let text = response
+                .text()
+                .await
+                .map_err(|e| JiraError::Request(e.without_url().to_string()))?;
+            if !status.is_success() {
+                return Err(JiraError::Rejected {
+                    status: status.as_u16(),
+                    message: error_message(status, &text),
+                });
+            }
+            if text.trim().is_empty() {
+                return Ok(Value::Null);
+            }
+            serde_json::from_str(&text).map_err(|e| JiraError::Request(format!("invalid JSON from Jira: {e}")))
+        })
+        .await
+    }
+}
+
+/// Jira's own explanation of a failed call, from its `errorMessages` and `errors` fields.
+fn error_message(status: StatusCode, body: &str) -> String {
+    let Ok(body) = serde_json::from_str::<Value>(body) else {
+        return status.canonical_reason().unwrap_or("error").to_owned();
+    };
+    let mut messages: Vec<String> = body["errorMessages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|message| message.as_str().map(str::to_owned))
+        .collect();
+    if let Some(errors) = body["errors"].as_object() {
+        messages.extend(
+            errors
+                .iter()
+                .map(|(field, message)| format!("{field}: {}", message.as_str().unwrap_or_default())),
+        );
+    }
+    if messages.is_empty() {
+        status.canonical_reason().unwrap_or("error").to_owned()
+    } else {
+        messages.join("; ")
+    }
+}
+
+/// Spawns the job that reads linked issues' status back from Jira every `interval`, on the
+/// replica holding the `jira_sync` lease.
+pub fn spawn_poll_job(
+    sync: Arc<JiraSync>,
+    todos: Arc<dyn TodoRepository>,
+    leases: Arc<JobLeases>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    info!(interval_secs = interval.as_secs(), "Starting Jira poll job");
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+            if !leases.acquire("jira_sync", interval * 2).await {
+                continue;
+            }
+
+            let span = tracing::debug_span!("jira_poll");
+            if let Err(e) = sync.poll(todos.as_ref()).instrument(span).await {
+                error!(error = %e, "Jira poll failed");
+            }
+        }
+    })
+}
@@ -0,0 +1,61 @@
+use crate::repository::SqliteTodoRepository;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{info, warn};
+
+/// Decides which replica runs each scheduled job, so replicas sharing a database don't all
+/// do the same work. A job's lease is kept in the database: the replica holding it renews
+/// it on every run, and another takes over once it lapses.
+pub struct JobLeases {
+    repository: Arc<SqliteTodoRepository>,
+    /// This replica's name in the lease table (`INSTANCE_ID`).
+    instance: String,
+    held: Mutex<HashSet<&'static str>>,
+}
+
+impl JobLeases {
+    pub fn new(repository: Arc<SqliteTodoRepository>, instance: impl Into<String>) -> Self {
+        Self {
+            repository,
+            instance: instance.into(),
+            held: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Whether this replica should run `job` now: takes or renews its lease for `ttl`. A
+    /// periodic job passes more than its interval, so the replica running it keeps it. If
+    /// the database can't say, the run is skipped rather than risk running it twice.
+    pub async fn acquire(&self, job: &'static str, ttl: Duration) -> bool {
+        let now = self.repository.clock().now();
+        let until = now + chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::zero());
+        let acquired = match self.repository.acquire_lease(job, &self.instance, now, until).await {
+            Ok(acquired) => acquired,
+            Err(e) => {
+                warn!(job, error = %e, "Failed to take job lease, skipping this run");
+                false
+            }
+        };
+
+        let mut held = self.held.lock().unwrap();
+        if acquired && held.insert(job) {
+            info!(job, instance = %self.instance, "Running job on this instance");
+        } else if !acquired && held.remove(job) {
+            info!(job, instance = %self.instance, "Job taken over by another instance");
+        }
+        acquired
+    }
+
+    /// Gives up every lease this replica holds, so others take over without waiting for
+    /// them to expire. Called on shutdown.
+    pub async fn release_all(&self) {
+        let held: Vec<&'static str> = self.held.lock().unwrap().drain().collect();
+        for job in held {
+            if let Err(e) = self.repository.release_lease(job, &self.instance).await {
+                warn!(job, error = %e, "Failed to release job lease");
+            }
+        }
+    }
+}
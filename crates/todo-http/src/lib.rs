@@ -0,0 +1,2957 @@
+//! The todo API as a library: shared state, every route and its middleware, and the
+//! services behind them. `todo-server` wires these up from the environment.
+
+mod access_log;
+pub mod analytics;
+pub mod attachments;
+mod audit;
+pub mod auth;
+mod auth_cache;
+mod body_log;
+pub mod body_schema;
+mod cloud_events;
+pub mod config;
+pub mod custom_fields;
+pub mod dav;
+pub mod deadline;
+pub mod description_limits;
+pub mod digest;
+pub mod dto;
+pub mod erasure;
+pub mod events;
+pub mod expiry;
+pub mod feature_flags;
+pub mod health;
+pub mod integrations;
+pub mod jira;
+pub mod leases;
+pub mod resilience;
+pub mod retention;
+mod expand;
+mod external_service;
+mod freshness;
+mod import;
+mod ip_filter;
+mod load_shed;
+pub mod query_cache;
+pub mod query_profile;
+pub mod maintenance;
+pub mod mcp;
+pub mod mentions;
+pub mod presence;
+pub mod metrics;
+mod negotiate;
+mod pagination;
+pub mod notification_channels;
+pub mod notification_worker;
+pub mod projects;
+mod rate_limit;
+mod read_only;
+mod slow_requests;
+pub mod sync;
+pub mod tags;
+pub mod telemetry;
+pub mod tenancy;
+mod ui;
+pub mod upload_scan;
+pub mod usage;
+pub mod user_export;
+pub mod validation;
+pub mod webhooks;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    Extension,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    middleware,
+    response::{IntoResponse, Response},
+    response::Redirect,
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use chrono::FixedOffset;
+use todo_domain::{clock::Clock, inbox::{self, InboxWeights}, markdown, models, quick_add};
+use todo_storage::{backup, blob_store, latency, redact, repository, span_errors, tenants};
+use models::*;
+use access_log::AccessLog;
+use analytics::Analytics;
+use attachments::{AttachmentError, AttachmentService, Download};
+use audit::{AuditEvent, ClientInfo};
+use auth::{AuthError, AuthService, Principal, Session};
+use body_schema::BodyValidator;
+use backup::{BackupError, BackupService};
+use config::Config;
+use custom_fields::{CustomFieldError, CustomFields};
+use erasure::ErasureService;
+use events::{EventStreamQuery, TodoEvent, TodoEvents};
+use feature_flags::{FeatureFlags, FlagError};
+use dav::CalDavNames;
+use deadline::Deadlines;
+use integrations::{InboundIntegrations, IntegrationError};
+use jira::{JiraError, JiraSync};
+use mcp::{Caller, McpServer};
+use metrics::HttpMetrics;
+use negotiate::{Format, Negotiated, Payload, XmlRoot};
+use dto::CamelCase;
+use expand::Expand;
+use ip_filter::IpFilter;
+use query_cache::{ListKey, QueryCache};
+use query_profile::QueryProfiler;
+use rate_limit::RateLimiter;
+use mentions::{MentionError, MentionService};
+use presence::Presence;
+use projects::{ProjectError, ProjectService, TodoChange};
+use webhooks::{WebhookError, WebhookSubscriptions};
+use tags::{TagError, TagService};
+use sync::{SyncError, SyncService};
+use tenancy::TenantRouting;
+use tenants::TenantDatabases;
+use upload_scan::ScanError;
+use usage::UsageTracker;
+use user_export::{ExportFile, ExportOutcome, UserExporter};
+use validation::{TodoValidator, ValidationError};
+use repository::{ListFilter, RepositoryError, TodoRepository};
+use retention::{Retention, RetentionError};
+use notification_worker::{NotificationJob, NotificationQueue};
+use prometheus::Encoder;
+use futures::{stream, FutureExt, StreamExt};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use opentelemetry::trace::TraceContextExt;
+use tracing::{error, info, instrument, warn, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub repository: Arc<dyn TodoRepository>,
+    pub notifications: NotificationQueue,
+    pub events: TodoEvents,
+    pub backup_service: Arc<BackupService>,
+    /// `None` when `JWT_SECRET` is unset; the `/auth` routes then answer `404`.
+    pub auth: Option<Arc<AuthService>>,
+    /// Present exactly when `auth` is, as is `erasure`.
+    pub user_export: Option<Arc<UserExporter>>,
+    pub erasure: Option<Arc<ErasureService>>,
+    pub flags: Arc<FeatureFlags>,
+    pub analytics: Arc<Analytics>,
+    pub query_cache: Arc<QueryCache>,
+    pub sync: Arc<SyncService>,
+    pub projects: Arc<ProjectService>,
+    pub mentions: Arc<MentionService>,
+    pub webhooks: Arc<WebhookSubscriptions>,
+    pub custom_fields: Arc<CustomFields>,
+    pub tags: Arc<TagService>,
+    /// The title, description size (`DESCRIPTION_WARN_BYTES`, `DESCRIPTION_MAX_BYTES`) and
+    /// custom field checks every todo write goes through.
+    pub validator: Arc<TodoValidator>,
+    pub attachments: Arc<AttachmentService>,
+    pub retention: Arc<Retention>,
+    pub integrations: Arc<InboundIntegrations>,
+    pub caldav: Arc<CalDavNames>,
+    /// `None` unless `JIRA_BASE_URL` is set; `/todos/:id/jira` then answers `404`.
+    pub jira: Option<Arc<JiraSync>>,
+    /// `None` when `SLOW_QUERY_WINDOW_SECS` is `0`; `/admin/slow-queries` then answers `404`.
+    pub profiler: Option<Arc<QueryProfiler>>,
+    /// `None` when `USAGE_FLUSH_INTERVAL_SECS` is `0`; `/admin/usage` then answers `404`.
+    pub usage: Option<Arc<UsageTracker>>,
+    /// `None` unless `TENANT_DATABASE_DIR` is set; `repository` then follows `X-Tenant-ID`.
+    pub tenants: Option<Arc<TenantDatabases>>,
+    /// `Config::summary`, as logged at startup.
+    pub effective_config: Arc<serde_json::Value>,
+    /// How `GET /todos/next` ranks todos (`INBOX_WEIGHT_*`).
+    pub inbox_weights: InboxWeights,
+    pub mcp: Arc<McpServer>,
+    /// Who has which project open, on this instance.
+    pub presence: Presence,
+    pub prometheus_registry: prometheus::Registry,
+    /// Where handlers read the current time, so due dates and snoozes can be worked out at a
+    /// chosen one. The same clock as the repository's.
+    pub clock: Arc<dyn Clock>,
+}
+
+#[instrument(skip(state))]
+async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
+    info!("Health check requested");
+
+    let report = health::check(state.repository.as_ref(), &state.notifications).await;
+    // Uptime monitors that only look at the status code still see an outage
+    let status = if report.status == health::HealthStatus::Unhealthy {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(report))
+}
+
+async fn health_schema() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/schema+json")], health::schema().to_string())
+}
+
+async fn request_schemas() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/schema+json")], body_schema::schema().to_string())
+}
+
+async fn prometheus_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let encoder = prometheus::TextEncoder::new();
+    match encoder.encode_to_string(&state.prometheus_registry.gather()) {
+        Ok(body) => Ok(([(header::CONTENT_TYPE, encoder.format_type().to_string())], body)),
+        Err(e) => {
+            error!(error = %e, "Failed to encode metrics");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to encode metrics"))
+        }
+    }
+}
+
+#[instrument(skip(state))]
+async fn list_todos(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    uri: Uri,
+    Query(mut query): Query<HashMap<String, String>>,
+) -> Result<Response, (StatusCode, String)> {
+    info!("Listing todos");
+
+    let page = pagination::Page::from_query(&query).map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+    let cursor = pagination::CursorPage::from_query(&query).map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+    let expand = query.remove("expand").map(|expand| expand.parse::<Expand>()).transpose();
+    let expand = expand.map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+    // Every page of a list shares its cache entry
+    query.remove("offset");
+    query.remove("limit");
+    query.remove("cursor");
+    query.remove("page");
+    query.remove("per_page");
+    let fields = match state.custom_fields.filter(&query).await {
+        Ok(fields) => fields,
+        Err(CustomFieldError::Repository(e)) => {
+            error!(error = %e, "Failed to load custom fields");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to retrieve todos".to_string()));
+        }
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e.to_string())),
+    };
+
+    // Todos of projects the caller isn't a member of are left out
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return Ok(rejection),
+    };
+
+    if let Some(cursor) = cursor {
+        // Filtering after the query would leave pages short and `has_more` wrong
+        if !fields.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "cursor can't be combined with field filters".to_string()));
+        }
+        return match state.repository.list_page(cursor.after, cursor.limit, visible.as_ref()).await {
+            Ok(page) => {
+                info!(count = page.todos.len(), has_more = page.has_more, "Retrieved a page of todos");
+                let headers = pagination::cursor_headers(&page, &uri);
+                match expand {
+                    Some(expand) => {
+                        let todos = expand_todos(&state, expand, page.todos).await?;
+                        Ok((headers, Negotiated(format, todos)).into_response())
+                    }
+                    None => Ok((headers, Negotiated(format, Arc::new(page.todos))).into_response()),
+                }
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to list todos");
+                Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to retrieve todos".to_string()))
+            }
+        };
+    }
+
+    let key = ListKey::new(tenancy::current_tenant(), &query, visible.as_ref());
+    let filter = ListFilter {
+        projects: visible.as_ref(),
+        fields,
+        ..ListFilter::default()
+    };
+
+    // A page is read on its own, with the summary of the whole list alongside
+    if let Some(page) = page {
+        return match state.repository.list(&filter, Some(page.window())).await {
+            Ok(list) => {
+                info!(count = list.todos.len(), total = list.summary.count, "Retrieved a page of todos");
+                let headers = pagination::paged_headers(&list.summary, Some(page), &uri);
+                match expand {
+                    Some(expand) => {
+                        let todos = expand_todos(&state, expand, list.todos).await?;
+                        Ok(paged_response(format, without_validators(headers), todos, list.summary.count, page))
+                    }
+                    None => Ok(paged_response(format, headers, list.todos, list.summary.count, page)),
+                }
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to list todos");
+                Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to retrieve todos".to_string()))
+            }
+        };
+    }
+
+    let load = async { Ok::<_, RepositoryError>(state.repository.list(&filter, None).await?.todos) };
+    match state.query_cache.list(key, load).await {
+        Ok(todos) => {
+            info!(count = todos.len(), "Retrieved todos");
+            let headers = freshness::list_headers(&freshness::summarize(&todos));
+            match expand {
+                Some(expand) => {
+                    let todos = expand_todos(&state, expand, todos.as_ref().clone()).await?;
+                    Ok((without_validators(headers), Negotiated(format, todos)).into_response())
+                }
+                None => Ok((headers, Negotiated(format, todos)).into_response()),
+            }
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to list todos");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to retrieve todos".to_string()))
+        }
+    }
+}
+
+/// A page of a list: the todos alone for `?offset=` and `?limit=`, or in an envelope with
+/// the totals for `?page=` and `?per_page=`. `total` counts the whole list.
+fn paged_response<T: XmlRoot + CamelCase>(
+    format: Format,
+    headers: HeaderMap,
+    todos: Vec<T>,
+    total: usize,
+    page: pagination::Page,
+) -> Response {
+    let Some(number) = page.number else {
+        return (headers, Negotiated(format, todos)).into_response();
+    };
+    let envelope = TodoListPage {
+        page: number,
+        per_page: page.limit.unwrap_or(pagination::DEFAULT_PAGE_SIZE),
+        total: total as u64,
+        total_pages: page.total_pages(total) as u64,
+        todos,
+    };
+    (headers, Negotiated(format, envelope)).into_response()
+}
+
+/// The todos with what `?expand=` asked for embedded in them.
+async fn expand_todos(state: &AppState, expand: Expand, todos: Vec<Todo>) -> Result<Vec<ExpandedTodo>, (StatusCode, String)> {
+    expand.apply(todos, &state.mentions, &state.projects).await.map_err(|e| {
+        error!(error = %e, "Failed to expand todos");
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to retrieve todos".to_string())
+    })
+}
+
+/// `headers` without `ETag` and `Last-Modified`, for expanded responses. They describe the
+/// todos alone, and a new comment changes neither, so a cache revalidating with them would
+/// keep serving the old comments.
+fn without_validators(mut headers: HeaderMap) -> HeaderMap {
+    headers.remove(header::ETAG);
+    headers.remove(header::LAST_MODIFIED);
+    headers
+}
+
+/// Only `{id, title, completed, due_at}` per todo, for clients rendering long lists.
+#[instrument(skip(state, principal))]
+async fn list_compact_todos(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    info!("Listing compact todos");
+
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    let load = state.repository.list_compact(visible.as_ref());
+    match state.query_cache.compact(tenancy::current_tenant(), visible.as_ref(), load).await {
+        Ok(todos) => {
+            info!(count = todos.len(), "Retrieved compact todos");
+            Negotiated(format, todos).into_response()
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to list compact todos");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to retrieve todos").into_response()
+        }
+    }
+}
+
+/// Open and completed counts with estimate rollups overall and per tag.
+#[instrument(skip(state, principal))]
+async fn todo_stats(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    let load = state.repository.stats(visible.as_ref());
+    match state.query_cache.stats(tenancy::current_tenant(), visible.as_ref(), load).await {
+        Ok(stats) => Negotiated(format, stats).into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to compute todo stats");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to compute stats").into_response()
+        }
+    }
+}
+
+/// Open, unblocked todos in the order to work on them, scored from their due date, priority,
+/// age and pinning with the configured weights. Each comes with its score and factors.
+#[instrument(skip(state, principal))]
+async fn next_todos(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<NextTodosQuery>,
+) -> Response {
+    let limit = query.limit.unwrap_or(DEFAULT_NEXT_LIMIT);
+    if !(1..=MAX_NEXT_LIMIT).contains(&limit) {
+        return (StatusCode::BAD_REQUEST, format!("limit must be between 1 and {MAX_NEXT_LIMIT}")).into_response();
+    }
+
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    let filter = ListFilter {
+        projects: visible.as_ref(),
+        ..ListFilter::default()
+    };
+    let todos = match state.repository.list(&filter, None).await {
+        Ok(list) => list.todos,
+        Err(e) => {
+            error!(error = %e, "Failed to list todos to rank");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to retrieve todos").into_response();
+        }
+    };
+
+    let ranked = inbox::rank(todos, state.clock.now(), &state.inbox_weights, limit);
+    info!(count = ranked.len(), "Ranked todos");
+    Negotiated(format, ranked).into_response()
+}
+
+/// Todos `GET /todos/next` returns without a `limit`, and the most it returns.
+const DEFAULT_NEXT_LIMIT: usize = 20;
+const MAX_NEXT_LIMIT: usize = 200;
+
+/// The caller's todos as a Markdown checklist to paste into issues, pull requests or notes,
+/// grouped by project or by tag.
+#[instrument(skip(state, principal))]
+async fn export_todos(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let ExportFormat::Markdown = query.format.unwrap_or_default();
+
+    // The same todos a list would show the caller
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    let filter = ListFilter {
+        projects: visible.as_ref(),
+        ..ListFilter::default()
+    };
+    let todos = match state.repository.list(&filter, None).await {
+        Ok(list) => list.todos,
+        Err(e) => {
+            error!(error = %e, "Failed to list todos to export");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to export todos").into_response();
+        }
+    };
+
+    let sections = match query.group.unwrap_or_default() {
+        ExportGroup::Project => {
+            let ids = todos.iter().filter_map(|todo| todo.project_id).collect();
+            let names = match state.projects.names(&ids).await {
+                Ok(names) => names,
+                Err(e) => {
+                    error!(error = %e, "Failed to load project names to export");
+                    return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to export todos").into_response();
+                }
+            };
+            markdown::by_project(&todos, |todo| todo.project_id.and_then(|id| names.get(&id).cloned()))
+        }
+        ExportGroup::Tag => markdown::by_tag(&todos),
+    };
+    info!(count = todos.len(), sections = sections.len(), "Exported todos as Markdown");
+    let body = markdown::checklist(&sections);
+    let mut response = ([(header::CONTENT_TYPE, "text/markdown; charset=utf-8")], body).into_response();
+    if query.download {
+        response.headers_mut().insert(
+            header::CONTENT_DISPOSITION,
+            HeaderValue::from_static("attachment; filename=\"todos.md\""),
+        );
+    }
+    response
+}
+
+/// Completions per day or week, for the last `periods` of them (30 days or 12 weeks by default).
+#[instrument(skip(state, principal))]
+async fn stats_velocity(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<VelocityQuery>,
+) -> Response {
+    let period = query.period.unwrap_or(VelocityPeriod::Day);
+    let periods = query.periods.unwrap_or(match period {
+        VelocityPeriod::Day => 30,
+        VelocityPeriod::Week => 12,
+    });
+    if !(1..=366).contains(&periods) {
+        return (StatusCode::BAD_REQUEST, "periods must be between 1 and 366").into_response();
+    }
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    match state.analytics.velocity(visible.as_ref(), period, periods).await {
+        Ok(velocity) => cacheable(state.analytics.ttl(), Negotiated(format, velocity)),
+        Err(e) => {
+            error!(error = %e, "Failed to compute velocity");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to compute stats").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state, principal))]
+async fn stats_aging(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    match state.analytics.aging(visible.as_ref()).await {
+        Ok(aging) => cacheable(state.analytics.ttl(), Negotiated(format, aging)),
+        Err(e) => {
+            error!(error = %e, "Failed to compute todo aging");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to compute stats").into_response()
+        }
+    }
+}
+
+/// Creation-to-completion percentiles over the last `days` (30 by default).
+#[instrument(skip(state, principal))]
+async fn stats_completion_time(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<CompletionTimeQuery>,
+) -> Response {
+    let days = query.days.unwrap_or(30);
+    if !(1..=3650).contains(&days) {
+        return (StatusCode::BAD_REQUEST, "days must be between 1 and 3650").into_response();
+    }
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    match state.analytics.completion_time(visible.as_ref(), days).await {
+        Ok(completion_time) => cacheable(state.analytics.ttl(), Negotiated(format, completion_time)),
+        Err(e) => {
+            error!(error = %e, "Failed to compute completion time");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to compute stats").into_response()
+        }
+    }
+}
+
+/// Lets clients and proxies reuse a statistic for as long as the server caches it.
+fn cacheable(ttl: Option<Duration>, body: impl IntoResponse) -> Response {
+    let cache_control = match ttl {
+        Some(ttl) => format!("private, max-age={}", ttl.as_secs()),
+        None => "no-cache".to_string(),
+    };
+    ([(header::CACHE_CONTROL, cache_control)], body).into_response()
+}
+
+/// Same headers as `GET /todos`, from a single aggregate query instead of the full list.
+#[instrument(skip(state, principal))]
+async fn head_todos(State(state): State<AppState>, principal: Option<Extension<Principal>>) -> Response {
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    let load = state.repository.summary(visible.as_ref());
+    match state.query_cache.summary(tenancy::current_tenant(), visible.as_ref(), load).await {
+        Ok(summary) => {
+            info!(count = summary.count, "Summarized todos");
+            freshness::list_headers(&summary).into_response()
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to summarize todos");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Server-sent events for every change to the todo list, until the client disconnects.
+/// Callers `AUTH_REQUIRED` identified only hear of the projects they were a member of when
+/// they connected.
+async fn todo_events(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<EventStreamQuery>,
+) -> Response {
+    match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => state.events.sse(query.envelope, visible).into_response(),
+        Err(rejection) => rejection,
+    }
+}
+
+#[instrument(skip(state, payload), fields(title = %redact::redacted(&payload.title)))]
+async fn create_todo(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<DryRunQuery>,
+    Payload(payload): Payload<CreateTodoRequest>,
+) -> impl IntoResponse {
+    info!("Creating todo");
+
+    state.validator.check_create(&payload, "api").await.map_err(validation_error)?;
+    let now = state.clock.now();
+    let todo = Todo {
+        id: Uuid::new_v4(),
+        title: payload.title,
+        description: payload.description,
+        completed: false,
+        due_at: payload.due_at,
+        tags: normalize_tags(payload.tags),
+        estimate_minutes: payload.estimate_minutes,
+        expires_at: payload.expires_at,
+        project_id: None,
+        priority: payload.priority,
+        pinned: payload.pinned,
+        custom_fields: normalize_custom_fields(payload.custom_fields),
+        created_at: now,
+        updated_at: now,
+        blocked: false,
+        version: 0,
+        change_seq: 0,
+    };
+
+    // Record todo ID in current span
+    Span::current().record("todo.id", tracing::field::display(&todo.id));
+
+    if query.dry_run {
+        info!("Dry run, todo not created");
+        return Ok(Negotiated(format, Todo { version: 1, ..todo }));
+    }
+
+    // Create in database
+    let created_todo = match state.repository.create(todo).await {
+        Ok(t) => t,
+        Err(e) => {
+            error!(error = %e, "Failed to create todo");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to create todo").into_response());
+        }
+    };
+
+    // Queue notification (don't fail the request if this fails)
+    let job = NotificationJob::Created {
+        todo_id: created_todo.id,
+        title: created_todo.title.clone(),
+    };
+    if let Err(e) = state.notifications.enqueue(job).await {
+        warn!(error = %e, "Failed to queue notification, continuing anyway");
+    }
+    state
+        .mentions
+        .description_saved(&created_todo, None, principal.as_deref().map(|p| &p.user))
+        .await;
+
+    info!("Todo created successfully");
+    Ok(Negotiated(format, created_todo))
+}
+
+/// Reads a todo out of free text without creating it, so the client can confirm the
+/// interpretation and then send `title`, `due_at` and `tags` to `POST /todos`.
+#[instrument(skip(state, payload), fields(text = %redact::redacted(&payload.text)))]
+async fn parse_todo(
+    State(state): State<AppState>,
+    format: Format,
+    Payload(payload): Payload<ParseTodoRequest>,
+) -> impl IntoResponse {
+    let offset_secs = payload.utc_offset_minutes.unwrap_or(0).saturating_mul(60);
+    let Some(offset) = FixedOffset::east_opt(offset_secs) else {
+        let message = "utc_offset_minutes must be within a day".to_string();
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, message));
+    };
+    match quick_add::parse(&payload.text, state.clock.now().with_timezone(&offset)) {
+        Ok(parsed) => {
+            info!(recognized = parsed.recognized.len(), "Parsed todo text");
+            Ok(Negotiated(format, parsed))
+        }
+        Err(e) => {
+            warn!(error = %e, "Failed to parse todo text");
+            Err((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+        }
+    }
+}
+
+#[instrument(skip(state, payload), fields(batch_size = payload.todos.len(), batch.atomic = query.atomic))]
+async fn create_batch(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<BatchCreateQuery>,
+    Payload(payload): Payload<BatchCreateRequest>,
+) -> impl IntoResponse {
+    info!(count = payload.todos.len(), "Creating batch of todos");
+
+    let total = payload.todos.len();
+    let items = payload.todos.into_iter().enumerate().collect();
+    let checked = in_batch_order(items, |index, req| {
+        let state = &state;
+        async move {
+            let error = batch_item_error(state, &req).await;
+            (req, error)
+        }
+        .instrument(tracing::info_span!("batch_item_check", item_index = index))
+    })
+    .await;
+    let mut errors = Vec::new();
+    let mut valid = Vec::with_capacity(total);
+    for (index, (req, error)) in checked {
+        match error? {
+            Some(error) => errors.push(BatchItemError { index, error }),
+            None => valid.push((index, req)),
+        }
+    }
+    let mut response = BatchCreateResponse {
+        atomic: query.atomic,
+        dry_run: query.dry_run,
+        created: Vec::new(),
+        total,
+        errors,
+    };
+    if query.atomic && !response.errors.is_empty() {
+        warn!(invalid_items = response.errors.len(), "Atomic batch has invalid items, nothing created");
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Negotiated(format, response)));
+    }
+    if valid.is_empty() {
+        return Ok((StatusCode::OK, Negotiated(format, response)));
+    }
+
+    let now = state.clock.now();
+    let todos: Vec<(usize, Todo)> = valid
+        .into_iter()
+        .map(|(index, req)| (index, Todo {
+            id: Uuid::new_v4(),
+            title: req.title,
+            description: req.description,
+            completed: false,
+            due_at: req.due_at,
+            tags: normalize_tags(req.tags),
+            estimate_minutes: req.estimate_minutes,
+            expires_at: req.expires_at,
+            project_id: None,
+            priority: req.priority,
+            pinned: req.pinned,
+            custom_fields: normalize_custom_fields(req.custom_fields),
+            created_at: now,
+            updated_at: now,
+            blocked: false,
+            version: 0,
+            change_seq: 0,
+        }))
+        .collect();
+
+    if query.dry_run {
+        info!(valid_items = todos.len(), "Dry run, batch not created");
+        response.created = todos.into_iter().map(|(_, todo)| Todo { version: 1, ..todo }).collect();
+        return Ok((StatusCode::OK, Negotiated(format, response)));
+    }
+
+    // Items created one by one get traces of their own, which the summary links to
+    let mut item_traces = Vec::new();
+    if query.atomic {
+        // One transaction, so a failure creates none of them
+        let todos = todos.into_iter().map(|(_, todo)| todo).collect();
+        response.created = match state.repository.create_batch(todos).await {
+            Ok(created) => created,
+            Err(e) => {
+                error!(error = %e, "Batch creation failed");
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, "Batch creation failed").into_response());
+            }
+        };
+    } else {
+        // Items stand alone, so they're created side by side and fail one by one. Each gets a
+        // trace linked back to the batch, so a large batch isn't one sprawling trace
+        let batch = Span::current();
+        let todos: Vec<(usize, (Todo, Span))> = todos
+            .into_iter()
+            .map(|(index, todo)| {
+                let span = tracing::info_span!(parent: None, "batch_item", item_index = index, todo.id = %todo.id);
+                span.follows_from(&batch);
+                (index, (todo, span))
+            })
+            .collect();
+        item_traces = todos
+            .iter()
+            .map(|(_, (_, span))| span.context().span().span_context().clone())
+            .collect();
+        let results = in_batch_order(todos, |_, (todo, span)| state.repository.create(todo).instrument(span)).await;
+        for (index, result) in results {
+            match result {
+                Ok(todo) => response.created.push(todo),
+                Err(e) => {
+                    error!(item_index = index, error = %e, "Batch item creation failed");
+                    let error = "Failed to create todo".to_string();
+                    response.errors.push(BatchItemError { index, error });
+                }
+            }
+        }
+        response.errors.sort_by_key(|error| error.index);
+        if response.created.is_empty() {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Batch creation failed").into_response());
+        }
+    }
+    info!(
+        created_count = response.created.len(),
+        invalid_items = response.errors.len(),
+        "Batch creation successful"
+    );
+    descriptions_created(&state, &response.created, principal.as_deref()).await;
+
+    // Queue batch summary notification
+    let job = NotificationJob::BatchSummary { count: response.created.len() };
+    if let Err(e) = state.notifications.enqueue_linked(job, item_traces).await {
+        warn!(error = %e, "Failed to queue batch summary");
+    }
+
+    Ok((StatusCode::OK, Negotiated(format, response)))
+}
+
+/// Batch items validated or created at the same time.
+const BATCH_CONCURRENCY: usize = 8;
+
+/// Runs `f` on each `(index, item)`, up to `BATCH_CONCURRENCY` at a time. Results come back
+/// sorted by index, however the items finish.
+async fn in_batch_order<T, R, Fut>(items: Vec<(usize, T)>, f: impl Fn(usize, T) -> Fut) -> Vec<(usize, R)>
+where
+    Fut: Future<Output = R>,
+{
+    let mut results: Vec<(usize, R)> = stream::iter(items)
+        .map(|(index, item)| f(index, item).map(move |result| (index, result)))
+        .buffer_unordered(BATCH_CONCURRENCY)
+        .collect()
+        .await;
+    results.sort_by_key(|(index, _)| *index);
+    results
+}
+
+/// Handles mentions in the descriptions of todos a batch or an import created.
+async fn descriptions_created(state: &AppState, todos: &[Todo], principal: Option<&Principal>) {
+    let actor = principal.map(|p| &p.user);
+    for todo in todos {
+        state.mentions.description_saved(todo, None, actor).await;
+    }
+}
+
+/// Why a batch item can't be created, or `None` if it can. Fails the whole request only
+/// when the custom field definitions can't be loaded.
+async fn batch_item_error(state: &AppState, req: &CreateTodoRequest) -> Result<Option<String>, Response> {
+    match state.validator.check_create(req, "batch").await {
+        Ok(()) => Ok(None),
+        Err(e @ ValidationError::Repository(_)) => Err(validation_error(e)),
+        Err(e) => Ok(Some(e.to_string())),
+    }
+}
+
+#[instrument(skip(state, body), fields(import.mode = ?query.mode))]
+async fn import_todos(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<ImportQuery>,
+    body: Body,
+) -> Result<(StatusCode, Json<ImportResponse>), Response> {
+    info!("Importing todos from CSV");
+
+    // Rows have no custom field columns, so a required field refuses the whole upload
+    state.validator.check_custom_fields(&BTreeMap::new(), true).await.map_err(validation_error)?;
+    let parsed = match import::parse_csv(body, state.clock.now(), &state.validator).await {
+        Ok(parsed) => parsed,
+        Err(e @ import::ImportError::TooManyRows) => {
+            warn!(error = %e, "Import rejected");
+            return Err((StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response());
+        }
+        Err(e) => {
+            warn!(error = %e, "Failed to read import");
+            return Err((StatusCode::BAD_REQUEST, e.to_string()).into_response());
+        }
+    };
+
+    let mut response = ImportResponse {
+        mode: query.mode,
+        dry_run: query.dry_run,
+        total_rows: parsed.total_rows,
+        created: 0,
+        errors: parsed.errors,
+    };
+    if query.mode == ImportMode::Transactional && !response.errors.is_empty() {
+        warn!(invalid_rows = response.errors.len(), "Transactional import has invalid rows, nothing imported");
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(response)));
+    }
+    if parsed.todos.is_empty() {
+        return Ok((StatusCode::OK, Json(response)));
+    }
+    if query.dry_run {
+        response.created = parsed.todos.len();
+        info!(created = response.created, invalid_rows = response.errors.len(), "Dry run, nothing imported");
+        return Ok((StatusCode::OK, Json(response)));
+    }
+
+    // The rows go in together, so on success each was stored as parsed
+    let described: Vec<Todo> = parsed.todos.iter().filter(|todo| todo.description.is_some()).cloned().collect();
+    response.created = match state.repository.import(parsed.todos).await {
+        Ok(created) => created,
+        Err(e) => {
+            error!(error = %e, "Import failed");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Import failed").into_response());
+        }
+    };
+    descriptions_created(&state, &described, principal.as_deref()).await;
+
+    let job = NotificationJob::BatchSummary { count: response.created };
+    if let Err(e) = state.notifications.enqueue(job).await {
+        warn!(error = %e, "Failed to queue batch summary");
+    }
+
+    info!(created = response.created, invalid_rows = response.errors.len(), "Import completed");
+    Ok((StatusCode::OK, Json(response)))
+}
+
+/// Todos from a Markdown checklist pasted from an issue or a notes app. Lines that aren't
+/// checklist items are listed in `unparsed`; the items are created together or not at all.
+#[instrument(skip(state, body), fields(import.items, import.unparsed_lines))]
+async fn import_markdown(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<MarkdownImportQuery>,
+    body: String,
+) -> Result<Json<MarkdownImportResponse>, Response> {
+    info!("Importing todos from a Markdown checklist");
+
+    // Checklist items can't carry custom fields either
+    state.validator.check_custom_fields(&BTreeMap::new(), true).await.map_err(validation_error)?;
+    let parsed = match markdown::parse_checklist(&body, state.clock.now()) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            warn!(error = %e, "Import rejected");
+            return Err((StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response());
+        }
+    };
+    for todo in &parsed.todos {
+        state
+            .validator
+            .check_text(Some(&todo.title), todo.description.as_deref(), "import")
+            .map_err(validation_error)?;
+    }
+    Span::current().record("import.items", parsed.todos.len());
+    Span::current().record("import.unparsed_lines", parsed.unparsed.len());
+
+    let mut response = MarkdownImportResponse {
+        dry_run: query.dry_run,
+        created: Vec::new(),
+        unparsed: parsed.unparsed,
+    };
+    if query.dry_run || parsed.todos.is_empty() {
+        response.created = parsed.todos;
+        info!(items = response.created.len(), unparsed_lines = response.unparsed.len(), "Nothing imported");
+        return Ok(Json(response));
+    }
+
+    response.created = match state.repository.create_batch(parsed.todos).await {
+        Ok(created) => created,
+        Err(e) => {
+            error!(error = %e, "Import failed");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Import failed").into_response());
+        }
+    };
+    descriptions_created(&state, &response.created, principal.as_deref()).await;
+
+    let job = NotificationJob::BatchSummary { count: response.created.len() };
+    if let Err(e) = state.notifications.enqueue(job).await {
+        warn!(error = %e, "Failed to queue batch summary");
+    }
+
+    info!(created = response.created.len(), unparsed_lines = response.unparsed.len(), "Import completed");
+    Ok(Json(response))
+}
+
+#[instrument(skip(state), fields(todo.id = %id))]
+async fn get_todo(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<ExpandQuery>,
+) -> Result<Response, Response> {
+    info!("Getting todo");
+
+    let expand = match query.expand.as_deref().map(str::parse::<Expand>).transpose() {
+        Ok(expand) => expand,
+        Err(message) => return Err((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    match state.repository.get(id).await {
+        Ok(todo) => {
+            check_todo_access(&state, principal.as_deref(), &todo, false)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            info!("Todo retrieved");
+            let headers = freshness::todo_headers(&todo);
+            let Some(expand) = expand else {
+                return Ok((headers, Negotiated(format, todo)).into_response());
+            };
+            let mut expanded = expand_todos(&state, expand, vec![todo]).await.map_err(IntoResponse::into_response)?;
+            Ok((without_validators(headers), Negotiated(format, expanded.remove(0))).into_response())
+        }
+        Err(repository::RepositoryError::NotFound(_)) => {
+            warn!("Todo not found");
+            Err((StatusCode::NOT_FOUND, "Todo not found").into_response())
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to get todo");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to retrieve todo").into_response())
+        }
+    }
+}
+
+#[instrument(skip(state, principal), fields(todo.id = %id))]
+async fn head_todo(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    match accessible_todo(&state, principal.as_deref(), id, false).await {
+        Ok(todo) => freshness::todo_headers(&todo).into_response(),
+        Err(rejection) => rejection,
+    }
+}
+
+#[instrument(skip(state, payload), fields(todo.id = %id))]
+async fn update_todo(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<DryRunQuery>,
+    Payload(payload): Payload<UpdateTodoRequest>,
+) -> impl IntoResponse {
+    info!("Updating todo");
+
+    // First, get the existing todo
+    let mut todo = match state.repository.get(id).await {
+        Ok(t) => t,
+        Err(repository::RepositoryError::NotFound(_)) => {
+            warn!("Todo not found for update");
+            return Err((StatusCode::NOT_FOUND, "Todo not found").into_response());
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to get todo for update");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to update todo").into_response());
+        }
+    };
+    check_todo_access(&state, principal.as_deref(), &todo, true)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    state.validator.check_update(&payload, "api").await.map_err(validation_error)?;
+
+    // Track if we're completing a todo
+    let was_completed = todo.completed;
+    let previous_description = todo.description.clone();
+
+    // Update fields
+    payload.apply(&mut todo, state.clock.now());
+
+    if query.dry_run {
+        info!("Dry run, todo not updated");
+        let version = todo.version + 1;
+        return Ok(Negotiated(format, Todo { version, ..todo }));
+    }
+
+    // Update in database
+    let updated_todo = match state.repository.update(todo).await {
+        Ok(t) => t,
+        Err(e) => {
+            error!(error = %e, "Failed to update todo");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to update todo").into_response());
+        }
+    };
+
+    // Send completion notification if todo was just completed
+    let just_completed = !was_completed && updated_todo.completed;
+    if just_completed {
+        let job = NotificationJob::Completed {
+            todo_id: updated_todo.id,
+            title: updated_todo.title.clone(),
+        };
+        if let Err(e) = state.notifications.enqueue(job).await {
+            warn!(error = %e, "Failed to queue completion notification");
+        }
+    }
+    let actor = principal.as_deref().map(|p| &p.user);
+    let change = if just_completed { TodoChange::Completed } else { TodoChange::Updated };
+    state.projects.todo_changed(&updated_todo, change, actor).await;
+    state
+        .mentions
+        .description_saved(&updated_todo, previous_description.as_deref(), actor)
+        .await;
+
+    info!("Todo updated successfully");
+    Ok(Negotiated(format, updated_todo))
+}
+
+/// Longest snooze accepted as a duration: one year.
+const MAX_SNOOZE_MINUTES: i64 = 365 * 24 * 60;
+
+#[instrument(skip(state, payload), fields(todo.id = %id))]
+async fn snooze_todo(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Payload(payload): Payload<SnoozeRequest>,
+) -> impl IntoResponse {
+    let todo = match state.repository.get(id).await {
+        Ok(t) => t,
+        Err(repository::RepositoryError::NotFound(_)) => {
+            warn!("Todo not found for snooze");
+            return Err((StatusCode::NOT_FOUND, "Todo not found"));
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to get todo for snooze");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to snooze todo"));
+        }
+    };
+    check_todo_access(&state, principal.as_deref(), &todo, true).await?;
+    if todo.completed {
+        return Err((StatusCode::CONFLICT, "Completed todos can't be snoozed"));
+    }
+
+    // A duration pushes from the current due date, or from now if that has already passed
+    let now = state.clock.now();
+    let until = match (payload.minutes, payload.until) {
+        (Some(minutes), None) if (1..=MAX_SNOOZE_MINUTES).contains(&minutes) => {
+            todo.due_at.map_or(now, |due_at| due_at.max(now)) + chrono::Duration::minutes(minutes)
+        }
+        (Some(_), None) => {
+            return Err((StatusCode::BAD_REQUEST, "minutes must be between 1 and 525600"));
+        }
+        (None, Some(until)) if until > now => until,
+        (None, Some(_)) => return Err((StatusCode::BAD_REQUEST, "until must be in the future")),
+        _ => return Err((StatusCode::BAD_REQUEST, "Give exactly one of minutes or until")),
+    };
+
+    match state.repository.snooze(id, until).await {
+        Ok(todo) => {
+            info!(snoozed_until = %until, "Todo snoozed");
+            state
+                .projects
+                .todo_changed(&todo, TodoChange::Updated, principal.as_deref().map(|p| &p.user))
+                .await;
+            Ok(Negotiated(format, todo))
+        }
+        Err(repository::RepositoryError::NotFound(_)) => Err((StatusCode::NOT_FOUND, "Todo not found")),
+        Err(e) => {
+            error!(error = %e, "Failed to snooze todo");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to snooze todo"))
+        }
+    }
+}
+
+#[instrument(skip(state, principal), fields(todo.id = %id))]
+async fn todo_history(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(rejection) = accessible_todo(&state, principal.as_deref(), id, false).await {
+        return rejection;
+    }
+    match state.repository.history(id).await {
+        Ok(history) => Negotiated(format, history).into_response(),
+        Err(repository::RepositoryError::NotFound(_)) => (StatusCode::NOT_FOUND, "Todo not found").into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to load todo history");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load todo history").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state), fields(todo.id = %id))]
+async fn list_comments(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+) -> impl IntoResponse {
+    let todo = match state.repository.get(id).await {
+        Ok(todo) => todo,
+        Err(repository::RepositoryError::NotFound(_)) => return Err((StatusCode::NOT_FOUND, "Todo not found")),
+        Err(e) => {
+            error!(error = %e, "Failed to get todo for comments");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to load comments"));
+        }
+    };
+    check_todo_access(&state, principal.as_deref(), &todo, false).await?;
+
+    match state.mentions.comments(id).await {
+        Ok(comments) => Ok(Negotiated(format, comments)),
+        Err(e) => {
+            error!(error = %e, "Failed to load comments");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to load comments"))
+        }
+    }
+}
+
+/// Anyone who can see a todo can comment on it, including project viewers.
+#[instrument(skip(state, payload), fields(todo.id = %id))]
+async fn create_comment(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Payload(payload): Payload<CreateCommentRequest>,
+) -> Response {
+    let todo = match state.repository.get(id).await {
+        Ok(todo) => todo,
+        Err(repository::RepositoryError::NotFound(_)) => {
+            return (StatusCode::NOT_FOUND, "Todo not found").into_response();
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to get todo for comment");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to add comment").into_response();
+        }
+    };
+    if let Err(rejection) = check_todo_access(&state, principal.as_deref(), &todo, false).await {
+        return rejection.into_response();
+    }
+
+    let actor = principal.as_deref().map(|p| &p.user);
+    match state.mentions.add_comment(&todo, &payload.body, actor).await {
+        Ok(comment) => (StatusCode::CREATED, Negotiated(format, comment)).into_response(),
+        Err(e @ MentionError::InvalidComment) => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to add comment");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to add comment").into_response()
+        }
+    }
+}
+
+/// The todo, if the caller may see it or, with `edit`, change it.
+async fn accessible_todo(
+    state: &AppState,
+    principal: Option<&Principal>,
+    id: Uuid,
+    edit: bool,
+) -> Result<Todo, Response> {
+    let todo = match state.repository.get(id).await {
+        Ok(todo) => todo,
+        Err(repository::RepositoryError::NotFound(_)) => {
+            return Err((StatusCode::NOT_FOUND, "Todo not found").into_response());
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to get todo");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to load todo").into_response());
+        }
+    };
+    check_todo_access(state, principal, &todo, edit)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    Ok(todo)
+}
+
+#[instrument(skip(state), fields(todo.id = %id))]
+async fn list_attachments(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(rejection) = accessible_todo(&state, principal.as_deref(), id, false).await {
+        return rejection;
+    }
+    match state.attachments.list(id).await {
+        Ok(attachments) => Negotiated(format, attachments).into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to list attachments");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list attachments").into_response()
+        }
+    }
+}
+
+/// The body is the file; its `Content-Type` is stored and served back with it.
+#[instrument(skip(state, headers, body), fields(todo.id = %id))]
+async fn upload_attachment(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<UploadQuery>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let todo = match accessible_todo(&state, principal.as_deref(), id, true).await {
+        Ok(todo) => todo,
+        Err(rejection) => return rejection,
+    };
+    let max_bytes = state.attachments.max_bytes();
+    // Read one byte past the limit, so a body that is too large is told apart from one that fits
+    let data = match axum::body::to_bytes(body, max_bytes.saturating_add(1)).await {
+        Ok(data) => data.to_vec(),
+        Err(_) => {
+            let message = AttachmentError::TooLarge(max_bytes).to_string();
+            return (StatusCode::PAYLOAD_TOO_LARGE, message).into_response();
+        }
+    };
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+
+    let uploader = principal.as_deref().map(|p| &p.user);
+    match state.attachments.upload(&todo, &query.filename, content_type, data, uploader).await {
+        Ok(attachment) => (StatusCode::CREATED, Negotiated(format, attachment)).into_response(),
+        Err(e @ AttachmentError::TooLarge(_)) => (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response(),
+        Err(e @ (AttachmentError::InvalidFilename | AttachmentError::Empty)) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()
+        }
+        Err(AttachmentError::Scan(e @ ScanError::Rejected(_))) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()
+        }
+        Err(AttachmentError::Scan(e @ ScanError::Unavailable { .. })) => {
+            error!(error = %e, "Failed to scan attachment");
+            (StatusCode::SERVICE_UNAVAILABLE, "Upload scanning is unavailable").into_response()
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to store attachment");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to store attachment").into_response()
+        }
+    }
+}
+
+/// Redirects to the blob store when it hands out links, and serves the file otherwise.
+#[instrument(skip(state), fields(todo.id = %id, attachment.id = %attachment_id))]
+async fn download_attachment(
+    State(state): State<AppState>,
+    Path((id, attachment_id)): Path<(Uuid, Uuid)>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(rejection) = accessible_todo(&state, principal.as_deref(), id, false).await {
+        return rejection;
+    }
+    let attachment = match state.attachments.get(id, attachment_id).await {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Attachment not found").into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to get attachment");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load attachment").into_response();
+        }
+    };
+
+    match state.attachments.download(&attachment).await {
+        Ok(Download::Redirect(url)) => Redirect::temporary(&url).into_response(),
+        Ok(Download::Bytes(data)) => (
+            [
+                (header::CONTENT_TYPE, attachment.content_type.clone()),
+                (header::CONTENT_DISPOSITION, attachments::content_disposition(&attachment.filename)),
+                // Uploaded HTML must not run as this origin's page
+                (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            ],
+            data,
+        )
+            .into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to read attachment");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load attachment").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state), fields(todo.id = %id, attachment.id = %attachment_id))]
+async fn delete_attachment(
+    State(state): State<AppState>,
+    Path((id, attachment_id)): Path<(Uuid, Uuid)>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(rejection) = accessible_todo(&state, principal.as_deref(), id, true).await {
+        return rejection;
+    }
+    match state.attachments.remove(id, attachment_id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Attachment not found").into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to delete attachment");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete attachment").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state, principal))]
+async fn sync_changes(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<SyncQuery>,
+) -> Response {
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    match state.sync.changes(query.since.as_deref(), query.limit, visible.as_ref()).await {
+        Ok(changes) => Negotiated(format, changes).into_response(),
+        Err(SyncError::InvalidToken) => (StatusCode::BAD_REQUEST, "Invalid sync token").into_response(),
+        Err(SyncError::ExpiredToken) => {
+            (StatusCode::GONE, "Sync token expired; sync again without `since`").into_response()
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to load changes");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load changes").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state, principal, payload), fields(count = payload.mutations.len()))]
+async fn sync_push(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Payload(payload): Payload<SyncPush>,
+) -> Response {
+    let editable = match editable_projects(&state, principal.as_deref()).await {
+        Ok(editable) => editable,
+        Err(rejection) => return rejection,
+    };
+    let actor = principal.as_deref().map(|p| &p.user);
+    match state.sync.push(payload.mutations, editable.as_ref(), actor).await {
+        Ok(response) => {
+            if response.conflicts > 0 {
+                warn!(conflicts = response.conflicts, "Pushed changes conflict with the server");
+            }
+            Negotiated(format, response).into_response()
+        }
+        Err(e @ (SyncError::TooManyMutations | SyncError::Invalid { source: ValidationError::DescriptionTooLarge(_), .. })) => {
+            (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response()
+        }
+        Err(e @ SyncError::Invalid { .. }) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to apply pushed changes");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to apply pushed changes").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state, principal, payload), fields(todo.id = %id, todo.blocker_id = %payload.blocker_id))]
+async fn add_blocker(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Payload(payload): Payload<AddDependencyRequest>,
+) -> Response {
+    if payload.blocker_id == id {
+        return (StatusCode::BAD_REQUEST, "A todo can't block itself").into_response();
+    }
+    // Blocking changes the todo, and shows whether the blocker is done
+    if let Err(rejection) = accessible_todo(&state, principal.as_deref(), id, true).await {
+        return rejection;
+    }
+    match accessible_todo(&state, principal.as_deref(), payload.blocker_id, false).await {
+        Ok(_) => {}
+        Err(rejection) if rejection.status() == StatusCode::NOT_FOUND => {
+            return (StatusCode::NOT_FOUND, "Blocker not found").into_response();
+        }
+        Err(rejection) => return rejection,
+    }
+
+    let response = match state.repository.add_dependency(payload.blocker_id, id).await {
+        Ok(todo) => {
+            info!("Blocker added");
+            Ok(Negotiated(format, todo))
+        }
+        Err(repository::RepositoryError::NotFound(missing)) if missing == id => {
+            Err((StatusCode::NOT_FOUND, "Todo not found"))
+        }
+        Err(repository::RepositoryError::NotFound(_)) => Err((StatusCode::NOT_FOUND, "Blocker not found")),
+        Err(repository::RepositoryError::DependencyCycle { .. }) => {
+            Err((StatusCode::CONFLICT, "Blocker already depends on this todo"))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to add blocker");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to add blocker"))
+        }
+    };
+    response.into_response()
+}
+
+#[instrument(skip(state, principal), fields(todo.id = %id, todo.blocker_id = %blocker_id))]
+async fn remove_blocker(
+    State(state): State<AppState>,
+    Path((id, blocker_id)): Path<(Uuid, Uuid)>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(rejection) = accessible_todo(&state, principal.as_deref(), id, true).await {
+        return rejection;
+    }
+    let response = match state.repository.remove_dependency(blocker_id, id).await {
+        Ok(()) => {
+            info!("Blocker removed");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(repository::RepositoryError::NotFound(_)) => {
+            Err((StatusCode::NOT_FOUND, "Todo is not blocked by that todo"))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to remove blocker");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to remove blocker"))
+        }
+    };
+    response.into_response()
+}
+
+#[instrument(skip(state), fields(todo.id = %id))]
+async fn delete_todo(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    principal: Option<Extension<Principal>>,
+) -> impl IntoResponse {
+    info!("Deleting todo");
+
+    // A todo in a project needs the caller's role checked first, and its members told after
+    let todo = match state.repository.get(id).await {
+        Ok(todo) if todo.project_id.is_some() => Some(todo),
+        Ok(_) | Err(repository::RepositoryError::NotFound(_)) => None,
+        Err(e) => {
+            error!(error = %e, "Failed to get todo for deletion");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete todo"));
+        }
+    };
+    if let Some(todo) = &todo {
+        check_todo_access(&state, principal.as_deref(), todo, true).await?;
+    }
+
+    match state.repository.delete(id).await {
+        Ok(()) => {
+            info!("Todo deleted");
+            if let Some(todo) = &todo {
+                state
+                    .projects
+                    .todo_changed(todo, TodoChange::Deleted, principal.as_deref().map(|p| &p.user))
+                    .await;
+            }
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(repository::RepositoryError::NotFound(_)) => {
+            warn!("Todo not found for deletion");
+            Err((StatusCode::NOT_FOUND, "Todo not found"))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to delete todo");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete todo"))
+        }
+    }
+}
+
+/// Adds and removes tags on many todos at once; see `TagService::tag_todos`. Callers
+/// `AUTH_REQUIRED` identified only change todos of projects they can edit.
+#[instrument(skip(state, principal, payload))]
+async fn tag_todos(
+    State(state): State<AppState>,
+    format: Format,
+    principal: Option<Extension<Principal>>,
+    Payload(payload): Payload<BulkTagRequest>,
+) -> Response {
+    let editable = match editable_projects(&state, principal.as_deref()).await {
+        Ok(editable) => editable,
+        Err(rejection) => return rejection,
+    };
+    match state.tags.tag_todos(payload, editable.as_ref()).await {
+        Ok(response) => Negotiated(format, response).into_response(),
+        Err(e) => tag_error(e),
+    }
+}
+
+/// Deleting goes through the repository, which sends a `deleted` event for each todo.
+/// Callers `AUTH_REQUIRED` identified only delete todos of projects they can edit.
+#[instrument(skip(state, client, principal))]
+async fn delete_completed(
+    State(state): State<AppState>,
+    format: Format,
+    client: ClientInfo,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    info!("Deleting all completed todos");
+
+    let editable = match editable_projects(&state, principal.as_deref()).await {
+        Ok(editable) => editable,
+        Err(rejection) => return rejection,
+    };
+    let response = match state.repository.delete_completed(editable.as_ref()).await {
+        Ok(ids) => {
+            info!(deleted_count = ids.len(), "Completed todos deleted");
+            if !ids.is_empty() {
+                let detail = serde_json::json!({"count": ids.len(), "ids": ids});
+                audit_admin(&state, &client, principal.as_deref(), AuditEvent::CompletedDeleted, detail).await;
+            }
+            Ok(Negotiated(
+                format,
+                DeleteCompletedResponse {
+                    deleted_count: ids.len(),
+                    deleted_ids: ids,
+                },
+            ))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to delete completed todos");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete completed todos"))
+        }
+    };
+    response.into_response()
+}
+
+#[instrument(skip(state, client, principal))]
+async fn create_backup(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    principal: Option<Extension<Principal>>,
+) -> impl IntoResponse {
+    info!("Creating database backup");
+
+    match state.backup_service.create_snapshot().await {
+        Ok(snapshot) => {
+            let name = snapshot
+                .path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let detail = serde_json::json!({"snapshot": name, "checksum": snapshot.checksum});
+            audit_admin(&state, &client, principal.as_deref(), AuditEvent::BackupCreated, detail).await;
+            Ok(Json(BackupResponse {
+                snapshot: name,
+                path: snapshot.path.display().to_string(),
+                checksum: snapshot.checksum,
+                size_bytes: snapshot.size_bytes,
+                created_at: snapshot.created_at,
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to create backup");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to create backup"))
+        }
+    }
+}
+
+#[instrument(skip(state, client, principal, payload), fields(backup.snapshot = %payload.snapshot))]
+async fn restore_backup(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    principal: Option<Extension<Principal>>,
+    Json(payload): Json<RestoreRequest>,
+) -> impl IntoResponse {
+    info!("Restoring database backup");
+
+    match state
+        .backup_service
+        .restore_snapshot(&payload.snapshot, payload.checksum.as_deref())
+        .await
+    {
+        Ok(restored_count) => {
+            info!(restored_count, "Backup restored");
+            state.events.publish(TodoEvent::Resync);
+            let detail = serde_json::json!({"snapshot": payload.snapshot, "restored_count": restored_count});
+            audit_admin(&state, &client, principal.as_deref(), AuditEvent::BackupRestored, detail).await;
+            Ok(Json(RestoreResponse {
+                snapshot: payload.snapshot,
+                restored_count,
+            }))
+        }
+        Err(BackupError::InvalidName(_)) => {
+            warn!("Invalid snapshot name");
+            Err((StatusCode::BAD_REQUEST, "Invalid snapshot name"))
+        }
+        Err(BackupError::NotFound(_)) => {
+            warn!("Snapshot not found");
+            Err((StatusCode::NOT_FOUND, "Snapshot not found"))
+        }
+        Err(BackupError::ChecksumMismatch(_)) => {
+            warn!("Snapshot checksum mismatch");
+            Err((StatusCode::UNPROCESSABLE_ENTITY, "Snapshot checksum mismatch"))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to restore backup");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to restore backup"))
+        }
+    }
+}
+
+/// Admin routes that need the `admin` scope whether or not `AUTH_REQUIRED` is set: backups
+/// and restores, since a restore replaces every todo, and the audit log and API usage, which
+/// name users and their addresses. Without authentication configured they answer `404`.
+fn admin_only_router(auth: Option<Arc<AuthService>>) -> Router<AppState> {
+    let routes = Router::new()
+        .route("/admin/backup", post(create_backup))
+        .route("/admin/restore", post(restore_backup))
+        .route("/admin/audit", get(audit_log))
+        .route("/admin/usage", get(api_usage));
+    match auth {
+        // The router-wide layer already checks the scope
+        Some(auth) if auth.required() => routes,
+        Some(auth) => routes.route_layer(middleware::from_fn_with_state(auth, auth::require_scope)),
+        None => routes.route_layer(middleware::from_fn(without_auth)),
+    }
+}
+
+async fn without_auth(_req: axum::extract::Request, _next: middleware::Next) -> Response {
+    (StatusCode::NOT_FOUND, "Authentication is not enabled").into_response()
+}
+
+fn auth_service(state: &AppState) -> Result<&AuthService, (StatusCode, String)> {
+    state
+        .auth
+        .as_deref()
+        .ok_or((StatusCode::NOT_FOUND, "Authentication is not enabled".to_string()))
+}
+
+fn auth_error(e: AuthError) -> Response {
+    let status = match &e {
+        AuthError::InvalidEmail
+        | AuthError::InvalidPassword
+        | AuthError::InvalidUsername
+        | AuthError::InvalidTokenRequest(_) => StatusCode::BAD_REQUEST,
+        AuthError::MissingScope(_) | AuthError::SessionRequired => StatusCode::FORBIDDEN,
+        AuthError::TokenNotFound | AuthError::ImpersonatedUserNotFound => StatusCode::NOT_FOUND,
+        AuthError::EmailTaken | AuthError::UsernameTaken => StatusCode::CONFLICT,
+        AuthError::InvalidCredentials | AuthError::InvalidToken | AuthError::InvalidRefreshToken => {
+            StatusCode::UNAUTHORIZED
+        }
+        AuthError::TooManyAttempts { retry_after } => {
+            let retry_after = retry_after.as_secs().max(1);
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                e.to_string(),
+            )
+                .into_response();
+        }
+        AuthError::Repository(_) | AuthError::Hash(_) => {
+            error!(error = %e, "Authentication failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Authentication failed").into_response();
+        }
+    };
+    (status, e.to_string()).into_response()
+}
+
+#[instrument(skip(state, client, payload))]
+async fn register(State(state): State<AppState>, client: ClientInfo, Json(payload): Json<Credentials>) -> Response {
+    let auth = match auth_service(&state) {
+        Ok(auth) => auth,
+        Err(e) => return e.into_response(),
+    };
+    match auth.register(&payload.email, &payload.password, &client).await {
+        Ok(user) => (StatusCode::CREATED, Json(user)).into_response(),
+        Err(e) => auth_error(e),
+    }
+}
+
+#[instrument(skip(state, client, payload))]
+async fn login(State(state): State<AppState>, client: ClientInfo, Json(payload): Json<Credentials>) -> Response {
+    let auth = match auth_service(&state) {
+        Ok(auth) => auth,
+        Err(e) => return e.into_response(),
+    };
+    match auth.login(&payload.email, &payload.password, &client).await {
+        Ok(session) => token_response(auth, session),
+        Err(e) => auth_error(e),
+    }
+}
+
+fn token_response(auth: &AuthService, session: Session) -> Response {
+    Json(TokenResponse {
+        access_token: session.access_token,
+        token_type: "Bearer",
+        expires_in: auth.token_ttl().as_secs(),
+        refresh_token: session.refresh_token,
+    })
+    .into_response()
+}
+
+#[instrument(skip(state, client, payload))]
+async fn refresh_session(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Json(payload): Json<RefreshRequest>,
+) -> Response {
+    let auth = match auth_service(&state) {
+        Ok(auth) => auth,
+        Err(e) => return e.into_response(),
+    };
+    match auth.refresh(&payload.refresh_token, &client).await {
+        Ok(session) => token_response(auth, session),
+        Err(e) => auth_error(e),
+    }
+}
+
+#[instrument(skip(state, client, payload))]
+async fn logout(State(state): State<AppState>, client: ClientInfo, Json(payload): Json<RefreshRequest>) -> Response {
+    let auth = match auth_service(&state) {
+        Ok(auth) => auth,
+        Err(e) => return e.into_response(),
+    };
+    match auth.logout(&payload.refresh_token, &client).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => auth_error(e),
+    }
+}
+
+/// The account behind the bearer credential, session or personal access token.
+#[instrument(skip(state, headers))]
+async fn current_user(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    match principal(&state, &headers).await {
+        Ok((_, principal)) => Json(principal.user).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Resolves the request's bearer credential for the `/auth` handlers.
+async fn principal<'a>(state: &'a AppState, headers: &HeaderMap) -> Result<(&'a AuthService, Principal), Response> {
+    let auth = auth_service(state).map_err(IntoResponse::into_response)?;
+    let bearer = auth::bearer_token(headers).ok_or_else(|| auth_error(AuthError::InvalidToken))?;
+    let principal = auth.principal(bearer).await.map_err(auth_error)?;
+    Ok((auth, principal))
+}
+
+#[instrument(skip(state, headers, client, payload))]
+async fn create_api_token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: ClientInfo,
+    Json(payload): Json<CreateApiTokenRequest>,
+) -> Response {
+    let (auth, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match auth.create_api_token(&principal, payload, &client).await {
+        Ok(created) => (StatusCode::CREATED, Json(created)).into_response(),
+        Err(e) => auth_error(e),
+    }
+}
+
+#[instrument(skip(state, headers))]
+async fn list_api_tokens(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let (auth, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match auth.list_api_tokens(&principal).await {
+        Ok(tokens) => Json(tokens).into_response(),
+        Err(e) => auth_error(e),
+    }
+}
+
+#[instrument(skip(state, headers, client), fields(api_token.id = %id))]
+async fn revoke_api_token(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    client: ClientInfo,
+) -> Response {
+    let (auth, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match auth.revoke_api_token(&principal, id, &client).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => auth_error(e),
+    }
+}
+
+/// Everything stored about the caller's account. Large exports are built in the background
+/// and answered with `202` and a download link.
+#[instrument(skip(state, headers, client))]
+async fn export_user_data(State(state): State<AppState>, headers: HeaderMap, client: ClientInfo) -> Response {
+    let (auth, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    if !principal.session {
+        return auth_error(AuthError::SessionRequired);
+    }
+    let Some(exporter) = &state.user_export else {
+        return (StatusCode::NOT_FOUND, "Authentication is not enabled").into_response();
+    };
+
+    let user = &principal.user;
+    let outcome = match exporter.export(user).await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            error!(error = %e, "Failed to export user data");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to export user data").into_response();
+        }
+    };
+    auth.audit()
+        .record(&client, AuditEvent::DataExported, Some(user.id), Some(&user.email), serde_json::json!({}))
+        .await;
+    match outcome {
+        ExportOutcome::Inline(export) => Json(export).into_response(),
+        ExportOutcome::Started(job) => {
+            (StatusCode::ACCEPTED, [(header::LOCATION, job.download_url.clone())], Json(job)).into_response()
+        }
+    }
+}
+
+/// `202` while the export is being written, then the file itself.
+#[instrument(skip(state, headers), fields(export.id = %id))]
+async fn download_user_export(State(state): State<AppState>, Path(id): Path<Uuid>, headers: HeaderMap) -> Response {
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    if !principal.session {
+        return auth_error(AuthError::SessionRequired);
+    }
+    let Some(exporter) = &state.user_export else {
+        return (StatusCode::NOT_FOUND, "Authentication is not enabled").into_response();
+    };
+
+    let file = match exporter.file(principal.user.id, id).await {
+        Ok(ExportFile::Ready(path)) => tokio::fs::File::open(path).await,
+        Ok(ExportFile::Pending) => {
+            let job = ExportJob {
+                id,
+                status: "pending",
+                download_url: format!("/users/me/exports/{id}"),
+            };
+            return (StatusCode::ACCEPTED, Json(job)).into_response();
+        }
+        Ok(ExportFile::Missing) => return (StatusCode::NOT_FOUND, "Export not found").into_response(),
+        Err(e) => Err(std::io::Error::other(e)),
+    };
+    match file {
+        Ok(file) => (
+            [
+                (header::CONTENT_TYPE, "application/json".to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"export-{id}.json\"")),
+            ],
+            Body::from_stream(tokio_util::io::ReaderStream::new(file)),
+        )
+            .into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to read user export");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read user export").into_response()
+        }
+    }
+}
+
+/// Starts erasing the caller's account and everything stored about it. Needs a session
+/// token and the account's password; answers `202` with where to follow progress.
+#[instrument(skip(state, headers, client, payload))]
+async fn delete_account(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: ClientInfo,
+    Json(payload): Json<DeleteAccountRequest>,
+) -> Response {
+    let (auth, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    if !principal.session {
+        return auth_error(AuthError::SessionRequired);
+    }
+    let Some(erasure) = &state.erasure else {
+        return (StatusCode::NOT_FOUND, "Authentication is not enabled").into_response();
+    };
+    let user = &principal.user;
+    if let Err(e) = auth.confirm_password(user, &payload.password).await {
+        return auth_error(e);
+    }
+
+    auth.audit()
+        .record(&client, AuditEvent::ErasureRequested, Some(user.id), Some(&user.email), serde_json::json!({}))
+        .await;
+    match erasure.start(user).await {
+        Ok(progress) => {
+            auth.forget_user(user.id);
+            let location = format!("/users/erasures/{}", progress.job.id);
+            (StatusCode::ACCEPTED, [(header::LOCATION, location)], Json(progress)).into_response()
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to start account erasure");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start account erasure").into_response()
+        }
+    }
+}
+
+/// Progress of an erasure. The job id is the only credential, since the account is gone.
+#[instrument(skip(state), fields(erasure.id = %id))]
+async fn erasure_progress(State(state): State<AppState>, Path(id): Path<Uuid>) -> Response {
+    let Some(erasure) = &state.erasure else {
+        return (StatusCode::NOT_FOUND, "Authentication is not enabled").into_response();
+    };
+    match erasure.progress(id).await {
+        Ok(Some(progress)) => Json(progress).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Erasure not found").into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to read erasure progress");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read erasure progress").into_response()
+        }
+    }
+}
+
+fn project_error(e: ProjectError) -> Response {
+    let status = match &e {
+        ProjectError::NotFound | ProjectError::NotMember => StatusCode::NOT_FOUND,
+        ProjectError::Forbidden(_) => StatusCode::FORBIDDEN,
+        ProjectError::InvalidName => StatusCode::BAD_REQUEST,
+        ProjectError::UnknownUser => StatusCode::UNPROCESSABLE_ENTITY,
+        ProjectError::LastOwner => StatusCode::CONFLICT,
+        ProjectError::Repository(_) => {
+            error!(error = %e, "Project request failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Project request failed").into_response();
+        }
+    };
+    (status, e.to_string()).into_response()
+}
+
+/// Checks that the caller `AUTH_REQUIRED` identified may see the todo or, with `edit`,
+/// change it. Todos of projects they aren't a member of are reported as not found.
+async fn check_todo_access(
+    state: &AppState,
+    principal: Option<&Principal>,
+    todo: &Todo,
+    edit: bool,
+) -> Result<(), (StatusCode, &'static str)> {
+    let Some(principal) = principal else {
+        return Ok(());
+    };
+    match state.projects.check_todo(&principal.user, todo, edit).await {
+        Ok(()) => Ok(()),
+        Err(ProjectError::NotFound) => Err((StatusCode::NOT_FOUND, "Todo not found")),
+        Err(ProjectError::Forbidden(_)) => Err((StatusCode::FORBIDDEN, "Viewers can't change this project's todos")),
+        Err(e) => {
+            error!(error = %e, "Failed to check project access");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to check project access"))
+        }
+    }
+}
+
+/// Projects whose todos the caller `AUTH_REQUIRED` identified may see, or `None` when nobody
+/// is signed in and every todo is open.
+async fn visible_projects(state: &AppState, principal: Option<&Principal>) -> Result<Option<HashSet<Uuid>>, Response> {
+    match principal {
+        Some(principal) => state.projects.visible(&principal.user).await.map(Some).map_err(project_error),
+        None => Ok(None),
+    }
+}
+
+/// Projects whose todos the caller `AUTH_REQUIRED` identified may change, or `None` when
+/// nobody is signed in.
+async fn editable_projects(state: &AppState, principal: Option<&Principal>) -> Result<Option<HashSet<Uuid>>, Response> {
+    match principal {
+        Some(principal) => state.projects.editable(&principal.user).await.map(Some).map_err(project_error),
+        None => Ok(None),
+    }
+}
+
+/// `413` for a description over the limit, saying what to do instead, and `422` for
+/// anything else `TodoValidator` refuses.
+fn validation_error(e: ValidationError) -> Response {
+    match e {
+        ValidationError::Repository(e) => {
+            error!(error = %e, "Failed to load custom fields");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to check custom fields").into_response()
+        }
+        e @ ValidationError::DescriptionTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response(),
+        e => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response(),
+    }
+}
+
+#[instrument(skip(state, headers, payload))]
+async fn create_project(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateProjectRequest>,
+) -> Response {
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match state.projects.create(&principal.user, &payload.name).await {
+        Ok(project) => (StatusCode::CREATED, Json(project)).into_response(),
+        Err(e) => project_error(e),
+    }
+}
+
+#[instrument(skip(state, headers, payload))]
+async fn update_profile(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<UpdateProfileRequest>,
+) -> Response {
+    let (auth, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match auth.update_profile(&principal.user, payload).await {
+        Ok(user) => Json(user).into_response(),
+        Err(e) => auth_error(e),
+    }
+}
+
+/// Mentions of the caller, newest first.
+#[instrument(skip(state, headers))]
+async fn activity_feed(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ActivityQuery>,
+) -> Response {
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match state.mentions.feed(&principal.user, query.before, query.limit).await {
+        Ok(items) => Json(items).into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to load activity feed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load activity feed").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state, headers))]
+async fn list_projects(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match state.projects.list(&principal.user).await {
+        Ok(projects) => Json(projects).into_response(),
+        Err(e) => project_error(e),
+    }
+}
+
+#[instrument(skip(state, headers), fields(project.id = %id))]
+async fn get_project(State(state): State<AppState>, Path(id): Path<Uuid>, headers: HeaderMap) -> Response {
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match state.projects.authorize(&principal.user, id, ProjectRole::Viewer).await {
+        Ok(project) => Json(project).into_response(),
+        Err(e) => project_error(e),
+    }
+}
+
+#[instrument(skip(state, headers), fields(project.id = %id))]
+async fn list_project_members(State(state): State<AppState>, Path(id): Path<Uuid>, headers: HeaderMap) -> Response {
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match state.projects.members(&principal.user, id).await {
+        Ok(members) => Json(members).into_response(),
+        Err(e) => project_error(e),
+    }
+}
+
+#[instrument(skip(state, headers, payload), fields(project.id = %id, role = payload.role.as_str()))]
+async fn add_project_member(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(payload): Json<AddMemberRequest>,
+) -> Response {
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match state
+        .projects
+        .add_member(&principal.user, id, &payload.email, payload.role)
+        .await
+    {
+        Ok(member) => Json(member).into_response(),
+        Err(e) => project_error(e),
+    }
+}
+
+#[instrument(skip(state, headers), fields(project.id = %id, member.id = %user_id))]
+async fn remove_project_member(
+    State(state): State<AppState>,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+    headers: HeaderMap,
+) -> Response {
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match state.projects.remove_member(&principal.user, id, user_id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => project_error(e),
+    }
+}
+
+/// Keeps the caller present on the project while the stream is open, sending who else is.
+#[instrument(skip(state, headers), fields(project.id = %id))]
+async fn project_presence(State(state): State<AppState>, Path(id): Path<Uuid>, headers: HeaderMap) -> Response {
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    match state.projects.authorize(&principal.user, id, ProjectRole::Viewer).await {
+        Ok(_) => state.presence.sse(id, &principal.user).into_response(),
+        Err(e) => project_error(e),
+    }
+}
+
+#[instrument(skip(state, headers), fields(project.id = %id))]
+async fn list_project_todos(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    format: Format,
+    headers: HeaderMap,
+    uri: Uri,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let page = match pagination::Page::from_query(&query) {
+        Ok(page) => page,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    let window = page.map(|page| page.window());
+    match state.projects.todos(&principal.user, id, window).await {
+        Ok(list) => match page {
+            Some(page) => {
+                let headers = pagination::paged_headers(&list.summary, Some(page), &uri);
+                paged_response(format, headers, list.todos, list.summary.count, page)
+            }
+            None => (freshness::list_headers(&list.summary), Negotiated(format, Arc::new(list.todos))).into_response(),
+        },
+        Err(e) => project_error(e),
+    }
+}
+
+/// Like `POST /todos`, for editors and owners of the project.
+#[instrument(skip(state, headers, payload), fields(project.id = %id, todo.id))]
+async fn create_project_todo(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    format: Format,
+    headers: HeaderMap,
+    Payload(payload): Payload<CreateTodoRequest>,
+) -> Response {
+    let (_, principal) = match principal(&state, &headers).await {
+        Ok(resolved) => resolved,
+        Err(response) => return response,
+    };
+    if let Err(e) = state.projects.authorize(&principal.user, id, ProjectRole::Editor).await {
+        return project_error(e);
+    }
+    if let Err(e) = state.validator.check_create(&payload, "api").await {
+        return validation_error(e);
+    }
+
+    let now = state.clock.now();
+    let todo = Todo {
+        id: Uuid::new_v4(),
+        title: payload.title,
+        description: payload.description,
+        completed: false,
+        due_at: payload.due_at,
+        tags: normalize_tags(payload.tags),
+        estimate_minutes: payload.estimate_minutes,
+        expires_at: payload.expires_at,
+        project_id: Some(id),
+        priority: payload.priority,
+        pinned: payload.pinned,
+        custom_fields: normalize_custom_fields(payload.custom_fields),
+        created_at: now,
+        updated_at: now,
+        blocked: false,
+        version: 0,
+        change_seq: 0,
+    };
+    Span::current().record("todo.id", tracing::field::display(&todo.id));
+
+    match state.repository.create(todo).await {
+        Ok(todo) => {
+            info!("Project todo created");
+            state
+                .projects
+                .todo_changed(&todo, TodoChange::Created, Some(&principal.user))
+                .await;
+            state.mentions.description_saved(&todo, None, Some(&principal.user)).await;
+            Negotiated(format, todo).into_response()
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to create project todo");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create todo").into_response()
+        }
+    }
+}
+
+/// Authentication and admin events, newest first.
+#[instrument(skip(state))]
+async fn audit_log(State(state): State<AppState>, Query(query): Query<AuditQuery>) -> Response {
+    let auth = match auth_service(&state) {
+        Ok(auth) => auth,
+        Err(e) => return e.into_response(),
+    };
+    match auth.audit().list(&query).await {
+        Ok(entries) => Json(entries).into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to read audit log");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read audit log").into_response()
+        }
+    }
+}
+
+/// The slowest repository calls in the profiling window (20 by default) and a per-method summary.
+#[instrument(skip(state))]
+async fn slow_queries(State(state): State<AppState>, Query(query): Query<SlowQueryQuery>) -> Response {
+    let Some(profiler) = &state.profiler else {
+        return (StatusCode::NOT_FOUND, "Query profiling is not enabled").into_response();
+    };
+    let limit = query.limit.unwrap_or(20).min(1000);
+    Json(profiler.report(limit)).into_response()
+}
+
+/// The busiest clients (20 by default) over the last `hours` hours (24 by default), with their
+/// error rates and busiest endpoints.
+#[instrument(skip(state))]
+async fn api_usage(State(state): State<AppState>, Query(query): Query<UsageQuery>) -> Response {
+    let Some(usage) = &state.usage else {
+        return (StatusCode::NOT_FOUND, "Usage tracking is not enabled").into_response();
+    };
+    let hours = query.hours.unwrap_or(24).clamp(1, 24 * 90);
+    let limit = query.limit.unwrap_or(20).min(1000);
+    match usage.report(hours, limit).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to load API usage");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load API usage").into_response()
+        }
+    }
+}
+
+/// What the retention rules would delete if they ran now. Nothing is deleted, whether or not
+/// the rules are enforced.
+#[instrument(skip(state))]
+async fn retention_report(State(state): State<AppState>) -> Response {
+    match state.retention.report(state.clock.now()).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e @ RetentionError::OutOfRange { .. }) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to evaluate retention rules");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to evaluate retention rules").into_response()
+        }
+    }
+}
+
+/// The configuration the server is running with, secrets redacted.
+#[instrument(skip(state))]
+async fn effective_config(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(state.effective_config.as_ref().clone())
+}
+
+#[instrument(skip(state))]
+async fn list_flags(State(state): State<AppState>) -> Response {
+    match state.flags.list().await {
+        Ok(flags) => Json(flags).into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to list feature flags");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list feature flags").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state, client, principal, payload), fields(flag.enabled = payload.enabled))]
+async fn set_flag(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    client: ClientInfo,
+    principal: Option<Extension<Principal>>,
+    Json(payload): Json<SetFeatureFlagRequest>,
+) -> Response {
+    match state
+        .flags
+        .set(&name, payload.enabled, payload.description.as_deref())
+        .await
+    {
+        Ok(flag) => {
+            let detail = serde_json::json!({"flag": flag.name, "enabled": flag.enabled});
+            audit_admin(&state, &client, principal.as_deref(), AuditEvent::FlagChanged, detail).await;
+            Json(flag).into_response()
+        }
+        Err(e @ FlagError::InvalidName) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(FlagError::Repository(e)) => {
+            error!(error = %e, "Failed to set feature flag");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to set feature flag").into_response()
+        }
+    }
+}
+
+/// Removes a flag, returning it to the default its callers use.
+#[instrument(skip(state, client, principal))]
+async fn delete_flag(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    client: ClientInfo,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    match state.flags.remove(&name).await {
+        Ok(true) => {
+            let detail = serde_json::json!({"flag": name, "enabled": null});
+            audit_admin(&state, &client, principal.as_deref(), AuditEvent::FlagChanged, detail).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "Feature flag not found").into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to delete feature flag");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete feature flag").into_response()
+        }
+    }
+}
+
+fn tag_error(e: TagError) -> Response {
+    let status = match &e {
+        TagError::Unknown(_) => StatusCode::NOT_FOUND,
+        TagError::AlreadyExists(_) => StatusCode::CONFLICT,
+        TagError::InvalidName
+        | TagError::InvalidColor
+        | TagError::DescriptionTooLong
+        | TagError::SameTag
+        | TagError::NoSelection
+        | TagError::TooManyIds
+        | TagError::NothingToChange
+        | TagError::AddedAndRemoved(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        TagError::Repository(_) => {
+            error!(error = %e, "Tag request failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Tag request failed").into_response();
+        }
+    };
+    (status, e.to_string()).into_response()
+}
+
+#[instrument(skip(state, principal))]
+async fn list_tags(State(state): State<AppState>, principal: Option<Extension<Principal>>) -> Response {
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    match state.tags.list(visible.as_ref()).await {
+        Ok(tags) => Json(tags).into_response(),
+        Err(e) => tag_error(e.into()),
+    }
+}
+
+#[instrument(skip(state, principal), fields(tag = %name))]
+async fn get_tag(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    match state.tags.get(&name, visible.as_ref()).await {
+        Ok(Some(tag)) => Json(tag).into_response(),
+        Ok(None) => tag_error(TagError::Unknown(name)),
+        Err(e) => tag_error(e.into()),
+    }
+}
+
+#[instrument(skip(state, principal, payload), fields(tag = %name))]
+async fn update_tag(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    principal: Option<Extension<Principal>>,
+    Json(payload): Json<UpdateTagRequest>,
+) -> Response {
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    match state.tags.update(&name, payload, visible.as_ref()).await {
+        Ok(tag) => Json(tag).into_response(),
+        Err(e) => tag_error(e),
+    }
+}
+
+/// Callers `AUTH_REQUIRED` identified only rename the tag on todos of projects they can edit.
+#[instrument(skip(state, principal, payload), fields(tag = %name, tag.to = %payload.name))]
+async fn rename_tag(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    principal: Option<Extension<Principal>>,
+    Json(payload): Json<RenameTagRequest>,
+) -> Response {
+    let editable = match editable_projects(&state, principal.as_deref()).await {
+        Ok(editable) => editable,
+        Err(rejection) => return rejection,
+    };
+    match state.tags.rename(&name, &payload.name, editable.as_ref()).await {
+        Ok(change) => Json(change).into_response(),
+        Err(e) => tag_error(e),
+    }
+}
+
+/// Callers `AUTH_REQUIRED` identified only merge the tag on todos of projects they can edit.
+#[instrument(skip(state, principal, payload), fields(tag = %name, tag.to = %payload.into))]
+async fn merge_tag(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    principal: Option<Extension<Principal>>,
+    Json(payload): Json<MergeTagRequest>,
+) -> Response {
+    let editable = match editable_projects(&state, principal.as_deref()).await {
+        Ok(editable) => editable,
+        Err(rejection) => return rejection,
+    };
+    match state.tags.merge(&name, &payload.into, editable.as_ref()).await {
+        Ok(change) => Json(change).into_response(),
+        Err(e) => tag_error(e),
+    }
+}
+
+#[instrument(skip(state))]
+async fn list_webhooks(State(state): State<AppState>) -> Response {
+    match state.webhooks.list().await {
+        Ok(webhooks) => Json(webhooks).into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to list webhooks");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list webhooks").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state, client, principal, payload))]
+async fn create_webhook(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    principal: Option<Extension<Principal>>,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> Response {
+    match state.webhooks.create(payload).await {
+        Ok(webhook) => {
+            let detail = serde_json::json!({"webhook": webhook.id, "action": "created"});
+            audit_admin(&state, &client, principal.as_deref(), AuditEvent::WebhookChanged, detail).await;
+            (StatusCode::CREATED, Json(webhook)).into_response()
+        }
+        Err(WebhookError::Repository(e)) => {
+            error!(error = %e, "Failed to create webhook");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create webhook").into_response()
+        }
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response(),
+    }
+}
+
+#[instrument(skip(state, client, principal), fields(webhook.id = %id))]
+async fn delete_webhook(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    client: ClientInfo,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    match state.webhooks.remove(id).await {
+        Ok(true) => {
+            let detail = serde_json::json!({"webhook": id, "action": "deleted"});
+            audit_admin(&state, &client, principal.as_deref(), AuditEvent::WebhookChanged, detail).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "Webhook not found").into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to delete webhook");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete webhook").into_response()
+        }
+    }
+}
+
+/// Creates the todo's Jira issue, or updates the one it has.
+#[instrument(skip(state), fields(todo.id = %id))]
+async fn push_to_jira(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    let Some(jira) = &state.jira else {
+        return (StatusCode::NOT_FOUND, "Jira integration is not configured").into_response();
+    };
+    let todo = match accessible_todo(&state, principal.as_deref(), id, true).await {
+        Ok(todo) => todo,
+        Err(rejection) => return rejection,
+    };
+    match jira.push(&todo).await {
+        Ok(issue) if issue.created => (StatusCode::CREATED, Json(issue)).into_response(),
+        Ok(issue) => Json(issue).into_response(),
+        Err(JiraError::Repository(e)) => {
+            error!(error = %e, "Failed to record Jira issue");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to push todo to Jira").into_response()
+        }
+        Err(e) => {
+            warn!(error = %e, "Jira refused the todo");
+            (StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        }
+    }
+}
+
+#[instrument(skip(state))]
+async fn list_custom_fields(State(state): State<AppState>) -> Response {
+    match state.custom_fields.list().await {
+        Ok(fields) => Json(fields).into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to list custom fields");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list custom fields").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state, client, principal, payload))]
+async fn create_custom_field(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    principal: Option<Extension<Principal>>,
+    Json(payload): Json<CreateCustomFieldRequest>,
+) -> Response {
+    match state.custom_fields.define(payload).await {
+        Ok(field) => {
+            let detail = serde_json::json!({"field": field.name, "action": "created"});
+            audit_admin(&state, &client, principal.as_deref(), AuditEvent::CustomFieldChanged, detail).await;
+            (StatusCode::CREATED, Json(field)).into_response()
+        }
+        Err(e @ CustomFieldError::AlreadyExists(_)) => (StatusCode::CONFLICT, e.to_string()).into_response(),
+        Err(CustomFieldError::Repository(e)) => {
+            error!(error = %e, "Failed to create custom field");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create custom field").into_response()
+        }
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response(),
+    }
+}
+
+/// Also deletes every todo's value for the field.
+#[instrument(skip(state, client, principal), fields(field.name = %name))]
+async fn delete_custom_field(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    client: ClientInfo,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    match state.custom_fields.remove(&name).await {
+        Ok(true) => {
+            let detail = serde_json::json!({"field": name, "action": "deleted"});
+            audit_admin(&state, &client, principal.as_deref(), AuditEvent::CustomFieldChanged, detail).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "Custom field not found").into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to delete custom field");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete custom field").into_response()
+        }
+    }
+}
+
+/// A GitHub webhook, signed with `INBOUND_GITHUB_SECRET`.
+async fn receive_github(state: State<AppState>, headers: HeaderMap, body: axum::body::Bytes) -> Response {
+    receive_inbound(state, Path("github".to_owned()), headers, body).await
+}
+
+/// A webhook from one of the `INBOUND_SOURCES_FILE` sources, which its rules may turn into
+/// a new todo or a change to the one already made for the same item. Signed rather than
+/// authenticated, so it's open even when `AUTH_REQUIRED` is set.
+#[instrument(skip(state, headers, body), fields(external_id))]
+async fn receive_inbound(
+    State(state): State<AppState>,
+    Path(source): Path<String>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Response {
+    match state
+        .integrations
+        .receive(state.repository.as_ref(), &source, &headers, &body)
+        .await
+    {
+        Ok(outcome) if outcome.outcome == "created" => (StatusCode::CREATED, Json(outcome)).into_response(),
+        Ok(outcome) => Json(outcome).into_response(),
+        Err(e @ IntegrationError::UnknownSource) => (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+        Err(e @ IntegrationError::InvalidSignature) => (StatusCode::UNAUTHORIZED, e.to_string()).into_response(),
+        Err(e @ IntegrationError::InvalidPayload(_)) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(IntegrationError::Repository(e)) => {
+            error!(error = %e, "Failed to apply inbound delivery");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to apply delivery").into_response()
+        }
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response(),
+    }
+}
+
+/// MCP over Streamable HTTP: one JSON-RPC message or batch per `POST`, answered with JSON.
+/// Writing tools need `todos:write` when `AUTH_REQUIRED` identified the caller.
+#[instrument(skip_all)]
+async fn mcp_endpoint(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    body: axum::body::Bytes,
+) -> Response {
+    let caller = match principal.as_deref() {
+        Some(principal) => {
+            let visible = match visible_projects(&state, Some(principal)).await {
+                Ok(visible) => visible,
+                Err(rejection) => return rejection,
+            };
+            let editable = match editable_projects(&state, Some(principal)).await {
+                Ok(editable) => editable,
+                Err(rejection) => return rejection,
+            };
+            Caller {
+                can_write: principal.scopes.contains(&Scope::TodosWrite),
+                visible,
+                editable,
+                actor: Some(principal.user.clone()),
+            }
+        }
+        None => Caller::unrestricted(),
+    };
+    match state.mcp.handle_json(&body, &caller).await {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+/// Records an admin action, attributed to the caller when `AUTH_REQUIRED` identified one.
+async fn audit_admin(
+    state: &AppState,
+    client: &ClientInfo,
+    principal: Option<&Principal>,
+    event: AuditEvent,
+    detail: serde_json::Value,
+) {
+    if let Some(auth) = &state.auth {
+        let user = principal.map(|p| &p.user);
+        auth.audit()
+            .record(client, event, user.map(|u| u.id), user.map(|u| u.email.as_str()), detail)
+            .await;
+    }
+}
+
+/// Every route, inside the auth, rate limit, IP filter, metrics, access log, tracing and
+/// request id layers that `config` turns on.
+pub fn router(state: AppState, config: &Config) -> Router {
+    let app = Router::new()
+        .route("/health", get(health_check))
+        .route("/health/schema", get(health_schema))
+        .route("/schemas/requests", get(request_schemas))
+        .route("/metrics", get(prometheus_metrics))
+        .route("/todos", get(list_todos).head(head_todos).post(create_todo))
+        .route("/todos/compact", get(list_compact_todos))
+        .route("/todos/stats", get(todo_stats))
+        .route("/todos/export", get(export_todos))
+        .route("/todos/next", get(next_todos))
+        .route("/todos/parse", post(parse_todo))
+        .route("/stats/velocity", get(stats_velocity))
+        .route("/stats/aging", get(stats_aging))
+        .route("/stats/completion-time", get(stats_completion_time))
+        .route("/todos/events", get(todo_events))
+        .route("/todos/batch", post(create_batch))
+        .route("/todos/import", post(import_todos))
+        .route("/import/markdown", post(import_markdown))
+        .route("/todos/completed", delete(delete_completed))
+        .route("/todos/tags", post(tag_todos))
+        .route(
+            "/todos/:id",
+            get(get_todo).head(head_todo).put(update_todo).delete(delete_todo),
+        )
+        .route("/todos/:id/snooze", post(snooze_todo))
+        .route("/todos/:id/history", get(todo_history))
+        .route("/todos/:id/comments", get(list_comments).post(create_comment))
+        .route("/todos/:id/attachments", get(list_attachments).post(upload_attachment))
+        .route(
+            "/todos/:id/attachments/:attachment_id",
+            get(download_attachment).delete(delete_attachment),
+        )
+        .route("/todos/:id/jira", post(push_to_jira))
+        .route("/todos/:id/blockers", post(add_blocker))
+        .route("/todos/:id/blockers/:blocker_id", delete(remove_blocker))
+        .route("/sync", get(sync_changes))
+        .route("/sync/push", post(sync_push))
+        .merge(admin_only_router(state.auth.clone()))
+        .route("/admin/flags", get(list_flags))
+        .route("/admin/slow-queries", get(slow_queries))
+        .route("/admin/retention", get(retention_report))
+        .route("/admin/config", get(effective_config))
+        .route("/admin/flags/:name", put(set_flag).delete(delete_flag))
+        .route("/admin/webhooks", get(list_webhooks).post(create_webhook))
+        .route("/admin/webhooks/:id", delete(delete_webhook))
+        .route("/admin/custom-fields", get(list_custom_fields).post(create_custom_field))
+        .route("/admin/custom-fields/:name", delete(delete_custom_field))
+        .route("/auth/register", post(register))
+        .route("/auth/login", post(login))
+        .route("/auth/refresh", post(refresh_session))
+        .route("/auth/logout", post(logout))
+        .route("/auth/me", get(current_user))
+        .route("/auth/tokens", get(list_api_tokens).post(create_api_token))
+        .route("/auth/tokens/:id", delete(revoke_api_token))
+        .route("/users/me", delete(delete_account).patch(update_profile))
+        .route("/users/me/activity", get(activity_feed))
+        .route("/users/me/export", get(export_user_data))
+        .route("/users/erasures/:id", get(erasure_progress))
+        .route("/users/me/exports/:id", get(download_user_export))
+        .route("/tags", get(list_tags))
+        .route("/tags/:name", get(get_tag).put(update_tag))
+        .route("/tags/:name/rename", post(rename_tag))
+        .route("/tags/:name/merge", post(merge_tag))
+        .route("/projects", get(list_projects).post(create_project))
+        .route("/projects/:id", get(get_project))
+        .route("/projects/:id/members", get(list_project_members).post(add_project_member))
+        .route("/projects/:id/members/:user_id", delete(remove_project_member))
+        .route("/projects/:id/presence", get(project_presence))
+        .route("/projects/:id/todos", get(list_project_todos).post(create_project_todo))
+        .route("/integrations/github", post(receive_github))
+        .route("/integrations/inbound/:source", post(receive_inbound))
+        .route("/mcp", post(mcp_endpoint))
+        .merge(ui::router())
+        .merge(dav::router());
+
+    // Innermost, so the tenant's database is only opened for requests that get this far
+    let app = match &state.tenants {
+        Some(databases) => {
+            let members = config.tenants.as_ref().map(|tenants| tenants.members.clone()).unwrap_or_default();
+            let routing = TenantRouting::new(databases.clone(), members);
+            app.layer(middleware::from_fn_with_state(Arc::new(routing), tenancy::route_tenant))
+        }
+        None => app,
+    };
+
+    // Outside tenant routing as well, so a malformed body never opens a tenant's database
+    let app = if config.request_validation {
+        info!(mode = ?config.api_mode, "Request body validation enabled");
+        app.layer(middleware::from_fn_with_state(
+            Arc::new(BodyValidator::new(config.api_mode)),
+            body_schema::validate_bodies,
+        ))
+    } else {
+        app
+    };
+
+    // Outside tenant routing, so no database is opened for a refused write; inside auth, so
+    // unauthenticated writes still get their 401
+    let app = if config.read_only {
+        info!("Read-only mode: writes are refused");
+        app.layer(middleware::from_fn(read_only::refuse_writes))
+    } else {
+        app
+    };
+
+    let app = match &state.auth {
+        Some(auth) if auth.required() => {
+            info!("Authentication required for todo and admin routes");
+            app.layer(middleware::from_fn_with_state(auth.clone(), auth::require_scope))
+        }
+        _ => app,
+    };
+
+    // Outside auth, so a slow credential lookup counts against the deadline too
+    let deadlines = Arc::new(Deadlines::new(config.request_timeout));
+    let app = app.layer(middleware::from_fn_with_state(deadlines, deadline::enforce_deadline));
+    let app = app.layer(middleware::from_fn_with_state(config.field_case, dto::negotiate_field_case));
+    // Read by `Payload` when it deserializes a body
+    let app = app.layer(Extension(config.api_mode));
+
+    // Inside the metrics layer, so rejected requests still show up as 429s
+    let app = match &config.rate_limit {
+        Some(rate_limit) => {
+            let limiter = RateLimiter::new(rate_limit, state.auth.clone()).expect("Invalid RATE_LIMIT_REDIS_URL");
+            app.layer(middleware::from_fn_with_state(Arc::new(limiter), rate_limit::enforce_rate_limit))
+        }
+        None => app,
+    };
+
+    // Outside auth and rate limiting too, so refused requests are counted against the client
+    let app = match &state.usage {
+        Some(usage) => app.layer(middleware::from_fn_with_state(usage.clone(), usage::track_usage)),
+        None => app,
+    };
+
+    // Outside auth and rate limiting, so refused clients cost neither a token lookup nor a bucket
+    let app = match &config.ip_filter_file {
+        Some(path) => {
+            let filter = Arc::new(IpFilter::load(path).unwrap_or_else(|e| panic!("Invalid IP_FILTER_FILE: {e}")));
+            #[cfg(unix)]
+            ip_filter::spawn_reload_on_sighup(filter.clone());
+            app.layer(middleware::from_fn_with_state(filter, ip_filter::enforce_ip_filter))
+        }
+        None => app,
+    };
+
+    // Sheds load before any other work, but inside the metrics layer so refusals show as 503s
+    let app = match &config.concurrency_limit {
+        Some(limit) => app.layer(middleware::from_fn_with_state(
+            Arc::new(load_shed::ConcurrencyLimiter::new(limit)),
+            load_shed::limit_concurrency,
+        )),
+        None => app,
+    };
+    let app = app.layer(middleware::from_fn_with_state(HttpMetrics::new(), metrics::track_http_metrics));
+
+    // Inside the trace span, so the warning names the trace
+    let app = match config.slow_request_threshold {
+        Some(threshold) => {
+            let watchdog = slow_requests::SlowRequestWatchdog::new(threshold, config.trace_url_template.clone());
+            app.layer(middleware::from_fn_with_state(Arc::new(watchdog), slow_requests::watch_slow_requests))
+        }
+        None => app,
+    };
+
+    // Body events need the request span too, and see requests that auth or limits refuse
+    let app = match &config.body_log {
+        Some(body_log) => {
+            info!(sample_rate = body_log.sample_rate, max_bytes = body_log.max_bytes, "Body logging enabled");
+            app.layer(middleware::from_fn_with_state(
+                Arc::new(body_log::BodyLog::new(body_log)),
+                body_log::log_bodies,
+            ))
+        }
+        None => app,
+    };
+
+    // Access log sits inside the trace span so it can report the trace id
+    let app = match config.access_log {
+        Some(format) => {
+            let access_log = AccessLog::new(format, config.access_log_path.as_deref().map(std::path::Path::new))
+                .expect("Failed to open access log");
+            info!(format = ?format, path = ?config.access_log_path, "Access log enabled");
+            app.layer(middleware::from_fn_with_state(Arc::new(access_log), access_log::log_access))
+        }
+        None => app,
+    };
+
+    app
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(telemetry::make_http_span)
+                .on_response(telemetry::record_http_response),
+        )
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state)
+}
@@ -0,0 +1,294 @@
+use crate::config::ConcurrencyLimitConfig;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use opentelemetry::{
+    global,
+    metrics::{Counter, ObservableGauge},
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info};
+
+/// Share of the limit kept when a response is slower than the target.
+const BACKOFF: f64 = 0.9;
+
+struct Limits {
+    /// Fractional, so additive increases can add up over many responses.
+    limit: f64,
+    in_flight: usize,
+    last_decrease: Instant,
+}
+
+/// Refuses requests with `503` once too many are in progress, rather than letting them
+/// queue up behind each other. With `adaptive` the limit follows latency (AIMD): every
+/// response within the target raises it by about one per limit's worth of responses, and a
+/// slower one cuts it by 10%, at most once per target latency.
+pub struct ConcurrencyLimiter {
+    min: f64,
+    max: f64,
+    adaptive: bool,
+    target_latency: Duration,
+    limits: Arc<Mutex<Limits>>,
+    shed: Counter<u64>,
+    _limit_gauge: ObservableGauge<u64>,
+    _in_flight_gauge: ObservableGauge<u64>,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(config: &ConcurrencyLimitConfig) -> Self {
+        let limits = Arc::new(Mutex::new(Limits {
+            limit: config.max as f64,
+            in_flight: 0,
+            last_decrease: Instant::now(),
+        }));
+        let meter = global::meter("todo-api");
+
+        let observed = limits.clone();
+        let limit_gauge = meter
+            .u64_observable_gauge("http.server.concurrency_limit")
+            .with_description("Requests allowed in progress at once")
+            .with_callback(move |observer| observer.observe(observed.lock().unwrap().limit as u64, &[]))
+            .init();
+        let observed = limits.clone();
+        let in_flight_gauge = meter
+            .u64_observable_gauge("http.server.concurrency_in_flight")
+            .with_description("Requests in progress under the concurrency limit")
+            .with_callback(move |observer| observer.observe(observed.lock().unwrap().in_flight as u64, &[]))
+            .init();
+
+        info!(
+            max = config.max,
+            min = config.min,
+            adaptive = config.adaptive,
+            target_latency_ms = config.target_latency.as_millis() as u64,
+            "Concurrency limit enabled"
+        );
+        Self {
+            min: config.min as f64,
+            max: config.max as f64,
+            adaptive: config.adaptive,
+            target_latency: config.target_latency,
+            limits,
+            shed: meter
+                .u64_counter("http.server.shed_requests")
+                .with_description("Requests refused because the concurrency limit was reached")
+                .init(),
+            _limit_gauge: limit_gauge,
+            _in_flight_gauge: in_flight_gauge,
+        }
+    }
+
+    /// Takes a slot, or returns `None` if every slot is taken.
+    fn acquire(self: &Arc<Self>) -> Option<Permit> {
+        let mut limits = self.limits.lock().unwrap();
+        if limits.in_flight >= (limits.limit as usize).max(1) {
+            return None;
+        }
+        limits.in_flight += 1;
+        Some(Permit {
+            limiter: self.clone(),
+            started: Instant::now(),
+            completed: false,
+        })
+    }
+
+    fn release(&self, latency: Option<Duration>) {
+        let mut limits = self.limits.lock().unwrap();
+        // Only responses that are in use tell anything about the limit
+        let saturated = limits.in_flight as f64 >= limits.limit / 2.0;
+        limits.in_flight -= 1;
+
+        let Some(latency) = latency.filter(|_| self.adaptive) else {
+            return;
+        };
+        if latency > self.target_latency {
+            if limits.last_decrease.elapsed() >= self.target_latency {
+                limits.limit = (limits.limit * BACKOFF).max(self.min);
+                limits.last_decrease = Instant::now();
+                debug!(
+                    limit = limits.limit as u64,
+                    latency_ms = latency.as_millis() as u64,
+                    "Concurrency limit lowered"
+                );
+            }
+        } else if saturated {
+            limits.limit = (limits.limit + 1.0 / limits.limit).min(self.max);
+        }
+    }
+}
+
+/// A request's slot, given back when dropped. Requests abandoned by the client don't
+/// adjust the limit.
+struct Permit {
+    limiter: Arc<ConcurrencyLimiter>,
+    started: Instant,
+    completed: bool,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let latency = self.completed.then(|| self.started.elapsed());
+        self.limiter.release(latency);
+    }
+}
+
+/// Holds a slot until the response head is ready, so streams such as `/todos/events` don't
+/// keep theirs. Health checks and metrics scrapes are never refused.
+pub async fn limit_concurrency(State(limiter): State<Arc<ConcurrencyLimiter>>, req: Request, next: Next) -> Response {
+    if matches!(req.uri().path(), "/health" | "/metrics") {
+        return next.run(req).await;
+    }
+
+    let Some(mut permit) = limiter.acquire() else {
+        limiter.shed.add(1, &[]);
+        debug!("Concurrency limit reached, request refused");
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, HeaderValue::from(1))],
+            "Server is at capacity, try again shortly",
+        )
+            .into_response();
+    };
+    let response = next.run(req).await;
+    permit.completed = true;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware, routing::get, Router};
+    use tokio::sync::Notify;
+
+    const TARGET: Duration = Duration::from_millis(100);
+
+    fn limiter(max: usize, min: usize, adaptive: bool) -> Arc<ConcurrencyLimiter> {
+        Arc::new(ConcurrencyLimiter::new(&ConcurrencyLimitConfig {
+            max,
+            min,
+            adaptive,
+            target_latency: TARGET,
+        }))
+    }
+
+    /// Sets the limit and requests in progress, with the last decrease long enough ago that
+    /// a slow response may lower the limit again.
+    fn set(limiter: &ConcurrencyLimiter, limit: f64, in_flight: usize) {
+        let mut limits = limiter.limits.lock().unwrap();
+        limits.limit = limit;
+        limits.in_flight = in_flight;
+        limits.last_decrease = Instant::now().checked_sub(TARGET * 2).unwrap();
+    }
+
+    fn limit(limiter: &ConcurrencyLimiter) -> f64 {
+        limiter.limits.lock().unwrap().limit
+    }
+
+    #[test]
+    fn permits_are_given_back_when_dropped() {
+        let limiter = limiter(2, 1, false);
+        let first = limiter.acquire().unwrap();
+        let second = limiter.acquire().unwrap();
+        assert!(limiter.acquire().is_none());
+
+        drop(first);
+        assert_eq!(limiter.limits.lock().unwrap().in_flight, 1);
+        let third = limiter.acquire().unwrap();
+        assert!(limiter.acquire().is_none());
+        drop((second, third));
+        assert_eq!(limiter.limits.lock().unwrap().in_flight, 0);
+    }
+
+    #[test]
+    fn a_limit_below_one_still_admits_one_request() {
+        let limiter = limiter(4, 1, true);
+        set(&limiter, 0.5, 0);
+        let only = limiter.acquire().unwrap();
+        assert!(limiter.acquire().is_none());
+        drop(only);
+    }
+
+    #[test]
+    fn fast_responses_raise_a_saturated_limit() {
+        let limiter = limiter(10, 2, true);
+        set(&limiter, 8.0, 5);
+        limiter.release(Some(TARGET / 2));
+        assert_eq!(limit(&limiter), 8.125);
+
+        // Nowhere near the limit, so its size wasn't what was tested
+        set(&limiter, 8.0, 1);
+        limiter.release(Some(TARGET / 2));
+        assert_eq!(limit(&limiter), 8.0);
+
+        set(&limiter, 10.0, 9);
+        limiter.release(Some(TARGET / 2));
+        assert_eq!(limit(&limiter), 10.0);
+    }
+
+    #[test]
+    fn slow_responses_cut_the_limit_once_per_target_latency() {
+        let limiter = limiter(10, 2, true);
+        set(&limiter, 8.0, 2);
+        limiter.release(Some(TARGET * 2));
+        assert!((limit(&limiter) - 7.2).abs() < 1e-9, "{}", limit(&limiter));
+        limiter.release(Some(TARGET * 2));
+        assert!((limit(&limiter) - 7.2).abs() < 1e-9, "{}", limit(&limiter));
+
+        set(&limiter, 2.1, 1);
+        limiter.release(Some(TARGET * 2));
+        assert_eq!(limit(&limiter), 2.0);
+    }
+
+    #[test]
+    fn only_completed_adaptive_responses_move_the_limit() {
+        let fixed = limiter(10, 2, false);
+        set(&fixed, 8.0, 5);
+        fixed.release(Some(TARGET * 2));
+        assert_eq!(limit(&fixed), 8.0);
+
+        let adaptive = limiter(10, 2, true);
+        set(&adaptive, 8.0, 5);
+        adaptive.release(None);
+        assert_eq!(limit(&adaptive), 8.0);
+        assert_eq!(adaptive.limits.lock().unwrap().in_flight, 4);
+    }
+
+    #[tokio::test]
+    async fn sheds_requests_over_the_limit() {
+        let limiter = limiter(1, 1, false);
+        let gate = Arc::new(Notify::new());
+        let held = gate.clone();
+        let app = Router::new()
+            .route("/slow", get(move || {
+                let held = held.clone();
+                async move { held.notified().await }
+            }))
+            .route("/health", get(|| async {}))
+            .layer(middleware::from_fn_with_state(limiter.clone(), limit_concurrency));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let slow = tokio::spawn(client.get(format!("{url}/slow")).send());
+        while limiter.limits.lock().unwrap().in_flight == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let shed = client.get(format!("{url}/slow")).send().await.unwrap();
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(shed.headers()[header::RETRY_AFTER], "1");
+        let health = client.get(format!("{url}/health")).send().await.unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+
+        gate.notify_one();
+        assert_eq!(slow.await.unwrap().unwrap().status(), StatusCode::OK);
+        assert_eq!(limiter.limits.lock().unwrap().in_flight, 0);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::leases::JobLeases;
+use crate::repository::SqliteTodoRepository;
+use opentelemetry::{global, KeyValue};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use tracing::{error, info, Instrument};
+
+/// Spawns the background job that periodically checkpoints, vacuums and analyzes
+/// the SQLite database, and prunes sync tombstones older than `tombstone_retention`. Only
+/// the replica holding the `maintenance` lease runs it.
+pub fn spawn_maintenance_job(
+    repository: Arc<SqliteTodoRepository>,
+    leases: Arc<JobLeases>,
+    interval: Duration,
+    tombstone_retention: Option<Duration>,
+) -> JoinHandle<()> {
+    let meter = global::meter("todo-api");
+    let duration_histogram = meter
+        .f64_histogram("db.maintenance.duration")
+        .with_description("Duration of scheduled database maintenance runs")
+        .with_unit("ms")
+        .init();
+    let reclaimed_pages = meter
+        .u64_counter("db.maintenance.reclaimed_pages")
+        .with_description("Pages returned to the filesystem by incremental vacuum")
+        .init();
+
+    info!(interval_secs = interval.as_secs(), "Starting database maintenance job");
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately; skip it so maintenance doesn't run at startup.
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            if !leases.acquire("maintenance", interval * 2).await {
+                continue;
+            }
+
+            let started = Instant::now();
+            let span = tracing::info_span!("database_maintenance");
+            let outcome = match repository.run_maintenance(tombstone_retention).instrument(span).await {
+                Ok(reclaimed) => {
+                    reclaimed_pages.add(reclaimed, &[]);
+                    "success"
+                }
+                Err(e) => {
+                    error!(error = %e, "Database maintenance failed");
+                    "error"
+                }
+            };
+
+            duration_histogram.record(
+                started.elapsed().as_secs_f64() * 1000.0,
+                &[KeyValue::new("outcome", outcome)],
+            );
+        }
+    })
+}
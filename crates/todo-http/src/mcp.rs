@@ -0,0 +1,427 @@
+use crate::mentions::MentionService;
+use crate::models::{normalize_custom_fields, normalize_tags, CreateTodoRequest, Todo, User};
+use crate::notification_worker::{NotificationJob, NotificationQueue};
+use crate::repository::{ListFilter, RepositoryError, TodoRepository};
+use crate::validation::{TodoValidator, ValidationError};
+use todo_domain::clock::{self, Clock};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashSet,
+    sync::Arc,
+};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+/// The newest protocol revision this server speaks; older clients get their own echoed back.
+const PROTOCOL_VERSION: &str = "2025-03-26";
+const SUPPORTED_VERSIONS: [&str; 3] = ["2024-11-05", "2025-03-26", "2025-06-18"];
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Debug, Deserialize)]
+struct ListArgs {
+    #[serde(default = "default_true")]
+    include_completed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompleteArgs {
+    id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchArgs {
+    query: String,
+    #[serde(default = "default_true")]
+    include_completed: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Who a request is answered for.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    /// Whether the credential has `todos:write`, which `create_todo` and `complete_todo` need.
+    pub can_write: bool,
+    /// Projects whose todos the caller may see; `None` for stdio clients and callers nobody
+    /// signed in as, who see every todo.
+    pub visible: Option<HashSet<Uuid>>,
+    /// Projects whose todos the caller may change, likewise.
+    pub editable: Option<HashSet<Uuid>>,
+    /// Who signed in, named as the author of `@mentions` in what they write.
+    pub actor: Option<User>,
+}
+
+impl Caller {
+    /// A caller limited only by the database: stdio clients, and HTTP with auth off.
+    pub fn unrestricted() -> Self {
+        Self {
+            can_write: true,
+            visible: None,
+            editable: None,
+            actor: None,
+        }
+    }
+
+    fn can_see(&self, todo: &Todo) -> bool {
+        in_projects(todo, self.visible.as_ref())
+    }
+
+    fn can_change(&self, todo: &Todo) -> bool {
+        in_projects(todo, self.editable.as_ref())
+    }
+}
+
+fn in_projects(todo: &Todo, projects: Option<&HashSet<Uuid>>) -> bool {
+    match (todo.project_id, projects) {
+        (Some(id), Some(projects)) => projects.contains(&id),
+        _ => true,
+    }
+}
+
+/// Why a tool call failed. Both kinds go back to the model as an `isError` result, so it
+/// can correct itself, rather than as a protocol error.
+#[derive(Debug, thiserror::Error)]
+enum ToolError {
+    #[error("Invalid arguments: {0}")]
+    InvalidArguments(#[from] serde_json::Error),
+
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl From<RepositoryError> for ToolError {
+    fn from(e: RepositoryError) -> Self {
+        match e {
+            RepositoryError::NotFound(id) => Self::Failed(format!("No todo with id {id}")),
+            other => Self::Failed(other.to_string()),
+        }
+    }
+}
+
+/// Todo operations as Model Context Protocol tools, answered from the same repository (and,
+/// in the HTTP server, the same notification queue) as the REST API.
+pub struct McpServer {
+    repository: Arc<dyn TodoRepository>,
+    validator: Arc<TodoValidator>,
+    notifications: Option<NotificationQueue>,
+    mentions: Option<Arc<MentionService>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl McpServer {
+    pub fn new(
+        repository: Arc<dyn TodoRepository>,
+        validator: Arc<TodoValidator>,
+        notifications: Option<NotificationQueue>,
+    ) -> Self {
+        Self {
+            repository,
+            validator,
+            notifications,
+            mentions: None,
+            clock: clock::system(),
+        }
+    }
+
+    /// Stamps the todos the tools create and complete with `clock`'s time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Notifies people `@mentioned` in the descriptions of todos the tools create. Without
+    /// it, as over stdio, mentions are left alone.
+    pub fn with_mentions(mut self, mentions: Arc<MentionService>) -> Self {
+        self.mentions = Some(mentions);
+        self
+    }
+
+    /// Parses and answers a request body, replying with a parse error if it isn't JSON.
+    pub async fn handle_json(&self, body: &[u8], caller: &Caller) -> Option<Value> {
+        match serde_json::from_slice(body) {
+            Ok(message) => self.handle(message, caller).await,
+            Err(e) => Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+        }
+    }
+
+    /// Answers one JSON-RPC message or batch. Returns `None` when nothing is owed back,
+    /// as for notifications.
+    async fn handle(&self, message: Value, caller: &Caller) -> Option<Value> {
+        match message {
+            Value::Array(batch) if !batch.is_empty() => {
+                let mut responses = Vec::new();
+                for message in batch {
+                    responses.extend(Box::pin(self.handle(message, caller)).await);
+                }
+                (!responses.is_empty()).then_some(Value::Array(responses))
+            }
+            Value::Object(_) => self.handle_one(message, caller).await,
+            _ => Some(error_response(Value::Null, INVALID_REQUEST, "Expected a JSON-RPC request")),
+        }
+    }
+
+    async fn handle_one(&self, message: Value, caller: &Caller) -> Option<Value> {
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // A response to something we never send, or garbage; either way there's no answer
+            return id.map(|id| error_response(id, INVALID_REQUEST, "Missing method"));
+        };
+        // Notifications (`notifications/initialized`, cancellations) need no reply
+        let id = id?;
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let result = match method {
+            "initialize" => Ok(self.initialize(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => match params.get("name").and_then(Value::as_str) {
+                Some(name) => {
+                    let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+                    Ok(self.call_tool(name, arguments, caller).await)
+                }
+                None => Err((INVALID_PARAMS, "Missing tool name".to_string())),
+            },
+            other => Err((METHOD_NOT_FOUND, format!("Unknown method {other}"))),
+        };
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, &message),
+        })
+    }
+
+    fn initialize(&self, params: &Value) -> Value {
+        let requested = params.get("protocolVersion").and_then(Value::as_str);
+        let version = requested
+            .filter(|v| SUPPORTED_VERSIONS.contains(v))
+            .unwrap_or(PROTOCOL_VERSION);
+        info!(client = ?params.get("clientInfo"), protocol = version, "MCP session initialized");
+        json!({
+            "protocolVersion": version,
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "todo-api", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    #[instrument(skip(self, arguments, caller), fields(mcp.tool = name))]
+    async fn call_tool(&self, name: &str, arguments: Value, caller: &Caller) -> Value {
+        let result = match name {
+            "list_todos" => self.list_todos(serde_json::from_value(arguments), caller).await,
+            "search" => self.search(serde_json::from_value(arguments), caller).await,
+            "create_todo" | "complete_todo" if !caller.can_write => {
+                Err(ToolError::Failed("This credential lacks the todos:write scope".to_string()))
+            }
+            "create_todo" => self.create_todo(serde_json::from_value(arguments), caller).await,
+            "complete_todo" => self.complete_todo(serde_json::from_value(arguments), caller).await,
+            other => Err(ToolError::Failed(format!("Unknown tool {other}"))),
+        };
+        match result {
+            Ok(value) => json!({
+                "content": [{ "type": "text", "text": value.to_string() }],
+                "isError": false,
+            }),
+            Err(e) => {
+                warn!(error = %e, "MCP tool call failed");
+                json!({
+                    "content": [{ "type": "text", "text": e.to_string() }],
+                    "isError": true,
+                })
+            }
+        }
+    }
+
+    async fn list_todos(&self, args: serde_json::Result<ListArgs>, caller: &Caller) -> Result<Value, ToolError> {
+        let args = args?;
+        let todos = self.repository.list(&ListFilter::default(), None).await?.todos;
+        let todos: Vec<Todo> = todos
+            .into_iter()
+            .filter(|todo| caller.can_see(todo))
+            .filter(|todo| args.include_completed || !todo.completed)
+            .collect();
+        Ok(json!(todos))
+    }
+
+    async fn search(&self, args: serde_json::Result<SearchArgs>, caller: &Caller) -> Result<Value, ToolError> {
+        let args = args?;
+        let query = args.query.trim().to_lowercase();
+        if query.is_empty() {
+            return Err(ToolError::Failed("query must not be empty".to_string()));
+        }
+        let matches: Vec<Todo> = self
+            .repository
+            .list(&ListFilter::default(), None)
+            .await?
+            .todos
+            .into_iter()
+            .filter(|todo| caller.can_see(todo))
+            .filter(|todo| args.include_completed || !todo.completed)
+            .filter(|todo| {
+                todo.title.to_lowercase().contains(&query)
+                    || todo.description.as_deref().is_some_and(|d| d.to_lowercase().contains(&query))
+                    || todo.tags.iter().any(|tag| tag.to_lowercase() == query)
+            })
+            .collect();
+        Ok(json!(matches))
+    }
+
+    async fn create_todo(&self, args: serde_json::Result<CreateTodoRequest>, caller: &Caller) -> Result<Value, ToolError> {
+        let args = args?;
+        match self.validator.check_create(&args, "mcp").await {
+            Ok(()) => {}
+            Err(ValidationError::Repository(e)) => return Err(e.into()),
+            Err(e) => return Err(ToolError::Failed(e.to_string())),
+        }
+        let now = self.clock.now();
+        let todo = Todo {
+            id: Uuid::new_v4(),
+            title: args.title,
+            description: args.description,
+            completed: false,
+            due_at: args.due_at,
+            tags: normalize_tags(args.tags),
+            estimate_minutes: args.estimate_minutes,
+            expires_at: args.expires_at,
+            project_id: None,
+            priority: args.priority,
+            pinned: args.pinned,
+            custom_fields: normalize_custom_fields(args.custom_fields),
+            created_at: now,
+            updated_at: now,
+            blocked: false,
+            version: 0,
+            change_seq: 0,
+        };
+        let created = self.repository.create(todo).await?;
+        self.notify(NotificationJob::Created {
+            todo_id: created.id,
+            title: created.title.clone(),
+        })
+        .await;
+        if let Some(mentions) = &self.mentions {
+            mentions.description_saved(&created, None, caller.actor.as_ref()).await;
+        }
+        Ok(json!(created))
+    }
+
+    async fn complete_todo(&self, args: serde_json::Result<CompleteArgs>, caller: &Caller) -> Result<Value, ToolError> {
+        let mut todo = self.repository.get(args?.id).await?;
+        // Other people's project todos are answered as if they didn't exist, as on the REST API
+        if !caller.can_see(&todo) {
+            return Err(RepositoryError::NotFound(todo.id).into());
+        }
+        if !caller.can_change(&todo) {
+            return Err(ToolError::Failed("Viewers can't change this project's todos".to_string()));
+        }
+        if todo.completed {
+            return Ok(json!(todo));
+        }
+        todo.completed = true;
+        todo.updated_at = self.clock.now();
+        let updated = self.repository.update(todo).await?;
+        self.notify(NotificationJob::Completed {
+            todo_id: updated.id,
+            title: updated.title.clone(),
+        })
+        .await;
+        Ok(json!(updated))
+    }
+
+    async fn notify(&self, job: NotificationJob) {
+        if let Some(notifications) = &self.notifications {
+            if let Err(e) = notifications.enqueue(job).await {
+                warn!(error = %e, "Failed to queue notification, continuing anyway");
+            }
+        }
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "list_todos",
+            "description": "List todos, newest first.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "include_completed": { "type": "boolean", "description": "Defaults to true" },
+                },
+            },
+        },
+        {
+            "name": "search",
+            "description": "Find todos whose title or description contains the query, or tagged with it.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "include_completed": { "type": "boolean", "description": "Defaults to true" },
+                },
+                "required": ["query"],
+            },
+        },
+        {
+            "name": "create_todo",
+            "description": "Create a todo and return it.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string" },
+                    "description": { "type": "string" },
+                    "due_at": { "type": "string", "format": "date-time", "description": "RFC 3339" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                    "estimate_minutes": { "type": "integer", "minimum": 0 },
+                    "priority": { "enum": ["high", "medium", "low"] },
+                    "pinned": { "type": "boolean", "description": "Puts the todo first in the ranked list" },
+                    "custom_fields": {
+                        "type": "object",
+                        "description": "Values for the custom fields defined on the server, by name",
+                    },
+                },
+                "required": ["title"],
+            },
+        },
+        {
+            "name": "complete_todo",
+            "description": "Mark a todo completed and return it.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "id": { "type": "string", "format": "uuid" } },
+                "required": ["id"],
+            },
+        },
+    ])
+}
+
+/// Serves newline-delimited JSON-RPC on stdin/stdout until stdin closes. Logs must go to
+/// stderr while this runs, since anything else on stdout breaks the protocol.
+pub async fn serve_stdio(server: McpServer) -> std::io::Result<()> {
+    info!("MCP server listening on stdio");
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Stdio clients can do whatever the database file lets them
+        if let Some(response) = server.handle_json(line.as_bytes(), &Caller::unrestricted()).await {
+            let mut out = serde_json::to_vec(&response)?;
+            out.push(b'\n');
+            stdout.write_all(&out).await?;
+            stdout.flush().await?;
+        }
+    }
+    info!("MCP client disconnected");
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use crate::models::{ActivityItem, ActivityKind, Comment, MentionChannel, Todo, User};
+use crate::notification_worker::{MemberNotice, NotificationJob, NotificationQueue};
+use crate::projects::ProjectService;
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use std::{collections::HashSet, sync::Arc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+pub const MIN_USERNAME_LEN: usize = 3;
+pub const MAX_USERNAME_LEN: usize = 32;
+
+/// Characters of the mentioning text kept in the activity feed and notices.
+const EXCERPT_LEN: usize = 200;
+
+/// Most people one piece of text can notify, so a pasted list of names can't spam everyone.
+const MAX_MENTIONS: usize = 20;
+
+const MAX_COMMENT_LEN: usize = 10_000;
+
+/// Feed entries per page when the client doesn't ask for a number, and the most it may ask for.
+const DEFAULT_FEED_LIMIT: u32 = 50;
+const MAX_FEED_LIMIT: u32 = 200;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MentionError {
+    #[error("Comments are 1 to {MAX_COMMENT_LEN} characters")]
+    InvalidComment,
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+fn is_username_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+pub fn valid_username(name: &str) -> bool {
+    (MIN_USERNAME_LEN..=MAX_USERNAME_LEN).contains(&name.len()) && name.chars().all(is_username_char)
+}
+
+/// The usernames `@mentioned` in `text`, lowercased, each once, in order of appearance. An
+/// `@` straight after a username character or `.`, as in an email address, isn't a mention.
+pub fn parse(text: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut previous = None;
+    for (i, c) in text.char_indices() {
+        let starts_mention = c == '@' && !previous.is_some_and(|p: char| is_username_char(p) || p == '.');
+        previous = Some(c);
+        if !starts_mention {
+            continue;
+        }
+        let rest = &text[i + 1..];
+        let end = rest.find(|c: char| !is_username_char(c)).unwrap_or(rest.len());
+        let name = rest[..end].to_lowercase();
+        if valid_username(&name) && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Comments on todos, and notices for people `@mentioned` in them or in descriptions, by email
+/// or only in their activity feed as each of them prefers.
+pub struct MentionService {
+    repository: Arc<SqliteTodoRepository>,
+    projects: Arc<ProjectService>,
+    notifications: NotificationQueue,
+}
+
+impl MentionService {
+    pub fn new(
+        repository: Arc<SqliteTodoRepository>,
+        projects: Arc<ProjectService>,
+        notifications: NotificationQueue,
+    ) -> Self {
+        Self { repository, projects, notifications }
+    }
+
+    /// Handles mentions in a saved description. Only names that weren't already mentioned
+    /// in `previous`, the description before this save, are notified.
+    pub async fn description_saved(&self, todo: &Todo, previous: Option<&str>, actor: Option<&User>) {
+        let Some(description) = todo.description.as_deref() else {
+            return;
+        };
+        let before = previous.map(parse).unwrap_or_default();
+        let names: Vec<String> = parse(description).into_iter().filter(|n| !before.contains(n)).collect();
+        self.deliver(todo, description, names, None, actor).await;
+    }
+
+    /// Saves a comment by `actor` on the todo and notifies anyone it mentions.
+    pub async fn add_comment(
+        &self,
+        todo: &Todo,
+        body: &str,
+        actor: Option<&User>,
+    ) -> Result<Comment, MentionError> {
+        let body = body.trim();
+        if !(1..=MAX_COMMENT_LEN).contains(&body.chars().count()) {
+            return Err(MentionError::InvalidComment);
+        }
+        let comment = Comment {
+            id: Uuid::new_v4(),
+            todo_id: todo.id,
+            author_id: actor.map(|a| a.id),
+            body: body.to_owned(),
+            created_at: self.repository.clock().now(),
+        };
+        self.repository.add_comment(&comment).await?;
+        info!(todo.id = %todo.id, comment.id = %comment.id, "Comment added");
+        self.deliver(todo, &comment.body, parse(&comment.body), Some(comment.id), actor)
+            .await;
+        Ok(comment)
+    }
+
+    pub async fn comments(&self, todo_id: Uuid) -> Result<Vec<Comment>, RepositoryError> {
+        self.repository.comments(todo_id).await
+    }
+
+    /// The comments on any of the todos, oldest first, read together.
+    pub async fn comments_for(&self, todo_ids: &HashSet<Uuid>) -> Result<Vec<Comment>, RepositoryError> {
+        self.repository.comments_for(todo_ids).await
+    }
+
+    /// The user's activity feed, newest first, before the entry `before` if given.
+    pub async fn feed(
+        &self,
+        user: &User,
+        before: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<Vec<ActivityItem>, RepositoryError> {
+        let limit = limit.unwrap_or(DEFAULT_FEED_LIMIT).clamp(1, MAX_FEED_LIMIT);
+        self.repository.activity_feed(user.id, before, limit).await
+    }
+
+    /// Records the mention in each mentioned user's feed and emails those who want it.
+    /// Users who can't see the todo, and the author mentioning themselves, are skipped.
+    /// Failures are logged; the text itself has already been saved.
+    async fn deliver(
+        &self,
+        todo: &Todo,
+        text: &str,
+        mut names: Vec<String>,
+        comment_id: Option<Uuid>,
+        actor: Option<&User>,
+    ) {
+        if names.is_empty() {
+            return;
+        }
+        names.truncate(MAX_MENTIONS);
+        let users = match self.repository.users_by_username(&names).await {
+            Ok(users) => users,
+            Err(e) => {
+                warn!(error = %e, todo.id = %todo.id, "Failed to resolve mentions");
+                return;
+            }
+        };
+
+        let mut mentioned = Vec::new();
+        for user in users {
+            if actor.is_some_and(|actor| actor.id == user.id) {
+                continue;
+            }
+            if self.projects.check_todo(&user, todo, false).await.is_ok() {
+                mentioned.push(user);
+            }
+        }
+        if mentioned.is_empty() {
+            return;
+        }
+
+        let item = ActivityItem {
+            id: 0,
+            kind: ActivityKind::Mention,
+            todo_id: todo.id,
+            comment_id,
+            actor_id: actor.map(|a| a.id),
+            excerpt: excerpt(text),
+            created_at: self.repository.clock().now(),
+        };
+        let ids: Vec<Uuid> = mentioned.iter().map(|u| u.id).collect();
+        if let Err(e) = self.repository.record_activity(&ids, &item).await {
+            warn!(error = %e, todo.id = %todo.id, "Failed to record mentions");
+        }
+        info!(todo.id = %todo.id, mentioned = ids.len(), "Mentions recorded");
+
+        let recipients: Vec<String> = mentioned
+            .into_iter()
+            .filter(|u| u.mention_channel == MentionChannel::Email)
+            .map(|u| u.email)
+            .collect();
+        if recipients.is_empty() {
+            return;
+        }
+        let who = actor.map_or("Someone", |a| a.username.as_deref().unwrap_or(&a.email));
+        let place = if comment_id.is_some() { "a comment on" } else { "the description of" };
+        let notice = MemberNotice {
+            recipients,
+            subject: format!("{who} mentioned you on {}", todo.title),
+            text: format!(
+                "{who} mentioned you in {place} \"{}\":\n\n{}\n\nID: {}\n",
+                todo.title, item.excerpt, todo.id
+            ),
+        };
+        if let Err(e) = self.notifications.enqueue(NotificationJob::Member(notice)).await {
+            warn!(error = %e, "Failed to queue mention notification");
+        }
+    }
+}
+
+fn excerpt(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(EXCERPT_LEN) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_owned(),
+    }
+}
@@ -0,0 +1,321 @@
+use crate::models::{CompactTodo, HistoryEntry, Todo, TodoStats};
+use crate::repository::{
+    ListFilter, ListWindow, RepositoryError, TodoCursor, TodoList, TodoListSummary, TodoPage, TodoRepository,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use axum::{
+    extract::{MatchedPath, State},
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::{
+    global,
+    metrics::{Counter, Histogram, ObservableGauge, UpDownCounter},
+    KeyValue,
+};
+use std::{collections::HashSet, future::Future, sync::Arc, time::Instant};
+use uuid::Uuid;
+
+/// Process and tokio runtime gauges. The handles must be kept alive for the
+/// callbacks to keep reporting.
+pub struct RuntimeMetrics {
+    _alive_tasks: ObservableGauge<u64>,
+    _workers: ObservableGauge<u64>,
+    _global_queue_depth: ObservableGauge<u64>,
+    _memory_rss: ObservableGauge<u64>,
+    _build_info: ObservableGauge<u64>,
+}
+
+pub fn register_runtime_metrics() -> RuntimeMetrics {
+    let meter = global::meter("todo-api");
+    // Callbacks run on the exporter's schedule, so capture the runtime handle up front.
+    let handle = tokio::runtime::Handle::current();
+
+    let tasks_handle = handle.clone();
+    let alive_tasks = meter
+        .u64_observable_gauge("tokio.tasks.alive")
+        .with_description("Number of tasks currently alive in the tokio runtime")
+        .with_callback(move |observer| {
+            observer.observe(tasks_handle.metrics().num_alive_tasks() as u64, &[])
+        })
+        .init();
+
+    let workers_handle = handle.clone();
+    let workers = meter
+        .u64_observable_gauge("tokio.workers")
+        .with_description("Number of tokio worker threads")
+        .with_callback(move |observer| {
+            observer.observe(workers_handle.metrics().num_workers() as u64, &[])
+        })
+        .init();
+
+    let global_queue_depth = meter
+        .u64_observable_gauge("tokio.global_queue.depth")
+        .with_description("Tasks waiting in the tokio runtime's global queue")
+        .with_callback(move |observer| {
+            observer.observe(handle.metrics().global_queue_depth() as u64, &[])
+        })
+        .init();
+
+    let memory_rss = meter
+        .u64_observable_gauge("process.memory.rss")
+        .with_description("Resident set size of the server process")
+        .with_unit("By")
+        .with_callback(|observer| {
+            if let Some(bytes) = resident_memory_bytes() {
+                observer.observe(bytes, &[]);
+            }
+        })
+        .init();
+
+    // Always 1; joining on its labels puts the running version next to any other series
+    let build_info = meter
+        .u64_observable_gauge("build.info")
+        .with_description("The version and source revision of the running server")
+        .with_callback(|observer| {
+            observer.observe(
+                1,
+                &[
+                    KeyValue::new("version", env!("CARGO_PKG_VERSION")),
+                    KeyValue::new("revision", option_env!("GIT_COMMIT").unwrap_or("unknown")),
+                ],
+            )
+        })
+        .init();
+
+    RuntimeMetrics {
+        _alive_tasks: alive_tasks,
+        _workers: workers,
+        _global_queue_depth: global_queue_depth,
+        _memory_rss: memory_rss,
+        _build_info: build_info,
+    }
+}
+
+/// Reads `VmRSS` from procfs; unavailable outside Linux.
+fn resident_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// HTTP server instruments shared by the metrics middleware.
+///
+/// `http.requests` and `http.requests.errors` carry the same two labels, route and method, so
+/// an error ratio for SLO burn-rate alerts is a plain division of their rates.
+#[derive(Clone)]
+pub struct HttpMetrics {
+    duration: Histogram<f64>,
+    active_requests: UpDownCounter<i64>,
+    requests: Counter<u64>,
+    errors: Counter<u64>,
+}
+
+impl Default for HttpMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpMetrics {
+    pub fn new() -> Self {
+        let meter = global::meter("todo-api");
+        Self {
+            duration: meter
+                .f64_histogram("http.server.request.duration")
+                .with_description("Duration of HTTP server requests")
+                .with_unit("s")
+                .init(),
+            active_requests: meter
+                .i64_up_down_counter("http.server.active_requests")
+                .with_description("Number of in-flight HTTP server requests")
+                .init(),
+            requests: meter
+                .u64_counter("http.requests")
+                .with_description("HTTP requests answered")
+                .init(),
+            errors: meter
+                .u64_counter("http.requests.errors")
+                .with_description("HTTP requests answered with a 5xx, which count against the error budget")
+                .init(),
+        }
+    }
+}
+
+/// `2xx`, `4xx`, ... so dashboards can aggregate without enumerating status codes.
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// Records request latency labelled with the matched route template, so `/todos/:id`
+/// stays a single series regardless of which id was requested.
+pub async fn track_http_metrics(
+    State(metrics): State<HttpMetrics>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let method = KeyValue::new("http.request.method", req.method().to_string());
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+    let route = KeyValue::new("http.route", route);
+    let in_flight = [method.clone(), route.clone()];
+    let started = Instant::now();
+
+    metrics.active_requests.add(1, &in_flight);
+    let response = next.run(req).await;
+    metrics.active_requests.add(-1, &in_flight);
+
+    let status = response.status();
+    metrics.requests.add(1, &in_flight);
+    if status.is_server_error() {
+        metrics.errors.add(1, &in_flight);
+    }
+    metrics.duration.record(
+        started.elapsed().as_secs_f64(),
+        &[
+            method,
+            route,
+            KeyValue::new("http.response.status_code", i64::from(status.as_u16())),
+            KeyValue::new("http.response.status_class", status_class(status)),
+        ],
+    );
+    response
+}
+
+/// Decorates a repository with operation duration and error metrics.
+pub struct MeteredRepository {
+    inner: Arc<dyn TodoRepository>,
+    duration: Histogram<f64>,
+    errors: Counter<u64>,
+}
+
+impl MeteredRepository {
+    pub fn new(inner: Arc<dyn TodoRepository>) -> Self {
+        let meter = global::meter("todo-api");
+        Self {
+            inner,
+            duration: meter
+                .f64_histogram("db.client.operation.duration")
+                .with_description("Duration of repository operations")
+                .with_unit("s")
+                .init(),
+            errors: meter
+                .u64_counter("db.client.operation.errors")
+                .with_description("Repository operations that returned an error")
+                .init(),
+        }
+    }
+
+    async fn observe<T>(
+        &self,
+        operation: &'static str,
+        fut: impl Future<Output = Result<T, RepositoryError>>,
+    ) -> Result<T, RepositoryError> {
+        let started = Instant::now();
+        let result = fut.await;
+
+        let outcome = match &result {
+            Ok(_) => "success",
+            // A missing row is an expected answer, not a storage failure.
+            Err(RepositoryError::NotFound(_)) => "not_found",
+            Err(_) => "error",
+        };
+        let attributes = [
+            KeyValue::new("db.operation", operation),
+            KeyValue::new("outcome", outcome),
+        ];
+        self.duration.record(started.elapsed().as_secs_f64(), &attributes);
+        if outcome == "error" {
+            self.errors.add(1, &attributes[..1]);
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl TodoRepository for MeteredRepository {
+    async fn create(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        self.observe("INSERT", self.inner.create(todo)).await
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Todo, RepositoryError> {
+        self.observe("SELECT", self.inner.get(id)).await
+    }
+
+    async fn list(&self, filter: &ListFilter<'_>, window: Option<ListWindow>) -> Result<TodoList, RepositoryError> {
+        self.observe(if window.is_some() { "SELECT_WINDOW" } else { "SELECT_ALL" }, self.inner.list(filter, window)).await
+    }
+
+    async fn list_page(
+        &self,
+        after: Option<TodoCursor>,
+        limit: usize,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<TodoPage, RepositoryError> {
+        self.observe("SELECT_PAGE", self.inner.list_page(after, limit, projects)).await
+    }
+
+    async fn list_compact(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<CompactTodo>, RepositoryError> {
+        self.observe("SELECT_COMPACT", self.inner.list_compact(projects)).await
+    }
+
+    async fn stats(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoStats, RepositoryError> {
+        self.observe("SELECT_STATS", self.inner.stats(projects)).await
+    }
+
+    async fn summary(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoListSummary, RepositoryError> {
+        self.observe("SELECT_SUMMARY", self.inner.summary(projects)).await
+    }
+
+    async fn update(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        self.observe("UPDATE", self.inner.update(todo)).await
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError> {
+        self.observe("DELETE", self.inner.delete(id)).await
+    }
+
+    async fn snooze(&self, id: Uuid, until: DateTime<Utc>) -> Result<Todo, RepositoryError> {
+        self.observe("SNOOZE", self.inner.snooze(id, until)).await
+    }
+
+    async fn history(&self, id: Uuid) -> Result<Vec<HistoryEntry>, RepositoryError> {
+        self.observe("SELECT_HISTORY", self.inner.history(id)).await
+    }
+
+    async fn add_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<Todo, RepositoryError> {
+        self.observe("INSERT_DEPENDENCY", self.inner.add_dependency(blocker, blocked)).await
+    }
+
+    async fn remove_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<(), RepositoryError> {
+        self.observe("DELETE_DEPENDENCY", self.inner.remove_dependency(blocker, blocked)).await
+    }
+
+    async fn create_batch(&self, todos: Vec<Todo>) -> Result<Vec<Todo>, RepositoryError> {
+        self.observe("BATCH_INSERT", self.inner.create_batch(todos)).await
+    }
+
+    async fn import(&self, todos: Vec<Todo>) -> Result<usize, RepositoryError> {
+        self.observe("IMPORT", self.inner.import(todos)).await
+    }
+
+    async fn delete_completed(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<Uuid>, RepositoryError> {
+        self.observe("DELETE_COMPLETED", self.inner.delete_completed(projects)).await
+    }
+
+    async fn database_version(&self) -> Result<String, RepositoryError> {
+        self.observe("SELECT_VERSION", self.inner.database_version()).await
+    }
+}
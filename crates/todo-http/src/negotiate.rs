@@ -0,0 +1,383 @@
+use crate::models::{
+    Attachment, BatchCreateResponse, BulkTagResponse, Comment, CompactTodo, CompletionTime, DeleteCompletedResponse, ExpandedTodo,
+    HistoryEntry, SyncChanges, SyncPushResponse, Todo, TodoAging, TodoListPage, TodoStats, Velocity,
+};
+use crate::dto::{self, CamelCase, FieldCase};
+use crate::quick_add::ParsedTodo;
+use todo_domain::inbox::RankedTodo;
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{de::DeserializeOwned, ser::SerializeStruct, Serialize, Serializer};
+use std::{str::FromStr, sync::Arc};
+use tracing::warn;
+
+/// Wire formats the todo endpoints can read and write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Xml,
+    MessagePack,
+}
+
+impl Format {
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Xml => "application/xml",
+            Self::MessagePack => "application/msgpack",
+        }
+    }
+
+    /// `None` for media types we can't produce or parse. Wildcards mean JSON.
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type.trim().to_ascii_lowercase().as_str() {
+            "application/json" | "application/*" | "*/*" => Some(Self::Json),
+            "application/xml" | "text/xml" => Some(Self::Xml),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            _ => None,
+        }
+    }
+
+    /// The supported type with the highest `q` in `Accept`, earliest first on ties.
+    /// JSON when the header is missing; `None` when nothing acceptable is supported.
+    fn from_accept(headers: &HeaderMap) -> Option<Self> {
+        let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+            return Some(Self::Json);
+        };
+
+        let mut best: Option<(Self, f32)> = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let Some(format) = params.next().and_then(Self::from_media_type) else {
+                continue;
+            };
+            let quality = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((format, quality));
+            }
+        }
+        best.map(|(format, _)| format)
+    }
+
+    /// Request body format from `Content-Type`; JSON when the header is missing.
+    fn from_content_type(headers: &HeaderMap) -> Option<Self> {
+        match headers.get(header::CONTENT_TYPE) {
+            None => Some(Self::Json),
+            Some(value) => {
+                let media_type = value.to_str().ok()?.split(';').next()?;
+                Self::from_media_type(media_type)
+            }
+        }
+    }
+}
+
+/// The response format the client asked for in `Accept`. Rejects with `406` when none of
+/// the listed types is supported.
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Self::from_accept(&parts.headers).ok_or((
+            StatusCode::NOT_ACCEPTABLE,
+            "Supported response types: application/json, application/xml, application/msgpack",
+        ))
+    }
+}
+
+/// Name of the root element in the XML representation.
+pub trait XmlRoot {
+    const ROOT: &'static str;
+    /// Root element for a list of these, which wraps one `ROOT` element per item.
+    const LIST_ROOT: &'static str = "list";
+}
+
+impl XmlRoot for Todo {
+    const ROOT: &'static str = "todo";
+    const LIST_ROOT: &'static str = "todos";
+}
+
+impl XmlRoot for CompactTodo {
+    const ROOT: &'static str = "todo";
+    const LIST_ROOT: &'static str = "todos";
+}
+
+impl XmlRoot for HistoryEntry {
+    const ROOT: &'static str = "entry";
+    const LIST_ROOT: &'static str = "history";
+}
+
+impl XmlRoot for Attachment {
+    const ROOT: &'static str = "attachment";
+    const LIST_ROOT: &'static str = "attachments";
+}
+
+impl XmlRoot for Comment {
+    const ROOT: &'static str = "comment";
+    const LIST_ROOT: &'static str = "comments";
+}
+
+impl XmlRoot for TodoStats {
+    const ROOT: &'static str = "stats";
+}
+
+impl XmlRoot for Velocity {
+    const ROOT: &'static str = "velocity";
+}
+
+impl XmlRoot for TodoAging {
+    const ROOT: &'static str = "aging";
+}
+
+impl XmlRoot for CompletionTime {
+    const ROOT: &'static str = "completion_time";
+}
+
+impl XmlRoot for BatchCreateResponse {
+    const ROOT: &'static str = "batch";
+}
+
+impl XmlRoot for ExpandedTodo {
+    const ROOT: &'static str = "todo";
+    const LIST_ROOT: &'static str = "todos";
+}
+
+impl<T> XmlRoot for TodoListPage<T> {
+    const ROOT: &'static str = "page";
+}
+
+impl XmlRoot for DeleteCompletedResponse {
+    const ROOT: &'static str = "deleted";
+}
+
+impl XmlRoot for BulkTagResponse {
+    const ROOT: &'static str = "tagged";
+}
+
+impl XmlRoot for SyncChanges {
+    const ROOT: &'static str = "sync";
+}
+
+impl XmlRoot for SyncPushResponse {
+    const ROOT: &'static str = "push";
+}
+
+impl XmlRoot for RankedTodo {
+    const ROOT: &'static str = "ranked_todo";
+    const LIST_ROOT: &'static str = "next";
+}
+
+impl XmlRoot for ParsedTodo {
+    const ROOT: &'static str = "parsed";
+}
+
+/// XML has no top-level sequences, so lists get an element per item.
+struct XmlList<'a, T>(&'a [T]);
+
+impl<T: XmlRoot + Serialize> Serialize for XmlList<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut list = serializer.serialize_struct(T::LIST_ROOT, 1)?;
+        list.serialize_field(T::ROOT, self.0)?;
+        list.end()
+    }
+}
+
+/// Something that can be written out in any `Format`.
+pub trait Negotiable {
+    fn to_xml(&self) -> Result<String, quick_xml::DeError>;
+    fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error>;
+    fn to_json(&self) -> Result<Vec<u8>, serde_json::Error>;
+    /// The value with camelCase field names, for clients that asked for them.
+    fn to_camel_case(&self) -> serde_json::Result<serde_json::Value>;
+}
+
+/// Field names are kept and ids and timestamps stay strings, so MessagePack clients
+/// see the same shape as JSON ones.
+fn msgpack<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    let mut buf = Vec::new();
+    value.serialize(
+        &mut rmp_serde::Serializer::new(&mut buf)
+            .with_struct_map()
+            .with_human_readable(),
+    )?;
+    Ok(buf)
+}
+
+impl<T: XmlRoot + CamelCase> Negotiable for T {
+    fn to_xml(&self) -> Result<String, quick_xml::DeError> {
+        quick_xml::se::to_string_with_root(T::ROOT, self)
+    }
+
+    fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        msgpack(self)
+    }
+
+    fn to_json(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec(self)
+    }
+
+    fn to_camel_case(&self) -> serde_json::Result<serde_json::Value> {
+        CamelCase::to_camel_case(self)
+    }
+}
+
+impl<T: XmlRoot + CamelCase> Negotiable for Vec<T> {
+    fn to_xml(&self) -> Result<String, quick_xml::DeError> {
+        quick_xml::se::to_string_with_root(T::LIST_ROOT, &XmlList(self))
+    }
+
+    fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        msgpack(self)
+    }
+
+    fn to_json(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec(self)
+    }
+
+    fn to_camel_case(&self) -> serde_json::Result<serde_json::Value> {
+        self.iter().map(CamelCase::to_camel_case).collect()
+    }
+}
+
+/// Cached results are shared between responses rather than copied for each.
+impl<T: Negotiable> Negotiable for Arc<T> {
+    fn to_xml(&self) -> Result<String, quick_xml::DeError> {
+        T::to_xml(self)
+    }
+
+    fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        T::to_msgpack(self)
+    }
+
+    fn to_json(&self) -> Result<Vec<u8>, serde_json::Error> {
+        T::to_json(self)
+    }
+
+    fn to_camel_case(&self) -> serde_json::Result<serde_json::Value> {
+        T::to_camel_case(self)
+    }
+}
+
+/// A response body serialized in the negotiated format, with the field names of the
+/// request's `FieldCase`. XML keeps the domain model's element names.
+pub struct Negotiated<T>(pub Format, pub T);
+
+impl<T: Negotiable> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        let Self(format, value) = self;
+        let camel = dto::current() == FieldCase::Camel;
+        let body = match format {
+            Format::Json if camel => value
+                .to_camel_case()
+                .and_then(|value| serde_json::to_vec(&value))
+                .map_err(|e| e.to_string()),
+            Format::Json => value.to_json().map_err(|e| e.to_string()),
+            Format::Xml => value.to_xml().map(String::into_bytes).map_err(|e| e.to_string()),
+            Format::MessagePack if camel => value
+                .to_camel_case()
+                .map_err(|e| e.to_string())
+                .and_then(|value| msgpack(&value).map_err(|e| e.to_string())),
+            Format::MessagePack => value.to_msgpack().map_err(|e| e.to_string()),
+        };
+        match body {
+            Ok(body) => (
+                [(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()))],
+                body,
+            )
+                .into_response(),
+            Err(e) => {
+                warn!(error = %e, format = ?format, "Failed to serialize response");
+                (StatusCode::INTERNAL_SERVER_ERROR, "Failed to serialize response").into_response()
+            }
+        }
+    }
+}
+
+/// What request bodies do with fields their type doesn't have (`API_MODE`): those read
+/// through `Payload`, and those checked against their schemas by `body_schema`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ApiMode {
+    /// Unknown fields are ignored, as serde ignores them.
+    #[default]
+    Lenient,
+    /// Unknown fields are refused with `422`, to catch client bugs such as a misspelt field
+    /// that would otherwise be dropped.
+    Strict,
+}
+
+impl FromStr for ApiMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "lenient" => Ok(Self::Lenient),
+            "strict" => Ok(Self::Strict),
+            other => Err(format!("unknown API mode: {other}")),
+        }
+    }
+}
+
+/// A request body in whichever supported format its `Content-Type` names. Under
+/// `ApiMode::Strict`, found in the request extensions, a body with fields its type doesn't
+/// have is refused, with every such field listed by its path.
+pub struct Payload<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Payload<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = Format::from_content_type(req.headers()).ok_or_else(|| {
+            (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Supported request types: application/json, application/xml, application/msgpack".to_string(),
+            )
+        })?;
+        let mode = req.extensions().get::<ApiMode>().copied().unwrap_or_default();
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| (e.status(), e.body_text()))?;
+
+        let mut unknown = Vec::new();
+        let track = |path: serde_ignored::Path| unknown.push(path.to_string());
+        let value = match format {
+            Format::Json => {
+                let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+                serde_ignored::deserialize(&mut deserializer, track)
+                    .and_then(|value| deserializer.end().map(|()| value))
+                    .map_err(|e| e.to_string())
+            }
+            Format::Xml => std::str::from_utf8(&bytes).map_err(|e| e.to_string()).and_then(|xml| {
+                let mut deserializer = quick_xml::de::Deserializer::from_str(xml);
+                serde_ignored::deserialize(&mut deserializer, track).map_err(|e| e.to_string())
+            }),
+            Format::MessagePack => {
+                let mut deserializer = rmp_serde::Deserializer::new(&bytes[..]).with_human_readable();
+                serde_ignored::deserialize(&mut deserializer, track).map_err(|e| e.to_string())
+            }
+        };
+        let value = value.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("Invalid request body: {e}")))?;
+        if mode == ApiMode::Strict && !unknown.is_empty() {
+            warn!(fields = ?unknown, "Request body with unknown fields refused");
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Invalid request body: unknown fields: {}", unknown.join(", ")),
+            ));
+        }
+        Ok(Payload(value))
+    }
+}
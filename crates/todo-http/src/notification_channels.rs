@@ -0,0 +1,560 @@
+use crate::digest::Digest;
+use crate::config::{DiscordConfig, EmailConfig, NotificationConfig, SlackConfig, TeamsConfig};
+use crate::feature_flags::FeatureFlags;
+use crate::latency::LatencyProfile;
+use crate::notification_worker::{MemberNotice, NotificationJob};
+use crate::external_service::{
+    post_json, webhook_client, HttpNotificationService, MockNotificationService, NotificationService,
+    ServiceError,
+};
+use crate::repository::SqliteTodoRepository;
+use crate::span_errors;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::future::join_all;
+use lettre::{message::Mailbox, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::{str::FromStr, sync::Arc, time::Duration};
+use tracing::{debug, info, info_span, instrument, warn, Instrument};
+use uuid::Uuid;
+
+/// A destination notifications can be sent to (`NOTIFICATION_CHANNELS`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationChannel {
+    /// Simulated webhook and email calls with random latency and failures.
+    Mock,
+    /// JSON `POST`s to the `WEBHOOK_*_URL` endpoints and webhook subscriptions.
+    Webhook,
+    Slack,
+    Discord,
+    Teams,
+    Email,
+}
+
+impl NotificationChannel {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Mock => "mock",
+            Self::Webhook => "webhook",
+            Self::Slack => "slack",
+            Self::Discord => "discord",
+            Self::Teams => "teams",
+            Self::Email => "email",
+        }
+    }
+}
+
+impl FromStr for NotificationChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mock" => Ok(Self::Mock),
+            "webhook" => Ok(Self::Webhook),
+            "slack" => Ok(Self::Slack),
+            "discord" => Ok(Self::Discord),
+            "teams" | "msteams" => Ok(Self::Teams),
+            "email" | "smtp" => Ok(Self::Email),
+            other => Err(format!("unknown notification channel: {other}")),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChannelConfigError {
+    #[error("{channel} channel requires {setting}")]
+    Missing {
+        channel: &'static str,
+        setting: &'static str,
+    },
+
+    #[error("Invalid {channel} channel configuration: {reason}")]
+    Invalid { channel: &'static str, reason: String },
+
+    #[error("No notification channels configured")]
+    Empty,
+}
+
+/// Builds the service for the configured channels, which fans each notification out to all
+/// of them. The `webhook` channel reads its subscriptions from `repository`.
+pub fn build_notification_service(
+    config: &NotificationConfig,
+    latency: LatencyProfile,
+    flags: Option<Arc<FeatureFlags>>,
+    repository: Arc<SqliteTodoRepository>,
+) -> Result<Arc<CompositeNotificationService>, ChannelConfigError> {
+    let mut channels = Vec::with_capacity(config.channels.len());
+    for &channel in &config.channels {
+        let service: Arc<dyn NotificationService> = match channel {
+            NotificationChannel::Mock => Arc::new(MockNotificationService::new(latency)),
+            // Subscriptions can be added at runtime, so no WEBHOOK_*_URL is required
+            NotificationChannel::Webhook => Arc::new(
+                HttpNotificationService::new(config.webhooks.clone(), repository.clone()).map_err(|e| {
+                    ChannelConfigError::Invalid {
+                        channel: channel.name(),
+                        reason: e.to_string(),
+                    }
+                })?,
+            ),
+            NotificationChannel::Slack => {
+                Arc::new(SlackNotificationService::new(&config.slack, config.webhooks.timeout)?)
+            }
+            NotificationChannel::Discord => {
+                Arc::new(DiscordNotificationService::new(&config.discord, config.webhooks.timeout)?)
+            }
+            NotificationChannel::Teams => {
+                Arc::new(TeamsNotificationService::new(&config.teams, config.webhooks.timeout)?)
+            }
+            NotificationChannel::Email => {
+                Arc::new(EmailNotificationService::new(&config.email, config.webhooks.timeout)?)
+            }
+        };
+        channels.push((channel.name(), service));
+    }
+
+    info!(channels = ?config.channels, "Notification channels configured");
+    if channels.is_empty() {
+        return Err(ChannelConfigError::Empty);
+    }
+    Ok(Arc::new(CompositeNotificationService::new(channels, flags)))
+}
+
+/// How one delivery attempt went across the channels it was sent to.
+pub struct ChannelDelivery {
+    /// The channels that took the notification this time.
+    pub delivered: Vec<&'static str>,
+    /// Fails if at least one channel did.
+    pub result: Result<(), ServiceError>,
+}
+
+/// Sends every notification to all channels concurrently. A failing channel never keeps the
+/// others from being tried, and the channels that delivered are reported, so a retry only
+/// goes to the ones that failed. A channel whose `notifications.<channel>` flag is off is
+/// skipped.
+pub struct CompositeNotificationService {
+    channels: Vec<(&'static str, Arc<dyn NotificationService>)>,
+    flags: Option<Arc<FeatureFlags>>,
+}
+
+impl CompositeNotificationService {
+    pub fn new(
+        channels: Vec<(&'static str, Arc<dyn NotificationService>)>,
+        flags: Option<Arc<FeatureFlags>>,
+    ) -> Self {
+        Self { channels, flags }
+    }
+
+    fn enabled(&self, channel: &str) -> bool {
+        self.flags
+            .as_ref()
+            .is_none_or(|flags| flags.is_enabled(&format!("notifications.{channel}"), true))
+    }
+
+    /// Sends `job` through every enabled channel not among `delivered`, the channels earlier
+    /// attempts already reached.
+    pub async fn deliver(&self, job: &NotificationJob, delivered: &[String]) -> ChannelDelivery {
+        let pending = self.channels.iter().filter(|(name, _)| {
+            if delivered.iter().any(|done| done == name) {
+                return false;
+            }
+            let enabled = self.enabled(name);
+            if !enabled {
+                debug!(channel = *name, "Notification channel switched off by feature flag");
+            }
+            enabled
+        });
+        let results = join_all(pending.map(|(name, service)| {
+            let span = info_span!("notification_channel", channel = *name);
+            async move { (*name, span_errors::capture(job.deliver(service.as_ref())).await) }.instrument(span)
+        }))
+        .await;
+
+        let total = results.len();
+        let mut succeeded = Vec::with_capacity(total);
+        let mut failures: Vec<(&str, ServiceError)> = Vec::new();
+        for (name, result) in results {
+            match result {
+                Ok(()) => succeeded.push(name),
+                Err(e) => failures.push((name, e)),
+            }
+        }
+        let result = if failures.is_empty() {
+            Ok(())
+        } else {
+            for (channel, error) in &failures {
+                warn!(channel, error = %error, "Notification channel failed");
+            }
+            // Only report rate limiting when that is the sole problem, so retries back off correctly
+            if failures.iter().all(|(_, e)| matches!(e, ServiceError::RateLimited)) {
+                Err(ServiceError::RateLimited)
+            } else {
+                let summary = failures
+                    .iter()
+                    .map(|(channel, error)| format!("{channel}: {error}"))
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                Err(ServiceError::NotificationFailed(format!(
+                    "{} of {total} channels failed ({summary})",
+                    failures.len()
+                )))
+            }
+        };
+        ChannelDelivery {
+            delivered: succeeded,
+            result,
+        }
+    }
+}
+
+/// Posts plain-text messages to a Slack incoming webhook.
+pub struct SlackNotificationService {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl SlackNotificationService {
+    pub fn new(config: &SlackConfig, timeout: Duration) -> Result<Self, ChannelConfigError> {
+        let webhook_url = config.webhook_url.as_ref().ok_or(ChannelConfigError::Missing {
+            channel: "slack",
+            setting: "SLACK_WEBHOOK_URL",
+        })?;
+        Ok(Self {
+            client: webhook_client(timeout).map_err(|e| ChannelConfigError::Invalid {
+                channel: "slack",
+                reason: e.to_string(),
+            })?,
+            webhook_url: webhook_url.expose().to_string(),
+        })
+    }
+
+    async fn post(&self, event: &str, text: String) -> Result<(), ServiceError> {
+        let payload = serde_json::json!({ "text": text });
+        post_json(&self.client, event, &self.webhook_url, &payload).await
+    }
+}
+
+#[async_trait]
+impl NotificationService for SlackNotificationService {
+    #[instrument(skip(self, title), fields(notification.type = "todo_created", todo.id = %todo_id))]
+    async fn send_created_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        self.post("todo.created", format!(":memo: New todo: *{title}* (`{todo_id}`)")).await
+    }
+
+    #[instrument(skip(self, title), fields(notification.type = "todo_completed", todo.id = %todo_id))]
+    async fn send_completed_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        self.post("todo.completed", format!(":white_check_mark: Completed: *{title}* (`{todo_id}`)")).await
+    }
+
+    #[instrument(skip(self), fields(notification.type = "batch_summary", batch.count = count))]
+    async fn send_batch_summary(&self, count: usize) -> Result<(), ServiceError> {
+        self.post("todo.batch_created", format!(":package: {count} todos created in a batch")).await
+    }
+
+    #[instrument(skip(self, digest), fields(notification.type = "daily_digest", digest.date = %digest.date))]
+    async fn send_digest(&self, digest: &Digest) -> Result<(), ServiceError> {
+        self.post("todo.digest", format!(":calendar: {}", digest.text())).await
+    }
+}
+
+/// Embed colours for Discord messages.
+const DISCORD_BLUE: u32 = 0x3498DB;
+const DISCORD_GREEN: u32 = 0x2ECC71;
+const DISCORD_RED: u32 = 0xE74C3C;
+
+/// Posts embeds to a Discord channel webhook.
+pub struct DiscordNotificationService {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl DiscordNotificationService {
+    pub fn new(config: &DiscordConfig, timeout: Duration) -> Result<Self, ChannelConfigError> {
+        let webhook_url = config.webhook_url.as_ref().ok_or(ChannelConfigError::Missing {
+            channel: "discord",
+            setting: "DISCORD_WEBHOOK_URL",
+        })?;
+        Ok(Self {
+            client: webhook_client(timeout).map_err(|e| ChannelConfigError::Invalid {
+                channel: "discord",
+                reason: e.to_string(),
+            })?,
+            webhook_url: webhook_url.expose().to_string(),
+        })
+    }
+
+    async fn post_embed(&self, event: &str, embed: serde_json::Value) -> Result<(), ServiceError> {
+        let payload = serde_json::json!({ "embeds": [embed] });
+        post_json(&self.client, event, &self.webhook_url, &payload).await
+    }
+
+    fn todo_embed(heading: &str, color: u32, todo_id: Uuid, title: &str) -> serde_json::Value {
+        serde_json::json!({
+            "title": heading,
+            "description": title,
+            "color": color,
+            "fields": [{ "name": "ID", "value": todo_id.to_string(), "inline": true }],
+            "timestamp": Utc::now().to_rfc3339(),
+        })
+    }
+}
+
+#[async_trait]
+impl NotificationService for DiscordNotificationService {
+    #[instrument(skip(self, title), fields(notification.type = "todo_created", todo.id = %todo_id))]
+    async fn send_created_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        let embed = Self::todo_embed("New todo", DISCORD_BLUE, todo_id, title);
+        self.post_embed("todo.created", embed).await
+    }
+
+    #[instrument(skip(self, title), fields(notification.type = "todo_completed", todo.id = %todo_id))]
+    async fn send_completed_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        let embed = Self::todo_embed("Todo completed", DISCORD_GREEN, todo_id, title);
+        self.post_embed("todo.completed", embed).await
+    }
+
+    #[instrument(skip(self), fields(notification.type = "batch_summary", batch.count = count))]
+    async fn send_batch_summary(&self, count: usize) -> Result<(), ServiceError> {
+        let embed = serde_json::json!({
+            "title": "Batch created",
+            "description": format!("{count} todos created in a batch"),
+            "color": DISCORD_BLUE,
+            "timestamp": Utc::now().to_rfc3339(),
+        });
+        self.post_embed("todo.batch_created", embed).await
+    }
+
+    #[instrument(skip(self, digest), fields(notification.type = "daily_digest", digest.date = %digest.date))]
+    async fn send_digest(&self, digest: &Digest) -> Result<(), ServiceError> {
+        let color = if digest.overdue_count > 0 { DISCORD_RED } else { DISCORD_BLUE };
+        let embed = serde_json::json!({
+            "title": format!("Todo digest for {}", digest.date),
+            "description": digest.text(),
+            "color": color,
+            "timestamp": Utc::now().to_rfc3339(),
+        });
+        self.post_embed("todo.digest", embed).await
+    }
+}
+
+/// Posts Adaptive Cards to a Microsoft Teams incoming webhook.
+pub struct TeamsNotificationService {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl TeamsNotificationService {
+    pub fn new(config: &TeamsConfig, timeout: Duration) -> Result<Self, ChannelConfigError> {
+        let webhook_url = config.webhook_url.as_ref().ok_or(ChannelConfigError::Missing {
+            channel: "teams",
+            setting: "TEAMS_WEBHOOK_URL",
+        })?;
+        Ok(Self {
+            client: webhook_client(timeout).map_err(|e| ChannelConfigError::Invalid {
+                channel: "teams",
+                reason: e.to_string(),
+            })?,
+            webhook_url: webhook_url.expose().to_string(),
+        })
+    }
+
+    /// Wraps card body elements in the message envelope Teams webhooks expect.
+    async fn post_card(&self, event: &str, body: serde_json::Value) -> Result<(), ServiceError> {
+        let payload = serde_json::json!({
+            "type": "message",
+            "attachments": [{
+                "contentType": "application/vnd.microsoft.card.adaptive",
+                "contentUrl": null,
+                "content": {
+                    "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                    "type": "AdaptiveCard",
+                    "version": "1.4",
+                    "body": body,
+                },
+            }],
+        });
+        post_json(&self.client, event, &self.webhook_url, &payload).await
+    }
+
+    fn todo_card(heading: &str, todo_id: Uuid, title: &str) -> serde_json::Value {
+        serde_json::json!([
+            { "type": "TextBlock", "text": heading, "weight": "Bolder", "size": "Medium" },
+            { "type": "TextBlock", "text": title, "wrap": true },
+            { "type": "FactSet", "facts": [{ "title": "ID", "value": todo_id.to_string() }] },
+        ])
+    }
+}
+
+#[async_trait]
+impl NotificationService for TeamsNotificationService {
+    #[instrument(skip(self, title), fields(notification.type = "todo_created", todo.id = %todo_id))]
+    async fn send_created_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        self.post_card("todo.created", Self::todo_card("New todo", todo_id, title))
+            .await
+    }
+
+    #[instrument(skip(self, title), fields(notification.type = "todo_completed", todo.id = %todo_id))]
+    async fn send_completed_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        self.post_card("todo.completed", Self::todo_card("Todo completed", todo_id, title))
+            .await
+    }
+
+    #[instrument(skip(self), fields(notification.type = "batch_summary", batch.count = count))]
+    async fn send_batch_summary(&self, count: usize) -> Result<(), ServiceError> {
+        let body = serde_json::json!([
+            { "type": "TextBlock", "text": "Batch created", "weight": "Bolder", "size": "Medium" },
+            { "type": "TextBlock", "text": format!("{count} todos created in a batch") },
+        ]);
+        self.post_card("todo.batch_created", body).await
+    }
+
+    #[instrument(skip(self, digest), fields(notification.type = "daily_digest", digest.date = %digest.date))]
+    async fn send_digest(&self, digest: &Digest) -> Result<(), ServiceError> {
+        let mut body = vec![
+            serde_json::json!({
+                "type": "TextBlock",
+                "text": format!("Todo digest for {}", digest.date),
+                "weight": "Bolder",
+                "size": "Medium",
+            }),
+            serde_json::json!({
+                "type": "FactSet",
+                "facts": [
+                    { "title": "Open", "value": digest.open_count.to_string() },
+                    { "title": "Due today", "value": digest.due_today_count.to_string() },
+                    { "title": "Overdue", "value": digest.overdue_count.to_string() },
+                ],
+            }),
+        ];
+        for (heading, items) in [("Overdue", &digest.overdue), ("Due today", &digest.due_today)] {
+            if items.is_empty() {
+                continue;
+            }
+            let lines: Vec<String> = items.iter().map(|item| format!("- {}", item.title)).collect();
+            body.push(serde_json::json!({ "type": "TextBlock", "text": heading, "weight": "Bolder" }));
+            body.push(serde_json::json!({ "type": "TextBlock", "text": lines.join("\r"), "wrap": true }));
+        }
+        self.post_card("todo.digest", serde_json::Value::Array(body)).await
+    }
+}
+
+/// Sends plain-text emails over SMTP.
+pub struct EmailNotificationService {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl EmailNotificationService {
+    pub fn new(config: &EmailConfig, timeout: Duration) -> Result<Self, ChannelConfigError> {
+        let invalid = |reason: String| ChannelConfigError::Invalid { channel: "email", reason };
+        let smtp_url = config.smtp_url.as_ref().ok_or(ChannelConfigError::Missing {
+            channel: "email",
+            setting: "SMTP_URL",
+        })?;
+        let from = config.from.as_deref().ok_or(ChannelConfigError::Missing {
+            channel: "email",
+            setting: "EMAIL_FROM",
+        })?;
+        if config.to.is_empty() {
+            return Err(ChannelConfigError::Missing {
+                channel: "email",
+                setting: "EMAIL_TO",
+            });
+        }
+
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(smtp_url.expose())
+            .map_err(|e| invalid(e.to_string()))?
+            .timeout(Some(timeout))
+            .build();
+        let from = from.parse().map_err(|e| invalid(format!("EMAIL_FROM: {e}")))?;
+        let to = config
+            .to
+            .iter()
+            .map(|address| address.parse().map_err(|e| invalid(format!("EMAIL_TO {address}: {e}"))))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { transport, from, to })
+    }
+
+    async fn send(&self, subject: &str, body: String) -> Result<(), ServiceError> {
+        self.send_to(&self.to, subject, body).await
+    }
+
+    #[instrument(name = "smtp_send", skip(self, to, subject, body), fields(otel.kind = "client", recipients = to.len()))]
+    async fn send_to(&self, to: &[Mailbox], subject: &str, body: String) -> Result<(), ServiceError> {
+        span_errors::capture(async {
+            let mut message = Message::builder().from(self.from.clone()).subject(subject);
+            for recipient in to {
+                message = message.to(recipient.clone());
+            }
+            let message = message
+                .body(body)
+                .map_err(|e| ServiceError::NotificationFailed(e.to_string()))?;
+
+            self.transport.send(message).await.map_err(|e| {
+                if e.is_timeout() {
+                    ServiceError::Timeout
+                } else {
+                    ServiceError::NotificationFailed(e.to_string())
+                }
+            })?;
+            info!("Email sent");
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl NotificationService for EmailNotificationService {
+    #[instrument(skip(self, title), fields(notification.type = "todo_created", todo.id = %todo_id))]
+    async fn send_created_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        self.send(
+            &format!("New todo: {title}"),
+            format!("A new todo was created.\n\nTitle: {title}\nID: {todo_id}\n"),
+        )
+        .await
+    }
+
+    #[instrument(skip(self, title), fields(notification.type = "todo_completed", todo.id = %todo_id))]
+    async fn send_completed_notification(&self, todo_id: Uuid, title: &str) -> Result<(), ServiceError> {
+        self.send(
+            &format!("Completed: {title}"),
+            format!("A todo was marked as completed.\n\nTitle: {title}\nID: {todo_id}\n"),
+        )
+        .await
+    }
+
+    #[instrument(skip(self), fields(notification.type = "batch_summary", batch.count = count))]
+    async fn send_batch_summary(&self, count: usize) -> Result<(), ServiceError> {
+        self.send(
+            &format!("{count} todos created"),
+            format!("{count} todos were created in a single batch.\n"),
+        )
+        .await
+    }
+
+    #[instrument(skip(self, digest), fields(notification.type = "daily_digest", digest.date = %digest.date))]
+    async fn send_digest(&self, digest: &Digest) -> Result<(), ServiceError> {
+        self.send(&format!("Todo digest for {}: {}", digest.date, digest.summary()), digest.text())
+            .await
+    }
+
+    /// Goes to the notice's recipients instead of `EMAIL_TO`.
+    #[instrument(skip(self, notice), fields(notification.type = "member_notice"))]
+    async fn send_member_notice(&self, notice: &MemberNotice) -> Result<(), ServiceError> {
+        let to: Vec<Mailbox> = notice
+            .recipients
+            .iter()
+            .filter_map(|address| match address.parse() {
+                Ok(mailbox) => Some(mailbox),
+                Err(e) => {
+                    warn!(error = %e, "Skipping recipient with an invalid address");
+                    None
+                }
+            })
+            .collect();
+        if to.is_empty() {
+            return Ok(());
+        }
+        self.send_to(&to, &notice.subject, notice.text.clone()).await
+    }
+}
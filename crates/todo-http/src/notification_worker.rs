@@ -0,0 +1,482 @@
+use crate::config::NotificationConfig;
+use crate::deadline;
+use crate::digest::Digest;
+use crate::external_service::{NotificationService, ServiceError};
+use crate::notification_channels::CompositeNotificationService;
+use crate::repository::{OutboxClaim, OutboxEntry, RepositoryError, SqliteTodoRepository};
+use chrono::{DateTime, Utc};
+use opentelemetry::{
+    global,
+    metrics::{Counter, ObservableGauge},
+    trace::{SpanContext, TraceContextExt},
+    KeyValue,
+};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+};
+use tracing::{debug_span, error, info, info_span, warn, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
+/// How long a claimed notification is reserved for one worker. If the process dies
+/// mid-delivery, the row becomes due again once this runs out. The worker renews it just
+/// before sending, so a row that waited in the queue still gets the full lease.
+const DELIVERY_LEASE: Duration = Duration::from_secs(60);
+
+/// Retries never wait longer than this, however many attempts have failed.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotificationJob {
+    Created { todo_id: Uuid, title: String },
+    Completed { todo_id: Uuid, title: String },
+    BatchSummary { count: usize },
+    Digest(Digest),
+    Member(MemberNotice),
+}
+
+/// A message for particular people rather than the whole team, e.g. the members of a
+/// project. Only channels that can reach a person deliver it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberNotice {
+    /// Email addresses.
+    pub recipients: Vec<String>,
+    pub subject: String,
+    pub text: String,
+}
+
+impl NotificationJob {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Created { .. } => "todo_created",
+            Self::Completed { .. } => "todo_completed",
+            Self::BatchSummary { .. } => "batch_summary",
+            Self::Digest(_) => "daily_digest",
+            Self::Member(_) => "member_notice",
+        }
+    }
+
+    pub(crate) async fn deliver(&self, service: &dyn NotificationService) -> Result<(), ServiceError> {
+        match self {
+            Self::Created { todo_id, title } => service.send_created_notification(*todo_id, title).await,
+            Self::Completed { todo_id, title } => {
+                service.send_completed_notification(*todo_id, title).await
+            }
+            Self::BatchSummary { count } => service.send_batch_summary(*count).await,
+            Self::Digest(digest) => service.send_digest(digest).await,
+            Self::Member(notice) => service.send_member_notice(notice).await,
+        }
+    }
+}
+
+/// An outbox row on its way to a worker, plus the context of the span it was queued from so
+/// the delivery trace links back to the request (or dispatcher pass) that produced it. Only
+/// the context is kept, so a queued row never holds that span open.
+struct QueuedJob {
+    id: Uuid,
+    job: NotificationJob,
+    attempts: u32,
+    /// The claim the row was queued under; the worker only sends it while this still holds.
+    claim: OutboxClaim,
+    /// Channels an earlier attempt already delivered it to, which retries leave out.
+    delivered: Vec<String>,
+    enqueued_from: SpanContext,
+    /// Spans of the work the notification sums up, such as a batch's items, linked from the
+    /// delivery span too. Rows picked up by the dispatcher have none.
+    links: Vec<SpanContext>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum NotificationError {
+    #[error("Failed to store notification: {0}")]
+    Storage(#[from] RepositoryError),
+
+    #[error("Failed to encode notification: {0}")]
+    Encode(#[from] serde_json::Error),
+}
+
+/// The current span's context, to link to from the delivery span.
+fn enqueuing_span() -> SpanContext {
+    Span::current().context().span().span_context().clone()
+}
+
+/// When a claim taken now on `outbox` lapses.
+fn lease_expiry(outbox: &SqliteTodoRepository) -> DateTime<Utc> {
+    outbox.clock().now() + DELIVERY_LEASE
+}
+
+/// Cloneable handle for handing notifications to the worker pool.
+#[derive(Clone)]
+pub struct NotificationQueue {
+    sender: mpsc::Sender<QueuedJob>,
+    outbox: Arc<SqliteTodoRepository>,
+    /// This instance's name on the rows it claims (`INSTANCE_ID`).
+    instance: Arc<str>,
+    enqueued: Counter<u64>,
+    deferred: Counter<u64>,
+}
+
+impl NotificationQueue {
+    /// Persists the notification, then hands it straight to a worker. When the in-memory
+    /// queue is full the row is left for the dispatcher instead, so nothing is dropped.
+    /// Gives up when the current request's deadline passes; a row stored by then is still
+    /// delivered by the dispatcher once its lease runs out.
+    pub async fn enqueue(&self, job: NotificationJob) -> Result<(), NotificationError> {
+        self.enqueue_linked(job, Vec::new()).await
+    }
+
+    /// `enqueue`, with the delivery span also linked to each of `links`.
+    pub async fn enqueue_linked(&self, job: NotificationJob, links: Vec<SpanContext>) -> Result<(), NotificationError> {
+        deadline::within(self.store(job, links))
+            .await
+            .unwrap_or(Err(NotificationError::Storage(RepositoryError::DeadlineExceeded)))
+    }
+
+    /// Notifications waiting for a worker, and how many the queue holds before it leaves
+    /// them to the dispatcher.
+    pub fn depth(&self) -> (usize, usize) {
+        (self.sender.max_capacity() - self.sender.capacity(), self.sender.max_capacity())
+    }
+
+    async fn store(&self, job: NotificationJob, links: Vec<SpanContext>) -> Result<(), NotificationError> {
+        let id = Uuid::new_v4();
+        let kind = [KeyValue::new("notification.type", job.kind())];
+        let payload = serde_json::to_string(&job)?;
+        let claim = OutboxClaim::new(self.instance.as_ref(), self.outbox.clock().now());
+        self.outbox
+            .enqueue_notification(id, &payload, &claim, lease_expiry(&self.outbox))
+            .await?;
+        self.enqueued.add(1, &kind);
+
+        let queued = QueuedJob {
+            id,
+            job,
+            attempts: 0,
+            claim: claim.clone(),
+            delivered: Vec::new(),
+            enqueued_from: enqueuing_span(),
+            links,
+        };
+        if self.sender.try_send(queued).is_err() {
+            self.deferred.add(1, &kind);
+            self.outbox
+                .reschedule_notification(id, &claim, 0, self.outbox.clock().now(), None, &[])
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Running worker and dispatcher tasks.
+pub struct NotificationWorkers {
+    handles: Vec<JoinHandle<()>>,
+    /// `None` when `dispatch_outbox` is off.
+    dispatcher: Option<JoinHandle<()>>,
+    _depth: ObservableGauge<u64>,
+}
+
+impl NotificationWorkers {
+    /// Stops pulling from the outbox and waits for the in-memory queue to drain, up to
+    /// `timeout`. The workers exit once every `NotificationQueue` has been dropped; anything
+    /// left undelivered stays in the outbox for the next start.
+    pub async fn drain(self, timeout: Duration) {
+        if let Some(dispatcher) = &self.dispatcher {
+            dispatcher.abort();
+        }
+        info!(workers = self.handles.len(), "Draining notification queue");
+        let all_done = async {
+            for handle in self.handles {
+                let _ = handle.await;
+            }
+        };
+        if tokio::time::timeout(timeout, all_done).await.is_err() {
+            warn!(timeout_secs = timeout.as_secs(), "Notification queue not drained before timeout");
+        } else {
+            info!("Notification queue drained");
+        }
+    }
+}
+
+/// Exponential backoff: `base`, `2 * base`, `4 * base`, ... capped at `MAX_RETRY_DELAY`.
+fn retry_delay(base: Duration, attempts: u32) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY)
+}
+
+struct Worker {
+    service: Arc<CompositeNotificationService>,
+    outbox: Arc<SqliteTodoRepository>,
+    max_attempts: u32,
+    retry_base_delay: Duration,
+    delivered: Counter<u64>,
+    /// `attempts` and `failures` share the `notification.type` label alone, so their ratio is
+    /// a failure rate without aggregating `outcome` away.
+    attempts: Counter<u64>,
+    failures: Counter<u64>,
+}
+
+impl Worker {
+    async fn process(&self, worker: usize, queued: QueuedJob) {
+        let QueuedJob { id, job, attempts, claim, mut delivered, enqueued_from, links } = queued;
+        let attempt = attempts + 1;
+        let span = info_span!(
+            parent: None,
+            "notification_job",
+            worker,
+            notification.id = %id,
+            notification.type = job.kind(),
+            notification.attempt = attempt,
+        );
+        span.add_link(enqueued_from);
+        for link in links {
+            span.add_link(link);
+        }
+
+        // The row may have sat in the queue past its lease and been claimed again elsewhere;
+        // only the current claimant sends it
+        match self
+            .outbox
+            .renew_notification_claim(id, &claim, lease_expiry(&self.outbox))
+            .instrument(span.clone())
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!(notification.id = %id, "Notification claimed by another worker, skipping");
+                self.record(&job, "skipped");
+                return;
+            }
+            Err(e) => {
+                // Left to the dispatcher once the lease runs out
+                error!(error = %e, notification.id = %id, "Failed to renew notification claim");
+                self.record(&job, "skipped");
+                return;
+            }
+        }
+
+        let delivery = self.service.deliver(&job, &delivered).instrument(span.clone()).await;
+        delivered.extend(delivery.delivered.into_iter().map(String::from));
+        let (outcome, settled) = match delivery.result {
+            Ok(()) => {
+                let settled = self.outbox.complete_notification(id, &claim).instrument(span).await;
+                ("success", settled)
+            }
+            Err(e) if attempt >= self.max_attempts => {
+                error!(error = %e, notification.id = %id, attempt, "Giving up on notification");
+                let settled = self
+                    .outbox
+                    .fail_notification(id, &claim, attempt, &e.to_string())
+                    .instrument(span)
+                    .await;
+                ("failed", settled)
+            }
+            Err(e) => {
+                let delay = retry_delay(self.retry_base_delay, attempt);
+                warn!(error = %e, notification.id = %id, attempt, retry_in_secs = delay.as_secs(), "Failed to send notification, will retry");
+                let next_attempt_at = self.outbox.clock().now() + delay;
+                let settled = self
+                    .outbox
+                    .reschedule_notification(id, &claim, attempt, next_attempt_at, Some(&e.to_string()), &delivered)
+                    .instrument(span)
+                    .await;
+                ("retry", settled)
+            }
+        };
+        match settled {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!(notification.id = %id, outcome, "Notification claim lost during delivery; another worker may send it again");
+            }
+            Err(e) => {
+                error!(error = %e, notification.id = %id, outcome, "Failed to record notification delivery");
+            }
+        }
+        self.record(&job, outcome);
+    }
+
+    fn record(&self, job: &NotificationJob, outcome: &'static str) {
+        let kind = KeyValue::new("notification.type", job.kind());
+        self.delivered.add(1, &[kind.clone(), KeyValue::new("outcome", outcome)]);
+        if outcome == "skipped" {
+            return;
+        }
+        let kind = [kind];
+        self.attempts.add(1, &kind);
+        if outcome != "success" {
+            self.failures.add(1, &kind);
+        }
+    }
+}
+
+/// Decodes a claimed outbox row; rows that no longer parse are failed right away.
+async fn decode_entry(
+    outbox: &SqliteTodoRepository,
+    claim: &OutboxClaim,
+    entry: OutboxEntry,
+) -> Option<QueuedJob> {
+    let attempts = u32::try_from(entry.attempts).unwrap_or(0);
+    let Ok(id) = Uuid::parse_str(&entry.id) else {
+        error!(notification.id = %entry.id, "Skipping outbox row with an invalid id");
+        return None;
+    };
+    // A row whose channel list doesn't parse is retried on every channel
+    let delivered = serde_json::from_str(&entry.delivered_channels).unwrap_or_default();
+    match serde_json::from_str(&entry.payload) {
+        Ok(job) => Some(QueuedJob {
+            id,
+            job,
+            attempts,
+            claim: claim.clone(),
+            delivered,
+            enqueued_from: enqueuing_span(),
+            links: Vec::new(),
+        }),
+        Err(e) => {
+            error!(error = %e, notification.id = %id, "Undecodable notification payload");
+            let _ = outbox.fail_notification(id, claim, attempts, &e.to_string()).await;
+            None
+        }
+    }
+}
+
+/// Moves due outbox rows (retries, overflow, leftovers from a previous run) onto the queue.
+async fn dispatch_due(outbox: &SqliteTodoRepository, sender: &mpsc::Sender<QueuedJob>, instance: &str) {
+    let room = sender.capacity();
+    if room == 0 {
+        return;
+    }
+
+    let claim = OutboxClaim::new(instance, outbox.clock().now());
+    let entries = match outbox.claim_due_notifications(&claim, lease_expiry(outbox), room).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!(error = %e, "Failed to claim due notifications");
+            return;
+        }
+    };
+    if entries.is_empty() {
+        return;
+    }
+
+    info!(count = entries.len(), "Dispatching notifications from outbox");
+    for entry in entries {
+        if let Some(queued) = decode_entry(outbox, &claim, entry).await {
+            if sender.send(queued).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Starts the worker pool and the outbox dispatcher. Notifications still pending from a
+/// previous run are picked up on the first dispatcher pass once their lease has expired.
+/// Rows are claimed under `instance`, so replicas sharing the outbox never send the same
+/// notification at once.
+pub fn spawn_notification_workers(
+    service: Arc<CompositeNotificationService>,
+    outbox: Arc<SqliteTodoRepository>,
+    config: &NotificationConfig,
+    instance: &str,
+) -> (NotificationQueue, NotificationWorkers) {
+    let instance: Arc<str> = Arc::from(instance);
+    let (sender, receiver) = mpsc::channel::<QueuedJob>(config.queue_capacity);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let meter = global::meter("todo-api");
+
+    let depth_sender = sender.downgrade();
+    let depth = meter
+        .u64_observable_gauge("notifications.queue.depth")
+        .with_description("Notifications waiting for a worker")
+        .with_callback(move |observer| {
+            if let Some(sender) = depth_sender.upgrade() {
+                let depth = sender.max_capacity() - sender.capacity();
+                observer.observe(depth as u64, &[]);
+            }
+        })
+        .init();
+
+    let worker = Arc::new(Worker {
+        service,
+        outbox: outbox.clone(),
+        max_attempts: config.max_attempts,
+        retry_base_delay: config.retry_base_delay,
+        delivered: meter
+            .u64_counter("notifications.delivered")
+            .with_description("Notification delivery attempts, by outcome")
+            .init(),
+        attempts: meter
+            .u64_counter("notification.attempts")
+            .with_description("Notifications sent to their channel, successfully or not")
+            .init(),
+        failures: meter
+            .u64_counter("notification.failures")
+            .with_description("Notification sends that failed, whether retried or given up on")
+            .init(),
+    });
+
+    let handles = (0..config.workers)
+        .map(|index| {
+            let receiver = receiver.clone();
+            let worker = worker.clone();
+            tokio::spawn(async move {
+                loop {
+                    // Hold the lock only while waiting for the next job, not while delivering it
+                    let next = receiver.lock().await.recv().await;
+                    let Some(queued) = next else {
+                        break;
+                    };
+                    worker.process(index, queued).await;
+                }
+            })
+        })
+        .collect();
+
+    let dispatcher = config.dispatch_outbox.then(|| {
+        let outbox = outbox.clone();
+        let sender = sender.clone();
+        let instance = instance.clone();
+        let poll_interval = config.poll_interval;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(poll_interval);
+            loop {
+                interval.tick().await;
+                dispatch_due(&outbox, &sender, &instance)
+                    .instrument(debug_span!("notification_dispatch"))
+                    .await;
+            }
+        })
+    });
+
+    info!(
+        workers = config.workers,
+        capacity = config.queue_capacity,
+        max_attempts = config.max_attempts,
+        "Notification workers started"
+    );
+
+    let queue = NotificationQueue {
+        sender,
+        outbox,
+        instance,
+        enqueued: meter
+            .u64_counter("notifications.enqueued")
+            .with_description("Notifications accepted into the outbox")
+            .init(),
+        deferred: meter
+            .u64_counter("notifications.deferred")
+            .with_description("Notifications left for the dispatcher because the queue was full")
+            .init(),
+    };
+    (
+        queue,
+        NotificationWorkers {
+            handles,
+            dispatcher,
+            _depth: depth,
+        },
+    )
+}
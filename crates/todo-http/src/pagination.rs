@@ -0,0 +1,201 @@
+use crate::freshness::list_headers;
+use crate::repository::{ListWindow, TodoCursor, TodoListSummary, TodoPage};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Uri};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub const X_HAS_MORE: HeaderName = HeaderName::from_static("x-has-more");
+
+/// Most todos one page can hold; larger `limit`s and `per_page`s are cut down to it.
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Todos on a numbered page without a `per_page`.
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Todos on a cursor page without a `limit`.
+pub const DEFAULT_CURSOR_PAGE_SIZE: usize = 50;
+
+/// `?offset=` and `?limit=` on a list, which is otherwise returned whole, or `?page=` and
+/// `?per_page=`, which stand for the same window and are answered in an envelope. Either is
+/// read with LIMIT and OFFSET in the query; `X-Total-Count` and the `ETag` still describe the
+/// whole list.
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    pub offset: usize,
+    /// `None` for everything after `offset`.
+    pub limit: Option<usize>,
+    /// The page number, from 1, when asked for with `?page=` or `?per_page=`.
+    pub number: Option<usize>,
+}
+
+impl Page {
+    /// `None` when the query has none of the parameters.
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Option<Self>, String> {
+        let number = |key: &str, min: usize| match query.get(key).map(|value| value.parse::<usize>()) {
+            None => Ok(None),
+            Some(Ok(n)) if n < min => Err(format!("{key} must be at least {min}")),
+            Some(Ok(n)) => Ok(Some(n)),
+            Some(Err(_)) => Err(format!("{key} must be a whole number")),
+        };
+        let (offset, limit) = (number("offset", 0)?, number("limit", 1)?);
+        let (page, per_page) = (number("page", 1)?, number("per_page", 1)?);
+        if page.is_some() || per_page.is_some() {
+            if offset.is_some() || limit.is_some() || query.contains_key("cursor") {
+                return Err("page and per_page can't be combined with offset, limit or cursor".to_string());
+            }
+            let (page, per_page) = (page.unwrap_or(1), per_page.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE));
+            return Ok(Some(Self {
+                offset: (page - 1).saturating_mul(per_page),
+                limit: Some(per_page),
+                number: Some(page),
+            }));
+        }
+        if offset.is_none() && limit.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            offset: offset.unwrap_or(0),
+            limit: limit.map(|limit| limit.min(MAX_PAGE_SIZE)),
+            number: None,
+        }))
+    }
+
+    /// The slice of the list this page holds.
+    pub fn window(&self) -> ListWindow {
+        ListWindow {
+            offset: self.offset,
+            limit: self.limit.unwrap_or(usize::MAX),
+        }
+    }
+
+    /// Pages of `limit` todos in a list of `total`.
+    pub fn total_pages(&self, total: usize) -> usize {
+        self.limit.map_or(1, |limit| total.div_ceil(limit))
+    }
+
+    /// `Link` to the next and previous pages of a list of `total` items, as `uri` with only its
+    /// `offset` changed, or to the first, last and neighbouring pages, as `uri` with only its
+    /// `page` changed, when numbered. `None` on a lone page, or without a `limit`.
+    pub fn links(&self, uri: &Uri, total: usize) -> Option<HeaderValue> {
+        let limit = self.limit?;
+        let mut links = Vec::new();
+        match self.number {
+            Some(page) => {
+                let link = |page: usize, rel: &str| format!("<{}>; rel=\"{rel}\"", with_param(uri, "page", &page.to_string()));
+                let last = self.total_pages(total).max(1);
+                links.push(link(1, "first"));
+                if page > 1 {
+                    links.push(link((page - 1).min(last), "prev"));
+                }
+                if page < last {
+                    links.push(link(page + 1, "next"));
+                }
+                links.push(link(last, "last"));
+            }
+            None => {
+                if self.offset.saturating_add(limit) < total {
+                    links.push(format!("<{}>; rel=\"next\"", with_offset(uri, self.offset + limit)));
+                }
+                if self.offset > 0 {
+                    let previous = self.offset.min(total).saturating_sub(limit);
+                    links.push(format!("<{}>; rel=\"prev\"", with_offset(uri, previous)));
+                }
+            }
+        }
+        if links.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&links.join(", ")).ok()
+    }
+}
+
+/// `list_headers` for the whole list `summary` describes, plus `Link` when paging.
+pub fn paged_headers(summary: &TodoListSummary, page: Option<Page>, uri: &Uri) -> HeaderMap {
+    let mut headers = list_headers(summary);
+    if let Some(links) = page.and_then(|page| page.links(uri, summary.count)) {
+        headers.insert(header::LINK, links);
+    }
+    headers
+}
+
+fn with_offset(uri: &Uri, offset: usize) -> String {
+    with_param(uri, "offset", &offset.to_string())
+}
+
+/// `uri` with `name` set to `value`, replacing any it had.
+fn with_param(uri: &Uri, name: &str, value: &str) -> String {
+    let param = format!("{name}={value}");
+    let mut params: Vec<&str> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|param| !param.is_empty() && param.split('=').next() != Some(name))
+        .collect();
+    params.push(&param);
+    format!("{}?{}", uri.path(), params.join("&"))
+}
+
+/// `?cursor=` and `?limit=` on `GET /todos`: a keyset page, for infinite scroll. Each page
+/// is read on its own, in one query that also tells whether another follows, so nothing is
+/// counted and pages don't shift when todos are added above them.
+#[derive(Debug, Clone, Copy)]
+pub struct CursorPage {
+    /// `None` for the first page.
+    pub after: Option<TodoCursor>,
+    pub limit: usize,
+}
+
+impl CursorPage {
+    /// `None` without a `cursor`. An empty one asks for the first page.
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Option<Self>, String> {
+        let Some(cursor) = query.get("cursor") else {
+            return Ok(None);
+        };
+        if query.contains_key("offset") {
+            return Err("cursor and offset can't be used together".to_string());
+        }
+        let after = match cursor.as_str() {
+            "" => None,
+            cursor => Some(decode_cursor(cursor).ok_or("cursor is not one this server gave out")?),
+        };
+        let limit = match query.get("limit") {
+            Some(limit) => match limit.parse::<usize>() {
+                Ok(0) => return Err("limit must be at least 1".to_string()),
+                Ok(limit) => limit.min(MAX_PAGE_SIZE),
+                Err(_) => return Err("limit must be a whole number".to_string()),
+            },
+            None => DEFAULT_CURSOR_PAGE_SIZE,
+        };
+        Ok(Some(Self { after, limit }))
+    }
+}
+
+/// `X-Has-More`, and `Link` to the next page when there is one, as `uri` with only its
+/// `cursor` changed.
+pub fn cursor_headers(page: &TodoPage, uri: &Uri) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(X_HAS_MORE, HeaderValue::from_static(if page.has_more { "true" } else { "false" }));
+    if let Some(next) = page.next() {
+        let link = format!("<{}>; rel=\"next\"", with_param(uri, "cursor", &encode_cursor(next)));
+        if let Ok(link) = HeaderValue::from_str(&link) {
+            headers.insert(header::LINK, link);
+        }
+    }
+    headers
+}
+
+/// Opaque to clients, so the keyset can change without breaking them.
+fn encode_cursor(cursor: TodoCursor) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{}|{}", cursor.created_at.to_rfc3339(), cursor.id))
+}
+
+fn decode_cursor(cursor: &str) -> Option<TodoCursor> {
+    let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+    let (created_at, id) = decoded.split_once('|')?;
+    Some(TodoCursor {
+        created_at: DateTime::parse_from_rfc3339(created_at).ok()?.with_timezone(&Utc),
+        id: Uuid::parse_str(id).ok()?,
+    })
+}
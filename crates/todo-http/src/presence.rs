@@ -0,0 +1,187 @@
+use crate::models::User;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::{stream, Stream, StreamExt};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+/// Events a viewer may fall behind by before it is sent a fresh snapshot instead.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Someone with a project open.
+#[derive(Debug, Clone, Serialize)]
+pub struct Viewer {
+    pub user_id: Uuid,
+    pub email: String,
+    pub username: Option<String>,
+}
+
+impl From<&User> for Viewer {
+    fn from(user: &User) -> Self {
+        Self {
+            user_id: user.id,
+            email: user.email.clone(),
+            username: user.username.clone(),
+        }
+    }
+}
+
+/// What `GET /projects/{id}/presence` sends.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PresenceEvent {
+    /// Everyone viewing the project, the subscriber included. Sent first, and again in place
+    /// of events the subscriber fell behind on.
+    Snapshot { viewers: Vec<Viewer> },
+    Join { viewer: Viewer },
+    Leave { viewer: Viewer },
+}
+
+impl PresenceEvent {
+    fn to_sse(&self) -> Event {
+        let name = match self {
+            Self::Snapshot { .. } => "snapshot",
+            Self::Join { .. } => "join",
+            Self::Leave { .. } => "leave",
+        };
+        Event::default()
+            .event(name)
+            .json_data(self)
+            .unwrap_or_else(|_| Event::default().event("snapshot"))
+    }
+}
+
+/// The viewers of one project and the channel their joins and leaves go out on.
+struct Room {
+    sender: broadcast::Sender<PresenceEvent>,
+    /// Each viewer with their number of open streams, so a second tab doesn't join twice.
+    viewers: HashMap<Uuid, (Viewer, usize)>,
+}
+
+impl Room {
+    fn viewers(&self) -> Vec<Viewer> {
+        let mut viewers: Vec<Viewer> = self.viewers.values().map(|(viewer, _)| viewer.clone()).collect();
+        viewers.sort_by(|a, b| a.email.cmp(&b.email));
+        viewers
+    }
+}
+
+/// Who has which project open, for clients to show alongside the list. A viewer is present
+/// while they hold a presence stream open, and leaves when the last one closes.
+///
+/// Presence is kept in memory: each replica knows only the streams it serves, and nothing
+/// survives a restart, when clients reconnect and join again.
+#[derive(Clone, Default)]
+pub struct Presence {
+    rooms: Arc<Mutex<HashMap<Uuid, Room>>>,
+    shutdown: CancellationToken,
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everyone viewing the project now.
+    pub fn viewers(&self, project_id: Uuid) -> Vec<Viewer> {
+        self.rooms
+            .lock()
+            .unwrap()
+            .get(&project_id)
+            .map(Room::viewers)
+            .unwrap_or_default()
+    }
+
+    /// Marks `user` as viewing the project until the returned guard is dropped, announcing
+    /// them if they weren't already. The receiver hears about joins and leaves after the
+    /// snapshot.
+    fn join(&self, project_id: Uuid, user: &User) -> (PresenceEvent, broadcast::Receiver<PresenceEvent>, Visit) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.entry(project_id).or_insert_with(|| Room {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            viewers: HashMap::new(),
+        });
+        let viewer = Viewer::from(user);
+        let (_, streams) = room.viewers.entry(user.id).or_insert_with(|| (viewer.clone(), 0));
+        *streams += 1;
+        if *streams == 1 {
+            info!(project.id = %project_id, user.id = %user.id, "Viewer joined project");
+            // An error only means nobody else is listening
+            let _ = room.sender.send(PresenceEvent::Join { viewer });
+        }
+        // Subscribed after the join goes out, since the snapshot already includes it
+        let receiver = room.sender.subscribe();
+        let visit = Visit {
+            presence: self.clone(),
+            project_id,
+            user_id: user.id,
+        };
+        (PresenceEvent::Snapshot { viewers: room.viewers() }, receiver, visit)
+    }
+
+    fn leave(&self, project_id: Uuid, user_id: Uuid) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let Some(room) = rooms.get_mut(&project_id) else {
+            return;
+        };
+        let Some((_, streams)) = room.viewers.get_mut(&user_id) else {
+            return;
+        };
+        *streams -= 1;
+        if *streams == 0 {
+            if let Some((viewer, _)) = room.viewers.remove(&user_id) {
+                info!(project.id = %project_id, user.id = %user_id, "Viewer left project");
+                let _ = room.sender.send(PresenceEvent::Leave { viewer });
+            }
+        }
+        if room.viewers.is_empty() {
+            rooms.remove(&project_id);
+        }
+    }
+
+    /// A stream that keeps `user` present on the project while it's open: a snapshot of the
+    /// viewers, then each join and leave.
+    pub fn sse(&self, project_id: Uuid, user: &User) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let (snapshot, receiver, visit) = self.join(project_id, user);
+        // The visit lives in the stream's state, so closing the stream is leaving
+        let updates = stream::unfold((receiver, visit), |(mut receiver, visit)| async move {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    debug!(missed, "Presence subscriber fell behind, sending a snapshot");
+                    PresenceEvent::Snapshot { viewers: visit.presence.viewers(visit.project_id) }
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            Some((Ok(event.to_sse()), (receiver, visit)))
+        });
+        let events = stream::once(async move { Ok(snapshot.to_sse()) }).chain(updates);
+        let shutdown = self.shutdown.clone().cancelled_owned();
+        Sse::new(events.take_until(shutdown)).keep_alive(KeepAlive::default())
+    }
+
+    /// Ends every open stream, so graceful shutdown isn't held up by idle viewers.
+    pub fn close(&self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// One open presence stream; dropping it leaves.
+struct Visit {
+    presence: Presence,
+    project_id: Uuid,
+    user_id: Uuid,
+}
+
+impl Drop for Visit {
+    fn drop(&mut self) {
+        self.presence.leave(self.project_id, self.user_id);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::models::{Project, ProjectMember, ProjectRole, Todo, User};
+use crate::notification_worker::{MemberNotice, NotificationJob, NotificationQueue};
+use crate::repository::{ListFilter, ListWindow, MembershipChange, RepositoryError, SqliteTodoRepository, TodoList, TodoRepository};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProjectError {
+    /// Also what non-members get, so they can't tell which projects exist.
+    #[error("Project not found")]
+    NotFound,
+
+    #[error("Requires the {} role in this project", .0.as_str())]
+    Forbidden(ProjectRole),
+
+    #[error("Project names are 1 to 200 characters")]
+    InvalidName,
+
+    #[error("No account with that email")]
+    UnknownUser,
+
+    #[error("Not a member of this project")]
+    NotMember,
+
+    #[error("A project must keep at least one owner")]
+    LastOwner,
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+/// What happened to a project's todo, for the notice its members get.
+#[derive(Debug, Clone, Copy)]
+pub enum TodoChange {
+    Created,
+    Updated,
+    Completed,
+    Deleted,
+}
+
+impl TodoChange {
+    fn verb(self) -> &'static str {
+        match self {
+            Self::Created => "added",
+            Self::Updated => "changed",
+            Self::Completed => "completed",
+            Self::Deleted => "deleted",
+        }
+    }
+}
+
+/// Shared lists: projects whose todos only their members see, and only owners and editors
+/// change. Members are told about changes made by the others.
+pub struct ProjectService {
+    repository: Arc<SqliteTodoRepository>,
+    notifications: NotificationQueue,
+}
+
+impl ProjectService {
+    pub fn new(repository: Arc<SqliteTodoRepository>, notifications: NotificationQueue) -> Self {
+        Self { repository, notifications }
+    }
+
+    /// A new project, owned by `user`.
+    pub async fn create(&self, user: &User, name: &str) -> Result<Project, ProjectError> {
+        let name = name.trim();
+        if !(1..=200).contains(&name.chars().count()) {
+            return Err(ProjectError::InvalidName);
+        }
+        let project = Project {
+            id: Uuid::new_v4(),
+            name: name.to_owned(),
+            role: ProjectRole::Owner,
+            created_at: self.repository.clock().now(),
+        };
+        self.repository.create_project(&project, user.id).await?;
+        info!(project.id = %project.id, "Project created");
+        Ok(project)
+    }
+
+    pub async fn list(&self, user: &User) -> Result<Vec<Project>, ProjectError> {
+        Ok(self.repository.list_projects(user.id).await?)
+    }
+
+    /// The project, if `user` is a member with at least the `needs` role.
+    pub async fn authorize(&self, user: &User, id: Uuid, needs: ProjectRole) -> Result<Project, ProjectError> {
+        let project = self
+            .repository
+            .get_project(id, user.id)
+            .await?
+            .ok_or(ProjectError::NotFound)?;
+        if project.role > needs {
+            return Err(ProjectError::Forbidden(needs));
+        }
+        Ok(project)
+    }
+
+    pub async fn members(&self, user: &User, id: Uuid) -> Result<Vec<ProjectMember>, ProjectError> {
+        self.authorize(user, id, ProjectRole::Viewer).await?;
+        Ok(self.repository.project_members(id).await?)
+    }
+
+    /// Adds the account with `email`, or changes its role. Only owners manage members.
+    pub async fn add_member(
+        &self,
+        user: &User,
+        id: Uuid,
+        email: &str,
+        role: ProjectRole,
+    ) -> Result<ProjectMember, ProjectError> {
+        let project = self.authorize(user, id, ProjectRole::Owner).await?;
+        let (member, _) = self
+            .repository
+            .find_user_by_email(email.trim())
+            .await?
+            .ok_or(ProjectError::UnknownUser)?;
+        if self.repository.set_project_member(id, member.id, role).await? == MembershipChange::LastOwner {
+            return Err(ProjectError::LastOwner);
+        }
+        info!(project.id = %id, member.id = %member.id, role = role.as_str(), "Project member set");
+
+        if member.id != user.id {
+            self.notify(MemberNotice {
+                recipients: vec![member.email.clone()],
+                subject: format!("You were added to {}", project.name),
+                text: format!(
+                    "{} added you to the project \"{}\" as {}.\n",
+                    user.email,
+                    project.name,
+                    role.as_str()
+                ),
+            })
+            .await;
+        }
+        self.repository
+            .project_members(id)
+            .await?
+            .into_iter()
+            .find(|m| m.user_id == member.id)
+            .ok_or(ProjectError::NotMember)
+    }
+
+    /// Owners may remove anyone; other members only themselves.
+    pub async fn remove_member(&self, user: &User, id: Uuid, member_id: Uuid) -> Result<(), ProjectError> {
+        let needs = if member_id == user.id { ProjectRole::Viewer } else { ProjectRole::Owner };
+        self.authorize(user, id, needs).await?;
+        match self.repository.remove_project_member(id, member_id).await? {
+            MembershipChange::Applied => {
+                info!(project.id = %id, member.id = %member_id, "Project member removed");
+                Ok(())
+            }
+            MembershipChange::NotMember => Err(ProjectError::NotMember),
+            MembershipChange::LastOwner => Err(ProjectError::LastOwner),
+        }
+    }
+
+    /// The project's todos, newest first like `GET /todos`, or the `window` of them.
+    pub async fn todos(&self, user: &User, id: Uuid, window: Option<ListWindow>) -> Result<TodoList, ProjectError> {
+        self.authorize(user, id, ProjectRole::Viewer).await?;
+        let filter = ListFilter {
+            project: Some(id),
+            ..ListFilter::default()
+        };
+        Ok(self.repository.list(&filter, window).await?)
+    }
+
+    /// Whether `user` may see the todo or, with `edit`, change it. Todos outside projects
+    /// are open to everyone.
+    pub async fn check_todo(&self, user: &User, todo: &Todo, edit: bool) -> Result<(), ProjectError> {
+        let Some(project_id) = todo.project_id else {
+            return Ok(());
+        };
+        let needs = if edit { ProjectRole::Editor } else { ProjectRole::Viewer };
+        self.authorize(user, project_id, needs).await.map(|_| ())
+    }
+
+    /// Ids of the projects whose todos `user` may see.
+    pub async fn visible(&self, user: &User) -> Result<HashSet<Uuid>, ProjectError> {
+        Ok(self.list(user).await?.into_iter().map(|p| p.id).collect())
+    }
+
+    /// Ids of the projects whose todos `user` may change.
+    pub async fn editable(&self, user: &User) -> Result<HashSet<Uuid>, ProjectError> {
+        Ok(self
+            .list(user)
+            .await?
+            .into_iter()
+            .filter(|p| p.role.can_edit())
+            .map(|p| p.id)
+            .collect())
+    }
+
+    /// The names of the given projects; those that no longer exist are left out.
+    pub async fn names(&self, ids: &HashSet<Uuid>) -> Result<HashMap<Uuid, String>, RepositoryError> {
+        self.repository.project_names(ids).await
+    }
+
+    /// Tells the members of the todo's project, other than `actor`, what happened to it.
+    /// Failures are logged; the change itself has already been made.
+    pub async fn todo_changed(&self, todo: &Todo, change: TodoChange, actor: Option<&User>) {
+        let Some(project_id) = todo.project_id else {
+            return;
+        };
+        let loaded = async {
+            let name = self.repository.project_name(project_id).await?;
+            let members = self.repository.project_members(project_id).await?;
+            Ok::<_, RepositoryError>((name, members))
+        };
+        let (name, members) = match loaded.await {
+            Ok((Some(name), members)) => (name, members),
+            Ok((None, _)) => return,
+            Err(e) => {
+                warn!(error = %e, project.id = %project_id, "Failed to load project members to notify");
+                return;
+            }
+        };
+        let recipients: Vec<String> = members
+            .into_iter()
+            .filter(|m| actor.is_none_or(|actor| actor.id != m.user_id))
+            .map(|m| m.email)
+            .collect();
+        if recipients.is_empty() {
+            return;
+        }
+
+        let by = actor.map(|a| format!(" by {}", a.email)).unwrap_or_default();
+        self.notify(MemberNotice {
+            recipients,
+            subject: format!("[{name}] {} {}", todo.title, change.verb()),
+            text: format!(
+                "\"{}\" was {} in the project \"{name}\"{by}.\n\nID: {}\n",
+                todo.title,
+                change.verb(),
+                todo.id
+            ),
+        })
+        .await;
+    }
+
+    async fn notify(&self, notice: MemberNotice) {
+        if let Err(e) = self.notifications.enqueue(NotificationJob::Member(notice)).await {
+            warn!(error = %e, "Failed to queue project notification");
+        }
+    }
+}
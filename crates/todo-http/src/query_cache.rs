@@ -0,0 +1,235 @@
+use crate::events::{Change, ChangeListener, TodoEvents};
+use crate::models::{CompactTodo, Todo, TodoStats};
+use crate::repository::TodoListSummary;
+use moka::future::Cache;
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tracing::debug;
+use uuid::Uuid;
+
+/// What a cached result depends on besides the todos themselves. Only lists have `params`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ListKey {
+    tenant: Option<String>,
+    /// The query string, which holds the filters.
+    params: BTreeMap<String, String>,
+    /// Projects the caller may see; `None` when nobody is signed in.
+    visible: Option<Vec<Uuid>>,
+}
+
+impl ListKey {
+    pub fn new(
+        tenant: Option<String>,
+        params: &HashMap<String, String>,
+        visible: Option<&HashSet<Uuid>>,
+    ) -> Self {
+        let visible = visible.map(|visible| {
+            let mut visible: Vec<Uuid> = visible.iter().copied().collect();
+            visible.sort();
+            visible
+        });
+        Self {
+            tenant,
+            params: params.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            visible,
+        }
+    }
+}
+
+/// The lists, summary and stats polled by dashboards, kept for a short TTL so repeated polls
+/// don't each query the database. A write published on the event bus drops its database's
+/// entries before the next lookup, so a client always sees its own writes. Writes the bus
+/// doesn't carry (other replicas, the expiry sweep) show up once the TTL runs out.
+pub struct QueryCache {
+    /// `None` when caching is off and every lookup loads afresh.
+    ttl: Option<Duration>,
+    lists: Cache<ListKey, Arc<Vec<Todo>>>,
+    compact: Cache<ListKey, Arc<Vec<CompactTodo>>>,
+    summaries: Cache<ListKey, TodoListSummary>,
+    stats: Cache<ListKey, TodoStats>,
+    changes: Mutex<ChangeListener>,
+    /// Bumped on every invalidation, so a load that raced with a write isn't kept.
+    generation: AtomicU64,
+    hits: Counter<u64>,
+    misses: Counter<u64>,
+}
+
+impl QueryCache {
+    /// Keeps up to `max_entries` results of each kind for `ttl`; a `ttl` of `None` disables
+    /// the cache.
+    pub fn new(events: &TodoEvents, ttl: Option<Duration>, max_entries: u64) -> Self {
+        // Entries are dropped by tenant, which takes a closure over their keys
+        let lists = build(ttl, max_entries);
+        let compact = build(ttl, max_entries);
+        let summaries = build(ttl, max_entries);
+        let stats = build(ttl, max_entries);
+
+        let meter = global::meter("todo-api");
+        Self {
+            ttl,
+            lists,
+            compact,
+            summaries,
+            stats,
+            changes: Mutex::new(events.listen()),
+            generation: AtomicU64::new(0),
+            hits: meter
+                .u64_counter("query_cache.hits")
+                .with_description("List and stats queries answered from the cache")
+                .init(),
+            misses: meter
+                .u64_counter("query_cache.misses")
+                .with_description("List and stats queries that went to the database")
+                .init(),
+        }
+    }
+
+    pub async fn list<E>(
+        &self,
+        key: ListKey,
+        load: impl Future<Output = Result<Vec<Todo>, E>>,
+    ) -> Result<Arc<Vec<Todo>>, Arc<E>>
+    where
+        E: Send + Sync + 'static,
+    {
+        self.lookup(&self.lists, "list", key, async { load.await.map(Arc::new) }).await
+    }
+
+    pub async fn compact<E>(
+        &self,
+        tenant: Option<String>,
+        visible: Option<&HashSet<Uuid>>,
+        load: impl Future<Output = Result<Vec<CompactTodo>, E>>,
+    ) -> Result<Arc<Vec<CompactTodo>>, Arc<E>>
+    where
+        E: Send + Sync + 'static,
+    {
+        let key = ListKey::new(tenant, &HashMap::new(), visible);
+        self.lookup(&self.compact, "compact", key, async { load.await.map(Arc::new) }).await
+    }
+
+    pub async fn summary<E>(
+        &self,
+        tenant: Option<String>,
+        visible: Option<&HashSet<Uuid>>,
+        load: impl Future<Output = Result<TodoListSummary, E>>,
+    ) -> Result<TodoListSummary, Arc<E>>
+    where
+        E: Send + Sync + 'static,
+    {
+        let key = ListKey::new(tenant, &HashMap::new(), visible);
+        self.lookup(&self.summaries, "summary", key, load).await
+    }
+
+    pub async fn stats<E>(
+        &self,
+        tenant: Option<String>,
+        visible: Option<&HashSet<Uuid>>,
+        load: impl Future<Output = Result<TodoStats, E>>,
+    ) -> Result<TodoStats, Arc<E>>
+    where
+        E: Send + Sync + 'static,
+    {
+        let key = ListKey::new(tenant, &HashMap::new(), visible);
+        self.lookup(&self.stats, "stats", key, load).await
+    }
+
+    async fn lookup<K, V, E>(
+        &self,
+        cache: &Cache<K, V>,
+        query: &'static str,
+        key: K,
+        load: impl Future<Output = Result<V, E>>,
+    ) -> Result<V, Arc<E>>
+    where
+        K: Hash + Eq + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+        E: Send + Sync + 'static,
+    {
+        if self.ttl.is_none() {
+            return load.await.map_err(Arc::new);
+        }
+        self.apply_changes().await;
+
+        let generation = self.generation.load(Ordering::Acquire);
+        // Concurrent misses for the same key share one load
+        let entry = cache.entry_by_ref(&key).or_try_insert_with(load).await?;
+        let attributes = [KeyValue::new("query", query)];
+        if !entry.is_fresh() {
+            self.hits.add(1, &attributes);
+            return Ok(entry.into_value());
+        }
+        self.misses.add(1, &attributes);
+        if self.generation.load(Ordering::Acquire) != generation {
+            // A write landed while this was loading, so the result may predate it
+            cache.invalidate(&key).await;
+        }
+        Ok(entry.into_value())
+    }
+
+    /// Forgets the results of every database written since the last lookup.
+    async fn apply_changes(&self) {
+        let changes: Vec<Change> = {
+            let mut listener = self.changes.lock().unwrap();
+            std::iter::from_fn(|| listener.try_next()).collect()
+        };
+        if changes.is_empty() {
+            return;
+        }
+        self.generation.fetch_add(1, Ordering::AcqRel);
+
+        if changes.contains(&Change::Unknown) {
+            debug!("Query cache cleared");
+            self.lists.invalidate_all();
+            self.compact.invalidate_all();
+            self.summaries.invalidate_all();
+            self.stats.invalidate_all();
+            return;
+        }
+        let tenants: HashSet<Option<String>> = changes
+            .into_iter()
+            .filter_map(|change| match change {
+                Change::Database(tenant) => Some(tenant),
+                Change::Unknown => None,
+            })
+            .collect();
+        debug!(databases = tenants.len(), "Query cache entries invalidated");
+        for tenant in tenants {
+            invalidate_tenant(&self.lists, &tenant);
+            invalidate_tenant(&self.compact, &tenant);
+            invalidate_tenant(&self.summaries, &tenant);
+            invalidate_tenant(&self.stats, &tenant);
+        }
+    }
+}
+
+/// Drops `cache`'s entries for the database of `tenant`.
+fn invalidate_tenant<V>(cache: &Cache<ListKey, V>, tenant: &Option<String>)
+where
+    V: Clone + Send + Sync + 'static,
+{
+    let tenant = tenant.clone();
+    // Only fails if the cache wasn't built to support closures, which it is
+    let _ = cache.invalidate_entries_if(move |key, _| key.tenant == tenant);
+}
+
+fn build<K, V>(ttl: Option<Duration>, max_entries: u64) -> Cache<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    let mut builder = Cache::builder().max_capacity(max_entries);
+    if let Some(ttl) = ttl {
+        builder = builder.time_to_live(ttl);
+    }
+    builder.support_invalidation_closures().build()
+}
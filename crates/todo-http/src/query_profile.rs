@@ -0,0 +1,145 @@
+use crate::access_log;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use todo_storage::repository::QueryObserver;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Samples kept regardless of the window, so a burst of queries can't grow memory unbounded.
+const MAX_SAMPLES: usize = 10_000;
+
+/// One timed repository call.
+#[derive(Debug, Clone, Serialize)]
+pub struct QuerySample {
+    /// The repository method, e.g. `list_open`.
+    pub operation: &'static str,
+    pub duration_ms: f64,
+    pub at: DateTime<Utc>,
+    /// The trace the call belonged to, for finding it in Jaeger.
+    pub trace_id: Option<String>,
+    #[serde(skip)]
+    recorded: Instant,
+}
+
+/// Totals for one repository method over the window.
+#[derive(Debug, Serialize)]
+pub struct OperationProfile {
+    pub operation: &'static str,
+    pub count: usize,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+}
+
+/// What `GET /admin/slow-queries` returns.
+#[derive(Debug, Serialize)]
+pub struct SlowQueryReport {
+    pub window_secs: u64,
+    pub sampled: usize,
+    /// The slowest individual calls, slowest first.
+    pub slowest: Vec<QuerySample>,
+    /// Every method called in the window, by its slowest call.
+    pub by_operation: Vec<OperationProfile>,
+}
+
+/// Keeps the timings of recent repository calls, so storage regressions show up without an
+/// external APM. Samples older than `window` are dropped as new ones arrive.
+pub struct QueryProfiler {
+    window: Duration,
+    samples: Mutex<VecDeque<QuerySample>>,
+}
+
+impl QueryProfiler {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, operation: &'static str, duration: Duration, trace_id: Option<String>) {
+        let sample = QuerySample {
+            operation,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            at: Utc::now(),
+            trace_id,
+            recorded: Instant::now(),
+        };
+        let mut samples = self.samples.lock().unwrap();
+        self.prune(&mut samples);
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    /// The `limit` slowest calls in the window and a per-method summary.
+    pub fn report(&self, limit: usize) -> SlowQueryReport {
+        let samples: Vec<QuerySample> = {
+            let mut samples = self.samples.lock().unwrap();
+            self.prune(&mut samples);
+            samples.iter().cloned().collect()
+        };
+
+        let mut by_operation: HashMap<&'static str, (usize, f64, f64)> = HashMap::new();
+        for sample in &samples {
+            let (count, total, max) = by_operation.entry(sample.operation).or_default();
+            *count += 1;
+            *total += sample.duration_ms;
+            *max = max.max(sample.duration_ms);
+        }
+        let mut by_operation: Vec<OperationProfile> = by_operation
+            .into_iter()
+            .map(|(operation, (count, total, max))| OperationProfile {
+                operation,
+                count,
+                mean_ms: total / count as f64,
+                max_ms: max,
+            })
+            .collect();
+        by_operation.sort_by(|a, b| b.max_ms.total_cmp(&a.max_ms));
+
+        let sampled = samples.len();
+        let mut slowest = samples;
+        slowest.sort_by(|a, b| b.duration_ms.total_cmp(&a.duration_ms));
+        slowest.truncate(limit);
+
+        SlowQueryReport {
+            window_secs: self.window.as_secs(),
+            sampled,
+            slowest,
+            by_operation,
+        }
+    }
+
+    fn prune(&self, samples: &mut VecDeque<QuerySample>) {
+        while samples.front().is_some_and(|sample| sample.recorded.elapsed() > self.window) {
+            samples.pop_front();
+        }
+    }
+}
+
+/// Puts each repository call's duration on its span as `db.duration_ms` and, when query
+/// profiling is on, into the profiler's window.
+pub struct QueryTiming {
+    profiler: Option<Arc<QueryProfiler>>,
+}
+
+impl QueryTiming {
+    pub fn new(profiler: Option<Arc<QueryProfiler>>) -> Self {
+        Self { profiler }
+    }
+}
+
+impl QueryObserver for QueryTiming {
+    fn observe(&self, operation: &'static str, elapsed: Duration) {
+        Span::current().set_attribute("db.duration_ms", elapsed.as_secs_f64() * 1000.0);
+        if let Some(profiler) = &self.profiler {
+            profiler.record(operation, elapsed, access_log::current_trace_id());
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use crate::auth::{self, AuthService, Principal};
+use crate::config::RateLimitConfig;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+
+const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+
+/// After a Redis error, buckets stay local for this long before Redis is tried again,
+/// so an outage doesn't add a connection timeout to every request.
+const REDIS_COOLDOWN: Duration = Duration::from_secs(5);
+/// Local buckets are pruned once there are this many, dropping the ones that refilled.
+const MAX_LOCAL_BUCKETS: usize = 10_000;
+
+/// Refills the bucket at `KEYS[1]` from the time elapsed since the last call and takes one
+/// token if there is one. Uses the Redis clock so replicas with skewed clocks agree.
+/// Returns `{allowed, remaining, retry_after_ms}`.
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local per_ms = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(bucket[1]) or capacity
+local updated_at = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - updated_at) * per_ms)
+
+local allowed = 0
+local retry_after = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+else
+    retry_after = math.ceil((1 - tokens) / per_ms)
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / per_ms))
+return {allowed, math.floor(tokens), retry_after}
+"#;
+
+#[derive(Debug, Clone, Copy)]
+struct Decision {
+    allowed: bool,
+    remaining: u64,
+    retry_after: Duration,
+}
+
+struct LocalBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+struct RedisBackend {
+    client: redis::Client,
+    connection: OnceCell<ConnectionManager>,
+    script: redis::Script,
+    unavailable_until: Mutex<Option<Instant>>,
+}
+
+/// Token-bucket rate limiter keyed by the account a credential belongs to, or by client IP
+/// for anonymous requests. Buckets live in Redis when it is configured and reachable, and in
+/// process otherwise.
+pub struct RateLimiter {
+    /// Resolves credentials to their account; `None` when `JWT_SECRET` is unset.
+    auth: Option<Arc<AuthService>>,
+    capacity: u32,
+    per_ms: f64,
+    redis: Option<RedisBackend>,
+    local: Mutex<HashMap<String, LocalBucket>>,
+    decisions: Counter<u64>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig, auth: Option<Arc<AuthService>>) -> Result<Self, redis::RedisError> {
+        let redis = config
+            .redis_url
+            .as_ref()
+            .map(|url| {
+                Ok::<_, redis::RedisError>(RedisBackend {
+                    client: redis::Client::open(url.expose())?,
+                    connection: OnceCell::new(),
+                    script: redis::Script::new(TOKEN_BUCKET_SCRIPT),
+                    unavailable_until: Mutex::new(None),
+                })
+            })
+            .transpose()?;
+
+        info!(
+            per_minute = config.per_minute,
+            burst = config.burst,
+            shared = redis.is_some(),
+            "Rate limiting enabled"
+        );
+        Ok(Self {
+            auth,
+            capacity: config.burst,
+            per_ms: f64::from(config.per_minute) / 60_000.0,
+            redis,
+            local: Mutex::new(HashMap::new()),
+            decisions: global::meter("todo-api")
+                .u64_counter("rate_limit.decisions")
+                .with_description("Requests checked against the rate limiter")
+                .init(),
+        })
+    }
+
+    /// Takes a token from the buckets a request draws from: its account's when it carries a
+    /// valid credential, so every token of one account shares a bucket, and its address's
+    /// otherwise. Nothing else the client sends picks the bucket, so it can't get a fresh one
+    /// by changing a header.
+    ///
+    /// A credential the auth cache doesn't hold is paid for from the address's bucket before it
+    /// is looked up, so made-up tokens, which never get cached, can't each buy a database query
+    /// past the limit. One that then resolves is charged to its account as well.
+    async fn decide(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> Decision {
+        let (Some(auth), Some(bearer)) = (&self.auth, auth::bearer_token(headers)) else {
+            return self.check(&peer_key(peer)).await;
+        };
+        if let Some(principal) = auth.cached_principal(bearer).await {
+            return self.check(&user_key(&principal)).await;
+        }
+        let decision = self.check(&peer_key(peer)).await;
+        if !decision.allowed {
+            return decision;
+        }
+        match auth.principal(bearer).await {
+            Ok(principal) => self.check(&user_key(&principal)).await,
+            Err(_) => decision,
+        }
+    }
+
+    async fn check(&self, key: &str) -> Decision {
+        let (decision, backend) = match self.check_redis(key).await {
+            Some(decision) => (decision, "redis"),
+            None => (self.check_local(key), "local"),
+        };
+        let outcome = if decision.allowed { "allowed" } else { "limited" };
+        self.decisions
+            .add(1, &[KeyValue::new("backend", backend), KeyValue::new("outcome", outcome)]);
+        decision
+    }
+
+    /// `None` when Redis isn't configured, is cooling down after an error, or just failed.
+    async fn check_redis(&self, key: &str) -> Option<Decision> {
+        let redis = self.redis.as_ref()?;
+        {
+            let mut unavailable_until = redis.unavailable_until.lock().unwrap();
+            match *unavailable_until {
+                Some(until) if Instant::now() < until => return None,
+                Some(_) => *unavailable_until = None,
+                None => {}
+            }
+        }
+
+        let result = async {
+            let connection = redis
+                .connection
+                .get_or_try_init(|| {
+                    let config = ConnectionManagerConfig::new()
+                        .set_connection_timeout(Duration::from_millis(250))
+                        .set_response_timeout(Duration::from_millis(250))
+                        .set_number_of_retries(1);
+                    ConnectionManager::new_with_config(redis.client.clone(), config)
+                })
+                .await?;
+            let (allowed, remaining, retry_after_ms): (i64, i64, i64) = redis
+                .script
+                .key(format!("ratelimit:{key}"))
+                .arg(self.capacity)
+                .arg(self.per_ms)
+                .invoke_async(&mut connection.clone())
+                .await?;
+            Ok::<_, redis::RedisError>(Decision {
+                allowed: allowed == 1,
+                remaining: remaining.max(0) as u64,
+                retry_after: Duration::from_millis(retry_after_ms.max(0) as u64),
+            })
+        }
+        .await;
+
+        match result {
+            Ok(decision) => Some(decision),
+            Err(e) => {
+                warn!(error = %e, "Redis rate limiter unavailable, limiting locally");
+                *redis.unavailable_until.lock().unwrap() = Some(Instant::now() + REDIS_COOLDOWN);
+                None
+            }
+        }
+    }
+
+    fn check_local(&self, key: &str) -> Decision {
+        let capacity = f64::from(self.capacity);
+        let now = Instant::now();
+        let mut buckets = self.local.lock().unwrap();
+
+        if buckets.len() >= MAX_LOCAL_BUCKETS {
+            buckets.retain(|_, bucket| {
+                let elapsed_ms = now.duration_since(bucket.updated_at).as_secs_f64() * 1000.0;
+                bucket.tokens + elapsed_ms * self.per_ms < capacity
+            });
+        }
+
+        let bucket = buckets.entry(key.to_owned()).or_insert(LocalBucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+        let elapsed_ms = now.duration_since(bucket.updated_at).as_secs_f64() * 1000.0;
+        bucket.tokens = (bucket.tokens + elapsed_ms * self.per_ms).min(capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Decision {
+                allowed: true,
+                remaining: bucket.tokens as u64,
+                retry_after: Duration::ZERO,
+            }
+        } else {
+            Decision {
+                allowed: false,
+                remaining: 0,
+                retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_ms / 1000.0),
+            }
+        }
+    }
+}
+
+/// The bucket of a request with a valid credential.
+fn user_key(principal: &Principal) -> String {
+    format!("user:{}", principal.user.id)
+}
+
+/// The bucket of a request without a credential.
+pub(crate) fn peer_key(peer: Option<SocketAddr>) -> String {
+    match peer {
+        Some(addr) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_owned(),
+    }
+}
+
+/// Rejects requests over the client's limit with `429` and a `Retry-After`. Health checks
+/// and metrics scrapes are never limited.
+pub async fn enforce_rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if matches!(req.uri().path(), "/health" | "/metrics") {
+        return next.run(req).await;
+    }
+
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let decision = limiter.decide(req.headers(), peer).await;
+    let limit = HeaderValue::from(limiter.capacity);
+
+    if !decision.allowed {
+        let retry_after = decision.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        warn!(retry_after_secs = retry_after, "Rate limit exceeded");
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [
+                (axum::http::header::RETRY_AFTER, HeaderValue::from(retry_after)),
+                (X_RATELIMIT_LIMIT, limit),
+                (X_RATELIMIT_REMAINING, HeaderValue::from(0)),
+            ],
+            "Rate limit exceeded",
+        )
+            .into_response();
+    }
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.insert(X_RATELIMIT_LIMIT, limit);
+    headers.insert(X_RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    response
+}
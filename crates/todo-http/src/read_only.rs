@@ -0,0 +1,32 @@
+use axum::{
+    extract::Request,
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::debug;
+
+/// `POST` routes that only compute a response and write nothing.
+const READ_ONLY_POSTS: &[&str] = &["/todos/parse"];
+
+/// In read-only mode, refuses every request that could write with `503`, so a load balancer
+/// or client retries it against the primary. Reads go through as usual.
+pub async fn refuse_writes(req: Request, next: Next) -> Response {
+    let method = req.method();
+    let reads = method == Method::GET
+        || method == Method::HEAD
+        || method == Method::OPTIONS
+        // WebDAV's reads, on `/dav`
+        || matches!(method.as_str(), "PROPFIND" | "REPORT")
+        || (method == Method::POST && READ_ONLY_POSTS.contains(&req.uri().path()));
+    if reads {
+        return next.run(req).await;
+    }
+
+    debug!(method = %method, path = req.uri().path(), "Write refused in read-only mode");
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        "This server is read-only; send writes to the primary",
+    )
+        .into_response()
+}
@@ -0,0 +1,257 @@
+use crate::models::{CompactTodo, HistoryEntry, Todo, TodoStats};
+use crate::repository::{
+    ListFilter, ListWindow, RepositoryError, SqliteTodoRepository, TodoCursor, TodoList, TodoListSummary, TodoPage,
+    TodoRepository,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use std::{
+    collections::HashSet,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use tracing::{info, warn, Instrument};
+use uuid::Uuid;
+
+/// Retries saved up while the database is healthy, and so the most a burst of failures
+/// can spend at once.
+const MAX_BUDGET: f64 = 10.0;
+
+/// Longest wait before a retry, however many attempts `DB_RETRY_ATTEMPTS` allows.
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// How transient repository failures are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Tries per call, the first included; `1` disables retries.
+    pub attempts: u32,
+    /// Delay before the first retry, doubling for each one after.
+    pub base_delay: Duration,
+    /// Retries each call earns, so retries can't exceed this fraction of calls for long.
+    pub budget_ratio: f64,
+}
+
+/// Shared allowance of retries across all calls, so an outage doesn't multiply the load
+/// on a struggling database.
+struct RetryBudget {
+    balance: Mutex<f64>,
+    ratio: f64,
+}
+
+impl RetryBudget {
+    fn deposit(&self) {
+        let mut balance = self.balance.lock().unwrap();
+        *balance = (*balance + self.ratio).min(MAX_BUDGET);
+    }
+
+    fn withdraw(&self) -> bool {
+        let mut balance = self.balance.lock().unwrap();
+        if *balance >= 1.0 {
+            *balance -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Decorates a repository with retries of transient errors (lost connections, an exhausted
+/// pool, a busy database), so a brief hiccup doesn't surface as a wave of `500`s.
+pub struct RetryingRepository {
+    inner: Arc<dyn TodoRepository>,
+    policy: RetryPolicy,
+    budget: RetryBudget,
+    retries: Counter<u64>,
+}
+
+impl RetryingRepository {
+    pub fn new(inner: Arc<dyn TodoRepository>, policy: RetryPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            budget: RetryBudget {
+                balance: Mutex::new(MAX_BUDGET),
+                ratio: policy.budget_ratio,
+            },
+            retries: global::meter("todo-api")
+                .u64_counter("db.client.operation.retries")
+                .with_description("Repository operations retried after a transient error")
+                .init(),
+        }
+    }
+
+    /// Calls `call` until it succeeds or fails for good. Only for reads and writes that can
+    /// safely be repeated.
+    async fn retry<T, F, Fut>(&self, operation: &'static str, call: F) -> Result<T, RepositoryError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RepositoryError>>,
+    {
+        self.retry_if(operation, RepositoryError::is_transient, call).await
+    }
+
+    /// Like `retry`, for writes that would be applied twice, or report a different result, if
+    /// repeated after going through: only failures that left nothing written are retried.
+    async fn retry_write<T, F, Fut>(&self, operation: &'static str, call: F) -> Result<T, RepositoryError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RepositoryError>>,
+    {
+        self.retry_if(operation, RepositoryError::is_unattempted, call).await
+    }
+
+    async fn retry_if<T, F, Fut>(
+        &self,
+        operation: &'static str,
+        retryable: fn(&RepositoryError) -> bool,
+        mut call: F,
+    ) -> Result<T, RepositoryError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RepositoryError>>,
+    {
+        self.budget.deposit();
+        let mut attempt = 1;
+        loop {
+            let error = match call().await {
+                Err(e) if retryable(&e) => e,
+                result => return result,
+            };
+            if attempt >= self.policy.attempts {
+                return Err(error);
+            }
+            if !self.budget.withdraw() {
+                warn!(db.operation = operation, error = %error, "Retry budget exhausted, not retrying");
+                return Err(error);
+            }
+
+            let delay = self
+                .policy
+                .base_delay
+                .saturating_mul(2u32.saturating_pow(attempt - 1))
+                .min(MAX_DELAY);
+            warn!(
+                db.operation = operation,
+                attempt,
+                retry_in_ms = delay.as_millis() as u64,
+                error = %error,
+                "Transient database error, retrying"
+            );
+            self.retries.add(1, &[KeyValue::new("db.operation", operation)]);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl TodoRepository for RetryingRepository {
+    async fn create(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        self.retry_write("INSERT", || self.inner.create(todo.clone())).await
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Todo, RepositoryError> {
+        self.retry("SELECT", || self.inner.get(id)).await
+    }
+
+    async fn list(&self, filter: &ListFilter<'_>, window: Option<ListWindow>) -> Result<TodoList, RepositoryError> {
+        self.retry(if window.is_some() { "SELECT_WINDOW" } else { "SELECT_ALL" }, || self.inner.list(filter, window)).await
+    }
+
+    async fn list_page(
+        &self,
+        after: Option<TodoCursor>,
+        limit: usize,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<TodoPage, RepositoryError> {
+        self.retry("SELECT_PAGE", || self.inner.list_page(after, limit, projects)).await
+    }
+
+    async fn list_compact(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<CompactTodo>, RepositoryError> {
+        self.retry("SELECT_COMPACT", || self.inner.list_compact(projects)).await
+    }
+
+    async fn summary(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoListSummary, RepositoryError> {
+        self.retry("SELECT_SUMMARY", || self.inner.summary(projects)).await
+    }
+
+    async fn stats(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoStats, RepositoryError> {
+        self.retry("SELECT_STATS", || self.inner.stats(projects)).await
+    }
+
+    async fn update(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        self.retry_write("UPDATE", || self.inner.update(todo.clone())).await
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError> {
+        self.retry_write("DELETE", || self.inner.delete(id)).await
+    }
+
+    async fn snooze(&self, id: Uuid, until: DateTime<Utc>) -> Result<Todo, RepositoryError> {
+        self.retry_write("SNOOZE", || self.inner.snooze(id, until)).await
+    }
+
+    async fn history(&self, id: Uuid) -> Result<Vec<HistoryEntry>, RepositoryError> {
+        self.retry("SELECT_HISTORY", || self.inner.history(id)).await
+    }
+
+    async fn add_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<Todo, RepositoryError> {
+        self.retry_write("INSERT_DEPENDENCY", || self.inner.add_dependency(blocker, blocked)).await
+    }
+
+    async fn remove_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<(), RepositoryError> {
+        self.retry_write("DELETE_DEPENDENCY", || self.inner.remove_dependency(blocker, blocked)).await
+    }
+
+    async fn create_batch(&self, todos: Vec<Todo>) -> Result<Vec<Todo>, RepositoryError> {
+        self.retry_write("BATCH_INSERT", || self.inner.create_batch(todos.clone())).await
+    }
+
+    async fn import(&self, todos: Vec<Todo>) -> Result<usize, RepositoryError> {
+        self.retry_write("IMPORT", || self.inner.import(todos.clone())).await
+    }
+
+    async fn delete_completed(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<Uuid>, RepositoryError> {
+        self.retry_write("DELETE_COMPLETED", || self.inner.delete_completed(projects)).await
+    }
+
+    async fn database_version(&self) -> Result<String, RepositoryError> {
+        self.retry("SELECT_VERSION", || self.inner.database_version()).await
+    }
+}
+
+/// Pings the database every `interval`, logging when it stops answering and when it comes
+/// back. The pool replaces broken connections on its own; this makes the outage visible.
+pub fn spawn_health_check_job(
+    repository: Arc<SqliteTodoRepository>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    info!(interval_secs = interval.as_secs(), "Starting database health check");
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut healthy = true;
+
+        loop {
+            ticker.tick().await;
+
+            let span = tracing::debug_span!("db_health_check");
+            match repository.ping().instrument(span).await {
+                Ok(()) if !healthy => {
+                    info!("Database reachable again");
+                    healthy = true;
+                }
+                Ok(()) => {}
+                Err(e) => {
+                    if healthy {
+                        warn!(error = %e, "Database health check failed");
+                    }
+                    healthy = false;
+                }
+            }
+        }
+    })
+}
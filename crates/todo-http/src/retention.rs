@@ -0,0 +1,192 @@
+use crate::attachments::{AttachmentError, AttachmentService};
+use crate::config::RetentionConfig;
+use crate::leases::JobLeases;
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use chrono::{DateTime, Utc};
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use tracing::{error, info, Instrument};
+
+/// What one rule matches now.
+#[derive(Debug, Serialize)]
+pub struct RuleReport {
+    /// `completed_todos` or `deleted_attachments`.
+    pub rule: &'static str,
+    pub retention_days: u64,
+    /// Data older than this is deleted.
+    pub cutoff: DateTime<Utc>,
+    pub matched: u64,
+    /// Total size of the matched attachments; absent for todos.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+}
+
+/// What `GET /admin/retention` returns: what each configured rule would delete if it ran now.
+#[derive(Debug, Serialize)]
+pub struct RetentionReport {
+    pub evaluated_at: DateTime<Utc>,
+    /// Whether the job deletes what's matched, or only reports it.
+    pub enforced: bool,
+    pub rules: Vec<RuleReport>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RetentionError {
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+
+    #[error(transparent)]
+    Attachment(#[from] AttachmentError),
+
+    /// The rule's retention reaches back before the earliest date that can be represented.
+    #[error("{rule} retention of {days} days is out of range")]
+    OutOfRange { rule: &'static str, days: u64 },
+}
+
+/// Deletes data past its useful life: completed todos some time after completion, and the
+/// attachments of deleted todos some time after the deletion, which leaves a window to restore
+/// them from a backup. Until enforcement is turned on, the rules are only evaluated, so
+/// operators can check what they'd delete first.
+pub struct Retention {
+    repository: Arc<SqliteTodoRepository>,
+    attachments: Arc<AttachmentService>,
+    config: RetentionConfig,
+    deleted: Counter<u64>,
+}
+
+impl Retention {
+    pub fn new(
+        repository: Arc<SqliteTodoRepository>,
+        attachments: Arc<AttachmentService>,
+        config: RetentionConfig,
+    ) -> Self {
+        Self {
+            repository,
+            attachments,
+            config,
+            deleted: global::meter("todo-api")
+                .u64_counter("retention.deleted")
+                .with_description("Todos and attachments deleted by retention rules")
+                .init(),
+        }
+    }
+
+    /// Whether a rule decides when attachments of deleted todos go, rather than the expiry
+    /// sweep.
+    pub fn governs_attachments(&self) -> bool {
+        self.config.deleted_attachments.is_some()
+    }
+
+    /// What each rule matches at `now`, without deleting anything.
+    pub async fn report(&self, now: DateTime<Utc>) -> Result<RetentionReport, RetentionError> {
+        let mut rules = Vec::new();
+        if let Some((days, cutoff)) = cutoff("completed_todos", self.config.completed_todos, now)? {
+            rules.push(RuleReport {
+                rule: "completed_todos",
+                retention_days: days,
+                cutoff,
+                matched: self.repository.count_completed_before(cutoff).await?,
+                bytes: None,
+            });
+        }
+        if let Some((days, cutoff)) = cutoff("deleted_attachments", self.config.deleted_attachments, now)? {
+            let (matched, bytes) = self.repository.orphaned_attachment_usage(Some(cutoff)).await?;
+            rules.push(RuleReport {
+                rule: "deleted_attachments",
+                retention_days: days,
+                cutoff,
+                matched,
+                bytes: Some(bytes),
+            });
+        }
+        Ok(RetentionReport {
+            evaluated_at: now,
+            enforced: self.config.enforce,
+            rules,
+        })
+    }
+
+    /// Deletes what each rule matches at `now`. Completed todos go first, so their attachments
+    /// wait out the attachment rule like any other deleted todo's.
+    pub async fn enforce(&self, now: DateTime<Utc>) -> Result<(), RetentionError> {
+        // Both cutoffs are checked before either rule deletes anything
+        let completed_todos = cutoff("completed_todos", self.config.completed_todos, now)?;
+        let deleted_attachments = cutoff("deleted_attachments", self.config.deleted_attachments, now)?;
+        if let Some((_, cutoff)) = completed_todos {
+            let deleted = self.repository.delete_completed_before(cutoff).await?;
+            self.deleted.add(deleted, &[KeyValue::new("rule", "completed_todos")]);
+        }
+        if let Some((_, cutoff)) = deleted_attachments {
+            let removed = self.attachments.remove_orphans(Some(cutoff)).await?;
+            self.deleted.add(removed, &[KeyValue::new("rule", "deleted_attachments")]);
+        }
+        Ok(())
+    }
+}
+
+/// A rule's retention in days and the cutoff it gives at `now`, or `None` when the rule is off.
+fn cutoff(
+    rule: &'static str,
+    retention: Option<Duration>,
+    now: DateTime<Utc>,
+) -> Result<Option<(u64, DateTime<Utc>)>, RetentionError> {
+    let Some(retention) = retention else {
+        return Ok(None);
+    };
+    let days = retention.as_secs() / 86_400;
+    let cutoff = chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|retention| now.checked_sub_signed(retention))
+        .ok_or(RetentionError::OutOfRange { rule, days })?;
+    Ok(Some((days, cutoff)))
+}
+
+/// Spawns the job that evaluates the retention rules. When they're enforced it deletes what
+/// they match; otherwise it logs what it would have deleted. Only the replica holding the
+/// `retention` lease runs them.
+pub fn spawn_retention_job(
+    retention: Arc<Retention>,
+    leases: Arc<JobLeases>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    info!(
+        interval_secs = interval.as_secs(),
+        enforce = retention.config.enforce,
+        "Starting retention job"
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+            if !leases.acquire("retention", interval * 2).await {
+                continue;
+            }
+
+            let span = tracing::debug_span!("retention_run");
+            let now = retention.repository.clock().now();
+            if retention.config.enforce {
+                if let Err(e) = retention.enforce(now).instrument(span).await {
+                    error!(error = %e, "Retention run failed");
+                }
+                continue;
+            }
+            match retention.report(now).instrument(span).await {
+                Ok(report) => {
+                    for rule in report.rules.iter().filter(|rule| rule.matched > 0) {
+                        info!(
+                            rule = rule.rule,
+                            matched = rule.matched,
+                            cutoff = %rule.cutoff,
+                            "Retention rule would delete data (RETENTION_ENFORCE is off)"
+                        );
+                    }
+                }
+                Err(e) => error!(error = %e, "Retention report failed"),
+            }
+        }
+    })
+}
@@ -0,0 +1,106 @@
+use crate::access_log::current_trace_id;
+use axum::{
+    body::Body,
+    extract::{MatchedPath, State},
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::Span;
+
+/// Flags requests that take longer than a threshold to answer, with the trace to look at.
+pub struct SlowRequestWatchdog {
+    threshold: Duration,
+    /// Trace viewer URL with a `{trace_id}` placeholder, e.g. Jaeger's `/trace/{trace_id}`.
+    trace_url_template: Option<String>,
+    slow_requests: Counter<u64>,
+}
+
+impl SlowRequestWatchdog {
+    pub fn new(threshold: Duration, trace_url_template: Option<String>) -> Self {
+        let slow_requests = global::meter("todo-api")
+            .u64_counter("slow_requests")
+            .with_description("Requests answered more slowly than SLOW_REQUEST_THRESHOLD_MS")
+            .init();
+        Self {
+            threshold,
+            trace_url_template,
+            slow_requests,
+        }
+    }
+}
+
+/// One request being timed. Checked when dropped, so requests abandoned by the client
+/// after the threshold are flagged as well.
+struct Watch {
+    watchdog: Arc<SlowRequestWatchdog>,
+    span: Span,
+    method: String,
+    route: String,
+    trace_id: Option<String>,
+    started: Instant,
+    status: Option<u16>,
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed();
+        if elapsed < self.watchdog.threshold {
+            return;
+        }
+
+        let trace_url = self
+            .watchdog
+            .trace_url_template
+            .as_ref()
+            .zip(self.trace_id.as_ref())
+            .map(|(template, trace_id)| template.replace("{trace_id}", trace_id));
+        tracing::warn!(
+            parent: &self.span,
+            http.request.method = %self.method,
+            http.route = %self.route,
+            http.response.status_code = self.status,
+            duration_ms = elapsed.as_millis() as u64,
+            threshold_ms = self.watchdog.threshold.as_millis() as u64,
+            trace_id = self.trace_id.as_deref(),
+            trace.url = trace_url.as_deref(),
+            "Slow request"
+        );
+        self.watchdog.slow_requests.add(
+            1,
+            &[
+                KeyValue::new("http.route", self.route.clone()),
+                KeyValue::new("http.request.method", self.method.clone()),
+            ],
+        );
+    }
+}
+
+/// Times each request up to its response head, so long-lived streams such as
+/// `/todos/events` aren't flagged. Must sit inside the `TraceLayer` to see the trace id.
+pub async fn watch_slow_requests(
+    State(watchdog): State<Arc<SlowRequestWatchdog>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let mut watch = Watch {
+        watchdog,
+        span: Span::current(),
+        method: req.method().to_string(),
+        route: req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| "unmatched".to_owned(), |path| path.as_str().to_owned()),
+        trace_id: current_trace_id(),
+        started: Instant::now(),
+        status: None,
+    };
+    let response = next.run(req).await;
+    watch.status = Some(response.status().as_u16());
+    response
+}
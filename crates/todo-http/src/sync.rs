@@ -0,0 +1,146 @@
+use crate::events::{TodoEvent, TodoEvents};
+use crate::mentions::MentionService;
+use crate::models::{SyncChanges, SyncMutation, SyncMutationResult, SyncPushResponse, User};
+use crate::repository::{RepositoryError, SqliteTodoRepository, SyncOutcome, TodoRepository};
+use crate::validation::{TodoValidator, ValidationError};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use uuid::Uuid;
+
+/// Changes per page when the client doesn't ask for a number, and the most it may ask for.
+const DEFAULT_LIMIT: usize = 500;
+const MAX_LIMIT: usize = 1000;
+
+/// Most edits accepted in one push; they are applied in a single transaction.
+const MAX_PUSH: usize = 500;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SyncError {
+    #[error("Invalid sync token")]
+    InvalidToken,
+
+    #[error("Sync token expired")]
+    ExpiredToken,
+
+    #[error("At most {MAX_PUSH} changes can be pushed at once")]
+    TooManyMutations,
+
+    /// A pushed change would write a todo `TodoValidator` refuses; nothing was applied.
+    #[error("Change to {id}: {source}")]
+    Invalid { id: Uuid, source: ValidationError },
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+/// Delta sync for offline-first clients: everything created, updated or deleted since the
+/// token from their last sync, so they don't have to fetch the whole list again, and the
+/// edits they made offline pushed back with conflicts caught.
+pub struct SyncService {
+    repository: Arc<SqliteTodoRepository>,
+    events: TodoEvents,
+    validator: Arc<TodoValidator>,
+    mentions: Arc<MentionService>,
+}
+
+impl SyncService {
+    pub fn new(
+        repository: Arc<SqliteTodoRepository>,
+        events: TodoEvents,
+        validator: Arc<TodoValidator>,
+        mentions: Arc<MentionService>,
+    ) -> Self {
+        Self { repository, events, validator, mentions }
+    }
+
+    /// Changes after `since`, or every todo without it. Tokens are change sequence numbers,
+    /// but clients are told to treat them as opaque. With `projects`, todos of other
+    /// projects are left out.
+    pub async fn changes(
+        &self,
+        since: Option<&str>,
+        limit: Option<usize>,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<SyncChanges, SyncError> {
+        let since = match since {
+            Some(token) => token.parse().map_err(|_| SyncError::InvalidToken)?,
+            None => 0,
+        };
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        match self.repository.changes_since(since, limit, projects).await? {
+            SyncOutcome::Changes(changes) => Ok(changes),
+            SyncOutcome::Expired => Err(SyncError::ExpiredToken),
+        }
+    }
+
+    /// Applies offline edits and reports each one as applied or in conflict. Applied edits
+    /// are published to event stream subscribers like any other write, and people they
+    /// `@mention` are notified on behalf of `actor`. With `projects`, edits to todos of other
+    /// projects are refused.
+    pub async fn push(
+        &self,
+        mutations: Vec<SyncMutation>,
+        projects: Option<&HashSet<Uuid>>,
+        actor: Option<&User>,
+    ) -> Result<SyncPushResponse, SyncError> {
+        if mutations.len() > MAX_PUSH {
+            return Err(SyncError::TooManyMutations);
+        }
+        for mutation in &mutations {
+            let checked = match mutation {
+                SyncMutation::Create { todo, .. } => self.validator.check_create(todo, "sync").await,
+                SyncMutation::Update { changes, .. } => self.validator.check_update(changes, "sync").await,
+                SyncMutation::Delete { .. } => Ok(()),
+            };
+            match checked {
+                Ok(()) => {}
+                Err(ValidationError::Repository(e)) => return Err(e.into()),
+                Err(source) => return Err(SyncError::Invalid { id: mutation.id(), source }),
+            }
+        }
+        let creates: Vec<bool> = mutations.iter().map(|m| matches!(m, SyncMutation::Create { .. })).collect();
+        let previous = self.replaced_descriptions(&mutations).await;
+        let results = self.repository.apply_mutations(mutations, projects).await?;
+
+        let mut applied = 0;
+        for (result, created) in results.iter().zip(creates) {
+            let SyncMutationResult::Applied { id, todo } = result else {
+                continue;
+            };
+            applied += 1;
+            self.events.publish(match todo {
+                Some(todo) if created => TodoEvent::Created { todo: todo.clone() },
+                Some(todo) => TodoEvent::Updated { todo: todo.clone() },
+                None => TodoEvent::Deleted { id: *id },
+            });
+            match (todo, previous.get(id)) {
+                (Some(todo), _) if created => self.mentions.description_saved(todo, None, actor).await,
+                (Some(todo), Some(before)) => self.mentions.description_saved(todo, before.as_deref(), actor).await,
+                _ => {}
+            }
+        }
+        let conflicts = results.len() - applied;
+        Ok(SyncPushResponse { results, applied, conflicts })
+    }
+
+    /// The descriptions that pushed updates setting one would replace, by todo id. An update
+    /// only applies to the version it was based on, so the description read now is the one
+    /// it replaces; todos that can't be read are left out, as their updates will conflict.
+    async fn replaced_descriptions(&self, mutations: &[SyncMutation]) -> HashMap<Uuid, Option<String>> {
+        let mut previous = HashMap::new();
+        for mutation in mutations {
+            let SyncMutation::Update { id, changes, .. } = mutation else {
+                continue;
+            };
+            if changes.description.is_none() {
+                continue;
+            }
+            if let Ok(todo) = self.repository.get(*id).await {
+                previous.insert(*id, todo.description);
+            }
+        }
+        previous
+    }
+}
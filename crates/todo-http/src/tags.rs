@@ -0,0 +1,202 @@
+use crate::events::{TodoEvent, TodoEvents};
+use crate::models::{normalize_tags, BulkTagRequest, BulkTagResponse, Tag, TagChange, UpdateTagRequest};
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use std::{collections::HashSet, sync::Arc};
+use tracing::info;
+use uuid::Uuid;
+
+const MAX_NAME_LEN: usize = 100;
+
+const MAX_DESCRIPTION_LEN: usize = 500;
+
+/// Most todos `POST /todos/tags` takes by id; more can be matched with a filter.
+const MAX_BULK_IDS: usize = 10_000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TagError {
+    #[error("Tag names are 1 to {MAX_NAME_LEN} characters, not counting surrounding whitespace")]
+    InvalidName,
+
+    #[error("Tag colors are written #rrggbb")]
+    InvalidColor,
+
+    #[error("Tag descriptions are at most {MAX_DESCRIPTION_LEN} characters")]
+    DescriptionTooLong,
+
+    #[error("Unknown tag: {0}")]
+    Unknown(String),
+
+    #[error("Tag {0} already exists; merge into it instead")]
+    AlreadyExists(String),
+
+    #[error("A tag can't be merged into itself")]
+    SameTag,
+
+    #[error("Name the todos with either ids or a filter")]
+    NoSelection,
+
+    #[error("At most {MAX_BULK_IDS} todos can be named by id; use a filter for more")]
+    TooManyIds,
+
+    #[error("Give tags to add or to remove")]
+    NothingToChange,
+
+    #[error("Tag {0} can't be both added and removed")]
+    AddedAndRemoved(String),
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+/// Trims `name` as tags are trimmed on todos.
+fn tag_name(name: &str) -> Result<&str, TagError> {
+    let name = name.trim();
+    if (1..=MAX_NAME_LEN).contains(&name.chars().count()) {
+        Ok(name)
+    } else {
+        Err(TagError::InvalidName)
+    }
+}
+
+fn names(tags: Vec<String>) -> Result<Vec<String>, TagError> {
+    let tags = normalize_tags(tags);
+    for tag in &tags {
+        tag_name(tag)?;
+    }
+    Ok(tags)
+}
+
+fn color(value: &str) -> Result<String, TagError> {
+    match value.trim().strip_prefix('#') {
+        Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Ok(format!("#{}", hex.to_ascii_lowercase()))
+        }
+        _ => Err(TagError::InvalidColor),
+    }
+}
+
+/// Tags as resources of their own: a color and description each, usage counts, and renames
+/// and merges that rewrite every todo carrying them in one statement.
+pub struct TagService {
+    repository: Arc<SqliteTodoRepository>,
+    events: TodoEvents,
+}
+
+impl TagService {
+    pub fn new(repository: Arc<SqliteTodoRepository>, events: TodoEvents) -> Self {
+        Self { repository, events }
+    }
+
+    /// With `projects`, only todos of those projects (or of none) are counted.
+    pub async fn list(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<Tag>, RepositoryError> {
+        self.repository.list_tags(projects).await
+    }
+
+    /// `None` if no todo carries the tag and it has no color or description.
+    pub async fn get(&self, name: &str, projects: Option<&HashSet<Uuid>>) -> Result<Option<Tag>, RepositoryError> {
+        let name = name.trim();
+        Ok(self.repository.list_tags(projects).await?.into_iter().find(|tag| tag.name == name))
+    }
+
+    /// Sets the tag's color and description; the tag needn't be in use yet.
+    pub async fn update(
+        &self,
+        name: &str,
+        request: UpdateTagRequest,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<Tag, TagError> {
+        let name = tag_name(name)?;
+        let color = request.color.as_deref().map(color).transpose()?;
+        let description = request.description.as_deref().map(str::trim).filter(|d| !d.is_empty());
+        if description.is_some_and(|d| d.chars().count() > MAX_DESCRIPTION_LEN) {
+            return Err(TagError::DescriptionTooLong);
+        }
+        self.repository.set_tag(name, color.as_deref(), description).await?;
+        self.existing(name, projects).await
+    }
+
+    /// Renames the tag on every todo, keeping its color and description. Fails if the new
+    /// name is already a tag: that's a merge. With `projects`, only todos of those projects
+    /// (or of none) are changed, and only their tags are looked at.
+    pub async fn rename(
+        &self,
+        from: &str,
+        to: &str,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<TagChange, TagError> {
+        let from = self.existing(from, projects).await?;
+        let to = tag_name(to)?;
+        if self.get(to, projects).await?.is_some() {
+            return Err(TagError::AlreadyExists(to.to_owned()));
+        }
+        let todos_updated = self.retag(&from.name, to, projects).await?;
+        info!(tag.from = %from.name, tag.to = %to, todos_updated, "Tag renamed");
+        Ok(TagChange { tag: self.existing(to, projects).await?, todos_updated })
+    }
+
+    /// Replaces the tag with `into` on every todo and drops it once no todo carries it.
+    /// `into` needn't exist yet. With `projects`, as for `rename`.
+    pub async fn merge(
+        &self,
+        source: &str,
+        into: &str,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<TagChange, TagError> {
+        let source = self.existing(source, projects).await?;
+        let into = tag_name(into)?;
+        if source.name == into {
+            return Err(TagError::SameTag);
+        }
+        let todos_updated = self.retag(&source.name, into, projects).await?;
+        info!(tag.from = %source.name, tag.to = %into, todos_updated, "Tags merged");
+        Ok(TagChange { tag: self.existing(into, projects).await?, todos_updated })
+    }
+
+    /// Adds and removes tags on every todo the request names, in one statement. With
+    /// `projects`, only todos of those projects (or of none) are changed.
+    pub async fn tag_todos(
+        &self,
+        request: BulkTagRequest,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<BulkTagResponse, TagError> {
+        let ids = match (&request.ids, &request.filter) {
+            (Some(ids), None) if ids.len() > MAX_BULK_IDS => return Err(TagError::TooManyIds),
+            (Some(ids), None) => Some(ids.as_slice()),
+            (None, Some(_)) => None,
+            _ => return Err(TagError::NoSelection),
+        };
+        let add = names(request.add)?;
+        let remove = names(request.remove)?;
+        if add.is_empty() && remove.is_empty() {
+            return Err(TagError::NothingToChange);
+        }
+        if let Some(tag) = add.iter().find(|tag| remove.contains(tag)) {
+            return Err(TagError::AddedAndRemoved(tag.clone()));
+        }
+        let mut filter = request.filter.unwrap_or_default();
+        filter.tag = filter.tag.map(|tag| tag.trim().to_owned());
+
+        let updated_ids = self.repository.tag_todos(ids, &filter, &add, &remove, projects).await?;
+        info!(updated_count = updated_ids.len(), added = add.len(), removed = remove.len(), "Todos retagged");
+        if !updated_ids.is_empty() {
+            self.events.publish(TodoEvent::Resync);
+        }
+        Ok(BulkTagResponse {
+            updated_count: updated_ids.len(),
+            updated_ids,
+        })
+    }
+
+    async fn retag(&self, source: &str, into: &str, projects: Option<&HashSet<Uuid>>) -> Result<u64, RepositoryError> {
+        let todos_updated = self.repository.merge_tags(&[source.to_owned()], into, projects).await?;
+        if todos_updated > 0 {
+            // Cached lists and open event streams can't tell which todos changed
+            self.events.publish(TodoEvent::Resync);
+        }
+        Ok(todos_updated)
+    }
+
+    async fn existing(&self, name: &str, projects: Option<&HashSet<Uuid>>) -> Result<Tag, TagError> {
+        self.get(name, projects).await?.ok_or_else(|| TagError::Unknown(name.trim().to_owned()))
+    }
+}
@@ -0,0 +1,374 @@
+use crate::config::TelemetryConfig;
+use axum::{
+    extract::MatchedPath,
+    http::{header, Request, Response},
+};
+use opentelemetry::{
+    global,
+    trace::{TraceResult, TracerProvider as _},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::{MetricsExporterBuilder, SpanExporterBuilder, WithExportConfig};
+use opentelemetry_sdk::{
+    metrics::{
+        reader::{DefaultAggregationSelector, DefaultTemporalitySelector},
+        new_view, Aggregation, Instrument, PeriodicReader, SdkMeterProvider, Stream, View,
+    },
+    export::trace::SpanData,
+    trace::{BatchSpanProcessor, SpanProcessor, TracerProvider},
+    Resource,
+};
+use std::{
+    str::FromStr,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use tokio::net::TcpStream;
+use tracing::{field, info, info_span, warn, Span};
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Where spans are sent (`OTEL_TRACES_EXPORTER`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceExporter {
+    Otlp,
+    /// Pretty-printed spans on stdout, for local debugging without a collector.
+    Stdout,
+    /// Spans are only visible through the fmt log output.
+    None,
+}
+
+impl FromStr for TraceExporter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "otlp" => Ok(Self::Otlp),
+            "stdout" | "console" => Ok(Self::Stdout),
+            "none" => Ok(Self::None),
+            other => Err(format!("unknown trace exporter: {other}")),
+        }
+    }
+}
+
+/// Wire protocol for OTLP exports (`OTEL_EXPORTER_OTLP_PROTOCOL`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    Grpc,
+    /// Protobuf over plain HTTP, for collectors behind proxies that block gRPC.
+    HttpProtobuf,
+}
+
+impl OtlpProtocol {
+    pub fn default_endpoint(self) -> &'static str {
+        match self {
+            Self::Grpc => "http://localhost:4317",
+            Self::HttpProtobuf => "http://localhost:4318",
+        }
+    }
+}
+
+impl FromStr for OtlpProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "grpc" => Ok(Self::Grpc),
+            "http/protobuf" | "http" => Ok(Self::HttpProtobuf),
+            other => Err(format!("unsupported OTLP protocol: {other}")),
+        }
+    }
+}
+
+fn resource(config: &TelemetryConfig) -> Resource {
+    Resource::new(vec![
+        KeyValue::new("service.name", config.service_name.clone()),
+        KeyValue::new("service.version", "0.2.0"),
+    ])
+}
+
+/// The HTTP exporter uses the endpoint verbatim, so the per-signal path has to be added here.
+fn http_endpoint(base: &str, signal_path: &str) -> String {
+    format!("{}/{}", base.trim_end_matches('/'), signal_path)
+}
+
+fn span_exporter(config: &TelemetryConfig) -> SpanExporterBuilder {
+    match config.otlp_protocol {
+        OtlpProtocol::Grpc => opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_endpoint(&config.otlp_endpoint)
+            .into(),
+        OtlpProtocol::HttpProtobuf => opentelemetry_otlp::new_exporter()
+            .http()
+            .with_endpoint(http_endpoint(&config.otlp_endpoint, "v1/traces"))
+            .into(),
+    }
+}
+
+fn metrics_exporter(config: &TelemetryConfig) -> MetricsExporterBuilder {
+    match config.otlp_protocol {
+        OtlpProtocol::Grpc => opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_endpoint(&config.otlp_endpoint)
+            .into(),
+        OtlpProtocol::HttpProtobuf => opentelemetry_otlp::new_exporter()
+            .http()
+            .with_endpoint(http_endpoint(&config.otlp_endpoint, "v1/metrics"))
+            .into(),
+    }
+}
+
+type BatchProcessor = BatchSpanProcessor<opentelemetry_sdk::runtime::TokioCurrentThread>;
+
+const RETRY_INITIAL_DELAY: Duration = Duration::from_secs(5);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// Span processor whose OTLP batch exporter is plugged in once the collector is reachable.
+/// Until then spans still get real trace ids (for log correlation) but are dropped on end.
+#[derive(Debug, Clone, Default)]
+struct DeferredSpanProcessor {
+    inner: Arc<OnceLock<BatchProcessor>>,
+}
+
+impl SpanProcessor for DeferredSpanProcessor {
+    fn on_start(&self, span: &mut opentelemetry_sdk::trace::Span, cx: &Context) {
+        if let Some(processor) = self.inner.get() {
+            processor.on_start(span, cx);
+        }
+    }
+
+    fn on_end(&self, span: SpanData) {
+        if let Some(processor) = self.inner.get() {
+            processor.on_end(span);
+        }
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.get().map_or(Ok(()), SpanProcessor::force_flush)
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        self.inner.get().map_or(Ok(()), SpanProcessor::shutdown)
+    }
+}
+
+/// Exporter construction is lazy, so a dead collector only shows up as failed exports later on.
+/// Connecting up front lets startup fall back to fmt-only logging instead.
+async fn collector_reachable(endpoint: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(endpoint) else {
+        return false;
+    };
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return false;
+    };
+
+    matches!(
+        tokio::time::timeout(Duration::from_secs(2), TcpStream::connect((host, port))).await,
+        Ok(Ok(_))
+    )
+}
+
+async fn try_batch_processor(config: &TelemetryConfig) -> Result<BatchProcessor, String> {
+    if !collector_reachable(&config.otlp_endpoint).await {
+        return Err(format!("collector at {} is unreachable", config.otlp_endpoint));
+    }
+    let exporter = span_exporter(config)
+        .build_span_exporter()
+        .map_err(|e| e.to_string())?;
+    let mut processor =
+        BatchSpanProcessor::builder(exporter, opentelemetry_sdk::runtime::TokioCurrentThread).build();
+    processor.set_resource(&resource(config));
+    Ok(processor)
+}
+
+/// Keeps retrying OTLP setup with exponential backoff and plugs the exporter in once it succeeds.
+fn spawn_exporter_retry(config: TelemetryConfig, slot: Arc<OnceLock<BatchProcessor>>) {
+    tokio::spawn(async move {
+        let mut delay = RETRY_INITIAL_DELAY;
+        loop {
+            tokio::time::sleep(delay).await;
+
+            match try_batch_processor(&config).await {
+                Ok(processor) => {
+                    let _ = slot.set(processor);
+                    info!(endpoint = %config.otlp_endpoint, "Trace export enabled");
+                    return;
+                }
+                Err(e) => {
+                    delay = (delay * 2).min(RETRY_MAX_DELAY);
+                    warn!(error = %e, retry_in_secs = delay.as_secs(), "Trace exporter still unavailable");
+                }
+            }
+        }
+    });
+}
+
+/// Never fails: if the OTLP exporter can't be set up, logging continues without trace export
+/// and setup is retried in the background.
+pub async fn init_tracing(config: &TelemetryConfig) {
+    let trace_config = || opentelemetry_sdk::trace::Config::default().with_resource(resource(config));
+    let mut pending_export = None;
+
+    let provider = match config.traces_exporter {
+        TraceExporter::Otlp => {
+            let processor = DeferredSpanProcessor::default();
+            match try_batch_processor(config).await {
+                Ok(batch) => {
+                    let _ = processor.inner.set(batch);
+                }
+                Err(e) => pending_export = Some((e, processor.inner.clone())),
+            }
+            Some(
+                TracerProvider::builder()
+                    .with_span_processor(processor)
+                    .with_config(trace_config())
+                    .build(),
+            )
+        }
+        TraceExporter::Stdout if config.log_to_stderr => None,
+        TraceExporter::Stdout => Some(
+            TracerProvider::builder()
+                .with_simple_exporter(opentelemetry_stdout::SpanExporter::default())
+                .with_config(trace_config())
+                .build(),
+        ),
+        TraceExporter::None => None,
+    };
+    let telemetry_layer = provider
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("todo-api")));
+
+    tracing_subscriber::registry()
+        .with(telemetry_layer)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(if config.log_to_stderr {
+                    BoxMakeWriter::new(std::io::stderr)
+                } else {
+                    BoxMakeWriter::new(std::io::stdout)
+                })
+                .with_target(false)
+                .compact(),
+        )
+        .with(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+
+    if config.log_to_stderr && config.traces_exporter == TraceExporter::Stdout {
+        warn!("Stdout trace export is disabled while stdout carries the MCP protocol");
+    }
+    if let Some((e, slot)) = pending_export {
+        warn!(error = %e, "Trace export unavailable, falling back to log output only");
+        spawn_exporter_retry(config.clone(), slot);
+    }
+
+    info!(
+        exporter = ?config.traces_exporter,
+        protocol = ?config.otlp_protocol,
+        endpoint = %config.otlp_endpoint,
+        "Tracing initialized"
+    );
+}
+
+/// The SDK's default buckets are sized for milliseconds; duration instruments here are in seconds.
+const SECONDS_BUCKETS: [f64; 14] = [
+    0.005, 0.01, 0.025, 0.05, 0.075, 0.1, 0.25, 0.5, 0.75, 1.0, 2.5, 5.0, 7.5, 10.0,
+];
+
+fn seconds_histogram_view(name: &str) -> opentelemetry::metrics::Result<Box<dyn View>> {
+    new_view(
+        Instrument::new().name(name.to_owned()),
+        Stream::new().aggregation(Aggregation::ExplicitBucketHistogram {
+            boundaries: SECONDS_BUCKETS.to_vec(),
+            record_min_max: true,
+        }),
+    )
+}
+
+/// Installs the global meter provider. Every instrument is readable from the Prometheus
+/// registry and, unless disabled, also pushed over OTLP to the same collector as traces.
+/// Instruments must be created after this runs, otherwise they bind to the no-op provider.
+/// Returns the registry backing the `/metrics` endpoint.
+pub fn init_metrics(config: &TelemetryConfig) -> prometheus::Registry {
+    let registry = prometheus::Registry::new();
+    let mut builder = SdkMeterProvider::builder().with_resource(resource(config));
+
+    for name in ["http.server.request.duration", "db.client.operation.duration"] {
+        match seconds_histogram_view(name) {
+            Ok(view) => builder = builder.with_view(view),
+            Err(e) => warn!(error = %e, instrument = name, "Failed to register histogram view"),
+        }
+    }
+
+    match opentelemetry_prometheus::exporter()
+        .with_registry(registry.clone())
+        .build()
+    {
+        Ok(exporter) => builder = builder.with_reader(exporter),
+        Err(e) => warn!(error = %e, "Failed to set up Prometheus exporter, /metrics will be empty"),
+    }
+
+    if config.metrics_enabled {
+        let exporter = metrics_exporter(config).build_metrics_exporter(
+            Box::new(DefaultTemporalitySelector::new()),
+            Box::new(DefaultAggregationSelector::new()),
+        );
+        match exporter {
+            Ok(exporter) => {
+                let reader = PeriodicReader::builder(exporter, opentelemetry_sdk::runtime::Tokio)
+                    .with_interval(config.metrics_export_interval)
+                    .build();
+                builder = builder.with_reader(reader);
+                info!(
+                    endpoint = %config.otlp_endpoint,
+                    interval_ms = config.metrics_export_interval.as_millis(),
+                    "OTLP metrics export enabled"
+                );
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to set up OTLP metrics export, continuing without it");
+            }
+        }
+    } else {
+        info!("OTLP metrics export disabled");
+    }
+
+    global::set_meter_provider(builder.build());
+    registry
+}
+
+/// Root span for each request, named after the matched route template rather than the raw
+/// path so ids don't end up in span names. Attributes follow the HTTP semantic conventions.
+pub fn make_http_span<B>(req: &Request<B>) -> Span {
+    let method = req.method().as_str();
+    let route = req.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
+    let span_name = match route {
+        Some(route) => format!("{method} {route}"),
+        None => method.to_string(),
+    };
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok());
+
+    info_span!(
+        "http_request",
+        otel.name = %span_name,
+        otel.kind = "server",
+        otel.status_code = field::Empty,
+        http.request.method = %method,
+        http.route = route,
+        url.path = %req.uri().path(),
+        url.query = req.uri().query(),
+        network.protocol.version = ?req.version(),
+        user_agent.original = user_agent,
+        // Set by `deadline::enforce_deadline` when the request has a deadline
+        http.request.timeout_ms = field::Empty,
+        http.response.status_code = field::Empty,
+    )
+}
+
+pub fn record_http_response<B>(res: &Response<B>, _latency: Duration, span: &Span) {
+    let status = res.status();
+    span.record("http.response.status_code", i64::from(status.as_u16()));
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+}
@@ -0,0 +1,215 @@
+use crate::auth::Principal;
+use crate::models::{CompactTodo, HistoryEntry, Todo, TodoStats};
+use crate::repository::{
+    ListFilter, ListWindow, RepositoryError, TodoCursor, TodoList, TodoListSummary, TodoPage, TodoRepository,
+};
+use async_trait::async_trait;
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use opentelemetry::{global, metrics::ObservableGauge};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::Arc,
+};
+use todo_storage::tenants::{self, TenantDatabases};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+/// The header naming the tenant a request is for.
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+#[derive(Clone)]
+struct Tenant {
+    id: String,
+    repository: Arc<dyn TodoRepository>,
+}
+
+tokio::task_local! {
+    /// The tenant the current request is for, set by `route_tenant` around the handler.
+    static TENANT: Tenant;
+}
+
+/// The tenant the current request is for, or `None` for the shared database.
+pub fn current_tenant() -> Option<String> {
+    TENANT.try_with(|tenant| tenant.id.clone()).ok()
+}
+
+/// Sends every call to the database of the request's tenant, or to `shared` outside one.
+pub struct TenantRoutedRepository {
+    shared: Arc<dyn TodoRepository>,
+}
+
+impl TenantRoutedRepository {
+    pub fn new(shared: Arc<dyn TodoRepository>) -> Self {
+        Self { shared }
+    }
+
+    fn current(&self) -> Arc<dyn TodoRepository> {
+        TENANT
+            .try_with(|tenant| tenant.repository.clone())
+            .unwrap_or_else(|_| self.shared.clone())
+    }
+}
+
+#[async_trait]
+impl TodoRepository for TenantRoutedRepository {
+    async fn create(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        self.current().create(todo).await
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Todo, RepositoryError> {
+        self.current().get(id).await
+    }
+
+    async fn list(&self, filter: &ListFilter<'_>, window: Option<ListWindow>) -> Result<TodoList, RepositoryError> {
+        self.current().list(filter, window).await
+    }
+
+    async fn list_page(
+        &self,
+        after: Option<TodoCursor>,
+        limit: usize,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<TodoPage, RepositoryError> {
+        self.current().list_page(after, limit, projects).await
+    }
+
+    async fn list_compact(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<CompactTodo>, RepositoryError> {
+        self.current().list_compact(projects).await
+    }
+
+    async fn summary(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoListSummary, RepositoryError> {
+        self.current().summary(projects).await
+    }
+
+    async fn stats(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoStats, RepositoryError> {
+        self.current().stats(projects).await
+    }
+
+    async fn update(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        self.current().update(todo).await
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError> {
+        self.current().delete(id).await
+    }
+
+    async fn snooze(&self, id: Uuid, until: DateTime<Utc>) -> Result<Todo, RepositoryError> {
+        self.current().snooze(id, until).await
+    }
+
+    async fn history(&self, id: Uuid) -> Result<Vec<HistoryEntry>, RepositoryError> {
+        self.current().history(id).await
+    }
+
+    async fn add_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<Todo, RepositoryError> {
+        self.current().add_dependency(blocker, blocked).await
+    }
+
+    async fn remove_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<(), RepositoryError> {
+        self.current().remove_dependency(blocker, blocked).await
+    }
+
+    async fn create_batch(&self, todos: Vec<Todo>) -> Result<Vec<Todo>, RepositoryError> {
+        self.current().create_batch(todos).await
+    }
+
+    async fn import(&self, todos: Vec<Todo>) -> Result<usize, RepositoryError> {
+        self.current().import(todos).await
+    }
+
+    async fn delete_completed(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<Uuid>, RepositoryError> {
+        self.current().delete_completed(projects).await
+    }
+
+    async fn database_version(&self) -> Result<String, RepositoryError> {
+        self.current().database_version().await
+    }
+}
+
+/// Picks the tenant database for each request with an `X-Tenant-ID` header, among the
+/// configured tenants.
+pub struct TenantRouting {
+    databases: Arc<TenantDatabases>,
+    /// Each tenant served, with the emails of the accounts that may use it.
+    members: BTreeMap<String, BTreeSet<String>>,
+    _open_gauge: ObservableGauge<u64>,
+}
+
+impl TenantRouting {
+    pub fn new(databases: Arc<TenantDatabases>, members: BTreeMap<String, BTreeSet<String>>) -> Self {
+        let observed = databases.clone();
+        let open_gauge = global::meter("todo-api")
+            .u64_observable_gauge("tenant.databases_open")
+            .with_description("Tenant databases with an open connection pool")
+            .with_callback(move |observer| observer.observe(observed.open_count() as u64, &[]))
+            .init();
+        Self {
+            databases,
+            members,
+            _open_gauge: open_gauge,
+        }
+    }
+}
+
+/// Whether every call the route makes goes through the `TodoRepository`, and so reaches the
+/// tenant's database. Everything else (tags, comments, attachments, custom fields, projects,
+/// sync, analytics, integrations, the event stream, MCP, the UI, CalDAV, accounts and admin)
+/// holds the shared database directly.
+fn tenant_routable(path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["health"] | ["health", "schema"] | ["metrics"] | ["schemas", "requests"] => true,
+        ["todos"] | ["import", "markdown"] => true,
+        ["todos", "tags" | "events"] => false,
+        ["todos", _] => true,
+        ["todos", _, "snooze" | "history" | "blockers"] | ["todos", _, "blockers", _] => true,
+        _ => false,
+    }
+}
+
+/// Runs the rest of the request with its tenant's database, opening it if needed. Requests
+/// without the header use the shared database. Tenants that aren't configured answer `404`,
+/// and callers `AUTH_REQUIRED` identified who aren't among a tenant's members `403`. Routes
+/// that would read or write the shared database whatever the header says answer `400`.
+pub async fn route_tenant(State(routing): State<Arc<TenantRouting>>, req: Request, next: Next) -> Response {
+    let Some(header) = req.headers().get(TENANT_HEADER) else {
+        return next.run(req).await;
+    };
+    if !tenant_routable(req.uri().path()) {
+        debug!(path = req.uri().path(), "X-Tenant-ID sent to a route that only uses the shared database");
+        return (StatusCode::BAD_REQUEST, "X-Tenant-ID isn't supported on this route").into_response();
+    }
+    let tenant = match header.to_str().map(tenants::normalize_tenant_id) {
+        Ok(Ok(tenant)) => tenant,
+        Ok(Err(RepositoryError::InvalidData(reason))) => {
+            return (StatusCode::BAD_REQUEST, format!("Invalid X-Tenant-ID header: {reason}")).into_response();
+        }
+        _ => return (StatusCode::BAD_REQUEST, "Invalid X-Tenant-ID header").into_response(),
+    };
+    let Some(members) = routing.members.get(&tenant) else {
+        return (StatusCode::NOT_FOUND, "Unknown tenant").into_response();
+    };
+    if let Some(principal) = req.extensions().get::<Principal>() {
+        if !members.contains(&principal.user.email.to_lowercase()) {
+            warn!(tenant, user.id = %principal.user.id, "Caller isn't a member of the tenant");
+            return (StatusCode::FORBIDDEN, "Not a member of this tenant").into_response();
+        }
+    }
+
+    match routing.databases.repository(&tenant).await {
+        Ok(repository) => {
+            debug!(tenant, "Request routed to tenant database");
+            TENANT.scope(Tenant { id: tenant, repository }, next.run(req)).await
+        }
+        Err(e) => {
+            error!(tenant, error = %e, "Failed to open tenant database");
+            (StatusCode::SERVICE_UNAVAILABLE, "Tenant database is unavailable").into_response()
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::auth::Principal;
+use crate::models::Todo;
+use crate::notification_worker::NotificationJob;
+use crate::redact;
+use crate::repository::{ListFilter, RepositoryError};
+use crate::{accessible_todo, check_todo_access, validation_error, visible_projects, AppState};
+use axum::{
+    extract::{Path, State},
+    Extension,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Form, Router,
+};
+use maud::{html, Markup, DOCTYPE};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tracing::{error, info, instrument, warn, Span};
+use uuid::Uuid;
+
+const HTMX_SRC: &str = "https://unpkg.com/htmx.org@1.9.12";
+
+const STYLE: &str = "
+    body { font-family: system-ui, sans-serif; max-width: 40rem; margin: 2rem auto; padding: 0 1rem; }
+    form.add { display: flex; gap: .5rem; margin-bottom: 1rem; }
+    form.add input { flex: 1; padding: .4rem; }
+    ul { list-style: none; padding: 0; }
+    li { display: flex; align-items: center; gap: .5rem; padding: .4rem 0; border-bottom: 1px solid #ddd; }
+    li .title { flex: 1; }
+    li.done .title { text-decoration: line-through; color: #888; }
+    .meta { font-size: .8rem; color: #666; }
+";
+
+#[derive(Debug, Deserialize)]
+pub struct NewTodoForm {
+    title: String,
+}
+
+/// Server-rendered pages over the same repository and notification queue as the JSON API.
+/// Buttons use HTMX, so adding, completing and deleting a todo swap a single list item.
+/// Callers `AUTH_REQUIRED` identified see and change project todos as their role allows.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/ui", get(index))
+        .route("/ui/todos", post(create))
+        .route("/ui/todos/:id/toggle", post(toggle))
+        .route("/ui/todos/:id", delete(remove))
+}
+
+#[instrument(skip(state, principal))]
+async fn index(State(state): State<AppState>, principal: Option<Extension<Principal>>) -> Response {
+    let visible = match visible_projects(&state, principal.as_deref()).await {
+        Ok(visible) => visible,
+        Err(rejection) => return rejection,
+    };
+    let filter = ListFilter {
+        projects: visible.as_ref(),
+        ..ListFilter::default()
+    };
+    let todos = match state.repository.list(&filter, None).await {
+        Ok(list) => list.todos,
+        Err(e) => {
+            error!(error = %e, "Failed to list todos");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to retrieve todos").into_response();
+        }
+    };
+
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                title { "Todos" }
+                script src=(HTMX_SRC) {}
+                style { (STYLE) }
+            }
+            body {
+                h1 { "Todos" }
+                form.add hx-post="/ui/todos" hx-target="#todos" hx-swap="beforeend"
+                    hx-on--after-request="if (event.detail.successful) this.reset()" {
+                    input name="title" placeholder="What needs doing?" required autofocus;
+                    button type="submit" { "Add" }
+                }
+                ul #todos {
+                    @for todo in &todos {
+                        (todo_item(todo))
+                    }
+                }
+            }
+        }
+    }
+    .into_response()
+}
+
+#[instrument(skip(state, form), fields(title = %redact::redacted(&form.title), todo.id))]
+async fn create(State(state): State<AppState>, Form(form): Form<NewTodoForm>) -> Response {
+    let title = form.title.trim();
+    if let Err(e) = state.validator.check_text(Some(title), None, "ui") {
+        return validation_error(e);
+    }
+    // The form only takes a title, so a required custom field can't be filled in here
+    if let Err(e) = state.validator.check_custom_fields(&BTreeMap::new(), true).await {
+        return validation_error(e);
+    }
+
+    let now = state.clock.now();
+    let todo = Todo {
+        id: Uuid::new_v4(),
+        title: title.to_string(),
+        description: None,
+        completed: false,
+        due_at: None,
+        tags: Vec::new(),
+        estimate_minutes: None,
+        expires_at: None,
+        project_id: None,
+        priority: None,
+        pinned: false,
+        custom_fields: BTreeMap::new(),
+        created_at: now,
+        updated_at: now,
+        blocked: false,
+        version: 0,
+        change_seq: 0,
+    };
+    Span::current().record("todo.id", tracing::field::display(&todo.id));
+
+    let created = match state.repository.create(todo).await {
+        Ok(todo) => todo,
+        Err(e) => {
+            error!(error = %e, "Failed to create todo");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create todo").into_response();
+        }
+    };
+    let job = NotificationJob::Created {
+        todo_id: created.id,
+        title: created.title.clone(),
+    };
+    if let Err(e) = state.notifications.enqueue(job).await {
+        warn!(error = %e, "Failed to queue notification, continuing anyway");
+    }
+
+    info!("Todo created from the UI");
+    todo_item(&created).into_response()
+}
+
+/// Marks an open todo completed, or reopens a completed one.
+#[instrument(skip(state, principal), fields(todo.id = %id))]
+async fn toggle(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    let mut todo = match accessible_todo(&state, principal.as_deref(), id, true).await {
+        Ok(todo) => todo,
+        Err(rejection) => return rejection,
+    };
+    todo.completed = !todo.completed;
+    todo.updated_at = state.clock.now();
+
+    let updated = match state.repository.update(todo).await {
+        Ok(todo) => todo,
+        Err(e) => {
+            error!(error = %e, "Failed to update todo");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update todo").into_response();
+        }
+    };
+    if updated.completed {
+        let job = NotificationJob::Completed {
+            todo_id: updated.id,
+            title: updated.title.clone(),
+        };
+        if let Err(e) = state.notifications.enqueue(job).await {
+            warn!(error = %e, "Failed to queue completion notification");
+        }
+    }
+
+    todo_item(&updated).into_response()
+}
+
+/// Answers `200` with an empty body rather than `204`, which HTMX wouldn't swap in.
+#[instrument(skip(state, principal), fields(todo.id = %id))]
+async fn remove(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    match state.repository.get(id).await {
+        Ok(todo) => {
+            if let Err(rejection) = check_todo_access(&state, principal.as_deref(), &todo, true).await {
+                return rejection.into_response();
+            }
+        }
+        // Already gone, which is what was asked for
+        Err(RepositoryError::NotFound(_)) => return (StatusCode::OK, "").into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to get todo for deletion");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete todo").into_response();
+        }
+    }
+    match state.repository.delete(id).await {
+        Ok(()) | Err(RepositoryError::NotFound(_)) => (StatusCode::OK, "").into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to delete todo");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete todo").into_response()
+        }
+    }
+}
+
+fn todo_item(todo: &Todo) -> Markup {
+    html! {
+        li.done[todo.completed] #{ "todo-" (todo.id) } {
+            input type="checkbox" checked[todo.completed]
+                hx-post={ "/ui/todos/" (todo.id) "/toggle" }
+                hx-target="closest li" hx-swap="outerHTML";
+            span.title {
+                (todo.title)
+                @if let Some(due_at) = todo.due_at {
+                    " " span.meta { "due " (due_at.format("%Y-%m-%d %H:%M")) }
+                }
+                @if todo.blocked {
+                    " " span.meta { "blocked" }
+                }
+                @for tag in &todo.tags {
+                    " " span.meta { "#" (tag) }
+                }
+            }
+            button hx-delete={ "/ui/todos/" (todo.id) }
+                hx-target="closest li" hx-swap="outerHTML" { "Delete" }
+        }
+    }
+}
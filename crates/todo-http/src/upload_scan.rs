@@ -0,0 +1,266 @@
+use crate::config::UploadScanConfig;
+use async_trait::async_trait;
+use opentelemetry::{global, metrics::Counter, KeyValue};
+use std::{sync::Arc, time::Duration};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{info, Instrument};
+
+/// Bytes sent to clamd per `INSTREAM` chunk.
+const CLAMAV_CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScanError {
+    /// The upload must not be stored; the reason is shown to the client.
+    #[error("Upload rejected: {0}")]
+    Rejected(String),
+
+    /// The scanner couldn't decide, so the upload is refused without blaming it.
+    #[error("Upload scanner {scanner} failed: {reason}")]
+    Unavailable { scanner: &'static str, reason: String },
+}
+
+/// An upload as it would be stored.
+pub struct Upload<'a> {
+    pub filename: &'a str,
+    /// The type the attachment will be served with.
+    pub content_type: &'a str,
+    pub data: &'a [u8],
+}
+
+/// A check uploads have to pass before they're stored.
+#[async_trait]
+pub trait UploadScanner: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn scan(&self, upload: &Upload<'_>) -> Result<(), ScanError>;
+}
+
+/// `type/subtype` of a content type, lowercased and without parameters.
+fn essence(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// Tighter size limits for some content types than `ATTACHMENT_MAX_BYTES`, which applies to all.
+pub struct SizeCapScanner {
+    /// `type/subtype` or `type/*`, and the most bytes uploads of it may have.
+    caps: Vec<(String, usize)>,
+}
+
+impl SizeCapScanner {
+    pub fn new(caps: Vec<(String, usize)>) -> Self {
+        Self { caps }
+    }
+
+    fn cap(&self, content_type: &str) -> Option<usize> {
+        let essence = essence(content_type);
+        self.caps
+            .iter()
+            .filter(|(pattern, _)| match pattern.strip_suffix("/*") {
+                Some(kind) => essence.split('/').next() == Some(kind),
+                None => *pattern == essence,
+            })
+            .map(|(_, bytes)| *bytes)
+            .min()
+    }
+}
+
+#[async_trait]
+impl UploadScanner for SizeCapScanner {
+    fn name(&self) -> &'static str {
+        "size_cap"
+    }
+
+    async fn scan(&self, upload: &Upload<'_>) -> Result<(), ScanError> {
+        match self.cap(upload.content_type) {
+            Some(cap) if upload.data.len() > cap => Err(ScanError::Rejected(format!(
+                "{} attachments are at most {cap} bytes",
+                essence(upload.content_type)
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Types recognised by their leading bytes.
+const SIGNATURES: &[(&str, &[u8])] = &[
+    ("image/png", b"\x89PNG\r\n\x1a\n"),
+    ("image/jpeg", b"\xff\xd8\xff"),
+    ("image/gif", b"GIF87a"),
+    ("image/gif", b"GIF89a"),
+    ("application/pdf", b"%PDF-"),
+    ("application/zip", b"PK\x03\x04"),
+    ("application/gzip", b"\x1f\x8b"),
+];
+
+/// Leading bytes of programs: Windows, Linux and macOS executables, and scripts.
+const EXECUTABLES: &[&[u8]] = &[
+    b"MZ",
+    b"\x7fELF",
+    b"\xfe\xed\xfa\xce",
+    b"\xfe\xed\xfa\xcf",
+    b"\xce\xfa\xed\xfe",
+    b"\xcf\xfa\xed\xfe",
+    b"\xca\xfe\xba\xbe",
+    b"#!",
+];
+
+/// The type `data` looks like, if its signature is known.
+fn sniff(data: &[u8]) -> Option<&'static str> {
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    SIGNATURES
+        .iter()
+        .find(|(_, magic)| data.starts_with(magic))
+        .map(|(content_type, _)| *content_type)
+}
+
+/// Looks at the bytes rather than trusting the declared type: programs are refused, and so
+/// are uploads declared as a type with a known signature that they don't have. Types
+/// without a signature, such as text, are taken as declared.
+pub struct MimeSniffer;
+
+#[async_trait]
+impl UploadScanner for MimeSniffer {
+    fn name(&self) -> &'static str {
+        "mime_sniff"
+    }
+
+    async fn scan(&self, upload: &Upload<'_>) -> Result<(), ScanError> {
+        if EXECUTABLES.iter().any(|magic| upload.data.starts_with(magic)) {
+            return Err(ScanError::Rejected("executable files can't be attached".into()));
+        }
+
+        let declared = essence(upload.content_type);
+        let has_signature = declared == "image/webp" || SIGNATURES.iter().any(|(t, _)| *t == declared);
+        match sniff(upload.data) {
+            Some(actual) if has_signature && actual != declared => Err(ScanError::Rejected(format!(
+                "declared as {declared} but the content is {actual}"
+            ))),
+            None if has_signature => {
+                Err(ScanError::Rejected(format!("declared as {declared} but the content isn't")))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Streams uploads to a clamd daemon with `INSTREAM` and refuses those it finds malware in.
+/// Uploads are refused as well while clamd can't be reached, rather than stored unscanned.
+pub struct ClamAvScanner {
+    /// `host:port`, or the path of clamd's Unix socket.
+    address: String,
+    timeout: Duration,
+}
+
+impl ClamAvScanner {
+    pub fn new(address: impl Into<String>, timeout: Duration) -> Self {
+        Self { address: address.into(), timeout }
+    }
+
+    async fn connect_and_scan(&self, data: &[u8]) -> std::io::Result<String> {
+        #[cfg(unix)]
+        if self.address.starts_with('/') {
+            let stream = tokio::net::UnixStream::connect(&self.address).await?;
+            return instream(stream, data).await;
+        }
+        let stream = tokio::net::TcpStream::connect(&self.address).await?;
+        instream(stream, data).await
+    }
+}
+
+/// Sends `data` as length-prefixed chunks and returns clamd's reply, such as `stream: OK`.
+async fn instream<S>(mut stream: S, data: &[u8]) -> std::io::Result<String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in data.chunks(CLAMAV_CHUNK) {
+        stream.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    stream.flush().await?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await?;
+    Ok(String::from_utf8_lossy(&reply).trim_end_matches(['\0', '\n']).to_owned())
+}
+
+#[async_trait]
+impl UploadScanner for ClamAvScanner {
+    fn name(&self) -> &'static str {
+        "clamav"
+    }
+
+    async fn scan(&self, upload: &Upload<'_>) -> Result<(), ScanError> {
+        let unavailable = |reason: String| ScanError::Unavailable { scanner: self.name(), reason };
+        let reply = tokio::time::timeout(self.timeout, self.connect_and_scan(upload.data))
+            .await
+            .map_err(|_| unavailable("timed out".into()))?
+            .map_err(|e| unavailable(e.to_string()))?;
+
+        let verdict = reply.strip_prefix("stream: ").unwrap_or(&reply);
+        if verdict == "OK" {
+            Ok(())
+        } else if let Some(signature) = verdict.strip_suffix(" FOUND") {
+            Err(ScanError::Rejected(format!("malware detected ({signature})")))
+        } else {
+            Err(unavailable(reply))
+        }
+    }
+}
+
+/// Runs scanners in order; the first to object decides.
+pub struct ScanPipeline {
+    scanners: Vec<Arc<dyn UploadScanner>>,
+    rejections: Counter<u64>,
+}
+
+impl ScanPipeline {
+    pub fn new(scanners: Vec<Arc<dyn UploadScanner>>) -> Self {
+        let rejections = global::meter("todo-api")
+            .u64_counter("attachments.rejected")
+            .with_description("Uploads refused by upload scanning, by scanner")
+            .init();
+        Self { scanners, rejections }
+    }
+
+    /// The scanners `config` turns on: size caps, then sniffing, then ClamAV.
+    pub fn from_config(config: &UploadScanConfig) -> Self {
+        let mut scanners: Vec<Arc<dyn UploadScanner>> = Vec::new();
+        if !config.size_caps.is_empty() {
+            scanners.push(Arc::new(SizeCapScanner::new(config.size_caps.clone())));
+        }
+        if config.sniff_mime {
+            scanners.push(Arc::new(MimeSniffer));
+        }
+        if let Some(address) = &config.clamav_address {
+            scanners.push(Arc::new(ClamAvScanner::new(address, config.clamav_timeout)));
+        }
+        let names: Vec<&str> = scanners.iter().map(|s| s.name()).collect();
+        info!(scanners = ?names, "Upload scanning configured");
+        Self::new(scanners)
+    }
+}
+
+#[async_trait]
+impl UploadScanner for ScanPipeline {
+    fn name(&self) -> &'static str {
+        "pipeline"
+    }
+
+    async fn scan(&self, upload: &Upload<'_>) -> Result<(), ScanError> {
+        for scanner in &self.scanners {
+            let span = tracing::debug_span!("upload_scan", scanner = scanner.name());
+            if let Err(e) = scanner.scan(upload).instrument(span).await {
+                if let ScanError::Rejected(_) = e {
+                    info!(scanner = scanner.name(), error = %e, "Upload refused");
+                }
+                self.rejections.add(1, &[KeyValue::new("scanner", scanner.name())]);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+use crate::auth::Principal;
+use crate::leases::JobLeases;
+use crate::models::{UsageCounts, UsageKey};
+use crate::rate_limit;
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, DurationRound, Utc};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, Instrument};
+use uuid::Uuid;
+
+/// Client, endpoint and hour combinations held between flushes. Past this, new clients are
+/// counted as `other`, so a flood of addresses can't grow memory without bound.
+const MAX_PENDING: usize = 10_000;
+
+/// Endpoints listed for each client in the report.
+const TOP_ENDPOINTS: usize = 5;
+
+#[derive(Debug, Serialize)]
+pub struct EndpointUsage {
+    pub method: String,
+    pub route: String,
+    pub requests: u64,
+    pub errors: u64,
+}
+
+/// One client's requests over the report's hours.
+#[derive(Debug, Serialize)]
+pub struct ClientUsage {
+    /// `token:<id>`, `user:<id>` or `ip:<address>`.
+    pub client: String,
+    pub user_id: Option<Uuid>,
+    pub requests: u64,
+    pub client_errors: u64,
+    pub server_errors: u64,
+    /// The share of requests answered with a `4xx` or `5xx`.
+    pub error_rate: f64,
+    /// The client's busiest endpoints, busiest first.
+    pub top_endpoints: Vec<EndpointUsage>,
+}
+
+/// What `GET /admin/usage` returns: the busiest clients, busiest first.
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub since: DateTime<Utc>,
+    pub clients: Vec<ClientUsage>,
+}
+
+/// Counts requests per client and endpoint, so operators can find noisy or abusive clients.
+/// Counts are kept in memory and added to an hourly rollup table on every flush.
+pub struct UsageTracker {
+    repository: Arc<SqliteTodoRepository>,
+    pending: Mutex<HashMap<(DateTime<Utc>, UsageKey), UsageCounts>>,
+}
+
+impl UsageTracker {
+    pub fn new(repository: Arc<SqliteTodoRepository>) -> Self {
+        Self {
+            repository,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, mut key: UsageKey, status: StatusCode) {
+        let now = self.repository.clock().now();
+        let hour = now.duration_trunc(chrono::Duration::hours(1)).unwrap_or(now);
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= MAX_PENDING && !pending.contains_key(&(hour, key.clone())) {
+            key.client = "other".to_owned();
+            key.user_id = None;
+        }
+        let counts = pending.entry((hour, key)).or_default();
+        counts.requests += 1;
+        if status.is_client_error() {
+            counts.client_errors += 1;
+        } else if status.is_server_error() {
+            counts.server_errors += 1;
+        }
+    }
+
+    /// Writes the counts since the last flush. If that fails they are kept for the next one.
+    pub async fn flush(&self) -> Result<(), RepositoryError> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let usage: Vec<_> = pending
+            .into_iter()
+            .map(|((hour, key), counts)| (hour, key, counts))
+            .collect();
+        if let Err(e) = self.repository.record_usage(&usage).await {
+            let mut pending = self.pending.lock().unwrap();
+            for (hour, key, counts) in usage {
+                let merged = pending.entry((hour, key)).or_default();
+                merged.requests += counts.requests;
+                merged.client_errors += counts.client_errors;
+                merged.server_errors += counts.server_errors;
+            }
+            return Err(e);
+        }
+        debug!(rows = usage.len(), "Usage flushed");
+        Ok(())
+    }
+
+    /// The `limit` busiest clients over the last `hours` hours, including the current one.
+    pub async fn report(&self, hours: u32, limit: usize) -> Result<UsageReport, RepositoryError> {
+        self.flush().await?;
+        let now = self.repository.clock().now();
+        let since = now.duration_trunc(chrono::Duration::hours(1)).unwrap_or(now)
+            - chrono::Duration::hours(i64::from(hours.saturating_sub(1)));
+
+        let mut by_client: HashMap<String, ClientUsage> = HashMap::new();
+        for (key, counts) in self.repository.usage_since(since).await? {
+            let client = by_client.entry(key.client.clone()).or_insert_with(|| ClientUsage {
+                client: key.client,
+                user_id: key.user_id,
+                requests: 0,
+                client_errors: 0,
+                server_errors: 0,
+                error_rate: 0.0,
+                top_endpoints: Vec::new(),
+            });
+            client.requests += counts.requests;
+            client.client_errors += counts.client_errors;
+            client.server_errors += counts.server_errors;
+            client.top_endpoints.push(EndpointUsage {
+                method: key.method,
+                route: key.route,
+                requests: counts.requests,
+                errors: counts.client_errors + counts.server_errors,
+            });
+        }
+
+        let mut clients: Vec<ClientUsage> = by_client.into_values().collect();
+        clients.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.client.cmp(&b.client)));
+        clients.truncate(limit);
+        for client in &mut clients {
+            let errors = client.client_errors + client.server_errors;
+            client.error_rate = errors as f64 / client.requests.max(1) as f64;
+            client.top_endpoints.sort_by_key(|endpoint| std::cmp::Reverse(endpoint.requests));
+            client.top_endpoints.truncate(TOP_ENDPOINTS);
+        }
+        Ok(UsageReport { since, clients })
+    }
+}
+
+/// Spawns the job that flushes counts every `interval` and drops hours older than `retention`.
+/// Every replica flushes its own counts; only the one holding the `usage_prune` lease prunes.
+pub fn spawn_flush_job(
+    tracker: Arc<UsageTracker>,
+    leases: Arc<JobLeases>,
+    interval: Duration,
+    retention: Option<Duration>,
+) -> JoinHandle<()> {
+    info!(interval_secs = interval.as_secs(), "Starting usage tracking");
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            let span = tracing::debug_span!("usage_flush");
+            if let Err(e) = tracker.flush().instrument(span.clone()).await {
+                error!(error = %e, "Failed to flush usage");
+            }
+            let retention = retention.and_then(|r| chrono::Duration::from_std(r).ok());
+            if let Some(retention) = retention {
+                if !leases.acquire("usage_prune", interval * 2).await {
+                    continue;
+                }
+                if let Err(e) = tracker.repository.prune_usage(tracker.repository.clock().now() - retention).instrument(span).await {
+                    error!(error = %e, "Failed to prune usage");
+                }
+            }
+        }
+    })
+}
+
+/// Counts every request but health checks and metrics scrapes. Must sit outside
+/// authentication, which leaves the `Principal` on the response, and rate limiting, so
+/// refused requests are counted too.
+pub async fn track_usage(State(tracker): State<Arc<UsageTracker>>, req: Request, next: Next) -> Response {
+    if matches!(req.uri().path(), "/health" | "/metrics") {
+        return next.run(req).await;
+    }
+
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let anonymous = rate_limit::peer_key(peer);
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_owned(), |path| path.as_str().to_owned());
+    let method = req.method().to_string();
+
+    let response = next.run(req).await;
+
+    let (client, user_id) = match response.extensions().get::<Principal>() {
+        Some(principal) => {
+            let client = match principal.api_token_id {
+                Some(token_id) => format!("token:{token_id}"),
+                None => format!("user:{}", principal.user.id),
+            };
+            (client, Some(principal.user.id))
+        }
+        None => (anonymous, None),
+    };
+    tracker.record(UsageKey { client, user_id, method, route }, response.status());
+    response
+}
@@ -0,0 +1,195 @@
+use crate::models::{ActivityItem, AuditEntry, ExportJob, Todo, User, UserExport};
+use crate::repository::{RepositoryError, SqliteTodoRepository, TodoCursor, TodoRepository};
+use std::{collections::HashSet, path::PathBuf, sync::Arc};
+use tracing::{error, info, instrument, Instrument};
+use uuid::Uuid;
+
+/// Accounts with more audit entries than this get their export written to a file in the
+/// background instead of in the response.
+const INLINE_ACTIVITY_LIMIT: u64 = 1000;
+const ACTIVITY_PAGE_SIZE: u32 = 1000;
+const TODO_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("Repository error: {0}")]
+    Repository(#[from] RepositoryError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Serialization error: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
+
+pub enum ExportOutcome {
+    Inline(Box<UserExport>),
+    Started(ExportJob),
+}
+
+/// Where an export file stands, as far as its owner can tell.
+pub enum ExportFile {
+    Pending,
+    Ready(PathBuf),
+    Missing,
+}
+
+/// Collects everything stored about an account into one JSON document. Small exports are
+/// returned directly; large ones are written to `EXPORT_DIR` as `<user>-<export>.json`,
+/// replacing that user's earlier exports.
+pub struct UserExporter {
+    repository: Arc<SqliteTodoRepository>,
+    export_dir: PathBuf,
+}
+
+impl UserExporter {
+    pub fn new(repository: Arc<SqliteTodoRepository>, export_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            repository,
+            export_dir: export_dir.into(),
+        }
+    }
+
+    #[instrument(skip_all, fields(user.id = %user.id, export.inline))]
+    pub async fn export(self: &Arc<Self>, user: &User) -> Result<ExportOutcome, ExportError> {
+        let activity = self.repository.count_user_audit(user.id, &user.email).await?;
+        let inline = activity <= INLINE_ACTIVITY_LIMIT;
+        tracing::Span::current().record("export.inline", inline);
+        if inline {
+            return Ok(ExportOutcome::Inline(Box::new(self.collect(user).await?)));
+        }
+
+        tokio::fs::create_dir_all(&self.export_dir).await?;
+        self.remove_exports(user.id).await?;
+        let id = Uuid::new_v4();
+        // Claim the name before answering, so polling sees `pending` rather than a 404
+        tokio::fs::write(self.partial_path(user.id, id), b"").await?;
+
+        let exporter = self.clone();
+        let user = user.clone();
+        tokio::spawn(
+            async move {
+                if let Err(e) = exporter.write_export(&user, id).await {
+                    error!(error = %e, "User export failed");
+                    let _ = tokio::fs::remove_file(exporter.partial_path(user.id, id)).await;
+                }
+            }
+            .instrument(tracing::Span::current()),
+        );
+        info!(export.id = %id, activity, "User export started in the background");
+        Ok(ExportOutcome::Started(ExportJob {
+            id,
+            status: "pending",
+            download_url: format!("/users/me/exports/{id}"),
+        }))
+    }
+
+    pub async fn file(&self, user_id: Uuid, id: Uuid) -> Result<ExportFile, ExportError> {
+        let path = self.path(user_id, id);
+        if tokio::fs::try_exists(&path).await? {
+            Ok(ExportFile::Ready(path))
+        } else if tokio::fs::try_exists(self.partial_path(user_id, id)).await? {
+            Ok(ExportFile::Pending)
+        } else {
+            Ok(ExportFile::Missing)
+        }
+    }
+
+    /// Deletes the user's export files, finished or not.
+    pub async fn remove_exports(&self, user_id: Uuid) -> Result<(), ExportError> {
+        let mut entries = match tokio::fs::read_dir(&self.export_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let prefix = format!("{user_id}-");
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn collect(&self, user: &User) -> Result<UserExport, ExportError> {
+        let mut activity = Vec::new();
+        loop {
+            let after = activity.last().map_or(0, |entry: &AuditEntry| entry.id);
+            let page = self
+                .repository
+                .user_audit(user.id, &user.email, after, ACTIVITY_PAGE_SIZE)
+                .await?;
+            let done = page.len() < ACTIVITY_PAGE_SIZE as usize;
+            activity.extend(page);
+            if done {
+                break;
+            }
+        }
+
+        Ok(UserExport {
+            generated_at: self.repository.clock().now(),
+            user: user.clone(),
+            api_tokens: self.repository.all_api_tokens(user.id).await?,
+            sessions: self.repository.list_sessions(user.id).await?,
+            activity,
+            todos: self.visible_todos(user).await?,
+            comments: self.repository.comments_by(user.id).await?,
+            mentions: self.mentions(user).await?,
+            attachments: self.repository.attachments_by(user.id).await?,
+        })
+    }
+
+    /// The todos `GET /todos` shows the user, a page at a time.
+    async fn visible_todos(&self, user: &User) -> Result<Vec<Todo>, ExportError> {
+        let projects: HashSet<_> = self
+            .repository
+            .list_projects(user.id)
+            .await?
+            .into_iter()
+            .map(|project| project.id)
+            .collect();
+        let mut todos = Vec::new();
+        loop {
+            let after = todos.last().map(TodoCursor::after);
+            let page = self.repository.list_page(after, TODO_PAGE_SIZE, Some(&projects)).await?;
+            todos.extend(page.todos);
+            if !page.has_more {
+                return Ok(todos);
+            }
+        }
+    }
+
+    async fn mentions(&self, user: &User) -> Result<Vec<ActivityItem>, ExportError> {
+        let mut mentions = Vec::new();
+        loop {
+            let before = mentions.last().map(|item: &ActivityItem| item.id);
+            let page = self
+                .repository
+                .activity_feed(user.id, before, ACTIVITY_PAGE_SIZE)
+                .await?;
+            let done = page.len() < ACTIVITY_PAGE_SIZE as usize;
+            mentions.extend(page);
+            if done {
+                return Ok(mentions);
+            }
+        }
+    }
+
+    /// Writes to the `.partial` file and renames it, so a half-written export is never served.
+    async fn write_export(&self, user: &User, id: Uuid) -> Result<(), ExportError> {
+        let export = self.collect(user).await?;
+        let partial = self.partial_path(user.id, id);
+        tokio::fs::write(&partial, serde_json::to_vec(&export)?).await?;
+        tokio::fs::rename(&partial, self.path(user.id, id)).await?;
+        info!(export.id = %id, activity = export.activity.len(), "User export ready");
+        Ok(())
+    }
+
+    fn path(&self, user_id: Uuid, id: Uuid) -> PathBuf {
+        self.export_dir.join(format!("{user_id}-{id}.json"))
+    }
+
+    fn partial_path(&self, user_id: Uuid, id: Uuid) -> PathBuf {
+        self.export_dir.join(format!("{user_id}-{id}.json.partial"))
+    }
+}
@@ -0,0 +1,81 @@
+use crate::custom_fields::{CustomFieldError, CustomFields};
+use crate::description_limits::{DescriptionLimits, DescriptionTooLarge};
+use crate::models::{CreateTodoRequest, UpdateTodoRequest};
+use crate::repository::RepositoryError;
+use serde_json::Value;
+use std::{collections::BTreeMap, sync::Arc};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    #[error("title must not be empty")]
+    EmptyTitle,
+
+    #[error(transparent)]
+    DescriptionTooLarge(#[from] DescriptionTooLarge),
+
+    #[error(transparent)]
+    CustomFields(CustomFieldError),
+
+    /// The custom field definitions couldn't be loaded, so nothing was checked.
+    #[error(transparent)]
+    Repository(RepositoryError),
+}
+
+impl From<CustomFieldError> for ValidationError {
+    fn from(e: CustomFieldError) -> Self {
+        match e {
+            CustomFieldError::Repository(e) => Self::Repository(e),
+            e => Self::CustomFields(e),
+        }
+    }
+}
+
+/// The checks every write of a todo goes through, whichever route or protocol it came in
+/// on: a title that isn't blank, a description within `DESCRIPTION_MAX_BYTES`, and custom
+/// field values that fit their definitions. A write refused here is refused everywhere,
+/// dry runs included.
+pub struct TodoValidator {
+    descriptions: Arc<DescriptionLimits>,
+    custom_fields: Arc<CustomFields>,
+}
+
+impl TodoValidator {
+    pub fn new(descriptions: Arc<DescriptionLimits>, custom_fields: Arc<CustomFields>) -> Self {
+        Self { descriptions, custom_fields }
+    }
+
+    /// `source` says where the write came from, as for `DescriptionLimits::check`.
+    pub async fn check_create(&self, request: &CreateTodoRequest, source: &'static str) -> Result<(), ValidationError> {
+        self.check_text(Some(&request.title), request.description.as_deref(), source)?;
+        self.check_custom_fields(&request.custom_fields, true).await
+    }
+
+    /// Only the fields the update sets are checked.
+    pub async fn check_update(&self, request: &UpdateTodoRequest, source: &'static str) -> Result<(), ValidationError> {
+        self.check_text(request.title.as_deref(), request.description.as_deref(), source)?;
+        match &request.custom_fields {
+            Some(values) => self.check_custom_fields(values, false).await,
+            None => Ok(()),
+        }
+    }
+
+    /// The title, when one is set, and the description. Imports check each row with this
+    /// and their custom fields once for the whole upload, as no row can set any.
+    pub fn check_text(
+        &self,
+        title: Option<&str>,
+        description: Option<&str>,
+        source: &'static str,
+    ) -> Result<(), ValidationError> {
+        if title.is_some_and(|title| title.trim().is_empty()) {
+            return Err(ValidationError::EmptyTitle);
+        }
+        self.descriptions.check(description, source)?;
+        Ok(())
+    }
+
+    /// See `CustomFields::validate`.
+    pub async fn check_custom_fields(&self, values: &BTreeMap<String, Value>, creating: bool) -> Result<(), ValidationError> {
+        Ok(self.custom_fields.validate(values, creating).await?)
+    }
+}
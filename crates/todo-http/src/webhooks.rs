@@ -0,0 +1,282 @@
+use crate::cloud_events::CloudEvent;
+use crate::models::{CreateWebhookRequest, Todo, WebhookEvent, WebhookFormat, WebhookSubscription};
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+};
+use tracing::info;
+use uuid::Uuid;
+
+/// Todo fields each webhook API version carries, oldest first. A version's list never
+/// changes: new fields go in a new version, so receivers pinned to an older one keep
+/// getting exactly what they were written against.
+const API_VERSIONS: [&[&str]; 3] = [
+    &["id", "title", "description", "completed", "created_at", "updated_at"],
+    &[
+        "id",
+        "title",
+        "description",
+        "completed",
+        "due_at",
+        "tags",
+        "estimate_minutes",
+        "expires_at",
+        "project_id",
+        "created_at",
+        "updated_at",
+        "blocked",
+        "version",
+    ],
+    &[
+        "id",
+        "title",
+        "description",
+        "completed",
+        "due_at",
+        "tags",
+        "estimate_minutes",
+        "expires_at",
+        "project_id",
+        "created_at",
+        "updated_at",
+        "blocked",
+        "version",
+        "change_seq",
+    ],
+];
+
+pub const LATEST_API_VERSION: u32 = API_VERSIONS.len() as u32;
+
+/// `source` of every CloudEvents envelope.
+const EVENT_SOURCE: &str = "/todos";
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("Webhook URLs must be absolute http or https URLs")]
+    InvalidUrl,
+
+    #[error("Webhook host {0} can't be resolved")]
+    UnresolvableHost(String),
+
+    #[error("Webhook URLs can't point at loopback, link-local or private addresses")]
+    PrivateAddress,
+
+    #[error("A webhook must subscribe to at least one event")]
+    NoEvents,
+
+    #[error("Webhook API versions are 1 to {LATEST_API_VERSION}")]
+    UnsupportedVersion,
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+/// Webhook receivers registered at runtime, each with its own events and payload shape.
+/// The `webhook` notification channel delivers to them alongside the `WEBHOOK_*_URL`s.
+pub struct WebhookSubscriptions {
+    repository: Arc<SqliteTodoRepository>,
+}
+
+impl WebhookSubscriptions {
+    pub fn new(repository: Arc<SqliteTodoRepository>) -> Self {
+        Self { repository }
+    }
+
+    /// Refuses URLs that resolve to an address inside the network the server runs in, so a
+    /// subscription can't turn the server into a proxy for internal services.
+    pub async fn create(&self, request: CreateWebhookRequest) -> Result<WebhookSubscription, WebhookError> {
+        let url = request.url.trim();
+        let parsed = reqwest::Url::parse(url)
+            .ok()
+            .filter(|u| matches!(u.scheme(), "http" | "https") && u.host_str().is_some())
+            .ok_or(WebhookError::InvalidUrl)?;
+        check_public(&parsed).await?;
+        let mut events = Vec::with_capacity(request.events.len());
+        for event in request.events {
+            if !events.contains(&event) {
+                events.push(event);
+            }
+        }
+        if events.is_empty() {
+            return Err(WebhookError::NoEvents);
+        }
+        let api_version = request.api_version.unwrap_or(LATEST_API_VERSION);
+        if !(1..=LATEST_API_VERSION).contains(&api_version) {
+            return Err(WebhookError::UnsupportedVersion);
+        }
+
+        let webhook = WebhookSubscription {
+            id: Uuid::new_v4(),
+            url: url.to_owned(),
+            events,
+            format: request.format.unwrap_or_default(),
+            api_version,
+            created_at: self.repository.clock().now(),
+        };
+        self.repository.create_webhook(&webhook).await?;
+        info!(webhook.id = %webhook.id, format = webhook.format.as_str(), "Webhook subscription created");
+        Ok(webhook)
+    }
+
+    pub async fn list(&self) -> Result<Vec<WebhookSubscription>, RepositoryError> {
+        self.repository.list_webhooks().await
+    }
+
+    /// Returns false if there was no such subscription.
+    pub async fn remove(&self, id: Uuid) -> Result<bool, RepositoryError> {
+        self.repository.delete_webhook(id).await
+    }
+}
+
+/// Fails unless every address the URL's host resolves to is a public one.
+async fn check_public(url: &reqwest::Url) -> Result<(), WebhookError> {
+    let host = url.host_str().ok_or(WebhookError::InvalidUrl)?;
+    // IPv6 literals come bracketed
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port_or_known_default().ok_or(WebhookError::InvalidUrl)?;
+    let addresses: Vec<IpAddr> = match host.parse::<IpAddr>() {
+        Ok(address) => vec![address],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|_| WebhookError::UnresolvableHost(host.to_owned()))?
+            .map(|address| address.ip())
+            .collect(),
+    };
+    if addresses.is_empty() {
+        return Err(WebhookError::UnresolvableHost(host.to_owned()));
+    }
+    if addresses.iter().any(|address| !is_public(*address)) {
+        return Err(WebhookError::PrivateAddress);
+    }
+    Ok(())
+}
+
+/// Whether `address` is reachable on the public internet, as opposed to loopback,
+/// link-local (cloud metadata services included), private, shared or reserved space.
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(v4) => is_public_v4(v4),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public_v4(v4),
+            None => is_public_v6(v6),
+        },
+    }
+}
+
+fn is_public_v4(address: Ipv4Addr) -> bool {
+    let [a, b, ..] = address.octets();
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_private()
+        || address.is_link_local()
+        || address.is_broadcast()
+        || address.is_multicast()
+        || address.is_documentation()
+        // 100.64.0.0/10, carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        // 0.0.0.0/8 and 240.0.0.0/4
+        || a == 0
+        || a >= 240)
+}
+
+fn is_public_v6(address: Ipv6Addr) -> bool {
+    let first = address.segments()[0];
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_multicast()
+        // fc00::/7, unique local
+        || (first & 0xfe00) == 0xfc00
+        // fe80::/10, link-local
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// The todo as API version `api_version` describes it.
+fn versioned_todo(todo: &Todo, api_version: u32) -> Value {
+    let fields = API_VERSIONS[(api_version.clamp(1, LATEST_API_VERSION) - 1) as usize];
+    let Ok(Value::Object(all)) = serde_json::to_value(todo) else {
+        return Value::Null;
+    };
+    Value::Object(all.into_iter().filter(|(key, _)| fields.contains(&key.as_str())).collect())
+}
+
+/// Shapes one notification for a receiver. `delta` is the event's own payload, with `event`
+/// and `timestamp`; `todo` is the todo it is about, if any and if it still exists.
+pub fn render(
+    format: WebhookFormat,
+    api_version: u32,
+    event: WebhookEvent,
+    delta: &Map<String, Value>,
+    todo: Option<&Todo>,
+) -> Value {
+    let mut body = delta.clone();
+    if format == WebhookFormat::Delta {
+        return Value::Object(body);
+    }
+    if let Some(todo) = todo {
+        body.insert("todo".into(), versioned_todo(todo, api_version));
+    }
+    if format == WebhookFormat::Full {
+        return Value::Object(body);
+    }
+
+    // `time` and `type` take over from `timestamp` and `event`
+    let time = body
+        .remove("timestamp")
+        .and_then(|t| serde_json::from_value(t).ok())
+        .unwrap_or_else(Utc::now);
+    body.remove("event");
+    let subject = body.get("todo_id").and_then(Value::as_str).map(str::to_owned);
+    let envelope = CloudEvent {
+        time,
+        subject,
+        ..CloudEvent::new(EVENT_SOURCE, event.as_str(), Value::Object(body))
+    };
+    serde_json::to_value(envelope).unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(address: &str) -> bool {
+        is_public(address.parse().unwrap())
+    }
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for address in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!public(address), "{address}");
+        }
+    }
+
+    #[test]
+    fn internet_addresses_are_public() {
+        for address in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(public(address), "{address}");
+        }
+    }
+
+    #[tokio::test]
+    async fn urls_naming_internal_hosts_are_refused() {
+        for url in ["http://127.0.0.1:8080/hook", "http://[::1]/hook", "http://localhost/hook"] {
+            let url = reqwest::Url::parse(url).unwrap();
+            assert!(matches!(check_public(&url).await, Err(WebhookError::PrivateAddress)), "{url}");
+        }
+    }
+}
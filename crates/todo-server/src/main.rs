@@ -0,0 +1,503 @@
+use std::{net::SocketAddr, path::Path, sync::Arc};
+use todo_domain::clock;
+use todo_http::{
+    analytics::Analytics,
+    attachments::AttachmentService,
+    auth::AuthService,
+    config::{BlobStoreConfig, Config, RuntimeProfile},
+    custom_fields::CustomFields,
+    dav::CalDavNames,
+    deadline::DeadlineRepository,
+    description_limits::DescriptionLimits,
+    digest, expiry, feature_flags,
+    erasure::ErasureService,
+    events::{PublishingRepository, TodoEvents},
+    feature_flags::FeatureFlags,
+    integrations::InboundIntegrations,
+    jira::{self, JiraSync},
+    leases::JobLeases,
+    maintenance,
+    mcp::{self, McpServer},
+    mentions::MentionService,
+    presence::Presence,
+    metrics::{self, MeteredRepository},
+    notification_channels, notification_worker,
+    query_cache::QueryCache,
+    query_profile::{QueryProfiler, QueryTiming},
+    resilience::{self, RetryingRepository},
+    retention::{self, Retention},
+    projects::ProjectService,
+    sync::SyncService,
+    tags::TagService,
+    telemetry,
+    tenancy::TenantRoutedRepository,
+    upload_scan::ScanPipeline,
+    usage::{self, UsageTracker},
+    user_export::UserExporter,
+    validation::TodoValidator,
+    webhooks::WebhookSubscriptions,
+    AppState,
+};
+use todo_storage::{
+    backup::{self, BackupService, CheckStatus},
+    blob_store::{BlobStore, LocalBlobStore, S3BlobStore, S3Config},
+    crypto::FieldCipher,
+    data_migration,
+    latency::LatencyProfile,
+    redact,
+    repository::{SqliteTodoRepository, TodoRepository},
+    tenants::TenantDatabases,
+};
+use tracing::{error, info};
+
+#[tokio::main]
+async fn main() {
+    // `todo mcp` serves MCP on stdio instead of HTTP, `todo migrate-data <postgres-url>`
+    // copies the database to Postgres and exits, `todo verify-backup <path>` checks a
+    // snapshot and exits, and `todo grant-admin <email>` / `todo revoke-admin <email>` change
+    // an account's `admin` scope and exit. `--profile` picks the storage and defaults.
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut profile = std::env::var("APP_PROFILE").ok();
+    if let Some(i) = args.iter().position(|arg| arg == "--profile") {
+        if i + 1 < args.len() {
+            profile = Some(args.remove(i + 1));
+            args.remove(i);
+        }
+    }
+    let profile: RuntimeProfile = profile
+        .map(|p| p.parse().unwrap_or_else(|e| panic!("Invalid profile: {e}")))
+        .unwrap_or_default();
+    let read_only = match args.iter().position(|arg| arg == "--read-only") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (mcp_stdio, migrate_target, verify_path, admin_change) = match args[..] {
+        [] => (false, None, None, None),
+        ["mcp"] => (true, None, None, None),
+        ["migrate-data", target] => (false, Some(target), None, None),
+        ["verify-backup", path] => (false, None, Some(path), None),
+        ["grant-admin", email] => (false, None, None, Some((email, true))),
+        ["revoke-admin", email] => (false, None, None, Some((email, false))),
+        _ => {
+            eprintln!(
+                "Usage: todo [--profile memory|demo|sqlite] [--read-only] \
+                 [mcp | migrate-data <postgres-url> | verify-backup <path> | \
+                 grant-admin <email> | revoke-admin <email>]"
+            );
+            std::process::exit(2);
+        }
+    };
+    let mut config = Config::from_env(profile);
+    config.telemetry.log_to_stderr = mcp_stdio;
+    if read_only {
+        config.make_read_only();
+    }
+    redact::init(config.redaction_mode);
+    if let Some(path) = verify_path {
+        let database_key = config.database_key.as_ref().map(|k| k.expose());
+        let restorable = verify_backup(Path::new(path), database_key).await;
+        std::process::exit(if restorable { 0 } else { 1 });
+    }
+
+    telemetry::init_tracing(&config.telemetry).await;
+    let prometheus_registry = telemetry::init_metrics(&config.telemetry);
+    let _runtime_metrics = metrics::register_runtime_metrics();
+
+    // Log what every setting resolved to, so a missing or mistyped variable shows up here
+    let effective_config = Arc::new(config.summary());
+    if let Some(sections) = effective_config.as_object() {
+        for (section, settings) in sections {
+            info!("Config {section}: {settings}");
+        }
+    }
+
+    // Initialize repository. A read-only replica's file is kept up to date from elsewhere, so
+    // it isn't written at all; an in-memory database has nothing to replicate
+    let database_key = config.database_key.as_ref().map(|k| k.expose());
+    let repository = if config.read_only && config.profile == RuntimeProfile::Sqlite {
+        SqliteTodoRepository::new_read_only(&config.database_url, database_key, config.statement_cache_capacity)
+            .await
+    } else {
+        SqliteTodoRepository::new(&config.database_url, database_key, config.statement_cache_capacity).await
+    }
+    .expect("Failed to connect to database");
+
+    let cipher = (!config.description_keys.is_empty()).then(|| {
+        let keys: Vec<(String, String)> = config
+            .description_keys
+            .iter()
+            .map(|(id, key)| (id.clone(), key.expose().to_string()))
+            .collect();
+        let cipher = FieldCipher::new(&config.description_key_id, &keys)
+            .expect("Invalid description encryption key");
+        info!(key_id = %config.description_key_id, "Description encryption enabled");
+        Arc::new(cipher)
+    });
+    if config.latency_profile != LatencyProfile::Off {
+        info!(profile = ?config.latency_profile, "Simulated latency enabled");
+    }
+    let profiler = config
+        .slow_query_window
+        .map(|window| Arc::new(QueryProfiler::new(window)));
+    let observer = Arc::new(QueryTiming::new(profiler.clone()));
+    // Every time handlers, jobs and repositories work with comes from this one clock
+    let clock = clock::system();
+    // Tenant databases get the same cipher, latency, timing and clock as the shared one
+    let latency_profile = config.latency_profile;
+    let repository_clock = clock.clone();
+    let configure = move |mut repository: SqliteTodoRepository| {
+        if let Some(cipher) = &cipher {
+            repository = repository.with_description_cipher(cipher.clone());
+        }
+        if latency_profile != LatencyProfile::Off {
+            repository = repository.with_latency_profile(latency_profile);
+        }
+        repository
+            .with_query_observer(observer.clone())
+            .with_clock(repository_clock.clone())
+    };
+    let repository = Arc::new(configure(repository));
+    let tenants = config.tenants.as_ref().map(|tenants| {
+        info!(dir = %tenants.dir, max_open = tenants.max_open, "Per-tenant databases enabled");
+        Arc::new(TenantDatabases::new(
+            &tenants.dir,
+            tenants.max_open,
+            config.database_key.as_ref().map(|k| k.expose().to_owned()),
+            config.statement_cache_capacity,
+            config.read_only,
+            Box::new(configure),
+        ))
+    });
+    // Replicas only open the tenant databases the primary made
+    if let (Some(databases), Some(tenants)) = (&tenants, &config.tenants) {
+        if !config.read_only {
+            for tenant in tenants.members.keys() {
+                if let Err(e) = databases.provision(tenant).await {
+                    error!(tenant, error = %e, "Failed to provision tenant database");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    info!(profile = ?config.profile, "Storage ready");
+
+    if let Some(target) = migrate_target {
+        if let Err(e) = migrate_data(&repository, tenants.as_deref(), target).await {
+            error!(error = %e, "Data migration failed");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some((email, admin)) = admin_change {
+        match repository.set_user_admin(email, admin).await {
+            Ok(true) => {
+                info!(email, admin, "Admin scope changed");
+                return;
+            }
+            Ok(false) => eprintln!("No account with the email {email}"),
+            Err(e) => error!(error = %e, "Failed to change the admin scope"),
+        }
+        std::process::exit(1);
+    }
+    let routed: Arc<dyn TodoRepository> = match &tenants {
+        Some(_) => Arc::new(TenantRoutedRepository::new(repository.clone())),
+        None => repository.clone(),
+    };
+    let retrying = Arc::new(RetryingRepository::new(routed, config.db_retry));
+
+    let descriptions = Arc::new(DescriptionLimits::new(config.description_warn_bytes, config.description_max_bytes));
+
+    if mcp_stdio {
+        let validator = Arc::new(TodoValidator::new(descriptions, Arc::new(CustomFields::new(repository.clone()))));
+        let server = McpServer::new(Arc::new(MeteredRepository::new(retrying)), validator, None).with_clock(clock);
+        if let Err(e) = mcp::serve_stdio(server).await {
+            error!(error = %e, "MCP stdio transport failed");
+        }
+        return;
+    }
+
+    let blob_store: Arc<dyn BlobStore> = match &config.attachments.store {
+        BlobStoreConfig::Local { dir } => Arc::new(LocalBlobStore::new(dir)),
+        BlobStoreConfig::S3 { endpoint, region, bucket, access_key_id, secret_access_key } => Arc::new(
+            S3BlobStore::new(S3Config {
+                endpoint: endpoint.clone(),
+                region: region.clone(),
+                bucket: bucket.clone(),
+                access_key_id: access_key_id.clone(),
+                secret_access_key: secret_access_key.expose().to_owned(),
+            })
+            .expect("Invalid S3 blob store configuration")
+            .with_clock(clock.clone()),
+        ),
+    };
+    let scanner = Arc::new(ScanPipeline::from_config(&config.attachments.scan));
+    let attachments = Arc::new(AttachmentService::new(
+        repository.clone(),
+        blob_store,
+        scanner,
+        &config.attachments,
+    ));
+
+    // Scheduled jobs run on whichever replica holds their lease
+    let leases = Arc::new(JobLeases::new(repository.clone(), &config.instance_id));
+
+    // Schedule background database maintenance
+    if let Some(interval) = config.maintenance_interval {
+        maintenance::spawn_maintenance_job(
+            repository.clone(),
+            leases.clone(),
+            interval,
+            config.sync_tombstone_retention,
+        );
+    }
+    let retention = Arc::new(Retention::new(repository.clone(), attachments.clone(), config.retention));
+    let expiry_job = config.expiry_sweep_interval.map(|interval| {
+        // Attachments under a retention rule are left for the retention job
+        let orphans = (!retention.governs_attachments()).then(|| attachments.clone());
+        expiry::spawn_expiry_job(repository.clone(), orphans, leases.clone(), interval)
+    });
+    if let Some(interval) = config.retention.interval {
+        retention::spawn_retention_job(retention.clone(), leases.clone(), interval);
+    }
+    if let Some(interval) = config.db_health_check_interval {
+        resilience::spawn_health_check_job(repository.clone(), interval);
+    }
+
+    let flags = Arc::new(
+        FeatureFlags::load(repository.clone())
+            .await
+            .expect("Failed to load feature flags"),
+    );
+    if let Some(interval) = config.feature_flag_refresh {
+        feature_flags::spawn_refresh_job(flags.clone(), interval);
+    }
+
+    // Initialize services
+    let notification_service = notification_channels::build_notification_service(
+        &config.notifications,
+        config.latency_profile,
+        Some(flags.clone()),
+        repository.clone(),
+    )
+    .expect("Invalid notification channel configuration");
+    let (notifications, notification_workers) = notification_worker::spawn_notification_workers(
+        notification_service,
+        repository.clone(),
+        &config.notifications,
+        &config.instance_id,
+    );
+    let backup_service = BackupService::new(repository.clone(), &config.backup_dir);
+    let analytics = Arc::new(Analytics::new(repository.clone(), config.stats_cache_ttl));
+    let events = TodoEvents::new();
+    let presence = Presence::new();
+    let query_cache = Arc::new(QueryCache::new(
+        &events,
+        config.query_cache_ttl,
+        config.query_cache_max_entries,
+    ));
+    let custom_fields = Arc::new(CustomFields::new(repository.clone()));
+    let validator = Arc::new(TodoValidator::new(descriptions, custom_fields.clone()));
+    let tags = Arc::new(TagService::new(repository.clone(), events.clone()));
+    let projects = Arc::new(ProjectService::new(repository.clone(), notifications.clone()));
+    let mentions = Arc::new(MentionService::new(repository.clone(), projects.clone(), notifications.clone()));
+    let sync = Arc::new(SyncService::new(
+        repository.clone(),
+        events.clone(),
+        validator.clone(),
+        mentions.clone(),
+    ));
+    let webhooks = Arc::new(WebhookSubscriptions::new(repository.clone()));
+    let integrations = Arc::new(InboundIntegrations::new(
+        &config.inbound_sources,
+        repository.clone(),
+        validator.clone(),
+        notifications.clone(),
+    ));
+    let caldav = Arc::new(CalDavNames::new(repository.clone()));
+    let jira = config.jira.as_ref().map(|jira| {
+        Arc::new(JiraSync::new(jira, repository.clone(), notifications.clone()).expect("Failed to build Jira client"))
+    });
+    let digest_job = config
+        .digest
+        .map(|digest| digest::spawn_digest_job(repository.clone(), notifications.clone(), leases.clone(), digest));
+
+    let auth = config
+        .auth
+        .as_ref()
+        .map(|auth| Arc::new(AuthService::new(repository.clone(), auth)));
+
+    let user_export = auth
+        .as_ref()
+        .map(|_| Arc::new(UserExporter::new(repository.clone(), &config.export_dir)));
+    let erasure = user_export
+        .as_ref()
+        .map(|exporter| Arc::new(ErasureService::new(repository.clone(), exporter.clone())));
+    if let Some(erasure) = erasure.as_ref().filter(|_| !config.read_only) {
+        if let Err(e) = erasure.resume().await {
+            error!(error = %e, "Failed to resume account erasures");
+        }
+    }
+
+    let usage = config.usage_flush_interval.map(|interval| {
+        let tracker = Arc::new(UsageTracker::new(repository.clone()));
+        usage::spawn_flush_job(tracker.clone(), leases.clone(), interval, config.usage_retention);
+        tracker
+    });
+
+    // Inside the metrics decorator, so calls cut short by a request's deadline count as errors
+    let deadlines = Arc::new(DeadlineRepository::new(retrying));
+    let repository = Arc::new(PublishingRepository::new(deadlines, events.clone()));
+    let repository: Arc<dyn TodoRepository> = Arc::new(MeteredRepository::new(repository));
+    let mcp = Arc::new(
+        McpServer::new(repository.clone(), validator.clone(), Some(notifications.clone()))
+            .with_clock(clock.clone())
+            .with_mentions(mentions.clone()),
+    );
+    // Polling writes through the decorated repository, so changes reach the event stream
+    let jira_poll = config.jira.as_ref().and_then(|jira| jira.poll_interval);
+    if let (Some(jira), Some(interval)) = (&jira, jira_poll) {
+        jira::spawn_poll_job(jira.clone(), repository.clone(), leases.clone(), interval);
+    }
+    let state = AppState {
+        repository,
+        notifications,
+        events: events.clone(),
+        backup_service: Arc::new(backup_service),
+        auth: auth.clone(),
+        user_export,
+        erasure,
+        flags,
+        analytics,
+        query_cache,
+        sync,
+        projects,
+        mentions,
+        webhooks,
+        custom_fields,
+        tags,
+        validator,
+        attachments,
+        retention,
+        integrations,
+        caldav,
+        jira,
+        profiler,
+        usage: usage.clone(),
+        tenants,
+        effective_config,
+        inbox_weights: config.inbox_weights,
+        mcp,
+        presence: presence.clone(),
+        prometheus_registry,
+        clock,
+    };
+
+    let app = todo_http::router(state, &config);
+
+    let addr = config.bind_address;
+    info!("🚀 Server starting on http://{}", addr);
+
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .expect("Failed to bind to address");
+
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            events.close();
+            presence.close();
+        })
+        .await
+        .expect("Server failed to start");
+
+    // The router (and every queue handle in its state) is gone, so workers exit once drained
+    if let Some(digest_job) = digest_job {
+        digest_job.abort();
+    }
+    // Waited for, so no sweep is still running once its lease is released to another replica
+    if let Some(expiry_job) = expiry_job {
+        expiry_job.abort();
+        let _ = expiry_job.await;
+    }
+    notification_workers.drain(config.notifications.drain_timeout).await;
+    leases.release_all().await;
+    if let Some(usage) = usage {
+        if let Err(e) = usage.flush().await {
+            error!(error = %e, "Failed to flush usage");
+        }
+    }
+}
+
+/// Copies the shared database into Postgres, then each tenant's database into a schema of
+/// its own, `tenant_<id>`.
+async fn migrate_data(
+    repository: &SqliteTodoRepository,
+    tenants: Option<&TenantDatabases>,
+    target: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    data_migration::migrate_to_postgres(repository.pool(), target, None).await?;
+    let Some(tenants) = tenants else {
+        return Ok(());
+    };
+    for tenant in tenants.tenants()? {
+        info!(tenant, "Migrating tenant database");
+        let database = tenants.repository(&tenant).await?;
+        let schema = format!("tenant_{tenant}");
+        data_migration::migrate_to_postgres(database.pool(), target, Some(&schema)).await?;
+    }
+    Ok(())
+}
+
+/// Prints each check `verify_snapshot` made, then whether the snapshot is restorable.
+async fn verify_backup(path: &Path, database_key: Option<&str>) -> bool {
+    let verification = match backup::verify_snapshot(path, database_key).await {
+        Ok(verification) => verification,
+        Err(e) => {
+            eprintln!("{}: {e}", path.display());
+            return false;
+        }
+    };
+    for check in &verification.checks {
+        let status = match check.status {
+            CheckStatus::Passed => "ok",
+            CheckStatus::Skipped => "skip",
+            CheckStatus::Failed => "FAIL",
+        };
+        println!("{status:<5} {:<15} {}", check.name, check.detail);
+    }
+    let restorable = verification.restorable();
+    if restorable {
+        println!("{} is restorable", path.display());
+    } else {
+        println!("{} is NOT restorable", path.display());
+    }
+    restorable
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutdown signal received, stopping server");
+}
\ No newline at end of file
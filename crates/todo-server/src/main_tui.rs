@@ -0,0 +1,431 @@
+//! Interactive terminal client for the todo API (`todo-tui`).
+//!
+//! Talks to a running `todo` server (`TODO_API_URL`, default `http://127.0.0.1:3000`,
+//! with an optional `TODO_API_TOKEN` bearer) and follows `GET /todos/events` so changes made
+//! elsewhere show up straight away.
+
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use uuid::Uuid;
+
+/// How long to wait before reconnecting to the event stream.
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Deserialize)]
+struct Todo {
+    id: Uuid,
+    title: String,
+    completed: bool,
+    due_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    blocked: bool,
+}
+
+/// The `data` of a `GET /todos/events` message.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RemoteEvent {
+    Created { todo: Todo },
+    Updated { todo: Todo },
+    Deleted { id: Uuid },
+    Resync,
+}
+
+enum Message {
+    Key(KeyEvent),
+    Loaded(Vec<Todo>),
+    Remote(RemoteEvent),
+    Status(String),
+}
+
+#[derive(Clone)]
+struct Api {
+    client: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl Api {
+    fn from_env() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: std::env::var("TODO_API_URL")
+                .unwrap_or_else(|_| "http://127.0.0.1:3000".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            token: std::env::var("TODO_API_TOKEN").ok().filter(|t| !t.is_empty()),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, format!("{}{path}", self.base_url));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<Todo>, reqwest::Error> {
+        self.request(reqwest::Method::GET, "/todos")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn create(&self, title: &str) -> Result<Todo, reqwest::Error> {
+        self.request(reqwest::Method::POST, "/todos")
+            .json(&serde_json::json!({ "title": title }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn set_completed(&self, id: Uuid, completed: bool) -> Result<Todo, reqwest::Error> {
+        self.request(reqwest::Method::PUT, &format!("/todos/{id}"))
+            .json(&serde_json::json!({ "completed": completed }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<(), reqwest::Error> {
+        self.request(reqwest::Method::DELETE, &format!("/todos/{id}"))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Forwards server-sent events until the connection drops.
+    async fn follow_events(&self, tx: &UnboundedSender<Message>) -> Result<(), reqwest::Error> {
+        let response = self
+            .request(reqwest::Method::GET, "/todos/events")
+            .send()
+            .await?
+            .error_for_status()?;
+        // Anything missed while disconnected is picked up by reloading
+        let _ = tx.send(Message::Remote(RemoteEvent::Resync));
+        let _ = tx.send(Message::Status("Live updates on".to_string()));
+
+        let mut body = response.bytes_stream();
+        // Bytes, so a character split across two chunks is only decoded once it's whole
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = body.next().await {
+            buffer.extend_from_slice(&chunk?);
+            while let Some(end) = buffer.windows(2).position(|pair| pair == b"\n\n") {
+                let message: Vec<u8> = buffer.drain(..end + 2).collect();
+                let data: String = String::from_utf8_lossy(&message)
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data:"))
+                    .map(str::trim_start)
+                    .collect();
+                if let Ok(event) = serde_json::from_str::<RemoteEvent>(&data) {
+                    let _ = tx.send(Message::Remote(event));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+enum Mode {
+    Normal,
+    Adding(String),
+    Searching,
+}
+
+struct App {
+    api: Api,
+    tx: UnboundedSender<Message>,
+    todos: Vec<Todo>,
+    list: ListState,
+    mode: Mode,
+    filter: String,
+    status: String,
+    quit: bool,
+}
+
+impl App {
+    fn visible(&self) -> Vec<&Todo> {
+        filtered(&self.todos, &self.filter)
+    }
+
+    fn selected(&self) -> Option<Todo> {
+        let index = self.list.selected()?;
+        self.visible().get(index).map(|todo| (*todo).clone())
+    }
+
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::Key(key) => self.on_key(key),
+            Message::Loaded(todos) => self.todos = todos,
+            Message::Remote(RemoteEvent::Created { todo } | RemoteEvent::Updated { todo }) => {
+                // Our own changes arrive twice, once from the request and once from the stream
+                match self.todos.iter_mut().find(|t| t.id == todo.id) {
+                    Some(existing) => *existing = todo,
+                    None => self.todos.push(todo),
+                }
+            }
+            Message::Remote(RemoteEvent::Deleted { id }) => self.todos.retain(|t| t.id != id),
+            Message::Remote(RemoteEvent::Resync) => self.reload(),
+            Message::Status(status) => self.status = status,
+        }
+        self.clamp_selection();
+    }
+
+    fn clamp_selection(&mut self) {
+        let len = self.visible().len();
+        match self.list.selected() {
+            _ if len == 0 => self.list.select(None),
+            Some(index) if index >= len => self.list.select(Some(len - 1)),
+            None => self.list.select(Some(0)),
+            Some(_) => {}
+        }
+    }
+
+    fn on_key(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        match &mut self.mode {
+            Mode::Adding(title) => match key.code {
+                KeyCode::Enter => {
+                    let title = title.trim().to_string();
+                    self.mode = Mode::Normal;
+                    if !title.is_empty() {
+                        self.spawn(move |api| async move {
+                            api.create(&title).await.map(|todo| RemoteEvent::Created { todo })
+                        });
+                    }
+                }
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Backspace => {
+                    title.pop();
+                }
+                KeyCode::Char(c) => title.push(c),
+                _ => {}
+            },
+            Mode::Searching => match key.code {
+                KeyCode::Enter => self.mode = Mode::Normal,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.mode = Mode::Normal;
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            },
+            Mode::Normal => match key.code {
+                KeyCode::Char('q') => self.quit = true,
+                KeyCode::Esc => self.filter.clear(),
+                KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+                KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                KeyCode::Char('a') => self.mode = Mode::Adding(String::new()),
+                KeyCode::Char('/') => self.mode = Mode::Searching,
+                KeyCode::Char('r') => self.reload(),
+                KeyCode::Char(' ') | KeyCode::Enter => {
+                    if let Some(todo) = self.selected() {
+                        self.spawn(move |api| async move {
+                            api.set_completed(todo.id, !todo.completed)
+                                .await
+                                .map(|todo| RemoteEvent::Updated { todo })
+                        });
+                    }
+                }
+                KeyCode::Char('d') | KeyCode::Delete => {
+                    if let Some(todo) = self.selected() {
+                        self.spawn(move |api| async move {
+                            api.delete(todo.id).await.map(|()| RemoteEvent::Deleted { id: todo.id })
+                        });
+                    }
+                }
+                _ => {}
+            },
+        }
+        self.clamp_selection();
+    }
+
+    /// Runs an API call in the background, applying its result (or showing its error) once
+    /// it returns.
+    fn spawn<F, Fut>(&self, call: F)
+    where
+        F: FnOnce(Api) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = Result<RemoteEvent, reqwest::Error>> + Send,
+    {
+        let api = self.api.clone();
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let message = match call(api).await {
+                Ok(event) => Message::Remote(event),
+                Err(e) => Message::Status(format!("Request failed: {e}")),
+            };
+            let _ = tx.send(message);
+        });
+    }
+
+    fn reload(&self) {
+        let api = self.api.clone();
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let message = match api.list().await {
+                Ok(todos) => Message::Loaded(todos),
+                Err(e) => Message::Status(format!("Failed to load todos: {e}")),
+            };
+            let _ = tx.send(message);
+        });
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list_area, input_area, help_area] = Layout::vertical([
+            Constraint::Min(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let visible = filtered(&self.todos, &self.filter);
+        let items: Vec<ListItem> = visible.iter().map(|todo| todo_line(todo)).collect();
+        let mut title = format!(" Todos ({}/{}) ", visible.len(), self.todos.len());
+        if !self.filter.is_empty() {
+            title.push_str(&format!("matching \"{}\" ", self.filter));
+        }
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let input = match &self.mode {
+            Mode::Adding(title) => Line::from(vec!["New todo: ".bold(), Span::raw(title.as_str())]),
+            Mode::Searching => Line::from(vec!["Search: ".bold(), Span::raw(self.filter.as_str())]),
+            Mode::Normal => Line::from(self.status.as_str()).dim(),
+        };
+        frame.render_widget(Paragraph::new(input), input_area);
+
+        let help = match self.mode {
+            Mode::Normal => "a add  space complete  d delete  / search  r reload  q quit",
+            _ => "enter confirm  esc cancel",
+        };
+        frame.render_widget(Paragraph::new(help).dim(), help_area);
+    }
+}
+
+/// Todos whose title or a tag contains `filter`, ignoring case.
+fn filtered<'a>(todos: &'a [Todo], filter: &str) -> Vec<&'a Todo> {
+    let filter = filter.to_lowercase();
+    todos
+        .iter()
+        .filter(|todo| {
+            filter.is_empty()
+                || todo.title.to_lowercase().contains(&filter)
+                || todo.tags.iter().any(|tag| tag.to_lowercase().contains(&filter))
+        })
+        .collect()
+}
+
+fn todo_line(todo: &Todo) -> ListItem<'_> {
+    let mut spans = vec![
+        Span::raw(if todo.completed { "[x] " } else { "[ ] " }),
+        if todo.completed {
+            Span::raw(todo.title.as_str()).crossed_out().dim()
+        } else {
+            Span::raw(todo.title.as_str())
+        },
+    ];
+    if let Some(due_at) = todo.due_at {
+        spans.push(format!("  due {}", due_at.format("%Y-%m-%d %H:%M")).yellow());
+    }
+    if todo.blocked {
+        spans.push("  blocked".red());
+    }
+    for tag in &todo.tags {
+        spans.push(format!("  #{tag}").cyan());
+    }
+    ListItem::new(Line::from(spans))
+}
+
+async fn run(terminal: &mut DefaultTerminal, api: Api) -> std::io::Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    // crossterm's reads block, so they get a thread of their own
+    let keys = tx.clone();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if let Event::Key(key) = event {
+                if keys.send(Message::Key(key)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let events_api = api.clone();
+    let events_tx = tx.clone();
+    tokio::spawn(async move {
+        loop {
+            let status = match events_api.follow_events(&events_tx).await {
+                Ok(()) => "Live updates ended, reconnecting".to_string(),
+                Err(e) => format!("Live updates unavailable ({e}), retrying"),
+            };
+            if events_tx.send(Message::Status(status)).is_err() {
+                break;
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+
+    let mut app = App {
+        api,
+        tx,
+        todos: Vec::new(),
+        list: ListState::default(),
+        mode: Mode::Normal,
+        filter: String::new(),
+        status: String::new(),
+        quit: false,
+    };
+    app.reload();
+
+    while !app.quit {
+        terminal.draw(|frame| app.draw(frame))?;
+        match rx.recv().await {
+            Some(message) => app.handle(message),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let api = Api::from_env();
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, api).await;
+    ratatui::restore();
+    result
+}
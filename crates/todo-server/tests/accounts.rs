@@ -0,0 +1,45 @@
+//! Checks an account can log in with the password it registered with and no other, and that
+//! an email can only be registered once.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn registered_accounts_log_in_with_their_password_only() {
+    let client = Client::new();
+    let server = Server::start(&client, &[("JWT_SECRET", "account-test-secret-0123456789abcdefgh")]).await;
+    let url = |path: &str| format!("{}{path}", server.base_url);
+    let credentials = json!({ "email": "dev@example.com", "password": "correct horse battery" });
+
+    let registered = client.post(url("/auth/register")).json(&credentials).send().await.unwrap();
+    assert_eq!(registered.status(), StatusCode::CREATED);
+    let registered: Value = registered.json().await.unwrap();
+    assert_eq!(registered["email"], "dev@example.com", "{registered}");
+    assert!(registered.get("password_hash").is_none(), "{registered}");
+
+    let login = client.post(url("/auth/login")).json(&credentials).send().await.unwrap();
+    assert_eq!(login.status(), StatusCode::OK);
+    let session: Value = login.json().await.unwrap();
+    let me = client.get(url("/auth/me")).bearer_auth(session["access_token"].as_str().unwrap());
+    let me: Value = me.send().await.unwrap().json().await.unwrap();
+    assert_eq!(me["id"], registered["id"], "{me}");
+
+    let wrong = json!({ "email": "dev@example.com", "password": "incorrect horse battery" });
+    let login = client.post(url("/auth/login")).json(&wrong).send().await.unwrap();
+    assert_eq!(login.status(), StatusCode::UNAUTHORIZED);
+    let unknown = json!({ "email": "nobody@example.com", "password": "correct horse battery" });
+    let login = client.post(url("/auth/login")).json(&unknown).send().await.unwrap();
+    assert_eq!(login.status(), StatusCode::UNAUTHORIZED);
+
+    let again = json!({ "email": "dev@example.com", "password": "another horse battery" });
+    let duplicate = client.post(url("/auth/register")).json(&again).send().await.unwrap();
+    assert_eq!(duplicate.status(), StatusCode::CONFLICT);
+    // The first account's password still works and the second one's never did
+    let login = client.post(url("/auth/login")).json(&again).send().await.unwrap();
+    assert_eq!(login.status(), StatusCode::UNAUTHORIZED);
+    let login = client.post(url("/auth/login")).json(&credentials).send().await.unwrap();
+    assert_eq!(login.status(), StatusCode::OK);
+}
@@ -0,0 +1,92 @@
+//! Checks the `admin` scope comes only from an operator's `todo grant-admin`, never from
+//! registering a particular email, and that backups, restores, the audit log and API usage
+//! need it even when `AUTH_REQUIRED` is off.
+
+mod common;
+
+use common::{Database, Server};
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn only_an_operator_grants_admin() {
+    let client = Client::new();
+    let database = Database::new();
+    let server = Server::start_on(
+        &client,
+        &database,
+        &[
+            ("JWT_SECRET", "admin-test-secret-0123456789abcdefgh"),
+            ("AUTH_REQUIRED", "true"),
+            // No longer read; registering a listed email must not make an admin
+            ("ADMIN_EMAILS", "root@example.com"),
+            ("AUTH_CACHE_SECS", "0"),
+        ],
+    )
+    .await;
+
+    let credentials = json!({ "email": "Root@Example.com", "password": "correct horse battery" });
+    let register = client.post(format!("{}/auth/register", server.base_url)).json(&credentials);
+    assert_eq!(register.send().await.unwrap().status(), StatusCode::CREATED);
+    let login = client.post(format!("{}/auth/login", server.base_url)).json(&credentials);
+    let session: Value = login.send().await.unwrap().json().await.unwrap();
+    let token = session["access_token"].as_str().unwrap();
+
+    let audit = format!("{}/admin/audit", server.base_url);
+    let status = || async { client.get(&audit).bearer_auth(token).send().await.unwrap().status() };
+    assert_eq!(status().await, StatusCode::FORBIDDEN);
+
+    // Emails match ignoring case, as they do for sign-in
+    database.set_admin("root@example.com", true);
+    assert_eq!(status().await, StatusCode::OK);
+
+    database.set_admin("root@example.com", false);
+    assert_eq!(status().await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn admin_only_routes_need_admin_even_when_auth_is_optional() {
+    let client = Client::new();
+    let database = Database::new();
+    let server = Server::start_on(
+        &client,
+        &database,
+        &[("JWT_SECRET", "admin-test-secret-0123456789abcdefgh"), ("AUTH_CACHE_SECS", "0")],
+    )
+    .await;
+
+    let backup = format!("{}/admin/backup", server.base_url);
+    let restore = format!("{}/admin/restore", server.base_url);
+    assert_eq!(client.post(&backup).send().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    let anonymous = client.post(&restore).json(&json!({ "snapshot": "latest.db" }));
+    assert_eq!(anonymous.send().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    // The audit log and usage name users and their addresses
+    for path in ["/admin/audit", "/admin/usage"] {
+        let anonymous = client.get(format!("{}{path}", server.base_url));
+        assert_eq!(anonymous.send().await.unwrap().status(), StatusCode::UNAUTHORIZED, "{path}");
+    }
+    // Other routes stay open
+    let todos = client.get(format!("{}/todos", server.base_url));
+    assert_eq!(todos.send().await.unwrap().status(), StatusCode::OK);
+
+    let credentials = json!({ "email": "ops@example.com", "password": "correct horse battery" });
+    let register = client.post(format!("{}/auth/register", server.base_url)).json(&credentials);
+    assert_eq!(register.send().await.unwrap().status(), StatusCode::CREATED);
+    let login = client.post(format!("{}/auth/login", server.base_url)).json(&credentials);
+    let session: Value = login.send().await.unwrap().json().await.unwrap();
+    let token = session["access_token"].as_str().unwrap();
+    let status = || async { client.post(&backup).bearer_auth(token).send().await.unwrap().status() };
+    assert_eq!(status().await, StatusCode::FORBIDDEN);
+
+    database.set_admin("ops@example.com", true);
+    assert_eq!(status().await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn backups_are_unavailable_without_authentication() {
+    let client = Client::new();
+    let server = Server::start(&client, &[]).await;
+
+    let backup = client.post(format!("{}/admin/backup", server.base_url));
+    assert_eq!(backup.send().await.unwrap().status(), StatusCode::NOT_FOUND);
+}
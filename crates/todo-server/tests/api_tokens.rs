@@ -0,0 +1,65 @@
+//! Checks an API token lists each of its scopes once, however the request repeated them, and
+//! that it can only do what those scopes allow until it is revoked.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn token_scopes_are_stored_once_each() {
+    let client = Client::new();
+    let server = Server::start(&client, &[("JWT_SECRET", "token-test-secret-0123456789abcdefgh")]).await;
+    let url = |path: &str| format!("{}{path}", server.base_url);
+
+    let credentials = json!({ "email": "dev@example.com", "password": "correct horse battery" });
+    let register = client.post(url("/auth/register")).json(&credentials);
+    assert_eq!(register.send().await.unwrap().status(), StatusCode::CREATED);
+    let login = client.post(url("/auth/login")).json(&credentials);
+    let session: Value = login.send().await.unwrap().json().await.unwrap();
+    let token = session["access_token"].as_str().unwrap();
+
+    // Repeats that aren't next to each other as well as ones that are
+    let scopes = ["todos:read", "todos:write", "todos:read", "todos:write", "todos:write"];
+    let request = json!({ "name": "ci", "scopes": scopes });
+    let created = client.post(url("/auth/tokens")).bearer_auth(token).json(&request).send().await.unwrap();
+    assert_eq!(created.status(), StatusCode::CREATED);
+    let created: Value = created.json().await.unwrap();
+    assert_eq!(created["scopes"], json!(["todos:read", "todos:write"]), "{created}");
+}
+
+#[tokio::test]
+async fn tokens_act_within_their_scopes_until_revoked() {
+    let client = Client::new();
+    let server = Server::start(
+        &client,
+        &[("JWT_SECRET", "token-test-secret-0123456789abcdefgh"), ("AUTH_REQUIRED", "true")],
+    )
+    .await;
+    let url = |path: &str| format!("{}{path}", server.base_url);
+
+    let credentials = json!({ "email": "dev@example.com", "password": "correct horse battery" });
+    let register = client.post(url("/auth/register")).json(&credentials);
+    assert_eq!(register.send().await.unwrap().status(), StatusCode::CREATED);
+    let login = client.post(url("/auth/login")).json(&credentials);
+    let session: Value = login.send().await.unwrap().json().await.unwrap();
+    let session = session["access_token"].as_str().unwrap();
+
+    let request = json!({ "name": "dashboard", "scopes": ["todos:read"] });
+    let created = client.post(url("/auth/tokens")).bearer_auth(session).json(&request).send().await.unwrap();
+    assert_eq!(created.status(), StatusCode::CREATED);
+    let created: Value = created.json().await.unwrap();
+    let token = created["token"].as_str().unwrap();
+    assert!(token.starts_with("tdo_"), "{created}");
+
+    let read = client.get(url("/todos")).bearer_auth(token).send().await.unwrap();
+    assert_eq!(read.status(), StatusCode::OK);
+    let write = client.post(url("/todos")).bearer_auth(token).json(&json!({ "title": "Not allowed" }));
+    assert_eq!(write.send().await.unwrap().status(), StatusCode::FORBIDDEN);
+
+    let revoke = client.delete(url(&format!("/auth/tokens/{}", created["id"].as_str().unwrap())));
+    assert_eq!(revoke.bearer_auth(session).send().await.unwrap().status(), StatusCode::NO_CONTENT);
+    let read = client.get(url("/todos")).bearer_auth(token).send().await.unwrap();
+    assert_eq!(read.status(), StatusCode::UNAUTHORIZED);
+}
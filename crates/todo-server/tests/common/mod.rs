@@ -0,0 +1,127 @@
+//! The `todo` binary as the integration tests run it.
+
+// Each test binary compiles this module and uses only part of it
+#![allow(dead_code)]
+
+use reqwest::Client;
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// The `todo` server, killed when dropped.
+pub struct Server {
+    child: Child,
+    pub base_url: String,
+}
+
+impl Server {
+    /// Starts a fresh in-memory server with `env` on top of a clean environment.
+    pub async fn start(client: &Client, env: &[(&str, &str)]) -> Self {
+        Self::spawn(client, todo(&["--profile", "memory"]), env).await
+    }
+
+    /// Starts a server on `database`'s file, so `todo` commands can change it as it runs.
+    pub async fn start_on(client: &Client, database: &Database, env: &[(&str, &str)]) -> Self {
+        Self::spawn(client, database.todo(&[]), env).await
+    }
+
+    async fn spawn(client: &Client, mut command: Command, env: &[(&str, &str)]) -> Self {
+        // The port is free as the listener is dropped; the server takes it straight after
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("no free port")
+            .port();
+        let child = command
+            .env("BIND_ADDRESS", format!("127.0.0.1:{port}"))
+            .envs(env.iter().copied())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start the todo server");
+        let server = Self {
+            child,
+            base_url: format!("http://127.0.0.1:{port}"),
+        };
+
+        for _ in 0..100 {
+            if client.get(format!("{}/health", server.base_url)).send().await.is_ok() {
+                return server;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("the todo server didn't start within 10 seconds");
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The `todo` binary with `args` on a clean environment.
+fn todo(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_todo"));
+    command
+        .args(args)
+        .env("OTEL_TRACES_EXPORTER", "none")
+        .env("OTEL_METRICS_EXPORTER", "none")
+        .env("RUST_LOG", "error")
+        .env_remove("APP_PROFILE")
+        .env_remove("JWT_SECRET")
+        .env_remove("AUTH_REQUIRED");
+    command
+}
+
+/// A SQLite file in a directory of its own, removed when dropped.
+pub struct Database {
+    dir: PathBuf,
+}
+
+impl Database {
+    pub fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("todo-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+
+    /// The SQLite file, for tests that open it alongside the server.
+    pub fn path(&self) -> PathBuf {
+        self.dir.join("todos.db")
+    }
+
+    /// The `todo` binary with `args`, on this database under the `sqlite` profile.
+    fn todo(&self, args: &[&str]) -> Command {
+        let mut command = todo(&["--profile", "sqlite"]);
+        command
+            .args(args)
+            .env("DATABASE_URL", format!("sqlite://{}?mode=rwc", self.path().display()))
+            .env("BACKUP_DIR", self.dir.join("backups"))
+            .env("EXPORT_DIR", self.dir.join("exports"))
+            .env("ATTACHMENTS_DIR", self.dir.join("attachments"));
+        command
+    }
+
+    /// Runs `todo grant-admin <email>`, or `revoke-admin` when `admin` is false.
+    pub fn set_admin(&self, email: &str, admin: bool) {
+        let command = if admin { "grant-admin" } else { "revoke-admin" };
+        let status = self
+            .todo(&[command, email])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .expect("failed to run todo");
+        assert!(status.success(), "todo {command} {email} failed: {status}");
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
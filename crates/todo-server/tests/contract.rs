@@ -0,0 +1,179 @@
+//! Replays the request/response fixtures in `contracts/` against the `todo` binary, so a change
+//! that would break an existing client fails here first. Each fixture file runs against a
+//! fresh in-memory server, its steps in order.
+//!
+//! A response matches when it has the fixture's status, every header it lists and, for JSON,
+//! every field it lists; fields and headers the fixture doesn't mention may be added freely.
+//! See "API Contract" in the README for the fixture format.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, Method};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+#[tokio::test]
+async fn fixtures_match_the_api() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../contracts");
+    let fixtures = fixture_files(&root);
+    assert!(!fixtures.is_empty(), "no fixtures under {}", root.display());
+
+    let client = Client::new();
+    let mut failures = Vec::new();
+    for path in fixtures {
+        let fixture: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        let server = Server::start(&client, &[]).await;
+        let label = path.strip_prefix(&root).unwrap_or(&path).display().to_string();
+        if let Err(failure) = replay(&client, &server.base_url, &fixture).await {
+            failures.push(format!("{label}: {failure}"));
+        }
+    }
+    assert!(failures.is_empty(), "contract broken:\n{}", failures.join("\n"));
+}
+
+/// Every `*.json` in every version directory (`v1`, `v2`, ...), in order.
+fn fixture_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for version in std::fs::read_dir(root).expect("contracts directory missing").flatten() {
+        if !version.file_name().to_string_lossy().starts_with('v') {
+            continue;
+        }
+        for file in std::fs::read_dir(version.path()).into_iter().flatten().flatten() {
+            if file.path().extension().is_some_and(|ext| ext == "json") {
+                files.push(file.path());
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Runs the fixture's steps, stopping at the first that doesn't match.
+async fn replay(client: &Client, base_url: &str, fixture: &Value) -> Result<(), String> {
+    let mut saved: HashMap<String, String> = HashMap::new();
+    let steps = fixture["steps"].as_array().ok_or("fixture has no steps")?;
+    for (i, step) in steps.iter().enumerate() {
+        let request = substitute(&step["request"], &saved);
+        let expected = substitute(&step["response"], &saved);
+        let method = request["method"].as_str().unwrap_or("GET");
+        let path = request["path"].as_str().ok_or(format!("step {i} has no path"))?;
+        let context = format!("step {i} ({method} {path})");
+
+        let mut builder = client.request(
+            Method::from_bytes(method.as_bytes()).map_err(|e| format!("{context}: {e}"))?,
+            format!("{base_url}{path}"),
+        );
+        for (name, value) in request["headers"].as_object().into_iter().flatten() {
+            builder = builder.header(name, value.as_str().unwrap_or_default());
+        }
+        if !request["body"].is_null() {
+            builder = builder.json(&request["body"]);
+        }
+        let response = builder.send().await.map_err(|e| format!("{context}: {e}"))?;
+
+        let status = response.status().as_u16();
+        if expected["status"].as_u64() != Some(u64::from(status)) {
+            return Err(format!("{context}: expected status {}, got {status}", expected["status"]));
+        }
+        for (name, value) in expected["headers"].as_object().into_iter().flatten() {
+            let actual = response.headers().get(name.as_str()).and_then(|v| v.to_str().ok());
+            let matched = match (value.as_str(), actual) {
+                (_, None) => false,
+                (Some("$any"), Some(_)) => true,
+                // Parameters such as `; charset=utf-8` don't change the media type
+                (Some(value), Some(actual)) if name.eq_ignore_ascii_case("content-type") => {
+                    actual.split(';').next().is_some_and(|media| media.trim() == value)
+                }
+                (value, actual) => value == actual,
+            };
+            if !matched {
+                let value = value.as_str().unwrap_or_default();
+                return Err(format!("{context}: expected header {name}: {value}, got {actual:?}"));
+            }
+        }
+
+        let text = response.text().await.map_err(|e| format!("{context}: {e}"))?;
+        let body = if expected["body"].is_null() && step["save"].is_null() {
+            Value::Null
+        } else {
+            serde_json::from_str(&text).map_err(|e| format!("{context}: body isn't JSON ({e}): {text}"))?
+        };
+        if !expected["body"].is_null() {
+            matches(&expected["body"], &body, "").map_err(|e| format!("{context}: {e}"))?;
+        }
+        for (name, pointer) in step["save"].as_object().into_iter().flatten() {
+            let value = pointer.as_str().and_then(|pointer| body.pointer(pointer));
+            let value = match value {
+                Some(Value::String(value)) => value.clone(),
+                Some(value) => value.to_string(),
+                None => return Err(format!("{context}: nothing at {pointer} to save as {name}")),
+            };
+            saved.insert(name.clone(), value);
+        }
+    }
+    Ok(())
+}
+
+/// Replaces `{{name}}` in every string with the value saved under `name`.
+fn substitute(value: &Value, saved: &HashMap<String, String>) -> Value {
+    match value {
+        Value::String(text) => {
+            let mut text = text.clone();
+            for (name, saved) in saved {
+                text = text.replace(&format!("{{{{{name}}}}}"), saved);
+            }
+            Value::String(text)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|item| substitute(item, saved)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), substitute(value, saved)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Whether `actual` has everything `expected` asks for. Objects may have extra fields, arrays
+/// must have the same length, and the strings `$any`, `$uuid`, `$timestamp`, `$string` and
+/// `$number` stand for any value of that kind.
+fn matches(expected: &Value, actual: &Value, at: &str) -> Result<(), String> {
+    let matched = match (expected, actual) {
+        (Value::String(kind), _) if kind.starts_with('$') => match (kind.as_str(), actual) {
+            ("$any", _) => true,
+            ("$uuid", Value::String(text)) => uuid::Uuid::parse_str(text).is_ok(),
+            ("$timestamp", Value::String(text)) => chrono::DateTime::parse_from_rfc3339(text).is_ok(),
+            ("$string", Value::String(_)) => true,
+            ("$number", Value::Number(_)) => true,
+            _ => false,
+        },
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, value) in expected {
+                let field = actual.get(key).ok_or(format!("{at}/{key} is missing"))?;
+                matches(value, field, &format!("{at}/{key}"))?;
+            }
+            true
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            if expected.len() != actual.len() {
+                return Err(format!("{at} has {} items, expected {}", actual.len(), expected.len()));
+            }
+            for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                matches(expected, actual, &format!("{at}/{i}"))?;
+            }
+            true
+        }
+        (expected, actual) => expected == actual,
+    };
+    if matched {
+        Ok(())
+    } else {
+        Err(format!("{} is {actual}, expected {expected}", if at.is_empty() { "/" } else { at }))
+    }
+}
@@ -0,0 +1,60 @@
+//! Checks a required custom field holds on every way of creating a todo, not only
+//! `POST /todos`: paths that can't supply the field refuse the create, and MCP checks the
+//! values it's given.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn required_custom_fields_hold_on_every_create_path() {
+    let client = Client::new();
+    let server = Server::start(&client, &[]).await;
+    let url = |path: &str| format!("{}{path}", server.base_url);
+
+    let field = json!({ "name": "customer", "type": "text", "required": true });
+    let defined = client.post(url("/admin/custom-fields")).json(&field).send().await.unwrap();
+    assert_eq!(defined.status(), StatusCode::CREATED);
+
+    let rest = client.post(url("/todos")).json(&json!({ "title": "Call back" }));
+    assert_eq!(rest.send().await.unwrap().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let form = client.post(url("/ui/todos")).form(&[("title", "Call back")]);
+    assert_eq!(form.send().await.unwrap().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let csv = client.post(url("/todos/import")).body("title\nCall back\n");
+    assert_eq!(csv.send().await.unwrap().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let markdown = client.post(url("/import/markdown")).body("- [ ] Call back\n");
+    assert_eq!(markdown.send().await.unwrap().status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let mcp = |arguments: Value| {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": "create_todo", "arguments": arguments },
+        });
+        client.post(url("/mcp")).json(&message).send()
+    };
+    let missing: Value = mcp(json!({ "title": "Call back" })).await.unwrap().json().await.unwrap();
+    assert_eq!(missing["result"]["isError"], true, "{missing}");
+    let wrong_type: Value = mcp(json!({ "title": "Call back", "custom_fields": { "customer": 7 } }))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(wrong_type["result"]["isError"], true, "{wrong_type}");
+    let created: Value = mcp(json!({ "title": "Call back", "custom_fields": { "customer": "Acme" } }))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(created["result"]["isError"], false, "{created}");
+
+    // MCP keeps the values it was given
+    let todos: Value = client.get(url("/todos")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(todos.as_array().unwrap().len(), 1, "{todos}");
+    assert_eq!(todos[0]["custom_fields"]["customer"], "Acme");
+}
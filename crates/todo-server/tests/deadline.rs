@@ -0,0 +1,40 @@
+//! Checks a request stuck behind a locked database is answered `504` once its
+//! `X-Request-Timeout` passes, rather than waiting for the lock.
+
+mod common;
+
+use common::{Database, Server};
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use sqlx::{Connection, SqliteConnection};
+
+#[tokio::test]
+async fn requests_past_their_deadline_get_504() {
+    let client = Client::new();
+    let database = Database::new();
+    let server = Server::start_on(&client, &database, &[]).await;
+    let create = || {
+        client
+            .post(format!("{}/todos", server.base_url))
+            .header("x-request-timeout", "300ms")
+            .json(&json!({ "title": "Renew passport" }))
+            .send()
+    };
+
+    let invalid = client.get(format!("{}/todos", server.base_url)).header("x-request-timeout", "0");
+    assert_eq!(invalid.send().await.unwrap().status(), StatusCode::BAD_REQUEST);
+
+    // Another writer holds the lock for longer than the request will wait
+    let url = format!("sqlite://{}", database.path().display());
+    let mut writer = SqliteConnection::connect(&url).await.unwrap();
+    sqlx::query("BEGIN IMMEDIATE").execute(&mut writer).await.unwrap();
+    let timed_out = create().await.unwrap();
+    assert_eq!(timed_out.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(timed_out.headers()["content-type"], "application/problem+json");
+    let problem: Value = timed_out.json().await.unwrap();
+    assert_eq!(problem["status"], 504, "{problem}");
+    assert_eq!(problem["title"], "Deadline exceeded", "{problem}");
+
+    sqlx::query("ROLLBACK").execute(&mut writer).await.unwrap();
+    assert_eq!(create().await.unwrap().status(), StatusCode::OK);
+}
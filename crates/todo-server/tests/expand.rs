@@ -0,0 +1,80 @@
+//! Checks `?expand=comments,project` embeds each todo's own comments and project, on single
+//! todos and on every kind of list page.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn todos_embed_their_comments_and_project() {
+    let client = Client::new();
+    let server = Server::start(&client, &[("JWT_SECRET", "expand-test-secret-0123456789abcdefgh")]).await;
+    let url = |path: &str| format!("{}{path}", server.base_url);
+    let credentials = json!({ "email": "dev@example.com", "password": "correct horse battery" });
+    let register = client.post(url("/auth/register")).json(&credentials);
+    assert_eq!(register.send().await.unwrap().status(), StatusCode::CREATED);
+    let session: Value = client.post(url("/auth/login")).json(&credentials).send().await.unwrap().json().await.unwrap();
+    let token = session["access_token"].as_str().unwrap();
+    let get = |path: &str| client.get(url(path)).bearer_auth(token).send();
+
+    let project = client.post(url("/projects")).bearer_auth(token).json(&json!({ "name": "Launch" }));
+    let project: Value = project.send().await.unwrap().json().await.unwrap();
+    let project_id = project["id"].as_str().unwrap();
+    let create = client
+        .post(url(&format!("/projects/{project_id}/todos")))
+        .bearer_auth(token)
+        .json(&json!({ "title": "Write the announcement" }));
+    let planned: Value = create.send().await.unwrap().json().await.unwrap();
+    let planned_id = planned["id"].as_str().unwrap();
+    let create = client.post(url("/todos")).bearer_auth(token).json(&json!({ "title": "Water plants" }));
+    let loose: Value = create.send().await.unwrap().json().await.unwrap();
+    for body in ["First draft is up", "Reviewed"] {
+        let comment = client
+            .post(url(&format!("/todos/{planned_id}/comments")))
+            .bearer_auth(token)
+            .json(&json!({ "body": body }));
+        assert_eq!(comment.send().await.unwrap().status(), StatusCode::CREATED);
+    }
+
+    let response = get(&format!("/todos/{planned_id}?expand=comments,project")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("etag").is_none());
+    let todo: Value = response.json().await.unwrap();
+    assert_eq!(todo["title"], "Write the announcement", "{todo}");
+    assert_eq!(todo["project"], json!({ "id": project_id, "name": "Launch" }), "{todo}");
+    let bodies: Vec<&str> = todo["comments"].as_array().unwrap().iter().map(|c| c["body"].as_str().unwrap()).collect();
+    assert_eq!(bodies, ["First draft is up", "Reviewed"]);
+
+    // Only what was asked for is embedded, and a todo in no project gets `null`
+    let todo: Value = get(&format!("/todos/{}?expand=project", loose["id"].as_str().unwrap()))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(todo["project"], Value::Null, "{todo}");
+    assert!(todo.get("comments").is_none(), "{todo}");
+
+    for path in ["/todos?expand=comments", "/todos?expand=comments&offset=0&limit=10", "/todos?expand=comments&limit=10&cursor="] {
+        let todos: Value = get(path).await.unwrap().json().await.unwrap();
+        let counts: Vec<usize> = todos.as_array().unwrap().iter().map(|t| t["comments"].as_array().unwrap().len()).collect();
+        assert_eq!(counts.iter().sum::<usize>(), 2, "{path}: {todos}");
+        assert_eq!(counts.len(), 2, "{path}: {todos}");
+    }
+    let page: Value = get("/todos?expand=project&page=1&per_page=10").await.unwrap().json().await.unwrap();
+    assert_eq!(page["total"], 2, "{page}");
+    assert!(page["todos"].as_array().unwrap().iter().any(|t| t["project"]["name"] == "Launch"), "{page}");
+
+    let camel = client
+        .get(url(&format!("/todos/{planned_id}?expand=comments")))
+        .bearer_auth(token)
+        .header("x-field-case", "camel");
+    let todo: Value = camel.send().await.unwrap().json().await.unwrap();
+    assert_eq!(todo["projectId"], project_id, "{todo}");
+    assert!(todo["comments"][0]["createdAt"].is_string(), "{todo}");
+
+    let unknown = get("/todos?expand=subtasks").await.unwrap();
+    assert_eq!(unknown.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,36 @@
+//! Checks `X-Field-Case: camel` renames the fields of every response it's honoured on,
+//! including todos nested inside other bodies.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn camel_case_reaches_nested_todos() {
+    let client = Client::new();
+    let server = Server::start(&client, &[]).await;
+    let url = |path: &str| format!("{}{path}", server.base_url);
+    let camel = |request: reqwest::RequestBuilder| request.header("x-field-case", "camel").send();
+
+    let todo = json!({ "title": "Pay rent", "due_at": "2030-01-01T09:00:00Z" });
+    let created = client.post(url("/todos")).json(&todo).send().await.unwrap();
+    assert_eq!(created.status(), StatusCode::OK);
+
+    let changes: Value = camel(client.get(url("/sync"))).await.unwrap().json().await.unwrap();
+    assert_eq!(changes["hasMore"], false, "{changes}");
+    assert_eq!(changes["created"][0]["dueAt"], "2030-01-01T09:00:00Z", "{changes}");
+    assert!(changes["created"][0].get("due_at").is_none(), "{changes}");
+    assert!(changes["created"][0]["changeSeq"].is_u64(), "{changes}");
+
+    let aging: Value = camel(client.get(url("/stats/aging"))).await.unwrap().json().await.unwrap();
+    assert_eq!(aging["openCount"], 1, "{aging}");
+    assert!(aging["buckets"][0].get("minDays").is_some(), "{aging}");
+
+    let text = json!({ "text": "Water plants every 2 weeks" });
+    let request = client.post(url("/todos/parse")).json(&text);
+    let parsed: Value = camel(request).await.unwrap().json().await.unwrap();
+    assert!(parsed.get("dueAt").is_some(), "{parsed}");
+    assert_eq!(parsed["recurrence"]["interval"], 2, "{parsed}");
+}
@@ -0,0 +1,131 @@
+//! Checks `X-Impersonate-User` is refused where it can't apply, and that refused attempts are
+//! audited like the ones that run.
+
+mod common;
+
+use common::{Database, Server};
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+struct Accounts {
+    server: Server,
+    _database: Database,
+    client: Client,
+    admin: String,
+    user: String,
+    user_id: String,
+}
+
+impl Accounts {
+    async fn start() -> Self {
+        let client = Client::new();
+        let database = Database::new();
+        let server = Server::start_on(
+            &client,
+            &database,
+            &[
+                ("JWT_SECRET", "impersonation-test-secret-0123456789"),
+                ("AUTH_REQUIRED", "true"),
+            ],
+        )
+        .await;
+        let mut accounts = Self {
+            server,
+            _database: database,
+            client,
+            admin: String::new(),
+            user: String::new(),
+            user_id: String::new(),
+        };
+        (accounts.admin, _) = accounts.account("admin@example.com").await;
+        accounts._database.set_admin("admin@example.com", true);
+        (accounts.user, accounts.user_id) = accounts.account("user@example.com").await;
+        accounts
+    }
+
+    /// Registers and signs in, returning the access token and user id.
+    async fn account(&self, email: &str) -> (String, String) {
+        let credentials = json!({ "email": email, "password": "correct horse battery" });
+        let url = format!("{}/auth/register", self.server.base_url);
+        let user: Value = self.client.post(url).json(&credentials).send().await.unwrap().json().await.unwrap();
+        let url = format!("{}/auth/login", self.server.base_url);
+        let session: Value = self.client.post(url).json(&credentials).send().await.unwrap().json().await.unwrap();
+        (
+            session["access_token"].as_str().unwrap().to_string(),
+            user["id"].as_str().unwrap().to_string(),
+        )
+    }
+
+    async fn impersonating(&self, method: reqwest::Method, path: &str, token: &str, user_id: &str) -> StatusCode {
+        self.client
+            .request(method, format!("{}{path}", self.server.base_url))
+            .bearer_auth(token)
+            .header("X-Impersonate-User", user_id)
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    /// The statuses of the `admin.impersonated` entries, oldest first.
+    async fn audited(&self) -> Vec<u64> {
+        let url = format!("{}/admin/audit", self.server.base_url);
+        let entries: Vec<Value> = self
+            .client
+            .get(url)
+            .bearer_auth(&self.admin)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let mut statuses: Vec<u64> = entries
+            .iter()
+            .filter(|entry| entry["event"] == "admin.impersonated")
+            .map(|entry| entry["detail"]["status"].as_u64().unwrap())
+            .collect();
+        statuses.reverse();
+        statuses
+    }
+}
+
+#[tokio::test]
+async fn routes_the_header_cant_apply_to_refuse_it() {
+    let accounts = Accounts::start().await;
+    for (method, path) in [
+        (reqwest::Method::GET, "/auth/me"),
+        (reqwest::Method::POST, "/auth/tokens"),
+        (reqwest::Method::DELETE, "/users/me"),
+        (reqwest::Method::GET, "/projects"),
+    ] {
+        let status = accounts.impersonating(method, path, &accounts.admin, &accounts.user_id).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{path}");
+    }
+    let me = accounts
+        .client
+        .get(format!("{}/auth/me", accounts.server.base_url))
+        .bearer_auth(&accounts.user)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(me.status(), StatusCode::OK, "the user's account is untouched");
+}
+
+#[tokio::test]
+async fn refused_impersonation_is_audited() {
+    let accounts = Accounts::start().await;
+    let get = reqwest::Method::GET;
+    let missing = "00000000-0000-0000-0000-000000000000";
+
+    let not_admin = accounts.impersonating(get.clone(), "/todos", &accounts.user, &accounts.user_id).await;
+    assert_eq!(not_admin, StatusCode::FORBIDDEN);
+    let unknown = accounts.impersonating(get.clone(), "/todos", &accounts.admin, missing).await;
+    assert_eq!(unknown, StatusCode::NOT_FOUND);
+    let malformed = accounts.impersonating(get.clone(), "/todos", &accounts.admin, "nobody").await;
+    assert_eq!(malformed, StatusCode::BAD_REQUEST);
+    let admin_route = accounts.impersonating(get, "/admin/flags", &accounts.admin, &accounts.user_id).await;
+    assert_eq!(admin_route, StatusCode::FORBIDDEN);
+
+    assert_eq!(accounts.audited().await, [403, 404, 400, 403]);
+}
@@ -0,0 +1,75 @@
+//! Checks `@mentions` in descriptions are picked up on every route that saves one, not only
+//! on `POST /todos` and `PUT /todos/{id}`.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+/// Registers `email` and returns a session token for it.
+async fn sign_up(client: &Client, server: &Server, email: &str) -> String {
+    let credentials = json!({ "email": email, "password": "correct horse battery" });
+    let register = client.post(format!("{}/auth/register", server.base_url)).json(&credentials);
+    assert_eq!(register.send().await.unwrap().status(), StatusCode::CREATED);
+    let login = client.post(format!("{}/auth/login", server.base_url)).json(&credentials);
+    let session: Value = login.send().await.unwrap().json().await.unwrap();
+    session["access_token"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn descriptions_mention_on_every_write() {
+    let client = Client::new();
+    let server = Server::start(
+        &client,
+        &[("JWT_SECRET", "mention-test-secret-0123456789abcdefgh"), ("AUTH_REQUIRED", "true")],
+    )
+    .await;
+    let url = |path: &str| format!("{}{path}", server.base_url);
+    let author = sign_up(&client, &server, "author@example.com").await;
+    let reader = sign_up(&client, &server, "reader@example.com").await;
+    let profile = client
+        .patch(url("/users/me"))
+        .bearer_auth(&reader)
+        .json(&json!({ "username": "reader", "mention_channel": "feed" }));
+    assert_eq!(profile.send().await.unwrap().status(), StatusCode::OK);
+
+    let batch = json!({ "todos": [{ "title": "Draft", "description": "@reader from a batch" }] });
+    let batched = client.post(url("/todos/batch")).bearer_auth(&author).json(&batch).send().await.unwrap();
+    assert_eq!(batched.status(), StatusCode::OK);
+
+    let id = uuid::Uuid::new_v4();
+    let create = json!({ "op": "create", "id": id, "todo": { "title": "Sync", "description": "@reader from sync" } });
+    let push = client.post(url("/sync/push")).bearer_auth(&author).json(&json!({ "mutations": [create] }));
+    let pushed: Value = push.send().await.unwrap().json().await.unwrap();
+    assert_eq!(pushed["applied"], 1, "{pushed}");
+    // Names already in the description aren't notified again
+    let version = pushed["results"][0]["todo"]["version"].clone();
+    let changes = json!({ "description": "@reader from sync, edited" });
+    let update = json!({ "op": "update", "id": id, "base_version": version, "changes": changes });
+    let push = client.post(url("/sync/push")).bearer_auth(&author).json(&json!({ "mutations": [update] }));
+    let pushed: Value = push.send().await.unwrap().json().await.unwrap();
+    assert_eq!(pushed["applied"], 1, "{pushed}");
+
+    let call = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": { "name": "create_todo", "arguments": { "title": "Tool", "description": "@reader from MCP" } },
+    });
+    let mcp: Value = client.post(url("/mcp")).bearer_auth(&author).json(&call).send().await.unwrap().json().await.unwrap();
+    assert_eq!(mcp["result"]["isError"], false, "{mcp}");
+
+    let feed: Value = client
+        .get(url("/users/me/activity"))
+        .bearer_auth(&reader)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let mut excerpts: Vec<&str> = feed.as_array().unwrap().iter().map(|item| item["excerpt"].as_str().unwrap()).collect();
+    excerpts.sort();
+    assert_eq!(excerpts, ["@reader from MCP", "@reader from a batch", "@reader from sync"], "{feed}");
+}
@@ -0,0 +1,325 @@
+//! Checks every route that reads or changes todos in bulk keeps a project's todos to its
+//! members, as "Shared Projects" in the README describes. Each test runs its own server with
+//! `AUTH_REQUIRED`, where an owner shares a completed todo with a viewer and not with an
+//! outsider.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const TITLE: &str = "Private launch plan";
+const TAG: &str = "stealth-launch";
+
+struct Project {
+    server: Server,
+    client: Client,
+    owner: String,
+    viewer: String,
+    outsider: String,
+    todo: Value,
+}
+
+impl Project {
+    async fn start() -> Self {
+        let client = Client::new();
+        let server = Server::start(
+            &client,
+            &[
+                ("JWT_SECRET", "project-access-test-secret-0123456789"),
+                ("AUTH_REQUIRED", "true"),
+            ],
+        )
+        .await;
+        let mut project = Self {
+            owner: String::new(),
+            viewer: String::new(),
+            outsider: String::new(),
+            todo: Value::Null,
+            server,
+            client,
+        };
+        project.owner = project.account("owner@example.com").await;
+        project.viewer = project.account("viewer@example.com").await;
+        project.outsider = project.account("outsider@example.com").await;
+
+        let created = project
+            .send(project.post("/projects", &project.owner).json(&json!({ "name": "Launch" })))
+            .await;
+        let id = created["id"].as_str().unwrap().to_string();
+        project
+            .send(
+                project
+                    .post(&format!("/projects/{id}/members"), &project.owner)
+                    .json(&json!({ "email": "viewer@example.com", "role": "viewer" })),
+            )
+            .await;
+        let todo = project
+            .send(
+                project
+                    .post(&format!("/projects/{id}/todos"), &project.owner)
+                    .json(&json!({ "title": TITLE, "tags": [TAG] })),
+            )
+            .await;
+        project.todo = project
+            .send(
+                project
+                    .request(reqwest::Method::PUT, &project.todo_path(&todo), &project.owner)
+                    .json(&json!({ "completed": true })),
+            )
+            .await;
+        project
+    }
+
+    async fn account(&self, email: &str) -> String {
+        let credentials = json!({ "email": email, "password": "correct horse battery" });
+        let url = format!("{}/auth/register", self.server.base_url);
+        let response = self.client.post(url).json(&credentials).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED, "registering {email}");
+        let url = format!("{}/auth/login", self.server.base_url);
+        let session = self.send(self.client.post(url).json(&credentials)).await;
+        session["access_token"].as_str().unwrap().to_string()
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str, token: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{path}", self.server.base_url))
+            .bearer_auth(token)
+    }
+
+    fn get(&self, path: &str, token: &str) -> RequestBuilder {
+        self.request(reqwest::Method::GET, path, token)
+    }
+
+    fn post(&self, path: &str, token: &str) -> RequestBuilder {
+        self.request(reqwest::Method::POST, path, token)
+    }
+
+    fn delete(&self, path: &str, token: &str) -> RequestBuilder {
+        self.request(reqwest::Method::DELETE, path, token)
+    }
+
+    fn todo_path(&self, todo: &Value) -> String {
+        format!("/todos/{}", todo["id"].as_str().unwrap())
+    }
+
+    /// Sends `request`, expecting success, and returns its JSON body.
+    async fn send(&self, request: RequestBuilder) -> Value {
+        let response = request.send().await.unwrap();
+        let status = response.status();
+        let text = response.text().await.unwrap();
+        assert!(status.is_success(), "{status}: {text}");
+        serde_json::from_str(&text).unwrap_or_else(|e| panic!("body isn't JSON ({e}): {text}"))
+    }
+
+    async fn status(&self, request: RequestBuilder) -> StatusCode {
+        request.send().await.unwrap().status()
+    }
+
+    async fn text(&self, request: RequestBuilder) -> String {
+        let response = request.send().await.unwrap();
+        assert!(response.status().is_success(), "{}", response.status());
+        response.text().await.unwrap()
+    }
+
+    /// Whether the owner can still read the todo.
+    async fn todo_exists(&self) -> bool {
+        self.status(self.get(&self.todo_path(&self.todo), &self.owner)).await == StatusCode::OK
+    }
+
+    async fn mcp(&self, token: &str, tool: &str, arguments: Value) -> Value {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": tool, "arguments": arguments },
+        });
+        let response = self.send(self.post("/mcp", token).json(&message)).await;
+        response["result"].clone()
+    }
+}
+
+#[tokio::test]
+async fn compact_list_leaves_out_other_projects() {
+    let project = Project::start().await;
+    let viewer = project.text(project.get("/todos/compact", &project.viewer)).await;
+    assert!(viewer.contains(TITLE));
+    let outsider = project.text(project.get("/todos/compact", &project.outsider)).await;
+    assert!(!outsider.contains(TITLE));
+}
+
+#[tokio::test]
+async fn stats_count_only_visible_projects() {
+    let project = Project::start().await;
+    let viewer = project.send(project.get("/todos/stats", &project.viewer)).await;
+    assert_eq!(viewer["completed_count"], 1);
+    let outsider = project.send(project.get("/todos/stats", &project.outsider)).await;
+    assert_eq!(outsider["completed_count"], 0);
+}
+
+#[tokio::test]
+async fn analytics_count_only_visible_projects() {
+    let project = Project::start().await;
+    // The viewer asks first, so the outsider would get the viewer's cached figures if the
+    // cache ignored who is asking
+    let viewer = project.send(project.get("/stats/completion-time", &project.viewer)).await;
+    assert_eq!(viewer["count"], 1);
+    let outsider = project.send(project.get("/stats/completion-time", &project.outsider)).await;
+    assert_eq!(outsider["count"], 0);
+
+    let completed = |velocity: &Value| -> u64 {
+        velocity["points"].as_array().unwrap().iter().map(|point| point["completed"].as_u64().unwrap()).sum()
+    };
+    let viewer = project.send(project.get("/stats/velocity", &project.viewer)).await;
+    assert_eq!(completed(&viewer), 1);
+    let outsider = project.send(project.get("/stats/velocity", &project.outsider)).await;
+    assert_eq!(completed(&outsider), 0);
+}
+
+#[tokio::test]
+async fn sync_leaves_out_other_projects() {
+    let project = Project::start().await;
+    let viewer = project.text(project.get("/sync", &project.viewer)).await;
+    assert!(viewer.contains(TITLE));
+    let outsider = project.text(project.get("/sync", &project.outsider)).await;
+    assert!(!outsider.contains(TITLE));
+}
+
+#[tokio::test]
+async fn sync_push_refuses_changes_to_projects_the_caller_cant_edit() {
+    let project = Project::start().await;
+    let id = project.todo["id"].clone();
+    let version = project.todo["version"].clone();
+    let mutations = json!({ "mutations": [
+        { "op": "update", "id": id, "base_version": version, "changes": { "title": "Leaked" } },
+        { "op": "delete", "id": id, "base_version": version },
+    ]});
+    for token in [&project.viewer, &project.outsider] {
+        let pushed = project.send(project.post("/sync/push", token).json(&mutations)).await;
+        assert_eq!(pushed["applied"], 0);
+        for result in pushed["results"].as_array().unwrap() {
+            assert_eq!(result["status"], "conflict");
+            assert_eq!(result["reason"], "forbidden");
+            assert!(result["server"].is_null());
+        }
+    }
+    let todo = project.send(project.get(&project.todo_path(&project.todo), &project.owner)).await;
+    assert_eq!(todo["title"], TITLE);
+}
+
+#[tokio::test]
+async fn bulk_delete_keeps_todos_the_caller_cant_edit() {
+    let project = Project::start().await;
+    for token in [&project.viewer, &project.outsider] {
+        let status = project.status(project.delete("/todos/completed", token)).await;
+        assert!(status.is_success(), "{status}");
+        assert!(project.todo_exists().await);
+    }
+}
+
+#[tokio::test]
+async fn mcp_tools_keep_to_the_callers_projects() {
+    let project = Project::start().await;
+    let viewer = project.mcp(&project.viewer, "list_todos", json!({})).await;
+    assert!(viewer.to_string().contains(TITLE));
+    let listed = project.mcp(&project.outsider, "list_todos", json!({})).await;
+    assert!(!listed.to_string().contains(TITLE));
+    let found = project.mcp(&project.outsider, "search", json!({ "query": "launch" })).await;
+    assert!(!found.to_string().contains(TITLE));
+
+    for token in [&project.viewer, &project.outsider] {
+        let completed = project.mcp(token, "complete_todo", json!({ "id": project.todo["id"] })).await;
+        assert_eq!(completed["isError"], true);
+    }
+}
+
+#[tokio::test]
+async fn web_ui_keeps_to_the_callers_projects() {
+    let project = Project::start().await;
+    let viewer = project.text(project.get("/ui", &project.viewer)).await;
+    assert!(viewer.contains(TITLE));
+    let outsider = project.text(project.get("/ui", &project.outsider)).await;
+    assert!(!outsider.contains(TITLE));
+
+    let id = project.todo["id"].as_str().unwrap();
+    for (token, refused) in [(&project.viewer, StatusCode::FORBIDDEN), (&project.outsider, StatusCode::NOT_FOUND)] {
+        let toggle = project.post(&format!("/ui/todos/{id}/toggle"), token);
+        assert_eq!(project.status(toggle).await, refused);
+        assert_eq!(project.status(project.delete(&format!("/ui/todos/{id}"), token)).await, refused);
+    }
+    let todo = project.send(project.get(&project.todo_path(&project.todo), &project.owner)).await;
+    assert_eq!(todo["completed"], true);
+}
+
+#[tokio::test]
+async fn event_stream_leaves_out_other_projects() {
+    let project = Project::start().await;
+    let mut events = project.get("/todos/events", &project.outsider).send().await.unwrap();
+    assert_eq!(events.status(), StatusCode::OK);
+
+    let path = project.todo_path(&project.todo);
+    let update = project.request(reqwest::Method::PUT, &path, &project.owner);
+    project.send(update.json(&json!({ "description": "Still secret" }))).await;
+    // Outside any project, so every subscriber hears of it after the update
+    let marker = project.post("/todos", &project.owner).json(&json!({ "title": "Public marker" }));
+    project.send(marker).await;
+
+    let mut received = String::new();
+    while !received.contains("Public marker") {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), events.chunk())
+            .await
+            .expect("no event within 5 seconds")
+            .unwrap()
+            .expect("event stream ended");
+        received.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    assert!(!received.contains(TITLE), "{received}");
+}
+
+#[tokio::test]
+async fn history_and_blockers_keep_to_the_callers_projects() {
+    let project = Project::start().await;
+    let path = project.todo_path(&project.todo);
+    let history = format!("{path}/history");
+    assert_eq!(project.status(project.get(&history, &project.viewer)).await, StatusCode::OK);
+    assert_eq!(project.status(project.get(&history, &project.outsider)).await, StatusCode::NOT_FOUND);
+
+    let own = json!({ "title": "Outsider's own todo" });
+    let own = project.send(project.post("/todos", &project.outsider).json(&own)).await;
+    let own_id = own["id"].as_str().unwrap();
+    let blocked_by_private = project
+        .post(&format!("/todos/{own_id}/blockers"), &project.outsider)
+        .json(&json!({ "blocker_id": project.todo["id"] }));
+    assert_eq!(project.status(blocked_by_private).await, StatusCode::NOT_FOUND);
+
+    for (token, refused) in [(&project.viewer, StatusCode::FORBIDDEN), (&project.outsider, StatusCode::NOT_FOUND)] {
+        let add = project
+            .post(&format!("{path}/blockers"), token)
+            .json(&json!({ "blocker_id": own_id }));
+        assert_eq!(project.status(add).await, refused);
+        let remove = project.delete(&format!("{path}/blockers/{own_id}"), token);
+        assert_eq!(project.status(remove).await, refused);
+    }
+}
+
+#[tokio::test]
+async fn tags_keep_to_the_callers_projects() {
+    let project = Project::start().await;
+    let viewer = project.text(project.get("/tags", &project.viewer)).await;
+    assert!(viewer.contains(TAG));
+    let outsider = project.text(project.get("/tags", &project.outsider)).await;
+    assert!(!outsider.contains(TAG));
+    let tag = format!("/tags/{TAG}");
+    assert_eq!(project.status(project.get(&tag, &project.outsider)).await, StatusCode::NOT_FOUND);
+
+    for token in [&project.viewer, &project.outsider] {
+        let rename = project.post(&format!("{tag}/rename"), token).json(&json!({ "name": "renamed" }));
+        assert_eq!(project.status(rename).await, StatusCode::NOT_FOUND);
+        let merge = project.post(&format!("{tag}/merge"), token).json(&json!({ "into": "merged" }));
+        assert_eq!(project.status(merge).await, StatusCode::NOT_FOUND);
+    }
+    let todo = project.send(project.get(&project.todo_path(&project.todo), &project.owner)).await;
+    assert_eq!(todo["tags"], json!([TAG]));
+}
@@ -0,0 +1,61 @@
+//! Checks that a client can't get a fresh rate limit bucket by changing the headers it sends,
+//! nor past its address's limit by sending tokens that have to be looked up.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn changing_headers_doesnt_reset_the_bucket() {
+    let client = Client::new();
+    let server = Server::start(&client, &[("RATE_LIMIT_PER_MINUTE", "1"), ("RATE_LIMIT_BURST", "2")]).await;
+
+    let mut statuses = Vec::new();
+    for attempt in 0..3 {
+        let response = client
+            .get(format!("{}/todos", server.base_url))
+            .header("X-API-Key", format!("key-{attempt}"))
+            .header("Authorization", format!("Bearer not-a-token-{attempt}"))
+            .send()
+            .await
+            .unwrap();
+        statuses.push(response.status());
+    }
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::OK, StatusCode::TOO_MANY_REQUESTS]);
+}
+
+#[tokio::test]
+async fn unknown_tokens_are_charged_to_the_address_before_they_are_looked_up() {
+    let client = Client::new();
+    let server = Server::start(
+        &client,
+        &[
+            ("RATE_LIMIT_PER_MINUTE", "1"),
+            ("RATE_LIMIT_BURST", "4"),
+            ("JWT_SECRET", "rate-limit-test-secret-0123456789abcd"),
+        ],
+    )
+    .await;
+
+    // Two of the address's four requests
+    let credentials = json!({ "email": "limited@example.com", "password": "correct horse battery" });
+    let register = client.post(format!("{}/auth/register", server.base_url)).json(&credentials);
+    assert_eq!(register.send().await.unwrap().status(), StatusCode::CREATED);
+    let login = client.post(format!("{}/auth/login", server.base_url)).json(&credentials);
+    let session: Value = login.send().await.unwrap().json().await.unwrap();
+    let token = session["access_token"].as_str().unwrap();
+
+    let todos = format!("{}/todos", server.base_url);
+    let get = |bearer: String| {
+        let request = client.get(&todos).bearer_auth(bearer);
+        async move { request.send().await.unwrap().status() }
+    };
+    // The first use of the token is looked up, on the address's bill; after that it's cached
+    assert_eq!(get(token.to_owned()).await, StatusCode::OK);
+    assert_eq!(get("made-up-1".to_owned()).await, StatusCode::OK);
+    assert_eq!(get("made-up-2".to_owned()).await, StatusCode::TOO_MANY_REQUESTS);
+    // The account still has its own bucket
+    assert_eq!(get(token.to_owned()).await, StatusCode::OK);
+}
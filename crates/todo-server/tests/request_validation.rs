@@ -0,0 +1,68 @@
+//! Checks `REQUEST_VALIDATION` holds bodies to the schemas derived from their request types on
+//! routes beyond `/todos`, and that `API_MODE=strict` is what refuses unknown fields.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn sync_pushes_and_project_bodies_are_checked() {
+    let client = Client::new();
+    let server = Server::start(&client, &[("REQUEST_VALIDATION", "true"), ("API_MODE", "strict")]).await;
+
+    let schema: Value = client
+        .get(format!("{}/schemas/requests", server.base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    for name in ["SyncPush", "RenameTagRequest", "CreateProjectRequest", "Credentials", "RestoreRequest"] {
+        assert!(schema["$defs"][name].is_object(), "no schema for {name}");
+    }
+
+    // The mutation's `op` picks the variant its problems are reported against
+    let push = json!({ "mutations": [{ "op": "create", "id": "nope", "todo": { "title": 5 } }] });
+    let response = client
+        .post(format!("{}/sync/push", server.base_url))
+        .json(&push)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let problem: Value = response.json().await.unwrap();
+    let pointers: Vec<_> = problem["errors"].as_array().unwrap().iter().map(|e| e["pointer"].clone()).collect();
+    assert_eq!(pointers, [json!("/mutations/0/id"), json!("/mutations/0/todo/title")]);
+
+    let response = client
+        .post(format!("{}/projects", server.base_url))
+        .json(&json!({ "name": "Launch", "colour": "red" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let problem: Value = response.json().await.unwrap();
+    assert_eq!(problem["detail"], "/colour: unknown field");
+}
+
+#[tokio::test]
+async fn unknown_fields_pass_validation_unless_the_api_is_strict() {
+    let client = Client::new();
+    let server = Server::start(&client, &[("REQUEST_VALIDATION", "true")]).await;
+    let url = format!("{}/todos", server.base_url);
+
+    let response = client.post(&url).json(&json!({ "title": "Call the bank", "compelted": true })).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Types are still checked
+    let response = client.post(&url).json(&json!({ "title": "Call the bank", "priority": "urgent" })).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    // So are lengths
+    let response = client.post(&url).json(&json!({ "title": "" })).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let problem: Value = response.json().await.unwrap();
+    assert_eq!(problem["detail"], "/title: must not be empty", "{problem}");
+}
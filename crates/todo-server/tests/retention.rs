@@ -0,0 +1,22 @@
+//! Checks a retention rule reaching back past the earliest representable date is reported as
+//! a bad request rather than taking the server down.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+
+#[tokio::test]
+async fn out_of_range_retention_is_a_bad_request() {
+    let client = Client::new();
+    let server = Server::start(&client, &[("RETENTION_COMPLETED_TODO_DAYS", "100000000")]).await;
+    let url = format!("{}/admin/retention", server.base_url);
+
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let message = response.text().await.unwrap();
+    assert!(message.contains("completed_todos retention of 100000000 days"), "{message}");
+
+    // The server is still up for the next request
+    assert_eq!(client.get(&url).send().await.unwrap().status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,55 @@
+//! Checks a refresh token can only be used once, and that ending a session, by logging out
+//! or by a refresh token turning up again, invalidates every token it issued.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn reused_refresh_tokens_and_logouts_end_the_session() {
+    let client = Client::new();
+    let server = Server::start(&client, &[("JWT_SECRET", "session-test-secret-0123456789abcdefgh")]).await;
+    let url = |path: &str| format!("{}{path}", server.base_url);
+    let credentials = json!({ "email": "dev@example.com", "password": "correct horse battery" });
+    let register = client.post(url("/auth/register")).json(&credentials);
+    assert_eq!(register.send().await.unwrap().status(), StatusCode::CREATED);
+    let login = || async {
+        let response = client.post(url("/auth/login")).json(&credentials).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response.json::<Value>().await.unwrap()
+    };
+    let refresh = |token: &Value| {
+        let body = json!({ "refresh_token": token["refresh_token"] });
+        client.post(url("/auth/refresh")).json(&body).send()
+    };
+    let me = |token: &Value| {
+        let access = token["access_token"].as_str().unwrap().to_owned();
+        client.get(url("/auth/me")).bearer_auth(access).send()
+    };
+
+    let first = login().await;
+    let rotated = refresh(&first).await.unwrap();
+    assert_eq!(rotated.status(), StatusCode::OK);
+    let rotated: Value = rotated.json().await.unwrap();
+    assert_ne!(rotated["refresh_token"], first["refresh_token"]);
+    assert_eq!(me(&rotated).await.unwrap().status(), StatusCode::OK);
+
+    // The rotated-out token turning up again means it leaked: the whole family goes
+    assert_eq!(refresh(&first).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(refresh(&rotated).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(me(&rotated).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+    let second = login().await;
+    assert_eq!(me(&second).await.unwrap().status(), StatusCode::OK);
+    let logout = client
+        .post(url("/auth/logout"))
+        .json(&json!({ "refresh_token": second["refresh_token"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(logout.status(), StatusCode::NO_CONTENT);
+    assert_eq!(me(&second).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(refresh(&second).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,53 @@
+//! Checks two clients pushing edits made from the same version can't overwrite each other:
+//! the second gets a conflict record instead of its write being applied.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn the_second_push_from_a_stale_version_conflicts() {
+    let client = Client::new();
+    let server = Server::start(&client, &[]).await;
+    let url = |path: &str| format!("{}{path}", server.base_url);
+
+    let created: Value = client
+        .post(url("/todos"))
+        .json(&json!({ "title": "Plan the offsite" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = created["id"].as_str().unwrap();
+    let base_version = created["version"].as_u64().unwrap();
+    let push = |title: &str| {
+        let update = json!({ "op": "update", "id": id, "base_version": base_version, "changes": { "title": title } });
+        client.post(url("/sync/push")).json(&json!({ "mutations": [update] })).send()
+    };
+
+    let first = push("Plan the offsite for May").await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+    let first: Value = first.json().await.unwrap();
+    assert_eq!(first["applied"], 1, "{first}");
+    assert_eq!(first["results"][0]["status"], "applied", "{first}");
+
+    let second = push("Plan the offsite for June").await.unwrap();
+    assert_eq!(second.status(), StatusCode::OK);
+    let second: Value = second.json().await.unwrap();
+    assert_eq!(second["applied"], 0, "{second}");
+    assert_eq!(second["conflicts"], 1, "{second}");
+    let conflict = &second["results"][0];
+    assert_eq!(conflict["status"], "conflict", "{second}");
+    assert_eq!(conflict["reason"], "version_mismatch", "{second}");
+    assert_eq!(conflict["client"]["changes"]["title"], "Plan the offsite for June", "{second}");
+    assert_eq!(conflict["server"]["title"], "Plan the offsite for May", "{second}");
+    assert!(conflict["server"]["version"].as_u64().unwrap() > base_version, "{second}");
+
+    let stored: Value = client.get(url(&format!("/todos/{id}"))).send().await.unwrap().json().await.unwrap();
+    assert_eq!(stored["title"], "Plan the offsite for May");
+    assert_eq!(stored["version"], first["results"][0]["todo"]["version"]);
+}
@@ -0,0 +1,127 @@
+//! Checks that `X-Tenant-ID` only reaches the tenants in `TENANTS_FILE`, and only for their
+//! members once `AUTH_REQUIRED` identifies callers.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// A tenant directory with `acme`, whose only member is alice, removed when dropped.
+struct Tenants {
+    dir: PathBuf,
+}
+
+impl Tenants {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("todo-tenants-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let members = json!({ "acme": ["Alice@example.com"] });
+        std::fs::write(dir.join("tenants.json"), members.to_string()).unwrap();
+        Self { dir }
+    }
+
+    fn env(&self) -> [(String, String); 2] {
+        [
+            ("TENANT_DATABASE_DIR".to_string(), self.dir.join("db").display().to_string()),
+            ("TENANTS_FILE".to_string(), self.dir.join("tenants.json").display().to_string()),
+        ]
+    }
+
+    async fn start(&self, client: &Client, extra: &[(&str, &str)]) -> Server {
+        let env = self.env();
+        let mut vars: Vec<(&str, &str)> = env.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        vars.extend_from_slice(extra);
+        Server::start(client, &vars).await
+    }
+
+    fn database(&self, tenant: &str) -> PathBuf {
+        self.dir.join("db").join(format!("{tenant}.db"))
+    }
+}
+
+impl Drop for Tenants {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[tokio::test]
+async fn unknown_tenants_are_refused_without_a_database() {
+    let tenants = Tenants::new();
+    let client = Client::new();
+    let server = tenants.start(&client, &[]).await;
+    assert!(tenants.database("acme").exists(), "listed tenants are provisioned at startup");
+
+    let todos = format!("{}/todos", server.base_url);
+    let unknown = client.get(&todos).header("X-Tenant-ID", "globex").send().await.unwrap();
+    assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+    assert!(!tenants.database("globex").exists());
+
+    let created = client
+        .post(&todos)
+        .header("X-Tenant-ID", "acme")
+        .json(&json!({ "title": "Only acme sees this" }))
+        .send()
+        .await
+        .unwrap();
+    assert!(created.status().is_success(), "{}", created.status());
+    let shared = client.get(&todos).send().await.unwrap().text().await.unwrap();
+    assert!(!shared.contains("Only acme sees this"));
+}
+
+#[tokio::test]
+async fn only_members_reach_a_tenant() {
+    let tenants = Tenants::new();
+    let client = Client::new();
+    let server = tenants
+        .start(
+            &client,
+            &[
+                ("JWT_SECRET", "tenancy-test-secret-0123456789abcdef"),
+                ("AUTH_REQUIRED", "true"),
+            ],
+        )
+        .await;
+
+    let mut tokens = Vec::new();
+    for email in ["alice@example.com", "mallory@example.com"] {
+        let credentials = json!({ "email": email, "password": "correct horse battery" });
+        let register = client.post(format!("{}/auth/register", server.base_url)).json(&credentials);
+        assert_eq!(register.send().await.unwrap().status(), StatusCode::CREATED);
+        let login = client.post(format!("{}/auth/login", server.base_url)).json(&credentials);
+        let session: Value = login.send().await.unwrap().json().await.unwrap();
+        tokens.push(session["access_token"].as_str().unwrap().to_string());
+    }
+
+    let todos = format!("{}/todos", server.base_url);
+    for (token, expected) in tokens.iter().zip([StatusCode::OK, StatusCode::FORBIDDEN]) {
+        let response = client
+            .get(&todos)
+            .header("X-Tenant-ID", "acme")
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), expected);
+    }
+}
+
+#[tokio::test]
+async fn routes_on_the_shared_database_refuse_the_header() {
+    let tenants = Tenants::new();
+    let client = Client::new();
+    let server = tenants.start(&client, &[]).await;
+
+    let comments = format!("/todos/{}/comments", Uuid::new_v4());
+    for path in ["/tags", "/todos/tags", "/sync", &comments] {
+        let url = format!("{}{path}", server.base_url);
+        let response = client.get(&url).header("X-Tenant-ID", "acme").send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{path}");
+    }
+    let todos = format!("{}/todos", server.base_url);
+    let routed = client.get(&todos).header("X-Tenant-ID", "acme").send().await.unwrap();
+    assert_eq!(routed.status(), StatusCode::OK);
+}
@@ -0,0 +1,63 @@
+//! Checks a blank title is refused the same way on every route that writes a todo, dry runs
+//! included, rather than only in batches.
+
+mod common;
+
+use common::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn blank_titles_are_refused_on_every_write() {
+    let client = Client::new();
+    let server = Server::start(&client, &[]).await;
+    let url = |path: &str| format!("{}{path}", server.base_url);
+    let blank = json!({ "title": "   " });
+
+    for path in ["/todos", "/todos?dry_run=true"] {
+        let response = client.post(url(path)).json(&blank).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY, "{path}");
+        assert_eq!(response.text().await.unwrap(), "title must not be empty");
+    }
+
+    let created: Value = client
+        .post(url("/todos"))
+        .json(&json!({ "title": "Book flights" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let todo = format!("/todos/{}", created["id"].as_str().unwrap());
+    let update = client.put(url(&todo)).json(&json!({ "title": "" })).send().await.unwrap();
+    assert_eq!(update.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let batch: Value = client
+        .post(url("/todos/batch?atomic=false"))
+        .json(&json!({ "todos": [blank, { "title": "Pack" }] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(batch["errors"], json!([{ "index": 0, "error": "title must not be empty" }]), "{batch}");
+
+    let push = json!({ "mutations": [{ "op": "create", "id": uuid::Uuid::new_v4(), "todo": blank }] });
+    let pushed = client.post(url("/sync/push")).json(&push).send().await.unwrap();
+    assert_eq!(pushed.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let call = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": { "name": "create_todo", "arguments": blank },
+    });
+    let mcp: Value = client.post(url("/mcp")).json(&call).send().await.unwrap().json().await.unwrap();
+    assert_eq!(mcp["result"]["isError"], true, "{mcp}");
+
+    // Only the one valid todo and the one from the batch were stored
+    let todos: Value = client.get(url("/todos")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(todos.as_array().unwrap().len(), 2, "{todos}");
+}
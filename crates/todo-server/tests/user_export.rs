@@ -0,0 +1,79 @@
+//! Checks `GET /users/me/export` includes what the account wrote and can see, not just the
+//! account itself.
+
+mod common;
+
+use common::{Database, Server};
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+/// Registers `email` and returns a session token for it.
+async fn sign_up(client: &Client, server: &Server, email: &str) -> String {
+    let credentials = json!({ "email": email, "password": "correct horse battery" });
+    let register = client.post(format!("{}/auth/register", server.base_url)).json(&credentials);
+    assert_eq!(register.send().await.unwrap().status(), StatusCode::CREATED);
+    let login = client.post(format!("{}/auth/login", server.base_url)).json(&credentials);
+    let session: Value = login.send().await.unwrap().json().await.unwrap();
+    session["access_token"].as_str().unwrap().to_owned()
+}
+
+async fn export(client: &Client, server: &Server, token: &str) -> Value {
+    let response = client
+        .get(format!("{}/users/me/export", server.base_url))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn exports_hold_todos_comments_mentions_and_uploads() {
+    let client = Client::new();
+    let database = Database::new();
+    let server = Server::start_on(
+        &client,
+        &database,
+        &[("JWT_SECRET", "export-test-secret-0123456789abcdefgh"), ("AUTH_REQUIRED", "true")],
+    )
+    .await;
+    let author = sign_up(&client, &server, "author@example.com").await;
+    let reader = sign_up(&client, &server, "reader@example.com").await;
+    let profile = client
+        .patch(format!("{}/users/me", server.base_url))
+        .bearer_auth(&reader)
+        .json(&json!({ "username": "reader", "mention_channel": "feed" }));
+    assert_eq!(profile.send().await.unwrap().status(), StatusCode::OK);
+
+    let create = client
+        .post(format!("{}/todos", server.base_url))
+        .bearer_auth(&author)
+        .json(&json!({ "title": "Review the draft" }));
+    let todo: Value = create.send().await.unwrap().json().await.unwrap();
+    let todo_url = format!("{}/todos/{}", server.base_url, todo["id"].as_str().unwrap());
+    let comment = client
+        .post(format!("{todo_url}/comments"))
+        .bearer_auth(&author)
+        .json(&json!({ "body": "@reader could you look at this?" }));
+    assert_eq!(comment.send().await.unwrap().status(), StatusCode::CREATED);
+    let upload = client
+        .post(format!("{todo_url}/attachments?filename=draft.txt"))
+        .bearer_auth(&author)
+        .header("Content-Type", "text/plain")
+        .body("First draft");
+    assert_eq!(upload.send().await.unwrap().status(), StatusCode::CREATED);
+
+    let exported = export(&client, &server, &author).await;
+    assert_eq!(exported["todos"][0]["title"], "Review the draft");
+    assert_eq!(exported["comments"][0]["body"], "@reader could you look at this?");
+    assert_eq!(exported["attachments"][0]["filename"], "draft.txt");
+    assert_eq!(exported["mentions"], json!([]));
+
+    // The reader wrote and uploaded nothing, but was mentioned and sees the todo
+    let exported = export(&client, &server, &reader).await;
+    assert_eq!(exported["todos"].as_array().unwrap().len(), 1);
+    assert_eq!(exported["comments"], json!([]));
+    assert_eq!(exported["attachments"], json!([]));
+    assert_eq!(exported["mentions"][0]["excerpt"], "@reader could you look at this?");
+}
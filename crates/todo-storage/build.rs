@@ -0,0 +1,4 @@
+// Re-embed migrations when a new file is added to the migrations directory.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,2 @@
+-- Key id used to encrypt the description; NULL means the description is stored in plaintext
+ALTER TABLE todos ADD COLUMN description_key_id TEXT;
@@ -0,0 +1,14 @@
+-- Pending notification deliveries, so queued and retrying sends survive a restart
+CREATE TABLE IF NOT EXISTS notification_outbox (
+    id TEXT PRIMARY KEY,
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- Unix milliseconds; while a worker holds the row this doubles as its lease expiry
+    next_attempt_at INTEGER NOT NULL,
+    last_error TEXT,
+    -- Set once retries are exhausted; failed rows are kept for inspection
+    failed_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_outbox_due ON notification_outbox(failed_at, next_attempt_at);
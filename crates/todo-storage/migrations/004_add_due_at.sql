@@ -0,0 +1,4 @@
+-- Optional deadline (RFC 3339, UTC); NULL means the todo has no due date
+ALTER TABLE todos ADD COLUMN due_at TEXT;
+
+CREATE INDEX idx_todos_open_due ON todos (completed, due_at);
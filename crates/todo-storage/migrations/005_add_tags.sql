@@ -0,0 +1,2 @@
+-- Tags as a JSON array of strings, e.g. '["home","urgent"]'
+ALTER TABLE todos ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
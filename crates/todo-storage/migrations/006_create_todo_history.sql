@@ -0,0 +1,10 @@
+-- Per-todo event history; `detail` is a JSON object whose shape depends on `event`
+CREATE TABLE todo_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    todo_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_todo_history_todo ON todo_history (todo_id, id);
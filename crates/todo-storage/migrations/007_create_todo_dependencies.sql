@@ -0,0 +1,9 @@
+-- `blocker_id` blocks `blocked_id`: the blocked todo can't start until the blocker is completed
+CREATE TABLE todo_dependencies (
+    blocker_id TEXT NOT NULL,
+    blocked_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (blocker_id, blocked_id)
+);
+
+CREATE INDEX idx_todo_dependencies_blocked ON todo_dependencies (blocked_id);
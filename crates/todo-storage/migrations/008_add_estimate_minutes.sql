@@ -0,0 +1,2 @@
+-- Estimated effort in minutes; NULL when not estimated
+ALTER TABLE todos ADD COLUMN estimate_minutes INTEGER;
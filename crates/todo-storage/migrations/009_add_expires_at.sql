@@ -0,0 +1,4 @@
+-- When an ephemeral todo stops being listed and becomes eligible for the expiry sweep
+ALTER TABLE todos ADD COLUMN expires_at TEXT;
+
+CREATE INDEX idx_todos_expires_at ON todos (expires_at) WHERE expires_at IS NOT NULL;
@@ -0,0 +1,12 @@
+-- Refresh tokens, stored as SHA-256 hashes. Each login starts a session (`family_id`), and
+-- every refresh revokes the presented token and adds its replacement to the same family.
+CREATE TABLE refresh_tokens (
+    token_hash TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    family_id TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    revoked_at TEXT
+);
+
+CREATE INDEX idx_refresh_tokens_family ON refresh_tokens (family_id);
@@ -0,0 +1,13 @@
+-- Personal access tokens, stored as SHA-256 hashes. `scopes` is a space-separated list.
+CREATE TABLE api_tokens (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT,
+    revoked_at TEXT
+);
+
+CREATE INDEX idx_api_tokens_user ON api_tokens (user_id);
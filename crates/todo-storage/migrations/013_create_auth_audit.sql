@@ -0,0 +1,15 @@
+-- Append-only log of authentication and admin events. `user_id` and `email` are whatever
+-- was known at the time; `detail` is a JSON object whose shape depends on `event`.
+CREATE TABLE auth_audit (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event TEXT NOT NULL,
+    user_id TEXT,
+    email TEXT,
+    ip TEXT,
+    user_agent TEXT,
+    detail TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_auth_audit_created ON auth_audit (created_at);
+CREATE INDEX idx_auth_audit_user ON auth_audit (user_id, id);
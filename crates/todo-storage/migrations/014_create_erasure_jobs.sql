@@ -0,0 +1,12 @@
+-- Account erasure requests. `user_id` and `email` are only kept until the job completes,
+-- so a finished job says that an erasure happened but not whose.
+CREATE TABLE erasure_jobs (
+    id TEXT PRIMARY KEY,
+    user_id TEXT,
+    email TEXT,
+    status TEXT NOT NULL,
+    steps_completed INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
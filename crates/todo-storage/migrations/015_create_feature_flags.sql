@@ -0,0 +1,7 @@
+-- Runtime feature flags, toggled through /admin/flags. A flag with no row has its default.
+CREATE TABLE feature_flags (
+    name TEXT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL,
+    description TEXT,
+    updated_at TEXT NOT NULL
+);
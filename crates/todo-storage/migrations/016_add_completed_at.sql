@@ -0,0 +1,6 @@
+-- When the todo was last marked completed; NULL while open. Todos completed before this
+-- column existed take their last update time.
+ALTER TABLE todos ADD COLUMN completed_at TEXT;
+UPDATE todos SET completed_at = updated_at WHERE completed = 1;
+
+CREATE INDEX idx_todos_completed_at ON todos(completed_at);
@@ -0,0 +1,65 @@
+-- Change tracking for `GET /sync`. Every write to a todo takes the next value of the change
+-- sequence, and deletions leave a tombstone, so a client's token (the highest sequence it
+-- has seen) is enough to find everything it missed. Triggers keep this up to date for every
+-- write path, including imports, the expiry sweep and restores.
+CREATE TABLE sync_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    change_seq INTEGER NOT NULL,
+    -- Tombstones up to this sequence have been pruned, so older tokens can't be served
+    pruned_through INTEGER NOT NULL DEFAULT 0
+);
+
+ALTER TABLE todos ADD COLUMN change_seq INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE todos ADD COLUMN created_seq INTEGER NOT NULL DEFAULT 0;
+
+-- Existing todos count as created, in insertion order, before the first token
+UPDATE todos SET change_seq = rowid, created_seq = rowid;
+INSERT INTO sync_state (id, change_seq) SELECT 1, COALESCE(MAX(change_seq), 0) FROM todos;
+
+CREATE INDEX idx_todos_change_seq ON todos(change_seq);
+
+CREATE TABLE todo_tombstones (
+    id TEXT PRIMARY KEY,
+    change_seq INTEGER NOT NULL,
+    deleted_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_todo_tombstones_change_seq ON todo_tombstones(change_seq);
+
+CREATE TRIGGER todos_sync_insert AFTER INSERT ON todos
+BEGIN
+    UPDATE sync_state SET change_seq = change_seq + 1;
+    UPDATE todos
+    SET change_seq = (SELECT change_seq FROM sync_state),
+        created_seq = (SELECT change_seq FROM sync_state)
+    WHERE id = NEW.id;
+    DELETE FROM todo_tombstones WHERE id = NEW.id;
+END;
+
+-- Skips the trigger's own update of `change_seq`. Completing or reopening a todo also changes
+-- the computed `blocked` flag of the todos it blocks, so they move forward too.
+CREATE TRIGGER todos_sync_update AFTER UPDATE ON todos
+WHEN NEW.change_seq = OLD.change_seq
+BEGIN
+    UPDATE sync_state SET change_seq = change_seq + 1;
+    UPDATE todos
+    SET change_seq = (SELECT change_seq FROM sync_state)
+    WHERE id = NEW.id
+        OR (NEW.completed IS NOT OLD.completed
+            AND id IN (SELECT blocked_id FROM todo_dependencies WHERE blocker_id = NEW.id));
+END;
+
+CREATE TRIGGER todos_sync_delete AFTER DELETE ON todos
+BEGIN
+    UPDATE sync_state SET change_seq = change_seq + 1;
+    INSERT INTO todo_tombstones (id, change_seq, deleted_at)
+    VALUES (OLD.id, (SELECT change_seq FROM sync_state), strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+    ON CONFLICT (id) DO UPDATE SET change_seq = excluded.change_seq, deleted_at = excluded.deleted_at;
+END;
+
+-- Removing a dependency, including when its blocker is deleted, can unblock the other todo
+CREATE TRIGGER todo_dependencies_sync_delete AFTER DELETE ON todo_dependencies
+BEGIN
+    UPDATE sync_state SET change_seq = change_seq + 1;
+    UPDATE todos SET change_seq = (SELECT change_seq FROM sync_state) WHERE id = OLD.blocked_id;
+END;
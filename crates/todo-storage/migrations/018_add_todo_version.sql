@@ -0,0 +1,20 @@
+-- Per-todo edit counter for `POST /sync/push`. Offline clients send back the version they
+-- last saw, and an edit made on the server since then is reported as a conflict. Unlike
+-- `change_seq` it only moves when the todo itself is written, not when a blocker changes.
+ALTER TABLE todos ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+
+-- Writes that bump `version` themselves (to read it back with RETURNING) are left alone;
+-- every other write path gets it bumped here.
+DROP TRIGGER todos_sync_update;
+
+CREATE TRIGGER todos_sync_update AFTER UPDATE ON todos
+WHEN NEW.change_seq = OLD.change_seq
+BEGIN
+    UPDATE sync_state SET change_seq = change_seq + 1;
+    UPDATE todos
+    SET change_seq = (SELECT change_seq FROM sync_state),
+        version = CASE WHEN id = NEW.id AND NEW.version = OLD.version THEN version + 1 ELSE version END
+    WHERE id = NEW.id
+        OR (NEW.completed IS NOT OLD.completed
+            AND id IN (SELECT blocked_id FROM todo_dependencies WHERE blocker_id = NEW.id));
+END;
@@ -0,0 +1,22 @@
+-- Shared lists. Members see a project's todos according to their role: `owner` manages
+-- members, `editor` changes todos, `viewer` only reads. Todos outside any project have no
+-- `project_id` and stay open to everyone, as before.
+CREATE TABLE projects (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE project_members (
+    project_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    added_at TEXT NOT NULL,
+    PRIMARY KEY (project_id, user_id)
+);
+
+CREATE INDEX idx_project_members_user ON project_members (user_id);
+
+ALTER TABLE todos ADD COLUMN project_id TEXT;
+
+CREATE INDEX idx_todos_project ON todos (project_id) WHERE project_id IS NOT NULL;
@@ -0,0 +1,31 @@
+-- @mentions: handles to mention people by, how each person wants to hear about it, comments
+-- to mention them in, and the per-user activity feed every mention is recorded in
+ALTER TABLE users ADD COLUMN username TEXT COLLATE NOCASE;
+ALTER TABLE users ADD COLUMN mention_channel TEXT NOT NULL DEFAULT 'email';
+
+CREATE UNIQUE INDEX idx_users_username ON users(username) WHERE username IS NOT NULL;
+
+CREATE TABLE todo_comments (
+    id TEXT PRIMARY KEY,
+    todo_id TEXT NOT NULL,
+    -- NULL when written without an account, or once the author's account is erased
+    author_id TEXT,
+    body TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_todo_comments_todo ON todo_comments(todo_id, created_at);
+
+CREATE TABLE activity (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    todo_id TEXT NOT NULL,
+    -- Set when the mention was in a comment rather than the todo's description
+    comment_id TEXT,
+    actor_id TEXT,
+    excerpt TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_activity_user ON activity(user_id, id);
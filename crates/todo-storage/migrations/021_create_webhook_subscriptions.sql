@@ -0,0 +1,11 @@
+-- Webhook receivers registered through `/admin/webhooks`, each with the events it wants and
+-- the payload shape it was written against, so new todo fields don't break it
+CREATE TABLE webhook_subscriptions (
+    id TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    -- JSON array of event names, e.g. ["todo.created"]
+    events TEXT NOT NULL,
+    format TEXT NOT NULL,
+    api_version INTEGER NOT NULL,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,19 @@
+-- Custom fields: typed fields defined at runtime through `/admin/custom-fields`, so teams
+-- can track things like a customer or sprint on todos, and each todo's values for them
+CREATE TABLE custom_field_defs (
+    name TEXT PRIMARY KEY,
+    -- text, number, boolean or date
+    type TEXT NOT NULL,
+    required BOOLEAN NOT NULL DEFAULT false,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE custom_field_values (
+    todo_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    -- The value as JSON, already checked against the field's type
+    value TEXT NOT NULL,
+    PRIMARY KEY (todo_id, name)
+);
+
+CREATE INDEX idx_custom_field_values_name ON custom_field_values(name, value);
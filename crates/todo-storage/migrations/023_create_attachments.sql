@@ -0,0 +1,13 @@
+-- Files attached to todos. The bytes live in the configured blob store under `blob_key`;
+-- rows outliving their todo are swept up along with their blobs
+CREATE TABLE attachments (
+    id TEXT PRIMARY KEY,
+    todo_id TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    blob_key TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_attachments_todo ON attachments(todo_id, created_at);
@@ -0,0 +1,13 @@
+-- Hourly request counts per client and endpoint, for `GET /admin/usage`. `client` is
+-- `token:<id>`, `user:<id>`, `key:<hash of X-API-Key>` or `ip:<address>`
+CREATE TABLE api_usage (
+    hour TEXT NOT NULL,
+    client TEXT NOT NULL,
+    user_id TEXT,
+    method TEXT NOT NULL,
+    route TEXT NOT NULL,
+    requests INTEGER NOT NULL DEFAULT 0,
+    client_errors INTEGER NOT NULL DEFAULT 0,
+    server_errors INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (hour, client, method, route)
+);
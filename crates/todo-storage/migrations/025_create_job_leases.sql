@@ -0,0 +1,8 @@
+-- Which replica runs each scheduled job. A lease is held until `expires_at` (Unix millis);
+-- its holder renews it while it keeps running the job, and anyone may take it once expired
+CREATE TABLE job_leases (
+    job TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    acquired_at TEXT NOT NULL,
+    expires_at INTEGER NOT NULL
+);
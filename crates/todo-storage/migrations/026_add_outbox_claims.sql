@@ -0,0 +1,5 @@
+-- Who is delivering an outbox row. A claim lasts until the row's lease (`next_attempt_at`)
+-- runs out; `claimed_at` changes with every claim, so a worker can tell whether the row is
+-- still its own before it finishes with it
+ALTER TABLE notification_outbox ADD COLUMN claimed_by TEXT;
+ALTER TABLE notification_outbox ADD COLUMN claimed_at TEXT;
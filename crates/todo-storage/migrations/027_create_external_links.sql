@@ -0,0 +1,11 @@
+-- Which todo stands for an item in another system, such as a GitHub issue, so later events
+-- about the same item find it again. A link outliving its todo is replaced on the next create
+CREATE TABLE todo_external_links (
+    source TEXT NOT NULL,
+    external_id TEXT NOT NULL,
+    todo_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (source, external_id)
+);
+
+CREATE INDEX idx_external_links_todo ON todo_external_links(todo_id);
@@ -0,0 +1,3 @@
+-- The item's state in the other system when it was last looked at, such as a Jira issue's
+-- status category, so only changes to it are reflected on the todo
+ALTER TABLE todo_external_links ADD COLUMN remote_state TEXT;
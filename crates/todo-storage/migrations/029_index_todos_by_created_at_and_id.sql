@@ -0,0 +1,4 @@
+-- Keyset pages walk the list by (created_at, id), newest first, which this index serves
+-- directly; it also covers everything the created_at index did
+CREATE INDEX idx_todos_created_at_id ON todos (created_at DESC, id DESC);
+DROP INDEX IF EXISTS idx_todos_created_at;
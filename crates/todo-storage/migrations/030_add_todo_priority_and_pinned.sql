@@ -0,0 +1,3 @@
+-- How much a todo matters and whether it's pinned, both weighed by `GET /todos/next`
+ALTER TABLE todos ADD COLUMN priority TEXT CHECK (priority IN ('high', 'medium', 'low'));
+ALTER TABLE todos ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,9 @@
+-- Color and description of a tag; the tags themselves live on `todos.tags`, so a tag can be
+-- in use without a row here, and can have a row before any todo carries it
+CREATE TABLE tags (
+    name TEXT PRIMARY KEY,
+    color TEXT,
+    description TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
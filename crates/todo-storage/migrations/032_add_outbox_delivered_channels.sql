@@ -0,0 +1,3 @@
+-- The channels that already took an outbox row's notification, as a JSON array of names, so
+-- a retry only goes to the channels that failed
+ALTER TABLE notification_outbox ADD COLUMN delivered_channels TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,2 @@
+-- Accounts with the `admin` scope, granted by an operator with `todo grant-admin`
+ALTER TABLE users ADD COLUMN admin BOOLEAN NOT NULL DEFAULT false;
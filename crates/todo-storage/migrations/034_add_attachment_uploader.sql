@@ -0,0 +1,3 @@
+-- Who uploaded each attachment, for their data export. NULL for uploads without an account,
+-- those from before this column, and once the uploader's account is erased
+ALTER TABLE attachments ADD COLUMN uploaded_by TEXT;
@@ -0,0 +1,271 @@
+-- Postgres schema matching the SQLite migrations, created by `todo migrate-data`.
+-- Column types and text formats are kept as they are in SQLite (timestamps as RFC 3339
+-- text, tags and details as JSON text), so rows copy across unchanged.
+
+-- Re-runs would otherwise log a notice for every table and index that already exists
+SET client_min_messages TO warning;
+
+CREATE TABLE IF NOT EXISTS todos (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    description TEXT,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    description_key_id TEXT,
+    due_at TEXT,
+    tags TEXT NOT NULL DEFAULT '[]',
+    estimate_minutes BIGINT,
+    expires_at TEXT,
+    completed_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_todos_completed ON todos (completed);
+CREATE INDEX IF NOT EXISTS idx_todos_created_at ON todos (created_at);
+CREATE INDEX IF NOT EXISTS idx_todos_created_at_id ON todos (created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_todos_open_due ON todos (completed, due_at);
+CREATE INDEX IF NOT EXISTS idx_todos_expires_at ON todos (expires_at) WHERE expires_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_todos_completed_at ON todos (completed_at);
+
+-- Added after the first release of this schema, so targets created by it get them too
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS change_seq BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS created_seq BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS project_id TEXT;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS priority TEXT;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS pinned BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX IF NOT EXISTS idx_todos_change_seq ON todos (change_seq);
+
+CREATE TABLE IF NOT EXISTS todo_tombstones (
+    id TEXT PRIMARY KEY,
+    change_seq BIGINT NOT NULL,
+    deleted_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_todo_tombstones_change_seq ON todo_tombstones (change_seq);
+
+CREATE TABLE IF NOT EXISTS sync_state (
+    id BIGINT PRIMARY KEY CHECK (id = 1),
+    change_seq BIGINT NOT NULL,
+    pruned_through BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS notification_outbox (
+    id TEXT PRIMARY KEY,
+    payload TEXT NOT NULL,
+    attempts BIGINT NOT NULL DEFAULT 0,
+    next_attempt_at BIGINT NOT NULL,
+    last_error TEXT,
+    failed_at TEXT,
+    created_at TEXT NOT NULL,
+    claimed_by TEXT,
+    claimed_at TEXT,
+    delivered_channels TEXT NOT NULL DEFAULT '[]'
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_outbox_due ON notification_outbox (failed_at, next_attempt_at);
+
+CREATE TABLE IF NOT EXISTS todo_history (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    todo_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_todo_history_todo ON todo_history (todo_id, id);
+
+CREATE TABLE IF NOT EXISTS todo_dependencies (
+    blocker_id TEXT NOT NULL,
+    blocked_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (blocker_id, blocked_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_todo_dependencies_blocked ON todo_dependencies (blocked_id);
+
+-- SQLite compares emails with COLLATE NOCASE; here uniqueness is on the lowercased email
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    password_hash TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_email ON users (lower(email));
+ALTER TABLE users ADD COLUMN IF NOT EXISTS username TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS mention_channel TEXT NOT NULL DEFAULT 'email';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS admin BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_username ON users (lower(username)) WHERE username IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    token_hash TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    family_id TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    revoked_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_family ON refresh_tokens (family_id);
+
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT,
+    revoked_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_tokens_user ON api_tokens (user_id);
+
+CREATE TABLE IF NOT EXISTS auth_audit (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    event TEXT NOT NULL,
+    user_id TEXT,
+    email TEXT,
+    ip TEXT,
+    user_agent TEXT,
+    detail TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_audit_created ON auth_audit (created_at);
+CREATE INDEX IF NOT EXISTS idx_auth_audit_user ON auth_audit (user_id, id);
+
+CREATE TABLE IF NOT EXISTS erasure_jobs (
+    id TEXT PRIMARY KEY,
+    user_id TEXT,
+    email TEXT,
+    status TEXT NOT NULL,
+    steps_completed BIGINT NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS feature_flags (
+    name TEXT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL,
+    description TEXT,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS projects (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS project_members (
+    project_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    added_at TEXT NOT NULL,
+    PRIMARY KEY (project_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_project_members_user ON project_members (user_id);
+CREATE INDEX IF NOT EXISTS idx_todos_project ON todos (project_id) WHERE project_id IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS todo_comments (
+    id TEXT PRIMARY KEY,
+    todo_id TEXT NOT NULL,
+    author_id TEXT,
+    body TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_todo_comments_todo ON todo_comments (todo_id, created_at);
+
+CREATE TABLE IF NOT EXISTS activity (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    todo_id TEXT NOT NULL,
+    comment_id TEXT,
+    actor_id TEXT,
+    excerpt TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_activity_user ON activity (user_id, id);
+
+CREATE TABLE IF NOT EXISTS webhook_subscriptions (
+    id TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    events TEXT NOT NULL,
+    format TEXT NOT NULL,
+    api_version BIGINT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS custom_field_defs (
+    name TEXT PRIMARY KEY,
+    type TEXT NOT NULL,
+    required BOOLEAN NOT NULL DEFAULT false,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS custom_field_values (
+    todo_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (todo_id, name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_custom_field_values_name ON custom_field_values (name, value);
+
+CREATE TABLE IF NOT EXISTS attachments (
+    id TEXT PRIMARY KEY,
+    todo_id TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    blob_key TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_attachments_todo ON attachments (todo_id, created_at);
+ALTER TABLE attachments ADD COLUMN IF NOT EXISTS uploaded_by TEXT;
+
+CREATE TABLE IF NOT EXISTS api_usage (
+    hour TEXT NOT NULL,
+    client TEXT NOT NULL,
+    user_id TEXT,
+    method TEXT NOT NULL,
+    route TEXT NOT NULL,
+    requests BIGINT NOT NULL DEFAULT 0,
+    client_errors BIGINT NOT NULL DEFAULT 0,
+    server_errors BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (hour, client, method, route)
+);
+
+CREATE TABLE IF NOT EXISTS job_leases (
+    job TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    acquired_at TEXT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS todo_external_links (
+    source TEXT NOT NULL,
+    external_id TEXT NOT NULL,
+    todo_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    remote_state TEXT,
+    PRIMARY KEY (source, external_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_external_links_todo ON todo_external_links (todo_id);
+
+CREATE TABLE IF NOT EXISTS tags (
+    name TEXT PRIMARY KEY,
+    color TEXT,
+    description TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
@@ -0,0 +1,297 @@
+use crate::repository::{RepositoryError, SqliteTodoRepository};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{info, instrument, warn};
+
+/// The oldest schema a snapshot can be restored from. Columns added since come back with
+/// their defaults.
+pub const MIN_RESTORABLE_VERSION: i64 = 9;
+
+/// Checks a snapshot at schema `version` can be restored into a database at `latest`,
+/// returning the version, or why it can't. Both restores and `verify_snapshot` go through
+/// this, so a snapshot that verifies is one a restore accepts.
+pub fn check_restorable(version: Option<i64>, latest: i64) -> Result<i64, String> {
+    match version {
+        None => Err("no migration table; not a todo database".to_owned()),
+        Some(version) if version > latest => {
+            Err(format!("version {version} is newer than this build's {latest}; restore with a newer server"))
+        }
+        Some(version) if version < MIN_RESTORABLE_VERSION => {
+            Err(format!("version {version} is older than {MIN_RESTORABLE_VERSION}, the oldest restores read"))
+        }
+        Some(version) => Ok(version),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("Repository error: {0}")]
+    Repository(#[from] RepositoryError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid snapshot name: {0}")]
+    InvalidName(String),
+
+    #[error("Snapshot not found: {0}")]
+    NotFound(String),
+
+    #[error("Checksum mismatch for snapshot {0}")]
+    ChecksumMismatch(String),
+
+    #[error("Invalid manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub checksum: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Written next to each snapshot as `<snapshot>.manifest.json`: what the snapshot held when it
+/// was taken, for `verify_snapshot` to compare a copy against later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub checksum: String,
+    pub created_at: DateTime<Utc>,
+    pub schema_version: Option<i64>,
+    pub row_counts: BTreeMap<String, u64>,
+}
+
+impl Manifest {
+    /// Where the manifest of the snapshot at `path` goes.
+    pub fn path_for(snapshot: &Path) -> PathBuf {
+        let mut name = snapshot.as_os_str().to_owned();
+        name.push(".manifest.json");
+        PathBuf::from(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Passed,
+    /// Nothing to check against, such as a snapshot taken before manifests were written.
+    Skipped,
+    Failed,
+}
+
+/// The outcome of one of `verify_snapshot`'s checks.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// What `verify_snapshot` found.
+#[derive(Debug, Clone, Serialize)]
+pub struct Verification {
+    pub path: PathBuf,
+    pub checks: Vec<Check>,
+}
+
+impl Verification {
+    /// Whether `POST /admin/restore` can be trusted with the snapshot: no check failed.
+    pub fn restorable(&self) -> bool {
+        self.checks.iter().all(|check| check.status != CheckStatus::Failed)
+    }
+}
+
+/// Checks the snapshot at `path` can be restored, without touching any live database: its
+/// checksum against the manifest, SQLite's `integrity_check`, that its schema is one this
+/// build restores from, and its row counts against the manifest. Only a snapshot that can't
+/// be read at all is an error; everything else is a failed check.
+#[instrument(skip(encryption_key), fields(backup.path = %path.display()))]
+pub async fn verify_snapshot(path: &Path, encryption_key: Option<&str>) -> Result<Verification, BackupError> {
+    if !tokio::fs::try_exists(path).await? {
+        return Err(BackupError::NotFound(path.display().to_string()));
+    }
+    let mut checks = Vec::new();
+
+    let checksum = checksum_file(path).await?;
+    let manifest = match tokio::fs::read(Manifest::path_for(path)).await {
+        Ok(bytes) => Some(serde_json::from_slice::<Manifest>(&bytes)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    checks.push(match &manifest {
+        Some(manifest) if manifest.checksum.eq_ignore_ascii_case(&checksum) => {
+            Check::new("checksum", CheckStatus::Passed, format!("SHA-256 {checksum} matches the manifest"))
+        }
+        Some(manifest) => Check::new(
+            "checksum",
+            CheckStatus::Failed,
+            format!("SHA-256 {checksum}, but the manifest says {}", manifest.checksum),
+        ),
+        None => Check::new("checksum", CheckStatus::Skipped, format!("SHA-256 {checksum}; no manifest to compare")),
+    });
+
+    let snapshot = match SqliteTodoRepository::open_snapshot(path, encryption_key).await {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            checks.push(Check::new("integrity", CheckStatus::Failed, format!("can't open the snapshot: {e}")));
+            return Ok(Verification { path: path.to_owned(), checks });
+        }
+    };
+
+    checks.push(match snapshot.integrity_check().await {
+        Ok(problems) if problems == ["ok"] => Check::new("integrity", CheckStatus::Passed, "ok"),
+        Ok(problems) => Check::new("integrity", CheckStatus::Failed, problems.join("; ")),
+        Err(e) => Check::new("integrity", CheckStatus::Failed, e.to_string()),
+    });
+
+    let stats = match snapshot.stats().await {
+        Ok(stats) => stats,
+        Err(e) => {
+            checks.push(Check::new("schema_version", CheckStatus::Failed, e.to_string()));
+            return Ok(Verification { path: path.to_owned(), checks });
+        }
+    };
+    let latest = SqliteTodoRepository::latest_schema_version();
+    checks.push(match check_restorable(stats.schema_version, latest) {
+        Err(reason) => Check::new("schema_version", CheckStatus::Failed, reason),
+        Ok(version) if manifest.as_ref().is_some_and(|m| m.schema_version != Some(version)) => Check::new(
+            "schema_version",
+            CheckStatus::Failed,
+            format!("version {version}, but the manifest says {:?}", manifest.as_ref().and_then(|m| m.schema_version)),
+        ),
+        Ok(version) => Check::new("schema_version", CheckStatus::Passed, format!("version {version} (this build: {latest})")),
+    });
+
+    let todos = stats.row_counts.get("todos").copied().unwrap_or_default();
+    checks.push(match &manifest {
+        Some(manifest) => {
+            let mismatches: Vec<String> = manifest
+                .row_counts
+                .iter()
+                .filter_map(|(table, &expected)| {
+                    let actual = stats.row_counts.get(table).copied();
+                    (actual != Some(expected)).then(|| match actual {
+                        Some(actual) => format!("{table}: {actual} rows, manifest says {expected}"),
+                        None => format!("{table}: missing, manifest says {expected} rows"),
+                    })
+                })
+                .collect();
+            if mismatches.is_empty() {
+                Check::new(
+                    "row_counts",
+                    CheckStatus::Passed,
+                    format!("{} tables match the manifest ({todos} todos)", manifest.row_counts.len()),
+                )
+            } else {
+                Check::new("row_counts", CheckStatus::Failed, mismatches.join("; "))
+            }
+        }
+        None => Check::new("row_counts", CheckStatus::Skipped, format!("{todos} todos; no manifest to compare")),
+    });
+
+    Ok(Verification { path: path.to_owned(), checks })
+}
+
+/// Takes and restores online snapshots of the SQLite database in a dedicated directory, each
+/// with its manifest alongside.
+pub struct BackupService {
+    repository: Arc<SqliteTodoRepository>,
+    backup_dir: PathBuf,
+}
+
+impl BackupService {
+    pub fn new(repository: Arc<SqliteTodoRepository>, backup_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            repository,
+            backup_dir: backup_dir.into(),
+        }
+    }
+
+    #[instrument(skip(self), fields(backup.dir = %self.backup_dir.display()))]
+    pub async fn create_snapshot(&self) -> Result<Snapshot, BackupError> {
+        tokio::fs::create_dir_all(&self.backup_dir).await?;
+
+        let created_at = self.repository.clock().now();
+        let file_name = format!("todos-{}.db", created_at.format("%Y%m%dT%H%M%S%.3fZ"));
+        let path = self.backup_dir.join(file_name);
+
+        self.repository.snapshot_to(&path).await?;
+
+        let checksum = checksum_file(&path).await?;
+        let size_bytes = tokio::fs::metadata(&path).await?.len();
+
+        let stats = self.repository.snapshot_stats(&path).await?;
+        let manifest = Manifest {
+            checksum: checksum.clone(),
+            created_at,
+            schema_version: stats.schema_version,
+            row_counts: stats.row_counts,
+        };
+        tokio::fs::write(Manifest::path_for(&path), serde_json::to_vec_pretty(&manifest)?).await?;
+
+        info!(path = %path.display(), size_bytes, "Backup snapshot created");
+        Ok(Snapshot {
+            path,
+            checksum,
+            size_bytes,
+            created_at,
+        })
+    }
+
+    /// Restores the snapshot called `name` from the backup directory. When `expected_checksum`
+    /// is given, the file must match it before anything is touched.
+    #[instrument(skip(self, expected_checksum))]
+    pub async fn restore_snapshot(
+        &self,
+        name: &str,
+        expected_checksum: Option<&str>,
+    ) -> Result<u64, BackupError> {
+        let path = self.resolve(name)?;
+        if !tokio::fs::try_exists(&path).await? {
+            return Err(BackupError::NotFound(name.to_string()));
+        }
+
+        if let Some(expected) = expected_checksum {
+            let actual = checksum_file(&path).await?;
+            if !actual.eq_ignore_ascii_case(expected) {
+                warn!(expected, actual, "Snapshot checksum mismatch");
+                return Err(BackupError::ChecksumMismatch(name.to_string()));
+            }
+        }
+
+        Ok(self.repository.restore_from(&path).await?)
+    }
+
+    /// Only bare file names are accepted so restore can't be pointed outside the backup directory.
+    fn resolve(&self, name: &str) -> Result<PathBuf, BackupError> {
+        let candidate = Path::new(name);
+        match candidate.file_name() {
+            Some(file_name) if file_name == candidate.as_os_str() => {
+                Ok(self.backup_dir.join(file_name))
+            }
+            _ => Err(BackupError::InvalidName(name.to_string())),
+        }
+    }
+}
+
+async fn checksum_file(path: &Path) -> Result<String, std::io::Error> {
+    let bytes = tokio::fs::read(path).await?;
+    Ok(hex::encode(Sha256::digest(&bytes)))
+}
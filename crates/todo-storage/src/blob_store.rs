@@ -0,0 +1,320 @@
+//! Where attachment bytes live: a directory on the local disk, or an S3-compatible bucket
+//! so uploads stay off the application host. Metadata stays in the repository; stores only
+//! map keys to bytes.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::{io::ErrorKind, path::PathBuf, sync::Arc, time::Duration};
+use todo_domain::clock::{self, Clock};
+use tracing::{info, instrument};
+
+/// Longest lifetime S3 accepts for a presigned URL: seven days.
+const MAX_PRESIGN_SECS: u64 = 7 * 24 * 3600;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlobError {
+    #[error("Blob not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid blob key: {0}")]
+    InvalidKey(String),
+
+    #[error("Invalid object store configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Object store request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Object store answered {status}: {body}")]
+    Status { status: u16, body: String },
+}
+
+/// Bytes stored under string keys such as `todos/<todo id>/<attachment id>`. Keys are made
+/// of `/`-separated segments without `.` or `..`.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Stores `data` under `key`, replacing anything already there.
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), BlobError>;
+    /// Fails with `NotFound` if nothing is stored under `key`.
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError>;
+    /// Deleting a key that holds nothing succeeds.
+    async fn delete(&self, key: &str) -> Result<(), BlobError>;
+    /// A URL clients can fetch the blob from directly for `expires_in`, or `None` if the
+    /// store has no such URLs and the API has to serve the bytes itself.
+    fn presign(&self, key: &str, expires_in: Duration) -> Result<Option<String>, BlobError>;
+    /// Short name for logs and spans.
+    fn kind(&self) -> &'static str;
+}
+
+fn check_key(key: &str) -> Result<(), BlobError> {
+    let valid = !key.is_empty()
+        && key
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != ".." && !segment.contains('\\'));
+    if valid {
+        Ok(())
+    } else {
+        Err(BlobError::InvalidKey(key.to_owned()))
+    }
+}
+
+/// Blobs as files under a directory, one per key.
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, BlobError> {
+        check_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    #[instrument(skip(self, data), fields(blob.store = "local", blob.size = data.len()))]
+    async fn put(&self, key: &str, _content_type: &str, data: Vec<u8>) -> Result<(), BlobError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Written aside and renamed, so a reader never sees half a file
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), fields(blob.store = "local"))]
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(data),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(BlobError::NotFound(key.to_owned())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[instrument(skip(self), fields(blob.store = "local"))]
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn presign(&self, key: &str, _expires_in: Duration) -> Result<Option<String>, BlobError> {
+        check_key(key)?;
+        Ok(None)
+    }
+
+    fn kind(&self) -> &'static str {
+        "local"
+    }
+}
+
+/// Where an [`S3BlobStore`] keeps its objects, and the credentials it signs requests with.
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// `scheme://host[:port]` of the S3 API, e.g. `https://s3.eu-west-1.amazonaws.com` or a
+    /// MinIO server.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+/// Blobs as objects in an S3-compatible bucket, addressed path-style
+/// (`<endpoint>/<bucket>/<key>`) so MinIO and other S3 servers work too. Requests are signed
+/// with AWS Signature Version 4.
+pub struct S3BlobStore {
+    client: reqwest::Client,
+    config: S3Config,
+    /// `host[:port]` of the endpoint, as signed.
+    host: String,
+    clock: Arc<dyn Clock>,
+}
+
+impl S3BlobStore {
+    pub fn new(config: S3Config) -> Result<Self, BlobError> {
+        let endpoint = reqwest::Url::parse(&config.endpoint)
+            .map_err(|e| BlobError::InvalidConfig(format!("endpoint {}: {e}", config.endpoint)))?;
+        let host = match (endpoint.host_str(), endpoint.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_owned(),
+            (None, _) => return Err(BlobError::InvalidConfig(format!("endpoint {} has no host", config.endpoint))),
+        };
+        if config.bucket.is_empty() {
+            return Err(BlobError::InvalidConfig("bucket is empty".to_owned()));
+        }
+        info!(endpoint = %config.endpoint, bucket = %config.bucket, "Using S3 blob store");
+        Ok(Self {
+            client: reqwest::Client::new(),
+            config: S3Config {
+                endpoint: config.endpoint.trim_end_matches('/').to_owned(),
+                ..config
+            },
+            host,
+            clock: clock::system(),
+        })
+    }
+
+    /// Dates request signatures and presigned URLs with `clock`'s time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The object's path, URI-encoded as both the request and its signature need it.
+    fn object_path(&self, key: &str) -> Result<String, BlobError> {
+        check_key(key)?;
+        Ok(format!("/{}/{}", uri_encode(&self.config.bucket, true), uri_encode(key, false)))
+    }
+
+    fn scope(&self, date: &str) -> String {
+        format!("{date}/{}/s3/aws4_request", self.config.region)
+    }
+
+    /// Hex signature of the canonical request made of the given parts.
+    fn signature(&self, now: DateTime<Utc>, canonical_request: &str) -> String {
+        let date = now.format("%Y%m%d").to_string();
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            now.format("%Y%m%dT%H%M%SZ"),
+            self.scope(&date),
+            hex::encode(Sha256::digest(canonical_request))
+        );
+        let mut key = hmac(format!("AWS4{}", self.config.secret_access_key).as_bytes(), &date);
+        for part in [self.config.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part);
+        }
+        hex::encode(hmac(&key, &string_to_sign))
+    }
+
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        content_type: Option<&str>,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, BlobError> {
+        let path = self.object_path(key)?;
+        let now = self.clock.now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{path}\n\nhost:{}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n\
+             {signed_headers}\n{payload_hash}",
+            self.host
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={signed_headers}, Signature={}",
+            self.config.access_key_id,
+            self.scope(&now.format("%Y%m%d").to_string()),
+            self.signature(now, &canonical_request)
+        );
+
+        let mut request = self
+            .client
+            .request(method, format!("{}{path}", self.config.endpoint))
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header(reqwest::header::AUTHORIZATION, authorization);
+        if let Some(content_type) = content_type {
+            request = request.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+        Ok(request.body(body).send().await?)
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    #[instrument(skip(self, data), fields(blob.store = "s3", blob.size = data.len()))]
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), BlobError> {
+        let response = self.send(reqwest::Method::PUT, key, Some(content_type), data).await?;
+        check_status(response).await.map(|_| ())
+    }
+
+    #[instrument(skip(self), fields(blob.store = "s3"))]
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError> {
+        let response = self.send(reqwest::Method::GET, key, None, Vec::new()).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(BlobError::NotFound(key.to_owned()));
+        }
+        Ok(check_status(response).await?.bytes().await?.to_vec())
+    }
+
+    #[instrument(skip(self), fields(blob.store = "s3"))]
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        // S3 answers 204 whether or not the object existed
+        let response = self.send(reqwest::Method::DELETE, key, None, Vec::new()).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check_status(response).await.map(|_| ())
+    }
+
+    fn presign(&self, key: &str, expires_in: Duration) -> Result<Option<String>, BlobError> {
+        let path = self.object_path(key)?;
+        let now = self.clock.now();
+        let credential = format!("{}/{}", self.config.access_key_id, self.scope(&now.format("%Y%m%d").to_string()));
+        // Already in the sorted order the canonical request needs
+        let query = format!(
+            "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential={}&X-Amz-Date={}&X-Amz-Expires={}\
+             &X-Amz-SignedHeaders=host",
+            uri_encode(&credential, true),
+            now.format("%Y%m%dT%H%M%SZ"),
+            expires_in.as_secs().clamp(1, MAX_PRESIGN_SECS)
+        );
+        let canonical_request = format!("GET\n{path}\n{query}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD", self.host);
+        let signature = self.signature(now, &canonical_request);
+        Ok(Some(format!("{}{path}?{query}&X-Amz-Signature={signature}", self.config.endpoint)))
+    }
+
+    fn kind(&self) -> &'static str {
+        "s3"
+    }
+}
+
+/// Fails with `Status`, carrying the start of the error body, unless the response is a 2xx.
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, BlobError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let mut body = response.text().await.unwrap_or_default();
+    body.truncate(500);
+    Err(BlobError::Status { status: status.as_u16(), body })
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but unreserved characters, as SigV4 wants; `/` too with
+/// `encode_slash`.
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
@@ -0,0 +1,177 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CryptoError {
+    #[error("Invalid key {0}: expected 32 bytes of base64")]
+    InvalidKey(String),
+
+    #[error("Unknown key id: {0}")]
+    UnknownKeyId(String),
+
+    #[error("Malformed ciphertext")]
+    Malformed,
+
+    #[error("Decryption failed")]
+    DecryptionFailed,
+}
+
+/// AES-256-GCM encryption for individual columns.
+///
+/// New values are always sealed with the active key; older key ids are kept around
+/// so rows written before a key rotation can still be read. The todo id is bound
+/// as associated data, so ciphertext copied onto another row fails to decrypt.
+pub struct FieldCipher {
+    active_key_id: String,
+    keys: HashMap<String, Aes256Gcm>,
+}
+
+impl FieldCipher {
+    /// `keys` maps key id to a base64-encoded 256-bit key and must contain `active_key_id`.
+    pub fn new(active_key_id: &str, keys: &[(String, String)]) -> Result<Self, CryptoError> {
+        let mut ciphers = HashMap::new();
+        for (id, encoded) in keys {
+            let bytes = STANDARD
+                .decode(encoded.trim())
+                .map_err(|_| CryptoError::InvalidKey(id.clone()))?;
+            if bytes.len() != 32 {
+                return Err(CryptoError::InvalidKey(id.clone()));
+            }
+            ciphers.insert(
+                id.clone(),
+                Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)),
+            );
+        }
+
+        if !ciphers.contains_key(active_key_id) {
+            return Err(CryptoError::UnknownKeyId(active_key_id.to_string()));
+        }
+
+        Ok(Self {
+            active_key_id: active_key_id.to_string(),
+            keys: ciphers,
+        })
+    }
+
+    /// Returns `(key_id, base64(nonce || ciphertext))`.
+    pub fn encrypt(&self, todo_id: Uuid, plaintext: &str) -> Result<(String, String), CryptoError> {
+        let cipher = &self.keys[&self.active_key_id];
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: todo_id.as_bytes(),
+                },
+            )
+            .map_err(|_| CryptoError::Malformed)?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok((self.active_key_id.clone(), STANDARD.encode(sealed)))
+    }
+
+    pub fn decrypt(&self, todo_id: Uuid, key_id: &str, sealed: &str) -> Result<String, CryptoError> {
+        let cipher = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| CryptoError::UnknownKeyId(key_id.to_string()))?;
+        let bytes = STANDARD.decode(sealed).map_err(|_| CryptoError::Malformed)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(CryptoError::Malformed);
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: todo_id.as_bytes(),
+                },
+            )
+            .map_err(|_| CryptoError::DecryptionFailed)?;
+
+        String::from_utf8(plaintext).map_err(|_| CryptoError::Malformed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> String {
+        STANDARD.encode([byte; 32])
+    }
+
+    fn cipher(active: &str) -> FieldCipher {
+        let keys = [("old".to_string(), key(1)), ("new".to_string(), key(2))];
+        FieldCipher::new(active, &keys).unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let cipher = cipher("new");
+        let todo = Uuid::new_v4();
+        let (key_id, sealed) = cipher.encrypt(todo, "Call the bank").unwrap();
+        assert_eq!(key_id, "new");
+        assert_ne!(sealed, "Call the bank");
+        assert_eq!(cipher.decrypt(todo, &key_id, &sealed).unwrap(), "Call the bank");
+    }
+
+    #[test]
+    fn another_todo_cant_decrypt_it() {
+        let cipher = cipher("new");
+        let (key_id, sealed) = cipher.encrypt(Uuid::new_v4(), "Call the bank").unwrap();
+        let moved = cipher.decrypt(Uuid::new_v4(), &key_id, &sealed);
+        assert!(matches!(moved, Err(CryptoError::DecryptionFailed)), "{moved:?}");
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let cipher = cipher("new");
+        let todo = Uuid::new_v4();
+        let (key_id, sealed) = cipher.encrypt(todo, "Call the bank").unwrap();
+        let mut bytes = STANDARD.decode(&sealed).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let tampered = cipher.decrypt(todo, &key_id, &STANDARD.encode(&bytes));
+        assert!(matches!(tampered, Err(CryptoError::DecryptionFailed)), "{tampered:?}");
+
+        let truncated = STANDARD.encode(&bytes[..NONCE_LEN - 1]);
+        assert!(matches!(cipher.decrypt(todo, &key_id, &truncated), Err(CryptoError::Malformed)));
+        assert!(matches!(cipher.decrypt(todo, &key_id, "not base64!"), Err(CryptoError::Malformed)));
+    }
+
+    #[test]
+    fn decrypts_with_the_key_it_was_sealed_with() {
+        let todo = Uuid::new_v4();
+        let (key_id, sealed) = cipher("old").encrypt(todo, "Call the bank").unwrap();
+        assert_eq!(key_id, "old");
+
+        // After rotating, old rows still read and new ones use the new key
+        let rotated = cipher("new");
+        assert_eq!(rotated.decrypt(todo, "old", &sealed).unwrap(), "Call the bank");
+        assert_eq!(rotated.encrypt(todo, "Call the bank").unwrap().0, "new");
+        let wrong_key = rotated.decrypt(todo, "new", &sealed);
+        assert!(matches!(wrong_key, Err(CryptoError::DecryptionFailed)), "{wrong_key:?}");
+        let unknown = rotated.decrypt(todo, "retired", &sealed);
+        assert!(matches!(unknown, Err(CryptoError::UnknownKeyId(id)) if id == "retired"));
+    }
+
+    #[test]
+    fn rejects_bad_keys() {
+        let short = [("k".to_string(), STANDARD.encode([0; 16]))];
+        assert!(matches!(FieldCipher::new("k", &short), Err(CryptoError::InvalidKey(_))));
+        let keys = [("k".to_string(), key(1))];
+        assert!(matches!(FieldCipher::new("missing", &keys), Err(CryptoError::UnknownKeyId(_))));
+    }
+}
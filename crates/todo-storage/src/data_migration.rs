@@ -0,0 +1,491 @@
+use futures::TryStreamExt;
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    sqlite::SqliteRow,
+    Pool, Postgres, QueryBuilder, Row, Sqlite,
+};
+use std::{str::FromStr, time::Instant};
+use tracing::{info, instrument};
+
+/// Postgres tables and indexes, created if missing on every run.
+const SCHEMA: &str = include_str!("../postgres/schema.sql");
+
+/// Rows written per `INSERT`, well under Postgres's limit of 65535 bound parameters.
+const BATCH_SIZE: usize = 500;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    #[error("Source database error: {0}")]
+    Source(sqlx::Error),
+
+    #[error("Target database error: {0}")]
+    Target(sqlx::Error),
+
+    #[error("The target must be a postgres:// URL")]
+    InvalidTarget,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Text,
+    Integer,
+    Boolean,
+}
+
+enum Value {
+    Text(Option<String>),
+    Integer(Option<i64>),
+    Boolean(Option<bool>),
+}
+
+struct Table {
+    name: &'static str,
+    /// The primary key, which re-runs upsert on.
+    key: &'static [&'static str],
+    columns: &'static [(&'static str, Kind)],
+    /// Whether `id` is an identity column whose sequence must be moved past the copied ids.
+    identity: bool,
+}
+
+use Kind::{Boolean, Integer, Text};
+
+const TABLES: [Table; 24] = [
+    Table {
+        name: "todos",
+        key: &["id"],
+        columns: &[
+            ("id", Text),
+            ("title", Text),
+            ("description", Text),
+            ("completed", Boolean),
+            ("created_at", Text),
+            ("updated_at", Text),
+            ("description_key_id", Text),
+            ("due_at", Text),
+            ("tags", Text),
+            ("estimate_minutes", Integer),
+            ("expires_at", Text),
+            ("completed_at", Text),
+            ("change_seq", Integer),
+            ("created_seq", Integer),
+            ("version", Integer),
+            ("project_id", Text),
+            ("priority", Text),
+            ("pinned", Boolean),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "todo_tombstones",
+        key: &["id"],
+        columns: &[("id", Text), ("change_seq", Integer), ("deleted_at", Text)],
+        identity: false,
+    },
+    Table {
+        name: "sync_state",
+        key: &["id"],
+        columns: &[("id", Integer), ("change_seq", Integer), ("pruned_through", Integer)],
+        identity: false,
+    },
+    Table {
+        name: "todo_history",
+        key: &["id"],
+        columns: &[
+            ("id", Integer),
+            ("todo_id", Text),
+            ("event", Text),
+            ("detail", Text),
+            ("created_at", Text),
+        ],
+        identity: true,
+    },
+    Table {
+        name: "todo_dependencies",
+        key: &["blocker_id", "blocked_id"],
+        columns: &[("blocker_id", Text), ("blocked_id", Text), ("created_at", Text)],
+        identity: false,
+    },
+    Table {
+        name: "notification_outbox",
+        key: &["id"],
+        columns: &[
+            ("id", Text),
+            ("payload", Text),
+            ("attempts", Integer),
+            ("next_attempt_at", Integer),
+            ("last_error", Text),
+            ("failed_at", Text),
+            ("created_at", Text),
+            ("claimed_by", Text),
+            ("claimed_at", Text),
+            ("delivered_channels", Text),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "users",
+        key: &["id"],
+        columns: &[
+            ("id", Text),
+            ("email", Text),
+            ("username", Text),
+            ("mention_channel", Text),
+            ("password_hash", Text),
+            ("created_at", Text),
+            ("admin", Boolean),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "refresh_tokens",
+        key: &["token_hash"],
+        columns: &[
+            ("token_hash", Text),
+            ("user_id", Text),
+            ("family_id", Text),
+            ("expires_at", Text),
+            ("created_at", Text),
+            ("revoked_at", Text),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "api_tokens",
+        key: &["id"],
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("name", Text),
+            ("token_hash", Text),
+            ("scopes", Text),
+            ("created_at", Text),
+            ("expires_at", Text),
+            ("revoked_at", Text),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "auth_audit",
+        key: &["id"],
+        columns: &[
+            ("id", Integer),
+            ("event", Text),
+            ("user_id", Text),
+            ("email", Text),
+            ("ip", Text),
+            ("user_agent", Text),
+            ("detail", Text),
+            ("created_at", Text),
+        ],
+        identity: true,
+    },
+    Table {
+        name: "erasure_jobs",
+        key: &["id"],
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("email", Text),
+            ("status", Text),
+            ("steps_completed", Integer),
+            ("error", Text),
+            ("created_at", Text),
+            ("updated_at", Text),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "feature_flags",
+        key: &["name"],
+        columns: &[
+            ("name", Text),
+            ("enabled", Boolean),
+            ("description", Text),
+            ("updated_at", Text),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "projects",
+        key: &["id"],
+        columns: &[("id", Text), ("name", Text), ("created_at", Text)],
+        identity: false,
+    },
+    Table {
+        name: "project_members",
+        key: &["project_id", "user_id"],
+        columns: &[
+            ("project_id", Text),
+            ("user_id", Text),
+            ("role", Text),
+            ("added_at", Text),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "todo_comments",
+        key: &["id"],
+        columns: &[
+            ("id", Text),
+            ("todo_id", Text),
+            ("author_id", Text),
+            ("body", Text),
+            ("created_at", Text),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "activity",
+        key: &["id"],
+        columns: &[
+            ("id", Integer),
+            ("user_id", Text),
+            ("kind", Text),
+            ("todo_id", Text),
+            ("comment_id", Text),
+            ("actor_id", Text),
+            ("excerpt", Text),
+            ("created_at", Text),
+        ],
+        identity: true,
+    },
+    Table {
+        name: "webhook_subscriptions",
+        key: &["id"],
+        columns: &[
+            ("id", Text),
+            ("url", Text),
+            ("events", Text),
+            ("format", Text),
+            ("api_version", Integer),
+            ("created_at", Text),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "custom_field_defs",
+        key: &["name"],
+        columns: &[("name", Text), ("type", Text), ("required", Boolean), ("created_at", Text)],
+        identity: false,
+    },
+    Table {
+        name: "custom_field_values",
+        key: &["todo_id", "name"],
+        columns: &[("todo_id", Text), ("name", Text), ("value", Text)],
+        identity: false,
+    },
+    Table {
+        name: "attachments",
+        key: &["id"],
+        columns: &[
+            ("id", Text),
+            ("todo_id", Text),
+            ("filename", Text),
+            ("content_type", Text),
+            ("size_bytes", Integer),
+            ("blob_key", Text),
+            ("created_at", Text),
+            ("uploaded_by", Text),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "api_usage",
+        key: &["hour", "client", "method", "route"],
+        columns: &[
+            ("hour", Text),
+            ("client", Text),
+            ("user_id", Text),
+            ("method", Text),
+            ("route", Text),
+            ("requests", Integer),
+            ("client_errors", Integer),
+            ("server_errors", Integer),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "job_leases",
+        key: &["job"],
+        columns: &[
+            ("job", Text),
+            ("holder", Text),
+            ("acquired_at", Text),
+            ("expires_at", Integer),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "todo_external_links",
+        key: &["source", "external_id"],
+        columns: &[
+            ("source", Text),
+            ("external_id", Text),
+            ("todo_id", Text),
+            ("created_at", Text),
+            ("remote_state", Text),
+        ],
+        identity: false,
+    },
+    Table {
+        name: "tags",
+        key: &["name"],
+        columns: &[
+            ("name", Text),
+            ("color", Text),
+            ("description", Text),
+            ("created_at", Text),
+            ("updated_at", Text),
+        ],
+        identity: false,
+    },
+];
+
+/// Copies every row of the SQLite database into Postgres at `target_url`, creating the
+/// schema first. Rows are upserted by primary key, so running it again after more writes
+/// brings the target up to date rather than failing on duplicates. Rows deleted from the
+/// source since an earlier run are left in the target.
+///
+/// With `schema`, the tables are created in (and copied into) that Postgres schema instead
+/// of the default one, which is how each tenant's database is kept apart.
+#[instrument(skip(source, target_url))]
+pub async fn migrate_to_postgres(
+    source: &Pool<Sqlite>,
+    target_url: &str,
+    schema: Option<&str>,
+) -> Result<u64, MigrationError> {
+    if !target_url.starts_with("postgres://") && !target_url.starts_with("postgresql://") {
+        return Err(MigrationError::InvalidTarget);
+    }
+    let mut options = PgConnectOptions::from_str(target_url).map_err(MigrationError::Target)?;
+    let quoted = schema.map(|schema| format!("\"{}\"", schema.replace('"', "\"\"")));
+    if let Some(quoted) = &quoted {
+        options = options.options([("search_path", quoted.as_str())]);
+    }
+    let target = PgPoolOptions::new()
+        .max_connections(2)
+        .connect_with(options)
+        .await
+        .map_err(MigrationError::Target)?;
+    if let Some(quoted) = &quoted {
+        sqlx::raw_sql(&format!("CREATE SCHEMA IF NOT EXISTS {quoted}"))
+            .execute(&target)
+            .await
+            .map_err(MigrationError::Target)?;
+    }
+    sqlx::raw_sql(SCHEMA)
+        .execute(&target)
+        .await
+        .map_err(MigrationError::Target)?;
+    info!("Postgres schema ready");
+
+    let started = Instant::now();
+    let mut copied = 0;
+    for table in &TABLES {
+        copied += copy_table(source, &target, table).await?;
+    }
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    info!(rows = copied, elapsed_ms, "Data migration finished");
+    Ok(copied)
+}
+
+#[instrument(skip(source, target, table), fields(table = table.name))]
+async fn copy_table(
+    source: &Pool<Sqlite>,
+    target: &Pool<Postgres>,
+    table: &Table,
+) -> Result<u64, MigrationError> {
+    let columns: Vec<&str> = table.columns.iter().map(|(name, _)| *name).collect();
+    let (total,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {}", table.name))
+        .fetch_one(source)
+        .await
+        .map_err(MigrationError::Source)?;
+    info!(total, "Copying table");
+
+    let select = format!(
+        "SELECT {} FROM {} ORDER BY {}",
+        columns.join(", "),
+        table.name,
+        table.key.join(", ")
+    );
+    let mut rows = sqlx::query(&select).fetch(source);
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut copied = 0;
+    while let Some(row) = rows.try_next().await.map_err(MigrationError::Source)? {
+        batch.push(read_row(&row, table).map_err(MigrationError::Source)?);
+        if batch.len() == BATCH_SIZE {
+            copied += write_batch(target, table, &columns, std::mem::take(&mut batch)).await?;
+            info!(copied, total, "Copied batch");
+        }
+    }
+    if !batch.is_empty() {
+        copied += write_batch(target, table, &columns, batch).await?;
+    }
+
+    if table.identity {
+        // Rows copied with explicit ids don't advance the sequence, so new rows would collide
+        sqlx::query(&format!(
+            "SELECT setval(pg_get_serial_sequence('{0}', 'id'), COALESCE(MAX(id), 0) + 1, false) \
+             FROM {0}",
+            table.name
+        ))
+        .execute(target)
+        .await
+        .map_err(MigrationError::Target)?;
+    }
+    info!(copied, "Table copied");
+    Ok(copied)
+}
+
+fn read_row(row: &SqliteRow, table: &Table) -> Result<Vec<Value>, sqlx::Error> {
+    table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, (_, kind))| {
+            Ok(match kind {
+                Text => Value::Text(row.try_get(i)?),
+                Integer => Value::Integer(row.try_get(i)?),
+                Boolean => Value::Boolean(row.try_get(i)?),
+            })
+        })
+        .collect()
+}
+
+async fn write_batch(
+    target: &Pool<Postgres>,
+    table: &Table,
+    columns: &[&str],
+    rows: Vec<Vec<Value>>,
+) -> Result<u64, MigrationError> {
+    let insert = format!("INSERT INTO {} ({}) ", table.name, columns.join(", "));
+    let mut query = QueryBuilder::<Postgres>::new(insert);
+    query.push_values(rows, |mut values, row| {
+        for value in row {
+            match value {
+                Value::Text(v) => values.push_bind(v),
+                Value::Integer(v) => values.push_bind(v),
+                Value::Boolean(v) => values.push_bind(v),
+            };
+        }
+    });
+
+    let updates: Vec<String> = columns
+        .iter()
+        .filter(|column| !table.key.contains(column))
+        .map(|column| format!("{column} = EXCLUDED.{column}"))
+        .collect();
+    query.push(format!(" ON CONFLICT ({}) ", table.key.join(", ")));
+    if updates.is_empty() {
+        query.push("DO NOTHING");
+    } else {
+        query.push(format!("DO UPDATE SET {}", updates.join(", ")));
+    }
+
+    let result = query
+        .build()
+        .execute(target)
+        .await
+        .map_err(MigrationError::Target)?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,52 @@
+use rand::Rng;
+use std::{ops::Range, str::FromStr, time::Duration};
+
+/// Artificial delays added to database queries and mock notification calls
+/// (`LATENCY_PROFILE=off|realistic|stress`), so traces have something to show in demos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LatencyProfile {
+    /// No added delay, for production and benchmarks.
+    #[default]
+    Off,
+    /// 10–60ms per query and 50–250ms per external call.
+    Realistic,
+    /// 50–500ms per query and 250–2000ms per external call.
+    Stress,
+}
+
+impl FromStr for LatencyProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(Self::Off),
+            "realistic" => Ok(Self::Realistic),
+            "stress" => Ok(Self::Stress),
+            other => Err(format!("unknown latency profile: {other}")),
+        }
+    }
+}
+
+impl LatencyProfile {
+    /// A random delay for one database query, or `None` when the profile is off.
+    pub fn db_delay(self) -> Option<Duration> {
+        match self {
+            Self::Off => None,
+            Self::Realistic => Some(random_millis(10..60)),
+            Self::Stress => Some(random_millis(50..500)),
+        }
+    }
+
+    /// A random delay for one simulated external API call, or `None` when the profile is off.
+    pub fn api_delay(self) -> Option<Duration> {
+        match self {
+            Self::Off => None,
+            Self::Realistic => Some(random_millis(50..250)),
+            Self::Stress => Some(random_millis(250..2000)),
+        }
+    }
+}
+
+fn random_millis(range: Range<u64>) -> Duration {
+    Duration::from_millis(rand::thread_rng().gen_range(range))
+}
@@ -0,0 +1,13 @@
+//! SQLite storage for todos: the repository and its migrations, field encryption, backups,
+//! attachment blob stores, per-tenant databases, and the copy into Postgres. Instrumented
+//! with `tracing` only; exporting spans is up to the application.
+
+pub mod backup;
+pub mod blob_store;
+pub mod crypto;
+pub mod data_migration;
+pub mod latency;
+pub mod redact;
+pub mod repository;
+pub mod span_errors;
+pub mod tenants;
@@ -0,0 +1,68 @@
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+/// How user-supplied text (titles, descriptions) is rendered into span attributes and log lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactionMode {
+    /// Record values verbatim.
+    Off,
+    /// Replace values with a short SHA-256 prefix, so identical titles still correlate.
+    Hash,
+    /// Keep only the first few characters.
+    Truncate,
+}
+
+impl FromStr for RedactionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(Self::Off),
+            "hash" => Ok(Self::Hash),
+            "truncate" => Ok(Self::Truncate),
+            other => Err(format!("unknown redaction mode: {other}")),
+        }
+    }
+}
+
+const TRUNCATE_CHARS: usize = 4;
+const HASH_HEX_CHARS: usize = 12;
+
+static MODE: OnceLock<RedactionMode> = OnceLock::new();
+
+/// Sets the process-wide redaction mode. Only the first call has an effect.
+pub fn init(mode: RedactionMode) {
+    let _ = MODE.set(mode);
+}
+
+fn mode() -> RedactionMode {
+    *MODE.get().unwrap_or(&RedactionMode::Off)
+}
+
+/// Wraps potentially sensitive text for use in `tracing` fields: `title = %redacted(&title)`.
+pub fn redacted(value: &str) -> Redacted<'_> {
+    Redacted(value)
+}
+
+pub struct Redacted<'a>(&'a str);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match mode() {
+            RedactionMode::Off => f.write_str(self.0),
+            RedactionMode::Hash => {
+                let digest = hex::encode(Sha256::digest(self.0.as_bytes()));
+                write!(f, "sha256:{}", &digest[..HASH_HEX_CHARS])
+            }
+            RedactionMode::Truncate => {
+                let mut chars = self.0.chars();
+                let prefix: String = chars.by_ref().take(TRUNCATE_CHARS).collect();
+                if chars.next().is_some() {
+                    write!(f, "{prefix}…")
+                } else {
+                    f.write_str(&prefix)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,4449 @@
+use async_trait::async_trait;
+use sqlx::{
+    sqlite::{SqliteAutoVacuum, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    Pool, Sqlite, SqliteConnection,
+};
+use tracing::{error, info, instrument, warn, Instrument, Span};
+use uuid::Uuid;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, SecondsFormat, Utc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+    time::{self, Instant},
+};
+use crate::backup;
+use crate::crypto::FieldCipher;
+use crate::latency::LatencyProfile;
+use crate::redact;
+use crate::span_errors;
+use todo_domain::clock::{self, Clock};
+use todo_domain::models::{
+    normalize_custom_fields, normalize_tags, ActivityItem, AgingBucket, ApiToken, Attachment, AuditEntry, AuditQuery,
+    BulkTagFilter, Comment, CompactTodo, CompletionTime, ConflictReason, CustomFieldDef, DeletedTodo, ErasureJob, ErasureStatus,
+    FeatureFlag, HistoryEntry, Priority, Project, ProjectMember, ProjectRole, Scope, SessionInfo, SyncChanges, SyncMutation,
+    SyncMutationResult, Tag, TagRollup, Todo, TodoAging, TodoStats, UsageCounts, UsageKey, User, Velocity, VelocityPeriod,
+    VelocityPoint, WebhookSubscription,
+};
+
+pub use todo_domain::repository::{
+    ListFilter, ListWindow, RepositoryError, TodoCursor, TodoList, TodoListSummary, TodoPage, TodoRepository,
+};
+
+/// Told how long every repository call took, e.g. to put it on the trace or keep it for
+/// profiling. Called on the call's own span.
+pub trait QueryObserver: Send + Sync {
+    fn observe(&self, operation: &'static str, elapsed: time::Duration);
+}
+
+pub struct SqliteTodoRepository {
+    pool: Pool<Sqlite>,
+    description_cipher: Option<Arc<FieldCipher>>,
+    latency: LatencyProfile,
+    observer: Option<Arc<dyn QueryObserver>>,
+    clock: Arc<dyn Clock>,
+}
+
+/// Columns selected for every todo query, in `TodoRow` order.
+/// Stored columns plus `custom_fields`, gathered from `custom_field_values` into one JSON
+/// object, and `blocked`, which is computed from the open blockers of each row.
+const TODO_COLUMNS: &str = "id, title, description, completed, due_at, tags, estimate_minutes, expires_at, \
+    project_id, priority, pinned, created_at, updated_at, description_key_id, version, change_seq, (\
+        SELECT json_group_object(v.name, json(v.value)) FROM custom_field_values v WHERE v.todo_id = todos.id\
+    ) AS custom_fields, EXISTS (\
+        SELECT 1 FROM todo_dependencies d JOIN todos b ON b.id = d.blocker_id \
+        WHERE d.blocked_id = todos.id AND b.completed = false\
+    ) AS blocked";
+
+/// Columns selected for every user query, in `UserRow` order.
+const USER_COLUMNS: &str = "id, email, username, mention_channel, password_hash, created_at, admin";
+
+/// Filter for queries that list todos, with the current time bound as `?1`. Timestamps are
+/// RFC 3339 in UTC, so comparing the text compares the times.
+const NOT_EXPIRED: &str = "(expires_at IS NULL OR expires_at > ?1)";
+
+/// Lower bounds, in days, of the `GET /stats/aging` buckets after the first.
+const AGING_BOUNDS_DAYS: [u32; 4] = [1, 7, 30, 90];
+
+/// A `todos` row as read, with `blocked` and `custom_fields` joined in. `row_to_todo` maps it
+/// to `Todo`, and `insert_query` and `update_query` map a `Todo` back to columns, so the
+/// schema and the model can change independently.
+#[derive(sqlx::FromRow)]
+struct TodoRow {
+    id: String,
+    title: String,
+    description: Option<String>,
+    completed: bool,
+    due_at: Option<String>,
+    tags: String,
+    estimate_minutes: Option<i64>,
+    expires_at: Option<String>,
+    project_id: Option<String>,
+    priority: Option<String>,
+    pinned: bool,
+    created_at: String,
+    updated_at: String,
+    description_key_id: Option<String>,
+    version: i64,
+    change_seq: i64,
+    custom_fields: String,
+    blocked: bool,
+}
+
+/// What happened to a refresh token presented for rotation.
+#[derive(Debug, Clone, Copy)]
+pub enum RefreshOutcome {
+    /// The token was valid; it is now revoked and its replacement stored.
+    Rotated { user_id: Uuid, family_id: Uuid },
+    /// The token had already been rotated or revoked, so the whole session was revoked.
+    Reused { user_id: Uuid },
+    Expired,
+    Unknown,
+}
+
+/// What a sync token asked for.
+#[derive(Debug)]
+pub enum SyncOutcome {
+    Changes(SyncChanges),
+    /// The token is older than the pruned tombstones or ahead of this database, so the
+    /// client has to start again with a full sync.
+    Expired,
+}
+
+/// What happened to a request to change a project member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MembershipChange {
+    Applied,
+    NotMember,
+    /// Refused, because the project would be left without an owner.
+    LastOwner,
+}
+
+/// An event to append to the authentication audit log.
+#[derive(Debug)]
+pub struct NewAuditEntry<'a> {
+    pub event: &'a str,
+    pub user_id: Option<Uuid>,
+    pub email: Option<&'a str>,
+    pub ip: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub detail: serde_json::Value,
+}
+
+#[derive(sqlx::FromRow)]
+struct SyncRow {
+    #[sqlx(flatten)]
+    todo: TodoRow,
+    created_seq: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct TombstoneRow {
+    id: String,
+    deleted_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct CompactTodoRow {
+    id: String,
+    title: String,
+    completed: bool,
+    due_at: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct StatsRow {
+    open_count: i64,
+    completed_count: i64,
+    open_estimate_minutes: i64,
+    unestimated_open_count: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct TagRollupRow {
+    tag: String,
+    open_count: i64,
+    open_estimate_minutes: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct CompletionTimeRow {
+    count: i64,
+    mean_seconds: Option<f64>,
+    p50_seconds: Option<f64>,
+    p75_seconds: Option<f64>,
+    p90_seconds: Option<f64>,
+    p95_seconds: Option<f64>,
+    max_seconds: Option<f64>,
+}
+
+#[derive(sqlx::FromRow)]
+struct UserRow {
+    id: String,
+    email: String,
+    username: Option<String>,
+    mention_channel: String,
+    password_hash: String,
+    created_at: String,
+    admin: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct ApiTokenRow {
+    id: String,
+    user_id: String,
+    name: String,
+    scopes: String,
+    created_at: String,
+    expires_at: Option<String>,
+    revoked_at: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct AuditRow {
+    id: i64,
+    event: String,
+    user_id: Option<String>,
+    email: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    detail: String,
+    created_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct ErasureJobRow {
+    id: String,
+    user_id: Option<String>,
+    email: Option<String>,
+    status: String,
+    steps_completed: i64,
+    error: Option<String>,
+    created_at: String,
+    updated_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct ProjectRow {
+    id: String,
+    name: String,
+    role: String,
+    created_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct ProjectMemberRow {
+    user_id: String,
+    email: String,
+    role: String,
+    added_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct AttachmentRow {
+    id: String,
+    todo_id: String,
+    filename: String,
+    content_type: String,
+    size_bytes: i64,
+    blob_key: String,
+    created_at: String,
+    uploaded_by: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ExternalLinkRow {
+    source: String,
+    external_id: String,
+    todo_id: String,
+    remote_state: Option<String>,
+    created_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct UsageRow {
+    client: String,
+    user_id: Option<String>,
+    method: String,
+    route: String,
+    requests: i64,
+    client_errors: i64,
+    server_errors: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct TagRow {
+    name: String,
+    color: Option<String>,
+    description: Option<String>,
+    usage_count: i64,
+    open_count: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct CustomFieldRow {
+    name: String,
+    #[sqlx(rename = "type")]
+    field_type: String,
+    required: bool,
+    created_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct WebhookRow {
+    id: String,
+    url: String,
+    events: String,
+    format: String,
+    api_version: i64,
+    created_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct CommentRow {
+    id: String,
+    todo_id: String,
+    author_id: Option<String>,
+    body: String,
+    created_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct ActivityRow {
+    id: i64,
+    kind: String,
+    todo_id: String,
+    comment_id: Option<String>,
+    actor_id: Option<String>,
+    excerpt: String,
+    created_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct HistoryRow {
+    event: String,
+    detail: String,
+    created_at: String,
+}
+
+/// What a database holds, for comparing a snapshot with the manifest written alongside it.
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseStats {
+    /// The newest migration applied, or `None` when there's no migration table at all.
+    pub schema_version: Option<i64>,
+    /// Rows in each table, by name, leaving out SQLite's own tables and the migration table.
+    pub row_counts: BTreeMap<String, u64>,
+}
+
+/// A notification waiting in the outbox, as claimed by the dispatcher.
+#[derive(Debug, sqlx::FromRow)]
+pub struct OutboxEntry {
+    pub id: String,
+    pub payload: String,
+    pub attempts: i64,
+    /// JSON array of the channels that already delivered it.
+    pub delivered_channels: String,
+}
+
+/// A worker's hold on outbox rows: the instance that claimed them and when, by the
+/// repository's clock. Every claim of a row gets a new `claimed_at`, so the pair tells one
+/// claim from the next, even on the same instance.
+#[derive(Debug, Clone)]
+pub struct OutboxClaim {
+    pub holder: String,
+    pub claimed_at: DateTime<Utc>,
+}
+
+impl OutboxClaim {
+    pub fn new(holder: impl Into<String>, claimed_at: DateTime<Utc>) -> Self {
+        Self {
+            holder: holder.into(),
+            claimed_at,
+        }
+    }
+}
+
+/// A todo standing for an item in another system, such as a GitHub or Jira issue.
+#[derive(Debug, Clone)]
+pub struct ExternalLink {
+    pub source: String,
+    pub external_id: String,
+    pub todo_id: Uuid,
+    /// The item's state in the source when it was last looked at; `None` until then.
+    pub remote_state: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl SqliteTodoRepository {
+    /// Connects to the database. With the `sqlcipher` feature, `encryption_key` is sent as
+    /// `PRAGMA key` before anything else touches the file.
+    ///
+    /// Each connection keeps up to `statement_cache_capacity` prepared statements, so a query
+    /// is compiled once per connection rather than on every call; `0` disables the cache.
+    pub async fn new(
+        database_url: &str,
+        encryption_key: Option<&str>,
+        statement_cache_capacity: usize,
+    ) -> Result<Self, sqlx::Error> {
+        Self::connect(database_url, encryption_key, statement_cache_capacity, false).await
+    }
+
+    /// Opens the snapshot at `path` read-only to inspect it, without running migrations.
+    pub async fn open_snapshot(path: &Path, encryption_key: Option<&str>) -> Result<Self, sqlx::Error> {
+        let url = format!("sqlite://{}", path.display());
+        Self::connect(&url, encryption_key, 0, true).await
+    }
+
+    /// The version of the newest migration this build knows, which a database it opens is
+    /// brought up to.
+    pub fn latest_schema_version() -> i64 {
+        sqlx::migrate!("./migrations")
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Connects without writing anything, for a replica whose file is kept up to date from
+    /// elsewhere: connections are opened read-only, and migrations aren't run, so the schema
+    /// must already be current.
+    pub async fn new_read_only(
+        database_url: &str,
+        encryption_key: Option<&str>,
+        statement_cache_capacity: usize,
+    ) -> Result<Self, sqlx::Error> {
+        Self::connect(database_url, encryption_key, statement_cache_capacity, true).await
+    }
+
+    async fn connect(
+        database_url: &str,
+        encryption_key: Option<&str>,
+        statement_cache_capacity: usize,
+        read_only: bool,
+    ) -> Result<Self, sqlx::Error> {
+        let mut options = SqliteConnectOptions::from_str(database_url)?
+            .statement_cache_capacity(statement_cache_capacity);
+        if read_only {
+            options = options.read_only(true);
+        } else {
+            // WAL keeps readers unblocked during checkpoints, and incremental auto-vacuum lets
+            // the maintenance job reclaim free pages without a full VACUUM.
+            options = options
+                .journal_mode(SqliteJournalMode::Wal)
+                .auto_vacuum(SqliteAutoVacuum::Incremental);
+        }
+
+        if let Some(key) = encryption_key {
+            if !cfg!(feature = "sqlcipher") {
+                // Plain SQLite silently ignores `PRAGMA key`, which would leave the data unencrypted.
+                return Err(sqlx::Error::Configuration(
+                    "a database key was configured but this build lacks the `sqlcipher` feature".into(),
+                ));
+            }
+            options = options.pragma("key", format!("'{}'", key.replace('\'', "''")));
+        }
+        // Connections are checked before use, so one broken while idle is replaced instead
+        // of failing the query that draws it
+        let mut pool_options = SqlitePoolOptions::new().test_before_acquire(true);
+        if database_url.contains(":memory:") || database_url.contains("mode=memory") {
+            // An in-memory database lives only as long as a connection to it, so keep one open
+            pool_options = pool_options
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None);
+        }
+        let pool = pool_options.connect_with(options).await?;
+
+        // Run migrations
+        if !read_only {
+            sqlx::migrate!("./migrations").run(&pool).await?;
+            Self::enable_auto_vacuum(&pool).await?;
+        }
+
+        Ok(Self {
+            pool,
+            description_cipher: None,
+            latency: LatencyProfile::Off,
+            observer: None,
+            clock: clock::system(),
+        })
+    }
+
+    /// Switches a database created before incremental auto-vacuum over to it. The pragma only
+    /// takes effect on an existing database through a full `VACUUM`, which runs once here;
+    /// until then `PRAGMA incremental_vacuum` reclaims nothing.
+    async fn enable_auto_vacuum(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        // 2 is INCREMENTAL
+        let mode: i64 = sqlx::query_scalar("PRAGMA auto_vacuum").fetch_one(pool).await?;
+        if mode != 2 {
+            info!(mode, "Rebuilding the database once to enable incremental auto-vacuum");
+            sqlx::query("VACUUM").execute(pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Encrypts descriptions at the application level before they reach the database.
+    /// Rows written without a cipher stay readable as plaintext.
+    pub fn with_description_cipher(mut self, cipher: Arc<FieldCipher>) -> Self {
+        self.description_cipher = Some(cipher);
+        self
+    }
+
+    /// Adds the profile's artificial delay to every query.
+    pub fn with_latency_profile(mut self, latency: LatencyProfile) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// The connection pool, for whole-database tools such as `migrate-data` that work below
+    /// the repository's operations.
+    pub fn pool(&self) -> &Pool<Sqlite> {
+        &self.pool
+    }
+
+    /// Reports every call's timing to `observer`.
+    pub fn with_query_observer(mut self, observer: Arc<dyn QueryObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Takes the current time from `clock` instead of the system, for expiry, retention,
+    /// overdue counts and the timestamps it writes.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The clock the repository reads, for jobs that pass it times such as expiry cut-offs.
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Seals the description for storage, returning `(description, key_id)` column values.
+    fn seal_description(&self, todo: &Todo) -> Result<(Option<String>, Option<String>), RepositoryError> {
+        match (&self.description_cipher, &todo.description) {
+            (Some(cipher), Some(description)) => {
+                let (key_id, sealed) = cipher
+                    .encrypt(todo.id, description)
+                    .map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+                Ok((Some(sealed), Some(key_id)))
+            }
+            _ => Ok((todo.description.clone(), None)),
+        }
+    }
+
+    fn row_to_todo(&self, row: TodoRow) -> Result<Todo, RepositoryError> {
+        let id = Uuid::parse_str(&row.id)
+            .map_err(|e| RepositoryError::InvalidData(format!("bad todo id {}: {e}", row.id)))?;
+
+        let description = match (row.description, row.description_key_id) {
+            (Some(sealed), Some(key_id)) => {
+                let cipher = self.description_cipher.as_ref().ok_or_else(|| {
+                    RepositoryError::InvalidData(format!(
+                        "description of {id} is encrypted with key {key_id} but no cipher is configured"
+                    ))
+                })?;
+                Some(
+                    cipher
+                        .decrypt(id, &key_id, &sealed)
+                        .map_err(|e| RepositoryError::InvalidData(format!("description of {id}: {e}")))?,
+                )
+            }
+            (description, _) => description,
+        };
+
+        Ok(Todo {
+            id,
+            title: row.title,
+            description,
+            completed: row.completed,
+            due_at: row.due_at.as_deref().map(parse_timestamp).transpose()?,
+            tags: serde_json::from_str(&row.tags)
+                .map_err(|e| RepositoryError::InvalidData(format!("tags of {id}: {e}")))?,
+            estimate_minutes: row
+                .estimate_minutes
+                .map(u32::try_from)
+                .transpose()
+                .map_err(|e| RepositoryError::InvalidData(format!("estimate of {id}: {e}")))?,
+            expires_at: row.expires_at.as_deref().map(parse_timestamp).transpose()?,
+            project_id: row
+                .project_id
+                .as_deref()
+                .map(Uuid::parse_str)
+                .transpose()
+                .map_err(|e| RepositoryError::InvalidData(format!("project of {id}: {e}")))?,
+            priority: row
+                .priority
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .map_err(|e| RepositoryError::InvalidData(format!("priority of {id}: {e}")))?,
+            pinned: row.pinned,
+            custom_fields: serde_json::from_str(&row.custom_fields)
+                .map_err(|e| RepositoryError::InvalidData(format!("custom fields of {id}: {e}")))?,
+            created_at: parse_timestamp(&row.created_at)?,
+            updated_at: parse_timestamp(&row.updated_at)?,
+            blocked: row.blocked,
+            version: row.version as u64,
+            change_seq: row.change_seq as u64,
+        })
+    }
+
+    /// Checkpoints the WAL, releases free pages and refreshes query planner statistics.
+    /// Sync tombstones older than `tombstone_retention` are dropped first, when it's set.
+    /// Returns the number of pages reclaimed by the incremental vacuum.
+    #[instrument(skip(self), fields(db.operation = "MAINTENANCE", reclaimed_pages))]
+    pub async fn run_maintenance(
+        &self,
+        tombstone_retention: Option<time::Duration>,
+    ) -> Result<u64, RepositoryError> {
+        self.capture("run_maintenance", async {
+            info!("Running database maintenance");
+
+            let freelist_before: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+                .fetch_one(&self.pool)
+                .await?;
+
+            // Expired refresh tokens can't be used or replayed, so they're only dead weight
+            sqlx::query("DELETE FROM refresh_tokens WHERE expires_at <= ?1")
+                .bind(self.clock.now().to_rfc3339())
+                .execute(&self.pool)
+                .await?;
+
+            if let Some(retention) = tombstone_retention {
+                let retention = Duration::from_std(retention)
+                    .map_err(|e| RepositoryError::InvalidData(format!("tombstone retention: {e}")))?;
+                self.prune_tombstones(self.clock.now() - retention).await?;
+            }
+
+            sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+                .execute(&self.pool)
+                .instrument(tracing::info_span!("wal_checkpoint"))
+                .await?;
+
+            sqlx::query("PRAGMA incremental_vacuum")
+                .execute(&self.pool)
+                .instrument(tracing::info_span!("incremental_vacuum"))
+                .await?;
+
+            sqlx::query("ANALYZE")
+                .execute(&self.pool)
+                .instrument(tracing::info_span!("analyze"))
+                .await?;
+
+            let freelist_after: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+                .fetch_one(&self.pool)
+                .await?;
+
+            let reclaimed = (freelist_before - freelist_after).max(0) as u64;
+            Span::current().record("reclaimed_pages", reclaimed);
+            info!("Database maintenance completed");
+            Ok(reclaimed)
+        })
+        .await
+    }
+
+    /// Writes a consistent copy of the database to `path` using `VACUUM INTO`.
+    /// Readers and writers keep going while the snapshot is taken.
+    #[instrument(skip(self), fields(db.operation = "VACUUM_INTO", backup.path = %path.display()))]
+    pub async fn snapshot_to(&self, path: &Path) -> Result<(), RepositoryError> {
+        self.capture("snapshot_to", async {
+            info!("Writing database snapshot");
+
+            sqlx::query("VACUUM INTO ?1")
+                .bind(path.to_string_lossy().into_owned())
+                .execute(&self.pool)
+                .await?;
+
+            info!("Database snapshot written");
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replaces the contents of the live database with the todos stored in the snapshot
+    /// at `path`, along with every row that belongs to them: dependencies, custom fields,
+    /// history, comments, activity, attachments, external links and tags. Every column the
+    /// snapshot has is copied; columns added since it was taken get their defaults. A snapshot
+    /// from a newer schema than this database's is refused, as its extra columns would be lost,
+    /// and so is one older than `backup::MIN_RESTORABLE_VERSION`; see `backup::check_restorable`.
+    /// The swap happens in a single transaction, so readers never observe a half-restored
+    /// table. Returns the number of restored todos.
+    ///
+    /// Attachments uploaded since the snapshot are kept, so the sweep can still find their
+    /// files once their todo is gone. Comments and activity of accounts erased since the
+    /// snapshot are scrubbed again, as the erasure did.
+    #[instrument(skip(self), fields(db.operation = "RESTORE", backup.path = %path.display()))]
+    pub async fn restore_from(&self, path: &Path) -> Result<u64, RepositoryError> {
+        self.capture("restore_from", async {
+            info!("Restoring database from snapshot");
+
+            let mut conn = self.pool.acquire().await?;
+
+            // ATTACH is not allowed inside a transaction, so attach first and always detach.
+            sqlx::query("ATTACH DATABASE ?1 AS snapshot")
+                .bind(path.to_string_lossy().into_owned())
+                .execute(&mut *conn)
+                .await?;
+
+            let result = async {
+                let live: Option<i64> =
+                    sqlx::query_scalar("SELECT MAX(version) FROM main._sqlx_migrations WHERE success = true")
+                        .fetch_one(&mut *conn)
+                        .await?;
+                let snapshot: Option<i64> =
+                    sqlx::query_scalar("SELECT MAX(version) FROM snapshot._sqlx_migrations WHERE success = true")
+                        .fetch_one(&mut *conn)
+                        .await?;
+                if let Err(reason) = backup::check_restorable(snapshot, live.unwrap_or_default()) {
+                    return Err(RepositoryError::InvalidData(format!("snapshot can't be restored: {reason}")));
+                }
+
+                let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+                let before: i64 = sqlx::query_scalar("SELECT change_seq FROM sync_state")
+                    .fetch_one(&mut *tx)
+                    .await?;
+                let restored = restore_table(&mut tx, "todos").await?;
+                // Todos the snapshot doesn't have leave tombstones, dated as `delete_todo_rows` does
+                sqlx::query("UPDATE todo_tombstones SET deleted_at = ?1 WHERE change_seq > ?2")
+                    .bind(tombstone_time(self.clock.now()))
+                    .bind(before)
+                    .execute(&mut *tx)
+                    .await?;
+                for table in RESTORED_WITH_TODOS {
+                    restore_table(&mut tx, table).await?;
+                }
+                keep_table(&mut tx, "attachments").await?;
+                // The same as `erase_user`, for every account that no longer exists
+                sqlx::query("DELETE FROM activity WHERE user_id NOT IN (SELECT id FROM users)")
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query("UPDATE activity SET actor_id = NULL WHERE actor_id NOT IN (SELECT id FROM users)")
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query("UPDATE todo_comments SET author_id = NULL WHERE author_id NOT IN (SELECT id FROM users)")
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query("UPDATE attachments SET uploaded_by = NULL WHERE uploaded_by NOT IN (SELECT id FROM users)")
+                    .execute(&mut *tx)
+                    .await?;
+                tx.commit().await?;
+                Ok(restored)
+            }
+            .await;
+
+            sqlx::query("DETACH DATABASE snapshot").execute(&mut *conn).await?;
+
+            let restored = result?;
+            info!(restored_count = restored, "Database restored from snapshot");
+            Ok(restored)
+        })
+        .await
+    }
+
+    /// Runs SQLite's `PRAGMA integrity_check`, which answers a single `ok` for a sound
+    /// database and otherwise a line per problem found.
+    #[instrument(skip(self), fields(db.operation = "INTEGRITY_CHECK"))]
+    pub async fn integrity_check(&self) -> Result<Vec<String>, RepositoryError> {
+        self.capture("integrity_check", async {
+            Ok(sqlx::query_scalar("PRAGMA integrity_check").fetch_all(&self.pool).await?)
+        })
+        .await
+    }
+
+    /// The schema version and row counts of this database.
+    #[instrument(skip(self), fields(db.operation = "DATABASE_STATS"))]
+    pub async fn stats(&self) -> Result<DatabaseStats, RepositoryError> {
+        self.capture("stats", async {
+            let mut conn = self.pool.acquire().await?;
+            Ok(database_stats(&mut conn, "main").await?)
+        })
+        .await
+    }
+
+    /// The schema version and row counts of the snapshot at `path`, read through this
+    /// database's connection so an encrypted snapshot opens with the same key.
+    #[instrument(skip(self), fields(db.operation = "SNAPSHOT_STATS", backup.path = %path.display()))]
+    pub async fn snapshot_stats(&self, path: &Path) -> Result<DatabaseStats, RepositoryError> {
+        self.capture("snapshot_stats", async {
+            let mut conn = self.pool.acquire().await?;
+            sqlx::query("ATTACH DATABASE ?1 AS snapshot")
+                .bind(path.to_string_lossy().into_owned())
+                .execute(&mut *conn)
+                .await?;
+            let stats = database_stats(&mut conn, "snapshot").await;
+            sqlx::query("DETACH DATABASE snapshot").execute(&mut *conn).await?;
+            Ok(stats?)
+        })
+        .await
+    }
+
+    /// Stores a notification for delivery, already claimed by `claim` until `lease_until`, so
+    /// the dispatcher leaves it alone while the caller hands it to a worker directly.
+    #[instrument(skip(self, payload, claim), fields(db.operation = "OUTBOX_INSERT", notification.id = %id))]
+    pub async fn enqueue_notification(
+        &self,
+        id: Uuid,
+        payload: &str,
+        claim: &OutboxClaim,
+        lease_until: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        self.capture("enqueue_notification", async {
+            sqlx::query(
+                r#"
+                INSERT INTO notification_outbox
+                    (id, payload, attempts, next_attempt_at, created_at, claimed_by, claimed_at)
+                VALUES (?1, ?2, 0, ?3, ?4, ?5, ?6)
+                "#
+            )
+            .bind(id.to_string())
+            .bind(payload)
+            .bind(lease_until.timestamp_millis())
+            .bind(self.clock.now().to_rfc3339())
+            .bind(&claim.holder)
+            .bind(claim.claimed_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Claims up to `limit` notifications that are due as of `claim.claimed_at`, leasing them
+    /// until `lease_until`. Rows whose lease expired because their worker died mid-delivery
+    /// are due again. Selecting and claiming is one statement, and SQLite runs one writer at
+    /// a time, so two dispatchers never claim the same row.
+    /// Runs on every dispatcher poll, so it only gets a span at debug level.
+    #[instrument(level = "debug", skip(self, claim), fields(db.operation = "OUTBOX_CLAIM", claimed))]
+    pub async fn claim_due_notifications(
+        &self,
+        claim: &OutboxClaim,
+        lease_until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<OutboxEntry>, RepositoryError> {
+        self.capture("claim_due_notifications", async {
+            let entries: Vec<OutboxEntry> = sqlx::query_as(
+                r#"
+                UPDATE notification_outbox
+                SET next_attempt_at = ?1, claimed_by = ?2, claimed_at = ?3
+                WHERE id IN (
+                    SELECT id FROM notification_outbox
+                    WHERE failed_at IS NULL AND next_attempt_at <= ?4
+                    ORDER BY next_attempt_at
+                    LIMIT ?5
+                )
+                RETURNING id, payload, attempts, delivered_channels
+                "#
+            )
+            .bind(lease_until.timestamp_millis())
+            .bind(&claim.holder)
+            .bind(claim.claimed_at.to_rfc3339())
+            .bind(claim.claimed_at.timestamp_millis())
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Span::current().record("claimed", entries.len());
+            Ok(entries)
+        })
+        .await
+    }
+
+    /// Extends `claim` on a notification to `lease_until`, right before it is delivered.
+    /// Returns `false` if the row has been claimed by someone else since (its lease ran out
+    /// while it waited for a worker) or is gone, in which case it must not be sent.
+    #[instrument(skip(self, claim), fields(db.operation = "OUTBOX_RENEW", notification.id = %id))]
+    pub async fn renew_notification_claim(
+        &self,
+        id: Uuid,
+        claim: &OutboxClaim,
+        lease_until: DateTime<Utc>,
+    ) -> Result<bool, RepositoryError> {
+        self.capture("renew_notification_claim", async {
+            let result = sqlx::query(
+                r#"
+                UPDATE notification_outbox
+                SET next_attempt_at = ?1
+                WHERE id = ?2 AND claimed_by = ?3 AND claimed_at = ?4 AND failed_at IS NULL
+                "#
+            )
+            .bind(lease_until.timestamp_millis())
+            .bind(id.to_string())
+            .bind(&claim.holder)
+            .bind(claim.claimed_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Records a delivery attempt, the channels that have delivered it so far and when the
+    /// next attempt is due, and releases the claim. Returns `false`, changing nothing, if
+    /// `claim` no longer holds the row.
+    #[instrument(
+        skip(self, claim, last_error, delivered_channels),
+        fields(db.operation = "OUTBOX_RESCHEDULE", notification.id = %id)
+    )]
+    pub async fn reschedule_notification(
+        &self,
+        id: Uuid,
+        claim: &OutboxClaim,
+        attempts: u32,
+        next_attempt_at: DateTime<Utc>,
+        last_error: Option<&str>,
+        delivered_channels: &[String],
+    ) -> Result<bool, RepositoryError> {
+        self.capture("reschedule_notification", async {
+            let result = sqlx::query(
+                r#"
+                UPDATE notification_outbox
+                SET attempts = ?1, next_attempt_at = ?2, last_error = COALESCE(?3, last_error),
+                    claimed_by = NULL, claimed_at = NULL, delivered_channels = ?7
+                WHERE id = ?4 AND claimed_by = ?5 AND claimed_at = ?6
+                "#
+            )
+            .bind(i64::from(attempts))
+            .bind(next_attempt_at.timestamp_millis())
+            .bind(last_error)
+            .bind(id.to_string())
+            .bind(&claim.holder)
+            .bind(claim.claimed_at.to_rfc3339())
+            .bind(serde_json::Value::from(delivered_channels.to_vec()).to_string())
+            .execute(&self.pool)
+            .await?;
+
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Removes a delivered notification from the outbox. Returns `false` if `claim` no longer
+    /// held the row, meaning another worker may be sending it too.
+    #[instrument(skip(self, claim), fields(db.operation = "OUTBOX_DELETE", notification.id = %id))]
+    pub async fn complete_notification(&self, id: Uuid, claim: &OutboxClaim) -> Result<bool, RepositoryError> {
+        self.capture("complete_notification", async {
+            let result = sqlx::query(
+                "DELETE FROM notification_outbox WHERE id = ?1 AND claimed_by = ?2 AND claimed_at = ?3",
+            )
+            .bind(id.to_string())
+            .bind(&claim.holder)
+            .bind(claim.claimed_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Stops retrying a notification; the row stays in the outbox with its last error.
+    /// Returns `false`, changing nothing, if `claim` no longer holds the row.
+    #[instrument(skip(self, claim, last_error), fields(db.operation = "OUTBOX_FAIL", notification.id = %id))]
+    pub async fn fail_notification(
+        &self,
+        id: Uuid,
+        claim: &OutboxClaim,
+        attempts: u32,
+        last_error: &str,
+    ) -> Result<bool, RepositoryError> {
+        self.capture("fail_notification", async {
+            let result = sqlx::query(
+                r#"
+                UPDATE notification_outbox
+                SET attempts = ?1, last_error = ?2, failed_at = ?3, claimed_by = NULL, claimed_at = NULL
+                WHERE id = ?4 AND claimed_by = ?5 AND claimed_at = ?6
+                "#
+            )
+            .bind(i64::from(attempts))
+            .bind(last_error)
+            .bind(self.clock.now().to_rfc3339())
+            .bind(id.to_string())
+            .bind(&claim.holder)
+            .bind(claim.claimed_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Deletes todos whose `expires_at` is at or before `now`, along with their history and
+    /// dependencies. Returns the number of todos deleted.
+    #[instrument(skip(self), fields(db.operation = "DELETE_EXPIRED", deleted_count))]
+    pub async fn delete_expired(&self, now: DateTime<Utc>) -> Result<u64, RepositoryError> {
+        self.capture("delete_expired", async {
+            let mut tx = self.pool.begin().await?;
+
+            let expired: Vec<String> = sqlx::query_scalar("SELECT id FROM todos WHERE expires_at <= ?1")
+                .bind(now.to_rfc3339())
+                .fetch_all(&mut *tx)
+                .await?;
+            for id in &expired {
+                delete_todo_rows(&mut tx, id, self.clock.now()).await?;
+            }
+            tx.commit().await?;
+
+            let deleted = expired.len() as u64;
+            Span::current().record("deleted_count", deleted);
+            if deleted > 0 {
+                info!(deleted_count = deleted, "Deleted expired todos");
+            }
+            Ok(deleted)
+        })
+        .await
+    }
+
+    /// How many todos were completed at or before `cutoff`.
+    #[instrument(skip(self), fields(db.operation = "COUNT_COMPLETED_BEFORE"))]
+    pub async fn count_completed_before(&self, cutoff: DateTime<Utc>) -> Result<u64, RepositoryError> {
+        self.capture("count_completed_before", async {
+            let count: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM todos WHERE completed = true AND completed_at <= ?1"
+            )
+            .bind(cutoff.to_rfc3339())
+            .fetch_one(&self.pool)
+            .await?;
+            Ok(count as u64)
+        })
+        .await
+    }
+
+    /// Deletes todos completed at or before `cutoff`, along with their history, comments,
+    /// field values and dependencies. Returns the number of todos deleted.
+    #[instrument(skip(self), fields(db.operation = "DELETE_COMPLETED_BEFORE", deleted_count))]
+    pub async fn delete_completed_before(&self, cutoff: DateTime<Utc>) -> Result<u64, RepositoryError> {
+        self.capture("delete_completed_before", async {
+            let mut tx = self.pool.begin().await?;
+
+            let deleted: Vec<String> = sqlx::query_scalar(
+                r#"
+                DELETE FROM todos
+                WHERE completed = true AND completed_at <= ?1
+                RETURNING id
+                "#
+            )
+            .bind(cutoff.to_rfc3339())
+            .fetch_all(&mut *tx)
+            .await?;
+            // Already gone from `todos`, so their tombstones are dated here
+            for id in &deleted {
+                delete_todo_rows(&mut tx, id, self.clock.now()).await?;
+                date_tombstone(&mut tx, id, self.clock.now()).await?;
+            }
+            tx.commit().await?;
+
+            let deleted = deleted.len() as u64;
+            Span::current().record("deleted_count", deleted);
+            if deleted > 0 {
+                info!(deleted_count = deleted, "Deleted todos completed before the retention cutoff");
+            }
+            Ok(deleted)
+        })
+        .await
+    }
+
+    /// Todos changed after the sync token `since`, and the ids of those deleted; `since` of `0`
+    /// asks for every todo. Roughly `limit` changes are returned at a time, oldest first, but
+    /// changes made by one write are never split across pages. With `projects`, todos in any
+    /// project not among them are left out; deletions, which carry nothing but the id, are not.
+    #[instrument(skip(self, projects), fields(db.operation = "SELECT_CHANGES", count))]
+    pub async fn changes_since(
+        &self,
+        since: u64,
+        limit: usize,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<SyncOutcome, RepositoryError> {
+        self.capture("changes_since", async {
+            self.simulate_db_latency().await;
+            let since = since as i64;
+            let mut tx = self.pool.begin().await?;
+
+            let (current, pruned_through): (i64, i64) =
+                sqlx::query_as("SELECT change_seq, pruned_through FROM sync_state")
+                    .fetch_one(&mut *tx)
+                    .await?;
+            if since > 0 && (since < pruned_through || since > current) {
+                return Ok(SyncOutcome::Expired);
+            }
+
+            // A full sync starts from nothing, so it has no use for tombstones
+            let seqs: Vec<i64> = sqlx::query_scalar(
+                r#"
+                SELECT change_seq FROM (
+                    SELECT change_seq FROM todos WHERE change_seq > ?1
+                    UNION ALL
+                    SELECT change_seq FROM todo_tombstones WHERE change_seq > ?1 AND ?1 > 0
+                )
+                ORDER BY change_seq
+                LIMIT ?2
+                "#
+            )
+            .bind(since)
+            .bind(limit as i64)
+            .fetch_all(&mut *tx)
+            .await?;
+            let full_page = seqs.len() == limit;
+            let through = if full_page { seqs.last().copied().unwrap_or(current) } else { current };
+
+            let rows = sqlx::query_as::<_, SyncRow>(&format!(
+                r#"
+                SELECT {TODO_COLUMNS}, created_seq
+                FROM todos
+                WHERE change_seq > ?2 AND change_seq <= ?3 AND {NOT_EXPIRED}
+                  AND (?4 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?4)))
+                ORDER BY change_seq
+                "#
+            ))
+            .bind(self.clock.now().to_rfc3339())
+            .bind(since)
+            .bind(through)
+            .bind(projects_json(projects))
+            .fetch_all(&mut *tx)
+            .await?;
+            let tombstones = sqlx::query_as::<_, TombstoneRow>(
+                r#"
+                SELECT id, deleted_at
+                FROM todo_tombstones
+                WHERE change_seq > ?1 AND change_seq <= ?2 AND ?1 > 0
+                ORDER BY change_seq
+                "#
+            )
+            .bind(since)
+            .bind(through)
+            .fetch_all(&mut *tx)
+            .await?;
+            tx.commit().await?;
+
+            let mut changes = SyncChanges {
+                created: Vec::new(),
+                updated: Vec::new(),
+                deleted: Vec::with_capacity(tombstones.len()),
+                token: through.to_string(),
+                has_more: full_page && through < current,
+            };
+            for row in rows {
+                let created = row.created_seq > since;
+                let todo = self.row_to_todo(row.todo)?;
+                if created {
+                    changes.created.push(todo);
+                } else {
+                    changes.updated.push(todo);
+                }
+            }
+            for tombstone in tombstones {
+                changes.deleted.push(DeletedTodo {
+                    id: Uuid::parse_str(&tombstone.id).map_err(|e| {
+                        RepositoryError::InvalidData(format!("bad tombstone id {}: {e}", tombstone.id))
+                    })?,
+                    deleted_at: parse_timestamp(&tombstone.deleted_at)?,
+                });
+            }
+
+            let count = changes.created.len() + changes.updated.len() + changes.deleted.len();
+            Span::current().record("count", count);
+            Ok(SyncOutcome::Changes(changes))
+        })
+        .await
+    }
+
+    /// Applies edits a client made offline, in order and in one transaction. An update or
+    /// delete whose `base_version` is no longer current, or a create whose id is taken, is
+    /// skipped and reported as a conflict along with the todo as stored. With `projects`, an
+    /// edit to a todo in any project not among them is skipped as `Forbidden`, without the todo.
+    #[instrument(skip(self, mutations, projects), fields(db.operation = "SYNC_PUSH", count = mutations.len(), conflicts))]
+    pub async fn apply_mutations(
+        &self,
+        mutations: Vec<SyncMutation>,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<Vec<SyncMutationResult>, RepositoryError> {
+        self.capture("apply_mutations", async {
+            self.simulate_db_latency().await;
+            let mut tx = self.pool.begin().await?;
+            let mut results = Vec::with_capacity(mutations.len());
+            let mut conflicts = 0;
+
+            for mutation in mutations {
+                let id = mutation.id();
+                let id_str = id.to_string();
+                let row = sqlx::query_as::<_, TodoRow>(&format!("SELECT {TODO_COLUMNS} FROM todos WHERE id = ?1"))
+                    .bind(&id_str)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+                let current = row.map(|row| self.row_to_todo(row)).transpose()?;
+
+                let forbidden = current
+                    .as_ref()
+                    .and_then(|todo| todo.project_id)
+                    .is_some_and(|project| projects.is_some_and(|projects| !projects.contains(&project)));
+                if forbidden {
+                    conflicts += 1;
+                    let reason = ConflictReason::Forbidden;
+                    results.push(SyncMutationResult::Conflict { id, reason, client: mutation, server: None });
+                    continue;
+                }
+                let conflict = match (&mutation, &current) {
+                    (SyncMutation::Create { .. }, Some(_)) => Some(ConflictReason::Exists),
+                    (SyncMutation::Update { .. }, None) => Some(ConflictReason::Deleted),
+                    (
+                        SyncMutation::Update { base_version, .. } | SyncMutation::Delete { base_version, .. },
+                        Some(todo),
+                    ) if todo.version != *base_version => Some(ConflictReason::VersionMismatch),
+                    _ => None,
+                };
+                if let Some(reason) = conflict {
+                    conflicts += 1;
+                    results.push(SyncMutationResult::Conflict { id, reason, client: mutation, server: current });
+                    continue;
+                }
+
+                let result = match (mutation, current) {
+                    (SyncMutation::Create { todo: request, .. }, _) => {
+                        let now = self.clock.now();
+                        let todo = Todo {
+                            id,
+                            title: request.title,
+                            description: request.description,
+                            completed: false,
+                            due_at: request.due_at,
+                            tags: normalize_tags(request.tags),
+                            estimate_minutes: request.estimate_minutes,
+                            expires_at: request.expires_at,
+                            project_id: None,
+                            priority: request.priority,
+                            pinned: request.pinned,
+                            custom_fields: normalize_custom_fields(request.custom_fields),
+                            created_at: now,
+                            updated_at: now,
+                            blocked: false,
+                            version: 1,
+                            change_seq: 0,
+                        };
+                        let (description, key_id) = self.seal_description(&todo)?;
+                        insert_query(&todo, description, key_id).execute(&mut *tx).await?;
+                        write_custom_fields(&mut tx, &todo).await?;
+                        let change_seq = read_change_seq(&mut tx, id).await?;
+                        Some(Todo { change_seq, ..todo })
+                    }
+                    (SyncMutation::Update { base_version, changes, .. }, Some(mut todo)) => {
+                        changes.apply(&mut todo, self.clock.now());
+                        let (description, key_id) = self.seal_description(&todo)?;
+                        let version = update_query(&todo, description, key_id, Some(base_version))
+                            .fetch_one(&mut *tx)
+                            .await?;
+                        write_custom_fields(&mut tx, &todo).await?;
+                        let change_seq = read_change_seq(&mut tx, id).await?;
+                        Some(Todo { version: version as u64, change_seq, ..todo })
+                    }
+                    // Deleting a todo that is already gone leaves the client where it wanted to be
+                    (SyncMutation::Delete { .. }, _) => {
+                        delete_todo_rows(&mut tx, &id_str, self.clock.now()).await?;
+                        None
+                    }
+                    (SyncMutation::Update { .. }, None) => unreachable!("reported as a conflict above"),
+                };
+                results.push(SyncMutationResult::Applied { id, todo: result });
+            }
+            tx.commit().await?;
+
+            Span::current().record("conflicts", conflicts);
+            info!(applied = results.len() - conflicts, conflicts, "Applied pushed changes");
+            Ok(results)
+        })
+        .await
+    }
+
+    /// Drops tombstones of todos deleted before `cutoff`. Tokens from before the newest one
+    /// dropped can no longer be served, which `changes_since` reports as `Expired`.
+    #[instrument(skip(self), fields(db.operation = "DELETE_TOMBSTONES", deleted_count))]
+    async fn prune_tombstones(&self, cutoff: DateTime<Utc>) -> Result<u64, RepositoryError> {
+        self.capture("prune_tombstones", async {
+            let cutoff = tombstone_time(cutoff);
+            let mut tx = self.pool.begin().await?;
+
+            sqlx::query(
+                r#"
+                UPDATE sync_state
+                SET pruned_through = MAX(
+                    pruned_through,
+                    COALESCE((SELECT MAX(change_seq) FROM todo_tombstones WHERE deleted_at <= ?1), 0)
+                )
+                "#
+            )
+            .bind(&cutoff)
+            .execute(&mut *tx)
+            .await?;
+            let deleted = sqlx::query("DELETE FROM todo_tombstones WHERE deleted_at <= ?1")
+                .bind(&cutoff)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            tx.commit().await?;
+
+            Span::current().record("deleted_count", deleted);
+            if deleted > 0 {
+                info!(deleted_count = deleted, "Pruned sync tombstones");
+            }
+            Ok(deleted)
+        })
+        .await
+    }
+
+    /// Fails with `AlreadyExists` if the email is taken, ignoring case.
+    #[instrument(skip(self, user, password_hash), fields(db.operation = "INSERT_USER", user.id = %user.id))]
+    pub async fn create_user(&self, user: &User, password_hash: &str) -> Result<(), RepositoryError> {
+        self.capture("create_user", async {
+            let result = sqlx::query(
+                r#"
+                INSERT INTO users (id, email, password_hash, created_at)
+                VALUES (?1, ?2, ?3, ?4)
+                "#
+            )
+            .bind(user.id.to_string())
+            .bind(&user.email)
+            .bind(password_hash)
+            .bind(user.created_at.to_rfc3339())
+            .execute(&self.pool)
+            .await;
+
+            match result {
+                Ok(_) => Ok(()),
+                Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                    Err(RepositoryError::AlreadyExists("email".to_string()))
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+        .await
+    }
+
+    /// The user with this email, ignoring case, and their password hash.
+    #[instrument(skip(self, email), fields(db.operation = "SELECT_USER"))]
+    pub async fn find_user_by_email(&self, email: &str) -> Result<Option<(User, String)>, RepositoryError> {
+        self.capture("find_user_by_email", async {
+            let row = sqlx::query_as::<_, UserRow>(&format!("SELECT {USER_COLUMNS} FROM users WHERE email = ?1"))
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(user_from_row).transpose()
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(db.operation = "SELECT_USER", user.id = %id))]
+    pub async fn get_user(&self, id: Uuid) -> Result<User, RepositoryError> {
+        self.capture("get_user", async {
+            let row = sqlx::query_as::<_, UserRow>(&format!("SELECT {USER_COLUMNS} FROM users WHERE id = ?1"))
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(RepositoryError::NotFound(id))?;
+            user_from_row(row).map(|(user, _)| user)
+        })
+        .await
+    }
+
+    /// Grants or withdraws the `admin` scope of the account with this email, ignoring case.
+    /// Returns `false` when there is no such account.
+    #[instrument(skip(self, email), fields(db.operation = "UPDATE_USER_ADMIN"))]
+    pub async fn set_user_admin(&self, email: &str, admin: bool) -> Result<bool, RepositoryError> {
+        self.capture("set_user_admin", async {
+            let result = sqlx::query("UPDATE users SET admin = ?2 WHERE email = ?1")
+                .bind(email)
+                .bind(admin)
+                .execute(&self.pool)
+                .await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    #[instrument(skip(self, token_hash), fields(db.operation = "INSERT_REFRESH_TOKEN", user.id = %user_id))]
+    pub async fn insert_refresh_token(
+        &self,
+        token_hash: &str,
+        user_id: Uuid,
+        family_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        self.capture("insert_refresh_token", async {
+            insert_refresh_token(&self.pool, token_hash, user_id, family_id, expires_at, self.clock.now()).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Swaps the refresh token hashed as `old_hash` for `new_hash` in one transaction.
+    /// Presenting a token that was already swapped out revokes its whole family, since
+    /// either the client or someone who stole the token is replaying it.
+    #[instrument(skip_all, fields(db.operation = "ROTATE_REFRESH_TOKEN"))]
+    pub async fn rotate_refresh_token(
+        &self,
+        old_hash: &str,
+        new_hash: &str,
+        new_expires_at: DateTime<Utc>,
+    ) -> Result<RefreshOutcome, RepositoryError> {
+        self.capture("rotate_refresh_token", async {
+            let now = self.clock.now();
+            let mut tx = self.pool.begin().await?;
+
+            let row: Option<(String, String, String, Option<String>)> = sqlx::query_as(
+                "SELECT user_id, family_id, expires_at, revoked_at FROM refresh_tokens WHERE token_hash = ?1",
+            )
+            .bind(old_hash)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let Some((user_id, family_id, expires_at, revoked_at)) = row else {
+                return Ok(RefreshOutcome::Unknown);
+            };
+
+            if revoked_at.is_some() {
+                warn!(session.id = %family_id, "Refresh token reused, revoking session");
+                revoke_family(&mut tx, &family_id, now).await?;
+                tx.commit().await?;
+                return Ok(RefreshOutcome::Reused {
+                    user_id: parse_uuid("user", &user_id)?,
+                });
+            }
+            if parse_timestamp(&expires_at)? <= now {
+                return Ok(RefreshOutcome::Expired);
+            }
+
+            sqlx::query("UPDATE refresh_tokens SET revoked_at = ?2 WHERE token_hash = ?1")
+                .bind(old_hash)
+                .bind(now.to_rfc3339())
+                .execute(&mut *tx)
+                .await?;
+            let user_id = Uuid::parse_str(&user_id)
+                .map_err(|e| RepositoryError::InvalidData(format!("bad user id {user_id}: {e}")))?;
+            let family_id = Uuid::parse_str(&family_id)
+                .map_err(|e| RepositoryError::InvalidData(format!("bad session id {family_id}: {e}")))?;
+            insert_refresh_token(&mut *tx, new_hash, user_id, family_id, new_expires_at, now).await?;
+            tx.commit().await?;
+
+            Ok(RefreshOutcome::Rotated { user_id, family_id })
+        })
+        .await
+    }
+
+    /// Revokes every token in the session the refresh token hashed as `token_hash` belongs to.
+    /// Returns the session's user, or `None` if there is no such token.
+    #[instrument(skip_all, fields(db.operation = "REVOKE_SESSION"))]
+    pub async fn revoke_session(&self, token_hash: &str) -> Result<Option<Uuid>, RepositoryError> {
+        self.capture("revoke_session", async {
+            let mut tx = self.pool.begin().await?;
+            let session: Option<(String, String)> =
+                sqlx::query_as("SELECT family_id, user_id FROM refresh_tokens WHERE token_hash = ?1")
+                    .bind(token_hash)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+            let Some((family_id, user_id)) = session else {
+                return Ok(None);
+            };
+            revoke_family(&mut tx, &family_id, self.clock.now()).await?;
+            tx.commit().await?;
+            Uuid::parse_str(&user_id)
+                .map(Some)
+                .map_err(|e| RepositoryError::InvalidData(format!("bad user id {user_id}: {e}")))
+        })
+        .await
+    }
+
+    /// Whether the session still has an unrevoked, unexpired refresh token.
+    #[instrument(skip(self), fields(db.operation = "SELECT_SESSION", session.id = %family_id))]
+    pub async fn session_active(&self, family_id: Uuid) -> Result<bool, RepositoryError> {
+        self.capture("session_active", async {
+            let active: Option<i64> = sqlx::query_scalar(
+                r#"
+                SELECT 1 FROM refresh_tokens
+                WHERE family_id = ?1 AND revoked_at IS NULL AND expires_at > ?2
+                LIMIT 1
+                "#
+            )
+            .bind(family_id.to_string())
+            .bind(self.clock.now().to_rfc3339())
+            .fetch_optional(&self.pool)
+            .await?;
+            Ok(active.is_some())
+        })
+        .await
+    }
+
+    #[instrument(skip(self, token, token_hash), fields(db.operation = "INSERT_API_TOKEN", user.id = %user_id))]
+    pub async fn create_api_token(
+        &self,
+        user_id: Uuid,
+        token: &ApiToken,
+        token_hash: &str,
+    ) -> Result<(), RepositoryError> {
+        self.capture("create_api_token", async {
+            sqlx::query(
+                r#"
+                INSERT INTO api_tokens (id, user_id, name, token_hash, scopes, created_at, expires_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                "#
+            )
+            .bind(token.id.to_string())
+            .bind(user_id.to_string())
+            .bind(&token.name)
+            .bind(token_hash)
+            .bind(token.scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(" "))
+            .bind(token.created_at.to_rfc3339())
+            .bind(token.expires_at.map(|e| e.to_rfc3339()))
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The user's tokens that are neither revoked nor expired, newest first.
+    #[instrument(skip(self), fields(db.operation = "SELECT_API_TOKENS", user.id = %user_id))]
+    pub async fn list_api_tokens(&self, user_id: Uuid) -> Result<Vec<ApiToken>, RepositoryError> {
+        self.capture("list_api_tokens", async {
+            let rows = sqlx::query_as::<_, ApiTokenRow>(
+                r#"
+                SELECT id, user_id, name, scopes, created_at, expires_at, revoked_at
+                FROM api_tokens
+                WHERE user_id = ?1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > ?2)
+                ORDER BY created_at DESC
+                "#
+            )
+            .bind(user_id.to_string())
+            .bind(self.clock.now().to_rfc3339())
+            .fetch_all(&self.pool)
+            .await?;
+            rows.into_iter().map(|row| api_token_from_row(row).map(|(_, token)| token)).collect()
+        })
+        .await
+    }
+
+    /// The owner and details of a live token, by the hash of its secret.
+    #[instrument(skip_all, fields(db.operation = "SELECT_API_TOKEN"))]
+    pub async fn find_api_token(&self, token_hash: &str) -> Result<Option<(Uuid, ApiToken)>, RepositoryError> {
+        self.capture("find_api_token", async {
+            let row = sqlx::query_as::<_, ApiTokenRow>(
+                r#"
+                SELECT id, user_id, name, scopes, created_at, expires_at, revoked_at
+                FROM api_tokens
+                WHERE token_hash = ?1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > ?2)
+                "#
+            )
+            .bind(token_hash)
+            .bind(self.clock.now().to_rfc3339())
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(api_token_from_row).transpose()
+        })
+        .await
+    }
+
+    /// Every token the user ever created, revoked and expired ones included, oldest first.
+    #[instrument(skip(self), fields(db.operation = "SELECT_ALL_API_TOKENS", user.id = %user_id))]
+    pub async fn all_api_tokens(&self, user_id: Uuid) -> Result<Vec<ApiToken>, RepositoryError> {
+        self.capture("all_api_tokens", async {
+            let rows = sqlx::query_as::<_, ApiTokenRow>(
+                r#"
+                SELECT id, user_id, name, scopes, created_at, expires_at, revoked_at
+                FROM api_tokens
+                WHERE user_id = ?1
+                ORDER BY created_at
+                "#
+            )
+            .bind(user_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+            rows.into_iter().map(|row| api_token_from_row(row).map(|(_, token)| token)).collect()
+        })
+        .await
+    }
+
+    /// The user's sessions, one per refresh token family, oldest first.
+    #[instrument(skip(self), fields(db.operation = "SELECT_SESSIONS", user.id = %user_id))]
+    pub async fn list_sessions(&self, user_id: Uuid) -> Result<Vec<SessionInfo>, RepositoryError> {
+        self.capture("list_sessions", async {
+            let rows: Vec<(String, String, String, String, Option<String>)> = sqlx::query_as(
+                r#"
+                SELECT family_id, MIN(created_at), MAX(created_at), MAX(expires_at),
+                       CASE WHEN COUNT(revoked_at) = COUNT(*) THEN MAX(revoked_at) END
+                FROM refresh_tokens
+                WHERE user_id = ?1
+                GROUP BY family_id
+                ORDER BY MIN(created_at)
+                "#
+            )
+            .bind(user_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+
+            rows.into_iter()
+                .map(|(id, started_at, last_refreshed_at, expires_at, revoked_at)| {
+                    Ok(SessionInfo {
+                        id: Uuid::parse_str(&id)
+                            .map_err(|e| RepositoryError::InvalidData(format!("bad session id {id}: {e}")))?,
+                        started_at: parse_timestamp(&started_at)?,
+                        last_refreshed_at: parse_timestamp(&last_refreshed_at)?,
+                        expires_at: parse_timestamp(&expires_at)?,
+                        revoked_at: revoked_at.as_deref().map(parse_timestamp).transpose()?,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Fails with `NotFound` unless the user has a live token with this id.
+    #[instrument(skip(self), fields(db.operation = "REVOKE_API_TOKEN", user.id = %user_id, api_token.id = %id))]
+    pub async fn revoke_api_token(&self, user_id: Uuid, id: Uuid) -> Result<(), RepositoryError> {
+        self.capture("revoke_api_token", async {
+            let result = sqlx::query(
+                "UPDATE api_tokens SET revoked_at = ?3 WHERE id = ?1 AND user_id = ?2 AND revoked_at IS NULL",
+            )
+            .bind(id.to_string())
+            .bind(user_id.to_string())
+            .bind(self.clock.now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+            if result.rows_affected() == 0 {
+                Err(RepositoryError::NotFound(id))
+            } else {
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    #[instrument(skip_all, fields(db.operation = "INSERT_AUDIT", audit.event = entry.event))]
+    pub async fn insert_audit(&self, entry: NewAuditEntry<'_>) -> Result<(), RepositoryError> {
+        self.capture("insert_audit", async {
+            sqlx::query(
+                r#"
+                INSERT INTO auth_audit (event, user_id, email, ip, user_agent, detail, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                "#
+            )
+            .bind(entry.event)
+            .bind(entry.user_id.map(|id| id.to_string()))
+            .bind(entry.email)
+            .bind(entry.ip)
+            .bind(entry.user_agent)
+            .bind(entry.detail.to_string())
+            .bind(self.clock.now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Audit entries matching every filter given, newest first.
+    #[instrument(skip(self), fields(db.operation = "SELECT_AUDIT", count))]
+    pub async fn list_audit(&self, query: &AuditQuery, limit: u32) -> Result<Vec<AuditEntry>, RepositoryError> {
+        self.capture("list_audit", async {
+            let rows = sqlx::query_as::<_, AuditRow>(
+                r#"
+                SELECT id, event, user_id, email, ip, user_agent, detail, created_at
+                FROM auth_audit
+                WHERE (?1 IS NULL OR event = ?1)
+                  AND (?2 IS NULL OR user_id = ?2)
+                  AND (?3 IS NULL OR created_at >= ?3)
+                  AND (?4 IS NULL OR id < ?4)
+                ORDER BY id DESC
+                LIMIT ?5
+                "#
+            )
+            .bind(query.event.as_deref())
+            .bind(query.user_id.map(|id| id.to_string()))
+            .bind(query.since.map(|since| since.to_rfc3339()))
+            .bind(query.before)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+
+            rows.into_iter().map(audit_entry_from_row).collect()
+        })
+        .await
+    }
+
+    /// Audit entries about a user: those naming their id, plus failed logins for their email.
+    #[instrument(skip(self, email), fields(db.operation = "COUNT_USER_AUDIT", user.id = %user_id))]
+    pub async fn count_user_audit(&self, user_id: Uuid, email: &str) -> Result<u64, RepositoryError> {
+        self.capture("count_user_audit", async {
+            let count: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM auth_audit WHERE user_id = ?1 OR email = ?2 COLLATE NOCASE",
+            )
+            .bind(user_id.to_string())
+            .bind(email)
+            .fetch_one(&self.pool)
+            .await?;
+            Ok(count as u64)
+        })
+        .await
+    }
+
+    /// A page of `count_user_audit`'s entries, oldest first, after the entry with id `after`.
+    #[instrument(skip(self, email), fields(db.operation = "SELECT_USER_AUDIT", user.id = %user_id))]
+    pub async fn user_audit(
+        &self,
+        user_id: Uuid,
+        email: &str,
+        after: i64,
+        limit: u32,
+    ) -> Result<Vec<AuditEntry>, RepositoryError> {
+        self.capture("user_audit", async {
+            let rows = sqlx::query_as::<_, AuditRow>(
+                r#"
+                SELECT id, event, user_id, email, ip, user_agent, detail, created_at
+                FROM auth_audit
+                WHERE (user_id = ?1 OR email = ?2 COLLATE NOCASE) AND id > ?3
+                ORDER BY id
+                LIMIT ?4
+                "#
+            )
+            .bind(user_id.to_string())
+            .bind(email)
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+            rows.into_iter().map(audit_entry_from_row).collect()
+        })
+        .await
+    }
+
+    #[instrument(skip_all, fields(db.operation = "INSERT_ERASURE_JOB", erasure.id = %id, user.id = %user.id))]
+    pub async fn create_erasure_job(&self, id: Uuid, user: &User) -> Result<ErasureJob, RepositoryError> {
+        self.capture("create_erasure_job", async {
+            let now = self.clock.now();
+            sqlx::query(
+                r#"
+                INSERT INTO erasure_jobs (id, user_id, email, status, created_at, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+                "#
+            )
+            .bind(id.to_string())
+            .bind(user.id.to_string())
+            .bind(&user.email)
+            .bind(ErasureStatus::Pending.as_str())
+            .bind(now.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+            Ok(ErasureJob {
+                id,
+                status: ErasureStatus::Pending,
+                steps_completed: 0,
+                error: None,
+                created_at: now,
+                updated_at: now,
+            })
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(db.operation = "SELECT_ERASURE_JOB"))]
+    pub async fn erasure_job(&self, id: Uuid) -> Result<Option<ErasureJob>, RepositoryError> {
+        self.capture("erasure_job", async {
+            let row = sqlx::query_as::<_, ErasureJobRow>(
+                r#"
+                SELECT id, user_id, email, status, steps_completed, error, created_at, updated_at
+                FROM erasure_jobs
+                WHERE id = ?1
+                "#
+            )
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(|row| erasure_job_from_row(row).map(|(job, _)| job)).transpose()
+        })
+        .await
+    }
+
+    /// Jobs that haven't completed, with the user and email they are erasing.
+    #[instrument(skip(self), fields(db.operation = "SELECT_UNFINISHED_ERASURE_JOBS"))]
+    pub async fn unfinished_erasure_jobs(&self) -> Result<Vec<(ErasureJob, Uuid, String)>, RepositoryError> {
+        self.capture("unfinished_erasure_jobs", async {
+            let rows = sqlx::query_as::<_, ErasureJobRow>(
+                r#"
+                SELECT id, user_id, email, status, steps_completed, error, created_at, updated_at
+                FROM erasure_jobs
+                WHERE status != ?1
+                ORDER BY created_at
+                "#
+            )
+            .bind(ErasureStatus::Completed.as_str())
+            .fetch_all(&self.pool)
+            .await?;
+            rows.into_iter()
+                .map(|row| {
+                    let (job, subject) = erasure_job_from_row(row)?;
+                    let (user_id, email) = subject.ok_or_else(|| {
+                        RepositoryError::InvalidData(format!("erasure job {} has no subject", job.id))
+                    })?;
+                    Ok((job, user_id, email))
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Completing a job also forgets whose account it erased.
+    #[instrument(skip(self, error), fields(db.operation = "UPDATE_ERASURE_JOB", erasure.id = %id))]
+    pub async fn update_erasure_job(
+        &self,
+        id: Uuid,
+        status: ErasureStatus,
+        steps_completed: u32,
+        error: Option<&str>,
+    ) -> Result<(), RepositoryError> {
+        self.capture("update_erasure_job", async {
+            sqlx::query(
+                r#"
+                UPDATE erasure_jobs
+                SET status = ?2, steps_completed = ?3, error = ?4, updated_at = ?5,
+                    user_id = CASE WHEN ?2 = 'completed' THEN NULL ELSE user_id END,
+                    email = CASE WHEN ?2 = 'completed' THEN NULL ELSE email END
+                WHERE id = ?1
+                "#
+            )
+            .bind(id.to_string())
+            .bind(status.as_str())
+            .bind(steps_completed)
+            .bind(error)
+            .bind(self.clock.now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Removes the account with its sessions, personal access tokens, project memberships,
+    /// activity feed and API usage, so it can no longer sign in or use any credential it was
+    /// issued. Its comments and uploads stay, without an author.
+    #[instrument(skip(self), fields(db.operation = "DELETE_USER", user.id = %user_id))]
+    pub async fn delete_user(&self, user_id: Uuid) -> Result<(), RepositoryError> {
+        self.capture("delete_user", async {
+            let id = user_id.to_string();
+            let mut tx = self.pool.begin().await?;
+            sqlx::query("DELETE FROM refresh_tokens WHERE user_id = ?1")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM api_tokens WHERE user_id = ?1")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM project_members WHERE user_id = ?1")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM activity WHERE user_id = ?1")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE activity SET actor_id = NULL WHERE actor_id = ?1")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE todo_comments SET author_id = NULL WHERE author_id = ?1")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE attachments SET uploaded_by = NULL WHERE uploaded_by = ?1")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM api_usage WHERE user_id = ?1 OR client = 'user:' || ?1")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM users WHERE id = ?1")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Strips the user id, email, IP, user agent and any user-chosen token name from the
+    /// user's audit entries, keeping the events themselves. Returns how many were changed.
+    #[instrument(skip(self, email), fields(db.operation = "ANONYMIZE_AUDIT", user.id = %user_id, count))]
+    pub async fn anonymize_audit(&self, user_id: Uuid, email: &str) -> Result<u64, RepositoryError> {
+        self.capture("anonymize_audit", async {
+            let result = sqlx::query(
+                r#"
+                UPDATE auth_audit
+                SET user_id = NULL, email = NULL, ip = NULL, user_agent = NULL,
+                    detail = json_remove(detail, '$.name')
+                WHERE user_id = ?1 OR email = ?2 COLLATE NOCASE
+                "#
+            )
+            .bind(user_id.to_string())
+            .bind(email)
+            .execute(&self.pool)
+            .await?;
+            Span::current().record("count", result.rows_affected());
+            Ok(result.rows_affected())
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(db.operation = "SELECT_FLAGS", count))]
+    pub async fn list_flags(&self) -> Result<Vec<FeatureFlag>, RepositoryError> {
+        self.capture("list_flags", async {
+            let rows: Vec<(String, bool, Option<String>, String)> = sqlx::query_as(
+                "SELECT name, enabled, description, updated_at FROM feature_flags ORDER BY name",
+            )
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter()
+                .map(|(name, enabled, description, updated_at)| {
+                    Ok(FeatureFlag {
+                        name,
+                        enabled,
+                        description,
+                        updated_at: parse_timestamp(&updated_at)?,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Creates or replaces the flag. A missing description keeps the stored one.
+    #[instrument(skip(self, description), fields(db.operation = "UPSERT_FLAG"))]
+    pub async fn set_flag(
+        &self,
+        name: &str,
+        enabled: bool,
+        description: Option<&str>,
+    ) -> Result<FeatureFlag, RepositoryError> {
+        self.capture("set_flag", async {
+            let updated_at = self.clock.now();
+            let description: Option<String> = sqlx::query_scalar(
+                r#"
+                INSERT INTO feature_flags (name, enabled, description, updated_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (name) DO UPDATE SET
+                    enabled = excluded.enabled,
+                    description = COALESCE(excluded.description, feature_flags.description),
+                    updated_at = excluded.updated_at
+                RETURNING description
+                "#
+            )
+            .bind(name)
+            .bind(enabled)
+            .bind(description)
+            .bind(updated_at.to_rfc3339())
+            .fetch_one(&self.pool)
+            .await?;
+            Ok(FeatureFlag {
+                name: name.to_owned(),
+                enabled,
+                description,
+                updated_at,
+            })
+        })
+        .await
+    }
+
+    /// Returns false if there was no such flag.
+    #[instrument(skip(self), fields(db.operation = "DELETE_FLAG"))]
+    pub async fn delete_flag(&self, name: &str) -> Result<bool, RepositoryError> {
+        self.capture("delete_flag", async {
+            let result = sqlx::query("DELETE FROM feature_flags WHERE name = ?1")
+                .bind(name)
+                .execute(&self.pool)
+                .await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Adds to the usage rollup, each count to the hour starting at its timestamp. Counts of
+    /// users erased since they were taken are dropped, so erasure leaves no usage behind.
+    #[instrument(skip_all, fields(db.operation = "UPSERT_USAGE", count = usage.len()))]
+    pub async fn record_usage(
+        &self,
+        usage: &[(DateTime<Utc>, UsageKey, UsageCounts)],
+    ) -> Result<(), RepositoryError> {
+        self.capture("record_usage", async {
+            let mut tx = self.pool.begin().await?;
+            for (hour, key, counts) in usage {
+                sqlx::query(
+                    r#"
+                    INSERT INTO api_usage
+                        (hour, client, user_id, method, route, requests, client_errors, server_errors)
+                    SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8
+                    WHERE ?3 IS NULL OR EXISTS (SELECT 1 FROM users WHERE id = ?3)
+                    ON CONFLICT (hour, client, method, route) DO UPDATE SET
+                        requests = api_usage.requests + excluded.requests,
+                        client_errors = api_usage.client_errors + excluded.client_errors,
+                        server_errors = api_usage.server_errors + excluded.server_errors
+                    "#
+                )
+                .bind(hour.to_rfc3339_opts(SecondsFormat::Secs, true))
+                .bind(&key.client)
+                .bind(key.user_id.map(|id| id.to_string()))
+                .bind(&key.method)
+                .bind(&key.route)
+                .bind(counts.requests as i64)
+                .bind(counts.client_errors as i64)
+                .bind(counts.server_errors as i64)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Totals per client and endpoint over the hours starting at or after `since`.
+    #[instrument(skip(self), fields(db.operation = "SELECT_USAGE", count))]
+    pub async fn usage_since(&self, since: DateTime<Utc>) -> Result<Vec<(UsageKey, UsageCounts)>, RepositoryError> {
+        self.capture("usage_since", async {
+            let rows = sqlx::query_as::<_, UsageRow>(
+                r#"
+                SELECT client, MAX(user_id) AS user_id, method, route,
+                       SUM(requests) AS requests,
+                       SUM(client_errors) AS client_errors,
+                       SUM(server_errors) AS server_errors
+                FROM api_usage
+                WHERE hour >= ?1
+                GROUP BY client, method, route
+                "#
+            )
+            .bind(since.to_rfc3339_opts(SecondsFormat::Secs, true))
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(usage_from_row).collect()
+        })
+        .await
+    }
+
+    /// Deletes usage of the hours before `before`. Returns how many rows went.
+    #[instrument(skip(self), fields(db.operation = "DELETE_USAGE"))]
+    pub async fn prune_usage(&self, before: DateTime<Utc>) -> Result<u64, RepositoryError> {
+        self.capture("prune_usage", async {
+            let result = sqlx::query("DELETE FROM api_usage WHERE hour < ?1")
+                .bind(before.to_rfc3339_opts(SecondsFormat::Secs, true))
+                .execute(&self.pool)
+                .await?;
+            Ok(result.rows_affected())
+        })
+        .await
+    }
+
+    /// Takes the lease on `job` for `holder` until `until`, or renews it if `holder` already
+    /// has it. Fails (returning `false`) while another holder's lease runs.
+    #[instrument(skip(self), fields(db.operation = "ACQUIRE_LEASE"))]
+    pub async fn acquire_lease(
+        &self,
+        job: &str,
+        holder: &str,
+        now: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<bool, RepositoryError> {
+        self.capture("acquire_lease", async {
+            let result = sqlx::query(
+                r#"
+                INSERT INTO job_leases (job, holder, acquired_at, expires_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (job) DO UPDATE SET
+                    holder = excluded.holder,
+                    acquired_at = CASE WHEN job_leases.holder = excluded.holder
+                        THEN job_leases.acquired_at ELSE excluded.acquired_at END,
+                    expires_at = excluded.expires_at
+                WHERE job_leases.holder = excluded.holder OR job_leases.expires_at <= ?5
+                "#
+            )
+            .bind(job)
+            .bind(holder)
+            .bind(now.to_rfc3339())
+            .bind(until.timestamp_millis())
+            .bind(now.timestamp_millis())
+            .execute(&self.pool)
+            .await?;
+            Ok(result.rows_affected() == 1)
+        })
+        .await
+    }
+
+    /// Gives up `holder`'s lease on `job`, so another replica can take it straight away.
+    #[instrument(skip(self), fields(db.operation = "RELEASE_LEASE"))]
+    pub async fn release_lease(&self, job: &str, holder: &str) -> Result<(), RepositoryError> {
+        self.capture("release_lease", async {
+            sqlx::query("DELETE FROM job_leases WHERE job = ?1 AND holder = ?2")
+                .bind(job)
+                .bind(holder)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The link of `external_id` in `source`, if any. Its todo may since have been deleted.
+    #[instrument(skip(self), fields(db.operation = "SELECT_EXTERNAL_LINK"))]
+    pub async fn external_link(&self, source: &str, external_id: &str) -> Result<Option<ExternalLink>, RepositoryError> {
+        self.capture("external_link", async {
+            let row = sqlx::query_as::<_, ExternalLinkRow>(
+                r#"
+                SELECT source, external_id, todo_id, remote_state, created_at
+                FROM todo_external_links
+                WHERE source = ?1 AND external_id = ?2
+                "#
+            )
+            .bind(source)
+            .bind(external_id)
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(external_link_from_row).transpose()
+        })
+        .await
+    }
+
+    /// The todo's most recent link to an item in `source`, if any.
+    #[instrument(skip(self), fields(db.operation = "SELECT_EXTERNAL_LINK", todo.id = %todo_id))]
+    pub async fn external_link_for_todo(
+        &self,
+        source: &str,
+        todo_id: Uuid,
+    ) -> Result<Option<ExternalLink>, RepositoryError> {
+        self.capture("external_link_for_todo", async {
+            let row = sqlx::query_as::<_, ExternalLinkRow>(
+                r#"
+                SELECT source, external_id, todo_id, remote_state, created_at
+                FROM todo_external_links
+                WHERE source = ?1 AND todo_id = ?2
+                ORDER BY created_at DESC
+                LIMIT 1
+                "#
+            )
+            .bind(source)
+            .bind(todo_id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(external_link_from_row).transpose()
+        })
+        .await
+    }
+
+    /// Every link to an item in `source`, oldest first. Their todos may since have been deleted.
+    #[instrument(skip(self), fields(db.operation = "SELECT_EXTERNAL_LINKS", count))]
+    pub async fn external_links(&self, source: &str) -> Result<Vec<ExternalLink>, RepositoryError> {
+        self.capture("external_links", async {
+            let rows = sqlx::query_as::<_, ExternalLinkRow>(
+                r#"
+                SELECT source, external_id, todo_id, remote_state, created_at
+                FROM todo_external_links
+                WHERE source = ?1
+                ORDER BY created_at, external_id
+                "#
+            )
+            .bind(source)
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(external_link_from_row).collect()
+        })
+        .await
+    }
+
+    /// Records the item's state in `source` as last seen.
+    #[instrument(skip(self), fields(db.operation = "UPDATE_EXTERNAL_LINK"))]
+    pub async fn set_external_state(
+        &self,
+        source: &str,
+        external_id: &str,
+        remote_state: &str,
+    ) -> Result<(), RepositoryError> {
+        self.capture("set_external_state", async {
+            sqlx::query("UPDATE todo_external_links SET remote_state = ?3 WHERE source = ?1 AND external_id = ?2")
+                .bind(source)
+                .bind(external_id)
+                .bind(remote_state)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Links `external_id` in `source` to the todo, replacing any earlier link and the state
+    /// recorded with it.
+    #[instrument(skip(self), fields(db.operation = "UPSERT_EXTERNAL_LINK", todo.id = %todo_id))]
+    pub async fn link_external(
+        &self,
+        source: &str,
+        external_id: &str,
+        todo_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        self.capture("link_external", async {
+            sqlx::query(
+                r#"
+                INSERT INTO todo_external_links (source, external_id, todo_id, created_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (source, external_id) DO UPDATE SET
+                    todo_id = excluded.todo_id,
+                    created_at = excluded.created_at,
+                    remote_state = NULL
+                "#
+            )
+            .bind(source)
+            .bind(external_id)
+            .bind(todo_id.to_string())
+            .bind(now.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self, webhook), fields(db.operation = "INSERT_WEBHOOK", webhook.id = %webhook.id))]
+    pub async fn create_webhook(&self, webhook: &WebhookSubscription) -> Result<(), RepositoryError> {
+        self.capture("create_webhook", async {
+            let events = serde_json::to_string(&webhook.events)
+                .map_err(|e| RepositoryError::InvalidData(format!("webhook events: {e}")))?;
+            sqlx::query(
+                r#"
+                INSERT INTO webhook_subscriptions (id, url, events, format, api_version, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                "#
+            )
+            .bind(webhook.id.to_string())
+            .bind(&webhook.url)
+            .bind(events)
+            .bind(webhook.format.as_str())
+            .bind(webhook.api_version)
+            .bind(webhook.created_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Every webhook subscription, oldest first.
+    #[instrument(skip(self), fields(db.operation = "SELECT_WEBHOOKS", count))]
+    pub async fn list_webhooks(&self) -> Result<Vec<WebhookSubscription>, RepositoryError> {
+        self.capture("list_webhooks", async {
+            let rows = sqlx::query_as::<_, WebhookRow>(
+                r#"
+                SELECT id, url, events, format, api_version, created_at
+                FROM webhook_subscriptions
+                ORDER BY created_at, id
+                "#
+            )
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(webhook_from_row).collect()
+        })
+        .await
+    }
+
+    /// Returns false if there was no such subscription.
+    #[instrument(skip(self), fields(db.operation = "DELETE_WEBHOOK", webhook.id = %id))]
+    pub async fn delete_webhook(&self, id: Uuid) -> Result<bool, RepositoryError> {
+        self.capture("delete_webhook", async {
+            let result = sqlx::query("DELETE FROM webhook_subscriptions WHERE id = ?1")
+                .bind(id.to_string())
+                .execute(&self.pool)
+                .await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Fails with `AlreadyExists` if a field with that name is already defined.
+    #[instrument(skip(self, field), fields(db.operation = "INSERT_CUSTOM_FIELD", field.name = %field.name))]
+    pub async fn create_custom_field(&self, field: &CustomFieldDef) -> Result<(), RepositoryError> {
+        self.capture("create_custom_field", async {
+            let result = sqlx::query(
+                r#"
+                INSERT INTO custom_field_defs (name, type, required, created_at)
+                VALUES (?1, ?2, ?3, ?4)
+                "#
+            )
+            .bind(&field.name)
+            .bind(field.field_type.as_str())
+            .bind(field.required)
+            .bind(field.created_at.to_rfc3339())
+            .execute(&self.pool)
+            .await;
+
+            match result {
+                Ok(_) => Ok(()),
+                Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                    Err(RepositoryError::AlreadyExists(field.name.clone()))
+                }
+                Err(e) => Err(RepositoryError::Database(e)),
+            }
+        })
+        .await
+    }
+
+    /// Every custom field definition, by name.
+    #[instrument(skip(self), fields(db.operation = "SELECT_CUSTOM_FIELDS", count))]
+    pub async fn list_custom_fields(&self) -> Result<Vec<CustomFieldDef>, RepositoryError> {
+        self.capture("list_custom_fields", async {
+            let rows = sqlx::query_as::<_, CustomFieldRow>(
+                r#"
+                SELECT name, type, required, created_at
+                FROM custom_field_defs
+                ORDER BY name
+                "#
+            )
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(custom_field_from_row).collect()
+        })
+        .await
+    }
+
+    /// Removes the definition and every todo's value for it. Returns false if there was no
+    /// such field.
+    #[instrument(skip(self), fields(db.operation = "DELETE_CUSTOM_FIELD", field.name = %name))]
+    pub async fn delete_custom_field(&self, name: &str) -> Result<bool, RepositoryError> {
+        self.capture("delete_custom_field", async {
+            let mut tx = self.pool.begin().await?;
+            let result = sqlx::query("DELETE FROM custom_field_defs WHERE name = ?1")
+                .bind(name)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM custom_field_values WHERE name = ?1")
+                .bind(name)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Every tag a todo carries or that has a color or description, by name. Expired todos
+    /// aren't counted. With `projects`, todos of other projects aren't counted either, and a
+    /// tag only they carry is left out even if it has a color or description.
+    #[instrument(skip(self, projects), fields(db.operation = "SELECT_TAGS", count))]
+    pub async fn list_tags(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<Tag>, RepositoryError> {
+        self.capture("list_tags", async {
+            let rows = sqlx::query_as::<_, TagRow>(&format!(
+                r#"
+                SELECT name, MAX(color) AS color, MAX(description) AS description,
+                    SUM(usage_count) AS usage_count, SUM(open_count) AS open_count
+                FROM (
+                    SELECT tag.value AS name, NULL AS color, NULL AS description,
+                        COUNT(*) AS usage_count, SUM(todos.completed = false) AS open_count
+                    FROM todos, json_each(todos.tags) AS tag
+                    WHERE {NOT_EXPIRED}
+                      AND (?2 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?2)))
+                    GROUP BY tag.value
+                    UNION ALL
+                    SELECT name, color, description, 0, 0 FROM tags
+                    WHERE ?2 IS NULL OR NOT EXISTS (
+                        SELECT 1 FROM todos, json_each(todos.tags) AS tag
+                        WHERE tag.value = tags.name
+                          AND project_id IS NOT NULL
+                          AND project_id NOT IN (SELECT value FROM json_each(?2))
+                    )
+                )
+                GROUP BY name
+                ORDER BY name
+                "#
+            ))
+            .bind(self.clock.now().to_rfc3339())
+            .bind(projects_json(projects))
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            Ok(rows
+                .into_iter()
+                .map(|row| Tag {
+                    name: row.name,
+                    color: row.color,
+                    description: row.description,
+                    usage_count: row.usage_count as u64,
+                    open_count: row.open_count as u64,
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Sets the tag's color and description, replacing any it had.
+    #[instrument(skip(self, color, description), fields(db.operation = "UPSERT_TAG", tag = %name))]
+    pub async fn set_tag(
+        &self,
+        name: &str,
+        color: Option<&str>,
+        description: Option<&str>,
+    ) -> Result<(), RepositoryError> {
+        self.capture("set_tag", async {
+            sqlx::query(
+                r#"
+                INSERT INTO tags (name, color, description, created_at, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?4)
+                ON CONFLICT (name) DO UPDATE
+                SET color = excluded.color, description = excluded.description, updated_at = excluded.updated_at
+                "#
+            )
+            .bind(name)
+            .bind(color)
+            .bind(description)
+            .bind(self.clock.now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replaces each of `sources` with `into` on every todo carrying one, keeping each todo's
+    /// tags in order and without duplicates, and returns how many todos changed. `into` keeps
+    /// its color and description, or takes a source's if it had none; the sources' are dropped.
+    /// Renaming a tag is merging it into a new one. With `projects`, todos of other projects
+    /// keep the sources, and so do the sources' colors and descriptions while any todo does.
+    #[instrument(skip(self, sources, projects), fields(db.operation = "MERGE_TAGS", tag = %into, todos_updated))]
+    pub async fn merge_tags(
+        &self,
+        sources: &[String],
+        into: &str,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<u64, RepositoryError> {
+        self.capture("merge_tags", async {
+            let sources = tags_json(sources);
+            let now = self.clock.now().to_rfc3339();
+            let mut tx = self.pool.begin().await?;
+            // `updated_at` and `version` move as for any other edit; the sync trigger moves `change_seq`
+            let updated = sqlx::query(
+                r#"
+                UPDATE todos
+                SET tags = (
+                        SELECT json_group_array(name) FROM (
+                            SELECT CASE WHEN tag.value IN (SELECT value FROM json_each(?1)) THEN ?2
+                                ELSE tag.value END AS name, MIN(tag.key) AS position
+                            FROM json_each(todos.tags) AS tag
+                            GROUP BY 1
+                            ORDER BY position
+                        )
+                    ),
+                    updated_at = ?3,
+                    version = version + 1
+                WHERE EXISTS (
+                    SELECT 1 FROM json_each(todos.tags) AS tag WHERE tag.value IN (SELECT value FROM json_each(?1))
+                )
+                  AND (?4 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?4)))
+                "#
+            )
+            .bind(&sources)
+            .bind(into)
+            .bind(&now)
+            .bind(projects_json(projects))
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            sqlx::query(
+                r#"
+                INSERT INTO tags (name, color, description, created_at, updated_at)
+                SELECT ?2, color, description, ?3, ?3
+                FROM tags
+                WHERE name IN (SELECT value FROM json_each(?1))
+                ORDER BY updated_at DESC
+                LIMIT 1
+                ON CONFLICT (name) DO NOTHING
+                "#
+            )
+            .bind(&sources)
+            .bind(into)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                r#"
+                DELETE FROM tags
+                WHERE name IN (SELECT value FROM json_each(?1)) AND name <> ?2
+                  AND NOT EXISTS (SELECT 1 FROM todos, json_each(todos.tags) AS tag WHERE tag.value = tags.name)
+                "#
+            )
+            .bind(&sources)
+            .bind(into)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            Span::current().record("todos_updated", updated);
+            Ok(updated)
+        })
+        .await
+    }
+
+    /// Adds `add` to and removes `remove` from the tags of every todo among `ids` (or every
+    /// todo, without them) that matches `filter`, in one statement, and returns the ids of
+    /// those that changed. Added tags go after a todo's own, in the order given. With
+    /// `projects`, todos of other projects are left alone. Expired todos are skipped.
+    #[instrument(
+        skip(self, ids, filter, add, remove, projects),
+        fields(db.operation = "UPDATE_TAGS", count)
+    )]
+    pub async fn tag_todos(
+        &self,
+        ids: Option<&[Uuid]>,
+        filter: &BulkTagFilter,
+        add: &[String],
+        remove: &[String],
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<Vec<Uuid>, RepositoryError> {
+        self.capture("tag_todos", async {
+            let ids = ids.map(|ids| {
+                serde_json::Value::from(ids.iter().map(Uuid::to_string).collect::<Vec<_>>()).to_string()
+            });
+            let now = self.clock.now().to_rfc3339();
+            // Only todos the change actually alters are written, so the rest keep their `version`
+            let rows: Vec<String> = sqlx::query_scalar(&format!(
+                r#"
+                UPDATE todos
+                SET tags = (
+                        SELECT json_group_array(name) FROM (
+                            SELECT tag.value AS name, 0 AS part, tag.key AS position
+                            FROM json_each(todos.tags) AS tag
+                            WHERE tag.value NOT IN (SELECT value FROM json_each(?2))
+                            UNION ALL
+                            SELECT added.value, 1, added.key
+                            FROM json_each(?3) AS added
+                            WHERE added.value NOT IN (SELECT value FROM json_each(todos.tags))
+                            ORDER BY part, position
+                        )
+                    ),
+                    updated_at = ?1,
+                    version = version + 1
+                WHERE {NOT_EXPIRED}
+                  AND (?4 IS NULL OR id IN (SELECT value FROM json_each(?4)))
+                  AND (?5 IS NULL OR completed = ?5)
+                  AND (?6 IS NULL OR EXISTS (SELECT 1 FROM json_each(todos.tags) WHERE value = ?6))
+                  AND (?7 IS NULL OR project_id = ?7)
+                  AND (?8 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?8)))
+                  AND (
+                      EXISTS (SELECT 1 FROM json_each(todos.tags) WHERE value IN (SELECT value FROM json_each(?2)))
+                      OR EXISTS (
+                          SELECT 1 FROM json_each(?3) AS added
+                          WHERE added.value NOT IN (SELECT value FROM json_each(todos.tags))
+                      )
+                  )
+                RETURNING id
+                "#
+            ))
+            .bind(&now)
+            .bind(tags_json(remove))
+            .bind(tags_json(add))
+            .bind(ids)
+            .bind(filter.completed)
+            .bind(filter.tag.as_deref())
+            .bind(filter.project_id.map(|id| id.to_string()))
+            .bind(projects_json(projects))
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.iter().map(|id| parse_uuid("todo", id)).collect()
+        })
+        .await
+    }
+
+    /// Stores a new project with `owner` as its only member.
+    #[instrument(skip(self, project), fields(db.operation = "INSERT_PROJECT", project.id = %project.id))]
+    pub async fn create_project(&self, project: &Project, owner: Uuid) -> Result<(), RepositoryError> {
+        self.capture("create_project", async {
+            let id = project.id.to_string();
+            let created_at = project.created_at.to_rfc3339();
+            let mut tx = self.pool.begin().await?;
+            sqlx::query("INSERT INTO projects (id, name, created_at) VALUES (?1, ?2, ?3)")
+                .bind(&id)
+                .bind(&project.name)
+                .bind(&created_at)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(
+                r#"
+                INSERT INTO project_members (project_id, user_id, role, added_at)
+                VALUES (?1, ?2, 'owner', ?3)
+                "#
+            )
+            .bind(&id)
+            .bind(owner.to_string())
+            .bind(&created_at)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The projects `user_id` is a member of, by name, with their role in each.
+    #[instrument(skip(self), fields(db.operation = "SELECT_PROJECTS", user.id = %user_id))]
+    pub async fn list_projects(&self, user_id: Uuid) -> Result<Vec<Project>, RepositoryError> {
+        self.capture("list_projects", async {
+            let rows = sqlx::query_as::<_, ProjectRow>(
+                r#"
+                SELECT p.id, p.name, m.role, p.created_at
+                FROM projects p JOIN project_members m ON m.project_id = p.id
+                WHERE m.user_id = ?1
+                ORDER BY p.name, p.id
+                "#
+            )
+            .bind(user_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+            rows.into_iter().map(project_from_row).collect()
+        })
+        .await
+    }
+
+    /// The project with `user_id`'s role in it, or `None` if they aren't a member of it, or
+    /// there is no such project.
+    #[instrument(skip(self), fields(db.operation = "SELECT_PROJECT", project.id = %id, user.id = %user_id))]
+    pub async fn get_project(&self, id: Uuid, user_id: Uuid) -> Result<Option<Project>, RepositoryError> {
+        self.capture("get_project", async {
+            let row = sqlx::query_as::<_, ProjectRow>(
+                r#"
+                SELECT p.id, p.name, m.role, p.created_at
+                FROM projects p JOIN project_members m ON m.project_id = p.id
+                WHERE p.id = ?1 AND m.user_id = ?2
+                "#
+            )
+            .bind(id.to_string())
+            .bind(user_id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(project_from_row).transpose()
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(db.operation = "SELECT_PROJECT", project.id = %id))]
+    pub async fn project_name(&self, id: Uuid) -> Result<Option<String>, RepositoryError> {
+        self.capture("project_name", async {
+            let name = sqlx::query_scalar("SELECT name FROM projects WHERE id = ?1")
+                .bind(id.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+            Ok(name)
+        })
+        .await
+    }
+
+    /// The names of the given projects, in one query; those that don't exist are left out.
+    #[instrument(skip(self, ids), fields(db.operation = "SELECT_PROJECT_NAMES", project.count = ids.len()))]
+    pub async fn project_names(&self, ids: &HashSet<Uuid>) -> Result<HashMap<Uuid, String>, RepositoryError> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        self.capture("project_names", async {
+            let rows: Vec<(String, String)> =
+                sqlx::query_as("SELECT id, name FROM projects WHERE id IN (SELECT value FROM json_each(?1))")
+                    .bind(ids_json(ids))
+                    .fetch_all(&self.pool)
+                    .await?;
+            rows.into_iter()
+                .map(|(id, name)| Ok((parse_uuid("project", &id)?, name)))
+                .collect()
+        })
+        .await
+    }
+
+    /// Owners first, then editors and viewers, each by email.
+    #[instrument(skip(self), fields(db.operation = "SELECT_PROJECT_MEMBERS", project.id = %id))]
+    pub async fn project_members(&self, id: Uuid) -> Result<Vec<ProjectMember>, RepositoryError> {
+        self.capture("project_members", async {
+            let rows = sqlx::query_as::<_, ProjectMemberRow>(
+                r#"
+                SELECT m.user_id, u.email, m.role, m.added_at
+                FROM project_members m JOIN users u ON u.id = m.user_id
+                WHERE m.project_id = ?1
+                ORDER BY CASE m.role WHEN 'owner' THEN 0 WHEN 'editor' THEN 1 ELSE 2 END, u.email
+                "#
+            )
+            .bind(id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+            rows.into_iter()
+                .map(|row| {
+                    Ok(ProjectMember {
+                        user_id: Uuid::parse_str(&row.user_id).map_err(|e| {
+                            RepositoryError::InvalidData(format!("bad member id {}: {e}", row.user_id))
+                        })?,
+                        email: row.email,
+                        role: row.role.parse().map_err(RepositoryError::InvalidData)?,
+                        added_at: parse_timestamp(&row.added_at)?,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Adds the user to the project, or changes their role if they are already a member.
+    /// Demoting the last owner is refused.
+    #[instrument(skip(self), fields(db.operation = "UPSERT_PROJECT_MEMBER", project.id = %id, user.id = %user_id))]
+    pub async fn set_project_member(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        role: ProjectRole,
+    ) -> Result<MembershipChange, RepositoryError> {
+        self.capture("set_project_member", async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(
+                r#"
+                INSERT INTO project_members (project_id, user_id, role, added_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (project_id, user_id) DO UPDATE SET role = excluded.role
+                "#
+            )
+            .bind(id.to_string())
+            .bind(user_id.to_string())
+            .bind(role.as_str())
+            .bind(self.clock.now().to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+            if !has_owner(&mut tx, id).await? {
+                return Ok(MembershipChange::LastOwner);
+            }
+            tx.commit().await?;
+            Ok(MembershipChange::Applied)
+        })
+        .await
+    }
+
+    /// Removing the last owner is refused.
+    #[instrument(skip(self), fields(db.operation = "DELETE_PROJECT_MEMBER", project.id = %id, user.id = %user_id))]
+    pub async fn remove_project_member(&self, id: Uuid, user_id: Uuid) -> Result<MembershipChange, RepositoryError> {
+        self.capture("remove_project_member", async {
+            let mut tx = self.pool.begin().await?;
+            let result = sqlx::query("DELETE FROM project_members WHERE project_id = ?1 AND user_id = ?2")
+                .bind(id.to_string())
+                .bind(user_id.to_string())
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                return Ok(MembershipChange::NotMember);
+            }
+            if !has_owner(&mut tx, id).await? {
+                return Ok(MembershipChange::LastOwner);
+            }
+            tx.commit().await?;
+            Ok(MembershipChange::Applied)
+        })
+        .await
+    }
+
+    /// Saves the user's username and mention channel. Fails with `AlreadyExists` if another
+    /// account has the username, ignoring case.
+    #[instrument(skip(self, user), fields(db.operation = "UPDATE_USER_PROFILE", user.id = %user.id))]
+    pub async fn update_profile(&self, user: &User) -> Result<(), RepositoryError> {
+        self.capture("update_profile", async {
+            let result = sqlx::query("UPDATE users SET username = ?2, mention_channel = ?3 WHERE id = ?1")
+                .bind(user.id.to_string())
+                .bind(&user.username)
+                .bind(user.mention_channel.as_str())
+                .execute(&self.pool)
+                .await;
+
+            match result {
+                Ok(r) if r.rows_affected() == 0 => Err(RepositoryError::NotFound(user.id)),
+                Ok(_) => Ok(()),
+                Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                    Err(RepositoryError::AlreadyExists("username".to_string()))
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+        .await
+    }
+
+    /// The users with any of these usernames, ignoring case. Unknown names are skipped.
+    #[instrument(skip(self, usernames), fields(db.operation = "SELECT_USERS_BY_USERNAME", count))]
+    pub async fn users_by_username(&self, usernames: &[String]) -> Result<Vec<User>, RepositoryError> {
+        self.capture("users_by_username", async {
+            let names = serde_json::to_string(usernames)
+                .map_err(|e| RepositoryError::InvalidData(format!("usernames: {e}")))?;
+            let rows = sqlx::query_as::<_, UserRow>(&format!(
+                "SELECT {USER_COLUMNS} FROM users WHERE username IN (SELECT value FROM json_each(?1))"
+            ))
+            .bind(names)
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(|row| user_from_row(row).map(|(user, _)| user)).collect()
+        })
+        .await
+    }
+
+    #[instrument(skip(self, comment), fields(db.operation = "INSERT_COMMENT", todo.id = %comment.todo_id))]
+    pub async fn add_comment(&self, comment: &Comment) -> Result<(), RepositoryError> {
+        self.capture("add_comment", async {
+            sqlx::query(
+                r#"
+                INSERT INTO todo_comments (id, todo_id, author_id, body, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                "#
+            )
+            .bind(comment.id.to_string())
+            .bind(comment.todo_id.to_string())
+            .bind(comment.author_id.map(|id| id.to_string()))
+            .bind(&comment.body)
+            .bind(comment.created_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self, attachment), fields(db.operation = "INSERT_ATTACHMENT", todo.id = %attachment.todo_id))]
+    pub async fn add_attachment(&self, attachment: &Attachment) -> Result<(), RepositoryError> {
+        self.capture("add_attachment", async {
+            sqlx::query(
+                r#"
+                INSERT INTO attachments (id, todo_id, filename, content_type, size_bytes, blob_key, created_at, uploaded_by)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                "#
+            )
+            .bind(attachment.id.to_string())
+            .bind(attachment.todo_id.to_string())
+            .bind(&attachment.filename)
+            .bind(&attachment.content_type)
+            .bind(attachment.size_bytes as i64)
+            .bind(&attachment.blob_key)
+            .bind(attachment.created_at.to_rfc3339())
+            .bind(attachment.uploaded_by.map(|id| id.to_string()))
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The todo's attachments, oldest first.
+    #[instrument(skip(self), fields(db.operation = "SELECT_ATTACHMENTS", todo.id = %todo_id, count))]
+    pub async fn attachments(&self, todo_id: Uuid) -> Result<Vec<Attachment>, RepositoryError> {
+        self.capture("attachments", async {
+            let rows = sqlx::query_as::<_, AttachmentRow>(
+                r#"
+                SELECT id, todo_id, filename, content_type, size_bytes, blob_key, created_at, uploaded_by
+                FROM attachments
+                WHERE todo_id = ?1
+                ORDER BY created_at, rowid
+                "#
+            )
+            .bind(todo_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(attachment_from_row).collect()
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(db.operation = "SELECT_ATTACHMENT", todo.id = %todo_id, attachment.id = %id))]
+    pub async fn get_attachment(&self, todo_id: Uuid, id: Uuid) -> Result<Option<Attachment>, RepositoryError> {
+        self.capture("get_attachment", async {
+            let row = sqlx::query_as::<_, AttachmentRow>(
+                r#"
+                SELECT id, todo_id, filename, content_type, size_bytes, blob_key, created_at, uploaded_by
+                FROM attachments
+                WHERE todo_id = ?1 AND id = ?2
+                "#
+            )
+            .bind(todo_id.to_string())
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(attachment_from_row).transpose()
+        })
+        .await
+    }
+
+    /// Returns false if there was no such attachment.
+    #[instrument(skip(self), fields(db.operation = "DELETE_ATTACHMENT", attachment.id = %id))]
+    pub async fn delete_attachment(&self, id: Uuid) -> Result<bool, RepositoryError> {
+        self.capture("delete_attachment", async {
+            let result = sqlx::query("DELETE FROM attachments WHERE id = ?1")
+                .bind(id.to_string())
+                .execute(&self.pool)
+                .await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Attachments whose todo has been deleted, at most `limit` of them. With `deleted_before`,
+    /// only those whose todo was deleted by then; a todo whose tombstone has been pruned was
+    /// deleted long enough ago to count.
+    #[instrument(skip(self), fields(db.operation = "SELECT_ORPHANED_ATTACHMENTS", count))]
+    pub async fn orphaned_attachments(
+        &self,
+        deleted_before: Option<DateTime<Utc>>,
+        limit: u32,
+    ) -> Result<Vec<Attachment>, RepositoryError> {
+        self.capture("orphaned_attachments", async {
+            let rows = sqlx::query_as::<_, AttachmentRow>(
+                r#"
+                SELECT id, todo_id, filename, content_type, size_bytes, blob_key, created_at, uploaded_by
+                FROM attachments
+                WHERE todo_id NOT IN (SELECT id FROM todos)
+                  AND (?1 IS NULL
+                       OR COALESCE((SELECT deleted_at FROM todo_tombstones WHERE id = todo_id), '') <= ?1)
+                LIMIT ?2
+                "#
+            )
+            .bind(deleted_before.map(tombstone_time))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(attachment_from_row).collect()
+        })
+        .await
+    }
+
+    /// How many attachments `orphaned_attachments` would find without a limit, and their total
+    /// size in bytes.
+    #[instrument(skip(self), fields(db.operation = "COUNT_ORPHANED_ATTACHMENTS"))]
+    pub async fn orphaned_attachment_usage(
+        &self,
+        deleted_before: Option<DateTime<Utc>>,
+    ) -> Result<(u64, u64), RepositoryError> {
+        self.capture("orphaned_attachment_usage", async {
+            let (count, bytes): (i64, i64) = sqlx::query_as(
+                r#"
+                SELECT COUNT(*), COALESCE(SUM(size_bytes), 0)
+                FROM attachments
+                WHERE todo_id NOT IN (SELECT id FROM todos)
+                  AND (?1 IS NULL
+                       OR COALESCE((SELECT deleted_at FROM todo_tombstones WHERE id = todo_id), '') <= ?1)
+                "#
+            )
+            .bind(deleted_before.map(tombstone_time))
+            .fetch_one(&self.pool)
+            .await?;
+            Ok((count as u64, bytes as u64))
+        })
+        .await
+    }
+
+    /// The todo's comments, oldest first.
+    #[instrument(skip(self), fields(db.operation = "SELECT_COMMENTS", todo.id = %todo_id, count))]
+    pub async fn comments(&self, todo_id: Uuid) -> Result<Vec<Comment>, RepositoryError> {
+        self.capture("comments", async {
+            let rows = sqlx::query_as::<_, CommentRow>(
+                r#"
+                SELECT id, todo_id, author_id, body, created_at
+                FROM todo_comments
+                WHERE todo_id = ?1
+                ORDER BY created_at, rowid
+                "#
+            )
+            .bind(todo_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(comment_from_row).collect()
+        })
+        .await
+    }
+
+    /// The comments on any of the given todos, oldest first, in one query.
+    #[instrument(skip(self, todo_ids), fields(db.operation = "SELECT_COMMENTS_FOR", todo.count = todo_ids.len(), count))]
+    pub async fn comments_for(&self, todo_ids: &HashSet<Uuid>) -> Result<Vec<Comment>, RepositoryError> {
+        if todo_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.capture("comments_for", async {
+            let rows = sqlx::query_as::<_, CommentRow>(
+                r#"
+                SELECT id, todo_id, author_id, body, created_at
+                FROM todo_comments
+                WHERE todo_id IN (SELECT value FROM json_each(?1))
+                ORDER BY created_at, rowid
+                "#
+            )
+            .bind(ids_json(todo_ids))
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(comment_from_row).collect()
+        })
+        .await
+    }
+
+    /// Every comment `user_id` wrote, oldest first.
+    #[instrument(skip(self), fields(db.operation = "SELECT_USER_COMMENTS", user.id = %user_id, count))]
+    pub async fn comments_by(&self, user_id: Uuid) -> Result<Vec<Comment>, RepositoryError> {
+        self.capture("comments_by", async {
+            let rows = sqlx::query_as::<_, CommentRow>(
+                r#"
+                SELECT id, todo_id, author_id, body, created_at
+                FROM todo_comments
+                WHERE author_id = ?1
+                ORDER BY created_at, rowid
+                "#
+            )
+            .bind(user_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(comment_from_row).collect()
+        })
+        .await
+    }
+
+    /// Every attachment `user_id` uploaded, oldest first, including those whose todo has
+    /// been deleted but not yet swept.
+    #[instrument(skip(self), fields(db.operation = "SELECT_USER_ATTACHMENTS", user.id = %user_id, count))]
+    pub async fn attachments_by(&self, user_id: Uuid) -> Result<Vec<Attachment>, RepositoryError> {
+        self.capture("attachments_by", async {
+            let rows = sqlx::query_as::<_, AttachmentRow>(
+                r#"
+                SELECT id, todo_id, filename, content_type, size_bytes, blob_key, created_at, uploaded_by
+                FROM attachments
+                WHERE uploaded_by = ?1
+                ORDER BY created_at, rowid
+                "#
+            )
+            .bind(user_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(attachment_from_row).collect()
+        })
+        .await
+    }
+
+    /// Adds an entry to each user's activity feed. The entry's `id` is assigned here.
+    #[instrument(skip(self, item, user_ids), fields(db.operation = "INSERT_ACTIVITY", todo.id = %item.todo_id))]
+    pub async fn record_activity(&self, user_ids: &[Uuid], item: &ActivityItem) -> Result<(), RepositoryError> {
+        self.capture("record_activity", async {
+            let mut tx = self.pool.begin().await?;
+            for user_id in user_ids {
+                sqlx::query(
+                    r#"
+                    INSERT INTO activity (user_id, kind, todo_id, comment_id, actor_id, excerpt, created_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                    "#
+                )
+                .bind(user_id.to_string())
+                .bind(item.kind.as_str())
+                .bind(item.todo_id.to_string())
+                .bind(item.comment_id.map(|id| id.to_string()))
+                .bind(item.actor_id.map(|id| id.to_string()))
+                .bind(&item.excerpt)
+                .bind(item.created_at.to_rfc3339())
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The user's most recent activity, newest first, before the entry with id `before` if
+    /// given.
+    #[instrument(skip(self), fields(db.operation = "SELECT_ACTIVITY", user.id = %user_id, count))]
+    pub async fn activity_feed(
+        &self,
+        user_id: Uuid,
+        before: Option<i64>,
+        limit: u32,
+    ) -> Result<Vec<ActivityItem>, RepositoryError> {
+        self.capture("activity_feed", async {
+            let rows = sqlx::query_as::<_, ActivityRow>(
+                r#"
+                SELECT id, kind, todo_id, comment_id, actor_id, excerpt, created_at
+                FROM activity
+                WHERE user_id = ?1 AND (?2 IS NULL OR id < ?2)
+                ORDER BY id DESC
+                LIMIT ?3
+                "#
+            )
+            .bind(user_id.to_string())
+            .bind(before)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+            Span::current().record("count", rows.len());
+            rows.into_iter().map(activity_from_row).collect()
+        })
+        .await
+    }
+
+    /// A trivial query, to check the database is answering.
+    pub async fn ping(&self) -> Result<(), RepositoryError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Every todo that isn't completed, earliest due date first and undated ones last.
+    #[instrument(skip(self), fields(db.operation = "SELECT_OPEN", count))]
+    pub async fn list_open(&self) -> Result<Vec<Todo>, RepositoryError> {
+        self.capture("list_open", async {
+            let rows = sqlx::query_as::<_, TodoRow>(&format!(
+                r#"
+                SELECT {TODO_COLUMNS}
+                FROM todos
+                WHERE completed = false AND {NOT_EXPIRED}
+                ORDER BY due_at IS NULL, due_at, created_at
+                "#
+            ))
+            .bind(self.clock.now().to_rfc3339())
+            .fetch_all(&self.pool)
+            .await?;
+
+            let todos = rows
+                .into_iter()
+                .map(|row| self.row_to_todo(row))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Span::current().record("count", todos.len());
+            Ok(todos)
+        })
+        .await
+    }
+
+    /// Completions in each of the last `periods` days or weeks, the current one included.
+    #[instrument(skip(self), fields(db.operation = "SELECT_VELOCITY"))]
+    pub async fn velocity(
+        &self,
+        period: VelocityPeriod,
+        periods: u32,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<Velocity, RepositoryError> {
+        self.capture("velocity", async {
+            let today = self.clock.now().date_naive();
+            // `weekday 0` moves forward to Sunday (or stays on it), so six days back is Monday
+            let (start, current, step) = match period {
+                VelocityPeriod::Day => ("date(completed_at)", today, 1),
+                VelocityPeriod::Week => (
+                    "date(completed_at, 'weekday 0', '-6 days')",
+                    today - Duration::days(i64::from(today.weekday().num_days_from_monday())),
+                    7,
+                ),
+            };
+            let first = current - Duration::days(step * (i64::from(periods) - 1));
+
+            let rows: Vec<(String, i64)> = sqlx::query_as(&format!(
+                r#"
+                SELECT {start} AS start, COUNT(*) AS completed
+                FROM todos
+                WHERE completed_at >= ?1
+                  AND (?2 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?2)))
+                GROUP BY start
+                "#
+            ))
+            .bind(first.and_time(NaiveTime::MIN).and_utc().to_rfc3339())
+            .bind(projects_json(projects))
+            .fetch_all(&self.pool)
+            .await?;
+
+            let mut counts = HashMap::new();
+            for (start, completed) in rows {
+                let start = NaiveDate::parse_from_str(&start, "%Y-%m-%d")
+                    .map_err(|e| RepositoryError::InvalidData(format!("bad date {start}: {e}")))?;
+                counts.insert(start, completed as u64);
+            }
+            let points = (0..i64::from(periods))
+                .map(|i| first + Duration::days(step * i))
+                .map(|start| VelocityPoint {
+                    start,
+                    completed: counts.get(&start).copied().unwrap_or(0),
+                })
+                .collect();
+            Ok(Velocity { period, points })
+        })
+        .await
+    }
+
+    /// Open todos bucketed by age: under a day, then from each of `AGING_BOUNDS_DAYS` up to the next.
+    #[instrument(skip(self), fields(db.operation = "SELECT_AGING"))]
+    pub async fn aging(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoAging, RepositoryError> {
+        self.capture("aging", async {
+            let bucket = AGING_BOUNDS_DAYS
+                .iter()
+                .enumerate()
+                .map(|(i, days)| format!("WHEN age < {days} THEN {i}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let rows: Vec<(i64, i64)> = sqlx::query_as(&format!(
+                r#"
+                SELECT CASE {bucket} ELSE {last} END AS bucket, COUNT(*) AS count
+                FROM (
+                    SELECT julianday(?1) - julianday(created_at) AS age
+                    FROM todos
+                    WHERE completed = false AND {NOT_EXPIRED}
+                      AND (?2 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?2)))
+                )
+                GROUP BY bucket
+                "#,
+                last = AGING_BOUNDS_DAYS.len(),
+            ))
+            .bind(self.clock.now().to_rfc3339())
+            .bind(projects_json(projects))
+            .fetch_all(&self.pool)
+            .await?;
+
+            let mut buckets: Vec<AgingBucket> = std::iter::once(0)
+                .chain(AGING_BOUNDS_DAYS)
+                .zip(AGING_BOUNDS_DAYS.map(Some).into_iter().chain([None]))
+                .map(|(min_days, max_days)| AgingBucket {
+                    min_days,
+                    max_days,
+                    count: 0,
+                })
+                .collect();
+            for (index, count) in rows {
+                if let Some(bucket) = buckets.get_mut(index as usize) {
+                    bucket.count = count as u64;
+                }
+            }
+            Ok(TodoAging {
+                open_count: buckets.iter().map(|bucket| bucket.count).sum(),
+                buckets,
+            })
+        })
+        .await
+    }
+
+    /// Creation-to-completion times for todos completed in the last `days` days.
+    #[instrument(skip(self), fields(db.operation = "SELECT_COMPLETION_TIME"))]
+    pub async fn completion_time(
+        &self,
+        days: u32,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<CompletionTime, RepositoryError> {
+        self.capture("completion_time", async {
+            // Nearest rank: the p-th percentile is the ceil(p * n / 100)-th smallest value
+            let row = sqlx::query_as::<_, CompletionTimeRow>(
+                r#"
+                WITH durations AS (
+                    SELECT (julianday(completed_at) - julianday(created_at)) * 86400.0 AS seconds
+                    FROM todos
+                    WHERE completed_at >= ?1
+                      AND (?2 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?2)))
+                ),
+                ranked AS (
+                    SELECT seconds, ROW_NUMBER() OVER (ORDER BY seconds) AS rank, COUNT(*) OVER () AS n
+                    FROM durations
+                )
+                SELECT
+                    COUNT(*) AS count,
+                    AVG(seconds) AS mean_seconds,
+                    MAX(CASE WHEN rank = (50 * n + 99) / 100 THEN seconds END) AS p50_seconds,
+                    MAX(CASE WHEN rank = (75 * n + 99) / 100 THEN seconds END) AS p75_seconds,
+                    MAX(CASE WHEN rank = (90 * n + 99) / 100 THEN seconds END) AS p90_seconds,
+                    MAX(CASE WHEN rank = (95 * n + 99) / 100 THEN seconds END) AS p95_seconds,
+                    MAX(seconds) AS max_seconds
+                FROM ranked
+                "#
+            )
+            .bind((self.clock.now() - Duration::days(i64::from(days))).to_rfc3339())
+            .bind(projects_json(projects))
+            .fetch_one(&self.pool)
+            .await?;
+
+            Ok(CompletionTime {
+                window_days: days,
+                count: row.count as u64,
+                mean_seconds: row.mean_seconds,
+                p50_seconds: row.p50_seconds,
+                p75_seconds: row.p75_seconds,
+                p90_seconds: row.p90_seconds,
+                p95_seconds: row.p95_seconds,
+                max_seconds: row.max_seconds,
+            })
+        })
+        .await
+    }
+
+    /// `span_errors::capture` that also times the call and reports it as `operation` to the
+    /// query observer, if there is one.
+    async fn capture<T>(
+        &self,
+        operation: &'static str,
+        fut: impl Future<Output = Result<T, RepositoryError>>,
+    ) -> Result<T, RepositoryError> {
+        let started = Instant::now();
+        let result = span_errors::capture(fut).await;
+        let elapsed = started.elapsed();
+
+        if let Some(observer) = &self.observer {
+            observer.observe(operation, elapsed);
+        }
+        result
+    }
+
+    /// Sleeps for the latency profile's query delay; with the profile off, not even a span
+    /// is recorded.
+    async fn simulate_db_latency(&self) {
+        let Some(delay) = self.latency.db_delay() else {
+            return;
+        };
+        let span = tracing::info_span!("simulate_db_latency", operation = "simulate_latency");
+        async {
+            tokio::time::sleep(delay).await;
+            info!(delay_ms = delay.as_millis(), "Simulated DB latency");
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+#[async_trait]
+impl TodoRepository for SqliteTodoRepository {
+    #[instrument(skip(self, todo), fields(todo.id = %todo.id, todo.title = %redact::redacted(&todo.title), db.operation = "INSERT"))]
+    async fn create(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        self.capture("create", async {
+            info!("Creating todo in database");
+            self.simulate_db_latency().await;
+
+            let (description, key_id) = self.seal_description(&todo)?;
+            let mut tx = self.pool.begin().await?;
+            let result = insert_query(&todo, description, key_id)
+                .execute(&mut *tx)
+                .await;
+
+            match result {
+                Ok(_) => {
+                    write_custom_fields(&mut tx, &todo).await?;
+                    let change_seq = read_change_seq(&mut tx, todo.id).await?;
+                    tx.commit().await?;
+                    info!("Todo created successfully in database");
+                    Ok(Todo { version: 1, change_seq, ..todo })
+                }
+                Err(e) => {
+                    error!(error = %e, "Failed to create todo in database");
+                    Err(RepositoryError::Database(e))
+                }
+            }
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(todo.id = %id, db.operation = "SELECT"))]
+    async fn get(&self, id: Uuid) -> Result<Todo, RepositoryError> {
+        self.capture("get", async {
+            info!("Fetching todo from database");
+            self.simulate_db_latency().await;
+
+            let id_str = id.to_string();
+            let row = sqlx::query_as::<_, TodoRow>(&format!(
+                r#"
+                SELECT {TODO_COLUMNS}
+                FROM todos
+                WHERE id = ?1
+                "#
+            ))
+            .bind(&id_str)
+            .fetch_optional(&self.pool)
+            .await?;
+
+            match row {
+                Some(row) => {
+                    info!("Todo found in database");
+                    self.row_to_todo(row)
+                }
+                None => {
+                    warn!("Todo not found in database");
+                    Err(RepositoryError::NotFound(id))
+                }
+            }
+        })
+        .await
+    }
+
+    #[instrument(skip(self, after, projects), fields(db.operation = "SELECT_PAGE", limit, count, has_more))]
+    async fn list_page(
+        &self,
+        after: Option<TodoCursor>,
+        limit: usize,
+        projects: Option<&HashSet<Uuid>>,
+    ) -> Result<TodoPage, RepositoryError> {
+        self.capture("list_page", async {
+            self.simulate_db_latency().await;
+            let projects = projects.map(|projects| {
+                serde_json::Value::from(projects.iter().map(Uuid::to_string).collect::<Vec<_>>()).to_string()
+            });
+            let mut rows = sqlx::query_as::<_, TodoRow>(&format!(
+                r#"
+                SELECT {TODO_COLUMNS}
+                FROM todos
+                WHERE {NOT_EXPIRED}
+                  AND (?2 IS NULL OR created_at < ?2 OR (created_at = ?2 AND id < ?3))
+                  AND (?4 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?4)))
+                ORDER BY created_at DESC, id DESC
+                LIMIT ?5
+                "#
+            ))
+            .bind(self.clock.now().to_rfc3339())
+            // Formatted as `insert_query` writes it, so it compares as the stored text does
+            .bind(after.map(|cursor| cursor.created_at.to_rfc3339()))
+            .bind(after.map(|cursor| cursor.id.to_string()))
+            .bind(projects)
+            // One row past the page says whether another follows, without counting them
+            .bind(i64::try_from(limit.saturating_add(1)).unwrap_or(i64::MAX))
+            .fetch_all(&self.pool)
+            .await?;
+
+            let has_more = rows.len() > limit;
+            rows.truncate(limit);
+            let todos = rows
+                .into_iter()
+                .map(|row| self.row_to_todo(row))
+                .collect::<Result<Vec<_>, _>>()?;
+            Span::current().record("limit", limit).record("count", todos.len()).record("has_more", has_more);
+            Ok(TodoPage { todos, has_more })
+        })
+        .await
+    }
+
+    #[instrument(
+        skip(self, filter),
+        fields(db.operation = if window.is_some() { "SELECT_WINDOW" } else { "SELECT_ALL" }, count, total)
+    )]
+    async fn list(&self, filter: &ListFilter<'_>, window: Option<ListWindow>) -> Result<TodoList, RepositoryError> {
+        self.capture("list", async {
+            info!("Listing todos from database");
+            self.simulate_db_latency().await;
+            let now = self.clock.now().to_rfc3339();
+            let projects = filter.projects.map(|projects| {
+                serde_json::Value::from(projects.iter().map(Uuid::to_string).collect::<Vec<_>>()).to_string()
+            });
+            let fields = (!filter.fields.is_empty())
+                .then(|| serde_json::Value::from(serde_json::Map::from_iter(filter.fields.clone())).to_string());
+            // Every wanted value is among the todo's; `json_extract` reads both as SQL values,
+            // so numbers compare as numbers
+            let selected = r#"
+                (?2 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?2)))
+                AND (?4 IS NULL OR project_id = ?4)
+                AND (?3 IS NULL OR NOT EXISTS (
+                    SELECT 1 FROM json_each(?3) AS wanted
+                    WHERE NOT EXISTS (
+                        SELECT 1 FROM custom_field_values AS field
+                        WHERE field.todo_id = todos.id
+                          AND field.name = wanted.key
+                          AND json_extract(field.value, '$') = wanted.value
+                    )
+                ))
+            "#;
+            let project = filter.project.map(|id| id.to_string());
+            // One read transaction, so the summary and the window see the same list
+            let mut tx = self.pool.begin().await?;
+            let (limit, offset) = match window {
+                Some(window) => (
+                    i64::try_from(window.limit).unwrap_or(i64::MAX),
+                    i64::try_from(window.offset).unwrap_or(i64::MAX),
+                ),
+                None => (-1, 0),
+            };
+            let rows = sqlx::query_as::<_, TodoRow>(&format!(
+                r#"
+                SELECT {TODO_COLUMNS}
+                FROM todos
+                WHERE {NOT_EXPIRED} AND {selected}
+                ORDER BY created_at DESC, id DESC
+                LIMIT ?5 OFFSET ?6
+                "#
+            ))
+            .bind(&now)
+            .bind(&projects)
+            .bind(&fields)
+            .bind(&project)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&mut *tx)
+            .await?;
+            let todos = rows
+                .into_iter()
+                .map(|row| self.row_to_todo(row))
+                .collect::<Result<Vec<_>, _>>()?;
+            let summary = match window {
+                // As in `summary`, MAX works on the RFC 3339 text
+                Some(_) => {
+                    let (count, last_modified, last_change_seq): (i64, Option<String>, Option<i64>) =
+                        sqlx::query_as(&format!(
+                            r#"
+                            SELECT COUNT(*), MAX(updated_at), MAX(change_seq)
+                            FROM todos
+                            WHERE {NOT_EXPIRED} AND {selected}
+                            "#
+                        ))
+                        .bind(&now)
+                        .bind(&projects)
+                        .bind(&fields)
+                        .bind(&project)
+                        .fetch_one(&mut *tx)
+                        .await?;
+                    TodoListSummary {
+                        count: count.max(0) as usize,
+                        last_modified: last_modified.as_deref().map(parse_timestamp).transpose()?,
+                        last_change_seq: last_change_seq.unwrap_or(0) as u64,
+                    }
+                }
+                None => TodoListSummary {
+                    count: todos.len(),
+                    last_modified: todos.iter().map(|t| t.updated_at).max(),
+                    last_change_seq: todos.iter().map(|t| t.change_seq).max().unwrap_or(0),
+                },
+            };
+            tx.commit().await?;
+
+            Span::current().record("count", todos.len()).record("total", summary.count);
+            info!(count = todos.len(), "Fetched todos from database");
+            Ok(TodoList { todos, summary })
+        })
+        .await
+    }
+
+    #[instrument(skip(self, projects), fields(db.operation = "SELECT_COMPACT"))]
+    async fn list_compact(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<CompactTodo>, RepositoryError> {
+        self.capture("list_compact", async {
+            info!("Listing compact todos from database");
+            self.simulate_db_latency().await;
+
+            // Skips descriptions entirely, so nothing needs decrypting either
+            let rows = sqlx::query_as::<_, CompactTodoRow>(&format!(
+                r#"
+                SELECT id, title, completed, due_at
+                FROM todos
+                WHERE {NOT_EXPIRED}
+                  AND (?2 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?2)))
+                ORDER BY created_at DESC
+                "#
+            ))
+            .bind(self.clock.now().to_rfc3339())
+            .bind(projects_json(projects))
+            .fetch_all(&self.pool)
+            .await?;
+
+            let todos = rows
+                .into_iter()
+                .map(|row| {
+                    Ok(CompactTodo {
+                        id: Uuid::parse_str(&row.id)
+                            .map_err(|e| RepositoryError::InvalidData(format!("bad todo id {}: {e}", row.id)))?,
+                        title: row.title,
+                        completed: row.completed,
+                        due_at: row.due_at.as_deref().map(parse_timestamp).transpose()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, RepositoryError>>()?;
+
+            info!(count = todos.len(), "Fetched compact todos from database");
+            Ok(todos)
+        })
+        .await
+    }
+
+    #[instrument(skip(self, projects), fields(db.operation = "SELECT_SUMMARY"))]
+    async fn summary(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoListSummary, RepositoryError> {
+        self.capture("summary", async {
+            // RFC 3339 timestamps in UTC sort lexicographically, so MAX works on the text
+            let (count, last_modified, last_change_seq): (i64, Option<String>, Option<i64>) = sqlx::query_as(&format!(
+                r#"
+                SELECT COUNT(*), MAX(updated_at), MAX(change_seq)
+                FROM todos
+                WHERE {NOT_EXPIRED}
+                  AND (?2 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?2)))
+                "#
+            ))
+            .bind(self.clock.now().to_rfc3339())
+            .bind(projects_json(projects))
+            .fetch_one(&self.pool)
+            .await?;
+
+            Ok(TodoListSummary {
+                count: count as usize,
+                last_modified: last_modified.as_deref().map(parse_timestamp).transpose()?,
+                last_change_seq: last_change_seq.unwrap_or(0) as u64,
+            })
+        })
+        .await
+    }
+
+    #[instrument(skip(self, projects), fields(db.operation = "SELECT_STATS"))]
+    async fn stats(&self, projects: Option<&HashSet<Uuid>>) -> Result<TodoStats, RepositoryError> {
+        self.capture("stats", async {
+            let now = self.clock.now().to_rfc3339();
+            let projects = projects_json(projects);
+            let totals = sqlx::query_as::<_, StatsRow>(&format!(
+                r#"
+                SELECT
+                    COALESCE(SUM(completed = false), 0) AS open_count,
+                    COALESCE(SUM(completed = true), 0) AS completed_count,
+                    COALESCE(SUM(CASE WHEN completed = false THEN estimate_minutes END), 0) AS open_estimate_minutes,
+                    COALESCE(SUM(completed = false AND estimate_minutes IS NULL), 0) AS unestimated_open_count
+                FROM todos
+                WHERE {NOT_EXPIRED}
+                  AND (?2 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?2)))
+                "#
+            ))
+            .bind(&now)
+            .bind(&projects)
+            .fetch_one(&self.pool)
+            .await?;
+
+            let by_tag = sqlx::query_as::<_, TagRollupRow>(&format!(
+                r#"
+                SELECT tag.value AS tag, COUNT(*) AS open_count,
+                    COALESCE(SUM(todos.estimate_minutes), 0) AS open_estimate_minutes
+                FROM todos, json_each(todos.tags) AS tag
+                WHERE todos.completed = false AND {NOT_EXPIRED}
+                  AND (?2 IS NULL OR todos.project_id IS NULL OR todos.project_id IN (SELECT value FROM json_each(?2)))
+                GROUP BY tag.value
+                ORDER BY open_estimate_minutes DESC, tag.value
+                "#
+            ))
+            .bind(&now)
+            .bind(&projects)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(TodoStats {
+                open_count: totals.open_count as u64,
+                completed_count: totals.completed_count as u64,
+                open_estimate_minutes: totals.open_estimate_minutes as u64,
+                unestimated_open_count: totals.unestimated_open_count as u64,
+                by_tag: by_tag
+                    .into_iter()
+                    .map(|row| TagRollup {
+                        tag: row.tag,
+                        open_count: row.open_count as u64,
+                        open_estimate_minutes: row.open_estimate_minutes as u64,
+                    })
+                    .collect(),
+            })
+        })
+        .await
+    }
+
+    #[instrument(skip(self, todo), fields(todo.id = %todo.id, db.operation = "UPDATE"))]
+    async fn update(&self, todo: Todo) -> Result<Todo, RepositoryError> {
+        self.capture("update", async {
+            info!("Updating todo in database");
+            self.simulate_db_latency().await;
+
+            let (description, key_id) = self.seal_description(&todo)?;
+            let mut tx = self.pool.begin().await?;
+            let version = update_query(&todo, description, key_id, None)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            match version {
+                Some(version) => {
+                    write_custom_fields(&mut tx, &todo).await?;
+                    let change_seq = read_change_seq(&mut tx, todo.id).await?;
+                    tx.commit().await?;
+                    info!("Todo updated successfully");
+                    Ok(Todo { version: version as u64, change_seq, ..todo })
+                }
+                None => {
+                    warn!("Todo not found for update");
+                    Err(RepositoryError::NotFound(todo.id))
+                }
+            }
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(todo.id = %id, db.operation = "DELETE"))]
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError> {
+        self.capture("delete", async {
+            info!("Deleting todo from database");
+            self.simulate_db_latency().await;
+
+            // One transaction, so a failure part way never leaves the todo's rows half deleted
+            let id_str = id.to_string();
+            let mut tx = self.pool.begin().await?;
+            let existed = delete_todo_rows(&mut tx, &id_str, self.clock.now()).await?;
+            tx.commit().await?;
+
+            if !existed {
+                warn!("Todo not found for deletion");
+                Err(RepositoryError::NotFound(id))
+            } else {
+                info!("Todo deleted successfully");
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    #[instrument(skip(self, todos), fields(batch_size = todos.len(), db.operation = "BATCH_INSERT"))]
+    async fn create_batch(&self, todos: Vec<Todo>) -> Result<Vec<Todo>, RepositoryError> {
+        self.capture("create_batch", async {
+            info!(count = todos.len(), "Creating batch of todos");
+
+            let current_span = Span::current();
+            let mut created_todos = Vec::with_capacity(todos.len());
+            let mut tx = self.pool.begin().await?;
+
+            for (index, todo) in todos.into_iter().enumerate() {
+                let span = tracing::info_span!(
+                    parent: &current_span,
+                    "batch_item",
+                    item_index = index,
+                    todo.id = %todo.id
+                );
+
+                let created = async {
+                    info!("Processing batch item");
+                    self.simulate_db_latency().await;
+                    let (description, key_id) = self.seal_description(&todo)?;
+                    insert_query(&todo, description, key_id).execute(&mut *tx).await?;
+                    write_custom_fields(&mut tx, &todo).await?;
+                    let change_seq = read_change_seq(&mut tx, todo.id).await?;
+                    Ok::<_, RepositoryError>(Todo { version: 1, change_seq, ..todo })
+                }
+                .instrument(span)
+                .await?;
+                created_todos.push(created);
+            }
+            tx.commit().await?;
+
+            info!(created_count = created_todos.len(), "Batch creation completed");
+            Ok(created_todos)
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(todo.id = %id, db.operation = "SNOOZE"))]
+    async fn snooze(&self, id: Uuid, until: DateTime<Utc>) -> Result<Todo, RepositoryError> {
+        self.capture("snooze", async {
+            info!(snoozed_until = %until, "Snoozing todo");
+            self.simulate_db_latency().await;
+
+            let id_str = id.to_string();
+            let now = self.clock.now().to_rfc3339();
+            let mut tx = self.pool.begin().await?;
+
+            let previous: Option<Option<String>> = sqlx::query_scalar("SELECT due_at FROM todos WHERE id = ?1")
+                .bind(&id_str)
+                .fetch_optional(&mut *tx)
+                .await?;
+            let Some(previous) = previous else {
+                warn!("Todo not found for snooze");
+                return Err(RepositoryError::NotFound(id));
+            };
+
+            sqlx::query("UPDATE todos SET due_at = ?2, updated_at = ?3 WHERE id = ?1")
+                .bind(&id_str)
+                .bind(until.to_rfc3339())
+                .bind(&now)
+                .execute(&mut *tx)
+                .await?;
+
+            let previous = previous.as_deref().map(parse_timestamp).transpose()?;
+            let detail = serde_json::json!({ "from": previous, "until": until });
+            sqlx::query(
+                r#"
+                INSERT INTO todo_history (todo_id, event, detail, created_at)
+                VALUES (?1, 'snoozed', ?2, ?3)
+                "#
+            )
+            .bind(&id_str)
+            .bind(detail.to_string())
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+
+            let row = sqlx::query_as::<_, TodoRow>(&format!("SELECT {TODO_COLUMNS} FROM todos WHERE id = ?1"))
+                .bind(&id_str)
+                .fetch_one(&mut *tx)
+                .await?;
+            tx.commit().await?;
+
+            info!("Todo snoozed");
+            self.row_to_todo(row)
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(todo.id = %id, db.operation = "SELECT_HISTORY"))]
+    async fn history(&self, id: Uuid) -> Result<Vec<HistoryEntry>, RepositoryError> {
+        self.capture("history", async {
+            let id_str = id.to_string();
+            let exists: Option<i64> = sqlx::query_scalar("SELECT 1 FROM todos WHERE id = ?1")
+                .bind(&id_str)
+                .fetch_optional(&self.pool)
+                .await?;
+            if exists.is_none() {
+                return Err(RepositoryError::NotFound(id));
+            }
+
+            let rows = sqlx::query_as::<_, HistoryRow>(
+                r#"
+                SELECT event, detail, created_at
+                FROM todo_history
+                WHERE todo_id = ?1
+                ORDER BY id
+                "#
+            )
+            .bind(&id_str)
+            .fetch_all(&self.pool)
+            .await?;
+
+            rows.into_iter()
+                .map(|row| {
+                    Ok(HistoryEntry {
+                        detail: serde_json::from_str(&row.detail)
+                            .map_err(|e| RepositoryError::InvalidData(format!("history detail: {e}")))?,
+                        created_at: parse_timestamp(&row.created_at)?,
+                        event: row.event,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(todo.blocker_id = %blocker, todo.id = %blocked, db.operation = "INSERT_DEPENDENCY"))]
+    async fn add_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<Todo, RepositoryError> {
+        self.capture("add_dependency", async {
+            info!("Adding dependency");
+            self.simulate_db_latency().await;
+
+            let (blocker_str, blocked_str) = (blocker.to_string(), blocked.to_string());
+            let mut tx = self.pool.begin().await?;
+
+            for id in [blocker, blocked] {
+                let exists: Option<i64> = sqlx::query_scalar("SELECT 1 FROM todos WHERE id = ?1")
+                    .bind(id.to_string())
+                    .fetch_optional(&mut *tx)
+                    .await?;
+                if exists.is_none() {
+                    return Err(RepositoryError::NotFound(id));
+                }
+            }
+
+            // Adding the edge closes a cycle if `blocked` is already upstream of `blocker`
+            let cycle: Option<i64> = sqlx::query_scalar(
+                r#"
+                WITH RECURSIVE upstream(id) AS (
+                    SELECT ?1
+                    UNION
+                    SELECT d.blocker_id FROM todo_dependencies d JOIN upstream u ON d.blocked_id = u.id
+                )
+                SELECT 1 FROM upstream WHERE id = ?2
+                "#
+            )
+            .bind(&blocker_str)
+            .bind(&blocked_str)
+            .fetch_optional(&mut *tx)
+            .await?;
+            if cycle.is_some() {
+                warn!("Dependency would create a cycle");
+                return Err(RepositoryError::DependencyCycle { blocker, blocked });
+            }
+
+            sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO todo_dependencies (blocker_id, blocked_id, created_at)
+                VALUES (?1, ?2, ?3)
+                "#
+            )
+            .bind(&blocker_str)
+            .bind(&blocked_str)
+            .bind(self.clock.now().to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+            touch(&mut tx, &blocked_str, self.clock.now()).await?;
+
+            let row = sqlx::query_as::<_, TodoRow>(&format!("SELECT {TODO_COLUMNS} FROM todos WHERE id = ?1"))
+                .bind(&blocked_str)
+                .fetch_one(&mut *tx)
+                .await?;
+            tx.commit().await?;
+
+            info!("Dependency added");
+            self.row_to_todo(row)
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(todo.blocker_id = %blocker, todo.id = %blocked, db.operation = "DELETE_DEPENDENCY"))]
+    async fn remove_dependency(&self, blocker: Uuid, blocked: Uuid) -> Result<(), RepositoryError> {
+        self.capture("remove_dependency", async {
+            info!("Removing dependency");
+            self.simulate_db_latency().await;
+
+            let blocked_str = blocked.to_string();
+            let mut tx = self.pool.begin().await?;
+            let result = sqlx::query("DELETE FROM todo_dependencies WHERE blocker_id = ?1 AND blocked_id = ?2")
+                .bind(blocker.to_string())
+                .bind(&blocked_str)
+                .execute(&mut *tx)
+                .await?;
+
+            if result.rows_affected() == 0 {
+                warn!("Dependency not found");
+                return Err(RepositoryError::NotFound(blocker));
+            }
+            touch(&mut tx, &blocked_str, self.clock.now()).await?;
+            tx.commit().await?;
+
+            info!("Dependency removed");
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self, todos), fields(batch_size = todos.len(), db.operation = "IMPORT"))]
+    async fn import(&self, todos: Vec<Todo>) -> Result<usize, RepositoryError> {
+        self.capture("import", async {
+            info!(count = todos.len(), "Importing todos");
+
+            let mut tx = self.pool.begin().await?;
+            for todo in &todos {
+                let (description, key_id) = self.seal_description(todo)?;
+                insert_query(todo, description, key_id).execute(&mut *tx).await?;
+                write_custom_fields(&mut tx, todo).await?;
+            }
+            tx.commit().await?;
+
+            info!(imported_count = todos.len(), "Import committed");
+            Ok(todos.len())
+        })
+        .await
+    }
+
+    #[instrument(skip(self, projects), fields(db.operation = "DELETE_COMPLETED", deleted_count))]
+    async fn delete_completed(&self, projects: Option<&HashSet<Uuid>>) -> Result<Vec<Uuid>, RepositoryError> {
+        self.capture("delete_completed", async {
+            info!("Deleting all completed todos");
+            self.simulate_db_latency().await;
+
+            // Choosing and deleting in one statement means a todo reopened meanwhile is either
+            // deleted and reported or kept, never deleted without being reported
+            let mut tx = self.pool.begin().await?;
+            let deleted: Vec<String> = sqlx::query_scalar(
+                r#"
+                DELETE FROM todos
+                WHERE completed = true
+                  AND (?1 IS NULL OR project_id IS NULL OR project_id IN (SELECT value FROM json_each(?1)))
+                RETURNING id
+                "#
+            )
+            .bind(projects_json(projects))
+            .fetch_all(&mut *tx)
+            .await?;
+            // Everything a deleted todo owns goes in the same transaction, as for `delete`
+            // Already gone from `todos`, so their tombstones are dated here
+            for id in &deleted {
+                delete_todo_rows(&mut tx, id, self.clock.now()).await?;
+                date_tombstone(&mut tx, id, self.clock.now()).await?;
+            }
+            tx.commit().await?;
+
+            let deleted = deleted
+                .iter()
+                .map(|id| parse_uuid("todo", id))
+                .collect::<Result<Vec<_>, _>>()?;
+            Span::current().record("deleted_count", deleted.len());
+            info!(deleted_count = deleted.len(), "Deleted completed todos");
+            Ok(deleted)
+        })
+        .await
+    }
+
+    #[instrument(skip(self), fields(db.operation = "SELECT_VERSION"))]
+    async fn database_version(&self) -> Result<String, RepositoryError> {
+        let (version,): (String,) = sqlx::query_as("SELECT sqlite_version()").fetch_one(&self.pool).await?;
+        Ok(format!("SQLite {version}"))
+    }
+}
+
+/// The `INSERT` shared by `create` and `import`, with the description already sealed.
+fn insert_query(
+    todo: &Todo,
+    description: Option<String>,
+    key_id: Option<String>,
+) -> sqlx::query::Query<'static, Sqlite, sqlx::sqlite::SqliteArguments<'static>> {
+    sqlx::query(
+        r#"
+        INSERT INTO todos (id, title, description, completed, due_at, tags, created_at, updated_at, description_key_id,
+            estimate_minutes, expires_at, completed_at, project_id, priority, pinned)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, CASE WHEN ?4 THEN ?8 END, ?12, ?13, ?14)
+        "#
+    )
+    .bind(todo.id.to_string())
+    .bind(todo.title.clone())
+    .bind(description)
+    .bind(todo.completed)
+    .bind(todo.due_at.map(|d| d.to_rfc3339()))
+    .bind(tags_json(&todo.tags))
+    .bind(todo.created_at.to_rfc3339())
+    .bind(todo.updated_at.to_rfc3339())
+    .bind(key_id)
+    .bind(todo.estimate_minutes)
+    .bind(todo.expires_at.map(|e| e.to_rfc3339()))
+    .bind(todo.project_id.map(|p| p.to_string()))
+    .bind(todo.priority.map(Priority::as_str))
+    .bind(todo.pinned)
+}
+
+/// Writes every editable field of `todo` and returns its new version. With `base_version`,
+/// nothing is written (and nothing returned) unless the stored todo is still at that version.
+fn update_query(
+    todo: &Todo,
+    description: Option<String>,
+    key_id: Option<String>,
+    base_version: Option<u64>,
+) -> sqlx::query::QueryScalar<'static, Sqlite, i64, sqlx::sqlite::SqliteArguments<'static>> {
+    sqlx::query_scalar(
+        r#"
+        UPDATE todos
+        SET title = ?2, description = ?3, completed = ?4, updated_at = ?5, description_key_id = ?6,
+            due_at = ?7, tags = ?8, estimate_minutes = ?9, expires_at = ?10, priority = ?12, pinned = ?13,
+            completed_at = CASE WHEN ?4 THEN COALESCE(completed_at, ?5) END,
+            version = version + 1
+        WHERE id = ?1 AND (?11 IS NULL OR version = ?11)
+        RETURNING version
+        "#
+    )
+    .bind(todo.id.to_string())
+    .bind(todo.title.clone())
+    .bind(description)
+    .bind(todo.completed)
+    .bind(todo.updated_at.to_rfc3339())
+    .bind(key_id)
+    .bind(todo.due_at.map(|d| d.to_rfc3339()))
+    .bind(tags_json(&todo.tags))
+    .bind(todo.estimate_minutes)
+    .bind(todo.expires_at.map(|e| e.to_rfc3339()))
+    .bind(base_version.map(|v| v as i64))
+    .bind(todo.priority.map(Priority::as_str))
+    .bind(todo.pinned)
+}
+
+/// Deletes a todo with everything it owns: its history, comments, custom field values and
+/// dependencies in either direction. Every path that deletes todos goes through here, so a
+/// table a todo owns is cleaned up everywhere once it's listed. Returns false if the todo
+/// was already gone, as it is when the caller deleted it with `RETURNING` to pick it; such
+/// callers date the tombstone themselves with `date_tombstone`.
+async fn delete_todo_rows(
+    conn: &mut SqliteConnection,
+    id: &str,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    for statement in [
+        "DELETE FROM todo_history WHERE todo_id = ?1",
+        "DELETE FROM todo_comments WHERE todo_id = ?1",
+        "DELETE FROM custom_field_values WHERE todo_id = ?1",
+        "DELETE FROM todo_dependencies WHERE blocker_id = ?1 OR blocked_id = ?1",
+    ] {
+        sqlx::query(statement).bind(id).execute(&mut *conn).await?;
+    }
+    let result = sqlx::query("DELETE FROM todos WHERE id = ?1").bind(id).execute(&mut *conn).await?;
+    let existed = result.rows_affected() > 0;
+    if existed {
+        date_tombstone(conn, id, now).await?;
+    }
+    Ok(existed)
+}
+
+/// Dates the tombstone the delete trigger left for `id` by the repository's clock. The
+/// trigger can only read SQLite's own clock, which tombstone retention doesn't go by.
+async fn date_tombstone(conn: &mut SqliteConnection, id: &str, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE todo_tombstones SET deleted_at = ?2 WHERE id = ?1")
+        .bind(id)
+        .bind(tombstone_time(now))
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// `deleted_at` as the tombstones store it, which must match the trigger's format so the
+/// text comparisons in `prune_tombstones` hold.
+fn tombstone_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// The change sequence the sync triggers gave a todo's latest write. `RETURNING` can't
+/// report it, because the triggers set it after the statement has run.
+async fn read_change_seq(conn: &mut SqliteConnection, id: Uuid) -> Result<u64, sqlx::Error> {
+    let seq: i64 = sqlx::query_scalar("SELECT change_seq FROM todos WHERE id = ?1")
+        .bind(id.to_string())
+        .fetch_one(&mut *conn)
+        .await?;
+    Ok(seq as u64)
+}
+
+/// Replaces the todo's stored custom field values with `todo.custom_fields`.
+async fn write_custom_fields(conn: &mut SqliteConnection, todo: &Todo) -> Result<(), sqlx::Error> {
+    let id = todo.id.to_string();
+    sqlx::query("DELETE FROM custom_field_values WHERE todo_id = ?1")
+        .bind(&id)
+        .execute(&mut *conn)
+        .await?;
+    for (name, value) in &todo.custom_fields {
+        sqlx::query("INSERT INTO custom_field_values (todo_id, name, value) VALUES (?1, ?2, ?3)")
+            .bind(&id)
+            .bind(name)
+            .bind(value.to_string())
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn insert_refresh_token<'e, E: sqlx::Executor<'e, Database = Sqlite>>(
+    executor: E,
+    token_hash: &str,
+    user_id: Uuid,
+    family_id: Uuid,
+    expires_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO refresh_tokens (token_hash, user_id, family_id, expires_at, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        "#
+    )
+    .bind(token_hash)
+    .bind(user_id.to_string())
+    .bind(family_id.to_string())
+    .bind(expires_at.to_rfc3339())
+    .bind(created_at.to_rfc3339())
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+async fn revoke_family(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    family_id: &str,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = ?2 WHERE family_id = ?1 AND revoked_at IS NULL")
+        .bind(family_id)
+        .bind(now.to_rfc3339())
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// The job, plus the user id and email while it is unfinished.
+fn erasure_job_from_row(row: ErasureJobRow) -> Result<(ErasureJob, Option<(Uuid, String)>), RepositoryError> {
+    let id = Uuid::parse_str(&row.id)
+        .map_err(|e| RepositoryError::InvalidData(format!("bad erasure job id {}: {e}", row.id)))?;
+    let subject = match (row.user_id, row.email) {
+        (Some(user_id), Some(email)) => Some((
+            Uuid::parse_str(&user_id)
+                .map_err(|e| RepositoryError::InvalidData(format!("bad user id {user_id}: {e}")))?,
+            email,
+        )),
+        _ => None,
+    };
+    let job = ErasureJob {
+        id,
+        status: row.status.parse().map_err(RepositoryError::InvalidData)?,
+        steps_completed: row.steps_completed as u32,
+        error: row.error,
+        created_at: parse_timestamp(&row.created_at)?,
+        updated_at: parse_timestamp(&row.updated_at)?,
+    };
+    Ok((job, subject))
+}
+
+fn audit_entry_from_row(row: AuditRow) -> Result<AuditEntry, RepositoryError> {
+    Ok(AuditEntry {
+        id: row.id,
+        user_id: row
+            .user_id
+            .as_deref()
+            .map(Uuid::parse_str)
+            .transpose()
+            .map_err(|e| RepositoryError::InvalidData(format!("audit user id: {e}")))?,
+        detail: serde_json::from_str(&row.detail)
+            .map_err(|e| RepositoryError::InvalidData(format!("audit detail: {e}")))?,
+        created_at: parse_timestamp(&row.created_at)?,
+        event: row.event,
+        email: row.email,
+        ip: row.ip,
+        user_agent: row.user_agent,
+    })
+}
+
+fn api_token_from_row(row: ApiTokenRow) -> Result<(Uuid, ApiToken), RepositoryError> {
+    let id = Uuid::parse_str(&row.id)
+        .map_err(|e| RepositoryError::InvalidData(format!("bad token id {}: {e}", row.id)))?;
+    let user_id = Uuid::parse_str(&row.user_id)
+        .map_err(|e| RepositoryError::InvalidData(format!("bad user id {}: {e}", row.user_id)))?;
+    let scopes = row
+        .scopes
+        .split_whitespace()
+        .map(str::parse::<Scope>)
+        .collect::<Result<_, _>>()
+        .map_err(|e| RepositoryError::InvalidData(format!("scopes of token {id}: {e}")))?;
+    let token = ApiToken {
+        id,
+        name: row.name,
+        scopes,
+        created_at: parse_timestamp(&row.created_at)?,
+        expires_at: row.expires_at.as_deref().map(parse_timestamp).transpose()?,
+        revoked_at: row.revoked_at.as_deref().map(parse_timestamp).transpose()?,
+    };
+    Ok((user_id, token))
+}
+
+fn user_from_row(row: UserRow) -> Result<(User, String), RepositoryError> {
+    let user = User {
+        id: Uuid::parse_str(&row.id)
+            .map_err(|e| RepositoryError::InvalidData(format!("bad user id {}: {e}", row.id)))?,
+        email: row.email,
+        username: row.username,
+        mention_channel: row.mention_channel.parse().map_err(RepositoryError::InvalidData)?,
+        created_at: parse_timestamp(&row.created_at)?,
+        admin: row.admin,
+    };
+    Ok((user, row.password_hash))
+}
+
+fn project_from_row(row: ProjectRow) -> Result<Project, RepositoryError> {
+    Ok(Project {
+        id: Uuid::parse_str(&row.id)
+            .map_err(|e| RepositoryError::InvalidData(format!("bad project id {}: {e}", row.id)))?,
+        name: row.name,
+        role: row.role.parse().map_err(RepositoryError::InvalidData)?,
+        created_at: parse_timestamp(&row.created_at)?,
+    })
+}
+
+fn webhook_from_row(row: WebhookRow) -> Result<WebhookSubscription, RepositoryError> {
+    Ok(WebhookSubscription {
+        id: parse_uuid("webhook", &row.id)?,
+        url: row.url,
+        events: serde_json::from_str(&row.events)
+            .map_err(|e| RepositoryError::InvalidData(format!("webhook events: {e}")))?,
+        format: row.format.parse().map_err(RepositoryError::InvalidData)?,
+        api_version: row.api_version as u32,
+        created_at: parse_timestamp(&row.created_at)?,
+    })
+}
+
+fn custom_field_from_row(row: CustomFieldRow) -> Result<CustomFieldDef, RepositoryError> {
+    Ok(CustomFieldDef {
+        field_type: row.field_type.parse().map_err(RepositoryError::InvalidData)?,
+        name: row.name,
+        required: row.required,
+        created_at: parse_timestamp(&row.created_at)?,
+    })
+}
+
+/// Tables restored along with `todos`, since their rows belong to todos (or, for the
+/// definitions and tags, describe what's stored on them).
+const RESTORED_WITH_TODOS: [&str; 8] = [
+    "todo_dependencies",
+    "custom_field_defs",
+    "custom_field_values",
+    "todo_history",
+    "todo_comments",
+    "activity",
+    "todo_external_links",
+    "tags",
+];
+
+/// Replaces `main.<table>` with the rows of `snapshot.<table>`, copying every column the two
+/// share, and returns how many rows were copied. A table the snapshot predates is left empty.
+async fn restore_table(conn: &mut SqliteConnection, table: &str) -> Result<u64, sqlx::Error> {
+    let columns = shared_columns(conn, table).await?;
+    sqlx::query(&format!("DELETE FROM main.{table}")).execute(&mut *conn).await?;
+    if columns.is_empty() {
+        return Ok(0);
+    }
+    Ok(sqlx::query(&format!(
+        "INSERT INTO main.{table} ({columns}) SELECT {columns} FROM snapshot.{table}"
+    ))
+    .execute(&mut *conn)
+    .await?
+    .rows_affected())
+}
+
+/// Adds the rows of `snapshot.<table>` that `main.<table>` doesn't have, keeping the live
+/// ones, and returns how many rows were added.
+async fn keep_table(conn: &mut SqliteConnection, table: &str) -> Result<u64, sqlx::Error> {
+    let columns = shared_columns(conn, table).await?;
+    if columns.is_empty() {
+        return Ok(0);
+    }
+    Ok(sqlx::query(&format!(
+        "INSERT OR IGNORE INTO main.{table} ({columns}) SELECT {columns} FROM snapshot.{table}"
+    ))
+    .execute(&mut *conn)
+    .await?
+    .rows_affected())
+}
+
+/// The quoted, comma-separated columns `main.<table>` and `snapshot.<table>` share, or an
+/// empty string when the snapshot predates the table.
+async fn shared_columns(conn: &mut SqliteConnection, table: &str) -> Result<String, sqlx::Error> {
+    let columns: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT live.name FROM pragma_table_info(?1, 'main') AS live
+        JOIN pragma_table_info(?1, 'snapshot') AS snapshot ON snapshot.name = live.name
+        ORDER BY live.cid
+        "#
+    )
+    .bind(table)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(columns
+        .iter()
+        .map(|column| format!("\"{}\"", column.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+/// Schema version and row counts of the attached database `schema` (`main` or an `ATTACH`
+/// name, never user input).
+async fn database_stats(conn: &mut SqliteConnection, schema: &str) -> Result<DatabaseStats, sqlx::Error> {
+    let tables: Vec<String> = sqlx::query_scalar(&format!(
+        "SELECT name FROM {schema}.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name"
+    ))
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut stats = DatabaseStats::default();
+    for table in tables {
+        if table == "_sqlx_migrations" {
+            stats.schema_version = sqlx::query_scalar(&format!(
+                "SELECT MAX(version) FROM {schema}._sqlx_migrations WHERE success = true"
+            ))
+            .fetch_one(&mut *conn)
+            .await?;
+            continue;
+        }
+        let count: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM {schema}.\"{}\"",
+            table.replace('"', "\"\"")
+        ))
+        .fetch_one(&mut *conn)
+        .await?;
+        stats.row_counts.insert(table, count as u64);
+    }
+    Ok(stats)
+}
+
+fn attachment_from_row(row: AttachmentRow) -> Result<Attachment, RepositoryError> {
+    Ok(Attachment {
+        id: parse_uuid("attachment", &row.id)?,
+        todo_id: parse_uuid("todo", &row.todo_id)?,
+        filename: row.filename,
+        content_type: row.content_type,
+        size_bytes: row.size_bytes as u64,
+        blob_key: row.blob_key,
+        created_at: parse_timestamp(&row.created_at)?,
+        uploaded_by: row.uploaded_by.as_deref().map(|id| parse_uuid("user", id)).transpose()?,
+    })
+}
+
+fn external_link_from_row(row: ExternalLinkRow) -> Result<ExternalLink, RepositoryError> {
+    Ok(ExternalLink {
+        source: row.source,
+        external_id: row.external_id,
+        todo_id: parse_uuid("todo", &row.todo_id)?,
+        remote_state: row.remote_state,
+        created_at: parse_timestamp(&row.created_at)?,
+    })
+}
+
+fn usage_from_row(row: UsageRow) -> Result<(UsageKey, UsageCounts), RepositoryError> {
+    let key = UsageKey {
+        client: row.client,
+        user_id: row.user_id.as_deref().map(|id| parse_uuid("user", id)).transpose()?,
+        method: row.method,
+        route: row.route,
+    };
+    let counts = UsageCounts {
+        requests: row.requests as u64,
+        client_errors: row.client_errors as u64,
+        server_errors: row.server_errors as u64,
+    };
+    Ok((key, counts))
+}
+
+fn comment_from_row(row: CommentRow) -> Result<Comment, RepositoryError> {
+    Ok(Comment {
+        id: parse_uuid("comment", &row.id)?,
+        todo_id: parse_uuid("todo", &row.todo_id)?,
+        author_id: row.author_id.as_deref().map(|id| parse_uuid("user", id)).transpose()?,
+        body: row.body,
+        created_at: parse_timestamp(&row.created_at)?,
+    })
+}
+
+fn activity_from_row(row: ActivityRow) -> Result<ActivityItem, RepositoryError> {
+    Ok(ActivityItem {
+        id: row.id,
+        kind: row.kind.parse().map_err(RepositoryError::InvalidData)?,
+        todo_id: parse_uuid("todo", &row.todo_id)?,
+        comment_id: row.comment_id.as_deref().map(|id| parse_uuid("comment", id)).transpose()?,
+        actor_id: row.actor_id.as_deref().map(|id| parse_uuid("user", id)).transpose()?,
+        excerpt: row.excerpt,
+        created_at: parse_timestamp(&row.created_at)?,
+    })
+}
+
+async fn has_owner(tx: &mut sqlx::Transaction<'_, Sqlite>, project_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM project_members WHERE project_id = ?1 AND role = 'owner')")
+        .bind(project_id.to_string())
+        .fetch_one(&mut **tx)
+        .await
+}
+
+/// Bumps `updated_at` so `ETag`s change when a todo's dependencies do.
+async fn touch(tx: &mut sqlx::Transaction<'_, Sqlite>, id: &str, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE todos SET updated_at = ?2 WHERE id = ?1")
+        .bind(id)
+        .bind(now.to_rfc3339())
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+fn tags_json(tags: &[String]) -> String {
+    serde_json::to_string(tags).expect("a list of strings always serializes")
+}
+
+/// `projects` as a JSON array for `json_each`, or `NULL` for no limit.
+fn projects_json(projects: Option<&HashSet<Uuid>>) -> Option<String> {
+    projects.map(ids_json)
+}
+
+/// A JSON array of the ids, for `IN (SELECT value FROM json_each(?))`.
+fn ids_json(ids: &HashSet<Uuid>) -> String {
+    serde_json::Value::from(ids.iter().map(Uuid::to_string).collect::<Vec<_>>()).to_string()
+}
+
+fn parse_uuid(what: &str, value: &str) -> Result<Uuid, RepositoryError> {
+    Uuid::parse_str(value).map_err(|e| RepositoryError::InvalidData(format!("bad {what} id {value}: {e}")))
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, RepositoryError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| RepositoryError::InvalidData(format!("bad timestamp {value}: {e}")))
+}
@@ -0,0 +1,47 @@
+use crate::repository::RepositoryError;
+use std::{error::Error, future::Future};
+
+/// Errors that can mark a span as failed.
+pub trait SpanError: Error + 'static {
+    /// Whether this error means the operation failed, as opposed to an expected
+    /// outcome such as a missing row.
+    fn is_failure(&self) -> bool {
+        true
+    }
+}
+
+impl SpanError for RepositoryError {
+    fn is_failure(&self) -> bool {
+        !matches!(self, RepositoryError::NotFound(_))
+    }
+}
+
+/// Records `err` on the current span following the OpenTelemetry exception conventions:
+/// the span status becomes `ERROR` and an `exception` event carries the message, type
+/// and source chain.
+pub fn record_error<E: SpanError>(err: &E) {
+    if !err.is_failure() {
+        return;
+    }
+
+    let chain: Vec<String> = std::iter::successors(err.source(), |&e| e.source())
+        .map(ToString::to_string)
+        .collect();
+
+    // An unnamed event with an `error` field is what tracing-opentelemetry maps to an exception.
+    tracing::error!(
+        error = %err,
+        "exception.type" = std::any::type_name::<E>(),
+        exception.stacktrace = ?chain,
+    );
+}
+
+/// Awaits `fut` and records any failure on the current span. Used to wrap the body of
+/// an `#[instrument]`ed function so the error lands on that function's span.
+pub async fn capture<T, E: SpanError>(fut: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let result = fut.await;
+    if let Err(e) = &result {
+        record_error(e);
+    }
+    result
+}
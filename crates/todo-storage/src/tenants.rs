@@ -0,0 +1,192 @@
+use crate::repository::SqliteTodoRepository;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use todo_domain::repository::RepositoryError;
+use tracing::{debug, info, instrument};
+
+/// Longest tenant id accepted; ids become file names and Postgres schema names.
+const MAX_TENANT_ID: usize = 48;
+
+/// Applies the same cipher, latency and query observer to each tenant's repository as to the
+/// shared one.
+pub type Configure = Box<dyn Fn(SqliteTodoRepository) -> SqliteTodoRepository + Send + Sync>;
+
+struct Open {
+    /// Each tenant's repository and the tick it was last used at.
+    repositories: HashMap<String, (Arc<SqliteTodoRepository>, u64)>,
+    clock: u64,
+}
+
+/// One SQLite file per tenant under a directory, created by `provision` and opened (and
+/// migrated) on first use. At most `max_open` are kept open; past that the least recently
+/// used tenant's pool is closed.
+pub struct TenantDatabases {
+    dir: PathBuf,
+    max_open: usize,
+    encryption_key: Option<String>,
+    statement_cache_capacity: usize,
+    /// Open tenant databases without writing, rather than migrating them.
+    read_only: bool,
+    configure: Configure,
+    open: Mutex<Open>,
+    /// Held while a database is opened, so two requests don't migrate the same file at once.
+    /// Lookups of tenants already open don't wait for it.
+    opening: tokio::sync::Mutex<()>,
+}
+
+impl TenantDatabases {
+    pub fn new(
+        dir: impl Into<PathBuf>,
+        max_open: usize,
+        encryption_key: Option<String>,
+        statement_cache_capacity: usize,
+        read_only: bool,
+        configure: Configure,
+    ) -> Self {
+        Self {
+            dir: dir.into(),
+            max_open: max_open.max(1),
+            encryption_key,
+            statement_cache_capacity,
+            read_only,
+            configure,
+            open: Mutex::new(Open {
+                repositories: HashMap::new(),
+                clock: 0,
+            }),
+            opening: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// The tenant's repository, opening its database if it isn't open yet.
+    pub async fn repository(&self, tenant: &str) -> Result<Arc<SqliteTodoRepository>, RepositoryError> {
+        let tenant = normalize_tenant_id(tenant)?;
+        if let Some(repository) = self.lookup(&tenant) {
+            return Ok(repository);
+        }
+
+        let _opening = self.opening.lock().await;
+        if let Some(repository) = self.lookup(&tenant) {
+            return Ok(repository);
+        }
+        let repository = Arc::new(self.connect(&tenant).await?);
+        self.insert(tenant, repository.clone());
+        Ok(repository)
+    }
+
+    /// Tenants with a database file, open or not, for tools such as `migrate-data`.
+    pub fn tenants(&self) -> std::io::Result<Vec<String>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut tenants = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("db") {
+                continue;
+            }
+            if let Some(tenant) = path.file_stem().and_then(|s| s.to_str()) {
+                if normalize_tenant_id(tenant).is_ok_and(|id| id == tenant) {
+                    tenants.push(tenant.to_owned());
+                }
+            }
+        }
+        tenants.sort();
+        Ok(tenants)
+    }
+
+    pub fn open_count(&self) -> usize {
+        self.open.lock().unwrap().repositories.len()
+    }
+
+    fn lookup(&self, tenant: &str) -> Option<Arc<SqliteTodoRepository>> {
+        let mut open = self.open.lock().unwrap();
+        open.clock += 1;
+        let tick = open.clock;
+        open.repositories.get_mut(tenant).map(|(repository, used)| {
+            *used = tick;
+            repository.clone()
+        })
+    }
+
+    fn insert(&self, tenant: String, repository: Arc<SqliteTodoRepository>) {
+        let evicted = {
+            let mut open = self.open.lock().unwrap();
+            open.clock += 1;
+            let tick = open.clock;
+            open.repositories.insert(tenant, (repository, tick));
+            if open.repositories.len() > self.max_open {
+                let oldest = open
+                    .repositories
+                    .iter()
+                    .min_by_key(|(_, (_, used))| *used)
+                    .map(|(tenant, _)| tenant.clone());
+                oldest.and_then(|tenant| open.repositories.remove_entry(&tenant))
+            } else {
+                None
+            }
+        };
+
+        if let Some((tenant, (repository, _))) = evicted {
+            debug!(tenant, "Closing least recently used tenant database");
+            // A request still using it keeps it open; the pool closes when that one finishes
+            if let Some(repository) = Arc::into_inner(repository) {
+                tokio::spawn(async move { repository.pool().close().await });
+            }
+        }
+    }
+
+    /// Creates and migrates the tenant's database if it doesn't exist yet. Requests only ever
+    /// open databases that do, so no request can add a tenant.
+    #[instrument(skip(self))]
+    pub async fn provision(&self, tenant: &str) -> Result<(), RepositoryError> {
+        let tenant = normalize_tenant_id(tenant)?;
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| RepositoryError::InvalidData(format!("tenant database directory: {e}")))?;
+        let url = format!("sqlite:{}?mode=rwc", self.path(&tenant).display());
+        let repository = SqliteTodoRepository::new(&url, self.encryption_key.as_deref(), self.statement_cache_capacity).await?;
+        repository.pool().close().await;
+        info!(tenant, "Tenant database provisioned");
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn connect(&self, tenant: &str) -> Result<SqliteTodoRepository, RepositoryError> {
+        let path = self.path(tenant);
+        let key = self.encryption_key.as_deref();
+        let repository = if self.read_only {
+            let url = format!("sqlite:{}?mode=ro", path.display());
+            SqliteTodoRepository::new_read_only(&url, key, self.statement_cache_capacity).await?
+        } else {
+            let url = format!("sqlite:{}?mode=rw", path.display());
+            SqliteTodoRepository::new(&url, key, self.statement_cache_capacity).await?
+        };
+        info!(tenant, "Tenant database opened");
+        Ok((self.configure)(repository))
+    }
+
+    fn path(&self, tenant: &str) -> PathBuf {
+        self.dir.join(format!("{tenant}.db"))
+    }
+}
+
+/// Lowercases a tenant id and checks that it's safe as a file and schema name: letters,
+/// digits, `-` and `_`, starting with a letter or digit.
+pub fn normalize_tenant_id(tenant: &str) -> Result<String, RepositoryError> {
+    let tenant = tenant.trim().to_ascii_lowercase();
+    let valid = !tenant.is_empty()
+        && tenant.len() <= MAX_TENANT_ID
+        && tenant.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && tenant.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(RepositoryError::InvalidData(format!(
+            "tenant id must be 1-{MAX_TENANT_ID} letters, digits, '-' or '_'"
+        )));
+    }
+    Ok(tenant)
+}
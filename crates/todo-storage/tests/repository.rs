@@ -0,0 +1,682 @@
+//! Repository operations that span several tables, run against a fresh in-memory database,
+//! where a missed table would only show up as data quietly left behind or lost.
+
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use todo_domain::clock::{Clock, FixedClock};
+use todo_domain::models::{
+    ActivityItem, ActivityKind, Attachment, Comment, CustomFieldDef, CustomFieldType, MentionChannel, Priority, Todo,
+    UsageCounts, UsageKey, User,
+};
+use todo_domain::repository::{ListFilter, ListWindow, RepositoryError, TodoCursor, TodoRepository};
+use todo_storage::backup::{verify_snapshot, BackupService, Manifest, MIN_RESTORABLE_VERSION};
+use todo_storage::repository::{OutboxClaim, SqliteTodoRepository};
+use uuid::Uuid;
+
+async fn repository() -> SqliteTodoRepository {
+    SqliteTodoRepository::new("sqlite::memory:", None, 0)
+        .await
+        .expect("failed to open an in-memory database")
+}
+
+fn user(email: &str) -> User {
+    User {
+        id: Uuid::new_v4(),
+        email: email.to_owned(),
+        username: None,
+        mention_channel: MentionChannel::default(),
+        created_at: Utc::now(),
+        admin: false,
+    }
+}
+
+fn usage(client: String, user_id: Option<Uuid>) -> (chrono::DateTime<Utc>, UsageKey, UsageCounts) {
+    let key = UsageKey {
+        client,
+        user_id,
+        method: "GET".to_owned(),
+        route: "/todos".to_owned(),
+    };
+    let counts = UsageCounts {
+        requests: 3,
+        client_errors: 0,
+        server_errors: 0,
+    };
+    (Utc::now(), key, counts)
+}
+
+#[tokio::test]
+async fn erasure_leaves_no_api_usage_behind() {
+    let repository = repository().await;
+    let erased = user("erased@example.com");
+    let kept = user("kept@example.com");
+    repository.create_user(&erased, "hash").await.unwrap();
+    repository.create_user(&kept, "hash").await.unwrap();
+    repository
+        .record_usage(&[
+            usage(format!("user:{}", erased.id), Some(erased.id)),
+            usage("token:1".to_owned(), Some(erased.id)),
+            usage(format!("user:{}", kept.id), Some(kept.id)),
+            usage("ip:127.0.0.1".to_owned(), None),
+        ])
+        .await
+        .unwrap();
+
+    repository.delete_user(erased.id).await.unwrap();
+    // Counts taken before the erasure but flushed after it
+    repository
+        .record_usage(&[usage(format!("user:{}", erased.id), Some(erased.id))])
+        .await
+        .unwrap();
+
+    let since = Utc::now() - chrono::Duration::hours(1);
+    let mut clients: Vec<String> = repository
+        .usage_since(since)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(key, _)| key.client)
+        .collect();
+    clients.sort();
+    assert_eq!(clients, vec!["ip:127.0.0.1".to_owned(), format!("user:{}", kept.id)]);
+}
+
+/// Every row of `table`, each column quoted as SQL would write it, so any column a restore
+/// dropped or reset shows up as a difference. The sync sequence is left out: a restore is a
+/// change like any other, so restored todos are given new positions in it.
+async fn dump(repository: &SqliteTodoRepository, table: &str) -> Vec<String> {
+    let columns: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM pragma_table_info(?1) WHERE name NOT IN ('change_seq', 'created_seq') ORDER BY cid",
+    )
+        .bind(table)
+        .fetch_all(repository.pool())
+        .await
+        .unwrap();
+    let row = columns
+        .iter()
+        .map(|column| format!("{column} = ' || quote({column})"))
+        .collect::<Vec<_>>()
+        .join(" || ', ");
+    sqlx::query_scalar(&format!("SELECT '{row} FROM {table} ORDER BY 1"))
+        .fetch_all(repository.pool())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn restore_brings_back_every_column_of_a_backup() {
+    // `VACUUM INTO` needs a database on disk to copy
+    let dir = std::env::temp_dir().join(format!("todo-restore-{}", Uuid::new_v4()));
+    std::fs::create_dir(&dir).unwrap();
+    let url = format!("sqlite://{}?mode=rwc", dir.join("todos.db").display());
+    let repository = SqliteTodoRepository::new(&url, None, 0).await.unwrap();
+    repository
+        .create_custom_field(&CustomFieldDef {
+            name: "sprint".to_owned(),
+            field_type: CustomFieldType::Number,
+            required: false,
+            created_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+    let now = Utc::now();
+    let todo = repository
+        .create(Todo {
+            id: Uuid::new_v4(),
+            title: "Ship the release".to_owned(),
+            description: Some("Tag and publish".to_owned()),
+            completed: false,
+            due_at: Some(now + Duration::days(2)),
+            tags: vec!["release".to_owned()],
+            estimate_minutes: Some(45),
+            expires_at: Some(now + Duration::days(30)),
+            project_id: Some(Uuid::new_v4()),
+            priority: Some(Priority::High),
+            pinned: true,
+            custom_fields: BTreeMap::from([("sprint".to_owned(), json!(12))]),
+            created_at: now,
+            updated_at: now,
+            blocked: false,
+            version: 1,
+            change_seq: 0,
+        })
+        .await
+        .unwrap();
+    let blocker = repository
+        .create(Todo {
+            id: Uuid::new_v4(),
+            title: "Write the changelog".to_owned(),
+            custom_fields: BTreeMap::new(),
+            project_id: None,
+            ..todo.clone()
+        })
+        .await
+        .unwrap();
+    repository.add_dependency(blocker.id, todo.id).await.unwrap();
+    // Completing it sets `completed_at` and bumps `version`
+    repository
+        .update(Todo {
+            completed: true,
+            ..repository.get(todo.id).await.unwrap()
+        })
+        .await
+        .unwrap();
+    let author = user("author@example.com");
+    repository.create_user(&author, "hash").await.unwrap();
+    let (comment, attachment, item) = belongings(todo.id, author.id);
+    repository.add_comment(&comment).await.unwrap();
+    repository.add_attachment(&attachment).await.unwrap();
+    repository.record_activity(&[author.id], &item).await.unwrap();
+    repository.link_external("github", "owner/repo#7", todo.id, now).await.unwrap();
+    repository.set_tag("release", Some("#ff0000"), Some("Shipping")).await.unwrap();
+    repository.snooze(todo.id, now + Duration::days(3)).await.unwrap();
+
+    const TABLES: [&str; 10] = [
+        "todos",
+        "todo_dependencies",
+        "custom_field_defs",
+        "custom_field_values",
+        "todo_history",
+        "todo_comments",
+        "activity",
+        "attachments",
+        "todo_external_links",
+        "tags",
+    ];
+    let mut before = Vec::new();
+    for table in TABLES {
+        before.push(dump(&repository, table).await);
+    }
+
+    let path = dir.join("snapshot.db");
+    repository.snapshot_to(&path).await.unwrap();
+    repository.delete(todo.id).await.unwrap();
+    repository.delete_custom_field("sprint").await.unwrap();
+    let restored = repository.restore_from(&path).await;
+
+    assert_eq!(restored.unwrap(), 2);
+    for (table, before) in TABLES.into_iter().zip(before) {
+        assert_eq!(dump(&repository, table).await, before, "{table} differs after the restore");
+    }
+    repository.pool().close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn restore_keeps_newer_attachments_and_erased_accounts_out() {
+    let dir = std::env::temp_dir().join(format!("todo-restore-{}", Uuid::new_v4()));
+    std::fs::create_dir(&dir).unwrap();
+    let url = format!("sqlite://{}?mode=rwc", dir.join("todos.db").display());
+    let repository = SqliteTodoRepository::new(&url, None, 0).await.unwrap();
+    let now = Utc::now();
+    let todo = repository
+        .create(Todo {
+            id: Uuid::new_v4(),
+            title: "Ship the release".to_owned(),
+            description: None,
+            completed: false,
+            due_at: None,
+            tags: Vec::new(),
+            estimate_minutes: None,
+            expires_at: None,
+            project_id: None,
+            priority: None,
+            pinned: false,
+            custom_fields: BTreeMap::new(),
+            created_at: now,
+            updated_at: now,
+            blocked: false,
+            version: 1,
+            change_seq: 0,
+        })
+        .await
+        .unwrap();
+    let author = user("author@example.com");
+    repository.create_user(&author, "hash").await.unwrap();
+    let (comment, _, item) = belongings(todo.id, author.id);
+    repository.add_comment(&comment).await.unwrap();
+    repository.record_activity(&[author.id], &item).await.unwrap();
+
+    let path = dir.join("snapshot.db");
+    repository.snapshot_to(&path).await.unwrap();
+    let (_, uploaded, _) = belongings(todo.id, author.id);
+    repository.add_attachment(&uploaded).await.unwrap();
+    repository.delete_user(author.id).await.unwrap();
+    repository.restore_from(&path).await.unwrap();
+
+    // Its blob is still in the store, so the row stays for the sweep to find
+    let attachments = repository.attachments(todo.id).await.unwrap();
+    assert_eq!(attachments.iter().map(|a| a.id).collect::<Vec<_>>(), vec![uploaded.id]);
+    assert_eq!(dump(&repository, "activity").await, Vec::<String>::new());
+    let comments = dump(&repository, "todo_comments").await;
+    assert_eq!(comments.len(), 1);
+    assert!(!comments[0].contains(&author.id.to_string()), "{comments:?}");
+    repository.pool().close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn restore_refuses_snapshots_older_than_it_reads() {
+    let dir = std::env::temp_dir().join(format!("todo-restore-{}", Uuid::new_v4()));
+    std::fs::create_dir(&dir).unwrap();
+    let url = format!("sqlite://{}?mode=rwc", dir.join("todos.db").display());
+    let repository = SqliteTodoRepository::new(&url, None, 0).await.unwrap();
+    let now = Utc::now();
+    let todo = Todo {
+        id: Uuid::new_v4(),
+        title: "Ship the release".to_owned(),
+        description: None,
+        completed: false,
+        due_at: None,
+        tags: Vec::new(),
+        estimate_minutes: None,
+        expires_at: None,
+        project_id: None,
+        priority: None,
+        pinned: false,
+        custom_fields: BTreeMap::new(),
+        created_at: now,
+        updated_at: now,
+        blocked: false,
+        version: 1,
+        change_seq: 0,
+    };
+    repository.create(todo.clone()).await.unwrap();
+    let path = dir.join("snapshot.db");
+    repository.snapshot_to(&path).await.unwrap();
+    // Forget the migrations from `MIN_RESTORABLE_VERSION` on, as a snapshot that old has none
+    let snapshot = SqliteTodoRepository::new(&format!("sqlite://{}", path.display()), None, 0).await.unwrap();
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version >= ?1")
+        .bind(MIN_RESTORABLE_VERSION)
+        .execute(snapshot.pool())
+        .await
+        .unwrap();
+    snapshot.pool().close().await;
+    repository.create(Todo { id: Uuid::new_v4(), title: "Write the changelog".to_owned(), ..todo }).await.unwrap();
+    let before = dump(&repository, "todos").await;
+
+    let refused = repository.restore_from(&path).await.unwrap_err();
+    assert!(refused.to_string().contains("is older than"), "{refused}");
+    assert_eq!(dump(&repository, "todos").await, before);
+    let verification = verify_snapshot(&path, None).await.unwrap();
+    assert!(!verification.restorable(), "{verification:?}");
+    repository.pool().close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A comment on the todo by `author`, an attachment and the mention the comment records.
+fn belongings(todo_id: Uuid, author: Uuid) -> (Comment, Attachment, ActivityItem) {
+    let comment = Comment {
+        id: Uuid::new_v4(),
+        todo_id,
+        author_id: Some(author),
+        body: "Blocked on @author".to_owned(),
+        created_at: Utc::now(),
+    };
+    let attachment = Attachment {
+        id: Uuid::new_v4(),
+        todo_id,
+        filename: "notes.txt".to_owned(),
+        content_type: "text/plain".to_owned(),
+        size_bytes: 5,
+        blob_key: Uuid::new_v4().to_string(),
+        created_at: Utc::now(),
+        uploaded_by: Some(author),
+    };
+    let item = ActivityItem {
+        id: 0,
+        kind: ActivityKind::Mention,
+        todo_id,
+        comment_id: Some(comment.id),
+        actor_id: Some(author),
+        excerpt: comment.body.clone(),
+        created_at: Utc::now(),
+    };
+    (comment, attachment, item)
+}
+
+#[tokio::test]
+async fn todos_expire_by_the_repository_clock() {
+    let start = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+    let clock = Arc::new(FixedClock::new(start));
+    let repository = repository().await.with_clock(clock.clone());
+    let todo = repository
+        .create(Todo {
+            id: Uuid::new_v4(),
+            title: "Renew the certificate".to_owned(),
+            description: None,
+            completed: false,
+            due_at: None,
+            tags: Vec::new(),
+            estimate_minutes: None,
+            expires_at: Some(start + Duration::hours(1)),
+            project_id: None,
+            priority: None,
+            pinned: false,
+            custom_fields: BTreeMap::new(),
+            created_at: start,
+            updated_at: start,
+            blocked: false,
+            version: 1,
+            change_seq: 0,
+        })
+        .await
+        .unwrap();
+    repository.snooze(todo.id, start + Duration::days(1)).await.unwrap();
+
+    assert_eq!(repository.list(&ListFilter::default(), None).await.unwrap().todos.len(), 1);
+    assert_eq!(repository.delete_expired(clock.now()).await.unwrap(), 0);
+    let history = dump(&repository, "todo_history").await;
+    assert!(history[0].contains(&start.to_rfc3339()), "{history:?}");
+
+    // Hidden from lists as soon as it lapses, then swept
+    clock.advance(Duration::hours(1));
+    assert!(repository.list(&ListFilter::default(), None).await.unwrap().todos.is_empty());
+    assert_eq!(repository.delete_expired(clock.now()).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn tombstones_are_kept_by_the_repository_clock() {
+    let start = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+    let clock = Arc::new(FixedClock::new(start));
+    let repository = repository().await.with_clock(clock.clone());
+    let todo = repository
+        .create(Todo {
+            id: Uuid::new_v4(),
+            title: "Draft the agenda".to_owned(),
+            description: None,
+            completed: false,
+            due_at: None,
+            tags: Vec::new(),
+            estimate_minutes: None,
+            expires_at: None,
+            project_id: None,
+            priority: None,
+            pinned: false,
+            custom_fields: BTreeMap::new(),
+            created_at: start,
+            updated_at: start,
+            blocked: false,
+            version: 1,
+            change_seq: 0,
+        })
+        .await
+        .unwrap();
+    repository.delete(todo.id).await.unwrap();
+    let tombstones = dump(&repository, "todo_tombstones").await;
+    assert!(tombstones[0].contains("2024-03-01T09:00:00.000Z"), "{tombstones:?}");
+
+    let retention = Some(std::time::Duration::from_secs(24 * 60 * 60));
+    clock.advance(Duration::hours(23));
+    repository.run_maintenance(retention).await.unwrap();
+    assert_eq!(dump(&repository, "todo_tombstones").await.len(), 1);
+    clock.advance(Duration::hours(1));
+    repository.run_maintenance(retention).await.unwrap();
+    assert!(dump(&repository, "todo_tombstones").await.is_empty());
+}
+
+#[tokio::test]
+async fn snapshots_are_dated_by_the_repository_clock() {
+    let dir = std::env::temp_dir().join(format!("todo-backup-{}", Uuid::new_v4()));
+    std::fs::create_dir(&dir).unwrap();
+    let url = format!("sqlite://{}?mode=rwc", dir.join("todos.db").display());
+    let start = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+    let repository = SqliteTodoRepository::new(&url, None, 0).await.unwrap();
+    let repository = Arc::new(repository.with_clock(Arc::new(FixedClock::new(start))));
+
+    let snapshot = BackupService::new(repository.clone(), dir.join("backups")).create_snapshot().await.unwrap();
+    assert_eq!(snapshot.created_at, start);
+    assert!(snapshot.path.ends_with("todos-20240301T090000.000Z.db"), "{}", snapshot.path.display());
+    let manifest: Manifest = serde_json::from_slice(&std::fs::read(Manifest::path_for(&snapshot.path)).unwrap()).unwrap();
+    assert_eq!(manifest.created_at, start);
+    repository.pool().close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn lists_filter_by_field_before_taking_a_window() {
+    let repository = repository().await;
+    repository
+        .create_custom_field(&CustomFieldDef {
+            name: "sprint".to_owned(),
+            field_type: CustomFieldType::Number,
+            required: false,
+            created_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+    let now = Utc::now();
+    let hidden = Uuid::new_v4();
+    let todos = [
+        (0, json!(12), None),
+        (1, json!(13), None),
+        (2, json!(12), Some(hidden)),
+        (3, json!(12.0), None),
+    ];
+    for (minutes, sprint, project_id) in todos {
+        repository
+            .create(Todo {
+                id: Uuid::new_v4(),
+                title: format!("Sprint {sprint}"),
+                description: None,
+                completed: false,
+                due_at: None,
+                tags: Vec::new(),
+                estimate_minutes: None,
+                expires_at: None,
+                project_id,
+                priority: None,
+                pinned: false,
+                custom_fields: BTreeMap::from([("sprint".to_owned(), sprint)]),
+                created_at: now + Duration::minutes(minutes),
+                updated_at: now,
+                blocked: false,
+                version: 1,
+                change_seq: 0,
+            })
+            .await
+            .unwrap();
+    }
+
+    let projects = Default::default();
+    let filter = ListFilter {
+        projects: Some(&projects),
+        fields: BTreeMap::from([("sprint".to_owned(), json!(12.0))]),
+        ..ListFilter::default()
+    };
+    let window = ListWindow { offset: 1, limit: 5 };
+    let list = repository.list(&filter, Some(window)).await.unwrap();
+
+    // `12` and `12.0` both match; the project's todo and sprint 13 don't count
+    assert_eq!(list.summary.count, 2);
+    let titles: Vec<_> = list.todos.iter().map(|todo| todo.title.as_str()).collect();
+    assert_eq!(titles, ["Sprint 12"]);
+    // The summary describes the whole filtered list, whatever the window holds
+    let all = repository.list(&filter, None).await.unwrap();
+    assert_eq!((all.todos.len(), all.summary), (2, list.summary));
+
+    let in_project = ListFilter {
+        project: Some(hidden),
+        ..ListFilter::default()
+    };
+    let list = repository.list(&in_project, Some(window)).await.unwrap();
+    assert_eq!((list.todos.len(), list.summary.count), (0, 1));
+}
+
+#[tokio::test]
+async fn rescheduled_notifications_remember_their_delivered_channels() {
+    let repository = repository().await;
+    let id = Uuid::new_v4();
+    let claim = OutboxClaim::new("test", Utc::now());
+    repository.enqueue_notification(id, "{}", &claim, Utc::now()).await.unwrap();
+    let delivered = ["slack".to_owned()];
+    let rescheduled = repository
+        .reschedule_notification(id, &claim, 1, Utc::now(), Some("teams: timed out"), &delivered)
+        .await
+        .unwrap();
+    assert!(rescheduled);
+
+    let entries = repository
+        .claim_due_notifications(&OutboxClaim::new("test", Utc::now()), Utc::now(), 10)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].delivered_channels, r#"["slack"]"#);
+}
+
+#[tokio::test]
+async fn databases_created_without_auto_vacuum_are_converted() {
+    let dir = std::env::temp_dir().join(format!("todo-vacuum-{}", Uuid::new_v4()));
+    std::fs::create_dir(&dir).unwrap();
+    let url = format!("sqlite://{}?mode=rwc", dir.join("todos.db").display());
+    let legacy = sqlx::SqlitePool::connect(&url).await.unwrap();
+    sqlx::query("CREATE TABLE legacy (id INTEGER)").execute(&legacy).await.unwrap();
+    legacy.close().await;
+
+    let repository = SqliteTodoRepository::new(&url, None, 0).await.unwrap();
+    repository.pool().close().await;
+    // A fresh connection reads the mode from the file rather than the pool's cached header
+    let reopened = sqlx::SqlitePool::connect(&url).await.unwrap();
+    let mode: i64 = sqlx::query_scalar("PRAGMA auto_vacuum").fetch_one(&reopened).await.unwrap();
+    assert_eq!(mode, 2, "auto_vacuum should be INCREMENTAL");
+    reopened.close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn deleting_completed_todos_leaves_none_of_their_rows_behind() {
+    let repository = repository().await;
+    let now = Utc::now();
+    let done = Todo {
+        id: Uuid::new_v4(),
+        title: "Send the invoices".to_owned(),
+        description: None,
+        completed: true,
+        due_at: None,
+        tags: Vec::new(),
+        estimate_minutes: None,
+        expires_at: None,
+        project_id: None,
+        priority: None,
+        pinned: false,
+        custom_fields: BTreeMap::new(),
+        created_at: now,
+        updated_at: now,
+        blocked: false,
+        version: 1,
+        change_seq: 0,
+    };
+    let done = repository.create(done).await.unwrap();
+    let open = Todo {
+        id: Uuid::new_v4(),
+        title: "File the taxes".to_owned(),
+        completed: false,
+        ..done.clone()
+    };
+    let open = repository.create(open).await.unwrap();
+    repository.snooze(done.id, now + Duration::days(1)).await.unwrap();
+    repository.add_dependency(done.id, open.id).await.unwrap();
+
+    assert_eq!(repository.delete_completed(None).await.unwrap(), vec![done.id]);
+    for table in ["todo_history", "todo_dependencies"] {
+        assert_eq!(dump(&repository, table).await, Vec::<String>::new(), "{table} kept rows");
+    }
+}
+
+#[tokio::test]
+async fn dependencies_that_would_close_a_cycle_are_refused() {
+    let repository = repository().await;
+    let now = Utc::now();
+    let mut ids = Vec::new();
+    for title in ["Book the venue", "Send the invites", "Order the catering"] {
+        let todo = Todo {
+            id: Uuid::new_v4(),
+            title: title.to_owned(),
+            description: None,
+            completed: false,
+            due_at: None,
+            tags: Vec::new(),
+            estimate_minutes: None,
+            expires_at: None,
+            project_id: None,
+            priority: None,
+            pinned: false,
+            custom_fields: BTreeMap::new(),
+            created_at: now,
+            updated_at: now,
+            blocked: false,
+            version: 1,
+            change_seq: 0,
+        };
+        ids.push(repository.create(todo).await.unwrap().id);
+    }
+    let [a, b, c] = ids[..] else { unreachable!() };
+    repository.add_dependency(a, b).await.unwrap();
+    repository.add_dependency(b, c).await.unwrap();
+    let edges = dump(&repository, "todo_dependencies").await;
+
+    // C is downstream of A through B, so it can't block A
+    let refused = repository.add_dependency(c, a).await.unwrap_err();
+    assert!(
+        matches!(refused, RepositoryError::DependencyCycle { blocker, blocked } if blocker == c && blocked == a),
+        "{refused:?}"
+    );
+    let refused = repository.add_dependency(a, a).await.unwrap_err();
+    assert!(matches!(refused, RepositoryError::DependencyCycle { .. }), "{refused:?}");
+    assert_eq!(dump(&repository, "todo_dependencies").await, edges);
+    assert!(!repository.get(a).await.unwrap().blocked);
+}
+
+#[tokio::test]
+async fn cursor_pages_cover_every_todo_once_across_created_at_ties() {
+    let repository = repository().await;
+    let start = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+    // Runs of todos created in the same instant, so pages have to break ties by id
+    for (index, minutes) in [0, 0, 0, 1, 1, 2, 2, 2, 2, 3].into_iter().enumerate() {
+        let created_at = start + Duration::minutes(minutes);
+        let todo = Todo {
+            id: Uuid::new_v4(),
+            title: format!("Todo {index}"),
+            description: None,
+            completed: false,
+            due_at: None,
+            tags: Vec::new(),
+            estimate_minutes: None,
+            expires_at: None,
+            project_id: None,
+            priority: None,
+            pinned: false,
+            custom_fields: BTreeMap::new(),
+            created_at,
+            updated_at: created_at,
+            blocked: false,
+            version: 1,
+            change_seq: 0,
+        };
+        repository.create(todo).await.unwrap();
+    }
+    let listed = repository.list(&ListFilter::default(), None).await.unwrap().todos;
+
+    let mut paged = Vec::new();
+    let mut after = None;
+    loop {
+        let page = repository.list_page(after, 3, None).await.unwrap();
+        paged.extend(page.todos.iter().map(|todo| todo.id));
+        match page.next() {
+            Some(next) => after = Some(next),
+            None => {
+                assert!(!page.has_more);
+                break;
+            }
+        }
+    }
+
+    assert_eq!(listed.len(), 10);
+    assert_eq!(paged, listed.iter().map(|todo| todo.id).collect::<Vec<_>>());
+    // The last page is full, and it still says nothing follows
+    let last = repository.list_page(Some(TodoCursor::after(&listed[8])), 1, None).await.unwrap();
+    assert_eq!(last.todos.len(), 1);
+    assert!(!last.has_more);
+}
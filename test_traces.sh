@@ -77,7 +77,7 @@ if [ ! -z "$TODO_ID" ] && [ "$TODO_ID" != "null" ]; then
     echo "Completing todo $TODO_ID (triggers completion notification)..."
     echo "Expected spans: validate_request → update_todo → database.SELECT + database.UPDATE + analytics_event"
     echo
-    
+
     curl -X PUT http://127.0.0.1:3000/todos/$TODO_ID \
       -H "Content-Type: application/json" \
       -d '{"completed": true, "title": "COMPLETED: Review PR #42"}' 2>/dev/null | jq '{id, title, completed}' 2>/dev/null || echo "Todo updated"